@@ -0,0 +1,258 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! `toodle`, a command line front end over the same `ListManager` the apps use. Besides
+//! being handy on the desktop, its tests drive the core end to end through a real store on
+//! disk, the way a host app would.
+//!
+//! The store is `--db PATH`, else `$TOODLE_DB`, else `toodle.db` in the working directory.
+//! The library's warnings and errors go to stderr, and its debug messages too if
+//! `$TOODLE_LOG` is `debug`.
+
+extern crate ffi_utils;
+extern crate list;
+extern crate store;
+extern crate time;
+
+use std::env;
+use std::ffi::CStr;
+use std::fs;
+use std::io::{
+    self,
+    Read,
+    Write,
+};
+use std::path::Path;
+use std::os::raw::c_char;
+use std::process;
+use std::sync::Arc;
+
+use time::{
+    now_utc,
+    Timespec,
+};
+
+use ffi_utils::logging::{
+    set_logger,
+    LogLevel,
+};
+use list::ListManager;
+use list::filters::ItemFilter;
+use list::importers::ImportSource;
+use list::items::Item;
+use list::todo_txt::to_todo_txt_line;
+use store::Store;
+use store::options::{
+    OpenOptions,
+    STORE_OPEN_CREATE_IF_MISSING,
+};
+
+const USAGE: &'static str = "usage: toodle [--db PATH] COMMAND
+
+commands:
+  list [--all | --done] [--label NAME]   open items, or every or only completed item
+  add TEXT...                            add an item written as a todo.txt line, e.g.
+                                         toodle add Buy milk +groceries due:2017-11-10
+  complete UUID                          mark the item done; a unique prefix will do
+  search TEXT...                         items whose name contains TEXT
+  import [--format FORMAT] FILE          todo.txt, ics, todoist or google-tasks; - is stdin
+  export [--format FORMAT] [FILE]        todo.txt, csv or ics; stdout if no FILE";
+
+extern "C" fn log_to_stderr(level: LogLevel, tag: *const c_char, message: *const c_char) {
+    let (tag, message) = unsafe { (CStr::from_ptr(tag), CStr::from_ptr(message)) };
+    eprintln!("{:?} {}: {}", level, tag.to_string_lossy(), message.to_string_lossy());
+}
+
+/// Removes `--name VALUE` from `args` and returns the value.
+fn take_option(args: &mut Vec<String>, name: &str) -> Result<Option<String>, String> {
+    match args.iter().position(|arg| arg == name) {
+        Some(index) if index + 1 < args.len() => {
+            args.remove(index);
+            Ok(Some(args.remove(index)))
+        },
+        Some(_) => Err(format!("{} needs a value", name)),
+        None => Ok(None),
+    }
+}
+
+/// Removes `--name` from `args` and returns whether it was there.
+fn take_flag(args: &mut Vec<String>, name: &str) -> bool {
+    match args.iter().position(|arg| arg == name) {
+        Some(index) => {
+            args.remove(index);
+            true
+        },
+        None => false,
+    }
+}
+
+fn open(path: &str) -> Result<ListManager, String> {
+    Store::open(path, OpenOptions::from_bits(STORE_OPEN_CREATE_IF_MISSING))
+        .and_then(|store| ListManager::open(Arc::new(store)))
+        .map_err(|e| format!("failed to open {}: {}", path, e))
+}
+
+fn print_items(items: &[Item]) {
+    for item in items.iter() {
+        println!("{}  {}", item.uuid, to_todo_txt_line(item));
+    }
+}
+
+fn list(manager: &ListManager, mut args: Vec<String>) -> Result<(), String> {
+    let completed = if take_flag(&mut args, "--all") {
+        None
+    } else if take_flag(&mut args, "--done") {
+        Some(true)
+    } else {
+        Some(false)
+    };
+    let mut labels = vec![];
+    while let Some(label) = take_option(&mut args, "--label")? {
+        labels.push(label);
+    }
+    if let Some(arg) = args.first() {
+        return Err(format!("unexpected argument {}", arg));
+    }
+    let filter = ItemFilter { labels: labels, completed: completed, ..ItemFilter::default() };
+    print_items(&manager.fetch_items(&filter));
+    Ok(())
+}
+
+fn add(manager: &ListManager, args: Vec<String>) -> Result<(), String> {
+    let line = args.join(" ");
+    if line.contains('\n') {
+        return Err("add takes a single item".to_string());
+    }
+    let uuids = manager.import_todo_txt(&line);
+    if uuids.is_empty() {
+        return Err("nothing to add".to_string());
+    }
+    for uuid in uuids.iter() {
+        println!("{}", uuid);
+    }
+    Ok(())
+}
+
+fn complete(manager: &ListManager, args: Vec<String>) -> Result<(), String> {
+    let prefix = match args.as_slice() {
+        [prefix] if !prefix.is_empty() => prefix,
+        _ => return Err("complete takes one uuid".to_string()),
+    };
+    let mut matching: Vec<Item> = manager.fetch_items(&ItemFilter::default())
+        .into_iter()
+        .filter(|item| item.uuid.starts_with(prefix.as_str()))
+        .collect();
+    if matching.len() > 1 {
+        return Err(format!("{} matches {} items", prefix, matching.len()));
+    }
+    let mut item = matching.pop().ok_or_else(|| format!("no item {}", prefix))?;
+    if item.completion_date.is_none() {
+        item.completion_date = Some(Timespec::new(now_utc().to_timespec().sec, 0));
+        manager.try_save_item(&item).map_err(|e| format!("failed to save {}: {}", item.uuid, e))?;
+    }
+    println!("{}  {}", item.uuid, to_todo_txt_line(&item));
+    Ok(())
+}
+
+fn search(manager: &ListManager, args: Vec<String>) -> Result<(), String> {
+    let text = args.join(" ");
+    if text.is_empty() {
+        return Err("search needs some text".to_string());
+    }
+    print_items(&manager.fetch_items(&ItemFilter { name_contains: Some(text), ..ItemFilter::default() }));
+    Ok(())
+}
+
+/// The format named by `--format`, else the one the file's extension suggests.
+fn format_of(format: Option<String>, path: Option<&String>) -> String {
+    format.unwrap_or_else(|| {
+        let extension = path.and_then(|path| Path::new(path).extension()).and_then(|e| e.to_str());
+        match extension {
+            Some("ics") | Some("ical") => "ics".to_string(),
+            Some("csv") => "csv".to_string(),
+            _ => "todo.txt".to_string(),
+        }
+    })
+}
+
+fn import(manager: &ListManager, mut args: Vec<String>) -> Result<(), String> {
+    let format = take_option(&mut args, "--format")?;
+    let path = match args.as_slice() {
+        [path] => path.clone(),
+        _ => return Err("import takes one file".to_string()),
+    };
+    let mut bytes = vec![];
+    let read = if path == "-" {
+        io::stdin().read_to_end(&mut bytes).map(|_| ())
+    } else {
+        fs::File::open(&path).and_then(|mut file| file.read_to_end(&mut bytes)).map(|_| ())
+    };
+    read.map_err(|e| format!("failed to read {}: {}", path, e))?;
+    let text = || String::from_utf8(bytes.clone()).map_err(|_| format!("{} is not UTF-8", path));
+    let uuids = match format_of(format, Some(&path)).as_str() {
+        "todo.txt" => manager.import_todo_txt(&text()?),
+        "ics" => manager.import_ical(&bytes).map_err(|e| format!("failed to import {}: {}", path, e))?,
+        "todoist" => manager.import_dump(&text()?, ImportSource::Todoist).created,
+        "google-tasks" => manager.import_dump(&text()?, ImportSource::GoogleTasks).created,
+        other => return Err(format!("cannot import {}", other)),
+    };
+    println!("imported {} items", uuids.len());
+    Ok(())
+}
+
+fn export(manager: &ListManager, mut args: Vec<String>) -> Result<(), String> {
+    let format = take_option(&mut args, "--format")?;
+    if args.len() > 1 {
+        return Err("export takes at most one file".to_string());
+    }
+    let path = args.first();
+    let text = match format_of(format, path).as_str() {
+        "todo.txt" => manager.to_todo_txt(),
+        "csv" => manager.export_csv(),
+        "ics" => manager.to_ical(),
+        other => return Err(format!("cannot export {}", other)),
+    };
+    let written = match path {
+        Some(path) => fs::write(path, text.as_bytes()),
+        None => io::stdout().write_all(text.as_bytes()),
+    };
+    written.map_err(|e| format!("failed to write: {}", e))
+}
+
+fn run(mut args: Vec<String>) -> Result<(), String> {
+    let path = match take_option(&mut args, "--db")? {
+        Some(path) => path,
+        None => env::var("TOODLE_DB").unwrap_or_else(|_| "toodle.db".to_string()),
+    };
+    if args.is_empty() || args[0] == "help" || args[0] == "--help" {
+        println!("{}", USAGE);
+        return Ok(());
+    }
+    let command = args.remove(0);
+    let manager = open(&path)?;
+    match command.as_str() {
+        "list" => list(&manager, args),
+        "add" => add(&manager, args),
+        "complete" => complete(&manager, args),
+        "search" => search(&manager, args),
+        "import" => import(&manager, args),
+        "export" => export(&manager, args),
+        other => Err(format!("unknown command {}\n{}", other, USAGE)),
+    }
+}
+
+fn main() {
+    let level = if env::var("TOODLE_LOG").map(|level| level == "debug").unwrap_or(false) { LogLevel::Debug } else { LogLevel::Warn };
+    set_logger(Some(log_to_stderr), level);
+    if let Err(message) = run(env::args().skip(1).collect()) {
+        eprintln!("toodle: {}", message);
+        process::exit(1);
+    }
+}
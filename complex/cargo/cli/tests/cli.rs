@@ -0,0 +1,117 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! Drives the `toodle` binary against a fresh store, covering the core from the outside:
+//! opening and migrating a store, todo.txt parsing, filters, saving and the exporters.
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+struct Store {
+    path: PathBuf,
+}
+
+impl Store {
+    fn new(name: &str) -> Store {
+        let path = env::temp_dir().join(format!("toodle_cli_{}.db", name));
+        let _ = fs::remove_file(&path);
+        Store { path: path }
+    }
+
+    /// Runs `toodle` with `args` and returns its output, asserting it succeeded.
+    fn run(&self, args: &[&str]) -> String {
+        let output = self.command(args).output().expect("expected toodle to run");
+        assert!(output.status.success(), "toodle {:?} failed: {}", args, String::from_utf8_lossy(&output.stderr));
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    /// Runs `toodle` with `args` and returns its error output, asserting it failed.
+    fn fail(&self, args: &[&str]) -> String {
+        let output = self.command(args).output().expect("expected toodle to run");
+        assert!(!output.status.success(), "toodle {:?} succeeded", args);
+        String::from_utf8(output.stderr).unwrap()
+    }
+
+    fn command(&self, args: &[&str]) -> Command {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_toodle"));
+        command.arg("--db").arg(&self.path).args(args);
+        command
+    }
+}
+
+impl Drop for Store {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[test]
+fn test_add_list_complete() {
+    let store = Store::new("add_list_complete");
+    let milk = store.run(&["add", "Buy", "milk", "+groceries", "due:2017-11-10"]).trim().to_string();
+    let call = store.run(&["add", "Call mum"]).trim().to_string();
+    assert_eq!(milk.len(), 32);
+
+    let open = store.run(&["list"]);
+    assert!(open.contains(&format!("{}  Buy milk +groceries due:2017-11-10", milk)));
+    assert!(open.contains(&format!("{}  Call mum", call)));
+    assert_eq!(store.run(&["list", "--label", "groceries"]).lines().count(), 1);
+
+    let done = store.run(&["complete", &milk[..8]]);
+    assert!(done.starts_with(&format!("{}  x ", milk)));
+    assert_eq!(store.run(&["list"]).lines().collect::<Vec<_>>(), vec![format!("{}  Call mum", call)]);
+    assert!(store.run(&["list", "--done"]).contains(&milk));
+    assert_eq!(store.run(&["list", "--all"]).lines().count(), 2);
+}
+
+#[test]
+fn test_search() {
+    let store = Store::new("search");
+    store.run(&["add", "Buy milk"]);
+    store.run(&["add", "Café au lait"]);
+    assert_eq!(store.run(&["search", "milk"]).lines().count(), 1);
+    assert!(store.run(&["search", "cafe"]).contains("Café au lait"));
+    assert_eq!(store.run(&["search", "tea"]), "");
+}
+
+#[test]
+fn test_import_export() {
+    let store = Store::new("import_export");
+    let todo_txt = env::temp_dir().join("toodle_cli_import.txt");
+    fs::write(&todo_txt, "(A) Pay rent due:2017-12-01\nx 2017-11-01 Fix bike @garage\n\nWater plants\n").unwrap();
+    assert_eq!(store.run(&["import", todo_txt.to_str().unwrap()]), "imported 3 items\n");
+    fs::remove_file(&todo_txt).unwrap();
+
+    let exported = store.run(&["export"]);
+    assert_eq!(exported.lines().count(), 3);
+    assert!(exported.contains("(A) Pay rent due:2017-12-01"));
+    assert!(exported.contains("x 2017-11-01 Fix bike @garage"));
+    assert!(store.run(&["export", "--format", "csv"]).lines().count() >= 4);
+
+    // an iCalendar round trip into a second store
+    let ics = env::temp_dir().join("toodle_cli_export.ics");
+    store.run(&["export", ics.to_str().unwrap()]);
+    let other = Store::new("import_export_other");
+    assert_eq!(other.run(&["import", ics.to_str().unwrap()]), "imported 3 items\n");
+    fs::remove_file(&ics).unwrap();
+    assert!(other.run(&["list", "--all"]).contains("Fix bike"));
+}
+
+#[test]
+fn test_errors() {
+    let store = Store::new("errors");
+    assert!(store.fail(&["frobnicate"]).contains("unknown command frobnicate"));
+    assert!(store.fail(&["complete", "nope"]).contains("no item nope"));
+    assert!(store.fail(&["add"]).contains("nothing to add"));
+    assert!(store.fail(&["import", "--format", "pdf", "-"]).contains("cannot import pdf"));
+    assert!(store.run(&["help"]).starts_with("usage: toodle"));
+}
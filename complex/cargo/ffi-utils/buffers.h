@@ -0,0 +1,15 @@
+#ifndef buffers_h
+#define buffers_h
+
+#include <stdint.h>
+
+// Bytes returned by the library. `data` is NULL when `len` is 0. Release with
+// byte_buffer_destroy.
+struct byte_buffer {
+    int64_t len;
+    uint8_t* _Nullable data;
+};
+
+void byte_buffer_destroy(struct byte_buffer buffer);
+
+#endif /* buffers_h */
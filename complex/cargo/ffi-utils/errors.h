@@ -0,0 +1,45 @@
+#ifndef errors_h
+#define errors_h
+
+// Functions that can fail take a `struct extern_error*` as their last argument. On failure
+// they fill it in and return false, NULL or -1, whichever their return type allows. Switch
+// on the category; the message is for logs and bug reports. A panic inside the library is
+// reported as ErrorCategoryBug instead of crashing the app.
+
+// What the host should do about an error.
+enum error_category {
+    ErrorCategoryNone = 0,
+    // Transient, e.g. the database is busy. Try again later.
+    ErrorCategoryRetryable = 1,
+    // The user has to fix something, e.g. free up space or correct what they typed.
+    ErrorCategoryNeedsUserInput = 2,
+    // The credentials or key are wrong. Ask the user to sign in again.
+    ErrorCategoryNeedsReauth = 3,
+    // The stored data is damaged. Offer to restore from a backup and report it.
+    ErrorCategoryDataCorruption = 4,
+    // A defect in the library or in how it was called. Report it.
+    ErrorCategoryBug = 5,
+    // Another process, e.g. a share extension, is using the database. Nothing was written;
+    // try again shortly.
+    ErrorCategoryBusy = 6,
+};
+
+// Filled in by functions taking an `error` out parameter when they fail; pass NULL to
+// ignore errors. Zero initialize before the call. The message is owned by the caller. A
+// message still in the error when it is filled in again is released, so after releasing it
+// yourself, set it to NULL or use extern_error_clear.
+struct extern_error {
+    enum error_category category;
+    char* _Nullable message;
+};
+
+void error_message_destroy(char* _Nullable message);
+
+// For Swift and Objective-C: report an extern_error as an NSError in this domain, with the
+// category as the code and the message as the localized description. After converting it,
+// call extern_error_clear, which releases the message and zeroes the error so it can be
+// passed to the next call.
+#define TOODLE_ERROR_DOMAIN "org.mozilla.toodle"
+void extern_error_clear(struct extern_error* _Nullable error);
+
+#endif /* errors_h */
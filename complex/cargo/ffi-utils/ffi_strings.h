@@ -0,0 +1,27 @@
+#ifndef ffi_strings_h
+#define ffi_strings_h
+
+#include <stdint.h>
+
+// Every char* the library returns is owned by the caller and released with string_destroy,
+// unless its declaration says otherwise. Strings passed in are only borrowed for the call.
+void string_destroy(char* _Nullable string);
+
+
+// A string as `len` UTF-16 code units, without a terminator, for Java and Kotlin, whose
+// modified UTF-8 mangles characters outside the Basic Multilingual Plane such as emoji.
+// `data` is NULL when `len` is 0. Release with utf16_string_destroy.
+struct utf16_string {
+    int64_t len;
+    uint16_t* _Nullable data;
+};
+
+void utf16_string_destroy(struct utf16_string string);
+
+// A NUL terminated UTF-16 string, i.e. a wchar_t* on Windows or a .NET string marshalled
+// as UnmanagedType.LPWStr. Functions ending in _wide take these for paths, which a char*
+// can't carry once .NET has converted them to the ANSI code page. Release the ones the
+// library returns with wide_string_destroy.
+void wide_string_destroy(uint16_t* _Nullable string);
+
+#endif /* ffi_strings_h */
@@ -0,0 +1,15 @@
+#ifndef memory_h
+#define memory_h
+
+#include <stddef.h>
+
+// Memory for arguments, from the library's own allocator, so that hosts such as Dart, whose
+// malloc differs by platform, allocate the same way everywhere; e.g. implement dart:ffi's
+// Allocator with these. Blocks are aligned for any type.
+
+// Returns NULL if `size` bytes could not be allocated.
+void* _Nullable toodle_alloc(size_t size);
+// Releases memory from toodle_alloc, and nothing else. Does nothing for NULL.
+void toodle_free(void* _Nullable memory);
+
+#endif /* memory_h */
@@ -0,0 +1,109 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! Support only built for Android.
+
+pub mod log {
+    //! Writes log messages to logcat. Unless the host registers its own logger, everything
+    //! logged with `log_debug!` and friends ends up here, under one tag with the module
+    //! that logged it at the start of the message. Messages below the level are dropped
+    //! before they are formatted.
+
+    use std::ffi::CString;
+    use std::fmt;
+    use std::os::raw::{
+        c_char,
+        c_int,
+    };
+    use std::sync::{
+        Mutex,
+        MutexGuard,
+    };
+
+    use logging::LogLevel;
+
+    #[link(name = "log")]
+    extern "C" {
+        fn __android_log_write(priority: c_int, tag: *const c_char, text: *const c_char) -> c_int;
+    }
+
+    // from android/log.h
+    const ANDROID_LOG_DEBUG: c_int = 3;
+    const ANDROID_LOG_INFO: c_int = 4;
+    const ANDROID_LOG_WARN: c_int = 5;
+    const ANDROID_LOG_ERROR: c_int = 6;
+
+    pub const DEFAULT_TAG: &'static str = "Toodle";
+
+    struct Config {
+        /// `DEFAULT_TAG` if `None`.
+        tag: Option<String>,
+        level: LogLevel,
+    }
+
+    static CONFIG: Mutex<Config> = Mutex::new(Config {
+        tag: None,
+        level: LogLevel::Info,
+    });
+
+    fn config() -> MutexGuard<'static, Config> {
+        CONFIG.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// The tag messages are written under, `DEFAULT_TAG` until it is set.
+    pub fn set_tag(tag: &str) {
+        config().tag = Some(tag.to_string());
+    }
+
+    /// Drops messages below `level`. The default is `LogLevel::Info`.
+    pub fn set_level(level: LogLevel) {
+        config().level = level;
+    }
+
+    pub fn enabled(level: LogLevel) -> bool {
+        level >= config().level
+    }
+
+    /// Writes `message`, logged by `module`, if `level` is enabled.
+    pub fn write(level: LogLevel, module: &str, message: fmt::Arguments) {
+        let tag = {
+            let config = config();
+            if level < config.level {
+                return;
+            }
+            config.tag.clone().unwrap_or_else(|| DEFAULT_TAG.to_string())
+        };
+        let priority = match level {
+            LogLevel::Debug => ANDROID_LOG_DEBUG,
+            LogLevel::Info => ANDROID_LOG_INFO,
+            LogLevel::Warn => ANDROID_LOG_WARN,
+            LogLevel::Error => ANDROID_LOG_ERROR,
+        };
+        let tag = CString::new(tag.replace('\0', "")).unwrap_or_default();
+        let text = CString::new(format!("{}: {}", module, message).replace('\0', "")).unwrap_or_default();
+        unsafe { __android_log_write(priority, tag.as_ptr(), text.as_ptr()) };
+    }
+
+    pub fn debug(module: &str, message: fmt::Arguments) {
+        write(LogLevel::Debug, module, message)
+    }
+
+    pub fn info(module: &str, message: fmt::Arguments) {
+        write(LogLevel::Info, module, message)
+    }
+
+    pub fn warn(module: &str, message: fmt::Arguments) {
+        write(LogLevel::Warn, module, message)
+    }
+
+    pub fn error(module: &str, message: fmt::Arguments) {
+        write(LogLevel::Error, module, message)
+    }
+}
@@ -0,0 +1,171 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! A record of the last few high level operations, for crash reports.
+//!
+//! Crash reporters read the record from a signal handler, where nothing may allocate or
+//! take a lock. So the record is also kept as text in a fixed buffer of atomics, guarded by
+//! a sequence number that is odd while the buffer is being rewritten: `crash_context`
+//! copies the buffer out and tries again if the sequence number moved meanwhile.
+
+use std::cmp;
+use std::collections::VecDeque;
+use std::hint;
+use std::panic;
+use std::sync::atomic::{
+    self,
+    AtomicU8,
+    AtomicUsize,
+    Ordering,
+};
+use std::sync::{
+    Mutex,
+    Once,
+};
+use std::time::{
+    SystemTime,
+    UNIX_EPOCH,
+};
+
+/// How many breadcrumbs are kept.
+pub const CAPACITY: usize = 32;
+/// The most text kept, the most recent breadcrumbs if they do not all fit.
+pub const SNAPSHOT_SIZE: usize = 4096;
+/// How often `crash_context` tries to copy the snapshot while it is being rewritten.
+const READ_ATTEMPTS: usize = 1000;
+
+// only written with `TRAIL` locked
+static TRAIL: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+static SNAPSHOT: [AtomicU8; SNAPSHOT_SIZE] = [const { AtomicU8::new(0) }; SNAPSHOT_SIZE];
+static SNAPSHOT_LEN: AtomicUsize = AtomicUsize::new(0);
+static SEQUENCE: AtomicUsize = AtomicUsize::new(0);
+static PANIC_HOOK: Once = Once::new();
+
+/// Records that `operation` is about to run. Operations should be names, never user data.
+pub fn leave(operation: &str) {
+    let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let breadcrumb = format!("{} {}\n", seconds, operation.replace('\0', ""));
+    let mut trail = TRAIL.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let dropped = if trail.len() == CAPACITY { trail.pop_front() } else { None };
+    trail.push_back(breadcrumb);
+
+    let len: usize = trail.iter().map(|breadcrumb| breadcrumb.len()).sum();
+    let skip = len.saturating_sub(SNAPSHOT_SIZE);
+    let sequence = SEQUENCE.load(Ordering::Relaxed);
+    SEQUENCE.store(sequence + 1, Ordering::Relaxed);
+    atomic::fence(Ordering::Release);
+    for (slot, &byte) in SNAPSHOT.iter().zip(trail.iter().flat_map(|breadcrumb| breadcrumb.as_bytes()).skip(skip)) {
+        slot.store(byte, Ordering::Relaxed);
+    }
+    SNAPSHOT_LEN.store(len - skip, Ordering::Relaxed);
+    SEQUENCE.store(sequence + 2, Ordering::Release);
+    drop(trail);
+    // freed after unlocking
+    drop(dropped);
+}
+
+/// Copies the breadcrumbs, oldest first, one per line as `<unix seconds> <operation>`,
+/// into `buffer` and returns how many bytes were copied. Keeps the most recent if they do
+/// not all fit. Neither allocates nor locks, so it may be called from a signal handler.
+/// Returns 0 if breadcrumbs were being left all along, e.g. by the thread it interrupted.
+pub fn crash_context(buffer: &mut [u8]) -> usize {
+    for _ in 0..READ_ATTEMPTS {
+        let sequence = SEQUENCE.load(Ordering::Acquire);
+        if sequence % 2 == 1 {
+            hint::spin_loop();
+            continue;
+        }
+        let len = SNAPSHOT_LEN.load(Ordering::Relaxed);
+        let copied = cmp::min(len, buffer.len());
+        for (byte, slot) in buffer.iter_mut().zip(SNAPSHOT[len - copied..len].iter()) {
+            *byte = slot.load(Ordering::Relaxed);
+        }
+        atomic::fence(Ordering::Acquire);
+        if SEQUENCE.load(Ordering::Relaxed) == sequence {
+            return copied;
+        }
+    }
+    0
+}
+
+/// Leaves a breadcrumb for every panic, before handing it on to the existing hook.
+/// Installing more than once has no effect.
+pub fn install_panic_hook() {
+    PANIC_HOOK.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            let location = info.location().map(|l| format!(" at {}:{}", l.file(), l.line())).unwrap_or_default();
+            leave(&format!("panic{}", location));
+            previous(info);
+        }));
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use std::thread;
+
+    use super::{
+        crash_context,
+        leave,
+        CAPACITY,
+        SNAPSHOT_SIZE,
+    };
+
+    fn context() -> String {
+        let mut buffer = [0; SNAPSHOT_SIZE];
+        let len = crash_context(&mut buffer);
+        String::from_utf8_lossy(&buffer[..len]).into_owned()
+    }
+
+    // one test, as the breadcrumbs are global
+    #[test]
+    fn test_breadcrumbs() {
+        leave("first");
+        leave("second");
+        let lines: Vec<String> = context().lines().map(|l| l.to_string()).collect();
+        assert!(lines[lines.len() - 2].ends_with(" first"));
+        assert!(lines[lines.len() - 1].ends_with(" second"));
+
+        for i in 0..CAPACITY {
+            leave(&format!("operation {}", i));
+        }
+        let context = context();
+        assert_eq!(context.lines().count(), CAPACITY);
+        assert!(!context.contains("second"));
+        assert!(context.ends_with(&format!(" operation {}\n", CAPACITY - 1)));
+
+        let mut short = [0; 8];
+        assert_eq!(crash_context(&mut short), short.len());
+        assert_eq!(&short[..], &context.as_bytes()[context.len() - short.len()..]);
+
+        // reads racing writers see one snapshot or another, never a mix, or give up
+        let writers: Vec<_> = (0..4).map(|writer| thread::spawn(move || {
+            for step in 0..200 {
+                leave(&format!("writer {} step {}", writer, step));
+            }
+        })).collect();
+        for _ in 0..2000 {
+            let context = self::context();
+            if context.is_empty() {
+                continue;
+            }
+            assert!(context.ends_with('\n') && context.lines().count() == CAPACITY, "torn read {:?}", context);
+            for line in context.lines() {
+                let words: Vec<&str> = line.split(' ').collect();
+                assert!(words[0].parse::<u64>().is_ok() && words.len() >= 2, "torn line {:?}", line);
+            }
+        }
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        assert!(self::context().contains(" step 199\n"));
+    }
+}
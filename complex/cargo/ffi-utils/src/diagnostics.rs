@@ -0,0 +1,108 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! Memory diagnostics, for chasing leaks and double frees across the FFI boundary.
+//!
+//! Off by default, when recording a drop costs one atomic load. Once enabled, drops are
+//! counted per type and every `sample_every`th drop of a type is logged, starting with
+//! the first.
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{
+    AtomicBool,
+    AtomicUsize,
+    Ordering,
+};
+use std::sync::Mutex;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static SAMPLE_EVERY: AtomicUsize = AtomicUsize::new(1);
+static DROPS: Mutex<BTreeMap<&'static str, u64>> = Mutex::new(BTreeMap::new());
+
+/// Turns drop diagnostics on or off. A `sample_every` of 0 counts drops without logging
+/// any. Counts are kept when turned off, until `reset_drop_counts`.
+pub fn set_drop_diagnostics(enabled: bool, sample_every: usize) {
+    SAMPLE_EVERY.store(sample_every, Ordering::Relaxed);
+    ENABLED.store(enabled, Ordering::Release);
+}
+
+pub fn drop_diagnostics_enabled() -> bool {
+    ENABLED.load(Ordering::Acquire)
+}
+
+/// Called from `Drop` impls. `describe` is only called for drops that are logged.
+pub fn record_drop<F>(type_name: &'static str, describe: F)
+where F: FnOnce() -> String {
+    if !drop_diagnostics_enabled() {
+        return;
+    }
+    let count = {
+        let mut drops = match DROPS.lock() {
+            Ok(drops) => drops,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        let count = drops.entry(type_name).or_insert(0);
+        *count += 1;
+        *count
+    };
+    let sample_every = SAMPLE_EVERY.load(Ordering::Relaxed) as u64;
+    if sample_every != 0 && (count - 1) % sample_every == 0 {
+        log_info!("{} is being deallocated ({} {} dropped)", describe(), count, type_name);
+    }
+}
+
+/// How many values of each type were dropped while diagnostics were on.
+pub fn drop_counts() -> BTreeMap<String, u64> {
+    let drops = match DROPS.lock() {
+        Ok(drops) => drops,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    drops.iter().map(|(name, count)| (name.to_string(), *count)).collect()
+}
+
+pub fn reset_drop_counts() {
+    match DROPS.lock() {
+        Ok(mut drops) => drops.clear(),
+        Err(poisoned) => poisoned.into_inner().clear(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::Cell;
+
+    use super::{
+        drop_counts,
+        record_drop,
+        set_drop_diagnostics,
+    };
+
+    #[test]
+    fn test_drop_diagnostics() {
+        let described = Cell::new(0);
+        let drop = || record_drop("test_drop_diagnostics", || {
+            described.set(described.get() + 1);
+            "value".to_string()
+        });
+
+        drop();
+        assert_eq!(drop_counts().get("test_drop_diagnostics"), None);
+
+        set_drop_diagnostics(true, 3);
+        for _ in 0..7 {
+            drop();
+        }
+        set_drop_diagnostics(false, 1);
+        drop();
+        assert_eq!(drop_counts().get("test_drop_diagnostics"), Some(&7));
+        // the 1st, 4th and 7th
+        assert_eq!(described.get(), 3);
+    }
+}
@@ -0,0 +1,218 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! Integer handles for objects owned by the library, for hosts that would otherwise hold
+//! raw pointers, e.g. as a Java `long`.
+//!
+//! A handle packs a slot index into its low 32 bits and the slot's generation into its high
+//! 32 bits. Freeing an object bumps its slot's generation, so using a handle after it was
+//! freed, or freeing it twice, is reported as an error rather than touching whatever
+//! reuses the slot. 0 is never a valid handle.
+
+use std::error::Error;
+use std::fmt;
+use std::sync::{
+    Mutex,
+    MutexGuard,
+};
+
+use errors::{
+    set_extern_error,
+    ErrorCategory,
+    ExternError,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HandleError {
+    /// The handle is 0.
+    Null,
+    /// No map ever handed out the handle.
+    Invalid(u64),
+    /// The object the handle referred to has been freed.
+    Stale(u64),
+}
+
+impl HandleError {
+    /// Always a bug in the host.
+    pub fn category(&self) -> ErrorCategory {
+        ErrorCategory::Bug
+    }
+}
+
+impl fmt::Display for HandleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            HandleError::Null => write!(f, "null handle"),
+            HandleError::Invalid(handle) => write!(f, "invalid handle {:#x}", handle),
+            HandleError::Stale(handle) => write!(f, "handle {:#x} used after it was freed", handle),
+        }
+    }
+}
+
+impl Error for HandleError {
+    fn description(&self) -> &str {
+        "bad handle"
+    }
+}
+
+pub unsafe fn set_handle_error(out: *mut ExternError, error: &HandleError) {
+    set_extern_error(out, error.category(), error.to_string());
+}
+
+struct Slot<T> {
+    generation: u32,
+    value: Option<T>,
+}
+
+struct Slots<T> {
+    slots: Vec<Slot<T>>,
+    free: Vec<u32>,
+}
+
+pub struct HandleMap<T> {
+    inner: Mutex<Slots<T>>,
+}
+
+impl<T> Default for HandleMap<T> {
+    fn default() -> HandleMap<T> {
+        HandleMap::new()
+    }
+}
+
+impl<T> HandleMap<T> {
+    pub const fn new() -> HandleMap<T> {
+        HandleMap {
+            inner: Mutex::new(Slots {
+                slots: Vec::new(),
+                free: Vec::new(),
+            }),
+        }
+    }
+
+    /// A host callback that panicked while the map was locked, and was caught at the FFI
+    /// boundary, leaves the slots as they were, so the poison is ignored.
+    fn lock(&self) -> MutexGuard<'_, Slots<T>> {
+        self.inner.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Takes ownership of `value` and returns its handle.
+    pub fn insert(&self, value: T) -> u64 {
+        let mut inner = self.lock();
+        let index = match inner.free.pop() {
+            Some(index) => index,
+            None => {
+                // generations start at 1 so that no handle is 0
+                inner.slots.push(Slot { generation: 1, value: None });
+                (inner.slots.len() - 1) as u32
+            },
+        };
+        let slot = &mut inner.slots[index as usize];
+        slot.value = Some(value);
+        (u64::from(slot.generation) << 32) | u64::from(index)
+    }
+
+    fn check(slots: &[Slot<T>], handle: u64) -> Result<usize, HandleError> {
+        if handle == 0 {
+            return Err(HandleError::Null);
+        }
+        let index = (handle & 0xffff_ffff) as usize;
+        let generation = (handle >> 32) as u32;
+        match slots.get(index) {
+            Some(slot) if slot.generation == generation && slot.value.is_some() => Ok(index),
+            Some(slot) if generation != 0 && generation < slot.generation => Err(HandleError::Stale(handle)),
+            _ => Err(HandleError::Invalid(handle)),
+        }
+    }
+
+    /// Calls `f` with the object while the map is locked, so `f` must not use this map
+    /// again, or it deadlocks.
+    pub fn get<R, F>(&self, handle: u64, f: F) -> Result<R, HandleError>
+    where F: FnOnce(&T) -> R {
+        let inner = self.lock();
+        let index = HandleMap::check(&inner.slots, handle)?;
+        Ok(f(inner.slots[index].value.as_ref().unwrap()))
+    }
+
+    /// Like `get`, and likewise `f` must not use this map again.
+    pub fn get_mut<R, F>(&self, handle: u64, f: F) -> Result<R, HandleError>
+    where F: FnOnce(&mut T) -> R {
+        let mut inner = self.lock();
+        let index = HandleMap::check(&inner.slots, handle)?;
+        Ok(f(inner.slots[index].value.as_mut().unwrap()))
+    }
+
+    /// Gives the object back to the caller. The handle is stale from then on.
+    pub fn remove(&self, handle: u64) -> Result<T, HandleError> {
+        let mut inner = self.lock();
+        let index = HandleMap::check(&inner.slots, handle)?;
+        let value = {
+            let slot = &mut inner.slots[index];
+            slot.generation = slot.generation.wrapping_add(1).max(1);
+            slot.value.take().unwrap()
+        };
+        inner.free.push(index as u32);
+        Ok(value)
+    }
+
+    /// How many objects are live.
+    pub fn len(&self) -> usize {
+        let inner = self.lock();
+        inner.slots.len() - inner.free.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        HandleError,
+        HandleMap,
+    };
+
+    #[test]
+    fn test_handles() {
+        let map = HandleMap::new();
+        let milk = map.insert("milk".to_string());
+        assert!(milk != 0);
+        assert_eq!(map.get(milk, |name| name.clone()), Ok("milk".to_string()));
+        map.get_mut(milk, |name| name.push_str(" and eggs")).unwrap();
+        assert_eq!(map.remove(milk), Ok("milk and eggs".to_string()));
+        assert!(map.is_empty());
+
+        // the slot is reused, but the old handle stays dead
+        let bread = map.insert("bread".to_string());
+        assert!(bread != milk);
+        assert_eq!(map.get(milk, |_| ()), Err(HandleError::Stale(milk)));
+        assert_eq!(map.remove(milk), Err(HandleError::Stale(milk)));
+        assert_eq!(map.get(bread, |name| name.clone()), Ok("bread".to_string()));
+
+        assert_eq!(map.get(0, |_| ()), Err(HandleError::Null));
+        assert_eq!(map.get(bread + 1, |_| ()), Err(HandleError::Invalid(bread + 1)));
+        assert_eq!(map.get(bread + (1 << 32), |_| ()), Err(HandleError::Invalid(bread + (1 << 32))));
+    }
+
+    #[test]
+    fn test_panic_in_callback_leaves_map_usable() {
+        use std::panic;
+
+        let map = HandleMap::new();
+        let milk = map.insert("milk".to_string());
+        let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            map.get_mut(milk, |_| panic!("host callback failed"))
+        }));
+        assert!(result.is_err());
+        assert_eq!(map.get(milk, |name| name.clone()), Ok("milk".to_string()));
+        assert_eq!(map.remove(milk), Ok("milk".to_string()));
+        assert!(map.is_empty());
+    }
+}
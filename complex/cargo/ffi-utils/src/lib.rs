@@ -8,14 +8,38 @@
 // CONDITIONS OF ANY KIND, either express or implied. See the License for the
 // specific language governing permissions and limitations under the License.
 
+#[macro_use]
+pub mod logging;
+#[cfg(target_os="android")]
+pub mod android;
+pub mod breadcrumbs;
+pub mod diagnostics;
+pub mod handles;
+#[macro_use]
+pub mod lock;
+#[macro_use]
+pub mod nulls;
+#[macro_use]
+pub mod panics;
+pub mod memory;
+
 pub mod strings {
     use std::os::raw::c_char;
+    use std::ptr;
+    use std::slice;
+
+    use nulls::null_argument;
     use std::ffi::{
         CString,
         CStr
     };
 
+    /// A null string reads as empty.
     pub fn c_char_to_string(cchar: *const c_char) -> String {
+        if cchar.is_null() {
+            unsafe { null_argument("c_char_to_string", "string", ptr::null_mut()) };
+            return "".to_string();
+        }
         let c_str = unsafe { CStr::from_ptr(cchar) };
         let r_str = match c_str.to_str() {
             Err(_) => "",
@@ -27,4 +51,425 @@ pub mod strings {
     pub fn string_to_c_char(r_string: String) -> *mut c_char {
         CString::new(r_string).unwrap().into_raw()
     }
+
+    /// Releases a string returned by any function in the library.
+    #[cfg_attr(feature = "legacy-ffi", no_mangle)]
+    pub unsafe extern "C" fn string_destroy(string: *mut c_char) {
+        ffi_guard!({
+            if !string.is_null() {
+                let _ = CString::from_raw(string);
+            }
+        })
+    }
+
+    /// A string handed to the host as UTF-16 code units, without a terminator, so Java
+    /// strings round trip without going through modified UTF-8. Owned by the caller and
+    /// released with `utf16_string_destroy`.
+    #[repr(C)]
+    #[derive(Debug)]
+    pub struct Utf16String {
+        pub len: i64,
+        pub data: *mut u16,
+    }
+
+    pub fn string_to_utf16(r_string: &str) -> Utf16String {
+        let units: Vec<u16> = r_string.encode_utf16().collect();
+        if units.is_empty() {
+            return Utf16String { len: 0, data: ptr::null_mut() };
+        }
+        let units = units.into_boxed_slice();
+        let len = units.len() as i64;
+        Utf16String {
+            len: len,
+            data: Box::into_raw(units) as *mut u16,
+        }
+    }
+
+    /// Reads `len` code units. Unpaired surrogates become U+FFFD. A null string reads as
+    /// empty.
+    pub unsafe fn utf16_to_string(data: *const u16, len: usize) -> String {
+        if len == 0 {
+            return "".to_string();
+        }
+        if data.is_null() {
+            null_argument("utf16_to_string", "string", ptr::null_mut());
+            return "".to_string();
+        }
+        String::from_utf16_lossy(slice::from_raw_parts(data, len))
+    }
+
+    #[cfg_attr(feature = "legacy-ffi", no_mangle)]
+    pub unsafe extern "C" fn utf16_string_destroy(string: Utf16String) {
+        ffi_guard!({
+            if !string.data.is_null() {
+                let _ = Box::from_raw(slice::from_raw_parts_mut(string.data, string.len as usize));
+            }
+        })
+    }
+
+    /// Reads a NUL terminated UTF-16 string, such as a Windows `wchar_t*` or a .NET string
+    /// marshalled as `LPWStr`. Unpaired surrogates become U+FFFD. A null string reads as
+    /// empty.
+    pub unsafe fn wide_to_string(wide: *const u16) -> String {
+        if wide.is_null() {
+            null_argument("wide_to_string", "string", ptr::null_mut());
+            return "".to_string();
+        }
+        let mut len = 0;
+        while *wide.add(len) != 0 {
+            len += 1;
+        }
+        String::from_utf16_lossy(slice::from_raw_parts(wide, len))
+    }
+
+    /// A NUL terminated UTF-16 copy of `r_string`, cut at any NUL it contains. Owned by the
+    /// caller and released with `wide_string_destroy`.
+    pub fn string_to_wide(r_string: &str) -> *mut u16 {
+        let mut units: Vec<u16> = r_string.encode_utf16().take_while(|&unit| unit != 0).collect();
+        units.push(0);
+        Box::into_raw(units.into_boxed_slice()) as *mut u16
+    }
+
+    #[cfg_attr(feature = "legacy-ffi", no_mangle)]
+    pub unsafe extern "C" fn wide_string_destroy(string: *mut u16) {
+        ffi_guard!({
+            if string.is_null() {
+                return;
+            }
+            let mut len = 1;
+            while *string.add(len - 1) != 0 {
+                len += 1;
+            }
+            let _ = Box::from_raw(slice::from_raw_parts_mut(string, len));
+        })
+    }
+
+    #[cfg(test)]
+    mod test {
+        use std::ptr;
+
+        use super::{
+            string_to_utf16,
+            string_to_wide,
+            utf16_string_destroy,
+            utf16_to_string,
+            wide_string_destroy,
+            wide_to_string,
+        };
+
+        #[test]
+        fn test_utf16_strings() {
+            let name = "call mom \u{1F469}\u{200D}\u{1F467}";
+            let units = string_to_utf16(name);
+            assert_eq!(units.len, name.encode_utf16().count() as i64);
+            unsafe {
+                assert_eq!(utf16_to_string(units.data, units.len as usize), name);
+                utf16_string_destroy(units);
+                assert_eq!(utf16_to_string([0x0061u16, 0xd83d].as_ptr(), 2), "a\u{FFFD}");
+            }
+            assert!(string_to_utf16("").data.is_null());
+        }
+
+        #[test]
+        fn test_wide_strings() {
+            let path = "C:\\Users\\Zoë\\AppData\\Local\\toodle \u{1F4DD}.db";
+            let wide = string_to_wide(path);
+            unsafe {
+                assert_eq!(*wide.add(path.encode_utf16().count()), 0);
+                assert_eq!(wide_to_string(wide), path);
+                wide_string_destroy(wide);
+
+                let cut = string_to_wide("milk\0eggs");
+                assert_eq!(wide_to_string(cut), "milk");
+                wide_string_destroy(cut);
+
+                assert_eq!(wide_to_string([0x0061u16, 0xdc00, 0].as_ptr()), "a\u{FFFD}");
+                assert_eq!(wide_to_string(ptr::null()), "");
+                wide_string_destroy(ptr::null_mut());
+            }
+        }
+    }
+}
+
+pub mod buffers {
+    use std::ptr;
+    use std::slice;
+
+    /// Bytes handed to the host, owned by the caller and released with `byte_buffer_destroy`.
+    #[repr(C)]
+    #[derive(Debug)]
+    pub struct ByteBuffer {
+        pub len: i64,
+        pub data: *mut u8,
+    }
+
+    impl Default for ByteBuffer {
+        fn default() -> ByteBuffer {
+            ByteBuffer { len: 0, data: ptr::null_mut() }
+        }
+    }
+
+    impl From<Vec<u8>> for ByteBuffer {
+        fn from(bytes: Vec<u8>) -> ByteBuffer {
+            if bytes.is_empty() {
+                return ByteBuffer { len: 0, data: ptr::null_mut() };
+            }
+            let bytes = bytes.into_boxed_slice();
+            let len = bytes.len() as i64;
+            ByteBuffer {
+                len: len,
+                data: Box::into_raw(bytes) as *mut u8,
+            }
+        }
+    }
+
+    #[cfg_attr(feature = "legacy-ffi", no_mangle)]
+    pub unsafe extern "C" fn byte_buffer_destroy(buffer: ByteBuffer) {
+        ffi_guard!({
+            if !buffer.data.is_null() {
+                let _ = Box::from_raw(slice::from_raw_parts_mut(buffer.data, buffer.len as usize));
+            }
+        })
+    }
+}
+
+pub mod lists {
+    use std::mem::ManuallyDrop;
+    use std::ptr;
+
+    /// A list handed to the host as `{ data, len, cap }`. Each element is boxed, so the host
+    /// reads `data[i]` as a pointer to an opaque struct. The list owns its elements: release
+    /// it with the destroy function for its element type, never the elements one by one.
+    #[repr(C)]
+    #[derive(Debug)]
+    pub struct FfiList<T> {
+        pub data: *mut *mut T,
+        pub len: usize,
+        pub cap: usize,
+    }
+
+    impl<T> FfiList<T> {
+        pub fn empty() -> FfiList<T> {
+            FfiList { data: ptr::null_mut(), len: 0, cap: 0 }
+        }
+
+        /// Borrows the elements of a list this library handed out.
+        pub unsafe fn as_vec(&self) -> Vec<&T> {
+            if self.data.is_null() {
+                return vec![];
+            }
+            (0..self.len).map(|i| &**self.data.add(i)).collect()
+        }
+
+        /// Takes back a list this library handed out.
+        pub unsafe fn into_vec(self) -> Vec<T> {
+            if self.data.is_null() {
+                return vec![];
+            }
+            let boxes = Vec::from_raw_parts(self.data, self.len, self.cap);
+            boxes.into_iter().map(|element| *Box::from_raw(element)).collect()
+        }
+    }
+
+    impl<T> Default for FfiList<T> {
+        fn default() -> FfiList<T> {
+            FfiList::empty()
+        }
+    }
+
+    impl<T> From<Vec<T>> for FfiList<T> {
+        fn from(elements: Vec<T>) -> FfiList<T> {
+            if elements.is_empty() {
+                return FfiList::empty();
+            }
+            let boxes: Vec<*mut T> = elements.into_iter().map(|element| Box::into_raw(Box::new(element))).collect();
+            let mut boxes = ManuallyDrop::new(boxes);
+            FfiList {
+                data: boxes.as_mut_ptr(),
+                len: boxes.len(),
+                cap: boxes.capacity(),
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod test {
+        use super::FfiList;
+
+        #[test]
+        fn test_ffi_list() {
+            let list = FfiList::from(vec!["milk".to_string(), "eggs".to_string()]);
+            assert_eq!(list.len, 2);
+            assert_eq!(unsafe { &**list.data.add(1) }, "eggs");
+            assert_eq!(unsafe { list.as_vec() }, vec!["milk", "eggs"]);
+            assert_eq!(unsafe { list.into_vec() }, vec!["milk".to_string(), "eggs".to_string()]);
+            assert!(unsafe { FfiList::<String>::empty().into_vec() }.is_empty());
+        }
+    }
+}
+
+/// Functions that can fail take an `error: *mut ExternError` as their last argument. On
+/// failure they fill it in and return false, null or -1, whichever their return type allows;
+/// the category is the code the host switches on and the message is for logs and bug reports.
+/// Panics are caught at the boundary with `ffi_guard!` and reported as `ErrorCategory::Bug`
+/// rather than unwinding into Swift or Java.
+pub mod errors {
+    use std::any::Any;
+    use std::ffi::CString;
+    use std::os::raw::c_char;
+    use std::panic::{
+        self,
+        AssertUnwindSafe,
+    };
+    use std::ptr;
+
+    use strings::string_to_c_char;
+
+    /// What the host should do about an error.
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum ErrorCategory {
+        /// No error occurred.
+        None = 0,
+        /// Transient, e.g. the database is busy. Try again later.
+        Retryable = 1,
+        /// The user has to fix something, e.g. free up space or correct what they typed.
+        NeedsUserInput = 2,
+        /// The credentials or key are wrong. Ask the user to sign in again.
+        NeedsReauth = 3,
+        /// The stored data is damaged. Offer to restore from a backup and report it.
+        DataCorruption = 4,
+        /// A defect in the library or in how it was called. Report it.
+        Bug = 5,
+        /// Another process, e.g. a share extension, is using the database. Nothing was
+        /// written; try again shortly.
+        Busy = 6,
+    }
+
+    /// Filled in by functions taking an `ExternError` out parameter when they fail. The
+    /// message is owned by the caller and released with `error_message_destroy`.
+    #[repr(C)]
+    #[derive(Debug)]
+    pub struct ExternError {
+        pub category: ErrorCategory,
+        pub message: *mut c_char,
+    }
+
+    impl Default for ExternError {
+        fn default() -> ExternError {
+            ExternError {
+                category: ErrorCategory::None,
+                message: ptr::null_mut(),
+            }
+        }
+    }
+
+    /// The `NSError` domain for errors from the library. The code is the `ErrorCategory` and
+    /// the localized description the message.
+    pub const ERROR_DOMAIN: &'static str = "org.mozilla.toodle";
+
+    /// Records an error in `out`, which may be null if the caller is not interested. A
+    /// message `out` already holds is released first, as `extern_error_clear` would.
+    pub unsafe fn set_extern_error(out: *mut ExternError, category: ErrorCategory, message: String) {
+        if let Some(out) = out.as_mut() {
+            if !out.message.is_null() {
+                let _ = CString::from_raw(out.message);
+            }
+            out.category = category;
+            out.message = string_to_c_char(message.replace('\0', ""));
+        }
+    }
+
+    fn panic_message(payload: &(dyn Any + Send)) -> String {
+        if let Some(message) = payload.downcast_ref::<&str>() {
+            message.to_string()
+        } else if let Some(message) = payload.downcast_ref::<String>() {
+            message.clone()
+        } else {
+            "unknown panic".to_string()
+        }
+    }
+
+    /// Runs `f`, returning `failed` and recording an `ErrorCategory::Bug` in `out` if it panics.
+    pub unsafe fn catch_panic<T, F>(out: *mut ExternError, failed: T, f: F) -> T
+    where F: FnOnce() -> T {
+        match panic::catch_unwind(AssertUnwindSafe(f)) {
+            Ok(value) => value,
+            Err(payload) => {
+                let message = panic_message(&*payload);
+                log_error!("caught panic at the FFI boundary {:?}", message);
+                set_extern_error(out, ErrorCategory::Bug, message);
+                failed
+            },
+        }
+    }
+
+    #[cfg_attr(feature = "legacy-ffi", no_mangle)]
+    pub unsafe extern "C" fn error_message_destroy(message: *mut c_char) {
+        ffi_guard!({
+            if !message.is_null() {
+                let _ = CString::from_raw(message);
+            }
+        })
+    }
+
+    /// Releases the message and resets the error to `ErrorCategory::None`, so one error can
+    /// be passed to call after call, as an `NSError` out parameter is.
+    #[cfg_attr(feature = "legacy-ffi", no_mangle)]
+    pub unsafe extern "C" fn extern_error_clear(error: *mut ExternError) {
+        ffi_guard!({
+            if let Some(error) = error.as_mut() {
+                error_message_destroy(error.message);
+                *error = ExternError::default();
+            }
+        })
+    }
+
+    #[cfg(test)]
+    mod test {
+        use std::ffi::CStr;
+
+        use super::{
+            catch_panic,
+            error_message_destroy,
+            extern_error_clear,
+            set_extern_error,
+            ErrorCategory,
+            ExternError,
+        };
+
+        #[test]
+        fn test_catch_panic() {
+            let mut error = ExternError::default();
+            assert_eq!(unsafe { catch_panic(&mut error, -1, || 7) }, 7);
+            assert_eq!(error.category, ErrorCategory::None);
+
+            let failed = unsafe { catch_panic(&mut error, -1, || -> i64 { panic!("no such {}", "item") }) };
+            assert_eq!(failed, -1);
+            assert_eq!(error.category, ErrorCategory::Bug);
+            assert_eq!(unsafe { CStr::from_ptr(error.message) }.to_str().unwrap(), "no such item");
+            unsafe { error_message_destroy(error.message) };
+        }
+
+        #[test]
+        fn test_set_extern_error_replaces_the_message() {
+            let mut error = ExternError::default();
+            unsafe {
+                set_extern_error(&mut error, ErrorCategory::Busy, "locked".to_string());
+                set_extern_error(&mut error, ErrorCategory::Bug, "no such item".to_string());
+                assert_eq!(error.category, ErrorCategory::Bug);
+                assert_eq!(CStr::from_ptr(error.message).to_str().unwrap(), "no such item");
+                extern_error_clear(&mut error);
+            }
+        }
+
+        #[test]
+        fn test_extern_error_clear() {
+            let mut error = ExternError::default();
+            unsafe { set_extern_error(&mut error, ErrorCategory::Busy, "locked".to_string()) };
+            unsafe { extern_error_clear(&mut error) };
+            assert_eq!(error.category, ErrorCategory::None);
+            assert!(error.message.is_null());
+        }
+    }
 }
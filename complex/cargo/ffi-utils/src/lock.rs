@@ -0,0 +1,221 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! The lock serializing calls into the library from host threads.
+//!
+//! Stores and list managers keep their state in `Cell`s and `RefCell`s and share one SQLite
+//! connection, so only one thread may use them at a time. Extern functions taking a toodle,
+//! store, list manager or cursor hold this lock for the duration of the call, which lets
+//! hosts call them from any thread, e.g. Android workers and iOS dispatch queues.
+//!
+//! The lock is reentrant: observers and sync callbacks run on the calling thread with the
+//! lock held and may call back into the library. They must not wait on another thread that
+//! does. Work that blocks for long without touching library state, e.g. waiting on the
+//! network, runs in `unlocked` so other threads can get in meanwhile.
+//!
+//! A transaction the host begins in one call and ends in another keeps the lock on its
+//! thread in between, with `hold`, so that other threads' writes cannot join it.
+
+use std::marker::PhantomData;
+use std::mem;
+use std::sync::{
+    Condvar,
+    Mutex,
+    MutexGuard,
+};
+use std::thread::{
+    self,
+    ThreadId,
+};
+
+struct Owner {
+    thread: Option<ThreadId>,
+    depth: usize,
+    /// How many of `depth` are `hold`s rather than `LibraryLock`s.
+    held: usize,
+}
+
+static OWNER: Mutex<Owner> = Mutex::new(Owner { thread: None, depth: 0, held: 0 });
+static RELEASED: Condvar = Condvar::new();
+
+fn owner() -> MutexGuard<'static, Owner> {
+    // a panic while the mutex is held cannot leave `Owner` half updated
+    OWNER.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Held until dropped, on the thread that took it.
+pub struct LibraryLock {
+    _not_send: PhantomData<*const ()>,
+}
+
+/// Blocks until no other thread holds the lock.
+pub fn enter() -> LibraryLock {
+    let me = thread::current().id();
+    let mut owner = owner();
+    while owner.thread.map_or(false, |thread| thread != me) {
+        owner = RELEASED.wait(owner).unwrap_or_else(|poisoned| poisoned.into_inner());
+    }
+    owner.thread = Some(me);
+    owner.depth += 1;
+    LibraryLock { _not_send: PhantomData }
+}
+
+impl Drop for LibraryLock {
+    fn drop(&mut self) {
+        let mut owner = owner();
+        owner.depth -= 1;
+        if owner.depth == 0 {
+            owner.thread = None;
+            RELEASED.notify_one();
+        }
+    }
+}
+
+/// Keeps the lock on this thread once the current call has returned, until `release`.
+/// Other threads wait for it meanwhile, even while this thread is in `unlocked`.
+pub fn hold() {
+    let library_lock = enter();
+    owner().held += 1;
+    mem::forget(library_lock);
+}
+
+/// Whether this thread has a `hold` on the lock.
+pub fn is_held() -> bool {
+    let owner = owner();
+    owner.thread == Some(thread::current().id()) && owner.held > 0
+}
+
+/// Gives up a `hold`. Panics unless called on the thread that took it.
+pub fn release() {
+    assert!(is_held(), "the library lock is not held by this thread");
+    owner().held -= 1;
+    drop(LibraryLock { _not_send: PhantomData });
+}
+
+/// Runs `f` with the lock released, if this thread holds it and has no `hold` on it, and
+/// takes it back before returning. `f` must not touch stores or list managers, and whatever
+/// the caller had borrowed from them may have changed by the time this returns.
+pub fn unlocked<F, R>(f: F) -> R
+where F: FnOnce() -> R {
+    let me = thread::current().id();
+    let depth = {
+        let mut owner = owner();
+        if owner.thread != Some(me) || owner.held > 0 {
+            drop(owner);
+            return f();
+        }
+        let depth = owner.depth;
+        owner.thread = None;
+        owner.depth = 0;
+        RELEASED.notify_one();
+        depth
+    };
+    // taken back on unwinding too, so the caller's `LibraryLock`s still balance
+    let _relock = Relock(depth);
+    f()
+}
+
+struct Relock(usize);
+
+impl Drop for Relock {
+    fn drop(&mut self) {
+        let _library_lock = enter();
+        owner().depth = self.0 + 1;
+        // `_library_lock` leaves the depth the caller had
+    }
+}
+
+/// Holds the library lock until the end of the enclosing block.
+#[macro_export]
+macro_rules! ffi_lock {
+    () => {
+        let _library_lock = $crate::lock::enter();
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+    use std::sync::atomic::{
+        AtomicUsize,
+        Ordering,
+    };
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+
+    use super::{
+        enter,
+        hold,
+        is_held,
+        release,
+        unlocked,
+    };
+
+    #[test]
+    fn test_lock_is_exclusive_and_reentrant() {
+        let inside = Arc::new(AtomicUsize::new(0));
+        let threads: Vec<_> = (0..4).map(|_| {
+            let inside = inside.clone();
+            thread::spawn(move || {
+                for _ in 0..100 {
+                    let _outer = enter();
+                    let _inner = enter();
+                    assert_eq!(inside.fetch_add(1, Ordering::SeqCst), 0);
+                    inside.fetch_sub(1, Ordering::SeqCst);
+                }
+            })
+        }).collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_unlocked_lets_other_threads_in() {
+        let outer = enter();
+        let inner = enter();
+        let (entered, waiting) = mpsc::channel();
+        let other = thread::spawn(move || {
+            let _library_lock = enter();
+            entered.send(()).unwrap();
+        });
+        unlocked(|| waiting.recv().unwrap());
+        other.join().unwrap();
+        // still held twice: another thread cannot get in until both are dropped
+        drop(inner);
+        let (entered, waiting) = mpsc::channel();
+        let other = thread::spawn(move || {
+            let _library_lock = enter();
+            entered.send(()).unwrap();
+        });
+        assert!(waiting.recv_timeout(Duration::from_millis(50)).is_err());
+        drop(outer);
+        waiting.recv().unwrap();
+        other.join().unwrap();
+    }
+
+    #[test]
+    fn test_hold_keeps_other_threads_out() {
+        hold();
+        assert!(is_held());
+        let (entered, waiting) = mpsc::channel();
+        let other = thread::spawn(move || {
+            assert!(!is_held());
+            let _library_lock = enter();
+            entered.send(()).unwrap();
+        });
+        unlocked(|| assert!(waiting.recv_timeout(Duration::from_millis(50)).is_err()));
+        release();
+        assert!(!is_held());
+        waiting.recv().unwrap();
+        other.join().unwrap();
+    }
+}
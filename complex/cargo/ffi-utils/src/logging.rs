@@ -0,0 +1,155 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! Log messages go to a callback the host registers, so that they end up in os_log on
+//! iOS, logcat on Android and wherever a test wants them. Until one is registered they are
+//! written to logcat on Android, through `android::log`, to the debugger output on Windows,
+//! where a desktop app has no console, and printed to stdout elsewhere.
+
+use std::ffi::CString;
+use std::fmt;
+use std::os::raw::c_char;
+use std::sync::Mutex;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub enum LogLevel {
+    Debug = 0,
+    Info = 1,
+    Warn = 2,
+    Error = 3,
+}
+
+impl LogLevel {
+    pub fn from_i32(value: i32) -> Option<LogLevel> {
+        match value {
+            0 => Some(LogLevel::Debug),
+            1 => Some(LogLevel::Info),
+            2 => Some(LogLevel::Warn),
+            3 => Some(LogLevel::Error),
+            _ => None,
+        }
+    }
+}
+
+/// Called with each message at or above the logger's level. `tag` names the module that
+/// logged it. Both strings are only valid for the duration of the call.
+pub type LogCallback = extern "C" fn(level: LogLevel, tag: *const c_char, message: *const c_char);
+
+static LOGGER: Mutex<Option<(LogCallback, LogLevel)>> = Mutex::new(None);
+
+/// Sends messages at `min_level` and above to `callback`, or back to the default if it is
+/// `None`.
+pub fn set_logger(callback: Option<LogCallback>, min_level: LogLevel) {
+    let mut logger = LOGGER.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    *logger = callback.map(|callback| (callback, min_level));
+}
+
+/// Called by `log_debug!`, `log_info!`, `log_warn!` and `log_error!`.
+pub fn log(level: LogLevel, tag: &str, message: fmt::Arguments) {
+    // copied out so that the callback may log
+    let logger = *LOGGER.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    match logger {
+        Some((callback, min_level)) => {
+            if level >= min_level {
+                let tag = CString::new(tag).unwrap_or_default();
+                let message = CString::new(message.to_string().replace('\0', "")).unwrap_or_default();
+                callback(level, tag.as_ptr(), message.as_ptr());
+            }
+        },
+        None => default_log(level, tag, message),
+    }
+}
+
+#[cfg(target_os="android")]
+fn default_log(level: LogLevel, tag: &str, message: fmt::Arguments) {
+    ::android::log::write(level, tag, message)
+}
+
+#[cfg(windows)]
+#[link(name = "kernel32")]
+extern "system" {
+    fn OutputDebugStringW(message: *const u16);
+}
+
+/// Shows up in Visual Studio's Output window and in DebugView.
+#[cfg(windows)]
+fn default_log(level: LogLevel, tag: &str, message: fmt::Arguments) {
+    let line = format!("{:?} {}: {}\n", level, tag, message);
+    let wide: Vec<u16> = line.encode_utf16().filter(|&unit| unit != 0).chain(Some(0)).collect();
+    unsafe { OutputDebugStringW(wide.as_ptr()) }
+}
+
+#[cfg(not(any(target_os="android", windows)))]
+fn default_log(_: LogLevel, _: &str, message: fmt::Arguments) {
+    println!("{}", message)
+}
+
+#[macro_export]
+macro_rules! log_at {
+    ($level:ident, $($arg:tt)+) => {
+        $crate::logging::log($crate::logging::LogLevel::$level, module_path!(), format_args!($($arg)+))
+    };
+}
+
+#[macro_export]
+macro_rules! log_debug {
+    ($($arg:tt)+) => { log_at!(Debug, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! log_info {
+    ($($arg:tt)+) => { log_at!(Info, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! log_warn {
+    ($($arg:tt)+) => { log_at!(Warn, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! log_error {
+    ($($arg:tt)+) => { log_at!(Error, $($arg)+) };
+}
+
+#[cfg(test)]
+mod test {
+    use std::ffi::CStr;
+    use std::os::raw::c_char;
+    use std::sync::Mutex;
+
+    use super::{
+        set_logger,
+        LogLevel,
+    };
+
+    static LOGGED: Mutex<Vec<(LogLevel, String, String)>> = Mutex::new(Vec::new());
+
+    extern "C" fn capture(level: LogLevel, tag: *const c_char, message: *const c_char) {
+        let read = |s: *const c_char| unsafe { CStr::from_ptr(s) }.to_string_lossy().into_owned();
+        LOGGED.lock().unwrap().push((level, read(tag), read(message)));
+    }
+
+    #[test]
+    fn test_logger() {
+        set_logger(Some(capture), LogLevel::Info);
+        log_debug!("too chatty {}", 1);
+        log_warn!("disk nearly full: {}%", 97);
+        set_logger(None, LogLevel::Debug);
+        log_error!("printed instead");
+
+        // other tests log too
+        let logged: Vec<_> = LOGGED.lock().unwrap().iter()
+            .filter(|&&(_, ref tag, _)| tag == module_path!())
+            .cloned()
+            .collect();
+        assert_eq!(logged, vec![(LogLevel::Warn, module_path!().to_string(), "disk nearly full: 97%".to_string())]);
+    }
+}
@@ -0,0 +1,91 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! An allocator for memory that crosses the boundary, for hosts such as Dart whose own
+//! allocator differs by platform. Implementing `dart:ffi`'s `Allocator` with these lets a
+//! Flutter client build arguments, and arenas of them, the same way everywhere.
+
+use std::alloc::{
+    alloc,
+    dealloc,
+    Layout,
+};
+use std::os::raw::c_void;
+use std::ptr;
+
+/// Room for the size in front of each block, which also keeps the block aligned for any
+/// type.
+const HEADER: usize = 16;
+
+fn layout(size: usize) -> Option<Layout> {
+    Layout::from_size_align(size.checked_add(HEADER)?, HEADER).ok()
+}
+
+/// `size` bytes, uninitialized and aligned for any type, released with `toodle_free`.
+/// Returns null if they could not be allocated.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub extern "C" fn toodle_alloc(size: usize) -> *mut c_void {
+    ffi_guard!({
+        let layout = match layout(size) {
+            Some(layout) => layout,
+            None => return ptr::null_mut(),
+        };
+        unsafe {
+            let block = alloc(layout);
+            if block.is_null() {
+                return ptr::null_mut();
+            }
+            *(block as *mut usize) = size;
+            block.add(HEADER) as *mut c_void
+        }
+    })
+}
+
+/// Releases memory from `toodle_alloc`, and nothing else. Does nothing for null.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn toodle_free(memory: *mut c_void) {
+    ffi_guard!({
+        if memory.is_null() {
+            return;
+        }
+        let block = (memory as *mut u8).sub(HEADER);
+        let size = *(block as *const usize);
+        dealloc(block, layout(size).unwrap());
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::ptr;
+    use std::slice;
+
+    use super::{
+        toodle_alloc,
+        toodle_free,
+    };
+
+    #[test]
+    fn test_alloc() {
+        let memory = toodle_alloc(40);
+        assert!(!memory.is_null());
+        assert_eq!(memory as usize % 16, 0);
+        unsafe {
+            let bytes = slice::from_raw_parts_mut(memory as *mut u8, 40);
+            for (index, byte) in bytes.iter_mut().enumerate() {
+                *byte = index as u8;
+            }
+            assert_eq!(bytes[39], 39);
+            toodle_free(memory);
+            toodle_free(ptr::null_mut());
+            toodle_free(toodle_alloc(0));
+        }
+        assert!(toodle_alloc(usize::max_value()).is_null());
+    }
+}
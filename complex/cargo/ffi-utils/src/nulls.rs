@@ -0,0 +1,128 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! Null pointers passed where an object is required. Instead of dereferencing them, extern
+//! functions log the mistake, fill in their `ExternError` if they take one, and return a
+//! default: false, null, 0 or an empty list. Hosts can opt in to an assertion instead, so
+//! the mistake is caught where it is made during development.
+
+use std::process;
+use std::sync::atomic::{
+    AtomicBool,
+    Ordering,
+};
+
+use breadcrumbs;
+use errors::{
+    set_extern_error,
+    ErrorCategory,
+    ExternError,
+};
+
+static ASSERT_ON_NULL: AtomicBool = AtomicBool::new(false);
+
+/// Makes null arguments abort in debug builds of the library. Release builds always return
+/// the default.
+pub fn set_assert_on_null(enabled: bool) {
+    ASSERT_ON_NULL.store(enabled, Ordering::SeqCst);
+}
+
+/// Called by `ffi_ref!` and `ffi_mut!` when `argument` is null.
+pub unsafe fn null_argument(function: &str, argument: &str, error: *mut ExternError) {
+    let message = format!("{} called with a null {}", function, argument);
+    log_error!("{}", message);
+    breadcrumbs::leave("null_argument");
+    if cfg!(debug_assertions) && ASSERT_ON_NULL.load(Ordering::SeqCst) {
+        // a panic would be caught by `ffi_guard!` and reported like any other bug
+        process::abort();
+    }
+    set_extern_error(error, ErrorCategory::Bug, message);
+}
+
+/// Borrows what `$ptr` points at, or returns `$default` (`Default::default()` if left out)
+/// from the enclosing function if it is null, filling in `$error` if given.
+#[macro_export]
+macro_rules! ffi_ref {
+    ($function:expr, $ptr:ident) => {
+        ffi_ref!($function, $ptr, Default::default())
+    };
+    ($function:expr, $ptr:ident, $default:expr) => {
+        ffi_ref!($function, $ptr, $default, ::std::ptr::null_mut())
+    };
+    ($function:expr, $ptr:ident, $default:expr, $error:expr) => {
+        match $ptr.as_ref() {
+            Some(value) => value,
+            None => {
+                $crate::nulls::null_argument($function, stringify!($ptr), $error);
+                return $default;
+            },
+        }
+    };
+}
+
+/// Like `ffi_ref!`, for arguments the function modifies.
+#[macro_export]
+macro_rules! ffi_mut {
+    ($function:expr, $ptr:ident) => {
+        ffi_mut!($function, $ptr, Default::default())
+    };
+    ($function:expr, $ptr:ident, $default:expr) => {
+        ffi_mut!($function, $ptr, $default, ::std::ptr::null_mut())
+    };
+    ($function:expr, $ptr:ident, $default:expr, $error:expr) => {
+        match $ptr.as_mut() {
+            Some(value) => value,
+            None => {
+                $crate::nulls::null_argument($function, stringify!($ptr), $error);
+                return $default;
+            },
+        }
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use std::ffi::CStr;
+    use std::ptr;
+
+    use errors::{
+        error_message_destroy,
+        ErrorCategory,
+        ExternError,
+    };
+
+    unsafe fn length(string: *const String, error: *mut ExternError) -> i64 {
+        let string = ffi_ref!("length", string, -1, error);
+        string.len() as i64
+    }
+
+    unsafe fn clear(string: *mut String) -> bool {
+        let string = ffi_mut!("clear", string);
+        string.clear();
+        true
+    }
+
+    #[test]
+    fn test_null_arguments() {
+        let mut milk = "milk".to_string();
+        let mut error = ExternError::default();
+        unsafe {
+            assert_eq!(length(&milk, &mut error), 4);
+            assert_eq!(error.category, ErrorCategory::None);
+            assert!(clear(&mut milk));
+            assert!(!clear(ptr::null_mut()));
+
+            assert_eq!(length(ptr::null(), &mut error), -1);
+            assert_eq!(error.category, ErrorCategory::Bug);
+            assert_eq!(CStr::from_ptr(error.message).to_str().unwrap(), "length called with a null string");
+            error_message_destroy(error.message);
+        }
+    }
+}
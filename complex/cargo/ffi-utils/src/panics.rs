@@ -0,0 +1,143 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! Keeps panics from unwinding into Swift or Java. Every extern function runs its body in
+//! `ffi_guard!`, which turns a panic into the function's failure value and, if it takes an
+//! `ExternError`, an `ErrorCategory::Bug`.
+
+use std::ptr;
+
+use buffers::ByteBuffer;
+use errors::{
+    catch_panic,
+    ExternError,
+};
+use lists::FfiList;
+use strings::Utf16String;
+
+/// What an extern function returns when its body panicked: false, null, -1, 0 for
+/// unsigned ints and ids, or an empty buffer or list.
+pub trait PanicValue {
+    fn panic_value() -> Self;
+}
+
+impl PanicValue for () {
+    fn panic_value() {}
+}
+
+impl PanicValue for bool {
+    fn panic_value() -> bool {
+        false
+    }
+}
+
+macro_rules! panic_value {
+    ($value:expr, $($ty:ty),*) => {
+        $(impl PanicValue for $ty {
+            fn panic_value() -> $ty {
+                $value
+            }
+        })*
+    };
+}
+
+panic_value!(-1, i32, i64);
+panic_value!(0, u8, u32, u64, usize);
+
+impl<T> PanicValue for *mut T {
+    fn panic_value() -> *mut T {
+        ptr::null_mut()
+    }
+}
+
+impl<T> PanicValue for *const T {
+    fn panic_value() -> *const T {
+        ptr::null()
+    }
+}
+
+impl PanicValue for ByteBuffer {
+    fn panic_value() -> ByteBuffer {
+        ByteBuffer::default()
+    }
+}
+
+impl PanicValue for Utf16String {
+    fn panic_value() -> Utf16String {
+        Utf16String { len: 0, data: ptr::null_mut() }
+    }
+}
+
+impl<T> PanicValue for FfiList<T> {
+    fn panic_value() -> FfiList<T> {
+        FfiList::empty()
+    }
+}
+
+/// Runs `f`, returning `T::panic_value()` if it panics.
+pub fn guard<T: PanicValue, F: FnOnce() -> T>(f: F) -> T {
+    unsafe { catch_panic(ptr::null_mut(), T::panic_value(), f) }
+}
+
+/// Like `guard`, also recording the panic in `out`, which may be null.
+pub unsafe fn guard_with_error<T: PanicValue, F: FnOnce() -> T>(out: *mut ExternError, f: F) -> T {
+    catch_panic(out, T::panic_value(), f)
+}
+
+/// Runs the body of an extern function, catching panics. Pass the function's
+/// `ExternError` first if it takes one.
+#[macro_export]
+macro_rules! ffi_guard {
+    ($body:block) => {
+        $crate::panics::guard(|| $body)
+    };
+    ($error:expr, $body:block) => {
+        $crate::panics::guard_with_error($error, || $body)
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use std::ffi::CStr;
+    use std::ptr;
+
+    use errors::{
+        error_message_destroy,
+        ErrorCategory,
+        ExternError,
+    };
+
+    unsafe fn fails(error: *mut ExternError) -> *mut u8 {
+        ffi_guard!(error, {
+            let empty: Vec<u8> = vec![];
+            Box::into_raw(Box::new(empty[1]))
+        })
+    }
+
+    fn counts() -> i64 {
+        ffi_guard!({
+            let counts: Vec<i64> = vec![];
+            counts[0]
+        })
+    }
+
+    #[test]
+    fn test_panics_become_errors() {
+        let mut error = ExternError::default();
+        unsafe {
+            assert!(fails(&mut error).is_null());
+            assert_eq!(error.category, ErrorCategory::Bug);
+            assert!(CStr::from_ptr(error.message).to_str().unwrap().contains("index out of bounds"));
+            error_message_destroy(error.message);
+            assert!(fails(ptr::null_mut()).is_null());
+        }
+        assert_eq!(counts(), -1);
+    }
+}
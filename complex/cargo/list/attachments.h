@@ -0,0 +1,24 @@
+#ifndef attachments_h
+#define attachments_h
+
+#include <stdbool.h>
+#include <stdint.h>
+#include "errors.h"
+
+struct list_manager;
+
+// `json` is an object whose `kind` is "link" with `url` and an optional `title`, "contact"
+// with a `vcard`, or "location" with `latitude`, `longitude` and an optional `label`. Names,
+// phone numbers and email addresses in contacts, link titles and URLs, and location labels
+// are found by name searches. Returns the attachment as JSON with its `id` added, or NULL and
+// fills in `error` if the payload is not understood (ErrorCategoryBug) or valid
+// (ErrorCategoryNeedsUserInput), there is no item with that uuid (ErrorCategoryBug), or it
+// could not be written.
+char* _Nullable list_manager_add_attachment(const struct list_manager* _Nonnull manager, const char* _Nonnull item_uuid, const char* _Nonnull json, struct extern_error* _Nullable error);
+// Returns the item's attachments as a JSON array, oldest first.
+char* _Nonnull list_manager_item_attachments(const struct list_manager* _Nonnull manager, const char* _Nonnull item_uuid);
+// Returns false if there is no attachment with that id, or if it could not be removed, filling
+// in `error`.
+bool list_manager_remove_attachment(const struct list_manager* _Nonnull manager, int64_t id, struct extern_error* _Nullable error);
+
+#endif /* attachments_h */
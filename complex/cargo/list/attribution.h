@@ -0,0 +1,15 @@
+#ifndef attribution_h
+#define attribution_h
+
+struct list_manager;
+
+// Sets the account or device that subsequent edits are attributed to. Pass NULL to stop
+// attributing edits.
+const void list_manager_set_editor(const struct list_manager* _Nonnull manager, const char* _Nullable editor);
+
+// Returns the item as JSON with a `last_edited` object mapping field names ("name",
+// "due_date", "completion_date", "labels") to the `editor` and `edited_at` of their last
+// change, or NULL if there is no such item.
+char* _Nullable list_manager_attributed_item_json(const struct list_manager* _Nonnull manager, const char* _Nonnull uuid);
+
+#endif /* attribution_h */
@@ -0,0 +1,27 @@
+#ifndef autosave_h
+#define autosave_h
+
+#include <stdbool.h>
+#include "errors.h"
+
+struct list_manager;
+struct item;
+
+// Queues the item to be written on the next flush instead of writing it now. Assigns the
+// item a uuid if it does not have one. Returns false and fills in `error` if the auto-save
+// interval had elapsed and the flush this started failed; the item stays queued.
+bool item_save(const struct list_manager* _Nonnull manager, struct item* _Nonnull item, struct extern_error* _Nullable error);
+bool item_is_unsaved(const struct list_manager* _Nonnull manager, const struct item* _Nonnull item);
+
+// Writes every queued item. Call this when the app is backgrounded. Returns false and fills
+// in `error` if they could not be written; they stay queued. Inside a transaction they also
+// stay queued until it commits.
+bool list_manager_flush(const struct list_manager* _Nonnull manager, struct extern_error* _Nullable error);
+bool list_manager_has_unsaved_changes(const struct list_manager* _Nonnull manager);
+// A negative interval turns auto-save off, which is the default.
+const void list_manager_set_autosave_interval(const struct list_manager* _Nonnull manager, int64_t seconds);
+// Flushes if queued items have waited for the auto-save interval. Call this from a timer.
+// Returns true if anything was written, and false, filling in `error`, if it failed.
+bool list_manager_autosave(const struct list_manager* _Nonnull manager, struct extern_error* _Nullable error);
+
+#endif /* autosave_h */
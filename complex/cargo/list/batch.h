@@ -0,0 +1,22 @@
+#ifndef batch_h
+#define batch_h
+
+#include "errors.h"
+
+struct list_manager;
+
+// Applies many writes in one call and one transaction. `changes` is a JSON array of objects
+// with an "op" key:
+//   {"op": "create_item", "item": {...}}       creates the item under a new uuid
+//   {"op": "save_item", "item": {...}}         like list_manager_update_item, recreating it if deleted
+//   {"op": "delete_item", "uuid": "..."}
+//   {"op": "create_label", "name": "...", "color": "..."}
+//   {"op": "delete_label", "name": "..."}
+// with items as item_to_json writes them. Returns a JSON array with an object per change, in
+// order: {"ok": true}, plus "uuid" for a created item and "deleted" for a delete, or
+// {"ok": false, "category": <error_category>, "error": "..."}. A change that fails is undone
+// on its own and the others are kept. Returns NULL and fills in `error`, writing nothing, if
+// the JSON is not understood or the transaction could not be committed.
+char* _Nullable list_manager_apply_changes(const struct list_manager* _Nonnull manager, const char* _Nonnull changes, struct extern_error* _Nullable error);
+
+#endif /* batch_h */
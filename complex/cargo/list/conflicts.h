@@ -0,0 +1,30 @@
+#ifndef conflicts_h
+#define conflicts_h
+
+#include <stdbool.h>
+#include <stdint.h>
+
+struct list_manager;
+
+// What sync does with an item changed both locally and on the server since the last sync.
+// Whichever is picked, list observers get a ChangeEventKindSyncConflict event for the item.
+enum conflict_strategy {
+    // Keep the local version and wait for list_manager_resolve_conflict. The default.
+    ConflictStrategyManual = 0,
+    // Keep the version edited most recently.
+    ConflictStrategyLastWriterWins = 1,
+    // Take each field from the version that edited it most recently.
+    ConflictStrategyFieldMerge = 2,
+    // Keep the local version and add the remote one as a new item.
+    ConflictStrategyDuplicateBoth = 3,
+};
+
+// Returns the conflicts waiting for the user as a JSON array of objects with `id`,
+// `item_uuid`, `local`, `remote` and `created_at` keys.
+char* _Nonnull list_manager_conflicts_pending(const struct list_manager* _Nonnull manager);
+
+// `choice` is "local", "remote" or a merged item as JSON. Returns false if the choice could
+// not be understood or there is no pending conflict with that id.
+bool list_manager_resolve_conflict(const struct list_manager* _Nonnull manager, const int64_t id, const char* _Nonnull choice);
+
+#endif /* conflicts_h */
@@ -0,0 +1,52 @@
+#ifndef cursors_h
+#define cursors_h
+
+#include <stdbool.h>
+#include <stdint.h>
+#include "filters.h"
+
+struct list_manager;
+struct cursor;
+
+#define CURSOR_KIND_ITEMS 0
+#define CURSOR_KIND_LABELS 1
+
+// filter may be NULL to iterate over every item. A page_size of 0 uses the default.
+struct cursor* _Nonnull list_manager_items_cursor(const struct list_manager* _Nonnull manager, const struct item_filter* _Nullable filter, int64_t page_size);
+struct cursor* _Nonnull list_manager_labels_cursor(const struct list_manager* _Nonnull manager, int64_t page_size);
+// utc_offset is the host's current offset from UTC in seconds, so "today" follows the
+// user's local day.
+struct cursor* _Nonnull list_manager_items_due_today(const struct list_manager* _Nonnull manager, int64_t utc_offset, int64_t page_size);
+struct cursor* _Nonnull list_manager_items_overdue(const struct list_manager* _Nonnull manager, int64_t utc_offset, int64_t page_size);
+struct cursor* _Nonnull list_manager_items_upcoming(const struct list_manager* _Nonnull manager, int64_t days, int64_t utc_offset, int64_t page_size);
+const void cursor_destroy(struct cursor* _Nonnull cursor);
+const int cursor_kind(const struct cursor* _Nonnull cursor);
+const int64_t cursor_count(const struct cursor* _Nonnull cursor);
+// A cursor becomes stale when the store is reloaded, after which it yields no more rows.
+const bool cursor_is_stale(const struct cursor* _Nonnull cursor);
+// Returns a struct item* or struct label* depending on the cursor kind, or NULL once the
+// cursor is exhausted. Release rows with item_destroy or label_destroy.
+void* _Nullable cursor_next(struct cursor* _Nonnull cursor);
+
+// Reading rows a column at a time, like android.database.Cursor, e.g. to back a
+// ContentProvider. Item cursors have the columns _id, uuid, name, due_date, completion_date
+// and labels; label cursors _id, name and color. _id is the row's position, dates are
+// seconds since 1970 and labels is a JSON array of label names.
+#define CURSOR_FIELD_TYPE_NULL 0
+#define CURSOR_FIELD_TYPE_INTEGER 1
+#define CURSOR_FIELD_TYPE_STRING 3
+
+int cursor_column_count(const struct cursor* _Nonnull cursor);
+// NULL if there is no such column. Release the name with string_destroy.
+char* _Nullable cursor_column_name(const struct cursor* _Nonnull cursor, int column);
+// Moves to the next row, returning false once there are none. The getters read the row moved
+// to; they return CURSOR_FIELD_TYPE_NULL, 0 and NULL before the first move, after the last
+// row and for columns that do not exist.
+bool cursor_move_to_next(struct cursor* _Nonnull cursor);
+int cursor_get_type(const struct cursor* _Nonnull cursor, int column);
+// 0 for null and text columns.
+int64_t cursor_get_long(const struct cursor* _Nonnull cursor, int column);
+// Integers are returned as text. NULL for null columns; release others with string_destroy.
+char* _Nullable cursor_get_string(const struct cursor* _Nonnull cursor, int column);
+
+#endif /* cursors_h */
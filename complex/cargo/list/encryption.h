@@ -0,0 +1,18 @@
+#ifndef encryption_h
+#define encryption_h
+
+#include <stdbool.h>
+#include "errors.h"
+
+struct list_manager;
+
+// Opens the database at `path` encrypted with `key`, creating it if needed. Returns NULL if
+// the key is wrong or the SQLite library in use cannot encrypt; the store is never opened
+// in the clear.
+struct list_manager* _Nullable list_manager_open_encrypted(const char* _Nonnull path, const char* _Nonnull key, struct extern_error* _Nullable error);
+
+// Re-encrypts the database with `key`. Returns false if the store is not encrypted or the
+// key could not be changed.
+bool list_manager_rekey(const struct list_manager* _Nonnull manager, const char* _Nonnull key, struct extern_error* _Nullable error);
+
+#endif /* encryption_h */
@@ -0,0 +1,36 @@
+#ifndef events_h
+#define events_h
+
+#include <stdbool.h>
+#include <stdint.h>
+
+struct list_manager;
+
+enum change_event_kind {
+    // Everything previously read may be out of date and should be refetched.
+    ChangeEventKindInvalidated = 0,
+    ChangeEventKindItemAdded = 1,
+    ChangeEventKindItemUpdated = 2,
+    ChangeEventKindItemDeleted = 3,
+    // A label was created or changed.
+    ChangeEventKindLabelChanged = 4,
+    // An open item moved between the Overdue, Today and Later views because the local day
+    // changed. Refresh those views and any reminder scheduled for the item.
+    ChangeEventKindDueBucketChanged = 5,
+    // An item was changed both here and on the sync server. With the manual conflict
+    // strategy it is waiting in list_manager_conflicts_pending; otherwise it was settled.
+    ChangeEventKindSyncConflict = 6,
+    ChangeEventKindLabelDeleted = 7,
+};
+
+// `id` is the uuid of the item or the name of the label that changed, NULL for
+// ChangeEventKindInvalidated. It is only valid for the duration of the call.
+typedef void (*change_callback)(enum change_event_kind kind, const char* _Nullable id, void* _Nullable user_data);
+
+// Calls `callback` on the calling thread after every change made through the list manager,
+// once the change has been committed. Returns an id for list_manager_remove_observer.
+uint64_t list_manager_add_observer(const struct list_manager* _Nonnull manager, change_callback _Nonnull callback, void* _Nullable user_data);
+// Returns false if there is no observer with that id.
+bool list_manager_remove_observer(const struct list_manager* _Nonnull manager, uint64_t id);
+
+#endif /* events_h */
@@ -0,0 +1,11 @@
+#ifndef export_h
+#define export_h
+
+struct list_manager;
+
+// Every item as CSV for spreadsheets, under a uuid,name,due_date,completion_date,labels
+// header. Dates are UTC in ISO 8601 form and labels are separated by ";". Values that a
+// spreadsheet would run as formulas are prefixed with "'".
+char* _Nonnull list_manager_export_csv(const struct list_manager* _Nonnull manager);
+
+#endif /* export_h */
@@ -0,0 +1,20 @@
+#ifndef filters_h
+#define filters_h
+
+#include <stdint.h>
+
+struct item_filter;
+
+struct item_filter* _Nonnull item_filter_new();
+const void item_filter_destroy(struct item_filter* _Nonnull filter);
+const void item_filter_add_label(struct item_filter* _Nonnull filter, const char* _Nonnull label_name);
+// 1 for completed items only, 0 for open items only, -1 for both.
+const void item_filter_set_completed(struct item_filter* _Nonnull filter, int completed);
+const void item_filter_set_due_before(struct item_filter* _Nonnull filter, const int64_t* _Nullable due_before);
+const void item_filter_set_due_after(struct item_filter* _Nonnull filter, const int64_t* _Nullable due_after);
+// Bounds relative to the time the filter runs, so saved filters keep their meaning.
+const void item_filter_set_due_before_from_now(struct item_filter* _Nonnull filter, int64_t seconds);
+const void item_filter_set_due_after_from_now(struct item_filter* _Nonnull filter, int64_t seconds);
+const void item_filter_set_name_contains(struct item_filter* _Nonnull filter, const char* _Nullable name);
+
+#endif /* filters_h */
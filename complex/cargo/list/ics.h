@@ -0,0 +1,21 @@
+#ifndef ics_h
+#define ics_h
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include "errors.h"
+
+struct list_manager;
+
+// Writes every item to `path` as an iCalendar file of VTODOs, for Reminders, Thunderbird and
+// the like. Labels become categories, and the open occurrences of a recurring item one VTODO
+// with a rule. Returns false and fills in `error` if the file could not be written.
+bool list_manager_export_ical(const struct list_manager* _Nonnull manager, const char* _Nonnull path, struct extern_error* _Nullable error);
+// Adds the VTODOs in the `len` bytes of an iCalendar file as new items, creating labels for
+// categories not in the store. Daily and weekly rules become recurring items; other rules
+// are dropped, and times in other zones are read as UTC. Returns the uuids of the new items
+// as a JSON array, or NULL, filling in `error`, if the bytes are not an iCalendar file.
+char* _Nullable list_manager_import_ical(const struct list_manager* _Nonnull manager, const uint8_t* _Nullable bytes, size_t len, struct extern_error* _Nullable error);
+
+#endif /* ics_h */
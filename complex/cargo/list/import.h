@@ -0,0 +1,25 @@
+#ifndef import_h
+#define import_h
+
+#include <stdint.h>
+
+enum import_format {
+    // A header row naming the columns, separated by commas, semicolons or tabs.
+    ImportFormatCsv = 0,
+    // An array of objects, or an object with such an array under "items" as exported.
+    ImportFormatJson = 1,
+};
+
+// Proposes which item field each column of a file to import holds, for the mapping screen,
+// from the column names and the first rows. Returns NULL if `text` is not in `format`, or
+// JSON of the form
+//   {"sampled_rows": 50, "columns": [{"column": "Due", "field": "due_date",
+//    "date_format": "day_month_year", "confidence": 0.75, "samples": ["24/12/2017", ...]}]}
+// `field` is one of "name", "due_date", "completion_date", "completed", "labels" and
+// "ignore"; each but "ignore" is proposed for one column at most. `date_format` is one of
+// "iso8601", "year_month_day", "month_day_year", "day_month_year", "day_month_year_dots" and
+// "unix_seconds", or null for fields that are not dates. `confidence` goes from 0, a guess,
+// to 1, where the column name and every sampled value agree.
+char* _Nullable import_preview(const char* _Nonnull text, enum import_format format);
+
+#endif /* import_h */
@@ -0,0 +1,23 @@
+#ifndef importers_h
+#define importers_h
+
+#include "errors.h"
+
+struct list_manager;
+
+enum import_source {
+    // A Todoist backup or the REST API's array of tasks. Projects and labels become labels.
+    ImportSourceTodoist = 0,
+    // Tasks.json from Google Takeout. Task lists become labels.
+    ImportSourceGoogleTasks = 1,
+};
+
+// Adds the tasks in another app's JSON export as new items, creating labels the store does not
+// have yet. Returns a report as JSON: {"created": [uuid, ...], "skipped": n, "errors":
+// [message, ...]}, `skipped` counting deleted and empty tasks and `errors` the tasks that could
+// not be read. If `text` is not an export from `source`, nothing is imported and `errors` says
+// why. Returns NULL and fills in `error` if the tasks could not be written, in which case none
+// are.
+char* _Nullable list_manager_import_dump(const struct list_manager* _Nonnull manager, const char* _Nonnull text, enum import_source source, struct extern_error* _Nullable error);
+
+#endif /* importers_h */
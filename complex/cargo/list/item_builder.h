@@ -0,0 +1,28 @@
+#ifndef item_builder_h
+#define item_builder_h
+
+#include <stdbool.h>
+#include <stdint.h>
+#include "errors.h"
+
+struct list_manager;
+struct item_builder;
+
+// Builds an item with all its fields and writes it once, with its labels, in one
+// transaction, instead of creating an empty item and saving it after every change.
+
+struct item_builder* _Nonnull item_builder_new(void);
+// Releases a builder that was not committed.
+void item_builder_destroy(struct item_builder* _Nullable builder);
+void item_builder_set_name(struct item_builder* _Nonnull builder, const char* _Nonnull name);
+// Like item_try_set_due_date and item_try_set_completion_date.
+bool item_builder_set_due_date(struct item_builder* _Nonnull builder, int64_t due_date, struct extern_error* _Nullable error);
+bool item_builder_set_completion_date(struct item_builder* _Nonnull builder, int64_t completion_date, struct extern_error* _Nullable error);
+// The label must exist by the time the item is committed.
+void item_builder_add_label(struct item_builder* _Nonnull builder, const char* _Nonnull label_name);
+// Writes the item and returns its uuid. Returns NULL and fills in `error`, writing nothing,
+// if a label does not exist (ErrorCategoryBug) or the item could not be written. The builder
+// is released either way.
+char* _Nullable item_builder_commit(struct item_builder* _Nonnull builder, const struct list_manager* _Nonnull manager, struct extern_error* _Nullable error);
+
+#endif /* item_builder_h */
@@ -0,0 +1,28 @@
+#ifndef item_handles_h
+#define item_handles_h
+
+#include <stdbool.h>
+#include <stdint.h>
+#include "errors.h"
+
+struct list_manager;
+
+// Items behind integer handles, for hosts that keep native objects in a long. 0 is never a
+// valid handle. Using a handle after item_handle_destroy, or destroying it twice, fails with
+// ErrorCategoryBug instead of touching freed memory.
+
+// A new, unsaved item with no name.
+uint64_t item_handle_new(void);
+// Returns 0 if there is no item with that uuid.
+uint64_t list_manager_fetch_item_handle(const struct list_manager* _Nonnull manager, const char* _Nonnull uuid);
+bool item_handle_destroy(uint64_t handle, struct extern_error* _Nullable error);
+// Empty for an item that has not been saved.
+char* _Nullable item_handle_get_uuid(uint64_t handle, struct extern_error* _Nullable error);
+char* _Nullable item_handle_get_name(uint64_t handle, struct extern_error* _Nullable error);
+bool item_handle_set_name(uint64_t handle, const char* _Nonnull name, struct extern_error* _Nullable error);
+// The item as item_to_json writes it.
+char* _Nullable item_handle_to_json(uint64_t handle, struct extern_error* _Nullable error);
+// Writes the item to the store, creating it and giving it a uuid if it has none.
+bool list_manager_save_item_handle(const struct list_manager* _Nonnull manager, uint64_t handle, struct extern_error* _Nullable error);
+
+#endif /* item_handles_h */
@@ -1,19 +1,111 @@
 #ifndef items_h
 #define items_h
 
+#include <stdbool.h>
+#include <stddef.h>
+#include "errors.h"
+#include "ffi_strings.h"
+
+// Items follow one ownership contract, whether called from Swift, through JNI or from C:
+// - A function returning a non-const struct item* hands the caller an owner, which it gives
+//   up with item_release.
+// - A function taking a const struct item* only reads the item during the call.
+// - A function taking a non-const struct item* changes the item in place and leaves the
+//   caller's ownership as it was.
+// The library never keeps a pointer it was given, so the host's wrapper can free its item
+// in deinit or close without asking the library.
 struct item;
+struct label;
+
+// Lists of items and labels. Read element i as data[i]; data is NULL when len is 0. The list
+// owns its elements: release it with item_list_destroy or label_list_destroy, and never
+// destroy the elements one by one.
+struct item_list {
+    struct item* _Nonnull * _Nullable data;
+    size_t len;
+    size_t cap;
+};
+
+struct label_list {
+    struct label* _Nonnull * _Nullable data;
+    size_t len;
+    size_t cap;
+};
+
+void item_list_destroy(struct item_list list);
+void timestamp_destroy(int64_t* _Nullable timestamp);
 
-const struct item* _Nonnull item_new();
-const void item_destroy(const struct item* _Nonnull item);
+struct item* _Nonnull item_new();
+// Items are reference counted, so several screens can hold the same one. An item returned by
+// the library has one owner, the caller. item_retain adds an owner and returns the item;
+// item_release drops one and frees the item once none are left. item_destroy is the same as
+// item_release. An item retained from an item_list stays valid after item_list_destroy.
+struct item* _Nonnull item_retain(struct item* _Nonnull item);
+void item_release(struct item* _Nullable item);
+void item_destroy(struct item* _Nullable item);
+// A separate copy of the item, with the same uuid and its own labels, for editing without
+// touching the original. Release it with item_release.
+struct item* _Nonnull item_clone(const struct item* _Nonnull item);
 
-const char* _Nonnull item_get_name(const struct item* _Nonnull item);
+// Fields for item_diff, e.g. to tell DiffUtil or SwiftUI which parts of a row changed.
+#define ITEM_FIELD_UUID (1 << 0)
+#define ITEM_FIELD_NAME (1 << 1)
+#define ITEM_FIELD_DUE_DATE (1 << 2)
+#define ITEM_FIELD_COMPLETION_DATE (1 << 3)
+#define ITEM_FIELD_LABELS (1 << 4)
+
+// Whether the items have the same uuid and contents, rather than being the same pointer.
+// Labels are compared by name and color, in any order.
+bool item_equals(const struct item* _Nonnull a, const struct item* _Nonnull b);
+// The ITEM_FIELD_ bits of the fields that differ between the items, 0 if item_equals.
+uint32_t item_diff(const struct item* _Nonnull a, const struct item* _Nonnull b);
+
+// Release the name with string_destroy.
+char* _Nonnull item_get_name(const struct item* _Nonnull item);
 const void item_set_name(struct item* _Nonnull item, const char* _Nonnull description);
+// The name as UTF-16 code units, e.g. for a Java String, released with utf16_string_destroy.
+struct utf16_string item_get_name_utf16(const struct item* _Nonnull item);
+// Sets the name from `len` UTF-16 code units. Unpaired surrogates become U+FFFD.
+void item_set_name_utf16(struct item* _Nonnull item, const uint16_t* _Nullable name, size_t len);
+// Dates are returned in seconds since 1970, or NULL if not set. Release them with
+// timestamp_destroy.
 int64_t* _Nullable item_get_due_date(const struct item* _Nonnull item);
+// 0 clears the due date. Dates out of range are logged and ignored.
 const void item_set_due_date(struct item* _Nonnull item, int64_t due_date);
+// Sets the due date in seconds since 1970. Values after the year 3000 are taken for
+// milliseconds, with a warning in the log, unless TOODLE_INIT_STRICT_DATES was passed to
+// toodle_init. Returns false, leaving the date unchanged, and fills in `error` with
+// ErrorCategoryBug if the date is before 1970 or still out of range.
+bool item_try_set_due_date(struct item* _Nonnull item, int64_t due_date, struct extern_error* _Nullable error);
 int64_t* _Nullable item_get_completion_date(const struct item* _Nonnull item);
 const void item_set_completion_date(struct item* _Nonnull item, int64_t completion_date);
-struct label** item_get_labels(const struct item* item);
-const size_t item_labels_count(const struct item* item);
-const struct label* item_label_at(const struct label** list, size_t index);
+bool item_try_set_completion_date(struct item* _Nonnull item, int64_t completion_date, struct extern_error* _Nullable error);
+// The dates in milliseconds since 1970, as Date and java.util.Date count them, returned by
+// value so there is nothing to release. The getters return 0 if the date is not set and set
+// `has_value`, which may be NULL, to whether it is. The setters clear the date if
+// `has_value` is false, and otherwise drop the milliseconds, as dates are kept to the
+// second. They return false, leaving the date unchanged, and fill in `error` with
+// ErrorCategoryBug if the date is before 1970 or after the year 3000.
+int64_t item_get_due_date_ms(const struct item* _Nonnull item, bool* _Nullable has_value);
+bool item_set_due_date_ms(struct item* _Nonnull item, bool has_value, int64_t ms, struct extern_error* _Nullable error);
+int64_t item_get_completion_date_ms(const struct item* _Nonnull item, bool* _Nullable has_value);
+bool item_set_completion_date_ms(struct item* _Nonnull item, bool has_value, int64_t ms, struct extern_error* _Nullable error);
+// A copy of the item's labels, released with label_list_destroy. To read them without
+// copying, use item_labels_count and item_label_at.
+struct label_list item_get_labels(const struct item* _Nonnull item);
+int item_labels_count(const struct item* _Nonnull item);
+// The label at `index`, borrowed from the item: it stays valid while the item is alive and
+// its labels are unchanged, and must not be destroyed. Returns NULL if `index` is out of range.
+const struct label* _Nullable item_label_at(const struct item* _Nonnull item, int index);
+
+// The item as JSON, e.g. {"uuid": "...", "name": "...", "due_date": "2017-11-10T12:00:00Z",
+// "completion_date": null, "labels": [{"name": "...", "color": "..."}]}. These keys are
+// the same wherever the library writes items as JSON and do not change. Dates are ISO 8601
+// UTC to the second, or null, so a Swift Codable struct decodes an item directly with a
+// JSONDecoder using .convertFromSnakeCase keys and the .iso8601 date strategy.
+char* _Nonnull item_to_json(const struct item* _Nonnull item);
+// Parses JSON made by item_to_json; uuid and labels may be left out, and dates may also be
+// given in seconds since 1970. Returns NULL and fills in `error` if the JSON is not an item.
+struct item* _Nullable item_from_json(const char* _Nonnull json, struct extern_error* _Nullable error);
 
 #endif /* items_h */
@@ -0,0 +1,34 @@
+#ifndef jobs_h
+#define jobs_h
+
+#include <stdbool.h>
+#include <stdint.h>
+#include "errors.h"
+#include "importers.h"
+
+struct list_manager;
+struct item_filter;
+
+// Long operations that would block the UI thread. Each _async function returns a job id at
+// once, or 0 if `manager` is NULL, and the work runs on a job thread the library owns. Jobs
+// run one at a time in the order they were started.
+
+// Called on the job thread when `job` finishes. On success `result` is its result as JSON and
+// `error` is NULL; on failure `result` is NULL. Neither may be freed or kept after the call.
+typedef void (*job_completion_callback)(uint64_t job, const char* _Nullable result, const struct extern_error* _Nullable error, void* _Nullable user_data);
+// Replaces the callback told about finished jobs. Set it before starting any; jobs finishing
+// while none is set are only logged.
+void jobs_set_completion_callback(job_completion_callback _Nonnull callback, void* _Nullable user_data);
+
+// Syncs like list_manager_sync_now. The result is {"pushed": n, "pulled": n, "conflicts": n}.
+uint64_t list_manager_sync_async(const struct list_manager* _Nonnull manager);
+// Imports like list_manager_import_dump, whose report is the result.
+uint64_t list_manager_import_dump_async(const struct list_manager* _Nonnull manager, const char* _Nonnull text, enum import_source source);
+// The result is a JSON array of the items matching `filter`, which may be NULL to fetch every
+// item. The filter is copied and may be destroyed once this returns.
+uint64_t list_manager_fetch_items_async(const struct list_manager* _Nonnull manager, const struct item_filter* _Nullable filter);
+// Keeps a job that has not started from running; it is then never reported to the completion
+// callback. Returns false if the job has started or finished, or there is no such job.
+bool jobs_cancel(uint64_t job);
+
+#endif /* jobs_h */
@@ -1,26 +1,102 @@
 #ifndef categories_h
 #define categories_h
 
-#import "items.h"
+#include "items.h"
+#include "filters.h"
+#include "cursors.h"
+#include "smart_lists.h"
+#include "stats.h"
+#include "raw_query.h"
+#include "conflicts.h"
+#include "attribution.h"
+#include "templates.h"
+#include "encryption.h"
+#include "autosave.h"
+#include "events.h"
+#include "prefs.h"
+#include "recent.h"
+#include "sync.h"
+#include "attachments.h"
+#include "recurrence.h"
+#include "import.h"
+#include "transfer.h"
+#include "ics.h"
+#include "todo_txt.h"
+#include "export.h"
+#include "importers.h"
+#include "wire.h"
+#include "share.h"
+#include "item_handles.h"
+#include "jobs.h"
+#include "batch.h"
+#include "item_builder.h"
 
 struct list_manager;
 struct label;
 struct item;
 
-const struct label** get_all_labels(const struct list_manager* manager);
-const void label_manager_create_item(const struct list_manager* manager, const struct item *item);
-const void label_manager_update_item(const struct list_manager* manager, const struct item *item);
+// Releases a list manager returned by toodle_list or list_manager_open_encrypted. Others
+// obtained from the same toodle stay valid.
+void list_manager_destroy(struct list_manager* _Nonnull manager);
+struct label_list list_manager_get_all_labels(const struct list_manager* _Nonnull manager);
+// Items matching `filter`, which may be NULL to fetch every item.
+struct item_list list_manager_fetch_items(const struct list_manager* _Nonnull manager, const struct item_filter* _Nullable filter);
+// Every item as a JSON array, each as from item_to_json, for the widget extension and others
+// that decode [Item] with a JSONDecoder rather than walk an item_list.
+char* _Nonnull list_manager_fetch_all_json(const struct list_manager* _Nonnull manager);
+// Returns the new item's uuid, or NULL and fills in `error` if it could not be written.
+char* _Nullable list_manager_create_item(const struct list_manager* _Nonnull manager, const struct item* _Nonnull item, struct extern_error* _Nullable error);
+// Returns false and fills in `error` if the item could not be written.
+bool list_manager_update_item(const struct list_manager* _Nonnull manager, const struct item* _Nonnull item, struct extern_error* _Nullable error);
+// Returns false if there is no item with that uuid, or if it could not be deleted, in which
+// case `error` is filled in.
+bool list_manager_delete_item(const struct list_manager* _Nonnull manager, const char* _Nonnull uuid, struct extern_error* _Nullable error);
+// Returns NULL and fills in `error` if the label could not be created, e.g. because there
+// already is one with that name.
+struct label* _Nullable list_manager_create_label(const struct list_manager* _Nonnull manager, const char* _Nonnull name, const char* _Nonnull color, struct extern_error* _Nullable error);
+// Removes the label from every item and deletes it. Smart lists filtering on it match
+// nothing. Returns false if there is no label with that name, or if it could not be
+// deleted, in which case `error` is filled in.
+bool list_manager_delete_label(const struct list_manager* _Nonnull manager, const char* _Nonnull name, struct extern_error* _Nullable error);
+// Forgets deletions made more than `horizon_days` ago (90 if negative) once sync has pushed
+// them. Devices that have not synced for longer may bring those records back. Returns how
+// many were forgotten.
+int64_t list_manager_prune_tombstones(const struct list_manager* _Nonnull manager, int64_t horizon_days);
 
-const size_t label_list_count(const struct label** list);
-const void label_list_destroy(const struct label** list);
-const struct label* label_list_item_at(const struct label** list, size_t index);
-const void add_label(const struct label** list, const struct label* label);
+void label_list_destroy(struct label_list list);
 
-struct label* label_new(const struct list_manager* manager, const char* name);
 const void label_destroy(const struct label* label);
-const char* label_get_name(const struct label* label);
-const char** label_get_color(const struct label* label);
+// Release the name and color with string_destroy.
+char* _Nonnull label_get_name(const struct label* _Nonnull label);
+char* _Nonnull label_get_color(const struct label* _Nonnull label);
 const void label_set_color(struct label* _Nonnull label, const char* _Nonnull color);
 
+// {"name": "...", "color": "..."}.
+char* _Nonnull label_to_json(const struct label* _Nonnull label);
+// Returns NULL and fills in `error` if the JSON is not a label.
+struct label* _Nullable label_from_json(const char* _Nonnull json, struct extern_error* _Nullable error);
+// A JSON array of the labels in the list, e.g. from list_manager_get_all_labels. The list is
+// still the caller's to destroy.
+char* _Nonnull label_list_to_json(struct label_list list);
+// Returns an empty list and fills in `error` if the JSON is not an array of labels.
+struct label_list label_list_from_json(const char* _Nonnull json, struct extern_error* _Nullable error);
+// The label and its items as {"label": {...}, "items": [...]}, each item as from
+// item_to_json. Returns NULL if there is no label with that name.
+char* _Nullable list_manager_list_json(const struct list_manager* _Nonnull manager, const char* _Nonnull name);
+
+struct label_count;
+
+// filter may be NULL to count every item.
+const struct label_count** list_manager_label_counts(const struct list_manager* manager, const struct item_filter* _Nullable filter);
+const void label_count_list_destroy(const struct label_count** list);
+const size_t label_count_list_count(const struct label_count** list);
+// Borrowed from the list; valid until label_count_list_destroy.
+const struct label_count* label_count_list_at(const struct label_count** list, size_t index);
+// Borrowed from the count; valid until label_count_list_destroy.
+const struct label* label_count_get_label(const struct label_count* count);
+const int64_t label_count_get_open(const struct label_count* count);
+const int64_t label_count_get_overdue(const struct label_count* count);
+const int64_t label_count_get_total(const struct label_count* count);
+
 
 #endif /* categories_h */
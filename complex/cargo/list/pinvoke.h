@@ -0,0 +1,75 @@
+#ifndef pinvoke_h
+#define pinvoke_h
+
+#include <stdint.h>
+#include "errors.h"
+#include "ffi_strings.h"
+
+struct list_manager;
+
+// A flat variant of the item and label functions for .NET's P/Invoke. Every struct is
+// blittable: strings are UTF-16 code units with a length, e.g. passed as `s` and `s.Length`
+// with CharSet.Unicode, dates are milliseconds since 1970 with a has_ flag, and flags are
+// int32_t, 1 or 0, rather than bool.
+//
+// Every function is cdecl. Declare them with CallingConvention.Cdecl, and callbacks with
+// [UnmanagedFunctionPointer(CallingConvention.Cdecl)]; the StdCall default differs on
+// 32-bit Windows. Open the store with new_toodle_with_options_wide.
+//
+// Fetches return a list of records for a SafeHandle to own. Its destroy function takes the
+// pointer alone, does nothing for NULL and may be called from any thread, e.g. in
+// ReleaseHandle on the finalizer thread. list_manager_destroy and toodle_destroy work the
+// same way.
+
+// An item without its labels. Its strings belong to the list it came from.
+struct item_record {
+    struct utf16_string uuid;
+    struct utf16_string name;
+    int64_t due_date_ms;
+    int64_t completion_date_ms;
+    int32_t has_due_date;
+    int32_t has_completion_date;
+};
+
+// A label. Its strings belong to the list it came from.
+struct label_record {
+    struct utf16_string name;
+    struct utf16_string color;
+};
+
+struct item_records;
+struct label_records;
+
+// Every item if `completed` is negative, otherwise the completed items for 1 and the open
+// ones for 0.
+struct item_records* _Nonnull pinvoke_fetch_items(const struct list_manager* _Nonnull manager, int32_t completed);
+// A list holding the item, or NULL if there is no item with that uuid.
+struct item_records* _Nullable pinvoke_fetch_item(const struct list_manager* _Nonnull manager, const uint16_t* _Nullable uuid, int32_t uuid_len);
+int32_t pinvoke_item_records_len(const struct item_records* _Nonnull records);
+// The record at `index`, valid until the list is destroyed, or NULL if out of range.
+const struct item_record* _Nullable pinvoke_item_records_get(const struct item_records* _Nonnull records, int32_t index);
+void pinvoke_item_records_destroy(struct item_records* _Nullable records);
+
+// Returns the new item's uuid, released with utf16_string_destroy, or an empty string and
+// fills in `error`. The due date is left unset if `has_due_date` is 0.
+struct utf16_string pinvoke_create_item(const struct list_manager* _Nonnull manager, const uint16_t* _Nullable name, int32_t name_len, int32_t has_due_date, int64_t due_date_ms, struct extern_error* _Nullable error);
+// Writes the record's name and dates to the item with its uuid, keeping its labels. Returns
+// 1, or 0 and fills in `error` if there is no such item or it could not be written.
+int32_t pinvoke_update_item(const struct list_manager* _Nonnull manager, const struct item_record* _Nonnull record, struct extern_error* _Nullable error);
+// Returns 1 if the item was deleted, or 0 if there was none or it could not be deleted, in
+// which case `error` is filled in.
+int32_t pinvoke_delete_item(const struct list_manager* _Nonnull manager, const uint16_t* _Nullable uuid, int32_t uuid_len, struct extern_error* _Nullable error);
+
+struct label_records* _Nonnull pinvoke_fetch_labels(const struct list_manager* _Nonnull manager);
+struct label_records* _Nonnull pinvoke_fetch_item_labels(const struct list_manager* _Nonnull manager, const uint16_t* _Nullable uuid, int32_t uuid_len);
+int32_t pinvoke_label_records_len(const struct label_records* _Nonnull records);
+const struct label_record* _Nullable pinvoke_label_records_get(const struct label_records* _Nonnull records, int32_t index);
+void pinvoke_label_records_destroy(struct label_records* _Nullable records);
+// Returns 1, or 0 and fills in `error` if the label could not be created, e.g. because one
+// with that name exists.
+int32_t pinvoke_create_label(const struct list_manager* _Nonnull manager, const uint16_t* _Nullable name, int32_t name_len, const uint16_t* _Nullable color, int32_t color_len, struct extern_error* _Nullable error);
+// Returns 1 if the label was deleted, or 0 if there was none or it could not be deleted, in
+// which case `error` is filled in.
+int32_t pinvoke_delete_label(const struct list_manager* _Nonnull manager, const uint16_t* _Nullable name, int32_t name_len, struct extern_error* _Nullable error);
+
+#endif /* pinvoke_h */
@@ -0,0 +1,25 @@
+#ifndef prefs_h
+#define prefs_h
+
+#include <stdbool.h>
+#include <stdint.h>
+#include "errors.h"
+
+struct list_manager;
+
+// Host settings kept next to the lists, e.g. "default_list" (a label name) and
+// "week_start_day" (0 for Sunday to 6 for Saturday). Each key holds a string, an integer or
+// a boolean; reading a key as another type finds nothing.
+// The setters return false and fill in `error` if the value could not be written.
+bool prefs_set_string(const struct list_manager* _Nonnull manager, const char* _Nonnull key, const char* _Nonnull value, struct extern_error* _Nullable error);
+bool prefs_set_int(const struct list_manager* _Nonnull manager, const char* _Nonnull key, int64_t value, struct extern_error* _Nullable error);
+bool prefs_set_bool(const struct list_manager* _Nonnull manager, const char* _Nonnull key, bool value, struct extern_error* _Nullable error);
+bool prefs_remove(const struct list_manager* _Nonnull manager, const char* _Nonnull key, struct extern_error* _Nullable error);
+
+// Returns NULL if the key is not set to a string.
+char* _Nullable prefs_get_string(const struct list_manager* _Nonnull manager, const char* _Nonnull key);
+// Return `default_value` if the key is not set to a value of that type.
+int64_t prefs_get_int(const struct list_manager* _Nonnull manager, const char* _Nonnull key, int64_t default_value);
+bool prefs_get_bool(const struct list_manager* _Nonnull manager, const char* _Nonnull key, bool default_value);
+
+#endif /* prefs_h */
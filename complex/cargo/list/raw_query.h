@@ -0,0 +1,13 @@
+#ifndef raw_query_h
+#define raw_query_h
+
+#include "errors.h"
+
+struct list_manager;
+
+// Runs a single read-only SQL query and returns the rows as a JSON array of objects keyed
+// by column name, or NULL if the query failed or would have modified the store. Meant for
+// debugging tools.
+char* _Nullable list_manager_raw_query(const struct list_manager* _Nonnull manager, const char* _Nonnull query, struct extern_error* _Nullable error);
+
+#endif /* raw_query_h */
@@ -0,0 +1,17 @@
+#ifndef recent_h
+#define recent_h
+
+#include <stdint.h>
+
+struct list_manager;
+
+// Items completed during the last `days` days as a JSON array, most recently completed first.
+char* _Nonnull list_manager_recently_completed(const struct list_manager* _Nonnull manager, int64_t days);
+int64_t list_manager_recently_completed_count(const struct list_manager* _Nonnull manager, int64_t days);
+// Items deleted during the last `days` days as a JSON array, most recently deleted first,
+// each as it was when deleted with an added `trashed_at` key. Deleted items are kept for 30
+// days, dropped as later items are deleted and by toodle_on_significant_time_change.
+char* _Nonnull list_manager_recently_trashed(const struct list_manager* _Nonnull manager, int64_t days);
+int64_t list_manager_recently_trashed_count(const struct list_manager* _Nonnull manager, int64_t days);
+
+#endif /* recent_h */
@@ -0,0 +1,33 @@
+#ifndef recurrence_h
+#define recurrence_h
+
+#include <stdint.h>
+#include "errors.h"
+
+struct list_manager;
+
+enum frequency {
+    FrequencyDaily = 0,
+    FrequencyWeekly = 1,
+};
+
+enum series_deletion {
+    // Whatever the "series_deletion" int pref holds, or SeriesDeletionThisOccurrence if it is not set.
+    SeriesDeletionDefault = 0,
+    SeriesDeletionThisOccurrence = 1,
+    SeriesDeletionThisAndFuture = 2,
+    SeriesDeletionEntireSeries = 3,
+};
+
+// Turns the item into the first of `count` occurrences, each due `interval` days or weeks
+// after the previous. Returns the uuids of the occurrences as a JSON array, or NULL and fills
+// in `error` if there is no item with that uuid (ErrorCategoryBug), it has no due date or
+// `interval` or `count` is not positive (ErrorCategoryNeedsUserInput), or the occurrences
+// could not be written.
+char* _Nullable list_manager_create_recurring_item(const struct list_manager* _Nonnull manager, const char* _Nonnull uuid, enum frequency frequency, int64_t interval, int64_t count, struct extern_error* _Nullable error);
+// Deletes the item and, if it recurs, the other occurrences `deletion` covers. Returns the
+// uuids of the deleted items as a JSON array, empty if there was no such item, or NULL and
+// fills in `error` if they could not be deleted, in which case none of them are.
+char* _Nullable list_manager_delete_recurring_item(const struct list_manager* _Nonnull manager, const char* _Nonnull uuid, enum series_deletion deletion, struct extern_error* _Nullable error);
+
+#endif /* recurrence_h */
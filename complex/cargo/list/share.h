@@ -0,0 +1,18 @@
+#ifndef share_h
+#define share_h
+
+#include <stdint.h>
+
+struct item;
+
+enum share_format {
+    ShareFormatPlainText = 0,
+    ShareFormatMarkdown = 1,
+};
+
+// The text to share for the item: its name behind a [ ] or [x] checkbox, then its due and
+// completion dates and labels, one per line. Dates are shown in local time, `utc_offset`
+// seconds ahead of UTC, without the time at midnight.
+char* _Nonnull item_to_share_text(const struct item* _Nonnull item, enum share_format format, int64_t utc_offset);
+
+#endif /* share_h */
@@ -0,0 +1,46 @@
+#ifndef smart_lists_h
+#define smart_lists_h
+
+#include <stdint.h>
+#include "filters.h"
+#include "cursors.h"
+#include "errors.h"
+
+struct list_manager;
+struct smart_list;
+
+enum export_format {
+    // {"name": ..., "items": [...]}
+    ExportFormatJson = 0,
+    // One row per item under a name,due_date,completion_date,labels header.
+    ExportFormatCsv = 1,
+    // A task list under the smart list's name.
+    ExportFormatMarkdown = 2,
+};
+
+// filter may be NULL to save a list of every item. Returns NULL and fills in `error` if the
+// list could not be created.
+struct smart_list* _Nullable list_manager_create_smart_list(const struct list_manager* _Nonnull manager, const char* _Nonnull name, const struct item_filter* _Nullable filter, struct extern_error* _Nullable error);
+// Returns false if there is no smart list with that id, or if it could not be deleted, in
+// which case `error` is filled in.
+bool list_manager_delete_smart_list(const struct list_manager* _Nonnull manager, int64_t id, struct extern_error* _Nullable error);
+const struct smart_list** list_manager_get_smart_lists(const struct list_manager* _Nonnull manager);
+// Returns NULL if there is no smart list with that id. A page_size of 0 uses the default.
+struct cursor* _Nullable list_manager_smart_list_cursor(const struct list_manager* _Nonnull manager, int64_t id, int64_t page_size);
+// The items currently matching the smart list, and nothing else, for sharing. Dates are UTC
+// in ISO 8601 form in CSV and Markdown. `format` is one of the enum export_format values.
+// Returns NULL and fills in `error` with ErrorCategoryBug if it is not, or if there is no
+// smart list with that id.
+char* _Nullable list_manager_smart_list_export(const struct list_manager* _Nonnull manager, int64_t id, uint32_t format, struct extern_error* _Nullable error);
+
+const void smart_list_list_destroy(const struct smart_list** list);
+const size_t smart_list_list_count(const struct smart_list** list);
+// Borrowed from the list; valid until smart_list_list_destroy.
+const struct smart_list* smart_list_list_at(const struct smart_list** list, size_t index);
+
+const void smart_list_destroy(struct smart_list* _Nonnull smart_list);
+const int64_t smart_list_get_id(const struct smart_list* _Nonnull smart_list);
+// Release the name with string_destroy.
+char* _Nonnull smart_list_get_name(const struct smart_list* _Nonnull smart_list);
+
+#endif /* smart_lists_h */
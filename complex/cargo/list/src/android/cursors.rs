@@ -0,0 +1,179 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+//! Natives of `com.mozilla.toodle.ToodleCursor`, for a `ContentProvider` over the store:
+//!
+//! ```java
+//! static native long openItems(long manager, int pageSize);
+//! static native long openDueToday(long manager, long utcOffset, int pageSize);
+//! static native long openLabels(long manager, int pageSize);
+//! static native void close(long cursor);
+//! static native int getCount(long cursor);
+//! static native String[] getColumnNames(long cursor);
+//! static native boolean moveToNext(long cursor);
+//! static native int getType(long cursor, int column);
+//! static native long getLong(long cursor, int column);
+//! static native String getString(long cursor, int column);
+//! ```
+//!
+//! `manager` is a pointer from `toodle_list`. A cursor only moves forward, so `query` either
+//! copies its rows into a `MatrixCursor`, which is simplest for small lists, or returns an
+//! `AbstractCursor` whose `onMove` calls `moveToNext` until it reaches the new position and
+//! reopens the cursor to move backwards. The columns and types are those of `cursor_get_type`
+//! and friends in cursors.h, and `getType` returns `Cursor.FIELD_TYPE_*` values.
+
+use std::ptr;
+use std::sync::Arc;
+
+use jni::JNIEnv;
+use jni::objects::JClass;
+use jni::sys::{
+    jboolean,
+    jint,
+    jlong,
+    jobjectArray,
+    jstring,
+    JNI_FALSE,
+    JNI_TRUE,
+};
+
+use android::{
+    java_string,
+    java_string_array,
+    Native,
+};
+use cursors::{
+    Cursor,
+    CursorSource,
+    CursorValue,
+    CURSOR_FIELD_TYPE_INTEGER,
+    CURSOR_FIELD_TYPE_NULL,
+    CURSOR_FIELD_TYPE_STRING,
+};
+use filters::ItemFilter;
+use ListManager;
+
+fn open(manager: &Arc<ListManager>, source: CursorSource, page_size: jint) -> jlong {
+    Box::into_raw(Box::new(Cursor::new(manager.clone(), source, page_size as i64))) as jlong
+}
+
+/// Every item, in the order `fetchItems` returns them.
+unsafe extern "C" fn openItems(env: JNIEnv, _: JClass, manager: jlong, page_size: jint) -> jlong {
+    ffi_lock!();
+    ffi_guard!({
+        let manager = jni_object!(env, "ToodleCursor.openItems", manager as Arc<ListManager>, 0);
+        open(manager, CursorSource::Items(ItemFilter::default()), page_size)
+    })
+}
+
+/// The items due today where the device is, for widgets.
+unsafe extern "C" fn openDueToday(env: JNIEnv, _: JClass, manager: jlong, utc_offset: jlong, page_size: jint) -> jlong {
+    ffi_lock!();
+    ffi_guard!({
+        let manager = jni_object!(env, "ToodleCursor.openDueToday", manager as Arc<ListManager>, 0);
+        let filter = ItemFilter::due_today(manager.now(), utc_offset);
+        open(manager, CursorSource::Items(filter), page_size)
+    })
+}
+
+unsafe extern "C" fn openLabels(env: JNIEnv, _: JClass, manager: jlong, page_size: jint) -> jlong {
+    ffi_lock!();
+    ffi_guard!({
+        let manager = jni_object!(env, "ToodleCursor.openLabels", manager as Arc<ListManager>, 0);
+        open(manager, CursorSource::Labels, page_size)
+    })
+}
+
+unsafe extern "C" fn close(_: JNIEnv, _: JClass, cursor: jlong) {
+    ffi_lock!();
+    ffi_guard!({
+        if cursor != 0 {
+            let _ = Box::from_raw(cursor as *mut Cursor);
+        }
+    })
+}
+
+/// The number of rows when the cursor was opened.
+unsafe extern "C" fn getCount(env: JNIEnv, _: JClass, cursor: jlong) -> jint {
+    ffi_lock!();
+    ffi_guard!({
+        let cursor = jni_object!(env, "ToodleCursor.getCount", cursor as Cursor, 0);
+        cursor.total_count() as jint
+    })
+}
+
+unsafe extern "C" fn getColumnNames(env: JNIEnv, _: JClass, cursor: jlong) -> jobjectArray {
+    ffi_lock!();
+    ffi_guard!({
+        let cursor = jni_object!(env, "ToodleCursor.getColumnNames", cursor as Cursor, ptr::null_mut());
+        let names: Vec<String> = cursor.columns().iter().map(|name| name.to_string()).collect();
+        java_string_array(&env, "ToodleCursor.getColumnNames", &names)
+    })
+}
+
+unsafe extern "C" fn moveToNext(env: JNIEnv, _: JClass, cursor: jlong) -> jboolean {
+    ffi_lock!();
+    ffi_guard!({
+        let cursor = jni_object!(env, "ToodleCursor.moveToNext", cursor as Cursor, JNI_FALSE);
+        if cursor.move_to_next() { JNI_TRUE } else { JNI_FALSE }
+    })
+}
+
+unsafe extern "C" fn getType(env: JNIEnv, _: JClass, cursor: jlong, column: jint) -> jint {
+    ffi_lock!();
+    ffi_guard!({
+        let cursor = jni_object!(env, "ToodleCursor.getType", cursor as Cursor, CURSOR_FIELD_TYPE_NULL);
+        match cursor.value(column as usize) {
+            Some(CursorValue::Integer(_)) => CURSOR_FIELD_TYPE_INTEGER,
+            Some(CursorValue::Text(_)) => CURSOR_FIELD_TYPE_STRING,
+            _ => CURSOR_FIELD_TYPE_NULL,
+        }
+    })
+}
+
+/// 0 for null and text columns.
+unsafe extern "C" fn getLong(env: JNIEnv, _: JClass, cursor: jlong, column: jint) -> jlong {
+    ffi_lock!();
+    ffi_guard!({
+        let cursor = jni_object!(env, "ToodleCursor.getLong", cursor as Cursor, 0);
+        match cursor.value(column as usize) {
+            Some(CursorValue::Integer(value)) => value,
+            _ => 0,
+        }
+    })
+}
+
+/// Integers are returned as text; null for null columns.
+unsafe extern "C" fn getString(env: JNIEnv, _: JClass, cursor: jlong, column: jint) -> jstring {
+    ffi_lock!();
+    ffi_guard!({
+        let cursor = jni_object!(env, "ToodleCursor.getString", cursor as Cursor, ptr::null_mut());
+        match cursor.value(column as usize) {
+            Some(CursorValue::Integer(value)) => java_string(&env, "ToodleCursor.getString", &value.to_string()),
+            Some(CursorValue::Text(value)) => java_string(&env, "ToodleCursor.getString", &value),
+            _ => ptr::null_mut(),
+        }
+    })
+}
+
+/// The natives of `ToodleCursor` above, for `JNI_OnLoad` to register.
+pub(crate) fn natives() -> Vec<Native> {
+    vec![
+        native!(openItems, "(JI)J"),
+        native!(openDueToday, "(JJI)J"),
+        native!(openLabels, "(JI)J"),
+        native!(close, "(J)V"),
+        native!(getCount, "(J)I"),
+        native!(getColumnNames, "(J)[Ljava/lang/String;"),
+        native!(moveToNext, "(J)Z"),
+        native!(getType, "(JI)I"),
+        native!(getLong, "(JI)J"),
+        native!(getString, "(JI)Ljava/lang/String;"),
+    ]
+}
@@ -0,0 +1,71 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! Failures are thrown to Java as a `ToodleException` carrying the `ErrorCategory`, so
+//! Kotlin can use try/catch instead of checking for null:
+//!
+//! ```java
+//! package com.mozilla.toodle;
+//!
+//! public class ToodleException extends RuntimeException {
+//!     // `category` is an ErrorCategory from errors.h
+//!     public ToodleException(int category, String message) { ... }
+//! }
+//! ```
+//!
+//! A native that throws returns a default value, which Java never sees.
+
+use std::ptr;
+
+use jni::JNIEnv;
+use jni::errors::Error;
+use jni::objects::{
+    JThrowable,
+    JValue,
+};
+
+use ffi_utils::errors::ErrorCategory;
+use ffi_utils::nulls::null_argument;
+
+use android::java_class;
+
+/// Throws a `ToodleException`, or a `RuntimeException` with the same message if that class
+/// cannot be found. Does nothing if an exception is already pending.
+pub(crate) fn throw(env: &JNIEnv, category: ErrorCategory, message: &str) {
+    log_error!("throwing {:?}: {}", category, message);
+    if env.exception_check().unwrap_or(false) {
+        return;
+    }
+    let exception = env.new_string(message)
+        .and_then(|message| env.new_object(java_class("ToodleException").as_str(), "(ILjava/lang/String;)V", &[JValue::Int(category as i32), JValue::Object(message.into())]));
+    let thrown = match exception {
+        Ok(exception) => env.throw(JThrowable::from(exception)),
+        Err(_) => {
+            // most likely the class is missing, which FindClass reported by throwing
+            let _ = env.exception_clear();
+            env.throw_new("java/lang/RuntimeException", message)
+        },
+    };
+    if let Err(e) = thrown {
+        log_error!("failed to throw {:?}", e);
+    }
+}
+
+/// Throws an `ErrorCategory::Bug` for a null `argument`, after logging it like `ffi_ref!`.
+pub(crate) unsafe fn throw_null(env: &JNIEnv, function: &str, argument: &str) {
+    null_argument(function, argument, ptr::null_mut());
+    throw(env, ErrorCategory::Bug, &format!("{} called with a null {}", function, argument));
+}
+
+/// Throws an `ErrorCategory::Bug` for a failed JNI call, unless it failed because Java
+/// threw, in which case that exception is left to propagate.
+pub(crate) fn throw_jni_error(env: &JNIEnv, function: &str, error: Error) {
+    throw(env, ErrorCategory::Bug, &format!("{} failed: {}", function, error));
+}
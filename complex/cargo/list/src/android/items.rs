@@ -0,0 +1,211 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! Natives of `com.mozilla.toodle.Item`:
+//!
+//! ```java
+//! static native String getName(long item);
+//! static native void setName(long item, String name);
+//! static native long getDueDate(long item);
+//! static native void setDueDate(long item, long dueDate);
+//! static native String[] getLabelNames(long item);
+//! static native void addLabel(long item, String name, String color);
+//! static native boolean removeLabel(long item, String name);
+//! static native byte[] toBytes(long item);
+//! static native long fromBytes(byte[] bytes);
+//! static native void release(long item);
+//! ```
+//!
+//! `item` is a pointer from the C API, e.g. `item_new`, and stays owned by the caller.
+//! `toBytes` and `fromBytes` are for `Parcelable`: they use the `Item` message from
+//! items.proto, as `item_to_bytes` does.
+
+use jni::JNIEnv;
+use jni::objects::{
+    JClass,
+    JString,
+};
+use jni::sys::{
+    jboolean,
+    jbyteArray,
+    jlong,
+    jobjectArray,
+    jstring,
+    JNI_FALSE,
+    JNI_TRUE,
+};
+use std::ptr;
+use std::sync::Arc;
+
+use android::exceptions::{
+    throw,
+    throw_jni_error,
+    throw_null,
+};
+use android::{
+    java_string,
+    java_string_array,
+    rust_string,
+    Native,
+};
+use dates::date_from_seconds;
+use items::{
+    item_release,
+    Item,
+};
+use labels::Label;
+use wire::{
+    decode_item,
+    encode_item,
+};
+
+unsafe extern "C" fn getName(env: JNIEnv, _: JClass, item: jlong) -> jstring {
+    ffi_guard!({
+        let item = jni_object!(env, "Item.getName", item as Item, ptr::null_mut());
+        java_string(&env, "Item.getName", &item.name)
+    })
+}
+
+unsafe extern "C" fn setName(env: JNIEnv, _: JClass, item: jlong, name: JString) {
+    ffi_guard!({
+        let item = jni_object!(env, "Item.setName", item as Item, ());
+        if let Some(name) = rust_string(&env, "Item.setName", name) {
+            item.name = name;
+        }
+    })
+}
+
+/// Seconds since 1970, or 0 if the item has no due date.
+unsafe extern "C" fn getDueDate(env: JNIEnv, _: JClass, item: jlong) -> jlong {
+    ffi_guard!({
+        let item = jni_object!(env, "Item.getDueDate", item as Item, 0);
+        item.due_date.map_or(0, |date| date.sec)
+    })
+}
+
+/// 0 clears the due date. Dates out of range, as for `item_try_set_due_date`, are thrown
+/// and leave the date unchanged.
+unsafe extern "C" fn setDueDate(env: JNIEnv, _: JClass, item: jlong, due_date: jlong) {
+    ffi_guard!({
+        let item = jni_object!(env, "Item.setDueDate", item as Item, ());
+        if due_date == 0 {
+            item.due_date = None;
+            return;
+        }
+        match date_from_seconds(due_date) {
+            Ok(date) => item.due_date = Some(date),
+            Err(e) => throw(&env, e.category(), &e.to_string()),
+        }
+    })
+}
+
+unsafe extern "C" fn getLabelNames(env: JNIEnv, _: JClass, item: jlong) -> jobjectArray {
+    ffi_guard!({
+        let item = jni_object!(env, "Item.getLabelNames", item as Item, ptr::null_mut());
+        let names: Vec<String> = item.labels.iter().map(|label| label.name.clone()).collect();
+        java_string_array(&env, "Item.getLabelNames", &names)
+    })
+}
+
+/// Adds the label, or changes its color if the item already has a label with that name.
+/// Like the item's other fields, it is stored when the item is saved.
+unsafe extern "C" fn addLabel(env: JNIEnv, _: JClass, item: jlong, name: JString, color: JString) {
+    ffi_guard!({
+        let item = jni_object!(env, "Item.addLabel", item as Item, ());
+        let name = match rust_string(&env, "Item.addLabel", name) {
+            Some(name) => name,
+            None => return,
+        };
+        let color = match rust_string(&env, "Item.addLabel", color) {
+            Some(color) => color,
+            None => return,
+        };
+        // copied on write, as other items may share the label
+        match item.labels.iter_mut().find(|label| label.name == name) {
+            Some(label) => Arc::make_mut(label).color = color,
+            None => item.labels.push(Arc::new(Label { name: name, color: color })),
+        }
+    })
+}
+
+/// Returns false if the item has no label with that name.
+unsafe extern "C" fn removeLabel(env: JNIEnv, _: JClass, item: jlong, name: JString) -> jboolean {
+    ffi_guard!({
+        let item = jni_object!(env, "Item.removeLabel", item as Item, JNI_FALSE);
+        let name = match rust_string(&env, "Item.removeLabel", name) {
+            Some(name) => name,
+            None => return JNI_FALSE,
+        };
+        let count = item.labels.len();
+        item.labels.retain(|label| label.name != name);
+        if item.labels.len() != count { JNI_TRUE } else { JNI_FALSE }
+    })
+}
+
+unsafe extern "C" fn toBytes(env: JNIEnv, _: JClass, item: jlong) -> jbyteArray {
+    ffi_guard!({
+        let item = jni_object!(env, "Item.toBytes", item as Item, ptr::null_mut());
+        match env.byte_array_from_slice(&encode_item(item)) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                throw_jni_error(&env, "Item.toBytes", e);
+                ptr::null_mut()
+            },
+        }
+    })
+}
+
+/// A new item, owned by the caller and freed with `release`, read from bytes made by
+/// `toBytes`. Bytes that are not an item are thrown.
+unsafe extern "C" fn fromBytes(env: JNIEnv, _: JClass, bytes: jbyteArray) -> jlong {
+    ffi_guard!({
+        if bytes.is_null() {
+            throw_null(&env, "Item.fromBytes", "bytes");
+            return 0;
+        }
+        let bytes = match env.convert_byte_array(bytes) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                throw_jni_error(&env, "Item.fromBytes", e);
+                return 0;
+            },
+        };
+        match decode_item(&bytes) {
+            Ok(item) => Box::into_raw(Box::new(item)) as jlong,
+            Err(e) => {
+                throw(&env, e.category(), &format!("Item.fromBytes failed: {}", e));
+                0
+            },
+        }
+    })
+}
+
+/// Drops the caller's ownership of the item, as `item_release` does.
+unsafe extern "C" fn release(_: JNIEnv, _: JClass, item: jlong) {
+    ffi_guard!({
+        item_release(item as *mut Item);
+    })
+}
+
+/// The natives of `Item` above, for `JNI_OnLoad` to register.
+pub(crate) fn natives() -> Vec<Native> {
+    vec![
+        native!(getName, "(J)Ljava/lang/String;"),
+        native!(setName, "(JLjava/lang/String;)V"),
+        native!(getDueDate, "(J)J"),
+        native!(setDueDate, "(JJ)V"),
+        native!(getLabelNames, "(J)[Ljava/lang/String;"),
+        native!(addLabel, "(JLjava/lang/String;Ljava/lang/String;)V"),
+        native!(removeLabel, "(JLjava/lang/String;)Z"),
+        native!(toBytes, "(J)[B"),
+        native!(fromBytes, "([B)J"),
+        native!(release, "(J)V"),
+    ]
+}
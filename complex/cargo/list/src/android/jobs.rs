@@ -0,0 +1,187 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! Natives of `com.mozilla.toodle.ListManager` that run on the job thread and report to a
+//! callback object, so Kotlin can wrap them in `suspendCancellableCoroutine` rather than
+//! block the thread it calls from:
+//!
+//! ```java
+//! public interface JobCallback {
+//!     // `result` is JSON, as for the _async functions in jobs.h
+//!     void onSuccess(String result);
+//!     // `category` is an ErrorCategory from errors.h
+//!     void onError(int category, String message);
+//! }
+//!
+//! static native long syncAsync(long manager, JobCallback callback);
+//! // `source` is an ImportSource from importers.h
+//! static native long importDumpAsync(long manager, String text, int source, JobCallback callback);
+//! // items whose name contains `query`, as for the name_contains filter
+//! static native long searchAsync(long manager, String query, JobCallback callback);
+//! // for invokeOnCancellation; false if the job has started
+//! static native boolean cancelJob(long job);
+//! ```
+//!
+//! Each returns the job's id. The callback is called once, on the job thread, unless the
+//! job is cancelled.
+
+use jni::JNIEnv;
+use jni::objects::{
+    JClass,
+    JObject,
+    JString,
+    JValue,
+};
+use jni::sys::{
+    jboolean,
+    jint,
+    jlong,
+    jobject,
+    JNI_FALSE,
+    JNI_TRUE,
+};
+use std::sync::Arc;
+
+use ffi_utils::errors::ErrorCategory;
+
+use android::exceptions::{
+    throw,
+    throw_null,
+};
+use android::{
+    attached_env,
+    clear_exception,
+    delete_global_ref,
+    new_global_ref,
+    rust_string,
+    Native,
+};
+use filters::ItemFilter;
+use importers::ImportSource;
+use jobs::{
+    self,
+    Completion,
+    JobResult,
+};
+use ListManager;
+
+/// A global reference to a `JobCallback`, deleted when dropped, which also happens on the
+/// job thread if the job is cancelled.
+struct JobCallback(jobject);
+
+unsafe impl Send for JobCallback {}
+
+impl JobCallback {
+    fn call(&self, env: &JNIEnv, result: JobResult) -> ::jni::errors::Result<()> {
+        let callback = JObject::from(self.0);
+        let (method, signature, args) = match result {
+            Ok(result) => ("onSuccess", "(Ljava/lang/String;)V", vec![JValue::Object(env.new_string(result)?.into())]),
+            Err((category, message)) => ("onError", "(ILjava/lang/String;)V", vec![JValue::Int(category as i32), JValue::Object(env.new_string(message)?.into())]),
+        };
+        let called = env.call_method(callback, method, signature, &args).map(|_| ());
+        // the job thread never returns to Java, which would free local references
+        for arg in args {
+            if let JValue::Object(object) = arg {
+                env.delete_local_ref(object)?;
+            }
+        }
+        called
+    }
+}
+
+impl Drop for JobCallback {
+    fn drop(&mut self) {
+        if let Some(env) = unsafe { attached_env() } {
+            unsafe { delete_global_ref(&env, self.0) };
+        }
+    }
+}
+
+/// The completion that calls `callback`, or `None`, having thrown, if it is null.
+unsafe fn completion(env: &JNIEnv, function: &str, callback: JObject) -> Option<Completion> {
+    if callback.into_inner().is_null() {
+        throw_null(env, function, "callback");
+        return None;
+    }
+    let callback = JobCallback(new_global_ref(env, callback));
+    Some(Box::new(move |result| {
+        if let Some(env) = attached_env() {
+            if let Err(e) = callback.call(&env, result) {
+                log_error!("failed to call a job callback {:?}", e);
+            }
+            clear_exception(&env, "JobCallback");
+        }
+    }))
+}
+
+unsafe extern "C" fn syncAsync(env: JNIEnv, _: JClass, manager: jlong, callback: JObject) -> jlong {
+    ffi_lock!();
+    ffi_guard!({
+        let manager = jni_object!(env, "ListManager.syncAsync", manager as Arc<ListManager>, 0);
+        match completion(&env, "ListManager.syncAsync", callback) {
+            Some(completion) => jobs::sync(manager, Some(completion)) as jlong,
+            None => 0,
+        }
+    })
+}
+
+unsafe extern "C" fn importDumpAsync(env: JNIEnv, _: JClass, manager: jlong, text: JString, source: jint, callback: JObject) -> jlong {
+    ffi_lock!();
+    ffi_guard!({
+        let manager = jni_object!(env, "ListManager.importDumpAsync", manager as Arc<ListManager>, 0);
+        let source = match source {
+            0 => ImportSource::Todoist,
+            1 => ImportSource::GoogleTasks,
+            source => {
+                throw(&env, ErrorCategory::Bug, &format!("{} is not an import source", source));
+                return 0;
+            },
+        };
+        let text = match rust_string(&env, "ListManager.importDumpAsync", text) {
+            Some(text) => text,
+            None => return 0,
+        };
+        match completion(&env, "ListManager.importDumpAsync", callback) {
+            Some(completion) => jobs::import_dump(manager, text, source, Some(completion)) as jlong,
+            None => 0,
+        }
+    })
+}
+
+unsafe extern "C" fn searchAsync(env: JNIEnv, _: JClass, manager: jlong, query: JString, callback: JObject) -> jlong {
+    ffi_lock!();
+    ffi_guard!({
+        let manager = jni_object!(env, "ListManager.searchAsync", manager as Arc<ListManager>, 0);
+        let filter = match rust_string(&env, "ListManager.searchAsync", query) {
+            Some(query) => ItemFilter { name_contains: Some(query), ..ItemFilter::default() },
+            None => return 0,
+        };
+        match completion(&env, "ListManager.searchAsync", callback) {
+            Some(completion) => jobs::fetch_items(manager, filter, Some(completion)) as jlong,
+            None => 0,
+        }
+    })
+}
+
+unsafe extern "C" fn cancelJob(_: JNIEnv, _: JClass, job: jlong) -> jboolean {
+    ffi_guard!({
+        if jobs::cancel(job as u64) { JNI_TRUE } else { JNI_FALSE }
+    })
+}
+
+/// The natives of `ListManager` above, for `JNI_OnLoad` to register.
+pub(crate) fn natives() -> Vec<Native> {
+    vec![
+        native!(syncAsync, "(JL$/JobCallback;)J"),
+        native!(importDumpAsync, "(JLjava/lang/String;IL$/JobCallback;)J"),
+        native!(searchAsync, "(JLjava/lang/String;L$/JobCallback;)J"),
+        native!(cancelJob, "(J)Z"),
+    ]
+}
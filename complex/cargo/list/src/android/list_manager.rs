@@ -0,0 +1,162 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! Natives of `com.mozilla.toodle.ListManager`, which read whole Java objects and write
+//! items given as pointers from the C API:
+//!
+//! ```java
+//! static native Item[] fetchItems(long manager);
+//! static native Item fetchItem(long manager, String uuid);
+//! static native Label[] fetchLabels(long manager);
+//! static native String createItem(long manager, long item);
+//! static native void saveItem(long manager, long item);
+//! static native boolean deleteItem(long manager, String uuid);
+//! ```
+//!
+//! `manager` is a pointer from `toodle_list`. Store errors are thrown as a
+//! `ToodleException` with the category `store::errors::category_of` gives them.
+
+use std::ptr;
+use std::sync::Arc;
+
+use jni::JNIEnv;
+use jni::objects::{
+    JClass,
+    JString,
+};
+use jni::sys::{
+    jboolean,
+    jlong,
+    jobject,
+    jobjectArray,
+    jstring,
+    JNI_FALSE,
+    JNI_TRUE,
+};
+use rusqlite;
+
+use android::exceptions::{
+    throw,
+    throw_jni_error,
+};
+use android::objects::Objects;
+use android::{
+    java_string,
+    rust_string,
+    Native,
+};
+use filters::ItemFilter;
+use items::Item;
+use store::errors::category_of;
+use ListManager;
+
+/// Throws if building the objects failed.
+fn built<T>(env: &JNIEnv, function: &str, result: ::jni::errors::Result<*mut T>) -> *mut T {
+    result.unwrap_or_else(|e| {
+        throw_jni_error(env, function, e);
+        ptr::null_mut()
+    })
+}
+
+fn throw_store_error(env: &JNIEnv, function: &str, error: &rusqlite::Error) {
+    throw(env, category_of(error), &format!("{} failed: {}", function, error));
+}
+
+/// Every item, unfiltered.
+unsafe extern "C" fn fetchItems(env: JNIEnv, _: JClass, manager: jlong) -> jobjectArray {
+    ffi_lock!();
+    ffi_guard!({
+        let manager = jni_object!(env, "ListManager.fetchItems", manager as Arc<ListManager>, ptr::null_mut());
+        let items = manager.fetch_items(&ItemFilter::default());
+        built(&env, "ListManager.fetchItems", Objects::new(&env).and_then(|objects| objects.items(&items)))
+    })
+}
+
+/// Null if there is no item with that uuid.
+unsafe extern "C" fn fetchItem(env: JNIEnv, _: JClass, manager: jlong, uuid: JString) -> jobject {
+    ffi_lock!();
+    ffi_guard!({
+        let manager = jni_object!(env, "ListManager.fetchItem", manager as Arc<ListManager>, ptr::null_mut());
+        let item = match rust_string(&env, "ListManager.fetchItem", uuid).and_then(|uuid| manager.fetch_item(&uuid)) {
+            Some(item) => item,
+            None => return ptr::null_mut(),
+        };
+        built(&env, "ListManager.fetchItem", Objects::new(&env).and_then(|objects| objects.item(&item)).map(|item| item.into_inner()))
+    })
+}
+
+unsafe extern "C" fn fetchLabels(env: JNIEnv, _: JClass, manager: jlong) -> jobjectArray {
+    ffi_lock!();
+    ffi_guard!({
+        let manager = jni_object!(env, "ListManager.fetchLabels", manager as Arc<ListManager>, ptr::null_mut());
+        let labels = manager.fetch_labels();
+        built(&env, "ListManager.fetchLabels", Objects::new(&env).and_then(|objects| objects.labels(&labels)))
+    })
+}
+
+/// Stores the item under a new uuid and returns it.
+unsafe extern "C" fn createItem(env: JNIEnv, _: JClass, manager: jlong, item: jlong) -> jstring {
+    ffi_lock!();
+    ffi_guard!({
+        let manager = jni_object!(env, "ListManager.createItem", manager as Arc<ListManager>, ptr::null_mut());
+        let item = jni_object!(env, "ListManager.createItem", item as Item, ptr::null_mut());
+        match manager.try_create_item(item) {
+            Ok(uuid) => java_string(&env, "ListManager.createItem", &uuid),
+            Err(e) => {
+                throw_store_error(&env, "ListManager.createItem", &e);
+                ptr::null_mut()
+            },
+        }
+    })
+}
+
+/// Writes the item over the stored item with the same uuid.
+unsafe extern "C" fn saveItem(env: JNIEnv, _: JClass, manager: jlong, item: jlong) {
+    ffi_lock!();
+    ffi_guard!({
+        let manager = jni_object!(env, "ListManager.saveItem", manager as Arc<ListManager>, ());
+        let item = jni_object!(env, "ListManager.saveItem", item as Item, ());
+        if let Err(e) = manager.try_save_item(item) {
+            throw_store_error(&env, "ListManager.saveItem", &e);
+        }
+    })
+}
+
+/// Returns false if there is no item with that uuid.
+unsafe extern "C" fn deleteItem(env: JNIEnv, _: JClass, manager: jlong, uuid: JString) -> jboolean {
+    ffi_lock!();
+    ffi_guard!({
+        let manager = jni_object!(env, "ListManager.deleteItem", manager as Arc<ListManager>, JNI_FALSE);
+        let uuid = match rust_string(&env, "ListManager.deleteItem", uuid) {
+            Some(uuid) => uuid,
+            None => return JNI_FALSE,
+        };
+        match manager.try_delete_item(&uuid) {
+            Ok(true) => JNI_TRUE,
+            Ok(false) => JNI_FALSE,
+            Err(e) => {
+                throw_store_error(&env, "ListManager.deleteItem", &e);
+                JNI_FALSE
+            },
+        }
+    })
+}
+
+/// The natives of `ListManager` above, for `JNI_OnLoad` to register.
+pub(crate) fn natives() -> Vec<Native> {
+    vec![
+        native!(fetchItems, "(J)[L$/Item;"),
+        native!(fetchItem, "(JLjava/lang/String;)L$/Item;"),
+        native!(fetchLabels, "(J)[L$/Label;"),
+        native!(createItem, "(JJ)Ljava/lang/String;"),
+        native!(saveItem, "(JJ)V"),
+        native!(deleteItem, "(JLjava/lang/String;)Z"),
+    ]
+}
@@ -0,0 +1,59 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! Natives of `com.mozilla.toodle.Toodle` that configure logging to logcat:
+//!
+//! ```java
+//! static native void setLogTag(String tag);
+//! // `level` is a LogLevel from toodle.h
+//! static native void setLogLevel(int level);
+//! ```
+
+use jni::JNIEnv;
+use jni::objects::{
+    JClass,
+    JString,
+};
+use jni::sys::jint;
+
+use ffi_utils::android::log;
+use ffi_utils::errors::ErrorCategory;
+use ffi_utils::logging::LogLevel;
+
+use android::exceptions::throw;
+use android::{
+    rust_string,
+    Native,
+};
+
+unsafe extern "C" fn setLogTag(env: JNIEnv, _: JClass, tag: JString) {
+    ffi_guard!({
+        if let Some(tag) = rust_string(&env, "Toodle.setLogTag", tag) {
+            log::set_tag(&tag);
+        }
+    })
+}
+
+unsafe extern "C" fn setLogLevel(env: JNIEnv, _: JClass, level: jint) {
+    ffi_guard!({
+        match LogLevel::from_i32(level) {
+            Some(level) => log::set_level(level),
+            None => throw(&env, ErrorCategory::Bug, &format!("{} is not a log level", level)),
+        }
+    })
+}
+
+/// The natives of `Toodle` above, for `JNI_OnLoad` to register.
+pub(crate) fn natives() -> Vec<Native> {
+    vec![
+        native!(setLogTag, "(Ljava/lang/String;)V"),
+        native!(setLogLevel, "(I)V"),
+    ]
+}
@@ -0,0 +1,271 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! JNI bindings for the Android app, in `com.mozilla.toodle`. Java keeps Rust objects as
+//! their address in a `long`, which is always the first argument of a native method, and
+//! the natives are `static` so they never need the Java object itself. Failures are thrown
+//! as a `ToodleException`.
+//!
+//! `JNI_OnLoad` registers the natives with `RegisterNatives`, so it is the only symbol Java
+//! needs and the rest can be hidden or stripped. The classes are looked up in the package
+//! `TOODLE_JAVA_PACKAGE` names at build time, e.g. `org.example.todo`, or in
+//! `com.mozilla.toodle` if it is not set.
+
+use std::ffi::CString;
+use std::os::raw::c_void;
+use std::ptr;
+use std::sync::atomic::{
+    AtomicPtr,
+    Ordering,
+};
+
+use jni::JNIEnv;
+use jni::errors::Result;
+use jni::objects::{
+    JObject,
+    JString,
+};
+use jni::sys::{
+    self,
+    jint,
+    jobject,
+    jobjectArray,
+    jsize,
+    jstring,
+    JavaVM,
+    JNINativeMethod,
+    JNI_ERR,
+    JNI_OK,
+    JNI_VERSION_1_6,
+};
+
+use android::exceptions::{
+    throw_jni_error,
+    throw_null,
+};
+
+/// Borrows the object Java passed as a `long`, or throws a `ToodleException` and returns
+/// `$default` from the enclosing function if it is 0.
+macro_rules! jni_object {
+    ($env:expr, $function:expr, $pointer:ident as $ty:ty, $default:expr) => {
+        match ($pointer as *mut $ty).as_mut() {
+            Some(value) => value,
+            None => {
+                ::android::exceptions::throw_null(&$env, $function, stringify!($pointer));
+                return $default;
+            },
+        }
+    };
+}
+
+/// A `Native` for the function of that name, whose JNI signature is `$signature` with `$`
+/// standing for the package, e.g. `"(J)[L$/Item;"`.
+macro_rules! native {
+    ($function:ident, $signature:expr) => {
+        ::android::Native {
+            name: stringify!($function),
+            signature: $signature,
+            function: $function as *mut ::std::os::raw::c_void,
+        }
+    };
+}
+
+pub mod cursors;
+pub mod exceptions;
+pub mod items;
+pub mod jobs;
+pub mod list_manager;
+pub mod logging;
+pub mod objects;
+pub mod observers;
+
+/// The JVM that loaded the library, for calling Java from threads the library owns.
+static VM: AtomicPtr<JavaVM> = AtomicPtr::new(ptr::null_mut());
+
+/// A native method for `RegisterNatives`.
+pub(crate) struct Native {
+    pub name: &'static str,
+    pub signature: &'static str,
+    pub function: *mut c_void,
+}
+
+/// The package of the Java classes, `/`-separated as JNI wants it.
+fn java_package() -> String {
+    option_env!("TOODLE_JAVA_PACKAGE").unwrap_or("com.mozilla.toodle").replace('.', "/")
+}
+
+/// The JNI name of the class in the package, e.g. `com/mozilla/toodle/Item` for `Item`.
+pub(crate) fn java_class(name: &str) -> String {
+    format!("{}/{}", java_package(), name)
+}
+
+/// The signature with `$` replaced by the package.
+pub(crate) fn java_signature(signature: &str) -> String {
+    signature.replace('$', &java_package())
+}
+
+/// Registers the natives on the class in the package. Returns false, logged, if the class
+/// cannot be found or does not declare one of the natives.
+unsafe fn register_natives(env: &JNIEnv, class: &str, natives: &[Native]) -> bool {
+    let class = java_class(class);
+    let found = match env.find_class(class.as_str()) {
+        Ok(found) => found,
+        Err(e) => {
+            log_error!("failed to find {}: {:?}", class, e);
+            clear_exception(env, "JNI_OnLoad");
+            return false;
+        },
+    };
+    // The strings must outlive the call; the JVM copies what it needs.
+    let strings: Vec<(CString, CString)> = natives.iter()
+        .map(|native| (CString::new(native.name).unwrap(), CString::new(java_signature(native.signature)).unwrap()))
+        .collect();
+    let methods: Vec<JNINativeMethod> = natives.iter().zip(strings.iter())
+        .map(|(native, &(ref name, ref signature))| JNINativeMethod {
+            name: name.as_ptr() as *mut _,
+            signature: signature.as_ptr() as *mut _,
+            fnPtr: native.function,
+        })
+        .collect();
+    let raw = env.get_native_interface();
+    let registered = match (**raw).RegisterNatives {
+        Some(register) => register(raw, found.into_inner(), methods.as_ptr(), methods.len() as jint) == JNI_OK,
+        None => false,
+    };
+    if !registered {
+        log_error!("failed to register the natives of {}", class);
+        clear_exception(env, "JNI_OnLoad");
+    }
+    let _ = env.delete_local_ref(found.into());
+    registered
+}
+
+/// Called by the JVM when `System.loadLibrary` loads the library. Fails the load if a class
+/// is missing or does not match the natives, rather than failing on the first call.
+#[no_mangle]
+pub unsafe extern "system" fn JNI_OnLoad(vm: *mut JavaVM, _: *mut c_void) -> jint {
+    ffi_guard!({
+        VM.store(vm, Ordering::SeqCst);
+        let mut env: *mut c_void = ptr::null_mut();
+        let get_env = match (**vm).GetEnv {
+            Some(get_env) => get_env,
+            None => return JNI_ERR,
+        };
+        if get_env(vm, &mut env, JNI_VERSION_1_6) != JNI_OK {
+            log_error!("failed to get an env for JNI_OnLoad");
+            return JNI_ERR;
+        }
+        let env = JNIEnv::from(env as *mut sys::JNIEnv);
+        let classes = [
+            ("Toodle", logging::natives()),
+            ("Toodle", observers::natives()),
+            ("Item", items::natives()),
+            ("ListManager", list_manager::natives()),
+            ("ListManager", jobs::natives()),
+            ("ToodleCursor", cursors::natives()),
+        ];
+        for &(class, ref natives) in classes.iter() {
+            if !register_natives(&env, class, natives) {
+                return JNI_ERR;
+            }
+        }
+        JNI_VERSION_1_6
+    })
+}
+
+/// An env for the current thread, attaching it to the JVM as a daemon thread if it is not
+/// attached yet. A thread stays attached until it exits, so this is only for threads the
+/// library owns and never stops. `None`, logged, if the library was not loaded by a JVM.
+pub(crate) unsafe fn attached_env<'a>() -> Option<JNIEnv<'a>> {
+    let vm = VM.load(Ordering::SeqCst);
+    let attach = match vm.as_ref().and_then(|vm| (**vm).AttachCurrentThreadAsDaemon) {
+        Some(attach) => attach,
+        None => {
+            log_error!("no JVM to call into; was the library loaded with System.loadLibrary?");
+            return None;
+        },
+    };
+    let mut env: *mut c_void = ptr::null_mut();
+    if attach(vm, &mut env, ptr::null_mut()) != JNI_OK {
+        log_error!("failed to attach a thread to the JVM");
+        return None;
+    }
+    Some(JNIEnv::from(env as *mut sys::JNIEnv))
+}
+
+/// A global reference to `object`, which stays valid on any thread until it is deleted.
+pub(crate) unsafe fn new_global_ref(env: &JNIEnv, object: JObject) -> jobject {
+    let raw = env.get_native_interface();
+    match (**raw).NewGlobalRef {
+        Some(new_global_ref) => new_global_ref(raw, object.into_inner()),
+        None => ptr::null_mut(),
+    }
+}
+
+pub(crate) unsafe fn delete_global_ref(env: &JNIEnv, object: jobject) {
+    let raw = env.get_native_interface();
+    if let Some(delete_global_ref) = (**raw).DeleteGlobalRef {
+        delete_global_ref(raw, object);
+    }
+}
+
+/// Logs and clears the exception Java code threw into native code, if there is one, so the
+/// env can be used again.
+pub(crate) fn clear_exception(env: &JNIEnv, function: &str) {
+    if env.exception_check().unwrap_or(false) {
+        log_error!("{} caught a Java exception", function);
+        let _ = env.exception_describe();
+        let _ = env.exception_clear();
+    }
+}
+
+/// The Java string as a Rust one, or `None`, having thrown, if it is null or cannot be
+/// read.
+pub(crate) unsafe fn rust_string<'a>(env: &'a JNIEnv<'a>, function: &str, string: JString<'a>) -> Option<String> {
+    if string.into_inner().is_null() {
+        throw_null(env, function, "string");
+        return None;
+    }
+    match env.get_string(string) {
+        Ok(string) => Some(string.into()),
+        Err(e) => {
+            throw_jni_error(env, function, e);
+            None
+        },
+    }
+}
+
+/// A new Java string, or null, having thrown, if the JVM could not make one.
+pub(crate) fn java_string(env: &JNIEnv, function: &str, string: &str) -> jstring {
+    match env.new_string(string) {
+        Ok(string) => string.into_inner(),
+        Err(e) => {
+            throw_jni_error(env, function, e);
+            ptr::null_mut()
+        },
+    }
+}
+
+/// A new `String[]`, or null, having thrown, if the JVM could not make one.
+pub(crate) fn java_string_array(env: &JNIEnv, function: &str, strings: &[String]) -> jobjectArray {
+    let array = || -> Result<jobjectArray> {
+        let array = env.new_object_array(strings.len() as jsize, "java/lang/String", JObject::from(ptr::null_mut()))?;
+        for (index, string) in strings.iter().enumerate() {
+            let string: JObject = env.new_string(string.as_str())?.into();
+            env.set_object_array_element(array, index as jsize, string)?;
+            env.delete_local_ref(string)?;
+        }
+        Ok(array)
+    };
+    array().unwrap_or_else(|e| {
+        throw_jni_error(env, function, e);
+        ptr::null_mut()
+    })
+}
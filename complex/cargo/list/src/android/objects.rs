@@ -0,0 +1,129 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! Builds Java `Item` and `Label` objects, so Java gets a whole item in one call instead of
+//! one native call per field. The classes are expected to look like:
+//!
+//! ```java
+//! package com.mozilla.toodle;
+//!
+//! public final class Label {
+//!     public Label(String name, String color) { ... }
+//! }
+//!
+//! public final class Item {
+//!     // dates are in seconds since 1970, 0 if not set
+//!     public Item(String uuid, String name, long dueDate, long completionDate, Label[] labels) { ... }
+//! }
+//! ```
+
+use std::borrow::Borrow;
+use std::ptr;
+
+use jni::JNIEnv;
+use jni::errors::Result;
+use jni::objects::{
+    JClass,
+    JMethodID,
+    JObject,
+    JValue,
+};
+use jni::sys::{
+    jobjectArray,
+    jsize,
+};
+
+use android::{
+    java_class,
+    java_signature,
+};
+use items::Item;
+use labels::Label;
+
+const LABEL_CONSTRUCTOR: &'static str = "(Ljava/lang/String;Ljava/lang/String;)V";
+const ITEM_CONSTRUCTOR: &'static str = "(Ljava/lang/String;Ljava/lang/String;JJ[L$/Label;)V";
+
+/// The classes and constructors, looked up once for all the objects one call returns.
+pub(crate) struct Objects<'a> {
+    env: &'a JNIEnv<'a>,
+    label_class: JClass<'a>,
+    label_constructor: JMethodID<'a>,
+    item_class: JClass<'a>,
+    item_constructor: JMethodID<'a>,
+}
+
+impl<'a> Objects<'a> {
+    pub fn new(env: &'a JNIEnv<'a>) -> Result<Objects<'a>> {
+        let label_class = env.find_class(java_class("Label").as_str())?;
+        let item_class = env.find_class(java_class("Item").as_str())?;
+        Ok(Objects {
+            env: env,
+            label_class: label_class,
+            label_constructor: env.get_method_id(label_class, "<init>", LABEL_CONSTRUCTOR)?,
+            item_class: item_class,
+            item_constructor: env.get_method_id(item_class, "<init>", java_signature(ITEM_CONSTRUCTOR).as_str())?,
+        })
+    }
+
+    fn string(&self, string: &str) -> Result<JValue<'a>> {
+        Ok(JValue::Object(self.env.new_string(string)?.into()))
+    }
+
+    /// Releases the local references the constructor arguments held.
+    fn delete(&self, args: &[JValue<'a>]) -> Result<()> {
+        for arg in args {
+            if let JValue::Object(object) = *arg {
+                self.env.delete_local_ref(object)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// An array of `class` with an element built by `object` for each value. Each element's
+    /// local reference is released once it is in the array, so long lists do not run out.
+    fn array<T, F>(&self, class: JClass<'a>, values: &[T], object: F) -> Result<jobjectArray>
+    where F: Fn(&T) -> Result<JObject<'a>> {
+        let array = self.env.new_object_array(values.len() as jsize, class, JObject::from(ptr::null_mut()))?;
+        for (index, value) in values.iter().enumerate() {
+            let element = object(value)?;
+            self.env.set_object_array_element(array, index as jsize, element)?;
+            self.env.delete_local_ref(element)?;
+        }
+        Ok(array)
+    }
+
+    pub fn label(&self, label: &Label) -> Result<JObject<'a>> {
+        let args = [self.string(&label.name)?, self.string(&label.color)?];
+        let label = self.env.new_object_by_id(self.label_class, self.label_constructor, &args)?;
+        self.delete(&args)?;
+        Ok(label)
+    }
+
+    pub fn labels<L: Borrow<Label>>(&self, labels: &[L]) -> Result<jobjectArray> {
+        self.array(self.label_class, labels, |label| self.label(label.borrow()))
+    }
+
+    pub fn item(&self, item: &Item) -> Result<JObject<'a>> {
+        let args = [
+            self.string(&item.uuid)?,
+            self.string(&item.name)?,
+            JValue::Long(item.due_date.map_or(0, |date| date.sec)),
+            JValue::Long(item.completion_date.map_or(0, |date| date.sec)),
+            JValue::Object(JObject::from(self.labels(&item.labels)?)),
+        ];
+        let item = self.env.new_object_by_id(self.item_class, self.item_constructor, &args)?;
+        self.delete(&args)?;
+        Ok(item)
+    }
+
+    pub fn items(&self, items: &[Item]) -> Result<jobjectArray> {
+        self.array(self.item_class, items, |item| self.item(item))
+    }
+}
@@ -0,0 +1,129 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! Java listeners for changes made through any list manager, so LiveData or a Flow can be
+//! updated from Rust. Listeners are called on the dispatcher thread, which is attached to
+//! the JVM on first use:
+//!
+//! ```java
+//! public interface ToodleObserver {
+//!     // `kind` is a ChangeEventKind from events.h; `id` is the item's uuid or the label's
+//!     // name, or null
+//!     void onChange(int kind, String id);
+//! }
+//!
+//! // on com.mozilla.toodle.Toodle
+//! static native long registerObserver(ToodleObserver observer);
+//! static native boolean unregisterObserver(long token);
+//! ```
+
+use std::collections::BTreeMap;
+use std::ffi::CStr;
+use std::os::raw::{
+    c_char,
+    c_void,
+};
+use std::ptr;
+use std::sync::Mutex;
+
+use jni::JNIEnv;
+use jni::objects::{
+    JClass,
+    JObject,
+    JValue,
+};
+use jni::sys::{
+    jboolean,
+    jlong,
+    jobject,
+    JNI_FALSE,
+    JNI_TRUE,
+};
+
+use android::exceptions::throw_null;
+use android::{
+    attached_env,
+    clear_exception,
+    delete_global_ref,
+    new_global_ref,
+    Native,
+};
+use dispatcher;
+use events::ChangeEventKind;
+
+/// Global references to the registered listeners, by token, kept as integers so they may
+/// cross threads.
+static OBSERVERS: Mutex<BTreeMap<u64, usize>> = Mutex::new(BTreeMap::new());
+
+extern "C" fn deliver(kind: ChangeEventKind, id: *const c_char, user_data: *mut c_void) {
+    let env = match unsafe { attached_env() } {
+        Some(env) => env,
+        None => return,
+    };
+    let id = if id.is_null() {
+        JObject::from(ptr::null_mut())
+    } else {
+        let id = unsafe { CStr::from_ptr(id) }.to_string_lossy();
+        match env.new_string(id.as_ref()) {
+            Ok(id) => id.into(),
+            Err(e) => {
+                log_error!("failed to make a string for an observer {:?}", e);
+                clear_exception(&env, "ToodleObserver.onChange");
+                return;
+            },
+        }
+    };
+    let listener = JObject::from(user_data as jobject);
+    if let Err(e) = env.call_method(listener, "onChange", "(ILjava/lang/String;)V", &[JValue::Int(kind as i32), JValue::Object(id)]) {
+        log_error!("failed to notify an observer {:?}", e);
+        clear_exception(&env, "ToodleObserver.onChange");
+    }
+    // the dispatcher thread never returns to Java, which would free local references
+    if !id.into_inner().is_null() {
+        let _ = env.delete_local_ref(id);
+    }
+}
+
+/// Returns a token for `unregisterObserver`, or 0 if `observer` is null.
+unsafe extern "C" fn registerObserver(env: JNIEnv, _: JClass, observer: JObject) -> jlong {
+    ffi_guard!({
+        if observer.into_inner().is_null() {
+            throw_null(&env, "Toodle.registerObserver", "observer");
+            return 0;
+        }
+        let listener = new_global_ref(&env, observer);
+        let mut observers = OBSERVERS.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let token = dispatcher::register(deliver, listener as *mut c_void);
+        observers.insert(token, listener as usize);
+        token as jlong
+    })
+}
+
+/// Returns false if there is no observer with that token. Once it returns the observer is
+/// not called again.
+unsafe extern "C" fn unregisterObserver(env: JNIEnv, _: JClass, token: jlong) -> jboolean {
+    ffi_guard!({
+        let listener = match OBSERVERS.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).remove(&(token as u64)) {
+            Some(listener) => listener as jobject,
+            None => return JNI_FALSE,
+        };
+        dispatcher::unregister(token as u64);
+        delete_global_ref(&env, listener);
+        JNI_TRUE
+    })
+}
+
+/// The natives of `Toodle` above, for `JNI_OnLoad` to register.
+pub(crate) fn natives() -> Vec<Native> {
+    vec![
+        native!(registerObserver, "(L$/ToodleObserver;)J"),
+        native!(unregisterObserver, "(J)Z"),
+    ]
+}
@@ -0,0 +1,383 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+use std::os::raw::c_char;
+use std::ptr;
+use std::sync::Arc;
+
+use rusqlite;
+use rusqlite::Row;
+use serde_json;
+
+use ffi_utils::errors::{
+    set_extern_error,
+    ErrorCategory,
+    ExternError,
+};
+use ffi_utils::strings::{
+    string_to_c_char,
+    c_char_to_string,
+};
+use events::ChangeEvent;
+use search::transliterate;
+use store::errors::set_sqlite_error;
+use store::migrations::MigrationStep;
+use ListManager;
+
+pub const CREATE_ATTACHMENTS_TABLE: MigrationStep = MigrationStep {
+    name: "create_attachments_table",
+    sql: r#"CREATE TABLE IF NOT EXISTS attachments (
+                id INTEGER PRIMARY KEY,
+                item_uuid TEXT NOT NULL,
+                kind TEXT NOT NULL,
+                url TEXT,
+                title TEXT,
+                vcard TEXT,
+                latitude REAL,
+                longitude REAL,
+                label TEXT,
+                search_text TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS attachments_item_uuid ON attachments (item_uuid);"#,
+};
+
+const KIND_LINK: &'static str = "link";
+const KIND_CONTACT: &'static str = "contact";
+const KIND_LOCATION: &'static str = "location";
+
+/// What is attached. Serialized with a `kind` key naming the variant.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Payload {
+    Link {
+        url: String,
+        #[serde(default)]
+        title: Option<String>,
+    },
+    /// A vCard, as shared by the platform's contacts picker.
+    Contact {
+        vcard: String,
+    },
+    Location {
+        latitude: f64,
+        longitude: f64,
+        #[serde(default)]
+        label: Option<String>,
+    },
+}
+
+/// The values of the vCard properties worth searching for: name, organization, phone
+/// numbers and email addresses.
+fn vcard_search_values(vcard: &str) -> Vec<String> {
+    vcard.lines().filter_map(|line| {
+        let colon = line.find(':')?;
+        let property = line[..colon].split(';').next().unwrap_or("").to_uppercase();
+        match property.as_str() {
+            "FN" | "ORG" | "TEL" | "EMAIL" => Some(line[colon + 1..].replace(';', " ").trim().to_string()),
+            _ => None,
+        }
+    }).collect()
+}
+
+impl Payload {
+    fn kind(&self) -> &'static str {
+        match *self {
+            Payload::Link { .. } => KIND_LINK,
+            Payload::Contact { .. } => KIND_CONTACT,
+            Payload::Location { .. } => KIND_LOCATION,
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        match *self {
+            Payload::Link { ref url, .. } => url.contains("://"),
+            Payload::Contact { ref vcard } => vcard.trim_start().to_uppercase().starts_with("BEGIN:VCARD"),
+            Payload::Location { latitude, longitude, .. } => {
+                latitude >= -90.0 && latitude <= 90.0 && longitude >= -180.0 && longitude <= 180.0
+            },
+        }
+    }
+
+    /// The text that finds the item when searched for by name.
+    fn search_text(&self) -> String {
+        let text = match *self {
+            Payload::Link { ref url, ref title } => format!("{} {}", title.as_deref().unwrap_or(""), url),
+            Payload::Contact { ref vcard } => vcard_search_values(vcard).join(" "),
+            Payload::Location { ref label, .. } => label.clone().unwrap_or_default(),
+        };
+        transliterate(&text)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Attachment {
+    pub id: i64,
+    pub item_uuid: String,
+    #[serde(flatten)]
+    pub payload: Payload,
+}
+
+fn attachment_from_row(row: &Row) -> Option<Attachment> {
+    let kind: String = row.get(2);
+    let payload = match kind.as_str() {
+        KIND_LINK => Payload::Link {
+            url: row.get(3),
+            title: row.get(4),
+        },
+        KIND_CONTACT => Payload::Contact {
+            vcard: row.get(5),
+        },
+        KIND_LOCATION => Payload::Location {
+            latitude: row.get(6),
+            longitude: row.get(7),
+            label: row.get(8),
+        },
+        _ => return None,
+    };
+    Some(Attachment {
+        id: row.get(0),
+        item_uuid: row.get(1),
+        payload: payload,
+    })
+}
+
+impl ListManager {
+    /// Returns `None` if there is no item with that uuid or the payload is not valid, e.g. a
+    /// location off the globe.
+    pub fn add_attachment(&self, item_uuid: &String, payload: Payload) -> Option<Attachment> {
+        let kind = payload.kind();
+        self.try_add_attachment(item_uuid, payload).unwrap_or_else(|e| {
+            log_error!("failed to add {} attachment {:?}", kind, e);
+            None
+        })
+    }
+
+    /// Like `add_attachment`, but returns the error if the attachment cannot be written.
+    pub fn try_add_attachment(&self, item_uuid: &String, payload: Payload) -> rusqlite::Result<Option<Attachment>> {
+        if !payload.is_valid() || self.fetch_item(item_uuid).is_none() {
+            return Ok(None);
+        }
+        let sql = r#"INSERT INTO attachments (item_uuid, kind, url, title, vcard, latitude, longitude, label, search_text)
+                     VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)"#;
+        let none_text: Option<String> = None;
+        let none_number: Option<f64> = None;
+        let search_text = payload.search_text();
+        let conn = self.get_store().get_conn();
+        match payload {
+            Payload::Link { ref url, ref title } =>
+                conn.execute(sql, &[item_uuid, &KIND_LINK, url, title, &none_text, &none_number, &none_number, &none_text, &search_text]),
+            Payload::Contact { ref vcard } =>
+                conn.execute(sql, &[item_uuid, &KIND_CONTACT, &none_text, &none_text, vcard, &none_number, &none_number, &none_text, &search_text]),
+            Payload::Location { latitude, longitude, ref label } =>
+                conn.execute(sql, &[item_uuid, &KIND_LOCATION, &none_text, &none_text, &none_text, &latitude, &longitude, label, &search_text]),
+        }?;
+        let attachment = Attachment {
+            id: conn.last_insert_rowid(),
+            item_uuid: item_uuid.clone(),
+            payload: payload,
+        };
+        self.notify(ChangeEvent::ItemUpdated(item_uuid.clone()));
+        Ok(Some(attachment))
+    }
+
+    pub fn fetch_attachments(&self, item_uuid: &String) -> Vec<Attachment> {
+        let sql = r#"SELECT id, item_uuid, kind, url, title, vcard, latitude, longitude, label
+                     FROM attachments WHERE item_uuid=? ORDER BY id"#;
+        let conn = self.get_store().get_conn();
+        let mut stmt = conn.prepare(sql).unwrap();
+        let attachment_iter = stmt.query_map(&[item_uuid], |row| attachment_from_row(row)).unwrap();
+        attachment_iter.filter_map(|result| result.ok().and_then(|attachment| attachment)).collect()
+    }
+
+    /// Returns false if there is no attachment with that id.
+    pub fn remove_attachment(&self, id: i64) -> bool {
+        self.try_remove_attachment(id).unwrap_or_else(|e| {
+            log_error!("failed to remove attachment {:?}", e);
+            false
+        })
+    }
+
+    pub fn try_remove_attachment(&self, id: i64) -> rusqlite::Result<bool> {
+        let conn = self.get_store().get_conn();
+        let item_uuid: String = match conn.query_row("SELECT item_uuid FROM attachments WHERE id=?", &[&id], |row| row.get(0)) {
+            Ok(item_uuid) => item_uuid,
+            Err(rusqlite::Error::QueryReturnedNoRows) => return Ok(false),
+            Err(e) => return Err(e),
+        };
+        conn.execute("DELETE FROM attachments WHERE id=?", &[&id])?;
+        self.notify(ChangeEvent::ItemUpdated(item_uuid));
+        Ok(true)
+    }
+}
+
+/// `json` is an object with a `kind` of `link` (`url`, optional `title`), `contact` (`vcard`)
+/// or `location` (`latitude`, `longitude`, optional `label`). Returns the attachment as JSON
+/// with its `id`, or null and fills in `error` if the payload is not understood (`Bug`) or
+/// valid (`NeedsUserInput`), there is no such item (`Bug`), or it could not be written.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_add_attachment(manager: *const Arc<ListManager>, item_uuid: *const c_char, json: *const c_char, error: *mut ExternError) -> *mut c_char {
+    ffi_lock!();
+    ffi_guard!(error, {
+        let manager = ffi_ref!("list_manager_add_attachment", manager, ptr::null_mut(), error);
+        let payload: Payload = match serde_json::from_str(&c_char_to_string(json)) {
+            Ok(payload) => payload,
+            Err(e) => {
+                log_error!("failed to parse attachment {:?}", e);
+                set_extern_error(error, ErrorCategory::Bug, e.to_string());
+                return ptr::null_mut();
+            },
+        };
+        if !payload.is_valid() {
+            set_extern_error(error, ErrorCategory::NeedsUserInput, format!("the {} attachment is not valid", payload.kind()));
+            return ptr::null_mut();
+        }
+        let item_uuid = c_char_to_string(item_uuid);
+        match manager.try_add_attachment(&item_uuid, payload) {
+            Ok(Some(attachment)) => string_to_c_char(serde_json::to_string(&attachment).unwrap()),
+            Ok(None) => {
+                set_extern_error(error, ErrorCategory::Bug, format!("no item with uuid {}", item_uuid));
+                ptr::null_mut()
+            },
+            Err(e) => {
+                log_error!("failed to add attachment {:?}", e);
+                set_sqlite_error(error, &e);
+                ptr::null_mut()
+            },
+        }
+    })
+}
+
+/// Returns the item's attachments as a JSON array, oldest first.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_item_attachments(manager: *const Arc<ListManager>, item_uuid: *const c_char) -> *mut c_char {
+    ffi_lock!();
+    ffi_guard!({
+        let manager = ffi_ref!("list_manager_item_attachments", manager, ptr::null_mut());
+        string_to_c_char(serde_json::to_string(&manager.fetch_attachments(&c_char_to_string(item_uuid))).unwrap())
+    })
+}
+
+/// Returns false if there is no attachment with that id, or if it could not be removed,
+/// filling in `error`.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_remove_attachment(manager: *const Arc<ListManager>, id: i64, error: *mut ExternError) -> bool {
+    ffi_lock!();
+    ffi_guard!(error, {
+        let manager = ffi_ref!("list_manager_remove_attachment", manager, false, error);
+        manager.try_remove_attachment(id).unwrap_or_else(|e| {
+            log_error!("failed to remove attachment {:?}", e);
+            set_sqlite_error(error, &e);
+            false
+        })
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use serde_json;
+
+    use super::Payload;
+
+    use filters::ItemFilter;
+    use items::Item;
+    use store::Store;
+    use ListManager;
+
+    const PLUMBER: &'static str = "BEGIN:VCARD\r\nVERSION:3.0\r\nN:Łukasz;Nowak;;;\r\nFN:Łukasz Nowak\r\nORG:Pipes & Co\r\nTEL;TYPE=CELL:+48 600 100 200\r\nEND:VCARD\r\n";
+
+    fn item(manager: &ListManager, name: &str) -> String {
+        manager.create_item(&Item::named(name))
+    }
+
+    #[test]
+    fn test_attachments() {
+        let manager = ListManager::new(Arc::new(Store::new(None)));
+        let uuid = item(&manager, "Call the plumber");
+        let contact = manager.add_attachment(&uuid, Payload::Contact { vcard: PLUMBER.to_string() }).unwrap();
+        let location: Payload = serde_json::from_str(r#"{"kind": "location", "latitude": 52.23, "longitude": 21.01, "label": "Home"}"#).unwrap();
+        manager.add_attachment(&uuid, location.clone()).unwrap();
+
+        let attachments = manager.fetch_attachments(&uuid);
+        assert_eq!(attachments.iter().map(|a| a.payload.clone()).collect::<Vec<Payload>>(),
+                   vec![Payload::Contact { vcard: PLUMBER.to_string() }, location]);
+        let json: serde_json::Value = serde_json::from_str(&serde_json::to_string(&attachments[1]).unwrap()).unwrap();
+        assert_eq!(json["kind"], "location");
+        assert_eq!(json["label"], "Home");
+
+        assert!(manager.remove_attachment(contact.id));
+        assert!(!manager.remove_attachment(contact.id));
+        assert_eq!(manager.fetch_attachments(&uuid).len(), 1);
+
+        manager.delete_item(&uuid);
+        assert_eq!(manager.fetch_attachments(&uuid), vec![]);
+    }
+
+    #[test]
+    fn test_invalid_attachments() {
+        let manager = ListManager::new(Arc::new(Store::new(None)));
+        let uuid = item(&manager, "Visit");
+        assert_eq!(manager.add_attachment(&uuid, Payload::Location { latitude: 91.0, longitude: 0.0, label: None }), None);
+        assert_eq!(manager.add_attachment(&uuid, Payload::Link { url: "not a url".to_string(), title: None }), None);
+        assert_eq!(manager.add_attachment(&uuid, Payload::Contact { vcard: "Łukasz".to_string() }), None);
+        let link = Payload::Link { url: "https://example.com".to_string(), title: None };
+        assert_eq!(manager.add_attachment(&"missing".to_string(), link), None);
+    }
+
+    #[test]
+    fn test_attachment_errors_cross_the_boundary() {
+        use std::ffi::CString;
+        use ffi_utils::errors::{
+            error_message_destroy,
+            ErrorCategory,
+            ExternError,
+        };
+        use super::list_manager_add_attachment;
+
+        let manager = Arc::new(ListManager::new(Arc::new(Store::new(None))));
+        let uuid = CString::new(item(&manager, "Visit")).unwrap();
+        let add = |uuid: &CString, json: &str| {
+            let json = CString::new(json).unwrap();
+            let mut error = ExternError::default();
+            let attachment = unsafe { list_manager_add_attachment(&manager, uuid.as_ptr(), json.as_ptr(), &mut error) };
+            assert!(attachment.is_null());
+            unsafe { error_message_destroy(error.message) };
+            error.category
+        };
+        assert_eq!(add(&uuid, r#"{"kind": "link"}"#), ErrorCategory::Bug);
+        assert_eq!(add(&uuid, r#"{"kind": "location", "latitude": 91.0, "longitude": 0.0}"#), ErrorCategory::NeedsUserInput);
+        assert_eq!(add(&CString::new("missing").unwrap(), r#"{"kind": "link", "url": "https://example.com"}"#), ErrorCategory::Bug);
+        manager.get_store().get_conn().execute_batch("PRAGMA query_only = 1").unwrap();
+        assert_eq!(add(&uuid, r#"{"kind": "link", "url": "https://example.com"}"#), ErrorCategory::NeedsUserInput);
+    }
+
+    #[test]
+    fn test_search_attachments() {
+        let manager = ListManager::new(Arc::new(Store::new(None)));
+        let plumber = item(&manager, "Call the plumber");
+        let reading = item(&manager, "Read later");
+        item(&manager, "Unrelated");
+        manager.add_attachment(&plumber, Payload::Contact { vcard: PLUMBER.to_string() }).unwrap();
+        manager.add_attachment(&reading, Payload::Link { url: "https://example.com/rust".to_string(), title: Some("Ownership explained".to_string()) }).unwrap();
+
+        let search = |text: &str| {
+            let filter = ItemFilter {
+                name_contains: Some(text.to_string()),
+                ..ItemFilter::default()
+            };
+            manager.fetch_items(&filter).iter().map(|i| i.name.clone()).collect::<Vec<String>>()
+        };
+        assert_eq!(search("lukasz"), vec!["Call the plumber"]);
+        assert_eq!(search("600 100"), vec!["Call the plumber"]);
+        assert_eq!(search("ownership"), vec!["Read later"]);
+        assert_eq!(search("example.com"), vec!["Read later"]);
+    }
+}
@@ -0,0 +1,250 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+use std::collections::BTreeMap;
+use std::os::raw::c_char;
+use std::ptr;
+use std::sync::Arc;
+
+use rusqlite;
+use rusqlite::Connection;
+use serde_json;
+use time::Timespec;
+
+use ffi_utils::strings::{
+    string_to_c_char,
+    c_char_to_string,
+};
+use items::Item;
+use store::migrations::MigrationStep;
+use ListManager;
+
+pub const CREATE_ITEM_FIELD_EDITS_TABLE: MigrationStep = MigrationStep {
+    name: "create_item_field_edits_table",
+    sql: r#"CREATE TABLE IF NOT EXISTS item_field_edits (
+            item_uuid TEXT NOT NULL,
+            field TEXT NOT NULL,
+            editor TEXT,
+            edited_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            PRIMARY KEY(item_uuid, field)
+        )"#,
+};
+
+pub const FIELD_NAME: &'static str = "name";
+pub const FIELD_DUE_DATE: &'static str = "due_date";
+pub const FIELD_COMPLETION_DATE: &'static str = "completion_date";
+pub const FIELD_LABELS: &'static str = "labels";
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+/// Who last changed a field of an item, and when.
+pub struct FieldEdit {
+    /// The account or device set with `set_editor` when the change was made, if any.
+    pub editor: Option<String>,
+    #[serde(serialize_with = "serialize_timespec")]
+    pub edited_at: Timespec,
+}
+
+fn serialize_timespec<S>(date: &Timespec, serializer: S) -> Result<S::Ok, S::Error>
+where S: ::serde::Serializer {
+    serializer.serialize_i64(date.sec)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+/// An item serialized together with the last edit of each of its fields, keyed by field name.
+pub struct AttributedItem {
+    #[serde(flatten)]
+    pub item: Item,
+    pub last_edited: BTreeMap<String, FieldEdit>,
+}
+
+/// The fields of `new` that differ from `old`, or every field that is set if there is
+/// no previous version.
+pub fn changed_fields(old: Option<&Item>, new: &Item) -> Vec<&'static str> {
+    let mut fields = Vec::new();
+    let label_names = |item: &Item| {
+        let mut names: Vec<String> = item.labels.iter().map(|l| l.name.clone()).collect();
+        names.sort();
+        names
+    };
+    match old {
+        Some(old) => {
+            if old.name != new.name {
+                fields.push(FIELD_NAME);
+            }
+            if old.due_date != new.due_date {
+                fields.push(FIELD_DUE_DATE);
+            }
+            if old.completion_date != new.completion_date {
+                fields.push(FIELD_COMPLETION_DATE);
+            }
+            if label_names(old) != label_names(new) {
+                fields.push(FIELD_LABELS);
+            }
+        },
+        None => {
+            fields.push(FIELD_NAME);
+            if new.due_date.is_some() {
+                fields.push(FIELD_DUE_DATE);
+            }
+            if new.completion_date.is_some() {
+                fields.push(FIELD_COMPLETION_DATE);
+            }
+            if !new.labels.is_empty() {
+                fields.push(FIELD_LABELS);
+            }
+        },
+    }
+    fields
+}
+
+impl ListManager {
+    /// Sets the account or device that subsequent edits are attributed to.
+    pub fn set_editor(&self, editor: Option<String>) {
+        *self.editor.borrow_mut() = editor;
+    }
+
+    /// Records the current editor against `fields` of the item. Expected to run inside
+    /// the transaction making the change.
+    pub(crate) fn record_field_edits(&self, conn: &Connection, item_uuid: &String, fields: &[&'static str]) -> rusqlite::Result<()> {
+        let sql = r#"INSERT OR REPLACE INTO item_field_edits (item_uuid, field, editor, edited_at)
+                     VALUES (?, ?, ?, ?)"#;
+        let editor = self.editor.borrow();
+        let now = self.now();
+        for field in fields.iter() {
+            conn.execute(sql, &[item_uuid, field, &*editor, &now])?;
+        }
+        Ok(())
+    }
+
+    /// Overwrites when fields of the item were last edited, e.g. with the times of edits
+    /// made on another device. Keeps who made them if that is known here.
+    pub(crate) fn set_edit_times(&self, item_uuid: &String, times: &BTreeMap<String, i64>) {
+        let conn = self.get_store().get_conn();
+        for (field, &seconds) in times.iter() {
+            let edited_at = Timespec::new(seconds, 0);
+            let updated = conn.execute("UPDATE item_field_edits SET edited_at=? WHERE item_uuid=? AND field=?",
+                                       &[&edited_at, item_uuid, field]).unwrap();
+            if updated == 0 {
+                conn.execute("INSERT INTO item_field_edits (item_uuid, field, edited_at) VALUES (?, ?, ?)",
+                             &[item_uuid, field, &edited_at]).unwrap();
+            }
+        }
+    }
+
+    pub fn fetch_field_edits(&self, item_uuid: &String) -> BTreeMap<String, FieldEdit> {
+        let sql = r#"SELECT field, editor, edited_at FROM item_field_edits WHERE item_uuid=?"#;
+        let conn = self.get_store().get_conn();
+        let mut stmt = conn.prepare(sql).unwrap();
+        let edit_iter = stmt.query_map(&[item_uuid], |row| {
+            let field: String = row.get(0);
+            (field, FieldEdit {
+                editor: row.get(1),
+                edited_at: row.get(2),
+            })
+        }).unwrap();
+        edit_iter.filter_map(|result| result.ok()).collect()
+    }
+
+    pub fn fetch_attributed_item(&self, item_uuid: &String) -> Option<AttributedItem> {
+        self.fetch_item(item_uuid).map(|item| {
+            AttributedItem {
+                last_edited: self.fetch_field_edits(item_uuid),
+                item: item,
+            }
+        })
+    }
+}
+
+/// `editor` may be null to stop attributing edits.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_set_editor(manager: *const Arc<ListManager>, editor: *const c_char) {
+    ffi_lock!();
+    ffi_guard!({
+        let manager = ffi_ref!("list_manager_set_editor", manager);
+        let editor = if editor.is_null() { None } else { Some(c_char_to_string(editor)) };
+        manager.set_editor(editor);
+    })
+}
+
+/// Returns the item as JSON with a `last_edited` object mapping field names to the
+/// `editor` and `edited_at` of their last change, or null if there is no such item.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_attributed_item_json(manager: *const Arc<ListManager>, uuid: *const c_char) -> *mut c_char {
+    ffi_lock!();
+    ffi_guard!({
+        let manager = ffi_ref!("list_manager_attributed_item_json", manager, ptr::null_mut());
+        match manager.fetch_attributed_item(&c_char_to_string(uuid)) {
+            Some(item) => string_to_c_char(serde_json::to_string(&item).unwrap()),
+            None => ptr::null_mut(),
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use serde_json;
+    use time::now_utc;
+
+    use super::{
+        FIELD_COMPLETION_DATE,
+        FIELD_DUE_DATE,
+        FIELD_LABELS,
+        FIELD_NAME,
+    };
+
+    use items::Item;
+    use store::Store;
+    use ListManager;
+
+    #[test]
+    fn test_edits_are_attributed_per_field() {
+        let manager = ListManager::new(Arc::new(Store::new(None)));
+        let label = manager.create_label("home".to_string(), "#000000".to_string()).unwrap();
+        manager.set_editor(Some("sam@phone".to_string()));
+        let mut item = Item {
+            uuid: "".to_string(),
+            name: "water plants".to_string(),
+            due_date: None,
+            completion_date: None,
+            labels: vec![Arc::new(label)],
+        };
+        item.uuid = manager.create_item(&item);
+
+        let edits = manager.fetch_field_edits(&item.uuid);
+        assert_eq!(edits.keys().cloned().collect::<Vec<String>>(), vec![FIELD_LABELS, FIELD_NAME]);
+        assert_eq!(edits[FIELD_NAME].editor, Some("sam@phone".to_string()));
+
+        manager.set_editor(Some("alex@laptop".to_string()));
+        item.due_date = Some(now_utc().to_timespec());
+        manager.save_item(&item);
+
+        let edits = manager.fetch_field_edits(&item.uuid);
+        assert_eq!(edits[FIELD_NAME].editor, Some("sam@phone".to_string()));
+        assert_eq!(edits[FIELD_DUE_DATE].editor, Some("alex@laptop".to_string()));
+        assert!(!edits.contains_key(FIELD_COMPLETION_DATE));
+    }
+
+    #[test]
+    fn test_attributed_item_json() {
+        let manager = ListManager::new(Arc::new(Store::new(None)));
+        let uuid = manager.create_item(&Item::named("call the bank"));
+
+        let item = manager.fetch_attributed_item(&uuid).expect("expected an item");
+        let json = serde_json::to_value(&item).unwrap();
+        assert_eq!(json["uuid"], uuid.as_str());
+        assert_eq!(json["name"], "call the bank");
+        assert!(json["last_edited"]["name"]["editor"].is_null());
+        assert!(json["last_edited"]["name"]["edited_at"].is_i64());
+
+        assert_eq!(manager.fetch_attributed_item(&"missing".to_string()), None);
+    }
+}
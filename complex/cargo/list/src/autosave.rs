@@ -0,0 +1,310 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+use std::cell::{
+    Cell,
+    RefCell,
+};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use rusqlite;
+use time::{
+    Duration,
+    Timespec,
+};
+
+use ffi_utils::breadcrumbs;
+use ffi_utils::errors::ExternError;
+use items::Item;
+use store::errors::set_sqlite_error;
+use ListManager;
+
+/// Items queued with `queue_save` that have not been written to the store yet, keyed
+/// by uuid. Saving the same item again replaces the queued copy.
+#[derive(Default)]
+pub struct PendingSaves {
+    items: Rc<RefCell<BTreeMap<String, Item>>>,
+    since: Rc<Cell<Option<Timespec>>>,
+    interval: Cell<Option<Duration>>,
+}
+
+impl fmt::Debug for PendingSaves {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "PendingSaves({})", self.items.borrow().len())
+    }
+}
+
+impl ListManager {
+    /// Queues `item` to be written on the next flush, giving it a uuid if it does not
+    /// have one yet. Flushes straight away if the auto-save interval has elapsed.
+    pub fn queue_save(&self, item: &mut Item) {
+        if let Err(e) = self.try_queue_save(item) {
+            log_error!("failed to auto-save {:?}", e);
+        }
+    }
+
+    /// Like `queue_save`, but returns the error if the auto-save fails. The item stays
+    /// queued either way.
+    pub fn try_queue_save(&self, item: &mut Item) -> rusqlite::Result<()> {
+        if item.uuid.is_empty() {
+            item.uuid = self.ids.next_uuid();
+        }
+        self.pending.items.borrow_mut().insert(item.uuid.clone(), item.clone());
+        if self.pending.since.get().is_none() {
+            self.pending.since.set(Some(self.now()));
+        }
+        self.autosave().map(|_| ())
+    }
+
+    /// Forgets the queued copy of an item, e.g. because it was deleted.
+    pub(crate) fn discard_queued_save(&self, uuid: &String) {
+        self.pending.items.borrow_mut().remove(uuid);
+    }
+
+    pub fn has_unsaved_changes(&self) -> bool {
+        !self.pending.items.borrow().is_empty()
+    }
+
+    pub fn is_unsaved(&self, uuid: &String) -> bool {
+        self.pending.items.borrow().contains_key(uuid)
+    }
+
+    /// Writes every queued item in a single transaction. If any cannot be written, none
+    /// are, and they stay queued. Inside a host transaction they stay queued until it
+    /// commits, as it may yet roll back.
+    pub fn flush(&self) -> rusqlite::Result<()> {
+        breadcrumbs::leave("list.flush");
+        let items: Vec<Item> = self.pending.items.borrow().values().cloned().collect();
+        if !items.is_empty() {
+            let tx = self.get_store().begin_transaction()?;
+            for item in items.iter() {
+                self.try_save_item(item)?;
+            }
+            tx.commit()?;
+        }
+        let (queued, since) = (self.pending.items.clone(), self.pending.since.clone());
+        self.get_store().after_commit(Box::new(move || {
+            let mut pending = queued.borrow_mut();
+            for item in items.iter() {
+                // queued again while it was being written
+                if pending.get(&item.uuid) == Some(item) {
+                    pending.remove(&item.uuid);
+                }
+            }
+            if pending.is_empty() {
+                since.set(None);
+            }
+        }));
+        Ok(())
+    }
+
+    /// How long queued items may wait before they are flushed. `None`, the default,
+    /// only flushes when asked to.
+    pub fn set_autosave_interval(&self, interval: Option<Duration>) {
+        self.pending.interval.set(interval);
+        if let Err(e) = self.autosave() {
+            log_error!("failed to auto-save {:?}", e);
+        }
+    }
+
+    /// Flushes if the oldest queued item has waited for the auto-save interval. Returns
+    /// true if anything was written.
+    pub fn autosave(&self) -> rusqlite::Result<bool> {
+        match (self.pending.since.get(), self.pending.interval.get()) {
+            (Some(since), Some(interval)) if self.now() - since >= interval => {
+                self.flush()?;
+                Ok(true)
+            },
+            _ => Ok(false),
+        }
+    }
+}
+
+/// Queues the item to be saved rather than writing it immediately, so that a run of
+/// setters costs one write. Assigns the item a uuid if it does not have one. Returns false
+/// and fills in `error` if this flushed the queue and that failed; the item stays queued.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn item_save(manager: *const Arc<ListManager>, item: *mut Item, error: *mut ExternError) -> bool {
+    ffi_lock!();
+    ffi_guard!(error, {
+        let manager = ffi_ref!("item_save", manager, false, error);
+        let item = ffi_mut!("item_save", item, false, error);
+        match manager.try_queue_save(item) {
+            Ok(()) => true,
+            Err(e) => {
+                log_error!("failed to auto-save {:?}", e);
+                set_sqlite_error(error, &e);
+                false
+            },
+        }
+    })
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn item_is_unsaved(manager: *const Arc<ListManager>, item: *const Item) -> bool {
+    ffi_lock!();
+    ffi_guard!({
+        let manager = ffi_ref!("item_is_unsaved", manager);
+        let item = ffi_ref!("item_is_unsaved", item);
+        manager.is_unsaved(&item.uuid)
+    })
+}
+
+/// Writes every queued item. Call this when the app is backgrounded. Returns false and
+/// fills in `error` if they could not be written; they stay queued. Inside a transaction
+/// they also stay queued until it commits.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_flush(manager: *const Arc<ListManager>, error: *mut ExternError) -> bool {
+    ffi_lock!();
+    ffi_guard!(error, {
+        let manager = ffi_ref!("list_manager_flush", manager, false, error);
+        match manager.flush() {
+            Ok(()) => true,
+            Err(e) => {
+                log_error!("failed to flush {:?}", e);
+                set_sqlite_error(error, &e);
+                false
+            },
+        }
+    })
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_has_unsaved_changes(manager: *const Arc<ListManager>) -> bool {
+    ffi_lock!();
+    ffi_guard!({
+        let manager = ffi_ref!("list_manager_has_unsaved_changes", manager);
+        manager.has_unsaved_changes()
+    })
+}
+
+/// A negative interval turns auto-save off.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_set_autosave_interval(manager: *const Arc<ListManager>, seconds: i64) {
+    ffi_lock!();
+    ffi_guard!({
+        let manager = ffi_ref!("list_manager_set_autosave_interval", manager);
+        let interval = if seconds < 0 { None } else { Some(Duration::seconds(seconds)) };
+        manager.set_autosave_interval(interval);
+    })
+}
+
+/// Flushes if queued items have waited for the auto-save interval. Meant to be called
+/// from a timer on the host. Returns true if anything was written, and false, filling in
+/// `error`, if the queued items could not be.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_autosave(manager: *const Arc<ListManager>, error: *mut ExternError) -> bool {
+    ffi_lock!();
+    ffi_guard!(error, {
+        let manager = ffi_ref!("list_manager_autosave", manager, false, error);
+        match manager.autosave() {
+            Ok(written) => written,
+            Err(e) => {
+                log_error!("failed to auto-save {:?}", e);
+                set_sqlite_error(error, &e);
+                false
+            },
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use time::{
+        Duration,
+        Timespec,
+    };
+
+    use items::Item;
+    use store::Store;
+    use ListManager;
+
+    #[test]
+    fn test_queued_items_are_written_on_flush() {
+        let manager = ListManager::new(Arc::new(Store::new(None)));
+        let mut item = Item::named("buy stamps");
+        manager.queue_save(&mut item);
+        assert!(!item.uuid.is_empty());
+        assert!(manager.is_unsaved(&item.uuid));
+        assert_eq!(manager.fetch_item(&item.uuid), None);
+
+        item.name = "buy more stamps".to_string();
+        manager.queue_save(&mut item);
+        manager.flush().unwrap();
+        assert!(!manager.has_unsaved_changes());
+        assert_eq!(manager.fetch_item(&item.uuid), Some(item));
+    }
+
+    #[test]
+    fn test_autosave_interval() {
+        let manager = ListManager::new(Arc::new(Store::new(None)));
+        let start = Timespec::new(1510315200, 0);
+        manager.freeze_clock(Some(start));
+        manager.set_autosave_interval(Some(Duration::seconds(30)));
+
+        let mut item = Item::named("post the letter");
+        manager.queue_save(&mut item);
+        assert!(!manager.autosave().unwrap());
+        assert!(manager.has_unsaved_changes());
+
+        manager.freeze_clock(Some(start + Duration::seconds(30)));
+        assert!(manager.autosave().unwrap());
+        assert!(!manager.has_unsaved_changes());
+        assert_eq!(manager.fetch_item(&item.uuid).map(|i| i.name.clone()), Some("post the letter".to_string()));
+    }
+
+    #[test]
+    fn test_failed_flush_keeps_items_queued() {
+        let manager = ListManager::new(Arc::new(Store::new(None)));
+        let mut first = Item::named("buy stamps");
+        let mut second = Item::named("post the letter");
+        manager.queue_save(&mut first);
+        manager.queue_save(&mut second);
+        let conn = manager.get_store().get_conn();
+        conn.execute_batch(r#"
+            CREATE TEMP TRIGGER full_disk BEFORE INSERT ON items WHEN NEW.name = 'post the letter'
+            BEGIN SELECT RAISE(ABORT, 'database or disk is full'); END;
+        "#).unwrap();
+        assert!(manager.flush().is_err());
+        assert!(manager.is_unsaved(&first.uuid) && manager.is_unsaved(&second.uuid));
+        assert_eq!(manager.fetch_item(&first.uuid), None);
+
+        conn.execute_batch("DROP TRIGGER full_disk").unwrap();
+        manager.flush().unwrap();
+        assert!(!manager.has_unsaved_changes());
+        assert_eq!(manager.fetch_item(&second.uuid), Some(second));
+    }
+
+    #[test]
+    fn test_flush_inside_host_transaction() {
+        let manager = ListManager::new(Arc::new(Store::new(None)));
+        let store = manager.get_store();
+        let mut item = Item::named("buy stamps");
+        manager.queue_save(&mut item);
+
+        let tx = store.begin_transaction().unwrap();
+        manager.flush().unwrap();
+        assert!(manager.is_unsaved(&item.uuid));
+        tx.rollback().unwrap();
+        assert!(manager.is_unsaved(&item.uuid));
+        assert_eq!(manager.fetch_item(&item.uuid), None);
+
+        let tx = store.begin_transaction().unwrap();
+        manager.flush().unwrap();
+        tx.commit().unwrap();
+        assert!(!manager.has_unsaved_changes());
+        assert_eq!(manager.fetch_item(&item.uuid), Some(item));
+    }
+}
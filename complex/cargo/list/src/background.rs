@@ -0,0 +1,165 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+//! One entry point for the time the OS grants an app in the background, e.g. an iOS
+//! `BGAppRefreshTask`. Pending work runs most important first until the budget is spent,
+//! and the report says what got done, so the host knows what to pass to `setTaskCompleted`.
+
+use std::time::{
+    Duration as StdDuration,
+    Instant,
+};
+
+use time::Duration;
+
+use ffi_utils::breadcrumbs;
+use tombstones::DEFAULT_HORIZON_DAYS;
+use ListManager;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RefreshOutcome {
+    Done,
+    /// There was nothing to do, e.g. sync is not set up.
+    NotNeeded,
+    Failed,
+    /// The budget ran out before the step started.
+    Skipped,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RefreshStep {
+    pub name: &'static str,
+    pub outcome: RefreshOutcome,
+    /// What the step did, or why it failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RefreshReport {
+    /// Every step ran and none failed.
+    pub completed: bool,
+    pub elapsed_ms: i64,
+    pub steps: Vec<RefreshStep>,
+}
+
+type Step = fn(&ListManager) -> (RefreshOutcome, Option<String>);
+
+fn flush(manager: &ListManager) -> (RefreshOutcome, Option<String>) {
+    if !manager.has_unsaved_changes() {
+        return (RefreshOutcome::NotNeeded, None);
+    }
+    match manager.flush() {
+        Ok(()) => (RefreshOutcome::Done, None),
+        Err(e) => {
+            log_error!("failed to flush {:?}", e);
+            (RefreshOutcome::Failed, Some(e.to_string()))
+        },
+    }
+}
+
+fn sync(manager: &ListManager) -> (RefreshOutcome, Option<String>) {
+    if !manager.is_sync_configured() {
+        return (RefreshOutcome::NotNeeded, None);
+    }
+    match manager.sync_now() {
+        Ok(summary) => (RefreshOutcome::Done, Some(format!("pulled {}, pushed {}", summary.pulled, summary.pushed))),
+        Err(e) => {
+            log_error!("background sync failed {:?}", e);
+            (RefreshOutcome::Failed, Some(e.to_string()))
+        },
+    }
+}
+
+fn prune_tombstones(manager: &ListManager) -> (RefreshOutcome, Option<String>) {
+    let pruned = manager.prune_tombstones(Duration::days(DEFAULT_HORIZON_DAYS));
+    (RefreshOutcome::Done, Some(format!("pruned {}", pruned)))
+}
+
+fn prune_history(manager: &ListManager) -> (RefreshOutcome, Option<String>) {
+    match manager.get_store().prune_to_caps() {
+        Ok(pruned) => (RefreshOutcome::Done, Some(format!("pruned {}", pruned))),
+        Err(e) => {
+            log_error!("failed to prune history {:?}", e);
+            (RefreshOutcome::Failed, Some(e.to_string()))
+        },
+    }
+}
+
+/// Most important first: unsaved edits, then the server, then housekeeping.
+const STEPS: [(&'static str, Step); 4] = [
+    ("flush", flush),
+    ("sync", sync),
+    ("prune_tombstones", prune_tombstones),
+    ("prune_history", prune_history),
+];
+
+impl ListManager {
+    /// Runs the pending background work in order while less than `budget` has passed. A
+    /// step is not interrupted, so leave the OS some slack: a sync started near the end of
+    /// the budget runs to completion.
+    pub fn background_refresh(&self, budget: StdDuration) -> RefreshReport {
+        breadcrumbs::leave("list.background_refresh");
+        let started = Instant::now();
+        let steps: Vec<RefreshStep> = STEPS.iter().map(|&(name, step)| {
+            let (outcome, detail) = if started.elapsed() < budget {
+                step(self)
+            } else {
+                (RefreshOutcome::Skipped, None)
+            };
+            RefreshStep {
+                name: name,
+                outcome: outcome,
+                detail: detail,
+            }
+        }).collect();
+        let elapsed = started.elapsed();
+        RefreshReport {
+            completed: steps.iter().all(|step| step.outcome == RefreshOutcome::Done || step.outcome == RefreshOutcome::NotNeeded),
+            elapsed_ms: elapsed.as_secs() as i64 * 1000 + elapsed.subsec_nanos() as i64 / 1_000_000,
+            steps: steps,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use super::RefreshOutcome;
+
+    use items::Item;
+    use store::Store;
+    use ListManager;
+
+    #[test]
+    fn test_background_refresh() {
+        let manager = ListManager::new(Arc::new(Store::new(None)));
+        let mut item = Item::named("renew passport");
+        manager.queue_save(&mut item);
+
+        let report = manager.background_refresh(Duration::from_secs(0));
+        assert!(!report.completed);
+        assert!(report.steps.iter().all(|step| step.outcome == RefreshOutcome::Skipped));
+        assert!(manager.has_unsaved_changes());
+
+        let report = manager.background_refresh(Duration::from_secs(30));
+        assert!(report.completed);
+        let outcomes: Vec<(&str, RefreshOutcome)> = report.steps.iter().map(|step| (step.name, step.outcome)).collect();
+        assert_eq!(outcomes, vec![
+            ("flush", RefreshOutcome::Done),
+            ("sync", RefreshOutcome::NotNeeded),
+            ("prune_tombstones", RefreshOutcome::Done),
+            ("prune_history", RefreshOutcome::Done),
+        ]);
+        assert_eq!(manager.fetch_item(&item.uuid).map(|item| item.name.clone()), Some("renew passport".to_string()));
+    }
+}
@@ -0,0 +1,208 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! Many writes in one call and one transaction, so bulk edits from the JVM cost one
+//! crossing instead of one per item and field.
+
+use std::os::raw::c_char;
+use std::ptr;
+use std::sync::Arc;
+
+use rusqlite;
+use serde_json;
+
+use ffi_utils::breadcrumbs;
+use ffi_utils::errors::{
+    set_extern_error,
+    ErrorCategory,
+    ExternError,
+};
+use ffi_utils::strings::{
+    c_char_to_string,
+    string_to_c_char,
+};
+use items::Item;
+use store::errors::{
+    category_of,
+    set_sqlite_error,
+};
+use ListManager;
+
+/// One write. Serialized with an `op` key naming the variant.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Change {
+    /// Creates the item under a new uuid, ignoring the one it carries.
+    CreateItem { item: Item },
+    /// Writes the item over the stored one with the same uuid, like `save_item`.
+    SaveItem { item: Item },
+    DeleteItem { uuid: String },
+    CreateLabel { name: String, color: String },
+    DeleteLabel { name: String },
+}
+
+/// What became of one change.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ChangeResult {
+    pub ok: bool,
+    /// The uuid of a created item.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uuid: Option<String>,
+    /// Whether a delete found something to delete.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deleted: Option<bool>,
+    /// The `ErrorCategory` of a failed change, as an int.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub category: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl ChangeResult {
+    fn ok() -> ChangeResult {
+        ChangeResult {
+            ok: true,
+            uuid: None,
+            deleted: None,
+            category: None,
+            error: None,
+        }
+    }
+
+    fn failed(error: &rusqlite::Error) -> ChangeResult {
+        ChangeResult {
+            ok: false,
+            category: Some(category_of(error) as i32),
+            error: Some(error.to_string()),
+            ..ChangeResult::ok()
+        }
+    }
+}
+
+impl ListManager {
+    fn apply_change(&self, change: &Change) -> rusqlite::Result<ChangeResult> {
+        Ok(match *change {
+            Change::CreateItem { ref item } => ChangeResult {
+                uuid: Some(self.try_create_item(item)?),
+                ..ChangeResult::ok()
+            },
+            Change::SaveItem { ref item } => {
+                self.try_save_item(item)?;
+                ChangeResult::ok()
+            },
+            Change::DeleteItem { ref uuid } => ChangeResult {
+                deleted: Some(self.try_delete_item(uuid)?),
+                ..ChangeResult::ok()
+            },
+            Change::CreateLabel { ref name, ref color } => {
+                self.try_create_label(name.clone(), color.clone())?;
+                ChangeResult::ok()
+            },
+            Change::DeleteLabel { ref name } => ChangeResult {
+                deleted: Some(self.try_delete_label(name)?),
+                ..ChangeResult::ok()
+            },
+        })
+    }
+
+    /// Applies `changes` in order in one transaction and returns a result for each. A change
+    /// that fails is undone on its own and the others are kept. Fails, writing nothing, only
+    /// if the transaction cannot be started or committed.
+    pub fn apply_changes(&self, changes: &[Change]) -> rusqlite::Result<Vec<ChangeResult>> {
+        breadcrumbs::leave("list.apply_changes");
+        let store = self.get_store();
+        let tx = store.begin_transaction()?;
+        let results = changes.iter()
+            .map(|change| self.apply_change(change).unwrap_or_else(|e| {
+                log_error!("failed to apply change {:?}", e);
+                ChangeResult::failed(&e)
+            }))
+            .collect();
+        tx.commit()?;
+        Ok(results)
+    }
+}
+
+/// `changes` is a JSON array of objects with an `op` key: `{"op": "create_item", "item":
+/// ...}`, `{"op": "save_item", "item": ...}`, `{"op": "delete_item", "uuid": ...}`,
+/// `{"op": "create_label", "name": ..., "color": ...}` or `{"op": "delete_label", "name":
+/// ...}`, with items as `item_to_json` writes them. Returns a JSON array with an object per
+/// change: `ok`, plus `uuid` for created items, `deleted` for deletes, and `category` and
+/// `error` for failures. Returns null and fills in `error` if the JSON is not understood or
+/// the transaction failed.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_apply_changes(manager: *const Arc<ListManager>, changes: *const c_char, error: *mut ExternError) -> *mut c_char {
+    ffi_lock!();
+    ffi_guard!(error, {
+        let manager = ffi_ref!("list_manager_apply_changes", manager, ptr::null_mut(), error);
+        let changes: Vec<Change> = match serde_json::from_str(&c_char_to_string(changes)) {
+            Ok(changes) => changes,
+            Err(e) => {
+                log_error!("failed to parse changes {:?}", e);
+                set_extern_error(error, ErrorCategory::Bug, e.to_string());
+                return ptr::null_mut();
+            },
+        };
+        match manager.apply_changes(&changes) {
+            Ok(results) => string_to_c_char(serde_json::to_string(&results).unwrap()),
+            Err(e) => {
+                log_error!("failed to apply changes {:?}", e);
+                set_sqlite_error(error, &e);
+                ptr::null_mut()
+            },
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use serde_json;
+
+    use super::Change;
+
+    use filters::ItemFilter;
+    use items::Item;
+    use labels::Label;
+    use store::Store;
+    use ListManager;
+
+    #[test]
+    fn test_apply_changes() {
+        let manager = ListManager::new(Arc::new(Store::new(None)));
+        let stale = manager.create_item(&Item::named("stale"));
+        let changes: Vec<Change> = serde_json::from_str(&format!(r##"[
+            {{"op": "create_label", "name": "home", "color": "#00ff00"}},
+            {{"op": "create_label", "name": "home", "color": "#ff0000"}},
+            {{"op": "create_item", "item": {{"name": "paint the fence"}}}},
+            {{"op": "delete_item", "uuid": "{}"}},
+            {{"op": "delete_label", "name": "work"}}
+        ]"##, stale)).unwrap();
+        let results = manager.apply_changes(&changes).unwrap();
+
+        assert!(results[0].ok);
+        assert!(!results[1].ok && results[1].error.is_some());
+        let fence = results[2].uuid.clone().unwrap();
+        assert_eq!(results[3].deleted, Some(true));
+        assert_eq!(results[4].deleted, Some(false));
+
+        // the failed change did not undo the others
+        assert_eq!(manager.fetch_labels(), vec![Label { name: "home".to_string(), color: "#00ff00".to_string() }]);
+        let names: Vec<String> = manager.fetch_items(&ItemFilter::default()).iter().map(|item| item.name.clone()).collect();
+        assert_eq!(names, vec!["paint the fence".to_string()]);
+
+        let mut fence = manager.fetch_item(&fence).unwrap();
+        fence.name = "paint the garden fence".to_string();
+        let results = manager.apply_changes(&[Change::SaveItem { item: fence.clone() }]).unwrap();
+        assert!(results[0].ok);
+        assert_eq!(manager.fetch_item(&fence.uuid), Some(fence));
+    }
+}
@@ -0,0 +1,332 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+use std::collections::BTreeMap;
+use std::os::raw::c_char;
+use std::sync::Arc;
+use std::ptr;
+
+use serde_json;
+use time::Timespec;
+
+use ffi_utils::strings::{
+    string_to_c_char,
+    c_char_to_string,
+};
+use attribution::{
+    FIELD_COMPLETION_DATE,
+    FIELD_DUE_DATE,
+    FIELD_LABELS,
+    FIELD_NAME,
+};
+use items::Item;
+use store::migrations::MigrationStep;
+use ListManager;
+
+pub const CREATE_CONFLICTS_TABLE: MigrationStep = MigrationStep {
+    name: "create_conflicts_table",
+    sql: r#"CREATE TABLE IF NOT EXISTS conflicts (
+            id INTEGER PRIMARY KEY,
+            item_uuid TEXT NOT NULL,
+            local TEXT NOT NULL,
+            remote TEXT NOT NULL,
+            created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+        )"#,
+};
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+/// Two versions of an item that could not be merged automatically and are waiting for
+/// the user to pick one.
+pub struct Conflict {
+    pub id: i64,
+    pub item_uuid: String,
+    pub local: Item,
+    pub remote: Item,
+    #[serde(serialize_with = "serialize_timespec")]
+    pub created_at: Timespec,
+}
+
+fn serialize_timespec<S>(date: &Timespec, serializer: S) -> Result<S::Ok, S::Error>
+where S: ::serde::Serializer {
+    serializer.serialize_i64(date.sec)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Resolution {
+    KeepLocal,
+    KeepRemote,
+    Merged(Item),
+}
+
+impl Resolution {
+    /// Parses `"local"`, `"remote"` or an item as JSON.
+    pub fn parse(choice: &str) -> Option<Resolution> {
+        match choice {
+            "local" => Some(Resolution::KeepLocal),
+            "remote" => Some(Resolution::KeepRemote),
+            json => serde_json::from_str(json).ok().map(Resolution::Merged),
+        }
+    }
+}
+
+/// What sync does with an item changed both locally and on the server since the last sync.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConflictStrategy {
+    /// Keep the local version and record the conflict for the user to resolve.
+    Manual = 0,
+    /// Keep the version edited most recently.
+    LastWriterWins = 1,
+    /// Take each field from the version that edited it most recently.
+    FieldMerge = 2,
+    /// Keep the local version and add the remote one as a new item.
+    DuplicateBoth = 3,
+}
+
+impl Default for ConflictStrategy {
+    fn default() -> ConflictStrategy {
+        ConflictStrategy::Manual
+    }
+}
+
+impl ConflictStrategy {
+    pub fn from_u32(value: u32) -> Option<ConflictStrategy> {
+        match value {
+            0 => Some(ConflictStrategy::Manual),
+            1 => Some(ConflictStrategy::LastWriterWins),
+            2 => Some(ConflictStrategy::FieldMerge),
+            3 => Some(ConflictStrategy::DuplicateBoth),
+            _ => None,
+        }
+    }
+}
+
+/// When each field of an item was last edited, in seconds. Fields missing were never edited
+/// as far as that side knows, and lose to any edit.
+pub type EditTimes = BTreeMap<String, i64>;
+
+fn edited_at(edits: &EditTimes, field: &str) -> i64 {
+    edits.get(field).cloned().unwrap_or(0)
+}
+
+/// Whether `remote` was edited at least as recently as `local`; ties go to the remote version.
+fn remote_is_newer(local_edits: &EditTimes, remote_edits: &EditTimes) -> bool {
+    let latest = |edits: &EditTimes| edits.values().cloned().max().unwrap_or(0);
+    latest(remote_edits) >= latest(local_edits)
+}
+
+/// Combines the two versions field by field, taking each from the side that edited it last.
+pub fn merge_fields(local: &Item, local_edits: &EditTimes, remote: &Item, remote_edits: &EditTimes) -> Item {
+    let remote_wins = |field| edited_at(remote_edits, field) >= edited_at(local_edits, field);
+    Item {
+        uuid: local.uuid.clone(),
+        name: if remote_wins(FIELD_NAME) { remote.name.clone() } else { local.name.clone() },
+        due_date: if remote_wins(FIELD_DUE_DATE) { remote.due_date } else { local.due_date },
+        completion_date: if remote_wins(FIELD_COMPLETION_DATE) { remote.completion_date } else { local.completion_date },
+        labels: if remote_wins(FIELD_LABELS) { remote.labels.clone() } else { local.labels.clone() },
+    }
+}
+
+impl ListManager {
+    pub(crate) fn edit_times(&self, item_uuid: &String) -> EditTimes {
+        self.fetch_field_edits(item_uuid).into_iter().map(|(field, edit)| (field, edit.edited_at.sec)).collect()
+    }
+
+    /// Settles an item changed on both sides according to `strategy`. Returns true if the
+    /// remote version should be saved over the local one; otherwise whatever is kept locally
+    /// is left to be pushed.
+    pub(crate) fn resolve_sync_conflict(&self, strategy: ConflictStrategy, local: &Item, remote: &Item, remote_edits: &EditTimes) -> bool {
+        let local_edits = self.edit_times(&local.uuid);
+        match strategy {
+            ConflictStrategy::Manual => {
+                self.record_conflict(local, remote);
+                false
+            },
+            ConflictStrategy::LastWriterWins => remote_is_newer(&local_edits, remote_edits),
+            ConflictStrategy::FieldMerge => {
+                let merged = merge_fields(local, &local_edits, remote, remote_edits);
+                if merged == *remote {
+                    return true;
+                }
+                if merged != *local {
+                    self.save_item(&merged);
+                    // each field keeps the time of the edit it came from
+                    let mut times = local_edits.clone();
+                    for (field, &time) in remote_edits.iter() {
+                        if time >= edited_at(&local_edits, field) {
+                            times.insert(field.clone(), time);
+                        }
+                    }
+                    self.set_edit_times(&local.uuid, &times);
+                }
+                false
+            },
+            ConflictStrategy::DuplicateBoth => {
+                self.create_item(remote);
+                false
+            },
+        }
+    }
+
+    pub fn record_conflict(&self, local: &Item, remote: &Item) -> i64 {
+        let sql = r#"INSERT INTO conflicts (item_uuid, local, remote) VALUES (?, ?, ?)"#;
+        let conn = self.get_store().get_conn();
+        let local_json = serde_json::to_string(local).unwrap();
+        let remote_json = serde_json::to_string(remote).unwrap();
+        conn.execute(sql, &[&local.uuid, &local_json, &remote_json]).unwrap();
+        conn.last_insert_rowid()
+    }
+
+    pub fn conflicts_pending(&self) -> Vec<Conflict> {
+        let sql = r#"SELECT id, item_uuid, local, remote, created_at FROM conflicts ORDER BY id"#;
+        let conn = self.get_store().get_conn();
+        let mut stmt = conn.prepare(sql).unwrap();
+        let conflict_iter = stmt.query_map(&[], |row| {
+            let local: String = row.get(2);
+            let remote: String = row.get(3);
+            (row.get(0), row.get(1), local, remote, row.get(4))
+        }).unwrap();
+
+        let mut conflicts = Vec::new();
+        for result in conflict_iter {
+            if let Ok((id, item_uuid, local, remote, created_at)) = result {
+                match (serde_json::from_str(&local), serde_json::from_str(&remote)) {
+                    (Ok(local), Ok(remote)) => conflicts.push(Conflict {
+                        id: id,
+                        item_uuid: item_uuid,
+                        local: local,
+                        remote: remote,
+                        created_at: created_at,
+                    }),
+                    _ => log_warn!("Unreadable conflict {:?}", id),
+                }
+            }
+        }
+        conflicts
+    }
+
+    /// Saves the chosen version of the item and removes the conflict. Returns false if
+    /// there is no pending conflict with that id.
+    pub fn resolve_conflict(&self, id: i64, resolution: Resolution) -> bool {
+        let conflict = match self.conflicts_pending().into_iter().find(|c| c.id == id) {
+            Some(conflict) => conflict,
+            None => return false,
+        };
+        let mut chosen = match resolution {
+            Resolution::KeepLocal => conflict.local.clone(),
+            Resolution::KeepRemote => conflict.remote.clone(),
+            Resolution::Merged(item) => item,
+        };
+        chosen.uuid = conflict.item_uuid.clone();
+        self.save_item(&chosen);
+        let conn = self.get_store().get_conn();
+        conn.execute("DELETE FROM conflicts WHERE id=?", &[&id]).unwrap();
+        true
+    }
+}
+
+/// Returns the pending conflicts as a JSON array of objects with `id`, `item_uuid`,
+/// `local`, `remote` and `created_at` keys.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_conflicts_pending(manager: *const Arc<ListManager>) -> *mut c_char {
+    ffi_lock!();
+    ffi_guard!({
+        let manager = ffi_ref!("list_manager_conflicts_pending", manager, ptr::null_mut());
+        string_to_c_char(serde_json::to_string(&manager.conflicts_pending()).unwrap())
+    })
+}
+
+/// `choice` is `"local"`, `"remote"` or a merged item as JSON. Returns false if the choice
+/// could not be understood or there is no pending conflict with that id.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_resolve_conflict(manager: *const Arc<ListManager>, id: i64, choice: *const c_char) -> bool {
+    ffi_lock!();
+    ffi_guard!({
+        let manager = ffi_ref!("list_manager_resolve_conflict", manager);
+        match Resolution::parse(&c_char_to_string(choice)) {
+            Some(resolution) => manager.resolve_conflict(id, resolution),
+            None => false,
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use time::Timespec;
+
+    use super::Resolution;
+
+    use items::Item;
+    use store::Store;
+    use ListManager;
+
+    fn conflicting_versions(manager: &ListManager) -> (Item, Item) {
+        let label = manager.create_label("work".to_string(), "#000000".to_string()).unwrap();
+        let mut local = Item {
+            uuid: "".to_string(),
+            name: "local name".to_string(),
+            due_date: Some(Timespec::new(1510315200, 0)),
+            completion_date: None,
+            labels: vec![Arc::new(label)],
+        };
+        local.uuid = manager.create_item(&local);
+        let mut remote = local.clone();
+        remote.name = "remote name".to_string();
+        remote.due_date = None;
+        (local, remote)
+    }
+
+    #[test]
+    fn test_record_and_resolve_conflict() {
+        let manager = ListManager::new(Arc::new(Store::new(None)));
+        let (local, remote) = conflicting_versions(&manager);
+        let id = manager.record_conflict(&local, &remote);
+
+        let pending = manager.conflicts_pending();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].local, local);
+        assert_eq!(pending[0].remote, remote);
+
+        assert!(manager.resolve_conflict(id, Resolution::KeepRemote));
+        assert_eq!(manager.fetch_item(&local.uuid), Some(remote));
+        assert_eq!(manager.conflicts_pending().len(), 0);
+        assert!(!manager.resolve_conflict(id, Resolution::KeepLocal));
+    }
+
+    #[test]
+    fn test_resolve_conflict_with_merged_json() {
+        let manager = ListManager::new(Arc::new(Store::new(None)));
+        let (local, remote) = conflicting_versions(&manager);
+        let id = manager.record_conflict(&local, &remote);
+
+        let merged = Resolution::parse(r##"{"name": "merged", "due_date": 1510315200, "labels": [{"name": "work", "color": "#000000"}]}"##)
+            .expect("expected a merged item");
+        assert!(manager.resolve_conflict(id, merged));
+        let saved = manager.fetch_item(&local.uuid).expect("expected an item");
+        assert_eq!(saved.name, "merged");
+        assert_eq!(saved.due_date, local.due_date);
+        assert_eq!(saved.labels, local.labels);
+
+        assert_eq!(Resolution::parse("neither"), None);
+    }
+
+    #[test]
+    fn test_resolve_conflict_recreates_deleted_item() {
+        let manager = ListManager::new(Arc::new(Store::new(None)));
+        let (local, remote) = conflicting_versions(&manager);
+        let id = manager.record_conflict(&local, &remote);
+        manager.get_store().get_conn().execute_batch("DELETE FROM item_labels; DELETE FROM items;").unwrap();
+
+        assert!(manager.resolve_conflict(id, Resolution::KeepLocal));
+        assert_eq!(manager.fetch_item(&local.uuid), Some(local));
+    }
+}
@@ -0,0 +1,272 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! What sync encryption needs from cryptography: PBKDF2-HMAC-SHA256 to derive a key from a
+//! passphrase (RFC 8018) and ChaCha20-Poly1305 to seal records with it (RFC 8439). These
+//! come from the platform, e.g. CryptoKit on iOS and javax.crypto on Android, set with
+//! `list_manager_set_sync_crypto`. Only randomness, from the OS, and base64, to carry the
+//! results in JSON, are done here.
+
+use std::io;
+use std::os::raw::c_void;
+
+use rand::{
+    OsRng,
+    Rng,
+};
+
+pub const KEY_LEN: usize = 32;
+pub const NONCE_LEN: usize = 12;
+pub const TAG_LEN: usize = 16;
+
+pub type Key = [u8; KEY_LEN];
+
+/// The primitives sync encryption is built on.
+pub trait SyncCrypto {
+    /// PBKDF2-HMAC-SHA256 of `passphrase` and `salt`, `KEY_LEN` bytes long.
+    fn derive_key(&self, passphrase: &[u8], salt: &[u8], rounds: u32) -> io::Result<Key>;
+    /// ChaCha20-Poly1305: `plaintext` encrypted, followed by the `TAG_LEN` byte tag that
+    /// authenticates it together with `aad`.
+    fn seal(&self, key: &Key, nonce: &[u8; NONCE_LEN], aad: &[u8], plaintext: &[u8]) -> io::Result<Vec<u8>>;
+    /// Returns `None` if `sealed` was not sealed with this key, nonce and `aad`, or was altered.
+    fn open(&self, key: &Key, nonce: &[u8; NONCE_LEN], aad: &[u8], sealed: &[u8]) -> Option<Vec<u8>>;
+}
+
+/// Fills in `key`, `KEY_LEN` bytes. Returns false on failure.
+pub type DeriveKeyCallback = extern "C" fn(passphrase: *const u8, passphrase_len: usize, salt: *const u8, salt_len: usize, rounds: u32, key: *mut u8, user_data: *mut c_void) -> bool;
+/// Fills in `sealed`, `plaintext_len + TAG_LEN` bytes. Returns false on failure.
+pub type SealCallback = extern "C" fn(key: *const u8, nonce: *const u8, aad: *const u8, aad_len: usize, plaintext: *const u8, plaintext_len: usize, sealed: *mut u8, user_data: *mut c_void) -> bool;
+/// Fills in `plaintext`, `sealed_len - TAG_LEN` bytes. Returns false if `sealed` does not
+/// authenticate.
+pub type OpenCallback = extern "C" fn(key: *const u8, nonce: *const u8, aad: *const u8, aad_len: usize, sealed: *const u8, sealed_len: usize, plaintext: *mut u8, user_data: *mut c_void) -> bool;
+
+/// The platform's implementation of `SyncCrypto`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct HostCrypto {
+    pub derive_key: DeriveKeyCallback,
+    pub seal: SealCallback,
+    pub open: OpenCallback,
+    pub user_data: *mut c_void,
+}
+
+impl SyncCrypto for HostCrypto {
+    fn derive_key(&self, passphrase: &[u8], salt: &[u8], rounds: u32) -> io::Result<Key> {
+        let mut key = [0; KEY_LEN];
+        if !(self.derive_key)(passphrase.as_ptr(), passphrase.len(), salt.as_ptr(), salt.len(), rounds, key.as_mut_ptr(), self.user_data) {
+            return Err(io::Error::new(io::ErrorKind::Other, "the host could not derive a key"));
+        }
+        Ok(key)
+    }
+
+    fn seal(&self, key: &Key, nonce: &[u8; NONCE_LEN], aad: &[u8], plaintext: &[u8]) -> io::Result<Vec<u8>> {
+        let mut sealed = vec![0; plaintext.len() + TAG_LEN];
+        if !(self.seal)(key.as_ptr(), nonce.as_ptr(), aad.as_ptr(), aad.len(), plaintext.as_ptr(), plaintext.len(), sealed.as_mut_ptr(), self.user_data) {
+            return Err(io::Error::new(io::ErrorKind::Other, "the host could not seal an item"));
+        }
+        Ok(sealed)
+    }
+
+    fn open(&self, key: &Key, nonce: &[u8; NONCE_LEN], aad: &[u8], sealed: &[u8]) -> Option<Vec<u8>> {
+        if sealed.len() < TAG_LEN {
+            return None;
+        }
+        let mut plaintext = vec![0; sealed.len() - TAG_LEN];
+        if !(self.open)(key.as_ptr(), nonce.as_ptr(), aad.as_ptr(), aad.len(), sealed.as_ptr(), sealed.len(), plaintext.as_mut_ptr(), self.user_data) {
+            return None;
+        }
+        Some(plaintext)
+    }
+}
+
+pub fn random_bytes(len: usize) -> io::Result<Vec<u8>> {
+    let mut bytes = vec![0; len];
+    OsRng::new()?.fill_bytes(&mut bytes);
+    Ok(bytes)
+}
+
+const BASE64_ALPHABET: &'static [u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+pub fn base64_encode(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity((bytes.len() + 2) / 3 * 4);
+    for chunk in bytes.chunks(3) {
+        let n = (chunk[0] as u32) << 16 | (*chunk.get(1).unwrap_or(&0) as u32) << 8 | *chunk.get(2).unwrap_or(&0) as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(BASE64_ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+pub fn base64_decode(encoded: &str) -> Option<Vec<u8>> {
+    let encoded = encoded.as_bytes();
+    if encoded.len() % 4 != 0 {
+        return None;
+    }
+    let mut bytes = Vec::with_capacity(encoded.len() / 4 * 3);
+    for chunk in encoded.chunks(4) {
+        let padding = chunk.iter().rev().take_while(|&&c| c == b'=').count();
+        if padding > 2 {
+            return None;
+        }
+        let mut n = 0u32;
+        for &c in chunk[..4 - padding].iter() {
+            let value = BASE64_ALPHABET.iter().position(|&a| a == c)? as u32;
+            n = n << 6 | value;
+        }
+        n <<= 6 * padding as u32;
+        let decoded = [(n >> 16) as u8, (n >> 8) as u8, n as u8];
+        bytes.extend_from_slice(&decoded[..3 - padding]);
+    }
+    Some(bytes)
+}
+
+#[cfg(test)]
+pub mod test {
+    use std::cell::Cell;
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{
+        Hash,
+        Hasher,
+    };
+    use std::io;
+    use std::os::raw::c_void;
+    use std::ptr;
+    use std::slice;
+
+    use super::*;
+
+    /// Stands in for the platform in tests. It keeps the shape of the real primitives, a
+    /// keystream and a tag over everything sealed, but is not cryptography.
+    #[derive(Default)]
+    pub struct TestCrypto {
+        pub derived: Cell<usize>,
+    }
+
+    fn hash<T: Hash>(value: T) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    fn keystream(key: &Key, nonce: &[u8; NONCE_LEN], bytes: &mut [u8]) {
+        for (i, b) in bytes.iter_mut().enumerate() {
+            *b ^= hash((key, nonce, i)) as u8;
+        }
+    }
+
+    fn tag(key: &Key, nonce: &[u8; NONCE_LEN], aad: &[u8], ciphertext: &[u8]) -> [u8; TAG_LEN] {
+        let mut tag = [0; TAG_LEN];
+        tag[..8].copy_from_slice(&hash((key, nonce, aad, ciphertext, 0)).to_le_bytes());
+        tag[8..].copy_from_slice(&hash((key, nonce, aad, ciphertext, 1)).to_le_bytes());
+        tag
+    }
+
+    impl SyncCrypto for TestCrypto {
+        fn derive_key(&self, passphrase: &[u8], salt: &[u8], rounds: u32) -> io::Result<Key> {
+            self.derived.set(self.derived.get() + 1);
+            let mut key = [0; KEY_LEN];
+            for (i, chunk) in key.chunks_mut(8).enumerate() {
+                chunk.copy_from_slice(&hash((passphrase, salt, rounds, i)).to_le_bytes());
+            }
+            Ok(key)
+        }
+
+        fn seal(&self, key: &Key, nonce: &[u8; NONCE_LEN], aad: &[u8], plaintext: &[u8]) -> io::Result<Vec<u8>> {
+            let mut sealed = plaintext.to_vec();
+            keystream(key, nonce, &mut sealed);
+            let tag = tag(key, nonce, aad, &sealed);
+            sealed.extend_from_slice(&tag);
+            Ok(sealed)
+        }
+
+        fn open(&self, key: &Key, nonce: &[u8; NONCE_LEN], aad: &[u8], sealed: &[u8]) -> Option<Vec<u8>> {
+            if sealed.len() < TAG_LEN {
+                return None;
+            }
+            let (ciphertext, sealed_tag) = sealed.split_at(sealed.len() - TAG_LEN);
+            if tag(key, nonce, aad, ciphertext) != sealed_tag {
+                return None;
+            }
+            let mut plaintext = ciphertext.to_vec();
+            keystream(key, nonce, &mut plaintext);
+            Some(plaintext)
+        }
+    }
+
+    extern "C" fn derive_key(passphrase: *const u8, passphrase_len: usize, salt: *const u8, salt_len: usize, rounds: u32, key: *mut u8, _: *mut c_void) -> bool {
+        let derived = unsafe {
+            TestCrypto::default().derive_key(slice::from_raw_parts(passphrase, passphrase_len), slice::from_raw_parts(salt, salt_len), rounds)
+        };
+        unsafe { ptr::copy_nonoverlapping(derived.unwrap().as_ptr(), key, KEY_LEN) };
+        true
+    }
+
+    unsafe fn key_and_nonce(key: *const u8, nonce: *const u8) -> (Key, [u8; NONCE_LEN]) {
+        let (mut k, mut n) = ([0; KEY_LEN], [0; NONCE_LEN]);
+        k.copy_from_slice(slice::from_raw_parts(key, KEY_LEN));
+        n.copy_from_slice(slice::from_raw_parts(nonce, NONCE_LEN));
+        (k, n)
+    }
+
+    extern "C" fn seal(key: *const u8, nonce: *const u8, aad: *const u8, aad_len: usize, plaintext: *const u8, plaintext_len: usize, sealed: *mut u8, _: *mut c_void) -> bool {
+        unsafe {
+            let (key, nonce) = key_and_nonce(key, nonce);
+            let result = TestCrypto::default().seal(&key, &nonce, slice::from_raw_parts(aad, aad_len), slice::from_raw_parts(plaintext, plaintext_len)).unwrap();
+            ptr::copy_nonoverlapping(result.as_ptr(), sealed, plaintext_len + TAG_LEN);
+        }
+        true
+    }
+
+    extern "C" fn open(key: *const u8, nonce: *const u8, aad: *const u8, aad_len: usize, sealed: *const u8, sealed_len: usize, plaintext: *mut u8, _: *mut c_void) -> bool {
+        unsafe {
+            let (key, nonce) = key_and_nonce(key, nonce);
+            match TestCrypto::default().open(&key, &nonce, slice::from_raw_parts(aad, aad_len), slice::from_raw_parts(sealed, sealed_len)) {
+                Some(result) => {
+                    ptr::copy_nonoverlapping(result.as_ptr(), plaintext, sealed_len - TAG_LEN);
+                    true
+                },
+                None => false,
+            }
+        }
+    }
+
+    #[test]
+    fn test_host_crypto() {
+        let host = HostCrypto {
+            derive_key: derive_key,
+            seal: seal,
+            open: open,
+            user_data: ptr::null_mut(),
+        };
+        let key = host.derive_key(b"correct horse", b"salt", 10).unwrap();
+        assert_eq!(key, TestCrypto::default().derive_key(b"correct horse", b"salt", 10).unwrap());
+        let nonce = [7; NONCE_LEN];
+        let sealed = host.seal(&key, &nonce, b"a", b"renew passport").unwrap();
+        assert_eq!(sealed.len(), b"renew passport".len() + TAG_LEN);
+        assert_eq!(host.open(&key, &nonce, b"a", &sealed), Some(b"renew passport".to_vec()));
+        assert_eq!(host.open(&key, &nonce, b"b", &sealed), None);
+        assert_eq!(host.open(&key, &nonce, b"a", &sealed[..TAG_LEN - 1]), None);
+    }
+
+    #[test]
+    fn test_base64() {
+        for &(bytes, encoded) in [(&b""[..], ""), (&b"f"[..], "Zg=="), (&b"fo"[..], "Zm8="), (&b"foo"[..], "Zm9v"), (&b"foobar"[..], "Zm9vYmFy")].iter() {
+            assert_eq!(base64_encode(bytes), encoded);
+            assert_eq!(base64_decode(encoded), Some(bytes.to_vec()));
+        }
+        assert_eq!(base64_decode("Zm9"), None);
+        assert_eq!(base64_decode("Zm9*"), None);
+    }
+}
@@ -0,0 +1,489 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+use std::collections::VecDeque;
+use std::os::raw::{
+    c_char,
+    c_int,
+    c_void,
+};
+use std::ptr;
+use std::sync::Arc;
+
+use serde_json;
+
+use ffi_utils::strings::string_to_c_char;
+use filters::ItemFilter;
+use items::Item;
+use labels::Label;
+use ListManager;
+
+const DEFAULT_PAGE_SIZE: i64 = 50;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CursorSource {
+    Items(ItemFilter),
+    Labels,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CursorRow {
+    Item(Item),
+    Label(Label),
+}
+
+/// The columns of item rows, read with `Cursor::value` after `Cursor::move_to_next`. `_id`
+/// is the row's position, as Android's `CursorAdapter` expects; dates are seconds since
+/// 1970; `labels` is a JSON array of label names.
+pub const ITEM_COLUMNS: [&'static str; 6] = ["_id", "uuid", "name", "due_date", "completion_date", "labels"];
+pub const LABEL_COLUMNS: [&'static str; 3] = ["_id", "name", "color"];
+
+/// One column of the current row. The types match `android.database.Cursor`'s field types.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CursorValue {
+    Null,
+    Integer(i64),
+    Text(String),
+}
+
+pub const CURSOR_FIELD_TYPE_NULL: c_int = 0;
+pub const CURSOR_FIELD_TYPE_INTEGER: c_int = 1;
+pub const CURSOR_FIELD_TYPE_STRING: c_int = 3;
+
+impl CursorRow {
+    fn value(&self, position: i64, column: usize) -> Option<CursorValue> {
+        if column == 0 {
+            return Some(CursorValue::Integer(position));
+        }
+        let text = |s: &String| CursorValue::Text(s.clone());
+        Some(match (self, column) {
+            (&CursorRow::Item(ref item), 1) => text(&item.uuid),
+            (&CursorRow::Item(ref item), 2) => text(&item.name),
+            (&CursorRow::Item(ref item), 3) => item.due_date.map_or(CursorValue::Null, |date| CursorValue::Integer(date.sec)),
+            (&CursorRow::Item(ref item), 4) => item.completion_date.map_or(CursorValue::Null, |date| CursorValue::Integer(date.sec)),
+            (&CursorRow::Item(ref item), 5) => {
+                let names: Vec<&String> = item.labels.iter().map(|label| &label.name).collect();
+                CursorValue::Text(serde_json::to_string(&names).unwrap())
+            },
+            (&CursorRow::Label(ref label), 1) => text(&label.name),
+            (&CursorRow::Label(ref label), 2) => text(&label.color),
+            _ => return None,
+        })
+    }
+}
+
+/// Walks a result set one page at a time so that only `page_size` rows are held in memory.
+/// Each page starts after the last row of the one before, so writes made while iterating
+/// neither skip nor repeat rows; rows created meanwhile come last.
+///
+/// Rows can be taken whole with `next`, or a column at a time, like a database cursor, with
+/// `move_to_next` and `value`. The latter is meant for an Android `ContentProvider`.
+#[derive(Debug)]
+pub struct Cursor {
+    manager: Arc<ListManager>,
+    source: CursorSource,
+    generation: u64,
+    page_size: i64,
+    /// The row id of the last row fetched, or 0 before the first page.
+    last_rowid: i64,
+    count: i64,
+    page: VecDeque<CursorRow>,
+    /// The row `move_to_next` moved to and its position, counting from 0.
+    current: Option<(i64, CursorRow)>,
+    moves: i64,
+}
+
+impl Cursor {
+    pub fn new(manager: Arc<ListManager>, source: CursorSource, page_size: i64) -> Cursor {
+        // Pin relative dates so every page sees the same result set.
+        let source = match source {
+            CursorSource::Items(filter) => CursorSource::Items(filter.resolved(manager.now())),
+            source => source,
+        };
+        let count = match source {
+            CursorSource::Items(ref filter) => manager.count_items(filter),
+            CursorSource::Labels => manager.count_labels(),
+        };
+        let generation = manager.get_store().generation();
+        Cursor {
+            manager: manager,
+            source: source,
+            generation: generation,
+            page_size: if page_size > 0 { page_size } else { DEFAULT_PAGE_SIZE },
+            last_rowid: 0,
+            count: count,
+            page: VecDeque::new(),
+            current: None,
+            moves: 0,
+        }
+    }
+
+    pub fn columns(&self) -> &'static [&'static str] {
+        match self.source {
+            CursorSource::Items(_) => &ITEM_COLUMNS,
+            CursorSource::Labels => &LABEL_COLUMNS,
+        }
+    }
+
+    /// Moves to the next row, returning false once there are none.
+    pub fn move_to_next(&mut self) -> bool {
+        self.current = self.next().map(|row| (self.moves, row));
+        self.moves += 1;
+        self.current.is_some()
+    }
+
+    /// The column of the row `move_to_next` moved to, or `None` if there is no such column
+    /// or no current row.
+    pub fn value(&self, column: usize) -> Option<CursorValue> {
+        self.current.as_ref().and_then(|&(position, ref row)| row.value(position, column))
+    }
+
+    /// The number of rows in the result set when the cursor was opened.
+    pub fn total_count(&self) -> i64 {
+        self.count
+    }
+
+    /// A cursor becomes stale when the store is reloaded, after which it yields no more rows.
+    pub fn is_stale(&self) -> bool {
+        self.manager.get_store().generation() != self.generation
+    }
+
+    fn fetch_page(&mut self) {
+        let rows: Vec<(i64, CursorRow)> = match self.source {
+            CursorSource::Items(ref filter) => {
+                self.manager.fetch_items_page(filter, self.page_size, self.last_rowid)
+                    .into_iter().map(|(rowid, item)| (rowid, CursorRow::Item(item))).collect()
+            },
+            CursorSource::Labels => {
+                self.manager.fetch_labels_page(self.page_size, self.last_rowid)
+                    .into_iter().map(|(rowid, label)| (rowid, CursorRow::Label(label))).collect()
+            },
+        };
+        if let Some(&(rowid, _)) = rows.last() {
+            self.last_rowid = rowid;
+        }
+        self.page.extend(rows.into_iter().map(|(_, row)| row));
+    }
+}
+
+impl Iterator for Cursor {
+    type Item = CursorRow;
+
+    fn next(&mut self) -> Option<CursorRow> {
+        if self.is_stale() {
+            self.page.clear();
+            return None;
+        }
+        if self.page.is_empty() {
+            self.fetch_page();
+        }
+        self.page.pop_front()
+    }
+}
+
+pub const CURSOR_KIND_ITEMS: c_int = 0;
+pub const CURSOR_KIND_LABELS: c_int = 1;
+
+/// `filter` may be null to iterate over every item. A `page_size` of 0 uses the default.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_items_cursor(manager: *const Arc<ListManager>, filter: *const ItemFilter, page_size: i64) -> *mut Cursor {
+    ffi_lock!();
+    ffi_guard!({
+        let manager = ffi_ref!("list_manager_items_cursor", manager, ptr::null_mut());
+        let filter = filter.as_ref().cloned().unwrap_or_default();
+        let cursor = Cursor::new(manager.clone(), CursorSource::Items(filter), page_size);
+        Box::into_raw(Box::new(cursor))
+    })
+}
+
+/// A `page_size` of 0 uses the default.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_labels_cursor(manager: *const Arc<ListManager>, page_size: i64) -> *mut Cursor {
+    ffi_lock!();
+    ffi_guard!({
+        let manager = ffi_ref!("list_manager_labels_cursor", manager, ptr::null_mut());
+        let cursor = Cursor::new(manager.clone(), CursorSource::Labels, page_size);
+        Box::into_raw(Box::new(cursor))
+    })
+}
+
+/// Open items due during the host's local today. `utc_offset` is the host's current offset
+/// from UTC in seconds.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_items_due_today(manager: *const Arc<ListManager>, utc_offset: i64, page_size: i64) -> *mut Cursor {
+    ffi_lock!();
+    ffi_guard!({
+        let manager = ffi_ref!("list_manager_items_due_today", manager, ptr::null_mut());
+        let filter = ItemFilter::due_today(manager.now(), utc_offset);
+        Box::into_raw(Box::new(Cursor::new(manager.clone(), CursorSource::Items(filter), page_size)))
+    })
+}
+
+/// Open items due before the host's local today began.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_items_overdue(manager: *const Arc<ListManager>, utc_offset: i64, page_size: i64) -> *mut Cursor {
+    ffi_lock!();
+    ffi_guard!({
+        let manager = ffi_ref!("list_manager_items_overdue", manager, ptr::null_mut());
+        let filter = ItemFilter::overdue(manager.now(), utc_offset);
+        Box::into_raw(Box::new(Cursor::new(manager.clone(), CursorSource::Items(filter), page_size)))
+    })
+}
+
+/// Open items due in the `days` local days after today.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_items_upcoming(manager: *const Arc<ListManager>, days: i64, utc_offset: i64, page_size: i64) -> *mut Cursor {
+    ffi_lock!();
+    ffi_guard!({
+        let manager = ffi_ref!("list_manager_items_upcoming", manager, ptr::null_mut());
+        let filter = ItemFilter::upcoming(manager.now(), utc_offset, days);
+        Box::into_raw(Box::new(Cursor::new(manager.clone(), CursorSource::Items(filter), page_size)))
+    })
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn cursor_destroy(cursor: *mut Cursor) {
+    ffi_lock!();
+    ffi_guard!({
+        if cursor.is_null() {
+            return;
+        }
+        let _ = Box::from_raw(cursor);
+    })
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn cursor_kind(cursor: *const Cursor) -> c_int {
+    ffi_lock!();
+    ffi_guard!({
+        let cursor = ffi_ref!("cursor_kind", cursor);
+        match cursor.source {
+            CursorSource::Items(_) => CURSOR_KIND_ITEMS,
+            CursorSource::Labels => CURSOR_KIND_LABELS,
+        }
+    })
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn cursor_count(cursor: *const Cursor) -> i64 {
+    ffi_lock!();
+    ffi_guard!({
+        let cursor = ffi_ref!("cursor_count", cursor);
+        cursor.total_count()
+    })
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn cursor_is_stale(cursor: *const Cursor) -> bool {
+    ffi_lock!();
+    ffi_guard!({
+        let cursor = ffi_ref!("cursor_is_stale", cursor);
+        cursor.is_stale()
+    })
+}
+
+/// Returns the next row, or null once the cursor is exhausted. The row is an `item` for
+/// item cursors and a `label` for label cursors, owned by the caller and released with
+/// `item_destroy` or `label_destroy` respectively.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn cursor_next(cursor: *mut Cursor) -> *mut c_void {
+    ffi_lock!();
+    ffi_guard!({
+        let cursor = ffi_mut!("cursor_next", cursor, ptr::null_mut());
+        match cursor.next() {
+            Some(CursorRow::Item(item)) => Box::into_raw(Box::new(item)) as *mut c_void,
+            Some(CursorRow::Label(label)) => Box::into_raw(Box::new(label)) as *mut c_void,
+            None => ptr::null_mut(),
+        }
+    })
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn cursor_column_count(cursor: *const Cursor) -> c_int {
+    ffi_lock!();
+    ffi_guard!({
+        let cursor = ffi_ref!("cursor_column_count", cursor);
+        cursor.columns().len() as c_int
+    })
+}
+
+/// Null if there is no such column.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn cursor_column_name(cursor: *const Cursor, column: c_int) -> *mut c_char {
+    ffi_lock!();
+    ffi_guard!({
+        let cursor = ffi_ref!("cursor_column_name", cursor, ptr::null_mut());
+        match cursor.columns().get(column as usize) {
+            Some(name) => string_to_c_char(name.to_string()),
+            None => ptr::null_mut(),
+        }
+    })
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn cursor_move_to_next(cursor: *mut Cursor) -> bool {
+    ffi_lock!();
+    ffi_guard!({
+        let cursor = ffi_mut!("cursor_move_to_next", cursor);
+        cursor.move_to_next()
+    })
+}
+
+unsafe fn cursor_value(function: &str, cursor: *const Cursor, column: c_int) -> CursorValue {
+    let cursor = ffi_ref!(function, cursor, CursorValue::Null);
+    cursor.value(column as usize).unwrap_or(CursorValue::Null)
+}
+
+/// `CURSOR_FIELD_TYPE_NULL` if the column is null, or there is no such column or no
+/// current row.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn cursor_get_type(cursor: *const Cursor, column: c_int) -> c_int {
+    ffi_lock!();
+    ffi_guard!({
+        match cursor_value("cursor_get_type", cursor, column) {
+            CursorValue::Null => CURSOR_FIELD_TYPE_NULL,
+            CursorValue::Integer(_) => CURSOR_FIELD_TYPE_INTEGER,
+            CursorValue::Text(_) => CURSOR_FIELD_TYPE_STRING,
+        }
+    })
+}
+
+/// 0 for null and text columns.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn cursor_get_long(cursor: *const Cursor, column: c_int) -> i64 {
+    ffi_lock!();
+    ffi_guard!({
+        match cursor_value("cursor_get_long", cursor, column) {
+            CursorValue::Integer(value) => value,
+            _ => 0,
+        }
+    })
+}
+
+/// Integers are returned as text, as SQLite cursors do. Null for null columns.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn cursor_get_string(cursor: *const Cursor, column: c_int) -> *mut c_char {
+    ffi_lock!();
+    ffi_guard!({
+        match cursor_value("cursor_get_string", cursor, column) {
+            CursorValue::Null => ptr::null_mut(),
+            CursorValue::Integer(value) => string_to_c_char(value.to_string()),
+            CursorValue::Text(value) => string_to_c_char(value),
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        Cursor,
+        CursorRow,
+        CursorSource,
+        CursorValue,
+    };
+
+    use std::sync::Arc;
+
+    use filters::ItemFilter;
+    use items::Item;
+    use store::Store;
+    use ListManager;
+
+    fn populated_manager() -> Arc<ListManager> {
+        let manager = ListManager::new(Arc::new(Store::new(None)));
+        for i in 0..7 {
+            let label = manager.create_label(format!("label{}", i), "#000000".to_string()).unwrap();
+            manager.create_item(&Item {
+                uuid: "".to_string(),
+                name: format!("item {}", i),
+                due_date: None,
+                completion_date: None,
+                labels: vec![Arc::new(label)],
+            });
+        }
+        Arc::new(manager)
+    }
+
+    #[test]
+    fn test_items_cursor() {
+        let manager = populated_manager();
+        let cursor = Cursor::new(manager, CursorSource::Items(ItemFilter::default()), 3);
+        assert_eq!(cursor.total_count(), 7);
+        let names: Vec<String> = cursor.map(|row| match row {
+            CursorRow::Item(ref item) => item.name.clone(),
+            CursorRow::Label(_) => panic!("expected an item"),
+        }).collect();
+        assert_eq!(names, (0..7).map(|i| format!("item {}", i)).collect::<Vec<String>>());
+    }
+
+    #[test]
+    fn test_items_cursor_across_writes() {
+        let manager = populated_manager();
+        let mut cursor = Cursor::new(manager.clone(), CursorSource::Items(ItemFilter::default()), 3);
+        let name = |row: CursorRow| match row {
+            CursorRow::Item(ref item) => item.name.clone(),
+            CursorRow::Label(_) => panic!("expected an item"),
+        };
+        let mut names: Vec<String> = cursor.by_ref().take(3).map(name).collect();
+        // an item already seen goes, an item yet to be seen goes, and one is added
+        let items = manager.fetch_items(&ItemFilter::default());
+        manager.delete_item(&items[0].uuid);
+        manager.delete_item(&items[4].uuid);
+        manager.create_item(&Item::named("item 7"));
+        names.extend(cursor.map(name));
+        assert_eq!(names, vec!["item 0", "item 1", "item 2", "item 3", "item 5", "item 6", "item 7"]);
+    }
+
+    #[test]
+    fn test_filtered_items_cursor() {
+        let manager = populated_manager();
+        let filter = ItemFilter {
+            labels: vec!["label4".to_string()],
+            ..ItemFilter::default()
+        };
+        let mut cursor = Cursor::new(manager, CursorSource::Items(filter), 3);
+        assert_eq!(cursor.total_count(), 1);
+        match cursor.next() {
+            Some(CursorRow::Item(item)) => assert_eq!(item.name, "item 4"),
+            other => panic!("expected an item, got {:?}", other),
+        }
+        assert_eq!(cursor.next(), None);
+    }
+
+    #[test]
+    fn test_labels_cursor() {
+        let manager = populated_manager();
+        let cursor = Cursor::new(manager, CursorSource::Labels, 2);
+        assert_eq!(cursor.total_count(), 7);
+        assert_eq!(cursor.filter(|row| match *row {
+            CursorRow::Label(_) => true,
+            CursorRow::Item(_) => false,
+        }).count(), 7);
+    }
+
+    #[test]
+    fn test_cursor_columns() {
+        let manager = populated_manager();
+        let mut cursor = Cursor::new(manager, CursorSource::Items(ItemFilter::default()), 3);
+        assert_eq!(cursor.columns(), &["_id", "uuid", "name", "due_date", "completion_date", "labels"]);
+        assert_eq!(cursor.value(2), None);
+
+        assert!(cursor.move_to_next());
+        assert!(cursor.move_to_next());
+        assert_eq!(cursor.value(0), Some(CursorValue::Integer(1)));
+        assert_eq!(cursor.value(2), Some(CursorValue::Text("item 1".to_string())));
+        assert_eq!(cursor.value(3), Some(CursorValue::Null));
+        assert_eq!(cursor.value(5), Some(CursorValue::Text(r#"["label1"]"#.to_string())));
+        assert_eq!(cursor.value(6), None);
+
+        while cursor.move_to_next() {}
+        assert_eq!(cursor.value(0), None);
+    }
+}
@@ -0,0 +1,159 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! Checks dates handed over by hosts, which now and then pass milliseconds where seconds
+//! are expected, or garbage.
+
+use std::error::Error;
+use std::fmt;
+use std::sync::atomic::{
+    AtomicBool,
+    Ordering,
+};
+
+use time::{
+    self,
+    Timespec,
+};
+
+use ffi_utils::errors::ErrorCategory;
+
+/// 3000-01-01T00:00:00Z. Later dates are taken for milliseconds unless dates are strict.
+pub const MAX_SECONDS: i64 = 32503680000;
+
+static STRICT_DATES: AtomicBool = AtomicBool::new(false);
+
+/// Rejects dates after `MAX_SECONDS` instead of reading them as milliseconds. Meant for
+/// debug builds, so that hosts passing the wrong unit find out.
+pub fn set_strict_dates(enabled: bool) {
+    STRICT_DATES.store(enabled, Ordering::SeqCst);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DateError {
+    /// Before 1970.
+    Negative(i64),
+    /// After `MAX_SECONDS`, even read as milliseconds where that is allowed.
+    OutOfRange(i64),
+}
+
+impl DateError {
+    /// Always a bug in the host.
+    pub fn category(&self) -> ErrorCategory {
+        ErrorCategory::Bug
+    }
+}
+
+impl fmt::Display for DateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DateError::Negative(value) => write!(f, "{} is before 1970", value),
+            DateError::OutOfRange(value) => write!(f, "{} is after the year 3000", value),
+        }
+    }
+}
+
+impl Error for DateError {
+    fn description(&self) -> &str {
+        "date out of range"
+    }
+}
+
+fn date_from(value: i64, strict: bool) -> Result<Timespec, DateError> {
+    if value < 0 {
+        return Err(DateError::Negative(value));
+    }
+    if value <= MAX_SECONDS {
+        return Ok(Timespec::new(value, 0));
+    }
+    if !strict && value / 1000 <= MAX_SECONDS {
+        log_warn!("reading date {} as milliseconds", value);
+        return Ok(Timespec::new(value / 1000, 0));
+    }
+    Err(DateError::OutOfRange(value))
+}
+
+/// The date `value` seconds after 1970, or milliseconds if too late for seconds and dates
+/// are not strict.
+pub fn date_from_seconds(value: i64) -> Result<Timespec, DateError> {
+    date_from(value, STRICT_DATES.load(Ordering::SeqCst))
+}
+
+/// The date `value` milliseconds after 1970, to the second, as dates are stored. Unlike
+/// `date_from_seconds` there is no guessing at the unit.
+pub fn date_from_millis(value: i64) -> Result<Timespec, DateError> {
+    if value < 0 {
+        return Err(DateError::Negative(value));
+    }
+    if value / 1000 > MAX_SECONDS {
+        return Err(DateError::OutOfRange(value));
+    }
+    Ok(Timespec::new(value / 1000, 0))
+}
+
+pub fn millis(date: Timespec) -> i64 {
+    date.sec * 1000 + date.nsec as i64 / 1_000_000
+}
+
+const ISO8601: &'static str = "%Y-%m-%dT%H:%M:%SZ";
+
+/// The date in UTC to the second, e.g. `2017-11-10T12:00:00Z`, as Foundation's
+/// `ISO8601DateFormatter` and `JSONDecoder.DateDecodingStrategy.iso8601` read it.
+pub fn iso8601(date: Timespec) -> String {
+    time::at_utc(date).strftime(ISO8601).unwrap().to_string()
+}
+
+/// Reads a date written by `iso8601`. Returns `None` if it is not one or is out of range.
+pub fn date_from_iso8601(value: &str) -> Option<Timespec> {
+    let date = time::strptime(value, ISO8601).ok()?.to_timespec();
+    date_from(date.sec, true).ok()
+}
+
+#[cfg(test)]
+mod test {
+    use time::Timespec;
+
+    use super::{
+        date_from,
+        date_from_iso8601,
+        date_from_millis,
+        iso8601,
+        millis,
+        DateError,
+        MAX_SECONDS,
+    };
+
+    #[test]
+    fn test_date_from() {
+        assert_eq!(date_from(0, false), Ok(Timespec::new(0, 0)));
+        assert_eq!(date_from(1510315200, false), Ok(Timespec::new(1510315200, 0)));
+        assert_eq!(date_from(MAX_SECONDS, true), Ok(Timespec::new(MAX_SECONDS, 0)));
+        assert_eq!(date_from(1510315200123, false), Ok(Timespec::new(1510315200, 0)));
+        assert_eq!(date_from(1510315200123, true), Err(DateError::OutOfRange(1510315200123)));
+        assert_eq!(date_from(-1, false), Err(DateError::Negative(-1)));
+        assert_eq!(date_from(i64::max_value(), false), Err(DateError::OutOfRange(i64::max_value())));
+    }
+
+    #[test]
+    fn test_date_from_millis() {
+        assert_eq!(date_from_millis(1510315200123), Ok(Timespec::new(1510315200, 0)));
+        assert_eq!(millis(Timespec::new(1510315200, 0)), 1510315200000);
+        assert_eq!(date_from_millis(-1), Err(DateError::Negative(-1)));
+        assert_eq!(date_from_millis((MAX_SECONDS + 1) * 1000), Err(DateError::OutOfRange((MAX_SECONDS + 1) * 1000)));
+    }
+
+    #[test]
+    fn test_iso8601() {
+        assert_eq!(iso8601(Timespec::new(1510315200, 0)), "2017-11-10T12:00:00Z");
+        assert_eq!(date_from_iso8601("2017-11-10T12:00:00Z"), Some(Timespec::new(1510315200, 0)));
+        assert_eq!(date_from_iso8601("2017-11-10"), None);
+        assert_eq!(date_from_iso8601("1510315200"), None);
+    }
+}
@@ -0,0 +1,175 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! Which of the Overdue, Today and Later views an open item belongs to depends on the local
+//! day, so crossing midnight or changing timezone moves items between views without any
+//! edit. The host reports such moments and is told exactly which items moved.
+
+use time::{
+    Duration,
+    Timespec,
+};
+
+use ffi_utils::breadcrumbs;
+use events::ChangeEvent;
+use filters::start_of_local_day;
+use ListManager;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DueBucket {
+    Overdue,
+    Today,
+    Later,
+}
+
+impl DueBucket {
+    /// The bucket of an open item due at `due`, matching `ItemFilter::overdue` and
+    /// `ItemFilter::due_today`.
+    pub fn of(due: Timespec, now: Timespec, utc_offset: i64) -> DueBucket {
+        let today = start_of_local_day(now, utc_offset);
+        if due < today {
+            DueBucket::Overdue
+        } else if due < today + Duration::days(1) {
+            DueBucket::Today
+        } else {
+            DueBucket::Later
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BucketChange {
+    pub uuid: String,
+    pub from: DueBucket,
+    pub to: DueBucket,
+}
+
+impl ListManager {
+    /// Call at local midnight, when the timezone changes and when daylight saving starts or
+    /// ends, with the new offset from UTC in seconds. Returns the open items whose bucket
+    /// changed since the previous call, and notifies observers with a `DueBucketChanged`
+    /// event for each so views and scheduled reminders for them can be refreshed. The first
+    /// call only records the day, so make one at launch too.
+    pub fn on_significant_time_change(&self, utc_offset: i64) -> Vec<BucketChange> {
+        breadcrumbs::leave("list.significant_time_change");
+        let now = self.now();
+        let (then, then_offset) = match self.day_boundary.replace(Some((now, utc_offset))) {
+            Some(previous) => previous,
+            None => {
+                self.refresh_widget_snapshot();
+                return vec![];
+            },
+        };
+        let sql = r#"SELECT uuid, due_date FROM items WHERE completion_date IS NULL AND due_date IS NOT NULL"#;
+        let conn = self.get_store().get_conn();
+        let mut stmt = conn.prepare(sql).unwrap();
+        let due_iter = stmt.query_map(&[], |row| -> (String, Timespec) { (row.get(0), row.get(1)) }).unwrap();
+        let changes: Vec<BucketChange> = due_iter.filter_map(|result| result.ok()).filter_map(|(uuid, due)| {
+            let from = DueBucket::of(due, then, then_offset);
+            let to = DueBucket::of(due, now, utc_offset);
+            if from == to {
+                None
+            } else {
+                Some(BucketChange {
+                    uuid: uuid,
+                    from: from,
+                    to: to,
+                })
+            }
+        }).collect();
+        for change in changes.iter() {
+            self.notify(ChangeEvent::DueBucketChanged(change.uuid.clone()));
+        }
+        if let Err(e) = self.expire_trash(&conn) {
+            log_error!("failed to expire trashed items {:?}", e);
+        }
+        // the day may have changed without moving any item
+        self.refresh_widget_snapshot();
+        changes
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::sync::Arc;
+
+    use time::{
+        Duration,
+        Timespec,
+    };
+
+    use super::{
+        BucketChange,
+        DueBucket,
+    };
+
+    use events::ChangeEvent;
+    use items::Item;
+    use store::Store;
+    use ListManager;
+
+    const HOUR: i64 = 3600;
+
+    fn item(name: &str, due_date: Timespec, completed: bool) -> Item {
+        let mut item = Item::named(name);
+        item.due_date = Some(due_date);
+        item.completion_date = if completed { Some(due_date) } else { None };
+        item
+    }
+
+    #[test]
+    fn test_midnight_rollover() {
+        let manager = ListManager::new(Arc::new(Store::new(None)));
+        // 2017-11-10 23:00 UTC
+        let evening = Timespec::new(1510354800, 0);
+        let tonight = manager.create_item(&item("tonight", evening + Duration::minutes(30), false));
+        let tomorrow = manager.create_item(&item("tomorrow", evening + Duration::hours(12), false));
+        manager.create_item(&item("next week", evening + Duration::days(7), false));
+        manager.create_item(&item("done", evening + Duration::minutes(30), true));
+
+        let events = Rc::new(RefCell::new(vec![]));
+        let seen = events.clone();
+        manager.add_observer(Box::new(move |event| seen.borrow_mut().push(event.clone())));
+
+        manager.freeze_clock(Some(evening));
+        assert_eq!(manager.on_significant_time_change(0), vec![]);
+
+        manager.freeze_clock(Some(evening + Duration::hours(1)));
+        let changes = manager.on_significant_time_change(0);
+        assert_eq!(changes, vec![
+            BucketChange { uuid: tonight.clone(), from: DueBucket::Today, to: DueBucket::Overdue },
+            BucketChange { uuid: tomorrow.clone(), from: DueBucket::Later, to: DueBucket::Today },
+        ]);
+        assert_eq!(*events.borrow(), vec![
+            ChangeEvent::DueBucketChanged(tonight),
+            ChangeEvent::DueBucketChanged(tomorrow),
+        ]);
+
+        // nothing moves without the day changing
+        assert_eq!(manager.on_significant_time_change(0), vec![]);
+    }
+
+    #[test]
+    fn test_timezone_change() {
+        let manager = ListManager::new(Arc::new(Store::new(None)));
+        let evening = Timespec::new(1510354800, 0);
+        let earlier = manager.create_item(&item("earlier", evening - Duration::minutes(90), false));
+        manager.create_item(&item("tonight", evening + Duration::minutes(30), false));
+        manager.freeze_clock(Some(evening));
+        manager.on_significant_time_change(0);
+
+        // flying east, it is already tomorrow at the destination
+        let changes = manager.on_significant_time_change(2 * HOUR);
+        assert_eq!(changes, vec![BucketChange { uuid: earlier, from: DueBucket::Today, to: DueBucket::Overdue }]);
+    }
+}
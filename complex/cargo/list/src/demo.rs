@@ -0,0 +1,80 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+use std::sync::Arc;
+
+use time::{
+    now_utc,
+    Duration,
+    Timespec,
+};
+
+use filters::ItemFilter;
+use store::Store;
+use templates::templates_available;
+use ListManager;
+
+/// Due date offsets, in days from now, given to the first items of each template so that
+/// the demo has something overdue, due today, upcoming and done. `None` completes the item.
+const DEMO_SCHEDULE: &'static [Option<i64>] = &[Some(-2), Some(0), Some(3), None];
+
+impl ListManager {
+    /// Fills the store with every starter template and a spread of due and completed items.
+    pub fn populate_demo(&self, locale: &str, now: Timespec) {
+        for template in templates_available(locale) {
+            self.template_instantiate(&template.id, locale);
+        }
+        let open = ItemFilter {
+            completed: Some(false),
+            ..ItemFilter::default()
+        };
+        let items = self.fetch_items(&open);
+        for (item, schedule) in items.iter().step_by(3).zip(DEMO_SCHEDULE.iter()) {
+            let mut item = item.clone();
+            match *schedule {
+                Some(days) => item.due_date = Some(now + Duration::days(days)),
+                None => item.completion_date = Some(now - Duration::hours(1)),
+            }
+            self.save_item(&item);
+        }
+    }
+}
+
+/// A list manager over a throwaway in memory store holding representative content.
+pub fn demo_list_manager(locale: &str) -> ListManager {
+    let manager = ListManager::new(Arc::new(Store::new(None)));
+    manager.populate_demo(locale, now_utc().to_timespec());
+    manager
+}
+
+#[cfg(test)]
+mod test {
+    use time::now_utc;
+
+    use super::demo_list_manager;
+
+    use filters::ItemFilter;
+
+    #[test]
+    fn test_demo_list_manager() {
+        let manager = demo_list_manager("en");
+        assert_eq!(manager.count_labels(), 3);
+        assert_eq!(manager.count_items(&ItemFilter::default()), 14);
+        assert_eq!(manager.items_overdue(0).len(), 1);
+        assert!(manager.items_upcoming(7, 0).len() >= 1);
+        let completed = ItemFilter {
+            completed: Some(true),
+            ..ItemFilter::default()
+        };
+        assert_eq!(manager.count_items(&completed), 1);
+        let now = now_utc().to_timespec();
+        assert!(manager.fetch_items(&completed)[0].completion_date.unwrap() < now);
+    }
+}
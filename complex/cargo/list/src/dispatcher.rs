@@ -0,0 +1,242 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! Push notifications of changes made through any list manager, delivered to C callbacks on
+//! one thread the library owns.
+//!
+//! `list_manager_add_observer` calls back on whichever thread made the change, with the
+//! library lock held. Hosts that update their UI from a single queue would rather have
+//! every notification arrive on the same thread, in the order the changes were made, and
+//! without blocking the writer. Changes are queued as they are made and a dispatcher
+//! thread, started by the first registration, hands them to each registered callback.
+
+use std::ffi::CString;
+use std::os::raw::c_void;
+use std::ptr;
+use std::sync::mpsc::{
+    self,
+    Receiver,
+    Sender,
+};
+use std::sync::{
+    Mutex,
+    MutexGuard,
+};
+use std::thread::{
+    self,
+    ThreadId,
+};
+
+use events::{
+    ChangeCallback,
+    ChangeEvent,
+};
+
+enum Message {
+    Change(ChangeEvent),
+    Flush(Sender<()>),
+    Run(Box<dyn FnOnce() + Send>),
+}
+
+struct Registration {
+    token: u64,
+    callback: ChangeCallback,
+    // a pointer only the host dereferences, kept as an integer so it may cross threads
+    user_data: usize,
+}
+
+struct Dispatcher {
+    sender: Option<Sender<Message>>,
+    thread: Option<ThreadId>,
+    registrations: Vec<Registration>,
+    next_token: u64,
+}
+
+static DISPATCHER: Mutex<Dispatcher> = Mutex::new(Dispatcher {
+    sender: None,
+    thread: None,
+    registrations: Vec::new(),
+    next_token: 0,
+});
+
+/// Held by the dispatcher thread while it calls back, so that `unregister` can wait for a
+/// callback in progress to return.
+static DELIVERING: Mutex<()> = Mutex::new(());
+
+fn dispatcher() -> MutexGuard<'static, Dispatcher> {
+    DISPATCHER.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn run(receiver: Receiver<Message>) {
+    for message in receiver {
+        match message {
+            Message::Change(event) => {
+                let _delivering = DELIVERING.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+                // copied so that callbacks may register and unregister
+                let registrations: Vec<(ChangeCallback, usize)> = dispatcher().registrations.iter()
+                    .map(|registration| (registration.callback, registration.user_data))
+                    .collect();
+                let id = event.id().map(|id| CString::new(id).unwrap_or_default());
+                let id_ptr = id.as_ref().map(|id| id.as_ptr()).unwrap_or(ptr::null());
+                for (callback, user_data) in registrations {
+                    callback(event.kind(), id_ptr, user_data as *mut c_void);
+                }
+            },
+            Message::Flush(done) => {
+                let _ = done.send(());
+            },
+            Message::Run(f) => f(),
+        }
+    }
+}
+
+/// Calls `callback` on the dispatcher thread after every change made through any list
+/// manager, in the order the changes were made. Returns a token for `unregister`.
+pub fn register(callback: ChangeCallback, user_data: *mut c_void) -> u64 {
+    let mut dispatcher = dispatcher();
+    if dispatcher.sender.is_none() {
+        let (sender, receiver) = mpsc::channel();
+        let thread = thread::Builder::new()
+            .name("toodle-observers".to_string())
+            .spawn(move || run(receiver))
+            .expect("expected the dispatcher thread to start");
+        dispatcher.sender = Some(sender);
+        dispatcher.thread = Some(thread.thread().id());
+    }
+    dispatcher.next_token += 1;
+    let token = dispatcher.next_token;
+    dispatcher.registrations.push(Registration {
+        token: token,
+        callback: callback,
+        user_data: user_data as usize,
+    });
+    token
+}
+
+/// Returns false if there is no callback with that token. Once it returns, the callback
+/// will not be called again and is not being called, unless `unregister` was called from
+/// the callback itself, so `user_data` may be freed.
+pub fn unregister(token: u64) -> bool {
+    let on_dispatcher_thread = {
+        let mut dispatcher = dispatcher();
+        let count = dispatcher.registrations.len();
+        dispatcher.registrations.retain(|registration| registration.token != token);
+        if dispatcher.registrations.len() == count {
+            return false;
+        }
+        dispatcher.thread == Some(thread::current().id())
+    };
+    if !on_dispatcher_thread {
+        drop(DELIVERING.lock());
+    }
+    true
+}
+
+/// Queues `event` for the registered callbacks. Does nothing if there are none.
+pub(crate) fn dispatch(event: &ChangeEvent) {
+    let dispatcher = dispatcher();
+    if dispatcher.registrations.is_empty() {
+        return;
+    }
+    if let Some(ref sender) = dispatcher.sender {
+        let _ = sender.send(Message::Change(event.clone()));
+    }
+}
+
+/// Runs `f` on the dispatcher thread once every change queued so far has been delivered, or
+/// now if the thread was never started. For releasing what a callback uses after
+/// `unregister`, which may have been called from the callback itself.
+pub fn defer(f: Box<dyn FnOnce() + Send>) {
+    let f = match dispatcher().sender {
+        Some(ref sender) => match sender.send(Message::Run(f)) {
+            Ok(()) => return,
+            Err(mpsc::SendError(Message::Run(f))) => f,
+            Err(_) => return,
+        },
+        None => f,
+    };
+    f();
+}
+
+/// Blocks until every change queued so far has been delivered.
+pub fn flush() {
+    let (done, finished) = mpsc::channel();
+    let sent = match dispatcher().sender {
+        Some(ref sender) => sender.send(Message::Flush(done)).is_ok(),
+        None => false,
+    };
+    if sent {
+        let _ = finished.recv();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::ffi::CStr;
+    use std::os::raw::{
+        c_char,
+        c_void,
+    };
+    use std::sync::{
+        Arc,
+        Mutex,
+    };
+    use std::thread::{
+        self,
+        ThreadId,
+    };
+
+    use events::ChangeEventKind;
+    use items::Item;
+    use store::Store;
+    use ListManager;
+
+    use super::{
+        flush,
+        register,
+        unregister,
+    };
+
+    type Seen = Mutex<Vec<(ChangeEventKind, Option<String>, ThreadId)>>;
+
+    extern "C" fn record(kind: ChangeEventKind, id: *const c_char, user_data: *mut c_void) {
+        let seen = unsafe { &*(user_data as *const Seen) };
+        let id = if id.is_null() { None } else { Some(unsafe { CStr::from_ptr(id) }.to_string_lossy().into_owned()) };
+        seen.lock().unwrap().push((kind, id, thread::current().id()));
+    }
+
+    #[test]
+    fn test_callbacks_fire_on_the_dispatcher_thread() {
+        let seen: Seen = Mutex::new(vec![]);
+        let token = register(record, &seen as *const Seen as *mut c_void);
+
+        let manager = ListManager::new(Arc::new(Store::new(None)));
+        manager.create_label("dispatched".to_string(), "#000000".to_string()).unwrap();
+        let uuid = manager.create_item(&Item::named("water the plants"));
+        assert!(manager.delete_item(&uuid));
+        flush();
+        assert!(unregister(token));
+        assert!(!unregister(token));
+
+        // other tests make changes too
+        let seen = seen.into_inner().unwrap();
+        let ours: Vec<(ChangeEventKind, String)> = seen.iter()
+            .filter_map(|&(kind, ref id, _)| id.clone().map(|id| (kind, id)))
+            .filter(|&(_, ref id)| *id == uuid || id == "dispatched")
+            .collect();
+        assert_eq!(ours, vec![
+            (ChangeEventKind::LabelChanged, "dispatched".to_string()),
+            (ChangeEventKind::ItemAdded, uuid.clone()),
+            (ChangeEventKind::ItemDeleted, uuid.clone()),
+        ]);
+        assert!(seen.iter().all(|&(_, _, thread)| thread == seen[0].2));
+        assert!(seen[0].2 != thread::current().id());
+    }
+}
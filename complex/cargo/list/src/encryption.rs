@@ -0,0 +1,58 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+use std::os::raw::c_char;
+use std::ptr;
+use std::sync::Arc;
+
+use ffi_utils::errors::{
+    set_extern_error,
+    ExternError,
+};
+use ffi_utils::strings::c_char_to_string;
+use store::Store;
+use ListManager;
+
+/// Opens the database at `path` encrypted with `key`, creating it if needed. Returns null
+/// and fills in `error` if the key is wrong or the SQLite library in use cannot encrypt.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_open_encrypted(path: *const c_char, key: *const c_char, error: *mut ExternError) -> *mut Arc<ListManager> {
+    ffi_guard!(error, {
+        let path = c_char_to_string(path);
+        let key = c_char_to_string(key);
+        match Store::open_encrypted(path, &key) {
+            Ok(store) => Box::into_raw(Box::new(Arc::new(ListManager::new(Arc::new(store))))),
+            Err(e) => {
+                log_error!("failed to open encrypted store {:?}", e);
+                set_extern_error(error, e.category(), e.to_string());
+                ptr::null_mut()
+            },
+        }
+    })
+}
+
+/// Re-encrypts the database with `key`. Returns false if the store is not encrypted or
+/// the key could not be changed.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_rekey(manager: *const Arc<ListManager>, key: *const c_char, error: *mut ExternError) -> bool {
+    ffi_lock!();
+    ffi_guard!(error, {
+        let manager = ffi_ref!("list_manager_rekey", manager, false, error);
+        let key = c_char_to_string(key);
+        match manager.get_store().rekey(&key) {
+            Ok(()) => true,
+            Err(e) => {
+                log_error!("failed to rekey store {:?}", e);
+                set_extern_error(error, e.category(), e.to_string());
+                false
+            },
+        }
+    })
+}
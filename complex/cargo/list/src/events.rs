@@ -0,0 +1,228 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+use std::cell::{
+    Cell,
+    RefCell,
+};
+use std::ffi::CString;
+use std::fmt;
+use std::os::raw::{
+    c_char,
+    c_void,
+};
+use std::ptr;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use dispatcher;
+use ListManager;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChangeEvent {
+    /// Everything previously read from the store may be out of date and should be refetched.
+    Invalidated,
+    /// The item with this uuid was created.
+    ItemAdded(String),
+    /// The item with this uuid was changed.
+    ItemUpdated(String),
+    /// The item with this uuid was deleted.
+    ItemDeleted(String),
+    /// The label with this name was created or changed.
+    LabelChanged(String),
+    /// The item with this uuid moved between the Overdue, Today and Later views because the
+    /// local day changed.
+    DueBucketChanged(String),
+    /// The item with this uuid was changed both here and on the sync server, and was settled
+    /// by the conflict strategy.
+    SyncConflict(String),
+    /// The label with this name was deleted.
+    LabelDeleted(String),
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChangeEventKind {
+    Invalidated = 0,
+    ItemAdded = 1,
+    ItemUpdated = 2,
+    ItemDeleted = 3,
+    LabelChanged = 4,
+    DueBucketChanged = 5,
+    SyncConflict = 6,
+    LabelDeleted = 7,
+}
+
+impl ChangeEvent {
+    pub fn kind(&self) -> ChangeEventKind {
+        match *self {
+            ChangeEvent::Invalidated => ChangeEventKind::Invalidated,
+            ChangeEvent::ItemAdded(_) => ChangeEventKind::ItemAdded,
+            ChangeEvent::ItemUpdated(_) => ChangeEventKind::ItemUpdated,
+            ChangeEvent::ItemDeleted(_) => ChangeEventKind::ItemDeleted,
+            ChangeEvent::LabelChanged(_) => ChangeEventKind::LabelChanged,
+            ChangeEvent::DueBucketChanged(_) => ChangeEventKind::DueBucketChanged,
+            ChangeEvent::SyncConflict(_) => ChangeEventKind::SyncConflict,
+            ChangeEvent::LabelDeleted(_) => ChangeEventKind::LabelDeleted,
+        }
+    }
+
+    /// The uuid of the item or the name of the label that changed.
+    pub fn id(&self) -> Option<&str> {
+        match *self {
+            ChangeEvent::Invalidated => None,
+            ChangeEvent::ItemAdded(ref id) |
+            ChangeEvent::ItemUpdated(ref id) |
+            ChangeEvent::ItemDeleted(ref id) |
+            ChangeEvent::LabelChanged(ref id) |
+            ChangeEvent::DueBucketChanged(ref id) |
+            ChangeEvent::SyncConflict(ref id) |
+            ChangeEvent::LabelDeleted(ref id) => Some(id),
+        }
+    }
+}
+
+pub type Observer = Box<dyn Fn(&ChangeEvent)>;
+
+/// Called with the kind of change and the uuid of the item or name of the label, which is
+/// null for `Invalidated` and only valid for the duration of the call.
+pub type ChangeCallback = extern "C" fn(kind: ChangeEventKind, id: *const c_char, user_data: *mut c_void);
+
+/// Functions called with each event of type `E`, list changes unless said otherwise.
+pub struct Observers<E = ChangeEvent> {
+    observers: RefCell<Vec<(u64, Rc<dyn Fn(&E)>)>>,
+    next_id: Cell<u64>,
+}
+
+impl<E> Default for Observers<E> {
+    fn default() -> Observers<E> {
+        Observers {
+            observers: RefCell::new(vec![]),
+            next_id: Cell::new(0),
+        }
+    }
+}
+
+impl<E> fmt::Debug for Observers<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Observers({})", self.observers.borrow().len())
+    }
+}
+
+impl<E> Observers<E> {
+    /// Returns an id for removing the observer again.
+    pub fn add(&self, observer: Box<dyn Fn(&E)>) -> u64 {
+        let id = self.next_id.get() + 1;
+        self.next_id.set(id);
+        self.observers.borrow_mut().push((id, Rc::from(observer)));
+        id
+    }
+
+    pub fn remove(&self, id: u64) -> bool {
+        let mut observers = self.observers.borrow_mut();
+        let count = observers.len();
+        observers.retain(|&(observer_id, _)| observer_id != id);
+        observers.len() != count
+    }
+
+    pub fn notify(&self, event: &E) {
+        // observers may add or remove observers while being notified
+        let observers: Vec<Rc<dyn Fn(&E)>> = self.observers.borrow().iter().map(|&(_, ref o)| o.clone()).collect();
+        for observer in observers.iter() {
+            observer(event);
+        }
+    }
+}
+
+impl ListManager {
+    /// Delivers `event` once the write it describes has committed. Inside a host transaction
+    /// that is when the outermost one commits; if it rolls back instead, the event is dropped.
+    pub(crate) fn notify(&self, event: ChangeEvent) {
+        let refresh = match event {
+            ChangeEvent::LabelChanged(_) | ChangeEvent::LabelDeleted(_) => None,
+            _ => Some(self.widget_refresh()),
+        };
+        let observers = self.observers.clone();
+        self.get_store().after_commit(Box::new(move || {
+            if let Some(refresh) = refresh {
+                refresh();
+            }
+            observers.notify(&event);
+            dispatcher::dispatch(&event);
+        }));
+    }
+}
+
+/// Calls `callback` on the calling thread after every change made through the list manager,
+/// once the change has been committed. Returns an id for `list_manager_remove_observer`.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_add_observer(manager: *const Arc<ListManager>, callback: ChangeCallback, user_data: *mut c_void) -> u64 {
+    ffi_lock!();
+    ffi_guard!({
+        let manager = ffi_ref!("list_manager_add_observer", manager);
+        let user_data = user_data as usize;
+        manager.add_observer(Box::new(move |event| {
+            let id = event.id().map(|id| CString::new(id).unwrap_or_default());
+            let id_ptr = id.as_ref().map(|id| id.as_ptr()).unwrap_or(ptr::null());
+            callback(event.kind(), id_ptr, user_data as *mut c_void);
+        }))
+    })
+}
+
+/// Returns false if there is no observer with that id.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_remove_observer(manager: *const Arc<ListManager>, id: u64) -> bool {
+    ffi_lock!();
+    ffi_guard!({
+        let manager = ffi_ref!("list_manager_remove_observer", manager);
+        manager.remove_observer(id)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::sync::Arc;
+
+    use super::ChangeEvent;
+
+    use items::Item;
+    use store::Store;
+    use ListManager;
+
+    #[test]
+    fn test_mutations_notify_observers() {
+        let manager = ListManager::new(Arc::new(Store::new(None)));
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let seen = events.clone();
+        let id = manager.add_observer(Box::new(move |event| seen.borrow_mut().push(event.clone())));
+
+        manager.create_label("home".to_string(), "#000000".to_string()).unwrap();
+        let mut item = Item::named("fix the gate");
+        item.uuid = manager.create_item(&item);
+        item.name = "fix the garden gate".to_string();
+        manager.save_item(&item);
+        assert!(manager.delete_item(&item.uuid));
+        assert!(!manager.delete_item(&item.uuid));
+
+        assert_eq!(*events.borrow(), vec![
+            ChangeEvent::LabelChanged("home".to_string()),
+            ChangeEvent::ItemAdded(item.uuid.clone()),
+            ChangeEvent::ItemUpdated(item.uuid.clone()),
+            ChangeEvent::ItemDeleted(item.uuid.clone()),
+        ]);
+
+        assert!(manager.remove_observer(id));
+        assert!(!manager.remove_observer(id));
+        manager.create_label("work".to_string(), "#000000".to_string()).unwrap();
+        assert_eq!(events.borrow().len(), 4);
+    }
+}
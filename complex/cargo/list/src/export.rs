@@ -0,0 +1,273 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+use std::os::raw::c_char;
+use std::ptr;
+use std::sync::Arc;
+
+use serde_json;
+use time::Timespec;
+
+use dates::iso8601;
+use ffi_utils::breadcrumbs;
+use ffi_utils::errors::{
+    set_extern_error,
+    ErrorCategory,
+    ExternError,
+};
+use ffi_utils::strings::string_to_c_char;
+use filters::ItemFilter;
+use items::Item;
+use ListManager;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    /// `{"name": ..., "items": [...]}` with items as everywhere else in the API.
+    Json = 0,
+    /// One row per item under a `name,due_date,completion_date,labels` header.
+    Csv = 1,
+    /// A task list under the smart list's name, for pasting into chats and issues.
+    Markdown = 2,
+}
+
+impl ExportFormat {
+    pub fn from_u32(value: u32) -> Option<ExportFormat> {
+        match value {
+            0 => Some(ExportFormat::Json),
+            1 => Some(ExportFormat::Csv),
+            2 => Some(ExportFormat::Markdown),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct JsonExport<'a> {
+    name: &'a str,
+    items: &'a [Item],
+}
+
+fn iso_date(date: Option<Timespec>) -> String {
+    date.map(iso8601).unwrap_or_default()
+}
+
+/// Quotes the value if needed. Values a spreadsheet would take for a formula are prefixed
+/// with `'`, so that opening an export cannot run one.
+fn csv_field(value: &str) -> String {
+    let value = if value.starts_with(|c| c == '=' || c == '+' || c == '-' || c == '@' || c == '\t') {
+        format!("'{}", value)
+    } else {
+        value.to_string()
+    };
+    if value.contains(|c| c == ',' || c == '"' || c == '\n' || c == '\r') {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
+
+/// One row per item, led by its uuid if `with_uuid`.
+fn to_csv(items: &[Item], with_uuid: bool) -> String {
+    let mut csv = if with_uuid { "uuid," } else { "" }.to_string();
+    csv.push_str("name,due_date,completion_date,labels\r\n");
+    for item in items.iter() {
+        let labels: Vec<&str> = item.labels.iter().map(|label| label.name.as_str()).collect();
+        let mut fields = vec![];
+        if with_uuid {
+            fields.push(csv_field(&item.uuid));
+        }
+        fields.push(csv_field(&item.name));
+        fields.push(iso_date(item.due_date));
+        fields.push(iso_date(item.completion_date));
+        fields.push(csv_field(&labels.join(";")));
+        csv.push_str(&fields.join(","));
+        csv.push_str("\r\n");
+    }
+    csv
+}
+
+pub(crate) fn markdown_escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        if "\\`*_[]#<>".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(if c == '\n' { ' ' } else { c });
+    }
+    escaped
+}
+
+fn to_markdown(name: &str, items: &[Item]) -> String {
+    let mut markdown = format!("# {}\n\n", markdown_escape(name));
+    for item in items.iter() {
+        let check = if item.completion_date.is_some() { "x" } else { " " };
+        markdown.push_str(&format!("- [{}] {}", check, markdown_escape(&item.name)));
+        if item.due_date.is_some() {
+            markdown.push_str(&format!(" (due {})", iso_date(item.due_date)));
+        }
+        for label in item.labels.iter() {
+            markdown.push_str(&format!(" `{}`", label.name.replace('`', "'")));
+        }
+        markdown.push('\n');
+    }
+    markdown
+}
+
+impl ListManager {
+    /// The items currently matching the smart list, and nothing else from the store, in
+    /// `format`. Returns `None` if there is no smart list with that id.
+    pub fn smart_list_export(&self, id: i64, format: ExportFormat) -> Option<String> {
+        let list = self.fetch_smart_list(id)?;
+        let items = self.fetch_items(&list.filter);
+        Some(match format {
+            ExportFormat::Json => serde_json::to_string(&JsonExport { name: &list.name, items: &items }).unwrap(),
+            ExportFormat::Csv => to_csv(&items, false),
+            ExportFormat::Markdown => to_markdown(&list.name, &items),
+        })
+    }
+
+    /// Every item as CSV, for spreadsheets: a `uuid,name,due_date,completion_date,labels`
+    /// header, then one row per item with dates in ISO 8601 UTC and labels separated by `;`.
+    pub fn export_csv(&self) -> String {
+        breadcrumbs::leave("list.export_csv");
+        to_csv(&self.fetch_items(&ItemFilter::default()), true)
+    }
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_export_csv(manager: *const Arc<ListManager>) -> *mut c_char {
+    ffi_lock!();
+    ffi_guard!({
+        let manager = ffi_ref!("list_manager_export_csv", manager, ptr::null_mut());
+        string_to_c_char(manager.export_csv())
+    })
+}
+
+/// `format` is one of the `ExportFormat` values. Returns null and fills in `error` with `Bug`
+/// if it is not, or if there is no smart list with that id.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_smart_list_export(manager: *const Arc<ListManager>, id: i64, format: u32, error: *mut ExternError) -> *mut c_char {
+    ffi_lock!();
+    ffi_guard!(error, {
+        let manager = ffi_ref!("list_manager_smart_list_export", manager, ptr::null_mut(), error);
+        let format = match ExportFormat::from_u32(format) {
+            Some(format) => format,
+            None => {
+                set_extern_error(error, ErrorCategory::Bug, format!("unknown export format {}", format));
+                return ptr::null_mut();
+            },
+        };
+        match manager.smart_list_export(id, format) {
+            Some(export) => string_to_c_char(export),
+            None => {
+                set_extern_error(error, ErrorCategory::Bug, format!("no smart list with id {}", id));
+                ptr::null_mut()
+            },
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use serde_json;
+    use time::Timespec;
+
+    use super::ExportFormat;
+
+    use filters::ItemFilter;
+    use items::Item;
+    use store::Store;
+    use ListManager;
+
+    fn release_list(manager: &ListManager) -> i64 {
+        let release = manager.create_label("release".to_string(), "#00ff00".to_string()).unwrap();
+        let due = Some(Timespec::new(1510315200, 0));
+        for &(name, completed) in [("Tag \"v2, final\"", false), ("Write *notes*", true)].iter() {
+            manager.create_item(&Item {
+                uuid: "".to_string(),
+                name: name.to_string(),
+                due_date: due,
+                completion_date: if completed { due } else { None },
+                labels: vec![Arc::new(release.clone())],
+            });
+        }
+        manager.create_item(&Item::named("Not shared"));
+        let filter = ItemFilter {
+            labels: vec!["release".to_string()],
+            ..ItemFilter::default()
+        };
+        manager.create_smart_list("#release".to_string(), &filter).unwrap().id
+    }
+
+    #[test]
+    fn test_export_formats() {
+        let manager = ListManager::new(Arc::new(Store::new(None)));
+        let id = release_list(&manager);
+
+        let csv = manager.smart_list_export(id, ExportFormat::Csv).unwrap();
+        assert_eq!(csv, "name,due_date,completion_date,labels\r\n\
+                         \"Tag \"\"v2, final\"\"\",2017-11-10T12:00:00Z,,release\r\n\
+                         Write *notes*,2017-11-10T12:00:00Z,2017-11-10T12:00:00Z,release\r\n");
+
+        let markdown = manager.smart_list_export(id, ExportFormat::Markdown).unwrap();
+        assert_eq!(markdown, "# \\#release\n\n\
+                              - [ ] Tag \"v2, final\" (due 2017-11-10T12:00:00Z) `release`\n\
+                              - [x] Write \\*notes\\* (due 2017-11-10T12:00:00Z) `release`\n");
+
+        let json: serde_json::Value = serde_json::from_str(&manager.smart_list_export(id, ExportFormat::Json).unwrap()).unwrap();
+        assert_eq!(json["name"], "#release");
+        assert_eq!(json["items"].as_array().unwrap().len(), 2);
+        assert_eq!(json["items"][0]["due_date"], "2017-11-10T12:00:00Z");
+
+        assert_eq!(manager.smart_list_export(id + 1, ExportFormat::Csv), None);
+    }
+
+    #[test]
+    fn test_unknown_export_format() {
+        use ffi_utils::errors::{
+            error_message_destroy,
+            ErrorCategory,
+            ExternError,
+        };
+        use ffi_utils::strings::string_destroy;
+        use super::list_manager_smart_list_export;
+
+        let manager = Arc::new(ListManager::new(Arc::new(Store::new(None))));
+        let id = release_list(&manager);
+        let mut error = ExternError::default();
+        let export = unsafe { list_manager_smart_list_export(&manager, id, ExportFormat::Markdown as u32, &mut error) };
+        assert!(!export.is_null());
+        assert_eq!(error.category, ErrorCategory::None);
+        unsafe { string_destroy(export) };
+
+        let export = unsafe { list_manager_smart_list_export(&manager, id, 3, &mut error) };
+        assert!(export.is_null());
+        assert_eq!(error.category, ErrorCategory::Bug);
+        unsafe { error_message_destroy(error.message) };
+    }
+
+    #[test]
+    fn test_export_csv() {
+        let manager = ListManager::new(Arc::new(Store::new(None)));
+        release_list(&manager);
+        let formula = manager.create_item(&Item::named("=HYPERLINK(\"http://example.com\")"));
+
+        let csv = manager.export_csv();
+        let mut lines: Vec<&str> = csv.split("\r\n").collect();
+        assert_eq!(lines.remove(0), "uuid,name,due_date,completion_date,labels");
+        assert_eq!(lines.pop(), Some(""));
+        assert_eq!(lines.len(), 4);
+        assert!(lines.contains(&format!("{},\"'=HYPERLINK(\"\"http://example.com\"\")\",,,", formula).as_str()));
+        assert!(lines.iter().any(|line| line.ends_with(",\"Tag \"\"v2, final\"\"\",2017-11-10T12:00:00Z,,release")));
+    }
+}
@@ -0,0 +1,260 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+use std::os::raw::{
+    c_char,
+    c_int,
+};
+
+use rusqlite::types::ToSql;
+use time::{
+    now_utc,
+    Duration,
+    Timespec,
+};
+
+use ffi_utils::strings::c_char_to_string;
+use search::transliterate;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DateBound {
+    Absolute(Timespec),
+    /// Seconds relative to the time the filter is run, so that saved filters such as
+    /// "Overdue" keep their meaning.
+    FromNow(i64),
+}
+
+impl DateBound {
+    pub fn resolve(&self, now: Timespec) -> Timespec {
+        match *self {
+            DateBound::Absolute(date) => date,
+            DateBound::FromNow(seconds) => now + Duration::seconds(seconds),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+/// Restricts a query to the items matching every condition that is set.
+pub struct ItemFilter {
+    /// Names of labels the item must carry, all of them.
+    pub labels: Vec<String>,
+    pub completed: Option<bool>,
+    pub due_before: Option<DateBound>,
+    pub due_after: Option<DateBound>,
+    pub name_contains: Option<String>,
+}
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+/// The UTC instant at which the local day containing `now` started, for a local time that
+/// is `utc_offset` seconds ahead of UTC.
+pub fn start_of_local_day(now: Timespec, utc_offset: i64) -> Timespec {
+    let local = now.sec + utc_offset;
+    let local_midnight = local - local.rem_euclid(SECONDS_PER_DAY);
+    Timespec::new(local_midnight - utc_offset, 0)
+}
+
+impl ItemFilter {
+    /// Open items due at any time during the local day containing `now`.
+    pub fn due_today(now: Timespec, utc_offset: i64) -> ItemFilter {
+        let today = start_of_local_day(now, utc_offset);
+        ItemFilter {
+            completed: Some(false),
+            due_after: Some(DateBound::Absolute(today)),
+            due_before: Some(DateBound::Absolute(today + Duration::days(1))),
+            ..ItemFilter::default()
+        }
+    }
+
+    /// Open items that were due before the local day containing `now` began. Items due
+    /// earlier today are part of today rather than overdue.
+    pub fn overdue(now: Timespec, utc_offset: i64) -> ItemFilter {
+        ItemFilter {
+            completed: Some(false),
+            due_before: Some(DateBound::Absolute(start_of_local_day(now, utc_offset))),
+            ..ItemFilter::default()
+        }
+    }
+
+    /// Open items due in the `days` local days following today.
+    pub fn upcoming(now: Timespec, utc_offset: i64, days: i64) -> ItemFilter {
+        let tomorrow = start_of_local_day(now, utc_offset) + Duration::days(1);
+        ItemFilter {
+            completed: Some(false),
+            due_after: Some(DateBound::Absolute(tomorrow)),
+            due_before: Some(DateBound::Absolute(tomorrow + Duration::days(days))),
+            ..ItemFilter::default()
+        }
+    }
+
+    /// Returns a copy of this filter with every relative date bound fixed against `now`.
+    pub fn resolved(&self, now: Timespec) -> ItemFilter {
+        ItemFilter {
+            due_before: self.due_before.map(|bound| DateBound::Absolute(bound.resolve(now))),
+            due_after: self.due_after.map(|bound| DateBound::Absolute(bound.resolve(now))),
+            ..self.clone()
+        }
+    }
+
+    /// Returns a SQL expression over the `items` table, and the parameters it binds.
+    /// An empty filter produces an expression that is always true. Relative date bounds
+    /// are resolved against the current time.
+    pub fn to_sql(&self) -> (String, Vec<Box<dyn ToSql>>) {
+        let now = now_utc().to_timespec();
+        let mut clauses: Vec<String> = vec!["1".to_string()];
+        let mut params: Vec<Box<dyn ToSql>> = Vec::new();
+        for label in self.labels.iter() {
+            clauses.push("items.uuid IN (SELECT item_uuid FROM item_labels WHERE label_name=?)".to_string());
+            params.push(Box::new(label.clone()));
+        }
+        match self.completed {
+            Some(true) => clauses.push("items.completion_date IS NOT NULL".to_string()),
+            Some(false) => clauses.push("items.completion_date IS NULL".to_string()),
+            None => {},
+        }
+        if let Some(bound) = self.due_before {
+            clauses.push("items.due_date < ?".to_string());
+            params.push(Box::new(bound.resolve(now)));
+        }
+        if let Some(bound) = self.due_after {
+            clauses.push("items.due_date >= ?".to_string());
+            params.push(Box::new(bound.resolve(now)));
+        }
+        if let Some(ref name) = self.name_contains {
+            clauses.push(r#"(instr(lower(items.name), lower(?)) > 0 OR
+                             items.uuid IN (SELECT item_uuid FROM item_search WHERE instr(latin, ?) > 0) OR
+                             items.uuid IN (SELECT item_uuid FROM attachments WHERE instr(search_text, ?) > 0))"#.to_string());
+            params.push(Box::new(name.clone()));
+            params.push(Box::new(transliterate(name)));
+            params.push(Box::new(transliterate(name)));
+        }
+        (clauses.join(" AND "), params)
+    }
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub extern "C" fn item_filter_new() -> *mut ItemFilter {
+    ffi_guard!({
+        Box::into_raw(Box::new(ItemFilter::default()))
+    })
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn item_filter_destroy(filter: *mut ItemFilter) {
+    ffi_guard!({
+        if filter.is_null() {
+            return;
+        }
+        let _ = Box::from_raw(filter);
+    })
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn item_filter_add_label(filter: *mut ItemFilter, label_name: *const c_char) {
+    ffi_guard!({
+        let filter = ffi_mut!("item_filter_add_label", filter);
+        filter.labels.push(c_char_to_string(label_name));
+    })
+}
+
+/// `completed` is 1 for completed items only, 0 for open items only and -1 for both.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn item_filter_set_completed(filter: *mut ItemFilter, completed: c_int) {
+    ffi_guard!({
+        let filter = ffi_mut!("item_filter_set_completed", filter);
+        filter.completed = match completed {
+            1 => Some(true),
+            0 => Some(false),
+            _ => None,
+        };
+    })
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn item_filter_set_due_before(filter: *mut ItemFilter, due_before: *const i64) {
+    ffi_guard!({
+        let filter = ffi_mut!("item_filter_set_due_before", filter);
+        filter.due_before = due_before.as_ref().map(|sec| DateBound::Absolute(Timespec::new(*sec, 0)));
+    })
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn item_filter_set_due_before_from_now(filter: *mut ItemFilter, seconds: i64) {
+    ffi_guard!({
+        let filter = ffi_mut!("item_filter_set_due_before_from_now", filter);
+        filter.due_before = Some(DateBound::FromNow(seconds));
+    })
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn item_filter_set_due_after(filter: *mut ItemFilter, due_after: *const i64) {
+    ffi_guard!({
+        let filter = ffi_mut!("item_filter_set_due_after", filter);
+        filter.due_after = due_after.as_ref().map(|sec| DateBound::Absolute(Timespec::new(*sec, 0)));
+    })
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn item_filter_set_due_after_from_now(filter: *mut ItemFilter, seconds: i64) {
+    ffi_guard!({
+        let filter = ffi_mut!("item_filter_set_due_after_from_now", filter);
+        filter.due_after = Some(DateBound::FromNow(seconds));
+    })
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn item_filter_set_name_contains(filter: *mut ItemFilter, name: *const c_char) {
+    ffi_guard!({
+        let filter = ffi_mut!("item_filter_set_name_contains", filter);
+        filter.name_contains = if name.is_null() { None } else { Some(c_char_to_string(name)) };
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use time::Timespec;
+
+    use super::{
+        start_of_local_day,
+        DateBound,
+        ItemFilter,
+    };
+
+    // 2017-11-10 23:30:00 UTC
+    const NOW: i64 = 1510356600;
+    const MIDNIGHT_UTC: i64 = 1510272000;
+
+    #[test]
+    fn test_start_of_local_day() {
+        let now = Timespec::new(NOW, 0);
+        assert_eq!(start_of_local_day(now, 0).sec, MIDNIGHT_UTC);
+        // 01:30 the next day in UTC+2
+        assert_eq!(start_of_local_day(now, 2 * 3600).sec, MIDNIGHT_UTC + 86400 - 2 * 3600);
+        // 15:30 the same day in UTC-8
+        assert_eq!(start_of_local_day(now, -8 * 3600).sec, MIDNIGHT_UTC + 8 * 3600);
+    }
+
+    #[test]
+    fn test_day_filters() {
+        let now = Timespec::new(NOW, 0);
+        let today = ItemFilter::due_today(now, 0);
+        assert_eq!(today.completed, Some(false));
+        assert_eq!(today.due_after, Some(DateBound::Absolute(Timespec::new(MIDNIGHT_UTC, 0))));
+        assert_eq!(today.due_before, Some(DateBound::Absolute(Timespec::new(MIDNIGHT_UTC + 86400, 0))));
+
+        let overdue = ItemFilter::overdue(now, 0);
+        assert_eq!(overdue.due_before, today.due_after);
+        assert_eq!(overdue.due_after, None);
+
+        let upcoming = ItemFilter::upcoming(now, 0, 7);
+        assert_eq!(upcoming.due_after, today.due_before);
+        assert_eq!(upcoming.due_before, Some(DateBound::Absolute(Timespec::new(MIDNIGHT_UTC + 8 * 86400, 0))));
+    }
+}
@@ -0,0 +1,88 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+use std::sync::Arc;
+
+use serde_json;
+use time::Timespec;
+
+use items::Item;
+use labels::Label;
+use store::Store;
+use ListManager;
+
+/// Datasets for screenshot and UI tests, embedded so that every platform loads exactly
+/// the same content.
+const FIXTURES: &'static [(&'static str, &'static str)] = &[
+    ("basic", include_str!("../tests/fixtures/basic.json")),
+    ("empty", include_str!("../tests/fixtures/empty.json")),
+];
+
+#[derive(Debug, Deserialize)]
+struct Fixture {
+    /// The instant the clock is frozen at, in seconds since the epoch.
+    now: i64,
+    labels: Vec<Label>,
+    items: Vec<Item>,
+}
+
+pub fn fixture_names() -> Vec<&'static str> {
+    FIXTURES.iter().map(|&(name, _)| name).collect()
+}
+
+/// A list manager over an in memory store holding the named fixture, with its clock frozen
+/// at the fixture's `now`. Returns `None` if there is no fixture with that name.
+pub fn fixture_list_manager(name: &str) -> Option<ListManager> {
+    let json = match FIXTURES.iter().find(|&&(fixture, _)| fixture == name) {
+        Some(&(_, json)) => json,
+        None => return None,
+    };
+    let fixture: Fixture = serde_json::from_str(json).expect("fixtures are valid");
+    let manager = ListManager::new(Arc::new(Store::new(None)));
+    manager.freeze_clock(Some(Timespec::new(fixture.now, 0)));
+    for label in fixture.labels.iter() {
+        manager.create_label(label.name.clone(), label.color.clone());
+    }
+    for item in fixture.items.iter() {
+        manager.save_item(item);
+    }
+    Some(manager)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        fixture_list_manager,
+        fixture_names,
+    };
+
+    use filters::ItemFilter;
+
+    #[test]
+    fn test_every_fixture_loads() {
+        for name in fixture_names() {
+            assert!(fixture_list_manager(name).is_some(), "fixture {} failed to load", name);
+        }
+        assert!(fixture_list_manager("missing").is_none());
+    }
+
+    #[test]
+    fn test_basic_fixture_is_deterministic() {
+        let manager = fixture_list_manager("basic").unwrap();
+        let other = fixture_list_manager("basic").unwrap();
+        assert_eq!(manager.fetch_items(&ItemFilter::default()), other.fetch_items(&ItemFilter::default()));
+        assert_eq!(manager.stats(30, 0), other.stats(30, 0));
+
+        let names = |items: Vec<::items::Item>| items.iter().map(|i| i.name.clone()).collect::<Vec<String>>();
+        assert_eq!(names(manager.items_overdue(0)), vec!["Renew passport"]);
+        assert_eq!(names(manager.items_due_today(0)), vec!["Send quarterly report", "Water the plants"]);
+        assert_eq!(names(manager.items_upcoming(7, 0)), vec!["Book team offsite", "Pick up dry cleaning"]);
+    }
+}
@@ -0,0 +1,84 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+use std::collections::BTreeMap;
+
+use serde_json;
+
+use ListManager;
+
+/// Settle items changed on both sides field by field, as `ConflictStrategy::FieldMerge` does,
+/// where the manual strategy would record a conflict for the user.
+pub const CRDT_MERGE: &'static str = "crdt_merge";
+
+/// Switches for rolling out risky changes gradually, set remotely by the embedding app.
+/// Every flag is off unless set, so a missing or stale configuration falls back to the
+/// proven code path. Names the library does not know yet are kept but have no effect.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Flags(BTreeMap<String, bool>);
+
+impl Flags {
+    /// Parses a JSON object mapping flag names to booleans.
+    pub fn parse(json: &str) -> Option<Flags> {
+        serde_json::from_str(json).ok()
+    }
+
+    pub fn is_enabled(&self, name: &str) -> bool {
+        self.0.get(name).cloned().unwrap_or(false)
+    }
+}
+
+impl ListManager {
+    /// Replaces every flag, so that a flag dropped from the remote configuration turns off.
+    pub fn set_flags(&self, flags: Flags) {
+        *self.flags.borrow_mut() = flags;
+    }
+
+    pub fn flags(&self) -> Flags {
+        self.flags.borrow().clone()
+    }
+
+    pub fn flag_enabled(&self, name: &str) -> bool {
+        self.flags.borrow().is_enabled(name)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use super::{
+        Flags,
+        CRDT_MERGE,
+    };
+
+    use store::Store;
+    use ListManager;
+
+    #[test]
+    fn test_flags() {
+        let manager = ListManager::new(Arc::new(Store::new(None)));
+        assert!(!manager.flag_enabled(CRDT_MERGE));
+
+        let flags = Flags::parse(r#"{"crdt_merge": true, "push_sync": false, "from_the_future": true}"#)
+            .expect("expected flags");
+        manager.set_flags(flags);
+        assert!(manager.flag_enabled(CRDT_MERGE));
+        assert!(!manager.flag_enabled("push_sync"));
+        assert!(manager.flag_enabled("from_the_future"));
+
+        // a new configuration replaces the old one
+        manager.set_flags(Flags::parse("{}").unwrap());
+        assert!(!manager.flag_enabled(CRDT_MERGE));
+
+        assert_eq!(Flags::parse(r#"{"crdt_merge": "yes"}"#), None);
+        assert_eq!(Flags::parse("not json"), None);
+    }
+}
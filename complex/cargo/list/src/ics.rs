@@ -0,0 +1,498 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! Items as iCalendar (RFC 5545) VTODO components, for moving tasks to and from Reminders,
+//! Thunderbird and other calendar apps.
+//!
+//! Labels become `CATEGORIES`. The open occurrences of a recurring series are exported as
+//! one VTODO with an `RRULE`, and daily or weekly rules are expanded into occurrences again
+//! on import. Dates in other time zones are read as UTC.
+
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io;
+use std::io::Write;
+use std::os::raw::c_char;
+use std::path::Path;
+use std::ptr;
+use std::slice;
+use std::str;
+use std::sync::Arc;
+
+use libc::size_t;
+use serde_json;
+use time::{
+    at_utc,
+    strptime,
+    Timespec,
+};
+
+use ffi_utils::breadcrumbs;
+use ffi_utils::errors::{
+    set_extern_error,
+    ErrorCategory,
+    ExternError,
+};
+use ffi_utils::strings::{
+    string_to_c_char,
+    c_char_to_string,
+};
+use filters::ItemFilter;
+use items::Item;
+use labels::Label;
+use recurrence::{
+    step,
+    Frequency,
+};
+use ListManager;
+
+/// The color of labels created for categories the store does not have yet.
+pub const IMPORTED_LABEL_COLOR: &'static str = "#808080";
+/// How many occurrences are created for a rule without `COUNT` or `UNTIL`.
+pub const MAX_IMPORTED_OCCURRENCES: i64 = 52;
+
+fn ical_date(date: Timespec) -> String {
+    at_utc(date).strftime("%Y%m%dT%H%M%SZ").unwrap().to_string()
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        match c {
+            '\\' | ';' | ',' => {
+                escaped.push('\\');
+                escaped.push(c);
+            },
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {},
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Unescapes a text value, splitting it on unescaped commas if `split`.
+fn unescape_parts(value: &str, split: bool) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('n') | Some('N') => parts.last_mut().unwrap().push('\n'),
+                Some(c) => parts.last_mut().unwrap().push(c),
+                None => {},
+            },
+            ',' if split => parts.push(String::new()),
+            c => parts.last_mut().unwrap().push(c),
+        }
+    }
+    parts
+}
+
+fn unescape(value: &str) -> String {
+    unescape_parts(value, false).pop().unwrap_or_default()
+}
+
+/// Appends the line, folded after 75 bytes as RFC 5545 asks.
+fn push_line(out: &mut String, line: &str) {
+    let mut len = 0;
+    for c in line.chars() {
+        if len + c.len_utf8() > 75 {
+            out.push_str("\r\n ");
+            len = 1;
+        }
+        out.push(c);
+        len += c.len_utf8();
+    }
+    out.push_str("\r\n");
+}
+
+/// `rule` is the frequency, interval and number of occurrences.
+fn push_todo(out: &mut String, item: &Item, rule: Option<(Frequency, i64, i64)>, stamp: &str) {
+    push_line(out, "BEGIN:VTODO");
+    push_line(out, &format!("UID:{}", item.uuid));
+    push_line(out, &format!("DTSTAMP:{}", stamp));
+    push_line(out, &format!("SUMMARY:{}", escape(&item.name)));
+    if let Some(due_date) = item.due_date {
+        push_line(out, &format!("DUE:{}", ical_date(due_date)));
+    }
+    match item.completion_date {
+        Some(completion_date) => {
+            push_line(out, "STATUS:COMPLETED");
+            push_line(out, &format!("COMPLETED:{}", ical_date(completion_date)));
+        },
+        None => push_line(out, "STATUS:NEEDS-ACTION"),
+    }
+    if !item.labels.is_empty() {
+        let names: Vec<String> = item.labels.iter().map(|label| escape(&label.name)).collect();
+        push_line(out, &format!("CATEGORIES:{}", names.join(",")));
+    }
+    if let Some((frequency, interval, count)) = rule {
+        let frequency = match frequency {
+            Frequency::Daily => "DAILY",
+            Frequency::Weekly => "WEEKLY",
+        };
+        push_line(out, &format!("RRULE:FREQ={};INTERVAL={};COUNT={}", frequency, interval, count));
+    }
+    push_line(out, "END:VTODO");
+}
+
+/// Splits a content line into its upper case name and its value. Parameters are dropped:
+/// `VALUE=DATE` shows in the value, and time zones are not looked up.
+fn split_line(line: &str) -> Option<(String, &str)> {
+    let mut quoted = false;
+    for (index, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ':' if !quoted => {
+                let name = line[..index].split(';').next().unwrap_or("");
+                return Some((name.to_uppercase(), &line[index + 1..]));
+            },
+            _ => {},
+        }
+    }
+    None
+}
+
+/// A `DATE` or `DATE-TIME`. Floating times and times with a `TZID` are read as UTC.
+fn parse_date(value: &str) -> Option<Timespec> {
+    let value = value.trim();
+    let format = if value.len() == 8 {
+        "%Y%m%d"
+    } else if value.ends_with('Z') || value.ends_with('z') {
+        "%Y%m%dT%H%M%SZ"
+    } else {
+        "%Y%m%dT%H%M%S"
+    };
+    strptime(&value.to_uppercase(), format).ok().map(|tm| tm.to_timespec())
+}
+
+/// The frequency, interval and number of occurrences of a daily or weekly rule for an item
+/// first due at `due_date`.
+fn parse_rule(value: &str, due_date: Timespec) -> Option<(Frequency, i64, i64)> {
+    let mut frequency = None;
+    let mut interval = 1;
+    let mut count = None;
+    let mut until = None;
+    for part in value.split(';') {
+        let mut pair = part.splitn(2, '=');
+        let key = pair.next().unwrap_or("").trim().to_uppercase();
+        let value = pair.next().unwrap_or("").trim();
+        match key.as_str() {
+            "FREQ" => frequency = match value.to_uppercase().as_str() {
+                "DAILY" => Some(Frequency::Daily),
+                "WEEKLY" => Some(Frequency::Weekly),
+                _ => return None,
+            },
+            "INTERVAL" => interval = value.parse().ok().filter(|interval| *interval > 0)?,
+            "COUNT" => count = value.parse().ok(),
+            "UNTIL" => until = parse_date(value),
+            // BYDAY and the like would change which days occur
+            "WKST" => {},
+            _ => return None,
+        }
+    }
+    let frequency = frequency?;
+    let count = match (count, until) {
+        (Some(count), _) => count,
+        (None, Some(until)) => (until - due_date).num_seconds() / step(frequency, interval).num_seconds() + 1,
+        (None, None) => MAX_IMPORTED_OCCURRENCES,
+    };
+    Some((frequency, interval, count.max(1).min(MAX_IMPORTED_OCCURRENCES)))
+}
+
+#[derive(Default)]
+struct Todo {
+    summary: Option<String>,
+    due_date: Option<Timespec>,
+    completion_date: Option<Timespec>,
+    completed: bool,
+    categories: Vec<String>,
+    rule: Option<String>,
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+impl ListManager {
+    /// Every item as a VCALENDAR of VTODOs.
+    pub fn to_ical(&self) -> String {
+        let stamp = ical_date(self.now());
+        let mut out = String::new();
+        push_line(&mut out, "BEGIN:VCALENDAR");
+        push_line(&mut out, "VERSION:2.0");
+        push_line(&mut out, "PRODID:-//Mozilla//Toodle//EN");
+        let mut exported_series = BTreeSet::new();
+        for item in self.fetch_items(&ItemFilter::default()).iter() {
+            let (series_id, frequency, interval) = match self.series_rule(&item.uuid) {
+                Some(rule) => rule,
+                None => {
+                    push_todo(&mut out, item, None, &stamp);
+                    continue;
+                },
+            };
+            if !exported_series.insert(series_id) {
+                continue;
+            }
+            let occurrences: Vec<Item> = self.series_occurrences(&item.uuid).unwrap_or_default().iter()
+                .filter_map(|uuid| self.fetch_item(uuid))
+                .collect();
+            let (done, open): (Vec<&Item>, Vec<&Item>) = occurrences.iter().partition(|item| item.completion_date.is_some());
+            for item in done.iter() {
+                push_todo(&mut out, item, None, &stamp);
+            }
+            if let Some(first) = open.first() {
+                push_todo(&mut out, first, Some((frequency, interval, open.len() as i64)), &stamp);
+            }
+        }
+        push_line(&mut out, "END:VCALENDAR");
+        out
+    }
+
+    /// Writes `to_ical` to `path`.
+    pub fn export_ical<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        breadcrumbs::leave("list.export_ical");
+        let mut file = File::create(path)?;
+        file.write_all(self.to_ical().as_bytes())
+    }
+
+    /// Adds the VTODOs in `bytes` as new items, creating labels for categories not in the
+    /// store. Daily and weekly rules become recurring series; other rules are dropped.
+    /// Returns the uuids of the new items, or an error if `bytes` is not an iCalendar file.
+    pub fn import_ical(&self, bytes: &[u8]) -> io::Result<Vec<String>> {
+        breadcrumbs::leave("list.import_ical");
+        let text = str::from_utf8(bytes).map_err(|_| invalid_data("not UTF-8"))?;
+        let text = text.replace("\r\n ", "").replace("\r\n\t", "").replace("\n ", "").replace("\n\t", "");
+        if !text.lines().any(|line| line.trim().eq_ignore_ascii_case("BEGIN:VCALENDAR")) {
+            return Err(invalid_data("not an iCalendar file"));
+        }
+
+        let store = self.get_store();
+        let tx = store.begin_transaction().map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        let mut uuids = vec![];
+        let mut todo: Option<Todo> = None;
+        // how deep inside components nested in the VTODO, e.g. VALARM
+        let mut nested = 0;
+        for line in text.lines() {
+            let (name, value) = match split_line(line.trim_end_matches('\r')) {
+                Some(property) => property,
+                None => continue,
+            };
+            let value_upper = value.trim().to_uppercase();
+            match (name.as_str(), todo.is_some()) {
+                ("BEGIN", false) if value_upper == "VTODO" => todo = Some(Todo::default()),
+                ("BEGIN", true) => nested += 1,
+                ("END", true) if nested > 0 => nested -= 1,
+                ("END", true) if value_upper == "VTODO" => {
+                    uuids.extend(self.import_todo(todo.take().unwrap()));
+                },
+                (_, true) if nested == 0 => {
+                    let todo = todo.as_mut().unwrap();
+                    match name.as_str() {
+                        "SUMMARY" => todo.summary = Some(unescape(value)),
+                        "DUE" => todo.due_date = parse_date(value),
+                        "COMPLETED" => todo.completion_date = parse_date(value),
+                        "STATUS" => todo.completed = value_upper == "COMPLETED",
+                        "CATEGORIES" => todo.categories.extend(unescape_parts(value, true)),
+                        "RRULE" => todo.rule = Some(value.to_string()),
+                        _ => {},
+                    }
+                },
+                _ => {},
+            }
+        }
+        let _ = tx.commit();
+        Ok(uuids)
+    }
+
+    fn import_todo(&self, todo: Todo) -> Vec<String> {
+        let name = match todo.summary {
+            Some(ref summary) if !summary.trim().is_empty() => summary.clone(),
+            _ => return vec![],
+        };
+        let completion_date = match todo.completion_date {
+            Some(date) => Some(date),
+            None if todo.completed => Some(self.now()),
+            None => None,
+        };
+        let mut labels: Vec<Arc<Label>> = vec![];
+        for category in todo.categories.iter().map(|category| category.trim()).filter(|category| !category.is_empty()) {
+            let label = self.fetch_label(&category.to_string())
+                .or_else(|| self.create_label(category.to_string(), IMPORTED_LABEL_COLOR.to_string()));
+            if let Some(label) = label.map(|label| self.share_label(&label)) {
+                if !labels.iter().any(|existing| existing.name == label.name) {
+                    labels.push(label);
+                }
+            }
+        }
+        let item = Item {
+            uuid: "".to_string(),
+            name: name,
+            due_date: todo.due_date,
+            completion_date: completion_date,
+            labels: labels,
+        };
+        let rule = match (todo.rule, todo.due_date) {
+            (Some(ref rule), Some(due_date)) => parse_rule(rule, due_date),
+            _ => None,
+        };
+        match rule {
+            Some((frequency, interval, count)) => self.create_recurring_item(&item, frequency, interval, count).unwrap_or_default(),
+            None => vec![self.create_item(&item)],
+        }
+    }
+}
+
+/// Returns false and fills in `error` if the file could not be written.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_export_ical(manager: *const Arc<ListManager>, path: *const c_char, error: *mut ExternError) -> bool {
+    ffi_lock!();
+    ffi_guard!(error, {
+        let manager = ffi_ref!("list_manager_export_ical", manager, false, error);
+        match manager.export_ical(c_char_to_string(path)) {
+            Ok(()) => true,
+            Err(e) => {
+                log_error!("failed to export ical {:?}", e);
+                set_extern_error(error, ErrorCategory::NeedsUserInput, e.to_string());
+                false
+            },
+        }
+    })
+}
+
+/// Returns the uuids of the new items as a JSON array, or null, filling in `error`, if the
+/// bytes are not an iCalendar file.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_import_ical(manager: *const Arc<ListManager>, bytes: *const u8, len: size_t, error: *mut ExternError) -> *mut c_char {
+    ffi_lock!();
+    ffi_guard!(error, {
+        let manager = ffi_ref!("list_manager_import_ical", manager, ptr::null_mut(), error);
+        let bytes = if len == 0 {
+            &[][..]
+        } else {
+            slice::from_raw_parts(ffi_ref!("list_manager_import_ical", bytes, ptr::null_mut(), error), len as usize)
+        };
+        match manager.import_ical(bytes) {
+            Ok(uuids) => string_to_c_char(serde_json::to_string(&uuids).unwrap()),
+            Err(e) => {
+                log_error!("failed to import ical {:?}", e);
+                set_extern_error(error, ErrorCategory::NeedsUserInput, e.to_string());
+                ptr::null_mut()
+            },
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use time::Timespec;
+
+    use filters::ItemFilter;
+    use items::Item;
+    use recurrence::Frequency;
+    use store::Store;
+    use ListManager;
+
+    const NOW: i64 = 1510315200;
+
+    fn manager() -> ListManager {
+        let manager = ListManager::new(Arc::new(Store::new(None)));
+        manager.freeze_clock(Some(Timespec::new(NOW, 0)));
+        manager
+    }
+
+    fn item(name: &str, due_date: Option<i64>) -> Item {
+        let mut item = Item::named(name);
+        item.due_date = due_date.map(|sec| Timespec::new(sec, 0));
+        item
+    }
+
+    fn items(manager: &ListManager) -> Vec<(String, Option<i64>, Option<i64>, Vec<String>)> {
+        let mut items: Vec<_> = manager.fetch_items(&ItemFilter::default()).into_iter().map(|item| {
+            (item.name.clone(),
+             item.due_date.map(|date| date.sec),
+             item.completion_date.map(|date| date.sec),
+             item.labels.iter().map(|label| label.name.clone()).collect())
+        }).collect();
+        items.sort();
+        items
+    }
+
+    #[test]
+    fn test_ical_round_trip() {
+        let phone = manager();
+        let home = phone.create_label("home".to_string(), "#00ff00".to_string()).unwrap();
+        let mut shopping = item("Buy milk, eggs; bread", Some(NOW));
+        shopping.labels = vec![Arc::new(home)];
+        phone.create_item(&shopping);
+        let mut call = item("Call\nmum", None);
+        call.completion_date = Some(Timespec::new(NOW, 0));
+        phone.create_item(&call);
+        let plants = phone.create_recurring_item(&item("Water plants", Some(NOW)), Frequency::Weekly, 1, 3).unwrap();
+        let mut first = phone.fetch_item(&plants[0]).unwrap();
+        first.completion_date = Some(Timespec::new(NOW, 0));
+        phone.update_item(&first, vec![]);
+
+        let ical = phone.to_ical();
+        assert!(ical.starts_with("BEGIN:VCALENDAR\r\nVERSION:2.0\r\n"));
+        assert!(ical.ends_with("END:VCALENDAR\r\n"));
+        assert_eq!(ical.matches("BEGIN:VTODO").count(), 4);
+        assert!(ical.contains("SUMMARY:Buy milk\\, eggs\\; bread\r\nDUE:20171110T120000Z\r\nSTATUS:NEEDS-ACTION\r\nCATEGORIES:home\r\n"));
+        assert!(ical.contains("SUMMARY:Call\\nmum\r\nSTATUS:COMPLETED\r\nCOMPLETED:20171110T120000Z\r\n"));
+        assert!(ical.contains("DUE:20171117T120000Z\r\nSTATUS:NEEDS-ACTION\r\nRRULE:FREQ=WEEKLY;INTERVAL=1;COUNT=2\r\n"));
+
+        let laptop = manager();
+        assert_eq!(laptop.import_ical(ical.as_bytes()).unwrap().len(), 5);
+        assert_eq!(items(&laptop), items(&phone));
+        assert_eq!(laptop.fetch_label(&"home".to_string()).unwrap().color, super::IMPORTED_LABEL_COLOR);
+    }
+
+    #[test]
+    fn test_import_from_other_apps() {
+        let manager = manager();
+        let ical = "BEGIN:VCALENDAR\r\n\
+                    PRODID:-//Apple Inc.//Reminders//EN\r\n\
+                    BEGIN:VTODO\r\n\
+                    UID:1\r\n\
+                    SUMMARY:Renew passport befo\r\n re the trip\r\n\
+                    DUE;VALUE=DATE:20171110\r\n\
+                    CATEGORIES:Errands\\, misc,Travel\r\n\
+                    RRULE:FREQ=DAILY;UNTIL=20171112T000000Z\r\n\
+                    BEGIN:VALARM\r\n\
+                    ACTION:DISPLAY\r\n\
+                    SUMMARY:Alarm\r\n\
+                    END:VALARM\r\n\
+                    END:VTODO\r\n\
+                    BEGIN:VTODO\r\n\
+                    SUMMARY:Pay rent\r\n\
+                    DUE;TZID=Europe/Berlin:20171201T090000\r\n\
+                    RRULE:FREQ=MONTHLY\r\n\
+                    STATUS:COMPLETED\r\n\
+                    END:VTODO\r\n\
+                    BEGIN:VTODO\r\n\
+                    DESCRIPTION:no summary\r\n\
+                    END:VTODO\r\n\
+                    END:VCALENDAR\r\n";
+        assert_eq!(manager.import_ical(ical.as_bytes()).unwrap().len(), 4);
+        let labels = vec!["Errands, misc".to_string(), "Travel".to_string()];
+        assert_eq!(items(&manager), vec![
+            ("Pay rent".to_string(), Some(1512118800), Some(NOW), vec![]),
+            ("Renew passport before the trip".to_string(), Some(1510272000), None, labels.clone()),
+            ("Renew passport before the trip".to_string(), Some(1510358400), None, labels.clone()),
+            ("Renew passport before the trip".to_string(), Some(1510444800), None, labels),
+        ]);
+
+        assert!(manager.import_ical(b"not a calendar").is_err());
+        assert!(manager.import_ical(&[0xff, 0xfe]).is_err());
+    }
+}
@@ -0,0 +1,123 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+use std::cell::Cell;
+use std::sync::atomic::{
+    AtomicBool,
+    Ordering,
+};
+
+use rusqlite;
+use rusqlite::Connection;
+use uuid::Uuid;
+
+static DETERMINISTIC_IDS: AtomicBool = AtomicBool::new(false);
+
+/// Makes list managers opened from now on number their ids 1, 2, 3... instead of picking
+/// random ones, so that test output is identical on every run. A manager reopening a store
+/// carries on after the ids already in it. Never use this with real data: ids from
+/// different stores collide.
+pub fn set_deterministic_ids(enabled: bool) {
+    DETERMINISTIC_IDS.store(enabled, Ordering::SeqCst);
+}
+
+/// Hands out the uuids of new items.
+#[derive(Debug)]
+pub struct IdGenerator {
+    sequence: Option<Cell<u64>>,
+}
+
+impl IdGenerator {
+    /// Random, unless deterministic ids were switched on.
+    pub fn new() -> IdGenerator {
+        if DETERMINISTIC_IDS.load(Ordering::SeqCst) {
+            IdGenerator::deterministic()
+        } else {
+            IdGenerator { sequence: None }
+        }
+    }
+
+    pub fn deterministic() -> IdGenerator {
+        IdGenerator { sequence: Some(Cell::new(0)) }
+    }
+
+    /// Carries a deterministic sequence on after the ids of items, series, tombstones and
+    /// trashed items already in `conn`, so reopening a store does not hand them out again.
+    pub fn resume(&self, conn: &Connection) -> rusqlite::Result<()> {
+        let sequence = match self.sequence {
+            Some(ref sequence) => sequence,
+            None => return Ok(()),
+        };
+        // same length lower case hex, so the greatest sorts last
+        let sql = r#"SELECT MAX(id) FROM (
+                SELECT uuid AS id FROM items
+                UNION ALL SELECT id FROM recurring_series
+                UNION ALL SELECT id FROM tombstones WHERE kind = 'item'
+                UNION ALL SELECT uuid FROM trashed_items
+            ) WHERE length(id) = 32 AND id LIKE '0000000000000000%'"#;
+        let last: Option<String> = conn.query_row(sql, &[], |row| row.get(0))?;
+        if let Some(last) = last.and_then(|last| u64::from_str_radix(&last[16..], 16).ok()) {
+            sequence.set(sequence.get().max(last));
+        }
+        Ok(())
+    }
+
+    /// A uuid in simple form: 32 lower case hex digits.
+    pub fn next_uuid(&self) -> String {
+        match self.sequence {
+            Some(ref sequence) => {
+                let next = sequence.get() + 1;
+                sequence.set(next);
+                format!("{:032x}", next)
+            },
+            None => Uuid::new_v4().simple().to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use super::IdGenerator;
+
+    use store::Store;
+    use ListManager;
+
+    #[test]
+    fn test_deterministic_ids() {
+        let ids = IdGenerator::deterministic();
+        assert_eq!(ids.next_uuid(), "00000000000000000000000000000001");
+        assert_eq!(ids.next_uuid(), "00000000000000000000000000000002");
+        // every generator starts again from one
+        assert_eq!(IdGenerator::deterministic().next_uuid(), "00000000000000000000000000000001");
+
+        let random = IdGenerator { sequence: None };
+        let uuid = random.next_uuid();
+        assert_eq!(uuid.len(), 32);
+        assert!(uuid != random.next_uuid());
+    }
+
+    #[test]
+    fn test_deterministic_ids_resume_after_a_reopened_store() {
+        let store = Arc::new(Store::new(None));
+        ListManager::new(store.clone());
+        let ids = IdGenerator::deterministic();
+        ids.resume(&store.get_conn()).unwrap();
+        let uuid = ids.next_uuid();
+        assert_eq!(uuid, "00000000000000000000000000000001");
+        store.get_conn().execute("INSERT INTO items (uuid, name) VALUES (?, 'renew passport')", &[&uuid]).unwrap();
+        store.get_conn().execute("INSERT INTO items (uuid, name) VALUES ('ffffffffffffffffffffffffffffffff', 'random')", &[]).unwrap();
+
+        let reopened = IdGenerator::deterministic();
+        reopened.resume(&store.get_conn()).unwrap();
+        assert_eq!(reopened.next_uuid(), "00000000000000000000000000000002");
+    }
+}
@@ -0,0 +1,426 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! Before importing a CSV or JSON file the user is shown which column becomes which item
+//! field. The proposal is worked out here from the column names and a sample of the rows,
+//! so every platform's mapping screen starts from the same guesses.
+
+use std::os::raw::c_char;
+use std::ptr;
+
+use serde_json;
+use serde_json::Value;
+
+use ffi_utils::strings::{
+    string_to_c_char,
+    c_char_to_string,
+};
+
+/// How many rows after the header are looked at.
+pub const SAMPLE_ROWS: usize = 50;
+/// How many values of each column are shown with the proposal.
+const SHOWN_SAMPLES: usize = 3;
+/// The share of a column's values that must read as dates or booleans for it to be taken
+/// for one.
+const TYPE_THRESHOLD: f64 = 0.8;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImportFormat {
+    /// A header row naming the columns, separated by commas, semicolons or tabs.
+    Csv = 0,
+    /// An array of objects, or an object with such an array under `items` as exported.
+    Json = 1,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportField {
+    Name,
+    DueDate,
+    CompletionDate,
+    /// A yes or no for whether the item is done, without a date.
+    Completed,
+    Labels,
+    Ignore,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DateFormat {
+    /// `2017-11-10T12:00:00Z`, with or without seconds and zone.
+    Iso8601,
+    /// `2017-11-10`
+    YearMonthDay,
+    /// `11/10/2017`
+    MonthDayYear,
+    /// `10/11/2017`
+    DayMonthYear,
+    /// `10.11.2017`
+    DayMonthYearDots,
+    /// Seconds since 1970.
+    UnixSeconds,
+}
+
+const DATE_FORMATS: [DateFormat; 6] = [
+    DateFormat::Iso8601,
+    DateFormat::YearMonthDay,
+    DateFormat::MonthDayYear,
+    DateFormat::DayMonthYear,
+    DateFormat::DayMonthYearDots,
+    DateFormat::UnixSeconds,
+];
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ColumnMapping {
+    pub column: String,
+    pub field: ImportField,
+    /// Only for date fields.
+    pub date_format: Option<DateFormat>,
+    /// From 0, a guess, to 1, where both the column name and every sampled value agree.
+    pub confidence: f64,
+    pub samples: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ImportPreview {
+    pub sampled_rows: usize,
+    pub columns: Vec<ColumnMapping>,
+}
+
+const NAME_HEADERS: [&'static str; 6] = ["name", "title", "task", "todo", "item", "subject"];
+const DUE_DATE_HEADERS: [&'static str; 6] = ["due", "duedate", "dueon", "dueby", "deadline", "date"];
+const COMPLETION_DATE_HEADERS: [&'static str; 7] = ["completiondate", "completed", "completedat", "completedon", "donedate", "doneat", "finished"];
+const COMPLETED_HEADERS: [&'static str; 6] = ["done", "complete", "completed", "iscompleted", "status", "checked"];
+const LABELS_HEADERS: [&'static str; 9] = ["labels", "label", "tags", "tag", "list", "lists", "category", "categories", "project"];
+
+fn normalize_header(header: &str) -> String {
+    header.chars().filter(|c| c.is_alphanumeric()).flat_map(|c| c.to_lowercase()).collect()
+}
+
+fn number(part: &str, digits: usize, min: u32, max: u32) -> bool {
+    if part.is_empty() || part.len() > digits || !part.chars().all(|c| c.is_ascii_digit()) {
+        return false;
+    }
+    let value: u32 = part.parse().unwrap();
+    value >= min && value <= max
+}
+
+/// Whether `value` is a date as day, month and year in the given order of `separator`
+/// separated parts.
+fn is_date(value: &str, separator: char, order: [usize; 3]) -> bool {
+    let parts: Vec<&str> = value.split(separator).collect();
+    parts.len() == 3
+        && number(parts[order[0]], 2, 1, 31)
+        && number(parts[order[1]], 2, 1, 12)
+        && parts[order[2]].len() == 4 && number(parts[order[2]], 4, 1900, 2999)
+}
+
+fn matches_date_format(value: &str, format: DateFormat) -> bool {
+    match format {
+        DateFormat::Iso8601 => {
+            value.len() > 10 && value.is_char_boundary(10) && is_date(&value[..10], '-', [2, 1, 0])
+                && value[10..].starts_with('T')
+                && value[11..].chars().all(|c| c.is_ascii_digit() || ":.+-Z".contains(c))
+        },
+        DateFormat::YearMonthDay => value.len() == 10 && is_date(value, '-', [2, 1, 0]),
+        DateFormat::MonthDayYear => is_date(value, '/', [1, 0, 2]),
+        DateFormat::DayMonthYear => is_date(value, '/', [0, 1, 2]),
+        DateFormat::DayMonthYearDots => is_date(value, '.', [0, 1, 2]),
+        // 2000 to 2100
+        DateFormat::UnixSeconds => value.parse::<i64>().map(|seconds| seconds >= 946684800 && seconds < 4102444800).unwrap_or(false),
+    }
+}
+
+fn is_boolean(value: &str) -> bool {
+    match value.to_lowercase().as_str() {
+        "true" | "false" | "yes" | "no" | "y" | "n" | "x" | "1" | "0" | "done" | "todo" => true,
+        _ => false,
+    }
+}
+
+/// The date format most of `values` are in, the share of them it reads, and whether another
+/// format reads as many, as with `01/02/2018`.
+fn infer_date_format(values: &[&str]) -> Option<(DateFormat, f64, bool)> {
+    let counts: Vec<(DateFormat, usize)> = DATE_FORMATS.iter().map(|&format| {
+        (format, values.iter().filter(|value| matches_date_format(value, format)).count())
+    }).collect();
+    let best = counts.iter().map(|&(_, count)| count).max().unwrap_or(0);
+    if best == 0 {
+        return None;
+    }
+    let format = counts.iter().find(|&&(_, count)| count == best).unwrap().0;
+    let share = best as f64 / values.len() as f64;
+    let ambiguous = counts.iter().filter(|&&(_, count)| count == best).count() > 1;
+    Some((format, share, ambiguous))
+}
+
+fn infer_column(column: &str, values: &[&str]) -> ColumnMapping {
+    let header = normalize_header(column);
+    let named = |headers: &[&str]| headers.contains(&header.as_str());
+    let filled: Vec<&str> = values.iter().map(|value| value.trim()).filter(|value| !value.is_empty()).collect();
+    let samples = filled.iter().take(SHOWN_SAMPLES).map(|value| value.to_string()).collect();
+    let mapping = |field: ImportField, date_format: Option<DateFormat>, confidence: f64| ColumnMapping {
+        column: column.to_string(),
+        field: field,
+        date_format: date_format,
+        confidence: (confidence * 100.0).round() / 100.0,
+        samples: samples,
+    };
+    if filled.is_empty() {
+        return mapping(ImportField::Ignore, None, 0.0);
+    }
+
+    if let Some((format, share, ambiguous)) = infer_date_format(&filled) {
+        if share >= TYPE_THRESHOLD {
+            let score = if ambiguous { share / 4.0 } else { share / 2.0 };
+            if named(&COMPLETION_DATE_HEADERS) {
+                return mapping(ImportField::CompletionDate, Some(format), 0.5 + score);
+            }
+            let hint = if named(&DUE_DATE_HEADERS) { 0.5 } else { 0.0 };
+            return mapping(ImportField::DueDate, Some(format), hint + score);
+        }
+    }
+    let boolean_share = filled.iter().filter(|value| is_boolean(value)).count() as f64 / filled.len() as f64;
+    if boolean_share >= TYPE_THRESHOLD {
+        let hint = if named(&COMPLETED_HEADERS) { 0.5 } else { 0.0 };
+        return mapping(ImportField::Completed, None, hint + boolean_share / 2.0);
+    }
+    if named(&LABELS_HEADERS) {
+        return mapping(ImportField::Labels, None, 1.0);
+    }
+    // an unnamed text column is most likely the name, the earliest if there are several
+    mapping(ImportField::Name, None, if named(&NAME_HEADERS) { 1.0 } else { 0.3 })
+}
+
+/// Splits CSV text into rows, stopping after the header and `SAMPLE_ROWS` rows.
+fn parse_csv(text: &str) -> Vec<Vec<String>> {
+    let text = text.trim_start_matches('\u{feff}');
+    let first_line = text.lines().next().unwrap_or("");
+    let separator = [',', ';', '\t'].iter().cloned().max_by_key(|&c| first_line.matches(c).count()).unwrap();
+    let mut rows = vec![];
+    let mut row = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if quoted {
+            if c == '"' {
+                if chars.peek() == Some(&'"') {
+                    chars.next();
+                    field.push('"');
+                } else {
+                    quoted = false;
+                }
+            } else {
+                field.push(c);
+            }
+        } else if c == '"' && field.is_empty() {
+            quoted = true;
+        } else if c == separator {
+            row.push(field.clone());
+            field.clear();
+        } else if c == '\n' || c == '\r' {
+            if c == '\r' && chars.peek() == Some(&'\n') {
+                chars.next();
+            }
+            row.push(field.clone());
+            field.clear();
+            rows.push(row.clone());
+            row.clear();
+            if rows.len() > SAMPLE_ROWS {
+                break;
+            }
+        } else {
+            field.push(c);
+        }
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    rows.retain(|row| row.iter().any(|field| !field.is_empty()));
+    rows
+}
+
+/// Label lists as exported, `[{"name": ..., "color": ...}]`, read as their names.
+fn json_text(value: &Value) -> String {
+    match *value {
+        Value::Null => String::new(),
+        Value::String(ref text) => text.clone(),
+        Value::Array(ref values) => values.iter().map(json_text).collect::<Vec<String>>().join(";"),
+        Value::Object(ref object) => match object.get("name") {
+            Some(&Value::String(ref name)) => name.clone(),
+            _ => value.to_string(),
+        },
+        ref other => other.to_string(),
+    }
+}
+
+/// The columns, in order of first appearance, and the sampled rows.
+fn parse_json(text: &str) -> Option<(Vec<String>, Vec<Vec<String>>)> {
+    let value: Value = serde_json::from_str(text).ok()?;
+    let objects = match value {
+        Value::Array(objects) => objects,
+        Value::Object(mut export) => match export.remove("items") {
+            Some(Value::Array(objects)) => objects,
+            _ => return None,
+        },
+        _ => return None,
+    };
+    let objects: Vec<_> = objects.into_iter().take(SAMPLE_ROWS).filter_map(|object| match object {
+        Value::Object(object) => Some(object),
+        _ => None,
+    }).collect();
+    let mut columns: Vec<String> = vec![];
+    for object in objects.iter() {
+        for key in object.keys() {
+            if !columns.contains(key) {
+                columns.push(key.clone());
+            }
+        }
+    }
+    let rows = objects.iter().map(|object| {
+        columns.iter().map(|column| object.get(column).map(json_text).unwrap_or_default()).collect()
+    }).collect();
+    Some((columns, rows))
+}
+
+/// Proposes a mapping of the file's columns to item fields, or returns `None` if it has no
+/// columns. Each field is proposed for at most one column, the one it fits best; the other
+/// columns are proposed to be ignored.
+pub fn preview_import(text: &str, format: ImportFormat) -> Option<ImportPreview> {
+    let (columns, rows) = match format {
+        ImportFormat::Csv => {
+            let mut rows = parse_csv(text);
+            if rows.is_empty() {
+                return None;
+            }
+            let header = rows.remove(0);
+            (header, rows)
+        },
+        ImportFormat::Json => parse_json(text)?,
+    };
+    if columns.is_empty() {
+        return None;
+    }
+    let mut mappings: Vec<ColumnMapping> = columns.iter().enumerate().map(|(index, column)| {
+        let values: Vec<&str> = rows.iter().map(|row| row.get(index).map(|value| value.as_str()).unwrap_or("")).collect();
+        infer_column(column, &values)
+    }).collect();
+    for index in 0..mappings.len() {
+        let field = mappings[index].field;
+        let better = mappings.iter().enumerate().any(|(other, mapping)| {
+            other != index && mapping.field == field
+                && (mapping.confidence > mappings[index].confidence || (mapping.confidence == mappings[index].confidence && other < index))
+        });
+        if field != ImportField::Ignore && better {
+            let mapping = &mut mappings[index];
+            mapping.field = ImportField::Ignore;
+            mapping.date_format = None;
+            mapping.confidence = 0.0;
+        }
+    }
+    Some(ImportPreview {
+        sampled_rows: rows.len(),
+        columns: mappings,
+    })
+}
+
+/// Returns the proposed mapping as JSON, or null if the text is not in `format`.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn import_preview(text: *const c_char, format: ImportFormat) -> *mut c_char {
+    ffi_guard!({
+        match preview_import(&c_char_to_string(text), format) {
+            Some(preview) => string_to_c_char(serde_json::to_string(&preview).unwrap()),
+            None => ptr::null_mut(),
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        preview_import,
+        DateFormat,
+        ImportField,
+        ImportFormat,
+    };
+
+    fn fields(csv: &str) -> Vec<(ImportField, Option<DateFormat>)> {
+        preview_import(csv, ImportFormat::Csv).unwrap().columns.iter().map(|column| (column.field, column.date_format)).collect()
+    }
+
+    #[test]
+    fn test_preview_exported_csv() {
+        let csv = "name,due_date,completion_date,labels\r\n\
+                   \"Tag \"\"v2, final\"\"\",2017-11-10T12:00:00Z,,release\r\n\
+                   Write notes,2017-11-10T12:00:00Z,2017-11-10T12:00:00Z,release\r\n";
+        let preview = preview_import(csv, ImportFormat::Csv).unwrap();
+        assert_eq!(preview.sampled_rows, 2);
+        assert_eq!(preview.columns[0].samples, vec!["Tag \"v2, final\"", "Write notes"]);
+        assert_eq!(fields(csv), vec![
+            (ImportField::Name, None),
+            (ImportField::DueDate, Some(DateFormat::Iso8601)),
+            (ImportField::CompletionDate, Some(DateFormat::Iso8601)),
+            (ImportField::Labels, None),
+        ]);
+        assert!(preview.columns.iter().all(|column| column.confidence == 1.0));
+    }
+
+    #[test]
+    fn test_infer_unnamed_columns() {
+        let csv = "Aufgabe;Wann;Erledigt;Notiz\n\
+                   Milch kaufen;24.12.2017;ja;\n\
+                   Steuern;31.05.2018;x;vor Juni\n";
+        let preview = preview_import(csv, ImportFormat::Csv).unwrap();
+        assert_eq!(fields(csv), vec![
+            (ImportField::Name, None),
+            (ImportField::DueDate, Some(DateFormat::DayMonthYearDots)),
+            (ImportField::Ignore, None),
+            (ImportField::Ignore, None),
+        ]);
+        // right on values alone, but nothing in the column names backs it up
+        assert_eq!(preview.columns[1].confidence, 0.5);
+    }
+
+    #[test]
+    fn test_ambiguous_dates() {
+        let csv = "task,due\nmilk,01/02/2018\nbread,03/04/2018\n";
+        let preview = preview_import(csv, ImportFormat::Csv).unwrap();
+        assert_eq!(preview.columns[1].date_format, Some(DateFormat::MonthDayYear));
+        assert_eq!(preview.columns[1].confidence, 0.75);
+
+        let csv = "task,due\nmilk,01/02/2018\nbread,25/04/2018\n";
+        assert_eq!(fields(csv)[1], (ImportField::DueDate, Some(DateFormat::DayMonthYear)));
+    }
+
+    #[test]
+    fn test_preview_json() {
+        let json = r##"{"name": "#release", "items": [
+            {"uuid": "a", "name": "milk", "due_date": 1510315200, "completion_date": null, "labels": [{"name": "home", "color": "#000000"}]},
+            {"uuid": "b", "name": "bread", "due_date": null, "completion_date": null, "labels": []}
+        ]}"##;
+        let preview = preview_import(json, ImportFormat::Json).unwrap();
+        let columns: Vec<(&str, ImportField)> = preview.columns.iter().map(|column| (column.column.as_str(), column.field)).collect();
+        assert_eq!(columns, vec![
+            ("completion_date", ImportField::Ignore),
+            ("due_date", ImportField::DueDate),
+            ("labels", ImportField::Labels),
+            ("name", ImportField::Name),
+            ("uuid", ImportField::Ignore),
+        ]);
+        assert_eq!(preview.columns[1].date_format, Some(DateFormat::UnixSeconds));
+        assert_eq!(preview.columns[2].samples, vec!["home"]);
+
+        assert_eq!(preview_import("[1, 2]", ImportFormat::Json), None);
+        assert_eq!(preview_import("", ImportFormat::Csv), None);
+    }
+}
@@ -0,0 +1,402 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! Imports the JSON exports of other task apps. Their formats change between versions, so
+//! they are read field by field rather than into fixed structs, and a task that cannot be
+//! read is reported without stopping the import.
+//!
+//! Todoist: a backup `{"items", "projects", "labels"}` or the REST API's array of tasks.
+//! Projects and labels both become labels. Google Tasks: the Takeout `Tasks.json`, whose
+//! task lists become labels.
+
+use std::collections::BTreeMap;
+use std::os::raw::c_char;
+use std::sync::Arc;
+use std::ptr;
+
+use rusqlite;
+use serde_json;
+use serde_json::Value;
+use time::{
+    strptime,
+    Duration,
+    Timespec,
+};
+
+use ffi_utils::breadcrumbs;
+use ffi_utils::errors::ExternError;
+use ffi_utils::strings::{
+    string_to_c_char,
+    c_char_to_string,
+};
+use ics::IMPORTED_LABEL_COLOR;
+use items::Item;
+use labels::Label;
+use store::errors::set_sqlite_error;
+use ListManager;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImportSource {
+    Todoist = 0,
+    GoogleTasks = 1,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct ImportReport {
+    /// The uuids of the new items.
+    pub created: Vec<String>,
+    /// Tasks left out on purpose: deleted ones and ones without a name.
+    pub skipped: i64,
+    /// Why each task that could not be read was left out, or why nothing was imported.
+    pub errors: Vec<String>,
+}
+
+/// A task as read from a dump, before its labels are looked up or created.
+#[derive(Debug, Default, PartialEq)]
+struct Task {
+    name: String,
+    due_date: Option<Timespec>,
+    completed: bool,
+    completion_date: Option<Timespec>,
+    labels: Vec<String>,
+}
+
+enum Parsed {
+    Task(Task),
+    Skipped,
+    Error(String),
+}
+
+/// `2017-11-10`, or `2017-11-10T12:00:00` with optional fractions and a `Z` or `+01:00`
+/// offset. Times without an offset are read as UTC.
+fn parse_timestamp(value: &str) -> Option<Timespec> {
+    if value.len() == 10 {
+        return strptime(value, "%Y-%m-%d").ok().map(|tm| tm.to_timespec());
+    }
+    if value.len() < 19 || !value.is_char_boundary(19) {
+        return None;
+    }
+    let time = strptime(&value[..19], "%Y-%m-%dT%H:%M:%S").ok()?.to_timespec();
+    let rest = value[19..].trim_start_matches(|c: char| c == '.' || c.is_ascii_digit());
+    let offset = match rest {
+        "" | "Z" | "z" => 0,
+        _ if rest.len() == 6 && (rest.starts_with('+') || rest.starts_with('-')) => {
+            let hours: i64 = rest[1..3].parse().ok()?;
+            let minutes: i64 = rest[4..6].parse().ok()?;
+            let offset = hours * 3600 + minutes * 60;
+            if rest.starts_with('-') { -offset } else { offset }
+        },
+        _ => return None,
+    };
+    Some(time - Duration::seconds(offset))
+}
+
+/// Ids are numbers in older Todoist exports and strings in newer ones.
+fn id_of(value: &Value) -> Option<String> {
+    match *value {
+        Value::String(ref id) => Some(id.clone()),
+        Value::Number(ref id) => Some(id.to_string()),
+        _ => None,
+    }
+}
+
+/// `true`, or 1 in older Todoist exports.
+fn is_set(value: &Value) -> bool {
+    value.as_bool().or_else(|| value.as_i64().map(|value| value != 0)).unwrap_or(false)
+}
+
+/// Names by id of the `projects` or `labels` in a Todoist backup.
+fn todoist_names(dump: &Value, key: &str) -> BTreeMap<String, String> {
+    let mut names = BTreeMap::new();
+    for entry in dump[key].as_array().map(|entries| entries.as_slice()).unwrap_or(&[]) {
+        if let (Some(id), Some(name)) = (id_of(&entry["id"]), entry["name"].as_str()) {
+            names.insert(id, name.to_string());
+        }
+    }
+    names
+}
+
+fn parse_todoist_task(task: &Value, projects: &BTreeMap<String, String>, labels: &BTreeMap<String, String>) -> Parsed {
+    if is_set(&task["is_deleted"]) {
+        return Parsed::Skipped;
+    }
+    let name = match task["content"].as_str() {
+        Some(name) if !name.trim().is_empty() => name.trim().to_string(),
+        Some(_) => return Parsed::Skipped,
+        None => return Parsed::Error(format!("task {} has no content", task["id"])),
+    };
+    let mut parsed = Task { name: name, ..Task::default() };
+    let due = &task["due"];
+    if let Some(due) = due["datetime"].as_str().or_else(|| due["date"].as_str()).or_else(|| task["due_date_utc"].as_str()) {
+        match parse_timestamp(due) {
+            Some(date) => parsed.due_date = Some(date),
+            None => return Parsed::Error(format!("{:?} has an unreadable due date {:?}", parsed.name, due)),
+        }
+    }
+    parsed.completed = is_set(&task["checked"]) || is_set(&task["is_completed"]);
+    if parsed.completed {
+        parsed.completion_date = task["completed_at"].as_str()
+            .or_else(|| task["date_completed"].as_str())
+            .and_then(parse_timestamp);
+    }
+    if let Some(project) = id_of(&task["project_id"]).and_then(|id| projects.get(&id)) {
+        parsed.labels.push(project.clone());
+    }
+    for label in task["labels"].as_array().map(|labels| labels.as_slice()).unwrap_or(&[]) {
+        // names in newer exports, ids in older ones
+        let name = match *label {
+            Value::String(ref name) => Some(name.clone()),
+            ref id => id_of(id).and_then(|id| labels.get(&id).cloned()),
+        };
+        if let Some(name) = name {
+            parsed.labels.push(name);
+        }
+    }
+    Parsed::Task(parsed)
+}
+
+fn parse_todoist(dump: &Value) -> Result<Vec<Parsed>, String> {
+    let tasks = match *dump {
+        Value::Array(ref tasks) => tasks,
+        Value::Object(_) => dump["items"].as_array().ok_or("not a Todoist export")?,
+        _ => return Err("not a Todoist export".to_string()),
+    };
+    let projects = todoist_names(dump, "projects");
+    let labels = todoist_names(dump, "labels");
+    Ok(tasks.iter().map(|task| parse_todoist_task(task, &projects, &labels)).collect())
+}
+
+fn parse_google_task(task: &Value, list: Option<&str>) -> Parsed {
+    if is_set(&task["deleted"]) || (is_set(&task["hidden"]) && task["status"] != "completed") {
+        return Parsed::Skipped;
+    }
+    let name = match task["title"].as_str() {
+        Some(name) if !name.trim().is_empty() => name.trim().to_string(),
+        // Google Tasks keeps the empty tasks left behind by tapping "add a task"
+        Some(_) => return Parsed::Skipped,
+        None => return Parsed::Error(format!("task {} has no title", task["id"])),
+    };
+    let mut parsed = Task { name: name, ..Task::default() };
+    if let Some(due) = task["due"].as_str() {
+        match parse_timestamp(due) {
+            Some(date) => parsed.due_date = Some(date),
+            None => return Parsed::Error(format!("{:?} has an unreadable due date {:?}", parsed.name, due)),
+        }
+    }
+    parsed.completed = task["status"] == "completed";
+    if parsed.completed {
+        parsed.completion_date = task["completed"].as_str().and_then(parse_timestamp);
+    }
+    if let Some(list) = list {
+        parsed.labels.push(list.to_string());
+    }
+    Parsed::Task(parsed)
+}
+
+fn parse_google_tasks(dump: &Value) -> Result<Vec<Parsed>, String> {
+    let lists = dump["items"].as_array().ok_or("not a Google Tasks export")?;
+    let mut parsed = vec![];
+    for list in lists.iter() {
+        let title = list["title"].as_str().map(|title| title.trim()).filter(|title| !title.is_empty());
+        for task in list["items"].as_array().map(|tasks| tasks.as_slice()).unwrap_or(&[]) {
+            parsed.push(parse_google_task(task, title));
+        }
+    }
+    Ok(parsed)
+}
+
+impl ListManager {
+    /// Adds the tasks in another app's export as new items, creating labels the store does
+    /// not have yet. Nothing is imported if `text` is not an export from `source`.
+    pub fn import_dump(&self, text: &str, source: ImportSource) -> ImportReport {
+        self.try_import_dump(text, source).unwrap_or_else(|e| {
+            log_error!("failed to import dump {:?}", e);
+            ImportReport { errors: vec![e.to_string()], ..ImportReport::default() }
+        })
+    }
+
+    /// Like `import_dump`, but nothing is imported and the error is returned if any task
+    /// cannot be written. Tasks that cannot be read are still reported in the report.
+    pub fn try_import_dump(&self, text: &str, source: ImportSource) -> rusqlite::Result<ImportReport> {
+        breadcrumbs::leave("list.import_dump");
+        let mut report = ImportReport::default();
+        let parsed = serde_json::from_str::<Value>(text).map_err(|e| e.to_string()).and_then(|dump| {
+            match source {
+                ImportSource::Todoist => parse_todoist(&dump),
+                ImportSource::GoogleTasks => parse_google_tasks(&dump),
+            }
+        });
+        let parsed = match parsed {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                report.errors.push(e);
+                return Ok(report);
+            },
+        };
+
+        let store = self.get_store();
+        let tx = store.begin_transaction()?;
+        for task in parsed {
+            let task = match task {
+                Parsed::Task(task) => task,
+                Parsed::Skipped => {
+                    report.skipped += 1;
+                    continue;
+                },
+                Parsed::Error(e) => {
+                    report.errors.push(e);
+                    continue;
+                },
+            };
+            let mut labels: Vec<Arc<Label>> = vec![];
+            for name in task.labels.iter() {
+                let label = match self.fetch_label(name) {
+                    Some(label) => label,
+                    None => self.try_create_label(name.clone(), IMPORTED_LABEL_COLOR.to_string())?,
+                };
+                let label = self.share_label(&label);
+                if !labels.iter().any(|existing| existing.name == label.name) {
+                    labels.push(label);
+                }
+            }
+            let completion_date = match task.completion_date {
+                Some(date) => Some(date),
+                None if task.completed => Some(self.now()),
+                None => None,
+            };
+            report.created.push(self.try_create_item(&Item {
+                uuid: "".to_string(),
+                name: task.name,
+                due_date: task.due_date,
+                completion_date: completion_date,
+                labels: labels,
+            })?);
+        }
+        tx.commit()?;
+        Ok(report)
+    }
+}
+
+/// Returns the import report as JSON, `{"created": [uuid, ...], "skipped": n, "errors":
+/// [message, ...]}`, or null and fills in `error` if the tasks could not be written, in which
+/// case none are.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_import_dump(manager: *const Arc<ListManager>, text: *const c_char, source: ImportSource, error: *mut ExternError) -> *mut c_char {
+    ffi_lock!();
+    ffi_guard!(error, {
+        let manager = ffi_ref!("list_manager_import_dump", manager, ptr::null_mut(), error);
+        match manager.try_import_dump(&c_char_to_string(text), source) {
+            Ok(report) => string_to_c_char(serde_json::to_string(&report).unwrap()),
+            Err(e) => {
+                log_error!("failed to import dump {:?}", e);
+                set_sqlite_error(error, &e);
+                ptr::null_mut()
+            },
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use time::Timespec;
+
+    use super::{
+        parse_timestamp,
+        ImportSource,
+    };
+
+    use filters::ItemFilter;
+    use store::Store;
+    use ListManager;
+
+    const NOV_10: i64 = 1510272000;
+
+    fn items(manager: &ListManager) -> Vec<(String, Option<i64>, Option<i64>, Vec<String>)> {
+        let mut items: Vec<_> = manager.fetch_items(&ItemFilter::default()).into_iter().map(|item| {
+            let mut labels: Vec<String> = item.labels.iter().map(|label| label.name.clone()).collect();
+            labels.sort();
+            (item.name.clone(), item.due_date.map(|date| date.sec), item.completion_date.map(|date| date.sec), labels)
+        }).collect();
+        items.sort();
+        items
+    }
+
+    #[test]
+    fn test_parse_timestamp() {
+        assert_eq!(parse_timestamp("2017-11-10"), Some(Timespec::new(NOV_10, 0)));
+        assert_eq!(parse_timestamp("2017-11-10T12:00:00"), Some(Timespec::new(NOV_10 + 43200, 0)));
+        assert_eq!(parse_timestamp("2017-11-10T12:00:00.000Z"), Some(Timespec::new(NOV_10 + 43200, 0)));
+        assert_eq!(parse_timestamp("2017-11-10T12:00:00+01:30"), Some(Timespec::new(NOV_10 + 37800, 0)));
+        assert_eq!(parse_timestamp("next tuesday"), None);
+    }
+
+    #[test]
+    fn test_import_todoist() {
+        let manager = ListManager::new(Arc::new(Store::new(None)));
+        manager.freeze_clock(Some(Timespec::new(NOV_10, 0)));
+        let backup = r#"{
+            "projects": [{"id": 1, "name": "Home"}],
+            "labels": [{"id": 7, "name": "errand"}],
+            "items": [
+                {"id": 100, "content": "Buy milk", "project_id": 1, "labels": [7], "checked": 0,
+                 "due": {"date": "2017-11-10", "is_recurring": false}},
+                {"id": 101, "content": "Fix tap", "project_id": 1, "labels": [], "checked": 1},
+                {"id": 102, "content": "Gone", "is_deleted": 1},
+                {"id": 103, "content": "Bad", "due": {"date": "soon"}},
+                {"id": 104}
+            ]
+        }"#;
+        let report = manager.import_dump(backup, ImportSource::Todoist);
+        assert_eq!(report.created.len(), 2);
+        assert_eq!(report.skipped, 1);
+        assert_eq!(report.errors, vec!["\"Bad\" has an unreadable due date \"soon\"", "task 104 has no content"]);
+        assert_eq!(items(&manager), vec![
+            ("Buy milk".to_string(), Some(NOV_10), None, vec!["Home".to_string(), "errand".to_string()]),
+            ("Fix tap".to_string(), None, Some(NOV_10), vec!["Home".to_string()]),
+        ]);
+
+        let rest = r#"[{"id": "2995104339", "content": "Call dentist", "is_completed": false,
+                        "labels": ["phone"], "due": {"date": "2017-11-10", "datetime": "2017-11-10T09:00:00Z"}}]"#;
+        let report = manager.import_dump(rest, ImportSource::Todoist);
+        assert_eq!((report.created.len(), report.skipped, report.errors.len()), (1, 0, 0));
+        assert_eq!(manager.fetch_item(&report.created[0]).unwrap().due_date, Some(Timespec::new(NOV_10 + 32400, 0)));
+
+        let report = manager.import_dump("{\"kind\": \"tasks#taskLists\"}", ImportSource::Todoist);
+        assert_eq!(report.errors, vec!["not a Todoist export"]);
+    }
+
+    #[test]
+    fn test_import_google_tasks() {
+        let manager = ListManager::new(Arc::new(Store::new(None)));
+        let takeout = r#"{"kind": "tasks#taskLists", "items": [
+            {"kind": "tasks#taskList", "id": "a", "title": "Groceries", "items": [
+                {"kind": "tasks#task", "id": "1", "title": "Apples", "status": "needsAction",
+                 "due": "2017-11-10T00:00:00.000Z"},
+                {"kind": "tasks#task", "id": "2", "title": "Pears", "status": "completed",
+                 "completed": "2017-11-10T12:00:00.000Z", "hidden": true},
+                {"kind": "tasks#task", "id": "3", "title": "", "status": "needsAction"},
+                {"kind": "tasks#task", "id": "4", "title": "Plums", "status": "needsAction", "deleted": true}
+            ]}
+        ]}"#;
+        let report = manager.import_dump(takeout, ImportSource::GoogleTasks);
+        assert_eq!((report.created.len(), report.skipped, report.errors.len()), (2, 2, 0));
+        assert_eq!(items(&manager), vec![
+            ("Apples".to_string(), Some(NOV_10), None, vec!["Groceries".to_string()]),
+            ("Pears".to_string(), None, Some(NOV_10 + 43200), vec!["Groceries".to_string()]),
+        ]);
+
+        let report = manager.import_dump("not json", ImportSource::GoogleTasks);
+        assert_eq!(report.created.len(), 0);
+        assert_eq!(report.errors.len(), 1);
+    }
+}
@@ -0,0 +1,225 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! Builds an item with all its fields before writing it once, instead of creating an
+//! empty item and saving it again after every setter.
+
+use std::os::raw::c_char;
+use std::ptr;
+use std::sync::Arc;
+
+use rusqlite;
+
+use dates::date_from_seconds;
+use ffi_utils::errors::{
+    set_extern_error,
+    ErrorCategory,
+    ExternError,
+};
+use ffi_utils::nulls::null_argument;
+use ffi_utils::strings::{
+    c_char_to_string,
+    string_to_c_char,
+};
+use items::{
+    date_set,
+    Item,
+};
+use store::errors::set_sqlite_error;
+use ListManager;
+
+#[derive(Debug)]
+pub struct ItemBuilder {
+    item: Item,
+    /// Looked up when the item is committed.
+    label_names: Vec<String>,
+}
+
+#[derive(Debug)]
+pub enum CommitError {
+    /// There is no label with this name.
+    UnknownLabel(String),
+    Sqlite(rusqlite::Error),
+}
+
+impl ItemBuilder {
+    pub fn new() -> ItemBuilder {
+        ItemBuilder {
+            item: Item {
+                uuid: "".to_string(),
+                name: "".to_string(),
+                due_date: None,
+                completion_date: None,
+                labels: vec![],
+            },
+            label_names: vec![],
+        }
+    }
+
+    /// Creates the item with its labels in one transaction and returns its uuid. Writes
+    /// nothing if a label does not exist.
+    pub fn commit(mut self, manager: &ListManager) -> Result<String, CommitError> {
+        for name in self.label_names.iter() {
+            match manager.fetch_label(name) {
+                Some(label) => self.item.labels.push(manager.share_label(&label)),
+                None => return Err(CommitError::UnknownLabel(name.clone())),
+            }
+        }
+        manager.try_create_item(&self.item).map_err(CommitError::Sqlite)
+    }
+}
+
+impl Default for ItemBuilder {
+    fn default() -> ItemBuilder {
+        ItemBuilder::new()
+    }
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub extern "C" fn item_builder_new() -> *mut ItemBuilder {
+    ffi_guard!({
+        Box::into_raw(Box::new(ItemBuilder::new()))
+    })
+}
+
+/// Releases a builder that was not committed.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn item_builder_destroy(builder: *mut ItemBuilder) {
+    ffi_guard!({
+        if builder.is_null() {
+            return;
+        }
+        let _ = Box::from_raw(builder);
+    })
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn item_builder_set_name(builder: *mut ItemBuilder, name: *const c_char) {
+    ffi_guard!({
+        let builder = ffi_mut!("item_builder_set_name", builder);
+        builder.item.name = c_char_to_string(name);
+    })
+}
+
+/// Like `item_try_set_due_date`.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn item_builder_set_due_date(builder: *mut ItemBuilder, due_date: i64, error: *mut ExternError) -> bool {
+    ffi_guard!(error, {
+        let builder = ffi_mut!("item_builder_set_due_date", builder, false, error);
+        date_set(&mut builder.item.due_date, date_from_seconds(due_date), error)
+    })
+}
+
+/// Like `item_try_set_completion_date`.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn item_builder_set_completion_date(builder: *mut ItemBuilder, completion_date: i64, error: *mut ExternError) -> bool {
+    ffi_guard!(error, {
+        let builder = ffi_mut!("item_builder_set_completion_date", builder, false, error);
+        date_set(&mut builder.item.completion_date, date_from_seconds(completion_date), error)
+    })
+}
+
+/// The label must exist by the time the item is committed.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn item_builder_add_label(builder: *mut ItemBuilder, label_name: *const c_char) {
+    ffi_guard!({
+        let builder = ffi_mut!("item_builder_add_label", builder);
+        let name = c_char_to_string(label_name);
+        if !builder.label_names.contains(&name) {
+            builder.label_names.push(name);
+        }
+    })
+}
+
+/// Writes the item and returns its uuid, or returns null and fills in `error` if a label
+/// does not exist or the item could not be written, in which case nothing is. Consumes the
+/// builder either way.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn item_builder_commit(builder: *mut ItemBuilder, manager: *const Arc<ListManager>, error: *mut ExternError) -> *mut c_char {
+    ffi_lock!();
+    ffi_guard!(error, {
+        if builder.is_null() {
+            null_argument("item_builder_commit", "builder", error);
+            return ptr::null_mut();
+        }
+        let builder = Box::from_raw(builder);
+        let manager = ffi_ref!("item_builder_commit", manager, ptr::null_mut(), error);
+        match builder.commit(manager) {
+            Ok(uuid) => string_to_c_char(uuid),
+            Err(CommitError::UnknownLabel(name)) => {
+                log_error!("failed to commit item, no label {:?}", name);
+                set_extern_error(error, ErrorCategory::Bug, format!("no label named {:?}", name));
+                ptr::null_mut()
+            },
+            Err(CommitError::Sqlite(e)) => {
+                log_error!("failed to commit item {:?}", e);
+                set_sqlite_error(error, &e);
+                ptr::null_mut()
+            },
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::ffi::CString;
+    use std::ptr;
+    use std::sync::Arc;
+
+    use time::Timespec;
+
+    use ffi_utils::errors::{
+        error_message_destroy,
+        ErrorCategory,
+        ExternError,
+    };
+    use ffi_utils::strings::c_char_to_string;
+    use filters::ItemFilter;
+    use store::Store;
+    use ListManager;
+
+    use super::{
+        item_builder_add_label,
+        item_builder_commit,
+        item_builder_new,
+        item_builder_set_due_date,
+        item_builder_set_name,
+    };
+
+    #[test]
+    fn test_item_builder() {
+        let manager = Arc::new(ListManager::new(Arc::new(Store::new(None))));
+        let home = manager.create_label("home".to_string(), "#00ff00".to_string()).unwrap();
+        let name = CString::new("descale the kettle").unwrap();
+        let home_name = CString::new("home").unwrap();
+        let work_name = CString::new("work").unwrap();
+        unsafe {
+            let builder = item_builder_new();
+            item_builder_set_name(builder, name.as_ptr());
+            assert!(item_builder_set_due_date(builder, 1510315200, ptr::null_mut()));
+            assert!(!item_builder_set_due_date(builder, -1, ptr::null_mut()));
+            item_builder_add_label(builder, home_name.as_ptr());
+            let uuid = c_char_to_string(item_builder_commit(builder, &manager, ptr::null_mut()));
+            let item = manager.fetch_item(&uuid).unwrap();
+            assert_eq!(item.name, "descale the kettle");
+            assert_eq!(item.due_date, Some(Timespec::new(1510315200, 0)));
+            assert_eq!(item.labels, vec![Arc::new(home)]);
+
+            let builder = item_builder_new();
+            item_builder_set_name(builder, name.as_ptr());
+            item_builder_add_label(builder, work_name.as_ptr());
+            let mut error = ExternError::default();
+            assert!(item_builder_commit(builder, &manager, &mut error).is_null());
+            assert_eq!(error.category, ErrorCategory::Bug);
+            error_message_destroy(error.message);
+            assert_eq!(manager.fetch_items(&ItemFilter::default()).len(), 1);
+        }
+    }
+}
@@ -0,0 +1,202 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! Items behind integer handles instead of `struct item*`, for hosts such as the JVM that
+//! keep native objects in a `long`. A stale or doubly freed handle fails with
+//! `ErrorCategory::Bug` instead of reading freed memory.
+
+use std::os::raw::c_char;
+use std::ptr;
+use std::sync::Arc;
+
+use serde_json;
+
+use ffi_utils::errors::ExternError;
+use ffi_utils::handles::{
+    set_handle_error,
+    HandleError,
+    HandleMap,
+};
+use ffi_utils::strings::{
+    c_char_to_string,
+    string_to_c_char,
+};
+use items::Item;
+use store::errors::set_sqlite_error;
+use ListManager;
+
+static ITEMS: HandleMap<Item> = HandleMap::new();
+
+/// How many item handles have not been destroyed, for finding leaks.
+pub fn live_item_handles() -> usize {
+    ITEMS.len()
+}
+
+unsafe fn handle_result<T>(result: Result<T, HandleError>, failed: T, error: *mut ExternError) -> T {
+    match result {
+        Ok(value) => value,
+        Err(e) => {
+            log_error!("failed to use item handle {:?}", e);
+            set_handle_error(error, &e);
+            failed
+        },
+    }
+}
+
+/// A handle to a new, unsaved item with no name.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub extern "C" fn item_handle_new() -> u64 {
+    ffi_guard!({
+        ITEMS.insert(Item {
+            uuid: "".to_string(),
+            name: "".to_string(),
+            due_date: None,
+            completion_date: None,
+            labels: vec![],
+        })
+    })
+}
+
+/// Returns 0 if there is no item with that uuid.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_fetch_item_handle(manager: *const Arc<ListManager>, uuid: *const c_char) -> u64 {
+    ffi_lock!();
+    ffi_guard!({
+        let manager = ffi_ref!("list_manager_fetch_item_handle", manager);
+        match manager.fetch_item(&c_char_to_string(uuid)) {
+            Some(item) => ITEMS.insert(item),
+            None => 0,
+        }
+    })
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn item_handle_destroy(handle: u64, error: *mut ExternError) -> bool {
+    ffi_guard!(error, {
+        handle_result(ITEMS.remove(handle).map(|_| true), false, error)
+    })
+}
+
+/// Empty for an item that has not been saved.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn item_handle_get_uuid(handle: u64, error: *mut ExternError) -> *mut c_char {
+    ffi_guard!(error, {
+        handle_result(ITEMS.get(handle, |item| string_to_c_char(item.uuid.clone())), ptr::null_mut(), error)
+    })
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn item_handle_get_name(handle: u64, error: *mut ExternError) -> *mut c_char {
+    ffi_guard!(error, {
+        handle_result(ITEMS.get(handle, |item| string_to_c_char(item.name.clone())), ptr::null_mut(), error)
+    })
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn item_handle_set_name(handle: u64, name: *const c_char, error: *mut ExternError) -> bool {
+    ffi_guard!(error, {
+        let name = c_char_to_string(name);
+        handle_result(ITEMS.get_mut(handle, |item| item.name = name).map(|_| true), false, error)
+    })
+}
+
+/// The item as `item_to_json` writes it.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn item_handle_to_json(handle: u64, error: *mut ExternError) -> *mut c_char {
+    ffi_guard!(error, {
+        handle_result(ITEMS.get(handle, |item| string_to_c_char(serde_json::to_string(item).unwrap())), ptr::null_mut(), error)
+    })
+}
+
+/// Writes the item to the store, creating it and giving it a uuid if it has none. Returns
+/// false and fills in `error` if the handle is bad or the item could not be written.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_save_item_handle(manager: *const Arc<ListManager>, handle: u64, error: *mut ExternError) -> bool {
+    ffi_lock!();
+    ffi_guard!(error, {
+        let manager = ffi_ref!("list_manager_save_item_handle", manager, false, error);
+        let item = match handle_result(ITEMS.get(handle, |item| Some(item.clone())), None, error) {
+            Some(item) => item,
+            None => return false,
+        };
+        let saved = if item.uuid.is_empty() {
+            manager.try_create_item(&item).map(|uuid| {
+                let _ = ITEMS.get_mut(handle, |item| item.uuid = uuid);
+            })
+        } else {
+            manager.try_save_item(&item)
+        };
+        match saved {
+            Ok(()) => true,
+            Err(e) => {
+                log_error!("failed to save item {:?}", e);
+                set_sqlite_error(error, &e);
+                false
+            },
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::ffi::{
+        CStr,
+        CString,
+    };
+    use std::ptr;
+    use std::sync::Arc;
+
+    use ffi_utils::errors::{
+        error_message_destroy,
+        ErrorCategory,
+        ExternError,
+    };
+    use ffi_utils::strings::c_char_to_string;
+    use store::Store;
+    use ListManager;
+
+    use super::{
+        item_handle_destroy,
+        item_handle_get_name,
+        item_handle_get_uuid,
+        item_handle_new,
+        item_handle_set_name,
+        list_manager_fetch_item_handle,
+        list_manager_save_item_handle,
+    };
+
+    #[test]
+    fn test_item_handles() {
+        let manager = Arc::new(ListManager::new(Arc::new(Store::new(None))));
+        let name = CString::new("milk").unwrap();
+        let handle = item_handle_new();
+        unsafe {
+            assert!(item_handle_set_name(handle, name.as_ptr(), ptr::null_mut()));
+            assert!(list_manager_save_item_handle(&manager, handle, ptr::null_mut()));
+            let uuid = item_handle_get_uuid(handle, ptr::null_mut());
+            let fetched = list_manager_fetch_item_handle(&manager, uuid);
+            assert!(fetched != handle);
+            assert_eq!(c_char_to_string(item_handle_get_name(fetched, ptr::null_mut())), "milk");
+            assert!(item_handle_destroy(fetched, ptr::null_mut()));
+            assert!(item_handle_destroy(handle, ptr::null_mut()));
+
+            let mut error = ExternError::default();
+            assert!(item_handle_get_name(handle, &mut error).is_null());
+            assert_eq!(error.category, ErrorCategory::Bug);
+            assert!(CStr::from_ptr(error.message).to_str().unwrap().contains("after it was freed"));
+            error_message_destroy(error.message);
+
+            let mut error = ExternError::default();
+            assert!(!item_handle_destroy(handle, &mut error));
+            assert_eq!(error.category, ErrorCategory::Bug);
+            error_message_destroy(error.message);
+        }
+    }
+}
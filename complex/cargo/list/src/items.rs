@@ -9,144 +9,679 @@
 // specific language governing permissions and limitations under the License.
 
 use libc::size_t;
+use std::collections::BTreeMap;
 use std::os::raw::{
     c_char,
     c_int,
 };
 use std::ptr;
+use std::sync::{
+    Arc,
+    Mutex,
+};
 
+use serde_json;
 use time::Timespec;
 
+use dates::{
+    date_from_millis,
+    date_from_seconds,
+    millis,
+    DateError,
+};
+use ffi_utils::diagnostics;
+use ffi_utils::errors::{
+    set_extern_error,
+    ErrorCategory,
+    ExternError,
+};
+use ffi_utils::lists::FfiList;
 use ffi_utils::strings::{
     string_to_c_char,
+    string_to_utf16,
     c_char_to_string,
+    utf16_to_string,
+    Utf16String,
+};
+use labels::{
+    Label,
+    LabelList,
 };
-use labels::Label;
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Item {
+    #[serde(default)]
     pub uuid: String,
     pub name: String,
+    #[serde(default, with = "optional_timespec")]
     pub due_date: Option<Timespec>,
+    #[serde(default, with = "optional_timespec")]
     pub completion_date: Option<Timespec>,
-    pub labels: Vec<Label>,
+    /// Shared with every other item read with the same label, through the manager's
+    /// `LabelTable`.
+    #[serde(default)]
+    pub labels: Vec<Arc<Label>>,
+}
+
+/// Writes dates as ISO 8601 UTC strings, so that Swift's `JSONDecoder` with the `.iso8601`
+/// date strategy decodes them. Reads those or whole seconds since the epoch, as items were
+/// written before, so stored payloads and older peers still parse.
+mod optional_timespec {
+    use serde::{
+        Deserialize,
+        Deserializer,
+        Serializer,
+    };
+    use serde::de::Error;
+    use time::Timespec;
+
+    use dates::{
+        date_from_iso8601,
+        date_from_seconds,
+        iso8601,
+    };
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Date {
+        Seconds(i64),
+        Iso8601(String),
+    }
+
+    pub fn serialize<S>(date: &Option<Timespec>, serializer: S) -> Result<S::Ok, S::Error>
+    where S: Serializer {
+        match *date {
+            Some(date) => serializer.serialize_some(&iso8601(date)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<Timespec>, D::Error>
+    where D: Deserializer<'de> {
+        match Option::deserialize(deserializer)? {
+            Some(Date::Seconds(seconds)) => date_from_seconds(seconds).map(Some).map_err(D::Error::custom),
+            Some(Date::Iso8601(date)) => match date_from_iso8601(&date) {
+                Some(date) => Ok(Some(date)),
+                None => Err(D::Error::custom(format!("{} is not an ISO 8601 date", date))),
+            },
+            None => Ok(None),
+        }
+    }
 }
 
+/// `struct item_list` in items.h.
+pub type ItemList = FfiList<Item>;
+
 impl Drop for Item {
     fn drop(&mut self) {
-        println!("{:?} is being deallocated", self);
+        diagnostics::record_drop("Item", || format!("{:?}", self));
     }
 }
 
-#[no_mangle]
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
 pub extern "C" fn item_new() -> *mut Item {
-    let item = Item{
-        uuid: "".to_string(),
-        name: "".to_string(),
-        due_date: None,
-        completion_date: None,
-        labels: vec![]
-    };
-    let boxed_item = Box::new(item);
-    Box::into_raw(boxed_item)
+    ffi_guard!({
+        let item = Item{
+            uuid: "".to_string(),
+            name: "".to_string(),
+            due_date: None,
+            completion_date: None,
+            labels: vec![]
+        };
+        let boxed_item = Box::new(item);
+        Box::into_raw(boxed_item)
+    })
 }
 
-#[no_mangle]
-pub unsafe extern "C" fn item_destroy(item: *mut Item) {
+/// A copy of the item, with the same uuid, that the caller owns.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn item_clone(item: *const Item) -> *mut Item {
+    ffi_guard!({
+        let item = ffi_ref!("item_clone", item, ptr::null_mut());
+        Box::into_raw(Box::new(item.clone()))
+    })
+}
+
+pub const ITEM_FIELD_UUID: u32 = 1 << 0;
+pub const ITEM_FIELD_NAME: u32 = 1 << 1;
+pub const ITEM_FIELD_DUE_DATE: u32 = 1 << 2;
+pub const ITEM_FIELD_COMPLETION_DATE: u32 = 1 << 3;
+pub const ITEM_FIELD_LABELS: u32 = 1 << 4;
+
+impl Item {
+    /// The `ITEM_FIELD_` bits of the fields that differ between the items. Labels are
+    /// compared as a set, so the order they were attached in does not matter.
+    pub fn diff(&self, other: &Item) -> u32 {
+        let labels = |item: &Item| {
+            let mut labels: Vec<(String, String)> = item.labels.iter().map(|l| (l.name.clone(), l.color.clone())).collect();
+            labels.sort();
+            labels
+        };
+        let mut fields = 0;
+        if self.uuid != other.uuid {
+            fields |= ITEM_FIELD_UUID;
+        }
+        if self.name != other.name {
+            fields |= ITEM_FIELD_NAME;
+        }
+        if self.due_date != other.due_date {
+            fields |= ITEM_FIELD_DUE_DATE;
+        }
+        if self.completion_date != other.completion_date {
+            fields |= ITEM_FIELD_COMPLETION_DATE;
+        }
+        if labels(self) != labels(other) {
+            fields |= ITEM_FIELD_LABELS;
+        }
+        fields
+    }
+}
+
+#[cfg(test)]
+impl Item {
+    /// A new item with just a name: no uuid, dates or labels.
+    pub fn named(name: &str) -> Item {
+        Item {
+            uuid: "".to_string(),
+            name: name.to_string(),
+            due_date: None,
+            completion_date: None,
+            labels: vec![],
+        }
+    }
+}
+
+/// Whether the items have the same uuid and contents, whether or not they are the same
+/// allocation.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn item_equals(a: *const Item, b: *const Item) -> bool {
+    ffi_guard!({
+        let a = ffi_ref!("item_equals", a, false);
+        let b = ffi_ref!("item_equals", b, false);
+        a.diff(b) == 0
+    })
+}
+
+/// The `ITEM_FIELD_` bits of the fields that differ between the items, 0 if they are equal.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn item_diff(a: *const Item, b: *const Item) -> u32 {
+    ffi_guard!({
+        let a = ffi_ref!("item_diff", a, 0);
+        let b = ffi_ref!("item_diff", b, 0);
+        a.diff(b)
+    })
+}
+
+/// How many owners each item handed to the host has beyond the first, by address. Items
+/// never retained are not in the table.
+static EXTRA_OWNERS: Mutex<BTreeMap<usize, usize>> = Mutex::new(BTreeMap::new());
+
+/// Drops one owner of `item`, freeing it if that was the last.
+unsafe fn release(item: *mut Item) {
+    {
+        let mut owners = EXTRA_OWNERS.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some(count) = owners.get_mut(&(item as usize)) {
+            *count -= 1;
+            if *count == 0 {
+                owners.remove(&(item as usize));
+            }
+            return;
+        }
+    }
     let _ = Box::from_raw(item);
 }
 
-#[no_mangle]
+/// Adds an owner to the item, so that it stays alive until `item_release` or `item_destroy`
+/// has been called once more than `item_retain`. Returns the item.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn item_retain(item: *mut Item) -> *mut Item {
+    ffi_guard!({
+        if item.is_null() {
+            return item;
+        }
+        *EXTRA_OWNERS.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).entry(item as usize).or_insert(0) += 1;
+        item
+    })
+}
+
+/// Drops the caller's ownership of the item, freeing it if no one else retained it.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn item_release(item: *mut Item) {
+    ffi_guard!({
+        if item.is_null() {
+            return;
+        }
+        release(item);
+    })
+}
+
+/// The same as `item_release`.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn item_destroy(item: *mut Item) {
+    ffi_guard!({
+        item_release(item);
+    })
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
 pub unsafe extern "C" fn item_get_name(item: *const Item) -> *mut c_char {
-    let item = &*item;
-    string_to_c_char(item.name.clone())
+    ffi_guard!({
+        let item = ffi_ref!("item_get_name", item, ptr::null_mut());
+        string_to_c_char(item.name.clone())
+    })
 }
 
-#[no_mangle]
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
 pub unsafe extern "C" fn item_set_name(item: *mut Item, name: *const c_char) {
-    let item = &mut*item;
-    item.name = c_char_to_string(name);
+    ffi_guard!({
+        let item = ffi_mut!("item_set_name", item);
+        item.name = c_char_to_string(name);
+    })
+}
+
+/// The name as UTF-16, released with `utf16_string_destroy`.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn item_get_name_utf16(item: *const Item) -> Utf16String {
+    ffi_guard!({
+        let item = ffi_ref!("item_get_name_utf16", item, string_to_utf16(""));
+        string_to_utf16(&item.name)
+    })
+}
+
+/// Sets the name from `len` UTF-16 code units.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn item_set_name_utf16(item: *mut Item, name: *const u16, len: usize) {
+    ffi_guard!({
+        let item = ffi_mut!("item_set_name_utf16", item);
+        item.name = utf16_to_string(name, len);
+    })
 }
 
-#[no_mangle]
+/// Releases a date returned by `item_get_due_date` or `item_get_completion_date`.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn timestamp_destroy(timestamp: *mut i64) {
+    ffi_guard!({
+        if !timestamp.is_null() {
+            let _ = Box::from_raw(timestamp);
+        }
+    })
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
 pub unsafe extern "C" fn item_get_due_date(item: *const Item) -> *mut i64 {
-    let item = &*item;
-    match item.due_date {
-        Some(date) => {
-            println!("item_get_due_date: returning {:?} for {:?}", date.sec, item.name);
-            Box::into_raw(Box::new(date.sec))
-        },
-        None => {
-            println!("item_get_due_date: returning null_mut for {:?}", item.name);
-            ptr::null_mut()
+    ffi_guard!({
+        let item = ffi_ref!("item_get_due_date", item, ptr::null_mut());
+        match item.due_date {
+            Some(date) => {
+                log_debug!("item_get_due_date: returning {:?} for {:?}", date.sec, item.name);
+                Box::into_raw(Box::new(date.sec))
+            },
+            None => {
+                log_debug!("item_get_due_date: returning null_mut for {:?}", item.name);
+                ptr::null_mut()
+            }
         }
-    }
 
+    })
 }
 
-#[no_mangle]
+/// 0 clears the date. Other values before 1970 or after the year 3000 are treated as in
+/// `item_try_set_due_date`, but only logged; the date is left unchanged.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
 pub unsafe extern "C" fn item_set_due_date(item: *mut Item, due_date: *const size_t) {
-    let item = &mut*item;
-    if !due_date.is_null() {
-        item.due_date = Some(Timespec::new(due_date as i64, 0));
-    } else {
-        item.due_date = None;
+    ffi_guard!({
+        let item = ffi_mut!("item_set_due_date", item);
+        if !due_date.is_null() {
+            match date_from_seconds(due_date as i64) {
+                Ok(date) => item.due_date = Some(date),
+                Err(e) => log_error!("failed to set due date {:?}", e),
+            }
+        } else {
+            item.due_date = None;
+        }
+    })
+}
+
+pub(crate) unsafe fn date_set(date: &mut Option<Timespec>, result: Result<Timespec, DateError>, error: *mut ExternError) -> bool {
+    match result {
+        Ok(value) => {
+            *date = Some(value);
+            true
+        },
+        Err(e) => {
+            log_error!("failed to set date {:?}", e);
+            set_extern_error(error, e.category(), e.to_string());
+            false
+        },
     }
 }
 
-#[no_mangle]
+/// Sets the due date to `due_date` seconds after 1970. Dates after the year 3000 are read as
+/// milliseconds unless strict dates are on. Returns false, leaving the date unchanged, and
+/// fills in `error` if the date is out of range.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn item_try_set_due_date(item: *mut Item, due_date: i64, error: *mut ExternError) -> bool {
+    ffi_guard!(error, {
+        let item = ffi_mut!("item_try_set_due_date", item, false, error);
+        date_set(&mut item.due_date, date_from_seconds(due_date), error)
+    })
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
 pub unsafe extern "C" fn item_get_completion_date(item: *const Item) -> *mut i64 {
-    let item = &*item;
-    match item.completion_date {
-        Some(date) => {
-            println!("item_get_due_date: returning {:?} for {:?}", date.sec, item.name);
-            Box::into_raw(Box::new(date.sec))
-        },
-        None => {
-            println!("item_get_due_date: returning null_mut for {:?}", item.name);
-            ptr::null_mut()
+    ffi_guard!({
+        let item = ffi_ref!("item_get_completion_date", item, ptr::null_mut());
+        match item.completion_date {
+            Some(date) => {
+                log_debug!("item_get_due_date: returning {:?} for {:?}", date.sec, item.name);
+                Box::into_raw(Box::new(date.sec))
+            },
+            None => {
+                log_debug!("item_get_due_date: returning null_mut for {:?}", item.name);
+                ptr::null_mut()
+            }
         }
-    }
 
+    })
 }
 
-#[no_mangle]
+/// 0 clears the date. Other out of range values are logged and leave the date unchanged.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
 pub unsafe extern "C" fn item_set_completion_date(item: *mut Item, completion_date: *const size_t) {
-    let item = &mut*item;
-    if !completion_date.is_null() {
-        item.completion_date = Some(Timespec::new(completion_date as i64, 0));
+    ffi_guard!({
+        let item = ffi_mut!("item_set_completion_date", item);
+        if !completion_date.is_null() {
+            match date_from_seconds(completion_date as i64) {
+                Ok(date) => item.completion_date = Some(date),
+                Err(e) => log_error!("failed to set completion date {:?}", e),
+            }
+        } else {
+            item.completion_date = None;
+        }
+    })
+}
+
+/// Like `item_try_set_due_date`, for the completion date.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn item_try_set_completion_date(item: *mut Item, completion_date: i64, error: *mut ExternError) -> bool {
+    ffi_guard!(error, {
+        let item = ffi_mut!("item_try_set_completion_date", item, false, error);
+        date_set(&mut item.completion_date, date_from_seconds(completion_date), error)
+    })
+}
+
+/// The date in milliseconds, or 0 if it is not set. `has_value`, which may be null, says
+/// which.
+unsafe fn date_get_ms(date: Option<Timespec>, has_value: *mut bool) -> i64 {
+    if let Some(has_value) = has_value.as_mut() {
+        *has_value = date.is_some();
+    }
+    date.map_or(0, millis)
+}
+
+pub(crate) unsafe fn date_set_ms(date: &mut Option<Timespec>, has_value: bool, ms: i64, error: *mut ExternError) -> bool {
+    if has_value {
+        date_set(date, date_from_millis(ms), error)
     } else {
-        item.completion_date = None;
+        *date = None;
+        true
     }
 }
 
-#[no_mangle]
-pub unsafe extern "C" fn item_get_labels(item: *const Item) -> *mut Vec<Label> {
-    let item = &*item;
-    let boxed_labels = Box::new(item.labels.clone());
-    Box::into_raw(boxed_labels)
+/// The due date in milliseconds since 1970, as `Date` and `java.util.Date` count them, or 0
+/// if it is not set. `has_value`, which may be null, is set to whether it is.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn item_get_due_date_ms(item: *const Item, has_value: *mut bool) -> i64 {
+    ffi_guard!({
+        let item = ffi_ref!("item_get_due_date_ms", item);
+        date_get_ms(item.due_date, has_value)
+    })
+}
+
+/// Sets the due date to `ms` milliseconds after 1970, dropping the milliseconds, or clears
+/// it if `has_value` is false. Returns false, leaving the date unchanged, and fills in
+/// `error` if the date is before 1970 or after the year 3000.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn item_set_due_date_ms(item: *mut Item, has_value: bool, ms: i64, error: *mut ExternError) -> bool {
+    ffi_guard!(error, {
+        let item = ffi_mut!("item_set_due_date_ms", item, false, error);
+        date_set_ms(&mut item.due_date, has_value, ms, error)
+    })
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn item_get_completion_date_ms(item: *const Item, has_value: *mut bool) -> i64 {
+    ffi_guard!({
+        let item = ffi_ref!("item_get_completion_date_ms", item);
+        date_get_ms(item.completion_date, has_value)
+    })
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn item_set_completion_date_ms(item: *mut Item, has_value: bool, ms: i64, error: *mut ExternError) -> bool {
+    ffi_guard!(error, {
+        let item = ffi_mut!("item_set_completion_date_ms", item, false, error);
+        date_set_ms(&mut item.completion_date, has_value, ms, error)
+    })
+}
+
+/// A copy of the item's labels, released with `label_list_destroy`. To read them without
+/// copying, use `item_labels_count` and `item_label_at`.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn item_get_labels(item: *const Item) -> LabelList {
+    ffi_guard!({
+        let item = ffi_ref!("item_get_labels", item);
+        LabelList::from(item.labels.iter().map(|label| Label::clone(label)).collect::<Vec<Label>>())
+    })
 }
 
-#[no_mangle]
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
 pub unsafe extern "C" fn item_labels_count(item: *const Item) -> c_int {
-    let item = &*item;
-    item.labels.len() as c_int
+    ffi_guard!({
+        let item = ffi_ref!("item_labels_count", item);
+        item.labels.len() as c_int
+    })
+}
+
+/// The label at `index`, borrowed from the item rather than copied, so listing an item's
+/// labels allocates nothing. It stays valid while the item is alive and its labels are
+/// unchanged, and is not destroyed by the caller. Returns null if `index` is out of range.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn item_label_at(item: *const Item, index: c_int) -> *const Label {
+    ffi_guard!({
+        let item = ffi_ref!("item_label_at", item, ptr::null());
+        if index < 0 {
+            return ptr::null();
+        }
+        match item.labels.get(index as usize) {
+            Some(label) => &**label as *const Label,
+            None => ptr::null(),
+        }
+    })
 }
 
-#[no_mangle]
-pub unsafe extern "C" fn item_label_at(label_list: *const Vec<Label>, index: size_t) -> *const Label {
-    let label_list = &*label_list;
-    let index = index as usize;
-    let label = Box::new(label_list[index].clone());
-    Box::into_raw(label)
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn item_list_destroy(list: ItemList) {
+    ffi_guard!({
+        if list.data.is_null() {
+            return;
+        }
+        // items retained from the list outlive it
+        for item in Vec::from_raw_parts(list.data, list.len, list.cap) {
+            release(item);
+        }
+    })
 }
 
+/// The item as JSON, with dates in ISO 8601 UTC and its labels in full.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn item_to_json(item: *const Item) -> *mut c_char {
+    ffi_guard!({
+        let item = ffi_ref!("item_to_json", item, ptr::null_mut());
+        string_to_c_char(serde_json::to_string(item).unwrap())
+    })
+}
+
+/// Parses JSON made by `item_to_json`. A missing uuid or label list is left empty. Returns
+/// null and fills in `error` if the JSON is not an item.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn item_from_json(json: *const c_char, error: *mut ExternError) -> *mut Item {
+    ffi_guard!(error, {
+        match serde_json::from_str::<Item>(&c_char_to_string(json)) {
+            Ok(item) => Box::into_raw(Box::new(item)),
+            Err(e) => {
+                log_error!("failed to parse item {:?}", e);
+                set_extern_error(error, ErrorCategory::Bug, e.to_string());
+                ptr::null_mut()
+            },
+        }
+    })
+}
 
 #[cfg(test)]
 mod test {
+    use std::ffi::CString;
+    use std::ptr;
+    use std::sync::Arc;
+
+    use time::Timespec;
+
+    use ffi_utils::errors::{
+        ErrorCategory,
+        ExternError,
+    };
+    use ffi_utils::strings::c_char_to_string;
+    use labels::Label;
+    use super::{
+        item_destroy,
+        item_diff,
+        item_equals,
+        item_from_json,
+        item_get_due_date_ms,
+        item_get_name,
+        item_label_at,
+        item_labels_count,
+        item_list_destroy,
+        item_release,
+        item_retain,
+        item_set_due_date_ms,
+        item_to_json,
+        Item,
+        ItemList,
+        ITEM_FIELD_DUE_DATE,
+        ITEM_FIELD_LABELS,
+        ITEM_FIELD_NAME,
+    };
 
     #[test]
     fn test_new_item() {
 
     }
+
+    #[test]
+    fn test_item_json() {
+        let item = Item {
+            uuid: "a".to_string(),
+            name: "water plants".to_string(),
+            due_date: Some(Timespec::new(1510315200, 0)),
+            completion_date: None,
+            labels: vec![Arc::new(Label { name: "home".to_string(), color: "#00ff00".to_string() })],
+        };
+        unsafe {
+            let json = item_to_json(&item);
+            assert_eq!(c_char_to_string(json), r##"{"uuid":"a","name":"water plants","due_date":"2017-11-10T12:00:00Z","completion_date":null,"labels":[{"name":"home","color":"#00ff00"}]}"##);
+            let parsed = item_from_json(json, ptr::null_mut());
+            assert_eq!(*parsed, item);
+            item_destroy(parsed);
+            let _ = CString::from_raw(json);
+
+            let seconds = CString::new(r#"{"name":"water plants","due_date":1510315200}"#).unwrap();
+            let parsed = item_from_json(seconds.as_ptr(), ptr::null_mut());
+            assert_eq!((*parsed).due_date, item.due_date);
+            item_destroy(parsed);
+
+            let bad = CString::new(r#"{"uuid":"a"}"#).unwrap();
+            assert!(item_from_json(bad.as_ptr(), ptr::null_mut()).is_null());
+            let bad = CString::new(r#"{"name":"a","due_date":"next tuesday"}"#).unwrap();
+            assert!(item_from_json(bad.as_ptr(), ptr::null_mut()).is_null());
+        }
+    }
+
+    #[test]
+    fn test_retained_items_outlive_their_list() {
+        unsafe {
+            let list = ItemList::from(vec![Item::named("milk"), Item::named("eggs")]);
+            let eggs = item_retain(*list.data.add(1));
+            item_list_destroy(list);
+            assert_eq!(c_char_to_string(item_get_name(eggs)), "eggs");
+
+            // two screens holding the same item
+            item_retain(eggs);
+            item_destroy(eggs);
+            assert_eq!(c_char_to_string(item_get_name(eggs)), "eggs");
+            item_release(eggs);
+        }
+    }
+
+    #[test]
+    fn test_item_label_at_borrows() {
+        let mut item = Item::named("water plants");
+        item.labels = vec![
+            Arc::new(Label { name: "home".to_string(), color: "#00ff00".to_string() }),
+            Arc::new(Label { name: "garden".to_string(), color: "#008000".to_string() }),
+        ];
+        unsafe {
+            assert_eq!(item_labels_count(&item), 2);
+            let garden = item_label_at(&item, 1);
+            assert_eq!(garden, &*item.labels[1] as *const Label);
+            assert_eq!((*garden).name, "garden");
+            assert!(item_label_at(&item, 2).is_null());
+            assert!(item_label_at(&item, -1).is_null());
+            assert!(item_label_at(ptr::null(), 0).is_null());
+        }
+    }
+
+    #[test]
+    fn test_item_equals_and_diff() {
+        let home = Label { name: "home".to_string(), color: "#00ff00".to_string() };
+        let work = Label { name: "work".to_string(), color: "#0000ff".to_string() };
+        let mut a = Item::named("water plants");
+        a.labels = vec![Arc::new(home.clone()), Arc::new(work.clone())];
+        let mut b = a.clone();
+        b.labels = vec![Arc::new(work), Arc::new(home)];
+        unsafe {
+            assert!(item_equals(&a, &b));
+            assert_eq!(item_diff(&a, &b), 0);
+
+            b.name = "water the plants".to_string();
+            b.due_date = Some(Timespec::new(1510315200, 0));
+            Arc::make_mut(&mut b.labels[0]).color = "#ff0000".to_string();
+            assert!(!item_equals(&a, &b));
+            assert_eq!(item_diff(&a, &b), ITEM_FIELD_NAME | ITEM_FIELD_DUE_DATE | ITEM_FIELD_LABELS);
+            assert!(!item_equals(&a, ptr::null()));
+        }
+    }
+
+    #[test]
+    fn test_due_date_ms() {
+        let mut item = Item::named("file taxes");
+        let mut has_value = true;
+        let mut error = ExternError::default();
+        unsafe {
+            assert_eq!(item_get_due_date_ms(&item, &mut has_value), 0);
+            assert!(!has_value);
+            assert!(item_set_due_date_ms(&mut item, true, 1510315200123, &mut error));
+            assert_eq!(item.due_date, Some(Timespec::new(1510315200, 0)));
+            assert_eq!(item_get_due_date_ms(&item, &mut has_value), 1510315200000);
+            assert!(has_value);
+            assert_eq!(item_get_due_date_ms(&item, ptr::null_mut()), 1510315200000);
+
+            assert!(!item_set_due_date_ms(&mut item, true, -1, &mut error));
+            assert_eq!(error.category, ErrorCategory::Bug);
+            assert!(item.due_date.is_some());
+
+            assert!(item_set_due_date_ms(&mut item, false, 0, ptr::null_mut()));
+            assert_eq!(item.due_date, None);
+        }
+    }
 }
@@ -0,0 +1,496 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! Long operations run on a job thread the library owns, so the UI thread is not blocked.
+//!
+//! Each `_async` function returns a job id at once and queues the work. Jobs run one at a
+//! time, in the order they were started, holding the library lock while they touch the
+//! list manager. When a job finishes, the completion callback is called on the job thread
+//! with its id and either its result as JSON or an error. A job that has not started yet
+//! can be cancelled, and is then never reported.
+
+use std::collections::BTreeSet;
+use std::ffi::{
+    CStr,
+    CString,
+};
+use std::os::raw::{
+    c_char,
+    c_void,
+};
+use std::ptr;
+use std::sync::mpsc::{
+    self,
+    Receiver,
+    Sender,
+};
+use std::sync::{
+    Arc,
+    Mutex,
+    MutexGuard,
+};
+use std::thread;
+
+use serde_json;
+
+use ffi_utils::errors::{
+    catch_panic,
+    error_message_destroy,
+    set_extern_error,
+    ErrorCategory,
+    ExternError,
+};
+use ffi_utils::lock;
+use ffi_utils::strings::c_char_to_string;
+use filters::ItemFilter;
+use importers::ImportSource;
+use store::errors::category_of;
+use ListManager;
+
+/// Called on the job thread when job `job` finishes. On success `result` is its result as
+/// JSON and `error` is null; on failure `result` is null. Both are only valid for the
+/// duration of the call.
+pub type JobCompletionCallback = extern "C" fn(job: u64, result: *const c_char, error: *const ExternError, user_data: *mut c_void);
+
+/// A job's result as JSON, or why it failed.
+pub type JobResult = Result<String, (ErrorCategory, String)>;
+
+/// Told how one job ended, on the job thread, in place of the completion callback.
+pub type Completion = Box<dyn FnOnce(JobResult) + Send>;
+
+type Work = Box<dyn FnOnce() -> JobResult + Send>;
+
+enum Message {
+    Run(u64, Work, Option<Completion>),
+    Flush(Sender<()>),
+}
+
+struct Jobs {
+    sender: Option<Sender<Message>>,
+    next_id: u64,
+    // user data is a pointer only the host dereferences, kept as an integer to cross threads
+    completion: Option<(JobCompletionCallback, usize)>,
+    /// Jobs started but not yet run, which may still be cancelled.
+    queued: BTreeSet<u64>,
+}
+
+static JOBS: Mutex<Jobs> = Mutex::new(Jobs {
+    sender: None,
+    next_id: 0,
+    completion: None,
+    queued: BTreeSet::new(),
+});
+
+fn jobs() -> MutexGuard<'static, Jobs> {
+    JOBS.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// A list manager handed to the job thread. It is only used, and dropped, with the library
+/// lock held.
+struct SharedManager(Arc<ListManager>);
+
+unsafe impl Send for SharedManager {}
+
+fn run(receiver: Receiver<Message>) {
+    for message in receiver {
+        let (job, work, completion) = match message {
+            Message::Run(job, work, completion) => (job, work, completion),
+            Message::Flush(done) => {
+                let _ = done.send(());
+                continue;
+            },
+        };
+        if !jobs().queued.remove(&job) {
+            log_debug!("skipping cancelled job {}", job);
+            // the work owns its list manager, which may only be dropped with the lock held
+            let _library_lock = lock::enter();
+            drop(work);
+            continue;
+        }
+        let mut error = ExternError::default();
+        let out: *mut ExternError = &mut error;
+        let result = unsafe {
+            let _library_lock = lock::enter();
+            catch_panic(out, None, || match work() {
+                Ok(result) => Some(result),
+                Err((category, message)) => {
+                    set_extern_error(out, category, message);
+                    None
+                },
+            })
+        };
+        if let Some(completion) = completion {
+            completion(match result {
+                Some(result) => Ok(result),
+                None if error.message.is_null() => Err((error.category, "".to_string())),
+                None => Err((error.category, unsafe { CStr::from_ptr(error.message) }.to_string_lossy().into_owned())),
+            });
+            unsafe { error_message_destroy(error.message) };
+            continue;
+        }
+        let completion = jobs().completion;
+        match completion {
+            Some((callback, user_data)) => {
+                let result = result.map(|result| CString::new(result).unwrap_or_default());
+                let result_ptr = result.as_ref().map(|result| result.as_ptr()).unwrap_or(ptr::null());
+                let error_ptr: *const ExternError = if result.is_some() { ptr::null() } else { &error };
+                callback(job, result_ptr, error_ptr, user_data as *mut c_void);
+            },
+            None => log_warn!("job {} finished with no completion callback", job),
+        }
+        unsafe { error_message_destroy(error.message) };
+    }
+}
+
+/// Queues `work` to run with `manager` on the job thread. Returns the job's id. Its result
+/// goes to `completion` if given, or to the completion callback.
+fn start<F>(manager: &Arc<ListManager>, completion: Option<Completion>, work: F) -> u64
+where F: FnOnce(&ListManager) -> JobResult + Send + 'static {
+    let manager = SharedManager(manager.clone());
+    let work: Work = Box::new(move || {
+        let manager = manager;
+        work(&manager.0)
+    });
+
+    let mut jobs = jobs();
+    if jobs.sender.is_none() {
+        let (sender, receiver) = mpsc::channel();
+        thread::Builder::new()
+            .name("toodle-jobs".to_string())
+            .spawn(move || run(receiver))
+            .expect("expected the job thread to start");
+        jobs.sender = Some(sender);
+    }
+    jobs.next_id += 1;
+    let job = jobs.next_id;
+    jobs.queued.insert(job);
+    if let Some(ref sender) = jobs.sender {
+        let _ = sender.send(Message::Run(job, work, completion));
+    }
+    job
+}
+
+/// Syncs like `ListManager::sync_now`; the result is the summary.
+pub(crate) fn sync(manager: &Arc<ListManager>, completion: Option<Completion>) -> u64 {
+    start(manager, completion, |manager| match manager.sync_now() {
+        Ok(summary) => Ok(serde_json::to_string(&summary).unwrap()),
+        Err(e) => {
+            log_error!("failed to sync {:?}", e);
+            Err((e.category(), e.to_string()))
+        },
+    })
+}
+
+/// Imports like `ListManager::import_dump`; the result is the report.
+pub(crate) fn import_dump(manager: &Arc<ListManager>, text: String, source: ImportSource, completion: Option<Completion>) -> u64 {
+    start(manager, completion, move |manager| match manager.try_import_dump(&text, source) {
+        Ok(report) => Ok(serde_json::to_string(&report).unwrap()),
+        Err(e) => {
+            log_error!("failed to import dump {:?}", e);
+            Err((category_of(&e), e.to_string()))
+        },
+    })
+}
+
+/// The result is the items matching `filter`, as a JSON array.
+pub(crate) fn fetch_items(manager: &Arc<ListManager>, filter: ItemFilter, completion: Option<Completion>) -> u64 {
+    start(manager, completion, move |manager| Ok(serde_json::to_string(&manager.fetch_items(&filter)).unwrap()))
+}
+
+/// Keeps a job that has not started from running. Returns false if it has started or
+/// there is no such job.
+pub fn cancel(job: u64) -> bool {
+    jobs().queued.remove(&job)
+}
+
+/// Blocks until every job started so far has finished.
+pub fn wait_for_jobs() {
+    let (done, finished) = mpsc::channel();
+    let sent = match jobs().sender {
+        Some(ref sender) => sender.send(Message::Flush(done)).is_ok(),
+        None => false,
+    };
+    if sent {
+        let _ = finished.recv();
+    }
+}
+
+/// Replaces the callback told about finished jobs. Jobs finishing while none is set are
+/// only logged.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub extern "C" fn jobs_set_completion_callback(callback: JobCompletionCallback, user_data: *mut c_void) {
+    ffi_guard!({
+        jobs().completion = Some((callback, user_data as usize));
+    })
+}
+
+/// Syncs like `list_manager_sync_now`. The result is the summary, `{"pushed": n, "pulled":
+/// n, "conflicts": n}`. Returns 0 without starting a job if `manager` is null.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_sync_async(manager: *const Arc<ListManager>) -> u64 {
+    ffi_lock!();
+    ffi_guard!({
+        let manager = ffi_ref!("list_manager_sync_async", manager);
+        sync(manager, None)
+    })
+}
+
+/// Imports like `list_manager_import_dump`, whose report is the result.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_import_dump_async(manager: *const Arc<ListManager>, text: *const c_char, source: ImportSource) -> u64 {
+    ffi_lock!();
+    ffi_guard!({
+        let manager = ffi_ref!("list_manager_import_dump_async", manager);
+        import_dump(manager, c_char_to_string(text), source, None)
+    })
+}
+
+/// The result is a JSON array of the items matching `filter`, which may be null to fetch
+/// every item. The filter is copied, so it may be destroyed once this returns.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_fetch_items_async(manager: *const Arc<ListManager>, filter: *const ItemFilter) -> u64 {
+    ffi_lock!();
+    ffi_guard!({
+        let manager = ffi_ref!("list_manager_fetch_items_async", manager);
+        fetch_items(manager, filter.as_ref().cloned().unwrap_or_default(), None)
+    })
+}
+
+/// Returns false if the job has started or there is no such job. A cancelled job is not
+/// reported to the completion callback.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub extern "C" fn jobs_cancel(job: u64) -> bool {
+    ffi_guard!({
+        cancel(job)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::ffi::{
+        CStr,
+        CString,
+    };
+    use std::os::raw::{
+        c_char,
+        c_void,
+    };
+    use std::io;
+    use std::ptr;
+    use std::sync::atomic::{
+        AtomicBool,
+        Ordering,
+    };
+    use std::sync::mpsc::{
+        self,
+        Receiver,
+        Sender,
+    };
+    use std::sync::{
+        Arc,
+        Mutex,
+    };
+    use std::time::Duration;
+
+    use ffi_utils::errors::{
+        ErrorCategory,
+        ExternError,
+    };
+    use filters::ItemFilter;
+    use ffi_utils::lock;
+    use importers::ImportSource;
+    use items::Item;
+    use serde_json;
+    use store::Store;
+    use sync::{
+        list_manager_pending_change_count,
+        Changes,
+        HttpRequest,
+        HttpResponse,
+        Transport,
+    };
+    use list_manager_update_item;
+    use ListManager;
+
+    use super::{
+        jobs_cancel,
+        jobs_set_completion_callback,
+        list_manager_fetch_items_async,
+        list_manager_import_dump_async,
+        list_manager_sync_async,
+        sync,
+        wait_for_jobs,
+    };
+
+    static FINISHED: Mutex<Vec<(u64, Result<String, ErrorCategory>)>> = Mutex::new(Vec::new());
+
+    extern "C" fn finished(job: u64, result: *const c_char, error: *const ExternError, _: *mut c_void) {
+        let result = unsafe {
+            match error.as_ref() {
+                Some(error) => Err(error.category),
+                None => Ok(CStr::from_ptr(result).to_string_lossy().into_owned()),
+            }
+        };
+        FINISHED.lock().unwrap().push((job, result));
+    }
+
+    #[test]
+    fn test_jobs_report_through_the_completion_callback() {
+        jobs_set_completion_callback(finished, ptr::null_mut());
+        let manager = Arc::new(ListManager::new(Arc::new(Store::new(None))));
+        manager.create_item(&Item::named("renew passport"));
+        let dump = CString::new(r#"[{"id": "1", "content": "book flights"}]"#).unwrap();
+
+        let (fetched, imported, synced) = unsafe {
+            let filter = ItemFilter::default();
+            (list_manager_fetch_items_async(&manager, &filter),
+             list_manager_import_dump_async(&manager, dump.as_ptr(), ImportSource::Todoist),
+             list_manager_sync_async(&manager))
+        };
+        assert!(fetched != 0 && imported > fetched && synced > imported);
+        wait_for_jobs();
+
+        let results = FINISHED.lock().unwrap().clone();
+        let result = |job| results.iter().find(|&&(id, _)| id == job).map(|&(_, ref result)| result.clone()).unwrap();
+        let items = result(fetched).unwrap();
+        assert!(items.contains("renew passport") && !items.contains("book flights"));
+        assert!(result(imported).unwrap().contains(r#""skipped":0"#));
+        assert_eq!(result(synced), Err(ErrorCategory::Bug));
+        assert_eq!(manager.fetch_items(&ItemFilter::default()).len(), 2);
+    }
+
+    #[test]
+    fn test_cancel_queued_jobs() {
+        jobs_set_completion_callback(finished, ptr::null_mut());
+        let manager = Arc::new(ListManager::new(Arc::new(Store::new(None))));
+        let filter = ItemFilter::default();
+        let (first, second, third) = unsafe {
+            // the job thread cannot get past the first job while we hold the lock
+            let _library_lock = lock::enter();
+            let jobs = (list_manager_fetch_items_async(&manager, &filter),
+                        list_manager_fetch_items_async(&manager, &filter),
+                        list_manager_fetch_items_async(&manager, &filter));
+            assert!(jobs_cancel(jobs.1));
+            assert!(!jobs_cancel(jobs.1));
+            jobs
+        };
+        wait_for_jobs();
+        assert!(!jobs_cancel(third));
+
+        let finished: Vec<u64> = FINISHED.lock().unwrap().iter().map(|&(job, _)| job).collect();
+        assert!(finished.contains(&first) && !finished.contains(&second) && finished.contains(&third));
+    }
+
+    /// Holds each request until told to answer it.
+    struct SlowTransport {
+        sending: Arc<AtomicBool>,
+        started: Sender<()>,
+        answer: Receiver<()>,
+    }
+
+    impl Transport for SlowTransport {
+        fn send(&self, _: &HttpRequest) -> io::Result<HttpResponse> {
+            self.sending.store(true, Ordering::SeqCst);
+            self.started.send(()).unwrap();
+            let answered = self.answer.recv_timeout(Duration::from_secs(5));
+            self.sending.store(false, Ordering::SeqCst);
+            answered.map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "never answered"))?;
+            Ok(HttpResponse { status: 503, body: "".to_string() })
+        }
+    }
+
+    #[test]
+    fn test_library_is_usable_while_sync_waits_on_the_network() {
+        let manager = Arc::new(ListManager::new(Arc::new(Store::new(None))));
+        let sending = Arc::new(AtomicBool::new(false));
+        let (started, waiting) = mpsc::channel();
+        let (answer, answers) = mpsc::channel();
+        manager.set_sync_config("http://sync.test/v1", "token");
+        manager.set_sync_transport(Box::new(SlowTransport {
+            sending: sending.clone(),
+            started: started,
+            answer: answers,
+        }));
+        let (done, finished) = mpsc::channel();
+        sync(&manager, Some(Box::new(move |result| done.send(result).unwrap())));
+
+        waiting.recv().unwrap();
+        assert_eq!(unsafe { list_manager_pending_change_count(&manager) }, 0);
+        assert!(sending.load(Ordering::SeqCst));
+        answer.send(()).unwrap();
+        assert_eq!(finished.recv().unwrap().map_err(|(category, _)| category), Err(ErrorCategory::Retryable));
+    }
+
+    /// Keeps every push in memory. A held device's pushes wait until told to go through.
+    struct RelayTransport {
+        log: Arc<Mutex<Vec<Changes>>>,
+        held: Option<(Sender<()>, Receiver<()>)>,
+    }
+
+    impl Transport for RelayTransport {
+        fn send(&self, request: &HttpRequest) -> io::Result<HttpResponse> {
+            if request.method == "POST" {
+                if let Some((ref started, ref answer)) = self.held {
+                    started.send(()).unwrap();
+                    answer.recv_timeout(Duration::from_secs(5))
+                        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "never answered"))?;
+                }
+                let mut log = self.log.lock().unwrap();
+                log.push(serde_json::from_str(request.body.as_ref().unwrap()).unwrap());
+                return Ok(HttpResponse { status: 200, body: format!("{{\"cursor\": \"{}\"}}", log.len()) });
+            }
+            let since: usize = request.url.split("since=").nth(1).map(|since| since.parse().unwrap()).unwrap_or(0);
+            let log = self.log.lock().unwrap();
+            let mut changes = Changes::default();
+            for logged in log.iter().skip(since) {
+                changes.items.extend(logged.items.iter().cloned());
+            }
+            changes.cursor = Some(log.len().to_string());
+            Ok(HttpResponse { status: 200, body: serde_json::to_string(&changes).unwrap() })
+        }
+    }
+
+    #[test]
+    fn test_edits_made_while_a_push_is_out_are_pushed_later() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let manager = Arc::new(ListManager::new(Arc::new(Store::new(None))));
+        let (started, waiting) = mpsc::channel();
+        let (answer, answers) = mpsc::channel();
+        manager.set_sync_config("http://sync.test/v1", "token");
+        manager.set_sync_transport(Box::new(RelayTransport { log: log.clone(), held: Some((started, answers)) }));
+        let uuid = manager.create_item(&Item::named("milk"));
+        let (done, finished) = mpsc::channel();
+        sync(&manager, Some(Box::new(move |result| done.send(result).unwrap())));
+
+        waiting.recv().unwrap();
+        let mut item = {
+            let _library_lock = lock::enter();
+            manager.fetch_item(&uuid).unwrap()
+        };
+        item.name = "oat milk".to_string();
+        assert!(unsafe { list_manager_update_item(&manager, &item, ptr::null_mut()) });
+        answer.send(()).unwrap();
+        assert!(finished.recv().unwrap().is_ok());
+        assert_eq!(unsafe { list_manager_pending_change_count(&manager) }, 1);
+
+        let (done, finished) = mpsc::channel();
+        answer.send(()).unwrap();
+        sync(&manager, Some(Box::new(move |result| done.send(result).unwrap())));
+        assert!(finished.recv().unwrap().is_ok());
+        assert_eq!(unsafe { list_manager_pending_change_count(&manager) }, 0);
+
+        let tablet = ListManager::new(Arc::new(Store::new(None)));
+        tablet.set_sync_config("http://sync.test/v1", "token");
+        tablet.set_sync_transport(Box::new(RelayTransport { log: log, held: None }));
+        let _library_lock = lock::enter();
+        tablet.sync_now().unwrap();
+        assert_eq!(tablet.fetch_item(&uuid).unwrap().name, "oat milk");
+    }
+}
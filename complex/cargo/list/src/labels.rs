@@ -8,46 +8,231 @@
 // CONDITIONS OF ANY KIND, either express or implied. See the License for the
 // specific language governing permissions and limitations under the License.
 
+use libc::size_t;
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::os::raw::{
     c_char,
+    c_int,
 };
+use std::ptr;
+use std::sync::Arc;
 
+use serde::de::DeserializeOwned;
+use serde_json;
+
+use ffi_utils::diagnostics;
+use ffi_utils::errors::{
+    set_extern_error,
+    ErrorCategory,
+    ExternError,
+};
+use ffi_utils::lists::FfiList;
 use ffi_utils::strings::{
     string_to_c_char,
     c_char_to_string,
 };
 
-#[derive(Debug, Clone, PartialEq)]
+/// Items refer to a label by its name, so compare names, not labels, to tell whether two
+/// are the same label; a recolored label is not equal to the one it was.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Label {
     pub name: String,
     pub color: String
 }
 
+/// The labels items were last read with, so that the thousands of items carrying one of a
+/// handful of labels share a single `Label` each instead of holding their own copy.
+#[derive(Debug, Default)]
+pub struct LabelTable {
+    labels: RefCell<HashMap<String, Arc<Label>>>,
+}
+
+impl LabelTable {
+    /// The shared label with this name and color, replacing the one in the table if its
+    /// color has since changed. Items already holding the old one keep it.
+    pub fn intern(&self, name: String, color: String) -> Arc<Label> {
+        let mut labels = self.labels.borrow_mut();
+        if let Some(label) = labels.get(&name) {
+            if label.color == color {
+                return Arc::clone(label);
+            }
+        }
+        let label = Arc::new(Label { name: name.clone(), color: color });
+        labels.insert(name, Arc::clone(&label));
+        label
+    }
+
+    pub fn forget(&self, name: &str) {
+        self.labels.borrow_mut().remove(name);
+    }
+}
+
+/// `struct label_list` in items.h.
+pub type LabelList = FfiList<Label>;
+
+#[derive(Debug, Clone, PartialEq)]
+/// Item totals for a single label, as shown by sidebar badges.
+pub struct LabelCount {
+    pub label: Label,
+    pub open: i64,
+    pub overdue: i64,
+    pub total: i64,
+}
+
 impl Drop for Label {
     fn drop(&mut self) {
-        println!("{:?} is being deallocated", self);
+        diagnostics::record_drop("Label", || format!("{:?}", self));
     }
 }
 
-#[no_mangle]
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
 pub unsafe extern "C" fn label_destroy(label: *mut Label) {
-    let _ = Box::from_raw(label);
+    ffi_guard!({
+        if label.is_null() {
+            return;
+        }
+        let _ = Box::from_raw(label);
+    })
 }
 
-#[no_mangle]
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
 pub unsafe extern "C" fn label_get_name(label: *const Label) -> *mut c_char {
-    let label = &*label;
-    string_to_c_char(label.name.clone())
+    ffi_guard!({
+        let label = ffi_ref!("label_get_name", label, ptr::null_mut());
+        string_to_c_char(label.name.clone())
+    })
 }
 
-#[no_mangle]
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
 pub unsafe extern "C" fn label_get_color(label: *const Label) -> *mut c_char {
-    let label = &*label;
-    string_to_c_char(label.color.clone())
+    ffi_guard!({
+        let label = ffi_ref!("label_get_color", label, ptr::null_mut());
+        string_to_c_char(label.color.clone())
+    })
 }
 
-#[no_mangle]
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
 pub unsafe extern "C" fn label_set_color(label: *mut Label, color: *const c_char) {
-    let label = &mut*label;
-    label.color = c_char_to_string(color);
+    ffi_guard!({
+        let label = ffi_mut!("label_set_color", label);
+        label.color = c_char_to_string(color);
+    })
+}
+
+unsafe fn from_json<T: DeserializeOwned>(json: *const c_char, error: *mut ExternError) -> *mut T {
+    match serde_json::from_str::<T>(&c_char_to_string(json)) {
+        Ok(value) => Box::into_raw(Box::new(value)),
+        Err(e) => {
+            log_error!("failed to parse labels {:?}", e);
+            set_extern_error(error, ErrorCategory::Bug, e.to_string());
+            ptr::null_mut()
+        },
+    }
+}
+
+/// `{"name": ..., "color": ...}`.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn label_to_json(label: *const Label) -> *mut c_char {
+    ffi_guard!({
+        let label = ffi_ref!("label_to_json", label, ptr::null_mut());
+        string_to_c_char(serde_json::to_string(label).unwrap())
+    })
+}
+
+/// Returns null and fills in `error` if the JSON is not a label.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn label_from_json(json: *const c_char, error: *mut ExternError) -> *mut Label {
+    ffi_guard!(error, {
+        from_json(json, error)
+    })
+}
+
+/// A JSON array of labels, e.g. for the list from `list_manager_get_all_labels`. Does not
+/// take ownership of the list.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn label_list_to_json(list: LabelList) -> *mut c_char {
+    ffi_guard!({
+        string_to_c_char(serde_json::to_string(&list.as_vec()).unwrap())
+    })
+}
+
+/// Returns an empty list and fills in `error` if the JSON is not an array of labels.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn label_list_from_json(json: *const c_char, error: *mut ExternError) -> LabelList {
+    ffi_guard!(error, {
+        match serde_json::from_str::<Vec<Label>>(&c_char_to_string(json)) {
+            Ok(labels) => LabelList::from(labels),
+            Err(e) => {
+                log_error!("failed to parse labels {:?}", e);
+                set_extern_error(error, ErrorCategory::Bug, e.to_string());
+                LabelList::empty()
+            },
+        }
+    })
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn label_list_destroy(list: LabelList) {
+    ffi_guard!({
+        let _ = list.into_vec();
+    })
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn label_count_list_destroy(list: *mut Vec<LabelCount>) {
+    ffi_guard!({
+        if list.is_null() {
+            return;
+        }
+        let _ = Box::from_raw(list);
+    })
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn label_count_list_count(list: *const Vec<LabelCount>) -> c_int {
+    ffi_guard!({
+        let list = ffi_ref!("label_count_list_count", list);
+        list.len() as c_int
+    })
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn label_count_list_at(list: *const Vec<LabelCount>, index: size_t) -> *const LabelCount {
+    ffi_guard!({
+        let list = ffi_ref!("label_count_list_at", list, ptr::null());
+        &list[index as usize]
+    })
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn label_count_get_label(count: *const LabelCount) -> *const Label {
+    ffi_guard!({
+        let count = ffi_ref!("label_count_get_label", count, ptr::null());
+        &count.label
+    })
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn label_count_get_open(count: *const LabelCount) -> i64 {
+    ffi_guard!({
+        let count = ffi_ref!("label_count_get_open", count);
+        count.open
+    })
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn label_count_get_overdue(count: *const LabelCount) -> i64 {
+    ffi_guard!({
+        let count = ffi_ref!("label_count_get_overdue", count);
+        count.overdue
+    })
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn label_count_get_total(count: *const LabelCount) -> i64 {
+    ffi_guard!({
+        let count = ffi_ref!("label_count_get_total", count);
+        count.total
+    })
 }
@@ -9,67 +9,273 @@
 // specific language governing permissions and limitations under the License.
 
 extern crate libc;
+extern crate rand;
 extern crate rusqlite;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
 extern crate time;
 extern crate uuid;
 extern crate store;
+#[macro_use]
 extern crate ffi_utils;
+#[cfg(target_os="android")]
+extern crate jni;
 
+use rusqlite::types::ToSql;
+use std::cell::{
+    Cell,
+    RefCell,
+};
 use std::os::raw::c_char;
+use std::ptr;
+use std::rc::Rc;
 use std::sync::{
     Arc,
 };
-use uuid::Uuid;
+use time::{
+    now_utc,
+    Timespec,
+};
 
+#[cfg(target_os="android")]
+#[allow(non_snake_case)]
+pub mod android;
+pub mod attachments;
+pub mod attribution;
+pub mod autosave;
+pub mod background;
+pub mod batch;
+pub mod conflicts;
+mod crypto;
+pub mod cursors;
+pub mod dates;
+pub mod day_boundary;
+pub mod demo;
+pub mod dispatcher;
+pub mod encryption;
+pub mod events;
+pub mod export;
+pub mod filters;
+pub mod fixtures;
+pub mod flags;
+pub mod ics;
+pub mod ids;
+pub mod import;
+pub mod importers;
+pub mod item_builder;
+pub mod item_handles;
 pub mod labels;
 pub mod items;
-
-use labels::Label;
-use ffi_utils::strings::c_char_to_string;
-use items::Item;
+pub mod jobs;
+pub mod pinvoke;
+pub mod prefs;
+pub mod raw_query;
+pub mod share;
+pub mod recent;
+pub mod recurrence;
+pub mod search;
+pub mod smart_lists;
+pub mod stats;
+pub mod sync;
+pub mod sync_encryption;
+pub mod sync_report;
+pub mod sync_selection;
+pub mod templates;
+pub mod todo_txt;
+pub mod tombstones;
+pub mod transfer;
+pub mod widget;
+pub mod wire;
+
+use autosave::PendingSaves;
+use events::{
+    ChangeEvent,
+    Observer,
+    Observers,
+};
+use filters::ItemFilter;
+use flags::Flags;
+use ids::IdGenerator;
+use sync::SyncClient;
+use labels::{
+    Label,
+    LabelCount,
+    LabelList,
+    LabelTable,
+};
+use ffi_utils::breadcrumbs;
+use ffi_utils::errors::ExternError;
+use ffi_utils::strings::{
+    c_char_to_string,
+    string_to_c_char,
+};
+use items::{
+    Item,
+    ItemList,
+};
 use store::Store;
+use store::errors::set_sqlite_error;
+use store::migrations::MigrationStep;
 
+/// The steps creating the list schema, in the order they must be applied.
+fn migration_steps() -> Vec<MigrationStep> {
+    vec![
+        MigrationStep::new("create_labels_table", r#"CREATE TABLE IF NOT EXISTS labels (
+                name TEXT NOT NULL PRIMARY KEY,
+                color TEXT NOT NULL
+            )"#),
+        MigrationStep::new("create_items_table", r#"CREATE TABLE IF NOT EXISTS items (
+                uuid TEXT NOT NULL PRIMARY KEY,
+                name TEXT NOT NULL,
+                due_date DATETIME,
+                completion_date DATETIME
+            )"#),
+        MigrationStep::new("create_item_labels_table", r#"CREATE TABLE IF NOT EXISTS item_labels (
+                item_uuid TEXT NOT NULL,
+                label_name TEXT NOT NULL,
+                PRIMARY KEY(item_uuid, label_name)
+            )"#),
+        smart_lists::CREATE_SMART_LISTS_TABLES,
+        conflicts::CREATE_CONFLICTS_TABLE,
+        attribution::CREATE_ITEM_FIELD_EDITS_TABLE,
+        search::CREATE_ITEM_SEARCH_TABLE,
+        prefs::CREATE_PREFS_TABLE,
+        recent::CREATE_RECENT_INDEXES,
+        sync::CREATE_SYNC_TABLES,
+        tombstones::CREATE_TOMBSTONES_TABLE,
+        sync::TRACK_LABEL_DELETIONS,
+        attachments::CREATE_ATTACHMENTS_TABLE,
+        recurrence::CREATE_RECURRENCE_TABLES,
+        sync::QUEUE_IN_CHANGE_ORDER,
+        sync_report::CREATE_SYNC_REPORT_TABLE,
+        sync_selection::CREATE_LOCAL_ONLY_LABELS_TABLE,
+    ]
+}
 
 #[derive(Debug)]
 #[repr(C)]
 pub struct ListManager {
     store: Arc<Store>,
+    observers: Rc<Observers>,
+    editor: RefCell<Option<String>>,
+    frozen_clock: Cell<Option<Timespec>>,
+    pending: PendingSaves,
+    flags: RefCell<Flags>,
+    ids: IdGenerator,
+    /// When and at which UTC offset `on_significant_time_change` was last called.
+    day_boundary: Cell<Option<(Timespec, i64)>>,
+    sync_client: SyncClient,
+    labels: LabelTable,
 }
 
 impl ListManager {
     pub fn new(store: Arc<Store>) -> ListManager {
-        let mut manager = ListManager {
+        ListManager::open(store).expect("expected the store to migrate")
+    }
+
+    /// Upgrades the store's schema if needed. Fails for read only stores that are out of date.
+    pub fn open(store: Arc<Store>) -> rusqlite::Result<ListManager> {
+        store.migrate(&migration_steps())?;
+        let manager = ListManager {
             store: store,
+            observers: Rc::new(Observers::default()),
+            editor: RefCell::new(None),
+            frozen_clock: Cell::new(None),
+            pending: PendingSaves::default(),
+            flags: RefCell::new(Flags::default()),
+            ids: IdGenerator::new(),
+            day_boundary: Cell::new(None),
+            sync_client: SyncClient::default(),
+            labels: LabelTable::default(),
         };
-        manager.create_labels_table();
-        manager.create_items_table();
-        manager.create_item_labels_table();
-        manager
+        manager.ids.resume(&manager.store.get_conn())?;
+        manager.index_unindexed_items()?;
+        manager.refresh_widget_snapshot();
+        Ok(manager)
     }
 
-    fn get_store(&self) -> Arc<Store> {
-        Arc::clone(&self.store)
+    /// The current time, unless the clock has been frozen.
+    pub fn now(&self) -> Timespec {
+        self.frozen_clock.get().unwrap_or_else(|| now_utc().to_timespec())
     }
 
-    fn get_store_mut(&mut self) -> &mut Store {
-        Arc::get_mut(&mut self.store).unwrap()
+    /// Pins `now` to a fixed instant, so that relative dates resolve identically on every
+    /// run. `None` lets the clock run again.
+    pub fn freeze_clock(&self, now: Option<Timespec>) {
+        self.frozen_clock.set(now);
     }
 
-    pub fn create_labels_table(&mut self) {
-        let sql = r#"CREATE TABLE IF NOT EXISTS labels (
-                name TEXT NOT NULL PRIMARY KEY,
-                color TEXT NOT NULL
-            )"#;
-        let s = self.get_store_mut();
-        let db = s.get_conn_mut();
-        db.execute(sql, &[]).unwrap();
+    /// Returns an id for `remove_observer`.
+    pub fn add_observer(&self, observer: Observer) -> u64 {
+        self.observers.add(observer)
+    }
+
+    pub fn remove_observer(&self, id: u64) -> bool {
+        self.observers.remove(id)
+    }
+
+    /// Reopens the underlying database, upgrading it if it was replaced by an older copy,
+    /// and tells observers to refetch everything.
+    pub fn reload_store(&self) -> rusqlite::Result<()> {
+        let store = self.get_store();
+        store.reopen()?;
+        store.migrate(&migration_steps())?;
+        self.index_unindexed_items()?;
+        self.notify(ChangeEvent::Invalidated);
+        Ok(())
+    }
+
+    pub fn get_store(&self) -> Arc<Store> {
+        Arc::clone(&self.store)
     }
 
     pub fn create_label(&self, name: String, color: String) -> Option<Label> {
+        match self.try_create_label(name, color) {
+            Ok(label) => Some(label),
+            Err(e) => {
+                log_error!("failed to create label {:?}", e);
+                None
+            },
+        }
+    }
+
+    /// Fails if there already is a label with that name.
+    pub fn try_create_label(&self, name: String, color: String) -> rusqlite::Result<Label> {
+        breadcrumbs::leave("list.create_label");
         let db = self.get_store().get_conn();
         let sql = r#"INSERT INTO labels (name, color) VALUES (?1, ?2)"#;
-        db.execute(sql, &[&name, &color]).unwrap();
-        self.fetch_label(&name)
+        db.execute(sql, &[&name, &color])?;
+        self.notify(ChangeEvent::LabelChanged(name.clone()));
+        Ok(Label { name: name, color: color })
+    }
+
+    /// Removes the label from every item and deletes it. Smart lists filtering on it keep
+    /// the name and match nothing. Returns false if there is no label with that name.
+    pub fn delete_label(&self, name: &String) -> bool {
+        self.try_delete_label(name).unwrap_or_else(|e| {
+            log_error!("failed to delete label {:?}", e);
+            false
+        })
+    }
+
+    pub fn try_delete_label(&self, name: &String) -> rusqlite::Result<bool> {
+        breadcrumbs::leave("list.delete_label");
+        let store = self.get_store();
+        let conn = store.get_conn();
+        let tx = store.begin_transaction()?;
+        conn.execute("DELETE FROM item_labels WHERE label_name=?", &[name])?;
+        let deleted = conn.execute("DELETE FROM labels WHERE name=?", &[name])? > 0;
+        if deleted {
+            self.bury(&conn, tombstones::KIND_LABEL, name)?;
+        }
+        tx.commit()?;
+        if deleted {
+            self.labels.forget(name);
+            self.notify(ChangeEvent::LabelDeleted(name.clone()));
+        }
+        Ok(deleted)
     }
 
     pub fn fetch_label(&self, name: &String) -> Option<Label> {
@@ -87,11 +293,16 @@ impl ListManager {
         if let Some(result) = label_iter.next() {
             result.ok()
         } else {
-            println!("No label found for name {:?}", name);
+            log_debug!("No label found for name {:?}", name);
             None
         }
     }
 
+    /// The copy of `label` that items read from this manager share.
+    pub fn share_label(&self, label: &Label) -> Arc<Label> {
+        self.labels.intern(label.name.clone(), label.color.clone())
+    }
+
     pub fn fetch_labels(&self) -> Vec<Label> {
         let sql = r#"SELECT name, color
                      FROM labels"#;
@@ -113,20 +324,41 @@ impl ListManager {
         label_list
     }
 
-    pub fn fetch_labels_for_item(&mut self, item_uuid: &String) -> Vec<Label> {
+    pub fn count_labels(&self) -> i64 {
+        let sql = r#"SELECT count(*) FROM labels"#;
+        let conn = self.get_store().get_conn();
+        conn.query_row(sql, &[], |row| row.get(0)).unwrap()
+    }
+
+    /// Fetches at most `limit` labels created after the one with row id `after`, in the
+    /// order they were created, each with its row id.
+    pub fn fetch_labels_page(&self, limit: i64, after: i64) -> Vec<(i64, Label)> {
+        let sql = r#"SELECT rowid, name, color
+                     FROM labels WHERE rowid > ?
+                     ORDER BY rowid LIMIT ?"#;
+        let conn = self.get_store().get_conn();
+        let mut stmt = conn.prepare(sql).unwrap();
+        let label_iter = stmt.query_map(&[&after, &limit], |row| {
+            (row.get(0), Label {
+                name: row.get(1),
+                color: row.get(2)
+            })
+        }).unwrap();
+        label_iter.filter_map(|result| result.ok()).collect()
+    }
+
+    /// The item's labels, shared with the other items carrying them.
+    pub fn fetch_labels_for_item(&self, item_uuid: &String) -> Vec<Arc<Label>> {
         let sql = r#"SELECT name, color
                      FROM labels JOIN item_labels on item_labels.label_name=labels.name
                      WHERE item_labels.item_uuid=?"#;
         let conn = self.get_store().get_conn();
         let mut stmt = conn.prepare(sql).unwrap();
         let mut label_iter = stmt.query_map(&[item_uuid], |row| {
-            Label {
-                name: row.get(0),
-                color: row.get(1)
-            }
+            self.labels.intern(row.get(0), row.get(1))
         }).unwrap();
 
-        let mut label_list: Vec<Label> = Vec::new();
+        let mut label_list: Vec<Arc<Label>> = Vec::new();
         while let Some(result) = label_iter.next() {
             if let Some(mut label) = result.ok() {
                 label_list.push(label);
@@ -135,31 +367,43 @@ impl ListManager {
         label_list
     }
 
-    pub fn create_items_table(&mut self) {
-        let sql = r#"CREATE TABLE IF NOT EXISTS items (
-                uuid TEXT NOT NULL PRIMARY KEY,
-                name TEXT NOT NULL,
-                due_date DATETIME,
-                completion_date DATETIME
-            )"#;
-        let db = self.get_store_mut().get_conn_mut();
-        db.execute(sql, &[]).unwrap();
+    pub fn label_counts(&self, filter: &ItemFilter) -> Vec<LabelCount> {
+        self.label_counts_as_of(filter, self.now())
     }
 
-    pub fn create_item_labels_table(&mut self) {
-        let sql = r#"CREATE TABLE IF NOT EXISTS item_labels (
-                item_uuid TEXT NOT NULL,
-                label_name TEXT NOT NULL,
-                PRIMARY KEY(item_uuid, label_name)
-            )"#;
-        let db = self.get_store_mut().get_conn_mut();
-        let r = db.execute(sql, &[]);
-        if r.is_err() {
-            println!("failed to create item_labels table {:?}", r.err());
-        }
+    /// Counts the items matching `filter` under every label in a single query. Items are
+    /// overdue if they are open and were due before `now`.
+    pub fn label_counts_as_of(&self, filter: &ItemFilter, now: Timespec) -> Vec<LabelCount> {
+        let (filter_sql, filter_params) = filter.resolved(now).to_sql();
+        let sql = format!(r#"SELECT labels.name, labels.color,
+                                    COUNT(items.uuid),
+                                    SUM(CASE WHEN items.uuid IS NOT NULL AND items.completion_date IS NULL THEN 1 ELSE 0 END),
+                                    SUM(CASE WHEN items.completion_date IS NULL AND items.due_date < ? THEN 1 ELSE 0 END)
+                             FROM labels
+                             LEFT JOIN item_labels ON item_labels.label_name=labels.name
+                             LEFT JOIN items ON items.uuid=item_labels.item_uuid AND {}
+                             GROUP BY labels.name
+                             ORDER BY labels.name"#, filter_sql);
+        let mut params: Vec<&dyn ToSql> = vec![&now];
+        params.extend(filter_params.iter().map(|p| &**p));
+
+        let conn = self.get_store().get_conn();
+        let mut stmt = conn.prepare(&sql).unwrap();
+        let count_iter = stmt.query_map(&params, |row| {
+            LabelCount {
+                label: Label {
+                    name: row.get(0),
+                    color: row.get(1),
+                },
+                total: row.get(2),
+                open: row.get(3),
+                overdue: row.get(4),
+            }
+        }).unwrap();
+        count_iter.filter_map(|result| result.ok()).collect()
     }
 
-    pub fn fetch_items_with_label(&mut self, label: &Label) -> Vec<Item> {
+    pub fn fetch_items_with_label(&self, label: &Label) -> Vec<Item> {
         let sql = r#"SELECT uuid, name, due_date, completion_date
                      FROM items JOIN item_labels on items.uuid=item_labels.item_uuid
                      WHERE item_labels.label_name=?"#;
@@ -185,7 +429,7 @@ impl ListManager {
         item_list
     }
 
-    pub fn fetch_item(&mut self, uuid: &String) -> Option<Item> {
+    pub fn fetch_item(&self, uuid: &String) -> Option<Item> {
         let sql = r#"SELECT uuid, name, due_date, completion_date FROM items WHERE uuid=?"#;
 
         let conn = self.get_store().get_conn();
@@ -203,85 +447,362 @@ impl ListManager {
         if let Some(result) = item_iter.next() {
             result.ok()
         } else {
-            println!("No item found for uuid {:?}", uuid);
+            log_debug!("No item found for uuid {:?}", uuid);
             None
         }
     }
 
-    pub fn create_item(&mut self, item: &Item) -> String {
+    pub fn count_items(&self, filter: &ItemFilter) -> i64 {
+        let (filter_sql, filter_params) = filter.resolved(self.now()).to_sql();
+        let sql = format!(r#"SELECT count(*) FROM items WHERE {}"#, filter_sql);
+        let params: Vec<&dyn ToSql> = filter_params.iter().map(|p| &**p).collect();
+        let conn = self.get_store().get_conn();
+        conn.query_row(&sql, &params, |row| row.get(0)).unwrap()
+    }
+
+    pub fn items_due_today(&self, utc_offset: i64) -> Vec<Item> {
+        self.fetch_items(&ItemFilter::due_today(self.now(), utc_offset))
+    }
+
+    pub fn items_overdue(&self, utc_offset: i64) -> Vec<Item> {
+        self.fetch_items(&ItemFilter::overdue(self.now(), utc_offset))
+    }
+
+    pub fn items_upcoming(&self, days: i64, utc_offset: i64) -> Vec<Item> {
+        self.fetch_items(&ItemFilter::upcoming(self.now(), utc_offset, days))
+    }
+
+    pub fn fetch_items(&self, filter: &ItemFilter) -> Vec<Item> {
+        self.fetch_items_page(filter, -1, 0).into_iter().map(|(_, item)| item).collect()
+    }
+
+    /// Fetches at most `limit` items matching `filter` that were created after the one with
+    /// row id `after`, in the order they were created, each with its row id.
+    pub fn fetch_items_page(&self, filter: &ItemFilter, limit: i64, after: i64) -> Vec<(i64, Item)> {
+        let (filter_sql, filter_params) = filter.resolved(self.now()).to_sql();
+        let sql = format!(r#"SELECT items.rowid, uuid, name, due_date, completion_date
+                             FROM items WHERE ({}) AND items.rowid > ?
+                             ORDER BY items.rowid LIMIT ?"#, filter_sql);
+        let mut params: Vec<&dyn ToSql> = filter_params.iter().map(|p| &**p).collect();
+        params.push(&after);
+        params.push(&limit);
+
+        let conn = self.get_store().get_conn();
+        let mut stmt = conn.prepare(&sql).unwrap();
+        let item_iter = stmt.query_map(&params, |row| {
+            let uuid: String = row.get(1);
+            (row.get(0), Item {
+                uuid: uuid.clone(),
+                name: row.get(2),
+                due_date: row.get(3),
+                completion_date: row.get(4),
+                labels: self.fetch_labels_for_item(&uuid)
+            })
+        }).unwrap();
+        item_iter.filter_map(|result| result.ok()).collect()
+    }
+
+    pub fn create_item(&self, item: &Item) -> String {
+        let item_uuid = self.ids.next_uuid();
+        if let Err(e) = self.insert_item(&item_uuid, item) {
+            log_error!("failed to create item {:?}", e);
+        }
+        item_uuid
+    }
+
+    /// Like `create_item`, but nothing is written and the error is returned if any
+    /// statement fails.
+    pub fn try_create_item(&self, item: &Item) -> rusqlite::Result<String> {
+        let item_uuid = self.ids.next_uuid();
+        self.insert_item(&item_uuid, item)?;
+        Ok(item_uuid)
+    }
+
+    /// Inserts `item` under the given uuid, ignoring the uuid it carries.
+    fn insert_item(&self, item_uuid: &String, item: &Item) -> rusqlite::Result<()> {
+        breadcrumbs::leave("list.insert_item");
         let item_sql = r#"INSERT INTO items (uuid, name, due_date, completion_date) VALUES (?, ?, ?, ?)"#;
-        let conn = self.get_store_mut().get_conn_mut();
-        let tx = conn.transaction().expect("expected a transaction");
-        let item_uuid = Uuid::new_v4().simple().to_string();
-        let _ = tx.execute(item_sql, &[&item_uuid, &item.name, &item.due_date, &item.completion_date]);
+        let store = self.get_store();
+        let conn = store.get_conn();
+        let tx = store.begin_transaction()?;
+        conn.execute(item_sql, &[item_uuid, &item.name, &item.due_date, &item.completion_date])?;
 
         let item_label_sql = r#"INSERT INTO item_labels (item_uuid, label_name) VALUES (?, ?)"#;
         for label in item.labels.iter() {
-            tx.execute(&item_label_sql, &[&item_uuid, &label.name]).unwrap();
+            conn.execute(&item_label_sql, &[item_uuid, &label.name])?;
+        }
+        search::index_item(&conn, item_uuid, &item.name)?;
+        self.record_field_edits(&conn, item_uuid, &attribution::changed_fields(None, item))?;
+        self.unbury(&conn, tombstones::KIND_ITEM, item_uuid)?;
+        tx.commit()?;
+        self.notify(ChangeEvent::ItemAdded(item_uuid.clone()));
+        Ok(())
+    }
+
+    /// Writes `item` over the stored item with the same uuid, recreating it if it has
+    /// since been deleted.
+    pub fn save_item(&self, item: &Item) {
+        if let Err(e) = self.try_save_item(item) {
+            log_error!("failed to save item {:?}", e);
+        }
+    }
+
+    pub fn try_save_item(&self, item: &Item) -> rusqlite::Result<()> {
+        if self.fetch_item(&item.uuid).is_some() {
+            let existing_labels = self.fetch_labels_for_item(&item.uuid);
+            self.try_update_item(item, existing_labels)
+        } else {
+            self.insert_item(&item.uuid, item)
+        }
+    }
+
+    pub fn update_item(&self, item: &Item, existing_labels: Vec<Arc<Label>>) {
+        if let Err(e) = self.try_update_item(item, existing_labels) {
+            log_error!("failed to update item {:?}", e);
         }
-        let _ = tx.commit();
-        item_uuid
     }
 
-    pub fn update_item(&mut self, item: &Item, existing_labels: Vec<Label>) {
+    /// Like `update_item`, but nothing is written and the error is returned if any
+    /// statement fails.
+    pub fn try_update_item(&self, item: &Item, existing_labels: Vec<Arc<Label>>) -> rusqlite::Result<()> {
+        breadcrumbs::leave("list.update_item");
         let sql = r#"UPDATE items SET name=?, due_date=?, completion_date=? WHERE uuid=?"#;
-        let conn = self.get_store_mut().get_conn_mut();
-        let tx = conn.transaction().expect("expected a transaction");
-        let _ = tx.execute(sql, &[&item.name, &item.due_date, &item.completion_date, &item.uuid]);
+        let previous = self.fetch_item(&item.uuid);
+        let store = self.get_store();
+        let conn = store.get_conn();
+        let tx = store.begin_transaction()?;
+        conn.execute(sql, &[&item.name, &item.due_date, &item.completion_date, &item.uuid])?;
 
         let item_label_insert_sql = r#"INSERT INTO item_labels (item_uuid, label_name) VALUES (?, ?)"#;
         for label in item.labels.iter() {
-            if !existing_labels.contains(label) {
+            if !existing_labels.iter().any(|existing| existing.name == label.name) {
                 // add label to item
-                tx.execute(&item_label_insert_sql, &[&item.uuid, &label.name]).unwrap();
+                conn.execute(&item_label_insert_sql, &[&item.uuid, &label.name])?;
             }
         }
         let item_label_delete_sql = r#"DELETE FROM item_labels WHERE item_uuid=? AND label_name=?"#;
         for label in existing_labels.iter() {
-            if !item.labels.contains(label) {
+            if !item.labels.iter().any(|kept| kept.name == label.name) {
                 // delete label from item
-                tx.execute(&item_label_delete_sql, &[&item.uuid, &label.name]).unwrap();
+                conn.execute(&item_label_delete_sql, &[&item.uuid, &label.name])?;
             }
         }
-        let _ = tx.commit();
+        search::index_item(&conn, &item.uuid, &item.name)?;
+        if let Some(ref previous) = previous {
+            self.record_field_edits(&conn, &item.uuid, &attribution::changed_fields(Some(previous), item))?;
+        }
+        tx.commit()?;
+        self.notify(ChangeEvent::ItemUpdated(item.uuid.clone()));
+        Ok(())
     }
+
+    /// Returns false if there is no item with that uuid.
+    pub fn delete_item(&self, uuid: &String) -> bool {
+        self.try_delete_item(uuid).unwrap_or_else(|e| {
+            log_error!("failed to delete item {:?}", e);
+            false
+        })
+    }
+
+    pub fn try_delete_item(&self, uuid: &String) -> rusqlite::Result<bool> {
+        breadcrumbs::leave("list.delete_item");
+        self.discard_queued_save(uuid);
+        let item = self.fetch_item(uuid);
+        let store = self.get_store();
+        let conn = store.get_conn();
+        let tx = store.begin_transaction()?;
+        if let Some(ref item) = item {
+            self.trash_item(&conn, item)?;
+        }
+        conn.execute("DELETE FROM item_labels WHERE item_uuid=?", &[uuid])?;
+        conn.execute("DELETE FROM item_field_edits WHERE item_uuid=?", &[uuid])?;
+        conn.execute("DELETE FROM item_search WHERE item_uuid=?", &[uuid])?;
+        conn.execute("DELETE FROM attachments WHERE item_uuid=?", &[uuid])?;
+        conn.execute("DELETE FROM series_occurrences WHERE item_uuid=?", &[uuid])?;
+        let deleted = conn.execute("DELETE FROM items WHERE uuid=?", &[uuid])? > 0;
+        if deleted {
+            self.bury(&conn, tombstones::KIND_ITEM, uuid)?;
+        }
+        tx.commit()?;
+        if deleted {
+            self.notify(ChangeEvent::ItemDeleted(uuid.clone()));
+        }
+        Ok(deleted)
+    }
+}
+
+
+/// Releases a list manager returned by `toodle_list` or `list_manager_open_encrypted`.
+/// Others obtained from the same toodle stay valid.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_destroy(manager: *mut Arc<ListManager>) {
+    ffi_lock!();
+    ffi_guard!({
+        if manager.is_null() {
+            return;
+        }
+        let _ = Box::from_raw(manager);
+    })
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_get_all_labels(manager: *const Arc<ListManager>) -> LabelList {
+    ffi_lock!();
+    ffi_guard!({
+        let manager = ffi_ref!("list_manager_get_all_labels", manager);
+        LabelList::from(manager.fetch_labels())
+    })
 }
 
-fn create_and_fetch_item(manager: &mut ListManager, item: &Item) -> Option<Item> {
-    let item_uuid = manager.create_item(item);
-    manager.fetch_item(&item_uuid)
+/// Items matching `filter`, which may be null to fetch every item.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_fetch_items(manager: *const Arc<ListManager>, filter: *const ItemFilter) -> ItemList {
+    ffi_lock!();
+    ffi_guard!({
+        let manager = ffi_ref!("list_manager_fetch_items", manager);
+        let items = match filter.as_ref() {
+            Some(filter) => manager.fetch_items(filter),
+            None => manager.fetch_items(&ItemFilter::default()),
+        };
+        ItemList::from(items)
+    })
+}
+
+#[derive(Serialize)]
+struct ListSnapshot<'a> {
+    label: &'a Label,
+    items: &'a [Item],
 }
 
+/// A label and its items as one JSON object, `{"label": ..., "items": [...]}`, for
+/// snapshots and web views. Returns null if there is no label with that name.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_list_json(manager: *const Arc<ListManager>, name: *const c_char) -> *mut c_char {
+    ffi_lock!();
+    ffi_guard!({
+        let manager = ffi_ref!("list_manager_list_json", manager, ptr::null_mut());
+        match manager.fetch_label(&c_char_to_string(name)) {
+            Some(label) => {
+                let items = manager.fetch_items_with_label(&label);
+                string_to_c_char(serde_json::to_string(&ListSnapshot { label: &label, items: &items }).unwrap())
+            },
+            None => ptr::null_mut(),
+        }
+    })
+}
+
+/// Every item as a JSON array, each as `item_to_json` writes it, for app extensions that
+/// decode the list with `Codable` instead of walking an `item_list`.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_fetch_all_json(manager: *const Arc<ListManager>) -> *mut c_char {
+    ffi_lock!();
+    ffi_guard!({
+        let manager = ffi_ref!("list_manager_fetch_all_json", manager, ptr::null_mut());
+        string_to_c_char(serde_json::to_string(&manager.fetch_items(&ItemFilter::default())).unwrap())
+    })
+}
+
+/// Returns the new item's uuid, or null and fills in `error` if it could not be written.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_create_item(manager: *const Arc<ListManager>, item: *const Item, error: *mut ExternError) -> *mut c_char {
+    ffi_lock!();
+    ffi_guard!(error, {
+        let manager = ffi_ref!("list_manager_create_item", manager, ptr::null_mut(), error);
+        let item = ffi_ref!("list_manager_create_item", item, ptr::null_mut(), error);
+        match manager.try_create_item(item) {
+            Ok(uuid) => string_to_c_char(uuid),
+            Err(e) => {
+                log_error!("failed to create item {:?}", e);
+                set_sqlite_error(error, &e);
+                ptr::null_mut()
+            },
+        }
+    })
+}
+
+/// Returns false and fills in `error` if the item could not be written.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_update_item(manager: *const Arc<ListManager>, item: *const Item, error: *mut ExternError) -> bool {
+    ffi_lock!();
+    ffi_guard!(error, {
+        let manager = ffi_ref!("list_manager_update_item", manager, false, error);
+        let item = ffi_ref!("list_manager_update_item", item, false, error);
+        let existing_labels = manager.fetch_labels_for_item(&(item.uuid));
+        match manager.try_update_item(item, existing_labels) {
+            Ok(()) => true,
+            Err(e) => {
+                log_error!("failed to update item {:?}", e);
+                set_sqlite_error(error, &e);
+                false
+            },
+        }
+    })
+}
 
-#[no_mangle]
-pub unsafe extern "C" fn list_manager_get_all_labels(manager: *mut Arc<ListManager>) -> *mut Vec<Label> {
-    let manager = Arc::get_mut(&mut *manager).unwrap();
-    let label_list = Box::new(manager.fetch_labels());
-    Box::into_raw(label_list)
+/// Returns false if there is no item with that uuid, or if it could not be deleted, in which
+/// case `error` is filled in.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_delete_item(manager: *const Arc<ListManager>, uuid: *const c_char, error: *mut ExternError) -> bool {
+    ffi_lock!();
+    ffi_guard!(error, {
+        let manager = ffi_ref!("list_manager_delete_item", manager, false, error);
+        let uuid = c_char_to_string(uuid);
+        manager.try_delete_item(&uuid).unwrap_or_else(|e| {
+            log_error!("failed to delete item {:?}", e);
+            set_sqlite_error(error, &e);
+            false
+        })
+    })
 }
 
-#[no_mangle]
-pub unsafe extern "C" fn list_manager_create_item(manager: *mut Arc<ListManager>, item: *const Item) {
-    let manager = Arc::get_mut(&mut *manager).unwrap();
-    let item = &*item;
-    create_and_fetch_item(manager, &item);
+/// Returns false if there is no label with that name, or if it could not be deleted, in which
+/// case `error` is filled in.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_delete_label(manager: *const Arc<ListManager>, name: *const c_char, error: *mut ExternError) -> bool {
+    ffi_lock!();
+    ffi_guard!(error, {
+        let manager = ffi_ref!("list_manager_delete_label", manager, false, error);
+        let name = c_char_to_string(name);
+        manager.try_delete_label(&name).unwrap_or_else(|e| {
+            log_error!("failed to delete label {:?}", e);
+            set_sqlite_error(error, &e);
+            false
+        })
+    })
 }
 
-#[no_mangle]
-pub unsafe extern "C" fn list_manager_update_item(manager: *mut Arc<ListManager>, item: *const Item) {
-    let manager = Arc::get_mut(&mut *manager).unwrap();
-    let item = &*item;
-    let existing_labels = manager.fetch_labels_for_item(&(item.uuid));
-    manager.update_item(item, existing_labels)
+/// Returns null and fills in `error` if the label could not be created, e.g. because there
+/// already is one with that name.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_create_label(manager: *const Arc<ListManager>, name: *const c_char, color: *const c_char, error: *mut ExternError) -> *mut Label {
+    ffi_lock!();
+    ffi_guard!(error, {
+        let manager = ffi_ref!("list_manager_create_label", manager, ptr::null_mut(), error);
+        let name = c_char_to_string(name);
+        let color = c_char_to_string(color);
+        match manager.try_create_label(name, color) {
+            Ok(label) => Box::into_raw(Box::new(label)),
+            Err(e) => {
+                log_error!("failed to create label {:?}", e);
+                set_sqlite_error(error, &e);
+                ptr::null_mut()
+            },
+        }
+    })
 }
 
-#[no_mangle]
-pub unsafe extern "C" fn list_manager_create_label(manager: *mut Arc<ListManager>, name: *const c_char, color: *const c_char) -> *mut Label {
-    let manager = Arc::get_mut(&mut *manager).unwrap();
-    let name = c_char_to_string(name);
-    let color = c_char_to_string(color);
-    let label = Box::new(manager.create_label(name, color).unwrap());
-    Box::into_raw(label)
+/// `filter` may be null to count every item.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_label_counts(manager: *const Arc<ListManager>, filter: *const ItemFilter) -> *mut Vec<LabelCount> {
+    ffi_lock!();
+    ffi_guard!({
+        let manager = ffi_ref!("list_manager_label_counts", manager, ptr::null_mut());
+        let counts = match filter.as_ref() {
+            Some(filter) => manager.label_counts(filter),
+            None => manager.label_counts(&ItemFilter::default()),
+        };
+        Box::into_raw(Box::new(counts))
+    })
 }
 
 
@@ -292,18 +813,30 @@ mod test {
         ListManager,
         Label,
         Item,
-        create_and_fetch_item,
+        ItemFilter,
+        list_manager_create_label,
+        list_manager_delete_item,
+        list_manager_fetch_items,
+        list_manager_get_all_labels,
     };
 
     use std::sync::Arc;
 
-    use time::now_utc;
+    use time::{
+        now_utc,
+        Duration,
+    };
 
     fn list_manager() -> ListManager {
         let store = Arc::new(Store::new(None));
         ListManager::new(store)
     }
 
+    fn create_and_fetch_item(manager: &ListManager, item: &Item) -> Option<Item> {
+        let item_uuid = manager.create_item(item);
+        manager.fetch_item(&item_uuid)
+    }
+
     #[test]
     fn test_new_list_manager() {
         let manager = list_manager();
@@ -363,7 +896,7 @@ mod test {
 
     #[test]
     fn test_create_item() {
-        let mut manager = list_manager();
+        let manager = list_manager();
         let l = Label {
             name: "label1".to_string(),
             color: "#000000".to_string()
@@ -382,10 +915,10 @@ mod test {
             name: "test item".to_string(),
             due_date: Some(date.clone()),
             completion_date: Some(date.clone()),
-            labels: vec![label, label2]
+            labels: vec![Arc::new(label), Arc::new(label2)]
         };
 
-        let item = create_and_fetch_item(&mut manager, &i).expect("expected an item");
+        let item = create_and_fetch_item(&manager, &i).expect("expected an item");
         assert!(item.uuid.len() > 0);
         assert_eq!(item.name, i.name);
         let due_date = item.due_date.expect("expecting a due date");
@@ -397,7 +930,7 @@ mod test {
 
     #[test]
     fn test_create_item_no_due_date() {
-        let mut manager = list_manager();
+        let manager = list_manager();
         let l = Label {
             name: "label1".to_string(),
             color: "#000000".to_string()
@@ -416,10 +949,10 @@ mod test {
             name: "test item".to_string(),
             due_date: None,
             completion_date: Some(date.clone()),
-            labels: vec![label, label2]
+            labels: vec![Arc::new(label), Arc::new(label2)]
         };
 
-        let item = create_and_fetch_item(&mut manager, &i).expect("expected an item");
+        let item = create_and_fetch_item(&manager, &i).expect("expected an item");
         assert!(item.uuid.len() > 0);
         assert_eq!(item.name, i.name);
         assert_eq!(item.due_date, i.due_date);
@@ -430,7 +963,7 @@ mod test {
 
     #[test]
     fn test_create_item_no_completion_date() {
-        let mut manager = list_manager();
+        let manager = list_manager();
         let l = Label {
             name: "label1".to_string(),
             color: "#000000".to_string()
@@ -449,10 +982,10 @@ mod test {
             name: "test item".to_string(),
             due_date: Some(date.clone()),
             completion_date: None,
-            labels: vec![label, label2]
+            labels: vec![Arc::new(label), Arc::new(label2)]
         };
 
-        let item = create_and_fetch_item(&mut manager, &i).expect("expected an item");
+        let item = create_and_fetch_item(&manager, &i).expect("expected an item");
         assert!(item.uuid.len() > 0);
         assert_eq!(item.name, i.name);
         let due_date = item.due_date.expect("expecting a due date");
@@ -463,14 +996,14 @@ mod test {
 
     #[test]
     fn test_fetch_item() {
-        let mut manager = list_manager();
+        let manager = list_manager();
         let label = manager.create_label("label1".to_string(), "#000000".to_string()).unwrap();
         let mut created_item = Item {
             uuid: "".to_string(),
             name: "test item".to_string(),
             due_date: None,
             completion_date: None,
-            labels: vec![label]
+            labels: vec![Arc::new(label)]
         };
 
         created_item.uuid = manager.create_item(&created_item);
@@ -483,7 +1016,7 @@ mod test {
 
     #[test]
     fn test_fetch_labels_for_item() {
-        let mut manager = list_manager();
+        let manager = list_manager();
         let label = manager.create_label("label1".to_string(), "#000000".to_string()).unwrap();
         let label2 = manager.create_label("label2".to_string(), "#000000".to_string()).unwrap();
         let label3 = manager.create_label("label3".to_string(), "#000000".to_string()).unwrap();
@@ -493,7 +1026,7 @@ mod test {
             name: "test item 1".to_string(),
             due_date: None,
             completion_date: None,
-            labels: vec![label, label2, label3]
+            labels: vec![Arc::new(label), Arc::new(label2), Arc::new(label3)]
         };
 
         item1.uuid = manager.create_item(&item1);
@@ -502,9 +1035,36 @@ mod test {
         assert_eq!(fetched_labels, item1.labels);
     }
 
+    #[test]
+    fn test_items_share_labels() {
+        let manager = list_manager();
+        let home = manager.create_label("home".to_string(), "#00ff00".to_string()).unwrap();
+        let mut uuids = vec![];
+        for name in ["water plants", "take out the bins"].iter() {
+            uuids.push(manager.create_item(&Item {
+                uuid: "".to_string(),
+                name: name.to_string(),
+                due_date: None,
+                completion_date: None,
+                labels: vec![Arc::new(home.clone())],
+            }));
+        }
+        let plants = manager.fetch_item(&uuids[0]).unwrap();
+        let bins = manager.fetch_item(&uuids[1]).unwrap();
+        assert!(Arc::ptr_eq(&plants.labels[0], &bins.labels[0]));
+
+        // a recolored label is read afresh
+        manager.get_store().get_conn().execute("UPDATE labels SET color='#ff0000' WHERE name='home'", &[]).unwrap();
+        let recolored = manager.fetch_item(&uuids[0]).unwrap();
+        assert_eq!(recolored.labels[0].name, plants.labels[0].name);
+        assert!(recolored.labels != plants.labels);
+        assert_eq!(recolored.labels[0].color, "#ff0000");
+        assert_eq!(plants.labels[0].color, "#00ff00");
+    }
+
     #[test]
     fn test_fetch_items_with_label() {
-        let mut manager = list_manager();
+        let manager = list_manager();
         let label = manager.create_label("label1".to_string(), "#000000".to_string()).unwrap();
         let label2 = manager.create_label("label2".to_string(), "#000000".to_string()).unwrap();
 
@@ -513,21 +1073,21 @@ mod test {
             name: "test item 1".to_string(),
             due_date: None,
             completion_date: None,
-            labels: vec![label.clone()]
+            labels: vec![Arc::new(label.clone())]
         };
         let mut item2 = Item {
             uuid: "".to_string(),
             name: "test item 2".to_string(),
             due_date: None,
             completion_date: None,
-            labels: vec![label.clone()]
+            labels: vec![Arc::new(label.clone())]
         };
         let mut item3 = Item {
             uuid: "".to_string(),
             name: "test item 3".to_string(),
             due_date: None,
             completion_date: None,
-            labels: vec![label.clone(), label2.clone()]
+            labels: vec![Arc::new(label.clone()), Arc::new(label2.clone())]
         };
 
         let mut item4 = Item {
@@ -535,7 +1095,7 @@ mod test {
             name: "test item 4".to_string(),
             due_date: None,
             completion_date: None,
-            labels: vec![label2.clone()]
+            labels: vec![Arc::new(label2.clone())]
         };
 
         item1.uuid = manager.create_item(&item1);
@@ -551,7 +1111,7 @@ mod test {
 
     #[test]
     fn test_update_item_add_label() {
-        let mut manager = list_manager();
+        let manager = list_manager();
         let label = manager.create_label("label1".to_string(), "#000000".to_string()).unwrap();
         let label2 = manager.create_label("label2".to_string(), "#000000".to_string()).unwrap();
         let label3 = manager.create_label("label3".to_string(), "#000000".to_string()).unwrap();
@@ -561,11 +1121,11 @@ mod test {
             name: "test item 1".to_string(),
             due_date: None,
             completion_date: None,
-            labels: vec![label, label2]
+            labels: vec![Arc::new(label), Arc::new(label2)]
         };
 
         item1.uuid = manager.create_item(&item1);
-        item1.labels.push(label3);
+        item1.labels.push(Arc::new(label3));
 
         let existing_labels = manager.fetch_labels_for_item(&item1.uuid);
         manager.update_item(&item1, existing_labels);
@@ -576,7 +1136,7 @@ mod test {
 
     #[test]
     fn test_update_item_remove_label() {
-        let mut manager = list_manager();
+        let manager = list_manager();
         let label = manager.create_label("label1".to_string(), "#000000".to_string()).unwrap();
         let label2 = manager.create_label("label2".to_string(), "#000000".to_string()).unwrap();
         let label3 = manager.create_label("label3".to_string(), "#000000".to_string()).unwrap();
@@ -586,7 +1146,7 @@ mod test {
             name: "test item 1".to_string(),
             due_date: None,
             completion_date: None,
-            labels: vec![label, label2, label3]
+            labels: vec![Arc::new(label), Arc::new(label2), Arc::new(label3)]
         };
 
         item1.uuid = manager.create_item(&item1);
@@ -601,7 +1161,7 @@ mod test {
 
     #[test]
     fn test_update_item_add_due_date() {
-        let mut manager = list_manager();
+        let manager = list_manager();
         let label = manager.create_label("label1".to_string(), "#000000".to_string()).unwrap();
         let label2 = manager.create_label("label2".to_string(), "#000000".to_string()).unwrap();
         let label3 = manager.create_label("label3".to_string(), "#000000".to_string()).unwrap();
@@ -611,7 +1171,7 @@ mod test {
             name: "test item 1".to_string(),
             due_date: None,
             completion_date: None,
-            labels: vec![label, label2, label3]
+            labels: vec![Arc::new(label), Arc::new(label2), Arc::new(label3)]
         };
 
         item1.uuid = manager.create_item(&item1);
@@ -627,7 +1187,7 @@ mod test {
 
     #[test]
     fn test_update_item_change_name() {
-        let mut manager = list_manager();
+        let manager = list_manager();
         let label = manager.create_label("label1".to_string(), "#000000".to_string()).unwrap();
         let label2 = manager.create_label("label2".to_string(), "#000000".to_string()).unwrap();
         let label3 = manager.create_label("label3".to_string(), "#000000".to_string()).unwrap();
@@ -638,7 +1198,7 @@ mod test {
             name: "test item 1".to_string(),
             due_date: Some(date),
             completion_date: None,
-            labels: vec![label, label2, label3]
+            labels: vec![Arc::new(label), Arc::new(label2), Arc::new(label3)]
         };
 
         item1.uuid = manager.create_item(&item1);
@@ -653,7 +1213,7 @@ mod test {
 
     #[test]
     fn test_update_item_complete_item() {
-        let mut manager = list_manager();
+        let manager = list_manager();
         let label = manager.create_label("label1".to_string(), "#000000".to_string()).unwrap();
         let label2 = manager.create_label("label2".to_string(), "#000000".to_string()).unwrap();
         let label3 = manager.create_label("label3".to_string(), "#000000".to_string()).unwrap();
@@ -664,7 +1224,7 @@ mod test {
             name: "test item 1".to_string(),
             due_date: None,
             completion_date: None,
-            labels: vec![label, label2, label3]
+            labels: vec![Arc::new(label), Arc::new(label2), Arc::new(label3)]
         };
 
         item1.uuid = manager.create_item(&item1);
@@ -677,4 +1237,293 @@ mod test {
         let completion_date = fetched_item.completion_date.expect("expected a completion_date");
         assert_eq!(completion_date.sec, date.sec);
     }
+
+    #[test]
+    fn test_label_counts() {
+        let manager = list_manager();
+        let work = manager.create_label("work".to_string(), "#000000".to_string()).unwrap();
+        let home = manager.create_label("home".to_string(), "#ffffff".to_string()).unwrap();
+        manager.create_label("unused".to_string(), "#ffffff".to_string()).unwrap();
+
+        let now = now_utc().to_timespec();
+        let items = vec![
+            ("overdue", Some(now - Duration::days(1)), None, vec![work.clone()]),
+            ("due later", Some(now + Duration::days(1)), None, vec![work.clone(), home.clone()]),
+            ("done", Some(now - Duration::days(1)), Some(now), vec![work.clone()]),
+            ("no due date", None, None, vec![home.clone()]),
+        ];
+        for (name, due_date, completion_date, labels) in items {
+            manager.create_item(&Item {
+                uuid: "".to_string(),
+                name: name.to_string(),
+                due_date: due_date,
+                completion_date: completion_date,
+                labels: labels.into_iter().map(Arc::new).collect(),
+            });
+        }
+
+        let counts = manager.label_counts_as_of(&ItemFilter::default(), now);
+        let summary: Vec<(&str, i64, i64, i64)> = counts.iter()
+            .map(|c| (c.label.name.as_str(), c.open, c.overdue, c.total))
+            .collect();
+        assert_eq!(summary, vec![("home", 2, 0, 2), ("unused", 0, 0, 0), ("work", 2, 1, 3)]);
+
+        let filter = ItemFilter {
+            name_contains: Some("DUE".to_string()),
+            ..ItemFilter::default()
+        };
+        let counts = manager.label_counts_as_of(&filter, now);
+        let summary: Vec<(&str, i64, i64, i64)> = counts.iter()
+            .map(|c| (c.label.name.as_str(), c.open, c.overdue, c.total))
+            .collect();
+        assert_eq!(summary, vec![("home", 2, 0, 2), ("unused", 0, 0, 0), ("work", 2, 1, 2)]);
+    }
+
+    #[test]
+    fn test_items_due_today_overdue_upcoming() {
+        let manager = list_manager();
+        let now = now_utc().to_timespec();
+        let items = vec![
+            ("last week", Some(now - Duration::days(7)), None),
+            ("in three days", Some(now + Duration::days(3)), None),
+            ("next month", Some(now + Duration::days(30)), None),
+            ("done last week", Some(now - Duration::days(7)), Some(now)),
+            ("whenever", None, None),
+        ];
+        for (name, due_date, completion_date) in items {
+            manager.create_item(&Item {
+                uuid: "".to_string(),
+                name: name.to_string(),
+                due_date: due_date,
+                completion_date: completion_date,
+                labels: vec![],
+            });
+        }
+        let names = |items: Vec<Item>| items.iter().map(|i| i.name.clone()).collect::<Vec<String>>();
+        assert_eq!(names(manager.items_overdue(0)), vec!["last week"]);
+        assert_eq!(names(manager.items_upcoming(7, 0)), vec!["in three days"]);
+        assert_eq!(names(manager.items_due_today(0)), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_reload_store() {
+        use std::cell::Cell;
+        use std::env;
+        use std::fs;
+        use std::rc::Rc;
+        use events::ChangeEvent;
+
+        let path = env::temp_dir().join("toodle_reload_test.db");
+        let _ = fs::remove_file(&path);
+        let manager = ListManager::new(Arc::new(Store::new(path.to_string_lossy().into_owned())));
+        let invalidations = Rc::new(Cell::new(0));
+        let counter = invalidations.clone();
+        manager.add_observer(Box::new(move |event| {
+            if *event == ChangeEvent::Invalidated {
+                counter.set(counter.get() + 1);
+            }
+        }));
+
+        let uuid = manager.create_item(&Item::named("before restore"));
+        // simulate the file being replaced by an empty database from a backup
+        fs::remove_file(&path).unwrap();
+        manager.reload_store().unwrap();
+
+        assert_eq!(invalidations.get(), 1);
+        assert_eq!(manager.fetch_item(&uuid), None);
+        assert_eq!(manager.fetch_labels().len(), 0);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_items_persist_across_stores() {
+        use std::env;
+        use std::fs;
+
+        let path = env::temp_dir().join("toodle_persist_test.db");
+        let _ = fs::remove_file(&path);
+        let uri = path.to_string_lossy().into_owned();
+
+        let mut item = Item::named("survives restart");
+        {
+            let store = Arc::new(Store::new(uri.clone()));
+            // writes must not need exclusive ownership of the store or the manager
+            let manager = Arc::new(ListManager::new(store.clone()));
+            let shared = manager.clone();
+            let label = shared.create_label("home".to_string(), "#00ff00".to_string()).unwrap();
+            item.labels.push(Arc::new(label));
+            item.uuid = shared.create_item(&item);
+            item.name = "survives restart, renamed".to_string();
+            shared.save_item(&item);
+        }
+
+        let manager = ListManager::new(Arc::new(Store::new(uri)));
+        assert_eq!(manager.fetch_item(&item.uuid), Some(item.clone()));
+        assert_eq!(manager.fetch_labels().into_iter().map(Arc::new).collect::<Vec<_>>(), item.labels);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_host_transaction_groups_item_writes() {
+        use std::cell::Cell;
+        use std::rc::Rc;
+
+        let manager = list_manager();
+        let store = manager.get_store();
+        let item = Item::named("imported");
+        let events = Rc::new(Cell::new(0));
+        let counter = events.clone();
+        manager.add_observer(Box::new(move |_| counter.set(counter.get() + 1)));
+
+        let tx = store.begin_transaction().unwrap();
+        for _ in 0..3 {
+            manager.create_item(&item);
+        }
+        tx.rollback().unwrap();
+        assert_eq!(manager.count_items(&ItemFilter::default()), 0);
+        assert_eq!(events.get(), 0);
+
+        let tx = store.begin_transaction().unwrap();
+        for _ in 0..3 {
+            manager.create_item(&item);
+        }
+        // not until the writes are durable
+        assert_eq!(events.get(), 0);
+        tx.commit().unwrap();
+        assert_eq!(manager.count_items(&ItemFilter::default()), 3);
+        assert_eq!(events.get(), 3);
+    }
+
+    #[test]
+    fn test_open_read_only_store() {
+        use std::env;
+        use std::fs;
+        use store::options::{
+            OpenOptions,
+            STORE_OPEN_CREATE_IF_MISSING,
+            STORE_OPEN_READ_ONLY,
+        };
+
+        let path = env::temp_dir().join("toodle_read_only_test.db");
+        let _ = fs::remove_file(&path);
+        let read_only = OpenOptions::from_bits(STORE_OPEN_READ_ONLY);
+
+        // an empty database cannot be upgraded without writing to it
+        Store::open(&path, OpenOptions::from_bits(STORE_OPEN_CREATE_IF_MISSING)).unwrap();
+        assert!(ListManager::open(Arc::new(Store::open(&path, read_only).unwrap())).is_err());
+
+        let manager = ListManager::open(Arc::new(Store::open(&path, OpenOptions::default()).unwrap())).unwrap();
+        manager.create_label("home".to_string(), "#000000".to_string()).unwrap();
+        drop(manager);
+
+        let manager = ListManager::open(Arc::new(Store::open(&path, read_only).unwrap())).unwrap();
+        assert_eq!(manager.fetch_labels().len(), 1);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_write_errors_reach_the_host() {
+        use std::ffi::CString;
+        use std::ptr;
+        use ffi_utils::errors::{
+            error_message_destroy,
+            ErrorCategory,
+            ExternError,
+        };
+
+        let manager = Arc::new(list_manager());
+        let name = CString::new("home").unwrap();
+        let color = CString::new("#000000").unwrap();
+        let mut error = ExternError::default();
+        let label = unsafe { list_manager_create_label(&manager, name.as_ptr(), color.as_ptr(), &mut error) };
+        assert!(!label.is_null());
+        assert_eq!(error.category, ErrorCategory::None);
+        unsafe { drop(Box::from_raw(label)) };
+
+        let duplicate = unsafe { list_manager_create_label(&manager, name.as_ptr(), color.as_ptr(), &mut error) };
+        assert!(duplicate.is_null());
+        assert_eq!(error.category, ErrorCategory::NeedsUserInput);
+        assert!(!error.message.is_null());
+        unsafe { error_message_destroy(error.message) };
+
+        let item = Item::named("milk");
+        let uuid = CString::new(manager.create_item(&item)).unwrap();
+        manager.get_store().get_conn().execute_batch("PRAGMA query_only = 1").unwrap();
+        let mut error = ExternError::default();
+        assert!(!unsafe { list_manager_delete_item(&manager, uuid.as_ptr(), &mut error) });
+        assert_eq!(error.category, ErrorCategory::NeedsUserInput);
+        unsafe { error_message_destroy(error.message) };
+        assert!(manager.fetch_item(&uuid.into_string().unwrap()).is_some());
+
+        // the error out parameter is optional
+        assert!(!unsafe { list_manager_delete_item(&manager, name.as_ptr(), ptr::null_mut()) });
+
+        // a null manager is reported rather than dereferenced
+        let mut error = ExternError::default();
+        assert!(!unsafe { list_manager_delete_item(ptr::null(), name.as_ptr(), &mut error) });
+        assert_eq!(error.category, ErrorCategory::Bug);
+        unsafe { error_message_destroy(error.message) };
+    }
+
+    #[test]
+    fn test_lists_cross_the_boundary() {
+        use std::ptr;
+        use items::item_list_destroy;
+        use labels::label_list_destroy;
+
+        let manager = Arc::new(list_manager());
+        let home = manager.create_label("home".to_string(), "#000000".to_string()).unwrap();
+        let item = Item {
+            uuid: "".to_string(),
+            name: "milk".to_string(),
+            due_date: None,
+            completion_date: None,
+            labels: vec![Arc::new(home.clone())],
+        };
+        manager.create_item(&item);
+
+        let labels = unsafe { list_manager_get_all_labels(&manager) };
+        assert_eq!(labels.len, 1);
+        assert_eq!(unsafe { labels.as_vec() }, vec![&home]);
+        unsafe { label_list_destroy(labels) };
+
+        let items = unsafe { list_manager_fetch_items(&manager, ptr::null()) };
+        assert_eq!(items.len, 1);
+        let milk = unsafe { &**items.data };
+        assert_eq!(milk.name, "milk");
+        assert_eq!(milk.labels, vec![Arc::new(home)]);
+        unsafe { item_list_destroy(items) };
+    }
+
+    #[test]
+    fn test_calls_from_many_threads() {
+        use std::ptr;
+        use std::thread;
+        use ffi_utils::strings::string_destroy;
+        use items::item_list_destroy;
+        use super::list_manager_create_item;
+
+        let manager = Box::into_raw(Box::new(Arc::new(list_manager())));
+        // raw pointers are not Send, but the library lock makes sharing this one safe
+        let address = manager as usize;
+        let threads: Vec<_> = (0..4).map(|t| {
+            thread::spawn(move || {
+                let manager = address as *const Arc<ListManager>;
+                for i in 0..25 {
+                    let item = Item::named(&format!("item {} {}", t, i));
+                    unsafe {
+                        let uuid = list_manager_create_item(manager, &item, ptr::null_mut());
+                        assert!(!uuid.is_null());
+                        string_destroy(uuid);
+                        item_list_destroy(list_manager_fetch_items(manager, ptr::null()));
+                    }
+                }
+            })
+        }).collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        let manager = unsafe { Box::from_raw(manager) };
+        assert_eq!(manager.count_items(&ItemFilter::default()), 100);
+    }
 }
@@ -0,0 +1,433 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! A flat variant of the item and label functions for .NET's P/Invoke, so that a Xamarin or
+//! MAUI client can use the core without custom marshalling. Every struct is blittable:
+//! strings are UTF-16 code units with a length, dates are milliseconds since 1970 with a
+//! flag, and flags are `i32` rather than `bool`, whose size .NET does not agree on.
+//!
+//! Fetches return a boxed list of records that a `SafeHandle` can own. Its destroy function
+//! takes the pointer alone, ignores null and may be called from any thread, as the
+//! finalizer's is not the one that fetched. `list_manager_destroy` already works the same.
+
+use std::borrow::Borrow;
+use std::ptr;
+use std::sync::Arc;
+
+use ffi_utils::breadcrumbs;
+use ffi_utils::errors::{
+    set_extern_error,
+    ErrorCategory,
+    ExternError,
+};
+use ffi_utils::strings::{
+    string_to_utf16,
+    utf16_to_string,
+    Utf16String,
+};
+use dates::millis;
+use filters::ItemFilter;
+use items::{
+    date_set_ms,
+    Item,
+};
+use labels::Label;
+use store::errors::set_sqlite_error;
+use ListManager;
+
+/// An item as fetched, without its labels. The strings belong to the list the record is in.
+#[repr(C)]
+#[derive(Debug)]
+pub struct ItemRecord {
+    pub uuid: Utf16String,
+    pub name: Utf16String,
+    pub due_date_ms: i64,
+    pub completion_date_ms: i64,
+    /// 1 if `due_date_ms` holds a date, otherwise 0.
+    pub has_due_date: i32,
+    pub has_completion_date: i32,
+}
+
+/// A label as fetched. The strings belong to the list the record is in.
+#[repr(C)]
+#[derive(Debug)]
+pub struct LabelRecord {
+    pub name: Utf16String,
+    pub color: Utf16String,
+}
+
+/// Records and the UTF-16 buffers their strings point into.
+pub struct RecordList<T> {
+    records: Vec<T>,
+    strings: Vec<Vec<u16>>,
+}
+
+/// `struct item_records` in pinvoke.h.
+pub type ItemRecords = RecordList<ItemRecord>;
+/// `struct label_records` in pinvoke.h.
+pub type LabelRecords = RecordList<LabelRecord>;
+
+impl<T> RecordList<T> {
+    fn new() -> RecordList<T> {
+        RecordList {
+            records: vec![],
+            strings: vec![],
+        }
+    }
+
+    /// The buffer moves into `strings` but its units stay where they are, so the pointer
+    /// stays good for as long as the list.
+    fn string(&mut self, value: &str) -> Utf16String {
+        let mut units: Vec<u16> = value.encode_utf16().collect();
+        let string = Utf16String {
+            len: units.len() as i64,
+            data: if units.is_empty() { ptr::null_mut() } else { units.as_mut_ptr() },
+        };
+        self.strings.push(units);
+        string
+    }
+
+    fn get(&self, index: i32) -> *const T {
+        if index < 0 {
+            return ptr::null();
+        }
+        self.records.get(index as usize).map_or(ptr::null(), |record| record as *const T)
+    }
+}
+
+impl ItemRecords {
+    fn from_items(items: &[Item]) -> *mut ItemRecords {
+        let mut list = ItemRecords::new();
+        for item in items {
+            let record = ItemRecord {
+                uuid: list.string(&item.uuid),
+                name: list.string(&item.name),
+                due_date_ms: item.due_date.map_or(0, millis),
+                completion_date_ms: item.completion_date.map_or(0, millis),
+                has_due_date: item.due_date.is_some() as i32,
+                has_completion_date: item.completion_date.is_some() as i32,
+            };
+            list.records.push(record);
+        }
+        Box::into_raw(Box::new(list))
+    }
+}
+
+impl LabelRecords {
+    fn from_labels<L: Borrow<Label>>(labels: &[L]) -> *mut LabelRecords {
+        let mut list = LabelRecords::new();
+        for label in labels.iter().map(Borrow::borrow) {
+            let record = LabelRecord {
+                name: list.string(&label.name),
+                color: list.string(&label.color),
+            };
+            list.records.push(record);
+        }
+        Box::into_raw(Box::new(list))
+    }
+}
+
+/// Strings come from .NET as a pointer and a length in code units, e.g. `s` and `s.Length`.
+unsafe fn utf16_argument(data: *const u16, len: i32) -> String {
+    utf16_to_string(data, if len < 0 { 0 } else { len as usize })
+}
+
+unsafe fn utf16_record_string(string: &Utf16String) -> String {
+    utf16_to_string(string.data, if string.len < 0 { 0 } else { string.len as usize })
+}
+
+/// Every item if `completed` is negative, otherwise the completed items if it is 1 and the
+/// open ones if it is 0.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn pinvoke_fetch_items(manager: *const Arc<ListManager>, completed: i32) -> *mut ItemRecords {
+    ffi_lock!();
+    ffi_guard!({
+        let manager = ffi_ref!("pinvoke_fetch_items", manager, ptr::null_mut());
+        let filter = ItemFilter {
+            completed: if completed < 0 { None } else { Some(completed != 0) },
+            ..ItemFilter::default()
+        };
+        ItemRecords::from_items(&manager.fetch_items(&filter))
+    })
+}
+
+/// A list holding the item, or null if there is no item with that uuid.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn pinvoke_fetch_item(manager: *const Arc<ListManager>, uuid: *const u16, uuid_len: i32) -> *mut ItemRecords {
+    ffi_lock!();
+    ffi_guard!({
+        let manager = ffi_ref!("pinvoke_fetch_item", manager, ptr::null_mut());
+        match manager.fetch_item(&utf16_argument(uuid, uuid_len)) {
+            Some(item) => ItemRecords::from_items(&[item]),
+            None => ptr::null_mut(),
+        }
+    })
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn pinvoke_item_records_len(records: *const ItemRecords) -> i32 {
+    ffi_guard!({
+        let records = ffi_ref!("pinvoke_item_records_len", records, 0);
+        records.records.len() as i32
+    })
+}
+
+/// The record at `index`, valid until the list is destroyed, or null if out of range.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn pinvoke_item_records_get(records: *const ItemRecords, index: i32) -> *const ItemRecord {
+    ffi_guard!({
+        let records = ffi_ref!("pinvoke_item_records_get", records, ptr::null());
+        records.get(index)
+    })
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn pinvoke_item_records_destroy(records: *mut ItemRecords) {
+    ffi_guard!({
+        if records.is_null() {
+            return;
+        }
+        let _ = Box::from_raw(records);
+    })
+}
+
+/// Creates an item with that name and due date, and returns its uuid, released with
+/// `utf16_string_destroy`. Returns an empty string and fills in `error` on failure.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn pinvoke_create_item(manager: *const Arc<ListManager>, name: *const u16, name_len: i32, has_due_date: i32, due_date_ms: i64, error: *mut ExternError) -> Utf16String {
+    ffi_lock!();
+    ffi_guard!(error, {
+        let manager = ffi_ref!("pinvoke_create_item", manager, string_to_utf16(""), error);
+        breadcrumbs::leave("list.pinvoke_create_item");
+        let mut item = Item {
+            uuid: "".to_string(),
+            name: utf16_argument(name, name_len),
+            due_date: None,
+            completion_date: None,
+            labels: vec![],
+        };
+        if !date_set_ms(&mut item.due_date, has_due_date != 0, due_date_ms, error) {
+            return string_to_utf16("");
+        }
+        match manager.try_create_item(&item) {
+            Ok(uuid) => string_to_utf16(&uuid),
+            Err(e) => {
+                log_error!("failed to create item {:?}", e);
+                set_sqlite_error(error, &e);
+                string_to_utf16("")
+            },
+        }
+    })
+}
+
+/// Writes the record's name and dates to the item with its uuid, keeping its labels.
+/// Returns 1, or 0 and fills in `error` if there is no such item or it could not be written.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn pinvoke_update_item(manager: *const Arc<ListManager>, record: *const ItemRecord, error: *mut ExternError) -> i32 {
+    ffi_lock!();
+    ffi_guard!(error, {
+        let manager = ffi_ref!("pinvoke_update_item", manager, 0, error);
+        let record = ffi_ref!("pinvoke_update_item", record, 0, error);
+        let uuid = utf16_record_string(&record.uuid);
+        let mut item = match manager.fetch_item(&uuid) {
+            Some(item) => item,
+            None => {
+                set_extern_error(error, ErrorCategory::Bug, format!("no item with uuid {}", uuid));
+                return 0;
+            },
+        };
+        item.name = utf16_record_string(&record.name);
+        if !date_set_ms(&mut item.due_date, record.has_due_date != 0, record.due_date_ms, error) ||
+           !date_set_ms(&mut item.completion_date, record.has_completion_date != 0, record.completion_date_ms, error) {
+            return 0;
+        }
+        match manager.try_save_item(&item) {
+            Ok(()) => 1,
+            Err(e) => {
+                log_error!("failed to update item {:?}", e);
+                set_sqlite_error(error, &e);
+                0
+            },
+        }
+    })
+}
+
+/// Returns 1 if the item was deleted, or 0 if there was none or it could not be deleted, in
+/// which case `error` is filled in.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn pinvoke_delete_item(manager: *const Arc<ListManager>, uuid: *const u16, uuid_len: i32, error: *mut ExternError) -> i32 {
+    ffi_lock!();
+    ffi_guard!(error, {
+        let manager = ffi_ref!("pinvoke_delete_item", manager, 0, error);
+        match manager.try_delete_item(&utf16_argument(uuid, uuid_len)) {
+            Ok(deleted) => deleted as i32,
+            Err(e) => {
+                log_error!("failed to delete item {:?}", e);
+                set_sqlite_error(error, &e);
+                0
+            },
+        }
+    })
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn pinvoke_fetch_labels(manager: *const Arc<ListManager>) -> *mut LabelRecords {
+    ffi_lock!();
+    ffi_guard!({
+        let manager = ffi_ref!("pinvoke_fetch_labels", manager, ptr::null_mut());
+        LabelRecords::from_labels(&manager.fetch_labels())
+    })
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn pinvoke_fetch_item_labels(manager: *const Arc<ListManager>, uuid: *const u16, uuid_len: i32) -> *mut LabelRecords {
+    ffi_lock!();
+    ffi_guard!({
+        let manager = ffi_ref!("pinvoke_fetch_item_labels", manager, ptr::null_mut());
+        LabelRecords::from_labels(&manager.fetch_labels_for_item(&utf16_argument(uuid, uuid_len)))
+    })
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn pinvoke_label_records_len(records: *const LabelRecords) -> i32 {
+    ffi_guard!({
+        let records = ffi_ref!("pinvoke_label_records_len", records, 0);
+        records.records.len() as i32
+    })
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn pinvoke_label_records_get(records: *const LabelRecords, index: i32) -> *const LabelRecord {
+    ffi_guard!({
+        let records = ffi_ref!("pinvoke_label_records_get", records, ptr::null());
+        records.get(index)
+    })
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn pinvoke_label_records_destroy(records: *mut LabelRecords) {
+    ffi_guard!({
+        if records.is_null() {
+            return;
+        }
+        let _ = Box::from_raw(records);
+    })
+}
+
+/// Returns 1, or 0 and fills in `error` if the label could not be created, e.g. because
+/// one with that name exists.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn pinvoke_create_label(manager: *const Arc<ListManager>, name: *const u16, name_len: i32, color: *const u16, color_len: i32, error: *mut ExternError) -> i32 {
+    ffi_lock!();
+    ffi_guard!(error, {
+        let manager = ffi_ref!("pinvoke_create_label", manager, 0, error);
+        match manager.try_create_label(utf16_argument(name, name_len), utf16_argument(color, color_len)) {
+            Ok(_) => 1,
+            Err(e) => {
+                log_error!("failed to create label {:?}", e);
+                set_sqlite_error(error, &e);
+                0
+            },
+        }
+    })
+}
+
+/// Returns 1 if the label was deleted, or 0 if there was none or it could not be deleted,
+/// in which case `error` is filled in.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn pinvoke_delete_label(manager: *const Arc<ListManager>, name: *const u16, name_len: i32, error: *mut ExternError) -> i32 {
+    ffi_lock!();
+    ffi_guard!(error, {
+        let manager = ffi_ref!("pinvoke_delete_label", manager, 0, error);
+        match manager.try_delete_label(&utf16_argument(name, name_len)) {
+            Ok(deleted) => deleted as i32,
+            Err(e) => {
+                log_error!("failed to delete label {:?}", e);
+                set_sqlite_error(error, &e);
+                0
+            },
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::ptr;
+    use std::slice;
+    use std::sync::Arc;
+
+    use ffi_utils::strings::{
+        utf16_string_destroy,
+        utf16_to_string,
+    };
+    use store::Store;
+    use ListManager;
+
+    use super::{
+        pinvoke_create_item,
+        pinvoke_create_label,
+        pinvoke_delete_item,
+        pinvoke_fetch_item,
+        pinvoke_fetch_items,
+        pinvoke_fetch_labels,
+        pinvoke_item_records_destroy,
+        pinvoke_item_records_get,
+        pinvoke_item_records_len,
+        pinvoke_label_records_destroy,
+        pinvoke_label_records_get,
+        pinvoke_label_records_len,
+        pinvoke_update_item,
+    };
+
+    fn utf16(value: &str) -> Vec<u16> {
+        value.encode_utf16().collect()
+    }
+
+    #[test]
+    fn test_pinvoke_items() {
+        let manager = Arc::new(ListManager::new(Arc::new(Store::new(None))));
+        let name = utf16("café ☕");
+        unsafe {
+            let uuid = pinvoke_create_item(&manager, name.as_ptr(), name.len() as i32, 1, 1510315200000, ptr::null_mut());
+            let uuid_units: Vec<u16> = slice::from_raw_parts(uuid.data, uuid.len as usize).to_vec();
+            utf16_string_destroy(uuid);
+
+            let records = pinvoke_fetch_items(&manager, 0);
+            assert_eq!(pinvoke_item_records_len(records), 1);
+            assert!(pinvoke_item_records_get(records, 1).is_null());
+            let record = &*pinvoke_item_records_get(records, 0);
+            assert_eq!(utf16_to_string(record.name.data, record.name.len as usize), "café ☕");
+            assert_eq!((record.has_due_date, record.due_date_ms, record.has_completion_date), (1, 1510315200000, 0));
+            pinvoke_item_records_destroy(records);
+            pinvoke_item_records_destroy(ptr::null_mut());
+
+            let records = pinvoke_fetch_item(&manager, uuid_units.as_ptr(), uuid_units.len() as i32);
+            let mut record = ptr::read(pinvoke_item_records_get(records, 0));
+            record.has_completion_date = 1;
+            record.completion_date_ms = 1510318800000;
+            assert_eq!(pinvoke_update_item(&manager, &record, ptr::null_mut()), 1);
+            pinvoke_item_records_destroy(records);
+
+            let open = pinvoke_fetch_items(&manager, 0);
+            assert_eq!(pinvoke_item_records_len(open), 0);
+            pinvoke_item_records_destroy(open);
+            assert_eq!(pinvoke_delete_item(&manager, uuid_units.as_ptr(), uuid_units.len() as i32, ptr::null_mut()), 1);
+            assert!(pinvoke_fetch_item(&manager, uuid_units.as_ptr(), uuid_units.len() as i32).is_null());
+
+            let (label, color) = (utf16("home"), utf16("#00ff00"));
+            assert_eq!(pinvoke_create_label(&manager, label.as_ptr(), label.len() as i32, color.as_ptr(), color.len() as i32, ptr::null_mut()), 1);
+            let labels = pinvoke_fetch_labels(&manager);
+            assert_eq!(pinvoke_label_records_len(labels), 1);
+            let record = &*pinvoke_label_records_get(labels, 0);
+            assert_eq!(utf16_to_string(record.color.data, record.color.len as usize), "#00ff00");
+            pinvoke_label_records_destroy(labels);
+        }
+    }
+}
@@ -0,0 +1,202 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+use std::os::raw::c_char;
+use std::ptr;
+use std::sync::Arc;
+
+use rusqlite;
+use rusqlite::types::ToSql;
+
+use ffi_utils::errors::ExternError;
+use ffi_utils::strings::{
+    string_to_c_char,
+    c_char_to_string,
+};
+use store::errors::set_sqlite_error;
+use store::migrations::MigrationStep;
+use ListManager;
+
+pub const CREATE_PREFS_TABLE: MigrationStep = MigrationStep {
+    name: "create_prefs_table",
+    sql: r#"CREATE TABLE IF NOT EXISTS prefs (
+            key TEXT NOT NULL PRIMARY KEY,
+            kind TEXT NOT NULL,
+            value
+        )"#,
+};
+
+/// The name of the label new items go to when none is picked.
+pub const DEFAULT_LIST: &'static str = "default_list";
+/// The first day of the week, 0 for Sunday to 6 for Saturday.
+pub const WEEK_START_DAY: &'static str = "week_start_day";
+
+const KIND_STRING: &'static str = "string";
+const KIND_INT: &'static str = "int";
+const KIND_BOOL: &'static str = "bool";
+
+/// Host settings kept in the same database as the lists. Each key holds a string, an
+/// integer or a boolean; reading a key as a different type finds nothing.
+impl ListManager {
+    fn set_pref(&self, key: &str, kind: &str, value: &dyn ToSql) -> rusqlite::Result<()> {
+        let sql = r#"INSERT OR REPLACE INTO prefs (key, kind, value) VALUES (?, ?, ?)"#;
+        self.get_store().get_conn().execute(sql, &[&key, &kind, value]).map(|_| ())
+    }
+
+    fn get_pref<T: rusqlite::types::FromSql>(&self, key: &str, kind: &str) -> Option<T> {
+        let sql = r#"SELECT value FROM prefs WHERE key=? AND kind=?"#;
+        self.get_store().get_conn().query_row(sql, &[&key, &kind], |row| row.get_checked(0)).ok().and_then(|value| value.ok())
+    }
+
+    pub fn set_pref_string(&self, key: &str, value: &str) -> rusqlite::Result<()> {
+        self.set_pref(key, KIND_STRING, &value)
+    }
+
+    pub fn set_pref_int(&self, key: &str, value: i64) -> rusqlite::Result<()> {
+        self.set_pref(key, KIND_INT, &value)
+    }
+
+    pub fn set_pref_bool(&self, key: &str, value: bool) -> rusqlite::Result<()> {
+        self.set_pref(key, KIND_BOOL, &value)
+    }
+
+    pub fn pref_string(&self, key: &str) -> Option<String> {
+        self.get_pref(key, KIND_STRING)
+    }
+
+    pub fn pref_int(&self, key: &str) -> Option<i64> {
+        self.get_pref(key, KIND_INT)
+    }
+
+    pub fn pref_bool(&self, key: &str) -> Option<bool> {
+        self.get_pref(key, KIND_BOOL)
+    }
+
+    pub fn remove_pref(&self, key: &str) -> rusqlite::Result<()> {
+        self.get_store().get_conn().execute("DELETE FROM prefs WHERE key=?", &[&key]).map(|_| ())
+    }
+}
+
+/// Reports a failed write, e.g. to a read only store, and returns false.
+unsafe fn pref_written(result: rusqlite::Result<()>, error: *mut ExternError) -> bool {
+    match result {
+        Ok(()) => true,
+        Err(e) => {
+            log_error!("failed to write pref {:?}", e);
+            set_sqlite_error(error, &e);
+            false
+        },
+    }
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn prefs_set_string(manager: *const Arc<ListManager>, key: *const c_char, value: *const c_char, error: *mut ExternError) -> bool {
+    ffi_lock!();
+    ffi_guard!(error, {
+        let manager = ffi_ref!("prefs_set_string", manager, false, error);
+        pref_written(manager.set_pref_string(&c_char_to_string(key), &c_char_to_string(value)), error)
+    })
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn prefs_set_int(manager: *const Arc<ListManager>, key: *const c_char, value: i64, error: *mut ExternError) -> bool {
+    ffi_lock!();
+    ffi_guard!(error, {
+        let manager = ffi_ref!("prefs_set_int", manager, false, error);
+        pref_written(manager.set_pref_int(&c_char_to_string(key), value), error)
+    })
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn prefs_set_bool(manager: *const Arc<ListManager>, key: *const c_char, value: bool, error: *mut ExternError) -> bool {
+    ffi_lock!();
+    ffi_guard!(error, {
+        let manager = ffi_ref!("prefs_set_bool", manager, false, error);
+        pref_written(manager.set_pref_bool(&c_char_to_string(key), value), error)
+    })
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn prefs_remove(manager: *const Arc<ListManager>, key: *const c_char, error: *mut ExternError) -> bool {
+    ffi_lock!();
+    ffi_guard!(error, {
+        let manager = ffi_ref!("prefs_remove", manager, false, error);
+        pref_written(manager.remove_pref(&c_char_to_string(key)), error)
+    })
+}
+
+/// Returns null if the key is not set to a string.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn prefs_get_string(manager: *const Arc<ListManager>, key: *const c_char) -> *mut c_char {
+    ffi_lock!();
+    ffi_guard!({
+        let manager = ffi_ref!("prefs_get_string", manager, ptr::null_mut());
+        match manager.pref_string(&c_char_to_string(key)) {
+            Some(value) => string_to_c_char(value),
+            None => ptr::null_mut(),
+        }
+    })
+}
+
+/// Returns `default_value` if the key is not set to an integer.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn prefs_get_int(manager: *const Arc<ListManager>, key: *const c_char, default_value: i64) -> i64 {
+    ffi_lock!();
+    ffi_guard!({
+        let manager = ffi_ref!("prefs_get_int", manager, default_value);
+        manager.pref_int(&c_char_to_string(key)).unwrap_or(default_value)
+    })
+}
+
+/// Returns `default_value` if the key is not set to a boolean.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn prefs_get_bool(manager: *const Arc<ListManager>, key: *const c_char, default_value: bool) -> bool {
+    ffi_lock!();
+    ffi_guard!({
+        let manager = ffi_ref!("prefs_get_bool", manager);
+        manager.pref_bool(&c_char_to_string(key)).unwrap_or(default_value)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use super::{
+        DEFAULT_LIST,
+        WEEK_START_DAY,
+    };
+
+    use store::Store;
+    use ListManager;
+
+    #[test]
+    fn test_prefs() {
+        let manager = ListManager::new(Arc::new(Store::new(None)));
+        assert_eq!(manager.pref_string(DEFAULT_LIST), None);
+
+        manager.set_pref_string(DEFAULT_LIST, "Groceries").unwrap();
+        manager.set_pref_int(WEEK_START_DAY, 1).unwrap();
+        manager.set_pref_bool("show_completed", true).unwrap();
+        assert_eq!(manager.pref_string(DEFAULT_LIST), Some("Groceries".to_string()));
+        assert_eq!(manager.pref_int(WEEK_START_DAY), Some(1));
+        assert_eq!(manager.pref_bool("show_completed"), Some(true));
+
+        // a key read as another type is not set
+        assert_eq!(manager.pref_int(DEFAULT_LIST), None);
+        assert_eq!(manager.pref_string(WEEK_START_DAY), None);
+        assert_eq!(manager.pref_bool(WEEK_START_DAY), None);
+
+        manager.set_pref_int(WEEK_START_DAY, 0).unwrap();
+        assert_eq!(manager.pref_int(WEEK_START_DAY), Some(0));
+        manager.remove_pref(WEEK_START_DAY).unwrap();
+        assert_eq!(manager.pref_int(WEEK_START_DAY), None);
+    }
+}
@@ -0,0 +1,125 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+use std::os::raw::c_char;
+use std::ptr;
+use std::sync::Arc;
+
+use rusqlite;
+use rusqlite::types::Value;
+use serde_json;
+use serde_json::{
+    Map,
+    Number,
+};
+
+use ffi_utils::errors::{
+    set_extern_error,
+    ErrorCategory,
+    ExternError,
+};
+use ffi_utils::strings::{
+    string_to_c_char,
+    c_char_to_string,
+};
+use ListManager;
+
+fn value_to_json(value: Value) -> serde_json::Value {
+    match value {
+        Value::Null => serde_json::Value::Null,
+        Value::Integer(i) => serde_json::Value::Number(i.into()),
+        Value::Real(f) => Number::from_f64(f).map(serde_json::Value::Number).unwrap_or(serde_json::Value::Null),
+        Value::Text(s) => serde_json::Value::String(s),
+        Value::Blob(bytes) => {
+            let hex: Vec<String> = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+            serde_json::Value::String(hex.concat())
+        },
+    }
+}
+
+impl ListManager {
+    /// Runs a single SQL query against the store and returns the rows as a JSON array of
+    /// objects keyed by column name. Blobs are returned as hex strings. The connection is
+    /// put into query-only mode for the duration, so statements that would modify the
+    /// database fail instead. Meant for debugging tools, not for regular app features.
+    pub fn raw_query(&self, sql: &str) -> rusqlite::Result<serde_json::Value> {
+        let conn = self.get_store().get_conn();
+        conn.execute_batch("PRAGMA query_only = 1")?;
+        let result = (|| {
+            let mut stmt = conn.prepare(sql)?;
+            let columns: Vec<String> = stmt.column_names().iter().map(|c| c.to_string()).collect();
+            let mut rows = stmt.query(&[])?;
+            let mut results = Vec::new();
+            while let Some(row) = rows.next() {
+                let row = row?;
+                let mut object = Map::new();
+                for (index, column) in columns.iter().enumerate() {
+                    let value: Value = row.get_checked(index as i32)?;
+                    object.insert(column.clone(), value_to_json(value));
+                }
+                results.push(serde_json::Value::Object(object));
+            }
+            Ok(serde_json::Value::Array(results))
+        })();
+        conn.execute_batch("PRAGMA query_only = 0")?;
+        result
+    }
+}
+
+/// Returns the rows as a JSON array, or null if the query failed or would have modified
+/// the store. The query is the user's input, so failures are reported as such.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_raw_query(manager: *const Arc<ListManager>, query: *const c_char, error: *mut ExternError) -> *mut c_char {
+    ffi_lock!();
+    ffi_guard!(error, {
+        let manager = ffi_ref!("list_manager_raw_query", manager, ptr::null_mut(), error);
+        let query = c_char_to_string(query);
+        match manager.raw_query(&query) {
+            Ok(rows) => string_to_c_char(rows.to_string()),
+            Err(e) => {
+                log_error!("raw query failed {:?}", e);
+                set_extern_error(error, ErrorCategory::NeedsUserInput, e.to_string());
+                ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use items::Item;
+    use store::Store;
+    use ListManager;
+
+    #[test]
+    fn test_raw_query() {
+        let manager = ListManager::new(Arc::new(Store::new(None)));
+        manager.create_label("work".to_string(), "#ff0000".to_string());
+        manager.create_item(&Item::named("raw"));
+
+        let rows = manager.raw_query("SELECT name, color, 1 AS one, 0.5 AS half, NULL AS nothing, x'00ff' AS bytes FROM labels").unwrap();
+        assert_eq!(rows.to_string(), r##"[{"bytes":"00ff","color":"#ff0000","half":0.5,"name":"work","nothing":null,"one":1}]"##);
+
+        let rows = manager.raw_query("SELECT name, due_date FROM items").unwrap();
+        assert_eq!(rows[0]["name"], "raw");
+        assert!(rows[0]["due_date"].is_null());
+    }
+
+    #[test]
+    fn test_raw_query_is_read_only() {
+        let manager = ListManager::new(Arc::new(Store::new(None)));
+        assert!(manager.raw_query("DELETE FROM labels").is_err());
+        assert!(manager.raw_query("not sql").is_err());
+        // the connection is writable again afterwards
+        assert!(manager.create_label("work".to_string(), "#ff0000".to_string()).is_some());
+    }
+}
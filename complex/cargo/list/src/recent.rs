@@ -0,0 +1,273 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+use std::os::raw::c_char;
+use std::sync::Arc;
+use std::ptr;
+
+use rusqlite;
+use rusqlite::Connection;
+use serde_json;
+use time::{
+    Duration,
+    Timespec,
+};
+
+use ffi_utils::strings::string_to_c_char;
+use items::Item;
+use labels::Label;
+use store::migrations::MigrationStep;
+use ListManager;
+
+/// How many days a deleted item is kept for `recently_trashed`.
+pub const TRASH_RETENTION_DAYS: i64 = 30;
+
+pub const CREATE_RECENT_INDEXES: MigrationStep = MigrationStep {
+    name: "create_recent_indexes",
+    sql: r#"CREATE INDEX IF NOT EXISTS items_completion_date ON items (completion_date);
+            CREATE TABLE IF NOT EXISTS trashed_items (
+                uuid TEXT NOT NULL PRIMARY KEY,
+                item TEXT NOT NULL,
+                trashed_at DATETIME NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS trashed_items_trashed_at ON trashed_items (trashed_at);"#,
+};
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+/// A deleted item, as it was when it was deleted.
+pub struct TrashedItem {
+    #[serde(flatten)]
+    pub item: Item,
+    #[serde(serialize_with = "serialize_timespec")]
+    pub trashed_at: Timespec,
+}
+
+fn serialize_timespec<S>(date: &Timespec, serializer: S) -> Result<S::Ok, S::Error>
+where S: ::serde::Serializer {
+    serializer.serialize_i64(date.sec)
+}
+
+impl ListManager {
+    fn days_ago(&self, days: i64) -> Timespec {
+        self.now() - Duration::days(days)
+    }
+
+    /// Items completed during the last `days` days, most recently completed first.
+    pub fn recently_completed(&self, days: i64) -> Vec<Item> {
+        let sql = r#"SELECT items.uuid, items.name, items.due_date, items.completion_date, labels.name, labels.color
+                     FROM items
+                     LEFT JOIN item_labels ON item_labels.item_uuid=items.uuid
+                     LEFT JOIN labels ON labels.name=item_labels.label_name
+                     WHERE items.completion_date >= ?
+                     ORDER BY items.completion_date DESC, items.rowid, item_labels.rowid"#;
+        let conn = self.get_store().get_conn();
+        let mut stmt = conn.prepare(sql).unwrap();
+        let row_iter = stmt.query_map(&[&self.days_ago(days)], |row| {
+            let item = Item {
+                uuid: row.get(0),
+                name: row.get(1),
+                due_date: row.get(2),
+                completion_date: row.get(3),
+                labels: vec![],
+            };
+            let label: Option<String> = row.get(4);
+            (item, label.map(|name| Label { name: name, color: row.get(5) }))
+        }).unwrap();
+        // one row per label, the rows of each item next to each other
+        let mut items: Vec<Item> = vec![];
+        for (item, label) in row_iter.filter_map(|result| result.ok()) {
+            if items.last().map(|last| last.uuid != item.uuid).unwrap_or(true) {
+                items.push(item);
+            }
+            if let Some(label) = label {
+                let shared = self.share_label(&label);
+                items.last_mut().unwrap().labels.push(shared);
+            }
+        }
+        items
+    }
+
+    pub fn count_recently_completed(&self, days: i64) -> i64 {
+        let sql = r#"SELECT count(*) FROM items WHERE completion_date >= ?"#;
+        self.get_store().get_conn().query_row(sql, &[&self.days_ago(days)], |row| row.get(0)).unwrap()
+    }
+
+    /// Keeps a copy of an item that is about to be deleted, dropping copies kept for longer
+    /// than `TRASH_RETENTION_DAYS`. Expected to run inside the transaction deleting it.
+    pub(crate) fn trash_item(&self, conn: &Connection, item: &Item) -> rusqlite::Result<()> {
+        self.expire_trash(conn)?;
+        let sql = r#"INSERT OR REPLACE INTO trashed_items (uuid, item, trashed_at) VALUES (?, ?, ?)"#;
+        let json = serde_json::to_string(item).unwrap();
+        conn.execute(sql, &[&item.uuid, &json, &self.now()]).map(|_| ())
+    }
+
+    /// Drops the copies of items deleted more than `TRASH_RETENTION_DAYS` ago.
+    pub(crate) fn expire_trash(&self, conn: &Connection) -> rusqlite::Result<()> {
+        let sql = r#"DELETE FROM trashed_items WHERE trashed_at < ?"#;
+        conn.execute(sql, &[&self.days_ago(TRASH_RETENTION_DAYS)]).map(|_| ())
+    }
+
+    /// Items deleted during the last `days` days, most recently deleted first.
+    pub fn recently_trashed(&self, days: i64) -> Vec<TrashedItem> {
+        let sql = r#"SELECT item, trashed_at FROM trashed_items WHERE trashed_at >= ? ORDER BY trashed_at DESC"#;
+        let conn = self.get_store().get_conn();
+        let mut stmt = conn.prepare(sql).unwrap();
+        let trashed_iter = stmt.query_map(&[&self.days_ago(days)], |row| {
+            let item: String = row.get(0);
+            (item, row.get(1))
+        }).unwrap();
+        trashed_iter.filter_map(|result| result.ok()).filter_map(|(item, trashed_at)| {
+            serde_json::from_str(&item).ok().map(|item| TrashedItem {
+                item: item,
+                trashed_at: trashed_at,
+            })
+        }).collect()
+    }
+
+    pub fn count_recently_trashed(&self, days: i64) -> i64 {
+        let sql = r#"SELECT count(*) FROM trashed_items WHERE trashed_at >= ?"#;
+        self.get_store().get_conn().query_row(sql, &[&self.days_ago(days)], |row| row.get(0)).unwrap()
+    }
+}
+
+/// Returns the items as a JSON array, most recently completed first.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_recently_completed(manager: *const Arc<ListManager>, days: i64) -> *mut c_char {
+    ffi_lock!();
+    ffi_guard!({
+        let manager = ffi_ref!("list_manager_recently_completed", manager, ptr::null_mut());
+        string_to_c_char(serde_json::to_string(&manager.recently_completed(days)).unwrap())
+    })
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_recently_completed_count(manager: *const Arc<ListManager>, days: i64) -> i64 {
+    ffi_lock!();
+    ffi_guard!({
+        let manager = ffi_ref!("list_manager_recently_completed_count", manager);
+        manager.count_recently_completed(days)
+    })
+}
+
+/// Returns the items as a JSON array, most recently deleted first, each with a
+/// `trashed_at` key.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_recently_trashed(manager: *const Arc<ListManager>, days: i64) -> *mut c_char {
+    ffi_lock!();
+    ffi_guard!({
+        let manager = ffi_ref!("list_manager_recently_trashed", manager, ptr::null_mut());
+        string_to_c_char(serde_json::to_string(&manager.recently_trashed(days)).unwrap())
+    })
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_recently_trashed_count(manager: *const Arc<ListManager>, days: i64) -> i64 {
+    ffi_lock!();
+    ffi_guard!({
+        let manager = ffi_ref!("list_manager_recently_trashed_count", manager);
+        manager.count_recently_trashed(days)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use time::{
+        Duration,
+        Timespec,
+    };
+
+    use super::TRASH_RETENTION_DAYS;
+
+    use items::Item;
+    use store::Store;
+    use ListManager;
+
+    fn item(name: &str, completion_date: Option<Timespec>) -> Item {
+        let mut item = Item::named(name);
+        item.completion_date = completion_date;
+        item
+    }
+
+    #[test]
+    fn test_recently_completed() {
+        let manager = ListManager::new(Arc::new(Store::new(None)));
+        let now = Timespec::new(1510315200, 0);
+        manager.freeze_clock(Some(now));
+        manager.create_item(&item("open", None));
+        manager.create_item(&item("last week", Some(now - Duration::days(6))));
+        manager.create_item(&item("last month", Some(now - Duration::days(30))));
+        manager.create_item(&item("yesterday", Some(now - Duration::days(1))));
+
+        let names: Vec<String> = manager.recently_completed(7).into_iter().map(|i| i.name.clone()).collect();
+        assert_eq!(names, vec!["yesterday", "last week"]);
+        assert_eq!(manager.count_recently_completed(7), 2);
+        assert_eq!(manager.count_recently_completed(31), 3);
+
+        let home = manager.create_label("home".to_string(), "#000000".to_string()).unwrap();
+        let work = manager.create_label("work".to_string(), "#ffffff".to_string()).unwrap();
+        let mut labelled = item("today", Some(now));
+        labelled.labels = vec![manager.share_label(&home), manager.share_label(&work)];
+        labelled.uuid = manager.create_item(&labelled);
+        let recent = manager.recently_completed(7);
+        assert_eq!(recent[0], labelled);
+        assert_eq!(recent[1].labels, vec![]);
+    }
+
+    #[test]
+    fn test_recently_trashed() {
+        let manager = ListManager::new(Arc::new(Store::new(None)));
+        let now = Timespec::new(1510315200, 0);
+        let label = manager.create_label("home".to_string(), "#000000".to_string()).unwrap();
+        let mut old = item("old", None);
+        old.uuid = manager.create_item(&old);
+        let mut recent = item("recent", None);
+        recent.labels = vec![Arc::new(label)];
+        recent.uuid = manager.create_item(&recent);
+
+        manager.freeze_clock(Some(now - Duration::days(10)));
+        manager.delete_item(&old.uuid);
+        manager.freeze_clock(Some(now));
+        manager.delete_item(&recent.uuid);
+
+        let trashed = manager.recently_trashed(7);
+        assert_eq!(trashed.len(), 1);
+        assert_eq!(trashed[0].item, recent);
+        assert_eq!(trashed[0].trashed_at, now);
+        assert_eq!(manager.count_recently_trashed(7), 1);
+        assert_eq!(manager.count_recently_trashed(30), 2);
+    }
+
+    #[test]
+    fn test_trash_expires() {
+        let manager = ListManager::new(Arc::new(Store::new(None)));
+        let now = Timespec::new(1510315200, 0);
+        let old = manager.create_item(&item("old", None));
+        let recent = manager.create_item(&item("recent", None));
+        manager.freeze_clock(Some(now - Duration::days(TRASH_RETENTION_DAYS + 1)));
+        manager.delete_item(&old);
+        manager.freeze_clock(Some(now - Duration::days(1)));
+        manager.delete_item(&recent);
+        assert_eq!(manager.count_recently_trashed(365), 2);
+
+        // a day later, the old copy has been kept long enough
+        manager.freeze_clock(Some(now));
+        manager.on_significant_time_change(0);
+        manager.freeze_clock(Some(now + Duration::days(1)));
+        manager.on_significant_time_change(0);
+        assert_eq!(manager.count_recently_trashed(365), 1);
+
+        let newest = manager.create_item(&item("newest", None));
+        manager.freeze_clock(Some(now + Duration::days(TRASH_RETENTION_DAYS)));
+        manager.delete_item(&newest);
+        assert_eq!(manager.recently_trashed(365).iter().map(|t| t.item.name.clone()).collect::<Vec<String>>(), vec!["newest"]);
+    }
+}
@@ -0,0 +1,336 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! A recurring item is a series of ordinary items, one per occurrence, each due one step
+//! after the previous. Deleting an occurrence follows the choices calendar apps offer: just
+//! this one, this one and those after it, or the whole series.
+
+use std::os::raw::c_char;
+use std::ptr;
+use std::sync::Arc;
+
+use rusqlite;
+use serde_json;
+use time::Duration;
+
+use ffi_utils::breadcrumbs;
+use ffi_utils::errors::{
+    set_extern_error,
+    ErrorCategory,
+    ExternError,
+};
+use ffi_utils::strings::{
+    string_to_c_char,
+    c_char_to_string,
+};
+use items::Item;
+use store::errors::set_sqlite_error;
+use store::migrations::MigrationStep;
+use ListManager;
+
+pub const CREATE_RECURRENCE_TABLES: MigrationStep = MigrationStep {
+    name: "create_recurrence_tables",
+    sql: r#"CREATE TABLE IF NOT EXISTS recurring_series (
+                id TEXT NOT NULL PRIMARY KEY,
+                frequency INTEGER NOT NULL,
+                interval INTEGER NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS series_occurrences (
+                item_uuid TEXT NOT NULL PRIMARY KEY,
+                series_id TEXT NOT NULL,
+                occurrence INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS series_occurrences_series_id ON series_occurrences (series_id, occurrence);"#,
+};
+
+/// The `SeriesDeletion` used when `SeriesDeletion::Default` is asked for, stored as an int.
+pub const SERIES_DELETION: &'static str = "series_deletion";
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Frequency {
+    Daily = 0,
+    Weekly = 1,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SeriesDeletion {
+    /// Whatever the `series_deletion` pref says, or `ThisOccurrence` if it is not set.
+    Default = 0,
+    ThisOccurrence = 1,
+    /// This occurrence and every later one, ending the series before it.
+    ThisAndFuture = 2,
+    EntireSeries = 3,
+}
+
+impl Frequency {
+    fn from_i64(value: i64) -> Option<Frequency> {
+        match value {
+            0 => Some(Frequency::Daily),
+            1 => Some(Frequency::Weekly),
+            _ => None,
+        }
+    }
+}
+
+impl SeriesDeletion {
+    fn from_i64(value: i64) -> Option<SeriesDeletion> {
+        match value {
+            1 => Some(SeriesDeletion::ThisOccurrence),
+            2 => Some(SeriesDeletion::ThisAndFuture),
+            3 => Some(SeriesDeletion::EntireSeries),
+            _ => None,
+        }
+    }
+}
+
+pub(crate) fn step(frequency: Frequency, interval: i64) -> Duration {
+    match frequency {
+        Frequency::Daily => Duration::days(interval),
+        Frequency::Weekly => Duration::weeks(interval),
+    }
+}
+
+impl ListManager {
+    /// Creates `count` occurrences of `item`, the first due at its due date and each later one
+    /// `interval` days or weeks after the previous. Returns their uuids, or `None` if the item
+    /// has no due date or `count` or `interval` is not positive.
+    pub fn create_recurring_item(&self, item: &Item, frequency: Frequency, interval: i64, count: i64) -> Option<Vec<String>> {
+        self.try_create_recurring_item(item, frequency, interval, count).unwrap_or_else(|e| {
+            log_error!("failed to create recurring item {:?}", e);
+            None
+        })
+    }
+
+    /// Like `create_recurring_item`, but no occurrence is written and the error is returned
+    /// if any of them cannot be.
+    pub fn try_create_recurring_item(&self, item: &Item, frequency: Frequency, interval: i64, count: i64) -> rusqlite::Result<Option<Vec<String>>> {
+        let due_date = match item.due_date {
+            Some(due_date) if interval >= 1 && count >= 1 => due_date,
+            _ => return Ok(None),
+        };
+        breadcrumbs::leave("list.create_recurring_item");
+        let series_id = self.ids.next_uuid();
+        let store = self.get_store();
+        let conn = store.get_conn();
+        let tx = store.begin_transaction()?;
+        conn.execute("INSERT INTO recurring_series (id, frequency, interval) VALUES (?, ?, ?)",
+                     &[&series_id, &(frequency as i64), &interval])?;
+        let mut occurrence = item.clone();
+        let mut uuids = vec![];
+        for index in 0..count {
+            occurrence.due_date = Some(due_date + step(frequency, interval * index));
+            let uuid = self.try_create_item(&occurrence)?;
+            conn.execute("INSERT INTO series_occurrences (item_uuid, series_id, occurrence) VALUES (?, ?, ?)",
+                         &[&uuid, &series_id, &index])?;
+            uuids.push(uuid);
+        }
+        tx.commit()?;
+        Ok(Some(uuids))
+    }
+
+    /// The uuids of the occurrences in the item's series, in order, or `None` if the item
+    /// does not recur.
+    pub fn series_occurrences(&self, uuid: &String) -> Option<Vec<String>> {
+        let sql = r#"SELECT item_uuid FROM series_occurrences
+                     WHERE series_id=(SELECT series_id FROM series_occurrences WHERE item_uuid=?)
+                     ORDER BY occurrence"#;
+        let conn = self.get_store().get_conn();
+        let mut stmt = conn.prepare(sql).unwrap();
+        let uuids: Vec<String> = stmt.query_map(&[uuid], |row| row.get(0)).unwrap().filter_map(|result| result.ok()).collect();
+        if uuids.is_empty() { None } else { Some(uuids) }
+    }
+
+    /// The id, frequency and interval of the item's series, or `None` if it does not recur.
+    pub(crate) fn series_rule(&self, uuid: &String) -> Option<(String, Frequency, i64)> {
+        let sql = r#"SELECT id, frequency, interval FROM recurring_series
+                     WHERE id=(SELECT series_id FROM series_occurrences WHERE item_uuid=?)"#;
+        let rule: Option<(String, i64, i64)> = self.get_store().get_conn().query_row(sql, &[uuid], |row| {
+            (row.get(0), row.get(1), row.get(2))
+        }).ok();
+        let (id, frequency, interval) = rule?;
+        Some((id, Frequency::from_i64(frequency)?, interval))
+    }
+
+    fn resolve_series_deletion(&self, deletion: SeriesDeletion) -> SeriesDeletion {
+        match deletion {
+            SeriesDeletion::Default => self.pref_int(SERIES_DELETION)
+                .and_then(SeriesDeletion::from_i64)
+                .unwrap_or(SeriesDeletion::ThisOccurrence),
+            deletion => deletion,
+        }
+    }
+
+    /// Deletes the item and, if it recurs, the other occurrences `deletion` covers. Returns
+    /// the uuids of the deleted items, empty if there is no item with that uuid.
+    pub fn delete_recurring_item(&self, uuid: &String, deletion: SeriesDeletion) -> Vec<String> {
+        self.try_delete_recurring_item(uuid, deletion).unwrap_or_else(|e| {
+            log_error!("failed to delete recurring item {:?}", e);
+            vec![]
+        })
+    }
+
+    /// Like `delete_recurring_item`, but nothing is deleted and the error is returned if any
+    /// of the occurrences cannot be.
+    pub fn try_delete_recurring_item(&self, uuid: &String, deletion: SeriesDeletion) -> rusqlite::Result<Vec<String>> {
+        breadcrumbs::leave("list.delete_recurring_item");
+        let sql = match self.resolve_series_deletion(deletion) {
+            SeriesDeletion::EntireSeries => r#"SELECT item_uuid FROM series_occurrences
+                                               WHERE series_id=(SELECT series_id FROM series_occurrences WHERE item_uuid=?1)
+                                               ORDER BY occurrence"#,
+            SeriesDeletion::ThisAndFuture => r#"SELECT others.item_uuid FROM series_occurrences this
+                                                JOIN series_occurrences others ON others.series_id=this.series_id
+                                                WHERE this.item_uuid=?1 AND others.occurrence >= this.occurrence
+                                                ORDER BY others.occurrence"#,
+            _ => r#"SELECT ?1"#,
+        };
+        let store = self.get_store();
+        let tx = store.begin_transaction()?;
+        let mut uuids: Vec<String> = {
+            let conn = store.get_conn();
+            let mut stmt = conn.prepare(sql)?;
+            let uuid_iter = stmt.query_map(&[uuid], |row| row.get(0))?;
+            uuid_iter.collect::<rusqlite::Result<_>>()?
+        };
+        if uuids.is_empty() {
+            uuids.push(uuid.clone());
+        }
+        let mut deleted = vec![];
+        for uuid in uuids {
+            if self.try_delete_item(&uuid)? {
+                deleted.push(uuid);
+            }
+        }
+        tx.commit()?;
+        Ok(deleted)
+    }
+}
+
+/// Returns the uuids of the occurrences as a JSON array, or null and fills in `error` if there
+/// is no item with that uuid (`Bug`), it has no due date or `count` or `interval` is not
+/// positive (`NeedsUserInput`), or the occurrences could not be written.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_create_recurring_item(manager: *const Arc<ListManager>, uuid: *const c_char, frequency: Frequency, interval: i64, count: i64, error: *mut ExternError) -> *mut c_char {
+    ffi_lock!();
+    ffi_guard!(error, {
+        let manager = ffi_ref!("list_manager_create_recurring_item", manager, ptr::null_mut(), error);
+        let uuid = c_char_to_string(uuid);
+        let item = match manager.fetch_item(&uuid) {
+            Some(item) => item,
+            None => {
+                set_extern_error(error, ErrorCategory::Bug, format!("no item with uuid {}", uuid));
+                return ptr::null_mut();
+            },
+        };
+        match manager.try_create_recurring_item(&item, frequency, interval, count) {
+            Ok(Some(uuids)) => string_to_c_char(serde_json::to_string(&uuids).unwrap()),
+            Ok(None) => {
+                set_extern_error(error, ErrorCategory::NeedsUserInput, "a recurring item needs a due date and a positive interval and count".to_string());
+                ptr::null_mut()
+            },
+            Err(e) => {
+                log_error!("failed to create recurring item {:?}", e);
+                set_sqlite_error(error, &e);
+                ptr::null_mut()
+            },
+        }
+    })
+}
+
+/// Returns null and fills in `error` if the occurrences could not be deleted, in which case
+/// none of them are.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_delete_recurring_item(manager: *const Arc<ListManager>, uuid: *const c_char, deletion: SeriesDeletion, error: *mut ExternError) -> *mut c_char {
+    ffi_lock!();
+    ffi_guard!(error, {
+        let manager = ffi_ref!("list_manager_delete_recurring_item", manager, ptr::null_mut(), error);
+        match manager.try_delete_recurring_item(&c_char_to_string(uuid), deletion) {
+            Ok(deleted) => string_to_c_char(serde_json::to_string(&deleted).unwrap()),
+            Err(e) => {
+                log_error!("failed to delete recurring item {:?}", e);
+                set_sqlite_error(error, &e);
+                ptr::null_mut()
+            },
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use time::{
+        Duration,
+        Timespec,
+    };
+
+    use super::{
+        Frequency,
+        SeriesDeletion,
+        SERIES_DELETION,
+    };
+
+    use items::Item;
+    use store::Store;
+    use ListManager;
+
+    fn weekly_review(manager: &ListManager) -> Vec<String> {
+        let item = Item {
+            uuid: "".to_string(),
+            name: "weekly review".to_string(),
+            due_date: Some(Timespec::new(1510315200, 0)),
+            completion_date: None,
+            labels: vec![],
+        };
+        manager.create_recurring_item(&item, Frequency::Weekly, 1, 4).unwrap()
+    }
+
+    #[test]
+    fn test_create_recurring_item() {
+        let manager = ListManager::new(Arc::new(Store::new(None)));
+        let uuids = weekly_review(&manager);
+        assert_eq!(uuids.len(), 4);
+        let last = manager.fetch_item(&uuids[3]).unwrap();
+        assert_eq!(last.due_date, Some(Timespec::new(1510315200, 0) + Duration::weeks(3)));
+        assert_eq!(manager.series_occurrences(&uuids[1]), Some(uuids.clone()));
+
+        let mut undated = last.clone();
+        undated.due_date = None;
+        assert_eq!(manager.create_recurring_item(&undated, Frequency::Daily, 1, 2), None);
+    }
+
+    #[test]
+    fn test_delete_recurring_item() {
+        let manager = ListManager::new(Arc::new(Store::new(None)));
+        let uuids = weekly_review(&manager);
+
+        assert_eq!(manager.delete_recurring_item(&uuids[1], SeriesDeletion::ThisOccurrence), vec![uuids[1].clone()]);
+        assert_eq!(manager.series_occurrences(&uuids[0]), Some(vec![uuids[0].clone(), uuids[2].clone(), uuids[3].clone()]));
+
+        assert_eq!(manager.delete_recurring_item(&uuids[2], SeriesDeletion::ThisAndFuture), vec![uuids[2].clone(), uuids[3].clone()]);
+        assert_eq!(manager.series_occurrences(&uuids[0]), Some(vec![uuids[0].clone()]));
+
+        let others = weekly_review(&manager);
+        assert_eq!(manager.delete_recurring_item(&others[2], SeriesDeletion::EntireSeries), others);
+        assert_eq!(manager.fetch_item(&others[0]), None);
+        assert_eq!(manager.delete_recurring_item(&others[0], SeriesDeletion::EntireSeries), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_default_series_deletion() {
+        let manager = ListManager::new(Arc::new(Store::new(None)));
+        let uuids = weekly_review(&manager);
+        assert_eq!(manager.delete_recurring_item(&uuids[0], SeriesDeletion::Default), vec![uuids[0].clone()]);
+
+        manager.set_pref_int(SERIES_DELETION, SeriesDeletion::EntireSeries as i64).unwrap();
+        assert_eq!(manager.delete_recurring_item(&uuids[3], SeriesDeletion::Default).len(), 3);
+    }
+}
@@ -0,0 +1,153 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+use rusqlite;
+use rusqlite::Connection;
+
+use store::migrations::MigrationStep;
+use ListManager;
+
+/// Item names spelled in Latin letters, so that "moskva" typed on a Latin keyboard finds
+/// "Москва trip". Kept up to date by every item write.
+pub const CREATE_ITEM_SEARCH_TABLE: MigrationStep = MigrationStep {
+    name: "create_item_search_table",
+    sql: r#"CREATE TABLE IF NOT EXISTS item_search (
+            item_uuid TEXT NOT NULL PRIMARY KEY,
+            latin TEXT NOT NULL
+        )"#,
+};
+
+/// The Latin spelling of a lower case letter, if it has one that differs from itself.
+fn latin(c: char) -> Option<&'static str> {
+    let latin = match c {
+        // Cyrillic, after the common Russian and Ukrainian romanizations
+        'а' => "a", 'б' => "b", 'в' => "v", 'г' => "g", 'ґ' => "g", 'д' => "d", 'е' => "e",
+        'ё' => "yo", 'є' => "ye", 'ж' => "zh", 'з' => "z", 'и' => "i", 'і' => "i", 'ї' => "yi",
+        'й' => "y", 'к' => "k", 'л' => "l", 'м' => "m", 'н' => "n", 'о' => "o", 'п' => "p",
+        'р' => "r", 'с' => "s", 'т' => "t", 'у' => "u", 'ф' => "f", 'х' => "kh", 'ц' => "ts",
+        'ч' => "ch", 'ш' => "sh", 'щ' => "shch", 'ъ' => "", 'ы' => "y", 'ь' => "", 'э' => "e",
+        'ю' => "yu", 'я' => "ya",
+        // Greek
+        'α' | 'ά' => "a", 'β' => "v", 'γ' => "g", 'δ' => "d", 'ε' | 'έ' => "e", 'ζ' => "z",
+        'η' | 'ή' => "i", 'θ' => "th", 'ι' | 'ί' | 'ϊ' | 'ΐ' => "i", 'κ' => "k", 'λ' => "l",
+        'μ' => "m", 'ν' => "n", 'ξ' => "x", 'ο' | 'ό' => "o", 'π' => "p", 'ρ' => "r",
+        'σ' | 'ς' => "s", 'τ' => "t", 'υ' | 'ύ' | 'ϋ' | 'ΰ' => "y", 'φ' => "f", 'χ' => "ch",
+        'ψ' => "ps", 'ω' | 'ώ' => "o",
+        // Latin letters with diacritics, which are often typed without them
+        'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' | 'ā' => "a", 'æ' => "ae", 'ç' | 'č' | 'ć' => "c",
+        'ď' => "d", 'è' | 'é' | 'ê' | 'ë' | 'ē' | 'ě' => "e", 'ì' | 'í' | 'î' | 'ï' | 'ī' => "i",
+        'ł' => "l", 'ñ' | 'ń' | 'ň' => "n", 'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' | 'ō' => "o",
+        'œ' => "oe", 'ř' => "r", 'ś' | 'š' => "s", 'ß' => "ss", 'ť' => "t",
+        'ù' | 'ú' | 'û' | 'ü' | 'ū' | 'ů' => "u", 'ý' | 'ÿ' => "y", 'ź' | 'ż' | 'ž' => "z",
+        _ => return None,
+    };
+    Some(latin)
+}
+
+/// Lower cases `text` and spells it in Latin letters where a romanization is known.
+/// Scripts without one, such as Han characters, are kept as they are.
+pub fn transliterate(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    for c in text.chars().flat_map(|c| c.to_lowercase()) {
+        match latin(c) {
+            Some(latin) => result.push_str(latin),
+            None => result.push(c),
+        }
+    }
+    result
+}
+
+/// Records the Latin spelling of an item's name. Expected to run inside the transaction
+/// writing the item.
+pub fn index_item(conn: &Connection, item_uuid: &String, name: &String) -> rusqlite::Result<()> {
+    let sql = r#"INSERT OR REPLACE INTO item_search (item_uuid, latin) VALUES (?, ?)"#;
+    conn.execute(sql, &[item_uuid, &transliterate(name)]).map(|_| ())
+}
+
+impl ListManager {
+    /// Indexes the items written before the search index existed. Read only stores are
+    /// left alone and only match names as written.
+    pub(crate) fn index_unindexed_items(&self) -> rusqlite::Result<()> {
+        let store = self.get_store();
+        if store.is_read_only() {
+            return Ok(());
+        }
+        let conn = store.get_conn();
+        let sql = r#"SELECT uuid, name FROM items WHERE uuid NOT IN (SELECT item_uuid FROM item_search)"#;
+        let mut stmt = conn.prepare(sql)?;
+        let unindexed: Vec<(String, String)> = stmt.query_map(&[], |row| (row.get(0), row.get(1)))?
+            .filter_map(|result| result.ok())
+            .collect();
+        if unindexed.is_empty() {
+            return Ok(());
+        }
+        let tx = store.begin_transaction()?;
+        for &(ref uuid, ref name) in unindexed.iter() {
+            index_item(&conn, uuid, name)?;
+        }
+        tx.commit()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use super::transliterate;
+
+    use filters::ItemFilter;
+    use items::Item;
+    use store::Store;
+    use ListManager;
+
+    #[test]
+    fn test_transliterate() {
+        assert_eq!(transliterate("Москва trip"), "moskva trip");
+        assert_eq!(transliterate("Одеса"), "odesa");
+        assert_eq!(transliterate("Αθήνα"), "athina");
+        assert_eq!(transliterate("Crème Brûlée"), "creme brulee");
+        assert_eq!(transliterate("北京"), "北京");
+    }
+
+    fn names_matching(manager: &ListManager, text: &str) -> Vec<String> {
+        let filter = ItemFilter {
+            name_contains: Some(text.to_string()),
+            ..ItemFilter::default()
+        };
+        manager.fetch_items(&filter).iter().map(|i| i.name.clone()).collect()
+    }
+
+    #[test]
+    fn test_search_by_transliteration() {
+        let manager = ListManager::new(Arc::new(Store::new(None)));
+        let mut item = Item::named("Москва trip");
+        item.uuid = manager.create_item(&item);
+
+        assert_eq!(names_matching(&manager, "moskva"), vec!["Москва trip"]);
+        assert_eq!(names_matching(&manager, "Москва"), vec!["Москва trip"]);
+        assert_eq!(names_matching(&manager, "MOSKVA TRIP"), vec!["Москва trip"]);
+
+        item.name = "Санкт-Петербург trip".to_string();
+        manager.save_item(&item);
+        assert_eq!(names_matching(&manager, "moskva"), Vec::<String>::new());
+        assert_eq!(names_matching(&manager, "peterburg"), vec!["Санкт-Петербург trip"]);
+    }
+
+    #[test]
+    fn test_items_written_before_the_index_are_indexed() {
+        let store = Arc::new(Store::new(None));
+        let manager = ListManager::new(store.clone());
+        store.get_conn().execute_batch(r#"INSERT INTO items (uuid, name) VALUES ('1', 'Прага')"#).unwrap();
+        assert_eq!(names_matching(&manager, "praga"), Vec::<String>::new());
+
+        let manager = ListManager::new(store);
+        assert_eq!(names_matching(&manager, "praga"), vec!["Прага"]);
+    }
+}
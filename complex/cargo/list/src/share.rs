@@ -0,0 +1,125 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! The text share sheets send for an item, made here so that every app sends the same.
+
+use std::os::raw::c_char;
+use std::ptr;
+
+use time::{
+    at_utc,
+    Timespec,
+};
+
+use ffi_utils::strings::string_to_c_char;
+use export::markdown_escape;
+use items::Item;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ShareFormat {
+    PlainText = 0,
+    Markdown = 1,
+}
+
+/// A date in the local time `utc_offset` seconds ahead of UTC, e.g. `Fri 10 Nov 2017,
+/// 12:00`, leaving out the time at midnight as for dates without one.
+fn local_date(date: Timespec, utc_offset: i64) -> String {
+    let local = at_utc(Timespec::new(date.sec + utc_offset, 0));
+    let format = if local.tm_hour == 0 && local.tm_min == 0 { "%a %d %b %Y" } else { "%a %d %b %Y, %H:%M" };
+    local.strftime(format).unwrap().to_string()
+}
+
+/// The item's name behind a checkbox showing whether it is done, followed by its due and
+/// completion dates and its labels, one per line.
+pub fn item_share_text(item: &Item, format: ShareFormat, utc_offset: i64) -> String {
+    let name = item.name.split_whitespace().collect::<Vec<&str>>().join(" ");
+    let done = item.completion_date.is_some();
+    let mut details = vec![];
+    if let Some(due_date) = item.due_date {
+        details.push(format!("Due: {}", local_date(due_date, utc_offset)));
+    }
+    if let Some(completion_date) = item.completion_date {
+        details.push(format!("Completed: {}", local_date(completion_date, utc_offset)));
+    }
+    match format {
+        ShareFormat::PlainText => {
+            if !item.labels.is_empty() {
+                let labels: Vec<&str> = item.labels.iter().map(|label| label.name.as_str()).collect();
+                details.push(format!("Labels: {}", labels.join(", ")));
+            }
+            let mut text = format!("{} {}\n", if done { "[x]" } else { "[ ]" }, name);
+            for line in details.iter() {
+                text.push_str(&format!("{}\n", line));
+            }
+            text
+        },
+        ShareFormat::Markdown => {
+            if !item.labels.is_empty() {
+                let labels: Vec<String> = item.labels.iter().map(|label| format!("`{}`", label.name.replace('`', "'"))).collect();
+                details.push(format!("Labels: {}", labels.join(", ")));
+            }
+            let mut text = format!("- [{}] {}\n", if done { "x" } else { " " }, markdown_escape(&name));
+            for line in details.iter() {
+                text.push_str(&format!("  - {}\n", line));
+            }
+            text
+        },
+    }
+}
+
+/// `utc_offset` is how many seconds local time is ahead of UTC, for the dates.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn item_to_share_text(item: *const Item, format: ShareFormat, utc_offset: i64) -> *mut c_char {
+    ffi_guard!({
+        let item = ffi_ref!("item_to_share_text", item, ptr::null_mut());
+        string_to_c_char(item_share_text(item, format, utc_offset))
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use time::Timespec;
+
+    use super::{
+        item_share_text,
+        ShareFormat,
+    };
+
+    use items::Item;
+    use labels::Label;
+
+    #[test]
+    fn test_item_share_text() {
+        let mut item = Item {
+            uuid: "a".to_string(),
+            name: "Book *tickets*\nfor Lisbon".to_string(),
+            due_date: Some(Timespec::new(1510315200, 0)),
+            completion_date: None,
+            labels: vec![
+                Arc::new(Label { name: "travel".to_string(), color: "#0000ff".to_string() }),
+                Arc::new(Label { name: "family".to_string(), color: "#00ff00".to_string() }),
+            ],
+        };
+        assert_eq!(item_share_text(&item, ShareFormat::PlainText, 3600),
+                   "[ ] Book *tickets* for Lisbon\nDue: Fri 10 Nov 2017, 13:00\nLabels: travel, family\n");
+
+        item.completion_date = Some(Timespec::new(1510358400, 0));
+        assert_eq!(item_share_text(&item, ShareFormat::Markdown, 0),
+                   "- [x] Book \\*tickets\\* for Lisbon\n  - Due: Fri 10 Nov 2017, 12:00\n  - Completed: Sat 11 Nov 2017\n  - Labels: `travel`, `family`\n");
+
+        item.due_date = None;
+        item.completion_date = None;
+        item.labels = vec![];
+        assert_eq!(item_share_text(&item, ShareFormat::PlainText, 0), "[ ] Book *tickets* for Lisbon\n");
+    }
+}
@@ -0,0 +1,374 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+use libc::size_t;
+use std::os::raw::{
+    c_char,
+    c_int,
+};
+use std::ptr;
+use std::sync::Arc;
+
+use rusqlite;
+use rusqlite::Row;
+use time::Timespec;
+
+use ffi_utils::errors::ExternError;
+use ffi_utils::strings::{
+    string_to_c_char,
+    c_char_to_string,
+};
+use cursors::{
+    Cursor,
+    CursorSource,
+};
+use filters::{
+    DateBound,
+    ItemFilter,
+};
+use items::Item;
+use store::errors::set_sqlite_error;
+use store::migrations::MigrationStep;
+use ListManager;
+
+pub const CREATE_SMART_LISTS_TABLES: MigrationStep = MigrationStep {
+    name: "create_smart_lists_tables",
+    sql: r#"CREATE TABLE IF NOT EXISTS smart_lists (
+            id INTEGER PRIMARY KEY,
+            name TEXT NOT NULL,
+            completed BOOLEAN,
+            due_before_date DATETIME,
+            due_before_offset INTEGER,
+            due_after_date DATETIME,
+            due_after_offset INTEGER,
+            name_contains TEXT
+        );
+        CREATE TABLE IF NOT EXISTS smart_list_labels (
+            smart_list_id INTEGER NOT NULL,
+            label_name TEXT NOT NULL,
+            PRIMARY KEY(smart_list_id, label_name)
+        )"#,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+/// A named filter saved in the store, such as "Overdue @work".
+pub struct SmartList {
+    pub id: i64,
+    pub name: String,
+    pub filter: ItemFilter,
+}
+
+fn date_bound_columns(bound: &Option<DateBound>) -> (Option<Timespec>, Option<i64>) {
+    match *bound {
+        Some(DateBound::Absolute(date)) => (Some(date), None),
+        Some(DateBound::FromNow(seconds)) => (None, Some(seconds)),
+        None => (None, None),
+    }
+}
+
+fn date_bound_from_columns(date: Option<Timespec>, offset: Option<i64>) -> Option<DateBound> {
+    match (date, offset) {
+        (Some(date), _) => Some(DateBound::Absolute(date)),
+        (None, Some(seconds)) => Some(DateBound::FromNow(seconds)),
+        (None, None) => None,
+    }
+}
+
+impl ListManager {
+    pub fn create_smart_list(&self, name: String, filter: &ItemFilter) -> Option<SmartList> {
+        self.try_create_smart_list(name, filter).unwrap_or_else(|e| {
+            log_error!("failed to create smart list {:?}", e);
+            None
+        })
+    }
+
+    pub fn try_create_smart_list(&self, name: String, filter: &ItemFilter) -> rusqlite::Result<Option<SmartList>> {
+        let sql = r#"INSERT INTO smart_lists (name, completed, due_before_date, due_before_offset,
+                                              due_after_date, due_after_offset, name_contains)
+                     VALUES (?, ?, ?, ?, ?, ?, ?)"#;
+        let (due_before_date, due_before_offset) = date_bound_columns(&filter.due_before);
+        let (due_after_date, due_after_offset) = date_bound_columns(&filter.due_after);
+        let store = self.get_store();
+        let conn = store.get_conn();
+        let tx = store.begin_transaction()?;
+        conn.execute(sql, &[&name, &filter.completed, &due_before_date, &due_before_offset,
+                            &due_after_date, &due_after_offset, &filter.name_contains])?;
+        let id = conn.last_insert_rowid();
+        let label_sql = r#"INSERT INTO smart_list_labels (smart_list_id, label_name) VALUES (?, ?)"#;
+        for label in filter.labels.iter() {
+            conn.execute(label_sql, &[&id, label])?;
+        }
+        tx.commit()?;
+        Ok(self.fetch_smart_list(id))
+    }
+
+    pub fn delete_smart_list(&self, id: i64) {
+        if let Err(e) = self.try_delete_smart_list(id) {
+            log_error!("failed to delete smart list {:?}", e);
+        }
+    }
+
+    /// Returns false if there is no smart list with that id.
+    pub fn try_delete_smart_list(&self, id: i64) -> rusqlite::Result<bool> {
+        let store = self.get_store();
+        let conn = store.get_conn();
+        let tx = store.begin_transaction()?;
+        conn.execute("DELETE FROM smart_list_labels WHERE smart_list_id=?", &[&id])?;
+        let deleted = conn.execute("DELETE FROM smart_lists WHERE id=?", &[&id])? > 0;
+        tx.commit()?;
+        Ok(deleted)
+    }
+
+    fn fetch_smart_list_labels(&self, id: i64) -> Vec<String> {
+        let sql = r#"SELECT label_name FROM smart_list_labels WHERE smart_list_id=? ORDER BY rowid"#;
+        let conn = self.get_store().get_conn();
+        let mut stmt = conn.prepare(sql).unwrap();
+        let label_iter = stmt.query_map(&[&id], |row| row.get(0)).unwrap();
+        label_iter.filter_map(|result| result.ok()).collect()
+    }
+
+    fn smart_list_from_row(&self, row: &Row) -> SmartList {
+        let id: i64 = row.get(0);
+        SmartList {
+            id: id,
+            name: row.get(1),
+            filter: ItemFilter {
+                labels: self.fetch_smart_list_labels(id),
+                completed: row.get(2),
+                due_before: date_bound_from_columns(row.get(3), row.get(4)),
+                due_after: date_bound_from_columns(row.get(5), row.get(6)),
+                name_contains: row.get(7),
+            },
+        }
+    }
+
+    pub fn fetch_smart_list(&self, id: i64) -> Option<SmartList> {
+        let sql = r#"SELECT id, name, completed, due_before_date, due_before_offset,
+                            due_after_date, due_after_offset, name_contains
+                     FROM smart_lists WHERE id=?"#;
+        let conn = self.get_store().get_conn();
+        let mut stmt = conn.prepare(sql).unwrap();
+        let mut list_iter = stmt.query_map(&[&id], |row| self.smart_list_from_row(row)).unwrap();
+        if let Some(result) = list_iter.next() {
+            result.ok()
+        } else {
+            log_debug!("No smart list found for id {:?}", id);
+            None
+        }
+    }
+
+    pub fn fetch_smart_lists(&self) -> Vec<SmartList> {
+        let sql = r#"SELECT id, name, completed, due_before_date, due_before_offset,
+                            due_after_date, due_after_offset, name_contains
+                     FROM smart_lists ORDER BY id"#;
+        let conn = self.get_store().get_conn();
+        let mut stmt = conn.prepare(sql).unwrap();
+        let list_iter = stmt.query_map(&[], |row| self.smart_list_from_row(row)).unwrap();
+        list_iter.filter_map(|result| result.ok()).collect()
+    }
+
+    /// Runs the saved filter, returning `None` if there is no smart list with that id.
+    pub fn fetch_smart_list_items(&self, id: i64) -> Option<Vec<Item>> {
+        self.fetch_smart_list(id).map(|list| self.fetch_items(&list.filter))
+    }
+}
+
+/// `filter` may be null to save a list of every item. Returns null and fills in `error` if
+/// the list could not be created.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_create_smart_list(manager: *const Arc<ListManager>, name: *const c_char, filter: *const ItemFilter, error: *mut ExternError) -> *mut SmartList {
+    ffi_lock!();
+    ffi_guard!(error, {
+        let manager = ffi_ref!("list_manager_create_smart_list", manager, ptr::null_mut(), error);
+        let name = c_char_to_string(name);
+        let filter = filter.as_ref().cloned().unwrap_or_default();
+        match manager.try_create_smart_list(name, &filter) {
+            Ok(Some(list)) => Box::into_raw(Box::new(list)),
+            Ok(None) => ptr::null_mut(),
+            Err(e) => {
+                log_error!("failed to create smart list {:?}", e);
+                set_sqlite_error(error, &e);
+                ptr::null_mut()
+            },
+        }
+    })
+}
+
+/// Returns false if there is no smart list with that id, or if it could not be deleted, in
+/// which case `error` is filled in.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_delete_smart_list(manager: *const Arc<ListManager>, id: i64, error: *mut ExternError) -> bool {
+    ffi_lock!();
+    ffi_guard!(error, {
+        let manager = ffi_ref!("list_manager_delete_smart_list", manager, false, error);
+        manager.try_delete_smart_list(id).unwrap_or_else(|e| {
+            log_error!("failed to delete smart list {:?}", e);
+            set_sqlite_error(error, &e);
+            false
+        })
+    })
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_get_smart_lists(manager: *const Arc<ListManager>) -> *mut Vec<SmartList> {
+    ffi_lock!();
+    ffi_guard!({
+        let manager = ffi_ref!("list_manager_get_smart_lists", manager, ptr::null_mut());
+        Box::into_raw(Box::new(manager.fetch_smart_lists()))
+    })
+}
+
+/// Returns a cursor over the items currently matching the smart list, or null if there is
+/// no smart list with that id. A `page_size` of 0 uses the default.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_smart_list_cursor(manager: *const Arc<ListManager>, id: i64, page_size: i64) -> *mut Cursor {
+    ffi_lock!();
+    ffi_guard!({
+        let manager = ffi_ref!("list_manager_smart_list_cursor", manager, ptr::null_mut());
+        match manager.fetch_smart_list(id) {
+            Some(list) => {
+                let cursor = Cursor::new(manager.clone(), CursorSource::Items(list.filter), page_size);
+                Box::into_raw(Box::new(cursor))
+            },
+            None => ptr::null_mut(),
+        }
+    })
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn smart_list_list_destroy(list: *mut Vec<SmartList>) {
+    ffi_guard!({
+        if list.is_null() {
+            return;
+        }
+        let _ = Box::from_raw(list);
+    })
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn smart_list_list_count(list: *const Vec<SmartList>) -> c_int {
+    ffi_guard!({
+        let list = ffi_ref!("smart_list_list_count", list);
+        list.len() as c_int
+    })
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn smart_list_list_at(list: *const Vec<SmartList>, index: size_t) -> *const SmartList {
+    ffi_guard!({
+        let list = ffi_ref!("smart_list_list_at", list, ptr::null());
+        &list[index as usize]
+    })
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn smart_list_destroy(smart_list: *mut SmartList) {
+    ffi_guard!({
+        if smart_list.is_null() {
+            return;
+        }
+        let _ = Box::from_raw(smart_list);
+    })
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn smart_list_get_id(smart_list: *const SmartList) -> i64 {
+    ffi_guard!({
+        let smart_list = ffi_ref!("smart_list_get_id", smart_list);
+        smart_list.id
+    })
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn smart_list_get_name(smart_list: *const SmartList) -> *mut c_char {
+    ffi_guard!({
+        let smart_list = ffi_ref!("smart_list_get_name", smart_list, ptr::null_mut());
+        string_to_c_char(smart_list.name.clone())
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use time::{
+        now_utc,
+        Duration,
+    };
+
+    use filters::{
+        DateBound,
+        ItemFilter,
+    };
+    use items::Item;
+    use store::Store;
+    use ListManager;
+
+    fn list_manager() -> ListManager {
+        ListManager::new(Arc::new(Store::new(None)))
+    }
+
+    #[test]
+    fn test_create_and_fetch_smart_lists() {
+        let manager = list_manager();
+        let overdue_at_work = ItemFilter {
+            labels: vec!["work".to_string()],
+            completed: Some(false),
+            due_before: Some(DateBound::FromNow(0)),
+            ..ItemFilter::default()
+        };
+        let created = manager.create_smart_list("Overdue @work".to_string(), &overdue_at_work).expect("expected a smart list");
+        assert_eq!(created.name, "Overdue @work");
+        assert_eq!(created.filter, overdue_at_work);
+
+        let in_three_days = now_utc().to_timespec() + Duration::days(3);
+        let absolute = ItemFilter {
+            due_after: Some(DateBound::Absolute(in_three_days)),
+            name_contains: Some("milk".to_string()),
+            ..ItemFilter::default()
+        };
+        let second = manager.create_smart_list("Milk".to_string(), &absolute).unwrap();
+        // dates are stored with second precision
+        assert_eq!(second.filter.due_after.unwrap().resolve(in_three_days).sec, in_three_days.sec);
+        assert_eq!(second.filter.name_contains, absolute.name_contains);
+
+        assert_eq!(manager.fetch_smart_lists().iter().map(|l| l.id).collect::<Vec<i64>>(), vec![created.id, second.id]);
+
+        manager.delete_smart_list(created.id);
+        assert_eq!(manager.fetch_smart_list(created.id), None);
+        assert_eq!(manager.fetch_smart_lists().len(), 1);
+    }
+
+    #[test]
+    fn test_fetch_smart_list_items() {
+        let manager = list_manager();
+        let work = manager.create_label("work".to_string(), "#000000".to_string()).unwrap();
+        let now = now_utc().to_timespec();
+        for &(name, days) in [("late", -1), ("soon", 1)].iter() {
+            manager.create_item(&Item {
+                uuid: "".to_string(),
+                name: name.to_string(),
+                due_date: Some(now + Duration::days(days)),
+                completion_date: None,
+                labels: vec![Arc::new(work.clone())],
+            });
+        }
+        let filter = ItemFilter {
+            labels: vec!["work".to_string()],
+            due_before: Some(DateBound::FromNow(0)),
+            ..ItemFilter::default()
+        };
+        let list = manager.create_smart_list("Overdue @work".to_string(), &filter).unwrap();
+        let items = manager.fetch_smart_list_items(list.id).expect("expected items");
+        assert_eq!(items.iter().map(|i| i.name.as_str()).collect::<Vec<&str>>(), vec!["late"]);
+
+        assert_eq!(manager.fetch_smart_list_items(list.id + 1), None);
+    }
+}
@@ -0,0 +1,195 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+use std::collections::HashSet;
+use std::os::raw::c_char;
+use std::sync::Arc;
+use std::ptr;
+
+use serde_json;
+use time::{
+    at_utc,
+    Duration,
+    Timespec,
+};
+
+use ffi_utils::strings::string_to_c_char;
+use filters::{
+    start_of_local_day,
+    ItemFilter,
+};
+use ListManager;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LabelStats {
+    pub name: String,
+    pub open: i64,
+    pub overdue: i64,
+    pub total: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+/// Figures for the dashboard screens.
+pub struct Stats {
+    pub total: i64,
+    pub open: i64,
+    pub completed: i64,
+    pub overdue: i64,
+    pub window_days: i64,
+    /// The share of items due during the last `window_days` days that have been completed,
+    /// or `None` if nothing was due.
+    pub completion_rate: Option<f64>,
+    /// Consecutive local days with at least one completion, ending today, or yesterday if
+    /// nothing has been completed yet today.
+    pub streak_days: i64,
+    pub labels: Vec<LabelStats>,
+}
+
+fn local_date(instant: Timespec, utc_offset: i64) -> String {
+    let local = Timespec::new(instant.sec + utc_offset, 0);
+    at_utc(local).strftime("%Y-%m-%d").unwrap().to_string()
+}
+
+impl ListManager {
+    pub fn stats(&self, window_days: i64, utc_offset: i64) -> Stats {
+        self.stats_as_of(window_days, utc_offset, self.now())
+    }
+
+    pub fn stats_as_of(&self, window_days: i64, utc_offset: i64, now: Timespec) -> Stats {
+        let conn = self.get_store().get_conn();
+        let today = start_of_local_day(now, utc_offset);
+
+        let sql = r#"SELECT count(*),
+                            SUM(CASE WHEN completion_date IS NULL THEN 1 ELSE 0 END),
+                            SUM(CASE WHEN completion_date IS NULL AND due_date < ? THEN 1 ELSE 0 END)
+                     FROM items"#;
+        let (total, open, overdue): (i64, i64, i64) = conn.query_row(sql, &[&today], |row| {
+            (row.get(0), row.get::<_, Option<i64>>(1).unwrap_or(0), row.get::<_, Option<i64>>(2).unwrap_or(0))
+        }).unwrap();
+
+        let sql = r#"SELECT count(*), SUM(CASE WHEN completion_date IS NOT NULL THEN 1 ELSE 0 END)
+                     FROM items WHERE due_date >= ? AND due_date < ?"#;
+        let window_start = today + Duration::days(1) - Duration::days(window_days);
+        let window_end = today + Duration::days(1);
+        let (due, done): (i64, Option<i64>) = conn.query_row(sql, &[&window_start, &window_end], |row| {
+            (row.get(0), row.get(1))
+        }).unwrap();
+        let completion_rate = if due > 0 { Some(done.unwrap_or(0) as f64 / due as f64) } else { None };
+
+        let sql = r#"SELECT DISTINCT date(completion_date, ?) FROM items WHERE completion_date IS NOT NULL"#;
+        let modifier = format!("{:+} seconds", utc_offset);
+        let mut stmt = conn.prepare(sql).unwrap();
+        let completion_days: HashSet<String> = stmt.query_map(&[&modifier], |row| row.get(0)).unwrap()
+            .filter_map(|result| result.ok())
+            .collect();
+        let mut day = now;
+        if !completion_days.contains(&local_date(day, utc_offset)) {
+            day = day - Duration::days(1);
+        }
+        let mut streak_days = 0;
+        while completion_days.contains(&local_date(day, utc_offset)) {
+            streak_days += 1;
+            day = day - Duration::days(1);
+        }
+
+        let labels = self.label_counts_as_of(&ItemFilter::default(), today).into_iter().map(|count| {
+            LabelStats {
+                name: count.label.name.clone(),
+                open: count.open,
+                overdue: count.overdue,
+                total: count.total,
+            }
+        }).collect();
+
+        Stats {
+            total: total,
+            open: open,
+            completed: total - open,
+            overdue: overdue,
+            window_days: window_days,
+            completion_rate: completion_rate,
+            streak_days: streak_days,
+            labels: labels,
+        }
+    }
+}
+
+/// Returns the statistics as a JSON object. `utc_offset` is the host's current offset from
+/// UTC in seconds, used to decide which local day completions fall on.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_stats_json(manager: *const Arc<ListManager>, window_days: i64, utc_offset: i64) -> *mut c_char {
+    ffi_lock!();
+    ffi_guard!({
+        let manager = ffi_ref!("list_manager_stats_json", manager, ptr::null_mut());
+        let stats = manager.stats(window_days, utc_offset);
+        string_to_c_char(serde_json::to_string(&stats).unwrap())
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use serde_json;
+    use time::{
+        Duration,
+        Timespec,
+    };
+
+    use items::Item;
+    use store::Store;
+    use ListManager;
+
+    // 2017-11-10 12:00:00 UTC
+    const NOW: i64 = 1510315200;
+
+    #[test]
+    fn test_stats() {
+        let manager = ListManager::new(Arc::new(Store::new(None)));
+        let work = manager.create_label("work".to_string(), "#000000".to_string()).unwrap();
+        let now = Timespec::new(NOW, 0);
+        let day = |n: i64| now + Duration::days(n);
+        let items = vec![
+            // completed on each of the last three days, but not today
+            ("a", Some(day(-1)), Some(day(-1))),
+            ("b", Some(day(-2)), Some(day(-2))),
+            ("c", None, Some(day(-3))),
+            // a gap, then an older completion outside the streak
+            ("d", Some(day(-10)), Some(day(-5))),
+            ("overdue", Some(day(-2)), None),
+            ("later", Some(day(5)), None),
+        ];
+        for (name, due_date, completion_date) in items {
+            manager.create_item(&Item {
+                uuid: "".to_string(),
+                name: name.to_string(),
+                due_date: due_date,
+                completion_date: completion_date,
+                labels: vec![Arc::new(work.clone())],
+            });
+        }
+
+        let stats = manager.stats_as_of(7, 0, now);
+        assert_eq!((stats.total, stats.open, stats.completed, stats.overdue), (6, 2, 4, 1));
+        // a, b and overdue were due in the last week
+        assert_eq!(stats.completion_rate, Some(2.0 / 3.0));
+        assert_eq!(stats.streak_days, 3);
+        assert_eq!(stats.labels.len(), 1);
+        assert_eq!((stats.labels[0].open, stats.labels[0].total), (2, 6));
+
+        let json: serde_json::Value = serde_json::from_str(&serde_json::to_string(&stats).unwrap()).unwrap();
+        assert_eq!(json["streak_days"], 3);
+        assert_eq!(json["labels"][0]["name"], "work");
+
+        let empty = ListManager::new(Arc::new(Store::new(None))).stats_as_of(7, 0, now);
+        assert_eq!(empty.completion_rate, None);
+        assert_eq!(empty.streak_days, 0);
+    }
+}
@@ -0,0 +1,1526 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! Keeps items and labels in step with a REST backend.
+//!
+//! Triggers record every item and label written locally in `sync_outbox`, which survives
+//! restarts and is replayed labels first, then in the order the records were last written.
+//!
+//! `sync_now` first pulls `GET {base}/changes?since={cursor}`, which answers `{"cursor",
+//! "items", "deleted_items", "labels", "deleted_labels"}`, applies those changes and stores
+//! the new cursor. It then pushes what is left in the outbox with
+//! `POST {base}/changes?since={cursor}`, a body of the same shape without the cursor. The server
+//! answers with the cursor following the pushed changes, so they are not pulled back, or
+//! with 409 if others pushed since the pull, in which case the client pulls and pushes
+//! again. Both directions carry `"edited_at": {uuid: {field: seconds}}` for the items they
+//! hold, which the conflict strategy uses to settle items changed on both sides. Deletions
+//! are only pushed for records with a tombstone, which also keeps an edit made elsewhere
+//! before a deletion here from bringing the record back.
+//!
+//! With a sync passphrase set, items travel as `"sealed_items"` and pushes offer a
+//! `"salt"`. The server keeps the first salt offered and returns it with every pull, see
+//! `sync_encryption`.
+//!
+//! Labels marked local-only, and the items carrying them, are left out in both directions.
+//!
+//! Requests are made by a `Transport`. The built in one speaks plain HTTP, which is enough
+//! for development servers; hosts install their platform's HTTP stack for HTTPS.
+
+use std::cell::{
+    Cell,
+    RefCell,
+};
+use std::collections::BTreeMap;
+use std::ffi::CString;
+use std::fmt;
+use std::io;
+use std::io::{
+    Read,
+    Write,
+};
+use std::net::{
+    IpAddr,
+    TcpStream,
+    ToSocketAddrs,
+};
+use std::os::raw::{
+    c_char,
+    c_void,
+};
+use std::ptr;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Duration;
+
+use rusqlite;
+use serde_json;
+
+use ffi_utils::breadcrumbs;
+use ffi_utils::errors::{
+    set_extern_error,
+    ErrorCategory,
+    ExternError,
+};
+use ffi_utils::lock;
+use ffi_utils::strings::c_char_to_string;
+use conflicts::{
+    ConflictStrategy,
+    EditTimes,
+};
+use crypto::SyncCrypto;
+use events::{
+    ChangeEvent,
+    Observers,
+};
+use flags::CRDT_MERGE;
+use items::Item;
+use labels::Label;
+use store::errors::category_of;
+use store::migrations::MigrationStep;
+use sync_report::SyncReport;
+use sync_encryption::{
+    SealedItem,
+    SyncKeys,
+    MAX_UNKNOWN_SALTS,
+};
+use tombstones::{
+    KIND_ITEM,
+    KIND_LABEL,
+};
+use ListManager;
+
+pub const CREATE_SYNC_TABLES: MigrationStep = MigrationStep {
+    name: "create_sync_tables",
+    sql: r#"CREATE TABLE IF NOT EXISTS sync_outbox (
+                kind TEXT NOT NULL,
+                id TEXT NOT NULL,
+                PRIMARY KEY(kind, id)
+            );
+            CREATE TABLE IF NOT EXISTS sync_state (
+                id INTEGER NOT NULL PRIMARY KEY CHECK (id = 0),
+                base_url TEXT NOT NULL,
+                cursor TEXT
+            );
+            CREATE TRIGGER IF NOT EXISTS sync_items_inserted AFTER INSERT ON items BEGIN
+                INSERT OR IGNORE INTO sync_outbox (kind, id) VALUES ('item', NEW.uuid);
+            END;
+            CREATE TRIGGER IF NOT EXISTS sync_items_updated AFTER UPDATE ON items BEGIN
+                INSERT OR IGNORE INTO sync_outbox (kind, id) VALUES ('item', NEW.uuid);
+            END;
+            CREATE TRIGGER IF NOT EXISTS sync_items_deleted AFTER DELETE ON items BEGIN
+                INSERT OR IGNORE INTO sync_outbox (kind, id) VALUES ('item', OLD.uuid);
+            END;
+            CREATE TRIGGER IF NOT EXISTS sync_item_labels_inserted AFTER INSERT ON item_labels BEGIN
+                INSERT OR IGNORE INTO sync_outbox (kind, id) VALUES ('item', NEW.item_uuid);
+            END;
+            CREATE TRIGGER IF NOT EXISTS sync_item_labels_deleted AFTER DELETE ON item_labels BEGIN
+                INSERT OR IGNORE INTO sync_outbox (kind, id) VALUES ('item', OLD.item_uuid);
+            END;
+            CREATE TRIGGER IF NOT EXISTS sync_labels_inserted AFTER INSERT ON labels BEGIN
+                INSERT OR IGNORE INTO sync_outbox (kind, id) VALUES ('label', NEW.name);
+            END;
+            CREATE TRIGGER IF NOT EXISTS sync_labels_updated AFTER UPDATE ON labels BEGIN
+                INSERT OR IGNORE INTO sync_outbox (kind, id) VALUES ('label', NEW.name);
+            END;
+            INSERT OR IGNORE INTO sync_outbox (kind, id) SELECT 'label', name FROM labels;
+            INSERT OR IGNORE INTO sync_outbox (kind, id) SELECT 'item', uuid FROM items;"#,
+};
+
+/// Deleted labels are tracked apart from the other triggers, as label deletion came later.
+pub const TRACK_LABEL_DELETIONS: MigrationStep = MigrationStep {
+    name: "track_label_deletions",
+    sql: r#"CREATE TRIGGER IF NOT EXISTS sync_labels_deleted AFTER DELETE ON labels BEGIN
+                INSERT OR IGNORE INTO sync_outbox (kind, id) VALUES ('label', OLD.name);
+            END"#,
+};
+
+/// Moves a record to the back of the outbox each time it is written again, so the outbox
+/// is replayed in the order of the latest local changes rather than the first.
+pub const QUEUE_IN_CHANGE_ORDER: MigrationStep = MigrationStep {
+    name: "queue_in_change_order",
+    sql: r#"DROP TRIGGER IF EXISTS sync_items_inserted;
+            CREATE TRIGGER sync_items_inserted AFTER INSERT ON items BEGIN
+                INSERT OR REPLACE INTO sync_outbox (kind, id) VALUES ('item', NEW.uuid);
+            END;
+            DROP TRIGGER IF EXISTS sync_items_updated;
+            CREATE TRIGGER sync_items_updated AFTER UPDATE ON items BEGIN
+                INSERT OR REPLACE INTO sync_outbox (kind, id) VALUES ('item', NEW.uuid);
+            END;
+            DROP TRIGGER IF EXISTS sync_items_deleted;
+            CREATE TRIGGER sync_items_deleted AFTER DELETE ON items BEGIN
+                INSERT OR REPLACE INTO sync_outbox (kind, id) VALUES ('item', OLD.uuid);
+            END;
+            DROP TRIGGER IF EXISTS sync_item_labels_inserted;
+            CREATE TRIGGER sync_item_labels_inserted AFTER INSERT ON item_labels BEGIN
+                INSERT OR REPLACE INTO sync_outbox (kind, id) VALUES ('item', NEW.item_uuid);
+            END;
+            DROP TRIGGER IF EXISTS sync_item_labels_deleted;
+            CREATE TRIGGER sync_item_labels_deleted AFTER DELETE ON item_labels BEGIN
+                INSERT OR REPLACE INTO sync_outbox (kind, id) VALUES ('item', OLD.item_uuid);
+            END;
+            DROP TRIGGER IF EXISTS sync_labels_inserted;
+            CREATE TRIGGER sync_labels_inserted AFTER INSERT ON labels BEGIN
+                INSERT OR REPLACE INTO sync_outbox (kind, id) VALUES ('label', NEW.name);
+            END;
+            DROP TRIGGER IF EXISTS sync_labels_updated;
+            CREATE TRIGGER sync_labels_updated AFTER UPDATE ON labels BEGIN
+                INSERT OR REPLACE INTO sync_outbox (kind, id) VALUES ('label', NEW.name);
+            END;
+            DROP TRIGGER IF EXISTS sync_labels_deleted;
+            CREATE TRIGGER sync_labels_deleted AFTER DELETE ON labels BEGIN
+                INSERT OR REPLACE INTO sync_outbox (kind, id) VALUES ('label', OLD.name);
+            END"#,
+};
+
+pub struct HttpRequest {
+    pub method: &'static str,
+    pub url: String,
+    pub auth_token: String,
+    pub body: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub body: String,
+}
+
+/// Makes the HTTP requests for sync.
+pub trait Transport {
+    fn send(&self, request: &HttpRequest) -> io::Result<HttpResponse>;
+}
+
+/// How long `PlainHttp` waits to connect, and then for each read or write.
+const PLAIN_HTTP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// HTTP/1.0 over a plain socket, for `http://` URLs on this machine only, e.g. a server run
+/// for development. The token would go out in the clear, so anything else needs a host
+/// transport.
+pub struct PlainHttp;
+
+/// The host name and port in `host`, e.g. `localhost:8080` or `[::1]`.
+fn split_host(host: &str) -> io::Result<(&str, u16)> {
+    let (name, port) = match host.rfind(':') {
+        Some(index) if !host[index..].contains(']') => {
+            let port = host[index + 1..].parse().map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "invalid port"))?;
+            (&host[..index], port)
+        },
+        _ => (host, 80),
+    };
+    Ok((name.trim_start_matches('[').trim_end_matches(']'), port))
+}
+
+fn is_loopback(name: &str) -> bool {
+    name.eq_ignore_ascii_case("localhost") || name.parse::<IpAddr>().map(|ip| ip.is_loopback()).unwrap_or(false)
+}
+
+impl Transport for PlainHttp {
+    fn send(&self, request: &HttpRequest) -> io::Result<HttpResponse> {
+        let rest = match request.url.find("://") {
+            Some(index) if &request.url[..index] == "http" => &request.url[index + 3..],
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "only http:// URLs are supported without a host transport")),
+        };
+        let (host, path) = match rest.find('/') {
+            Some(index) => (&rest[..index], &rest[index..]),
+            None => (rest, "/"),
+        };
+        let (name, port) = split_host(host)?;
+        if !is_loopback(name) {
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, "only this machine may be synced with without a host transport"));
+        }
+        let body = request.body.as_deref().unwrap_or("");
+
+        let mut last_error = io::Error::new(io::ErrorKind::NotFound, "the host has no address");
+        let mut connected = None;
+        for address in (name, port).to_socket_addrs()?.filter(|address| address.ip().is_loopback()) {
+            match TcpStream::connect_timeout(&address, PLAIN_HTTP_TIMEOUT) {
+                Ok(stream) => {
+                    connected = Some(stream);
+                    break;
+                },
+                Err(e) => last_error = e,
+            }
+        }
+        let mut stream = connected.ok_or(last_error)?;
+        stream.set_read_timeout(Some(PLAIN_HTTP_TIMEOUT))?;
+        stream.set_write_timeout(Some(PLAIN_HTTP_TIMEOUT))?;
+        write!(stream, "{} {} HTTP/1.0\r\nHost: {}\r\nAuthorization: Bearer {}\r\nAccept: application/json\r\n",
+               request.method, path, host, request.auth_token)?;
+        if request.body.is_some() {
+            write!(stream, "Content-Type: application/json\r\nContent-Length: {}\r\n", body.len())?;
+        }
+        write!(stream, "\r\n{}", body)?;
+        stream.flush()?;
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response)?;
+        let malformed = || io::Error::new(io::ErrorKind::InvalidData, "malformed HTTP response");
+        let header_end = response.find("\r\n\r\n").ok_or_else(malformed)?;
+        let status = response.split(' ').nth(1).and_then(|status| status.parse().ok()).ok_or_else(malformed)?;
+        Ok(HttpResponse {
+            status: status,
+            body: response[header_end + 4..].to_string(),
+        })
+    }
+}
+
+/// Performs a request on the host. Fill in the response with `sync_response_set` before
+/// returning; a response left unset counts as a network failure.
+pub type SyncHttpCallback = extern "C" fn(method: *const c_char, url: *const c_char, auth_token: *const c_char, body: *const c_char, response: *mut HttpResponse, user_data: *mut c_void);
+
+struct HostTransport {
+    callback: SyncHttpCallback,
+    user_data: *mut c_void,
+}
+
+impl Transport for HostTransport {
+    fn send(&self, request: &HttpRequest) -> io::Result<HttpResponse> {
+        let method = CString::new(request.method).unwrap_or_default();
+        let url = CString::new(request.url.clone()).unwrap_or_default();
+        let auth_token = CString::new(request.auth_token.clone()).unwrap_or_default();
+        let body = request.body.as_ref().map(|body| CString::new(body.clone()).unwrap_or_default());
+        let mut response = HttpResponse {
+            status: 0,
+            body: String::new(),
+        };
+        (self.callback)(method.as_ptr(), url.as_ptr(), auth_token.as_ptr(),
+                        body.as_ref().map(|body| body.as_ptr()).unwrap_or(ptr::null()),
+                        &mut response, self.user_data);
+        if response.status == 0 {
+            return Err(io::Error::new(io::ErrorKind::Other, "the host could not complete the request"));
+        }
+        Ok(response)
+    }
+}
+
+/// Where to sync to, and how.
+pub struct SyncClient {
+    config: RefCell<Option<(String, String)>>,
+    transport: RefCell<Rc<dyn Transport>>,
+    strategy: Cell<ConflictStrategy>,
+    progress: Observers<SyncProgress>,
+    pub(crate) crypto: RefCell<Option<Rc<dyn SyncCrypto>>>,
+    pub(crate) keys: RefCell<Option<SyncKeys>>,
+}
+
+impl Default for SyncClient {
+    fn default() -> SyncClient {
+        SyncClient {
+            config: RefCell::new(None),
+            transport: RefCell::new(Rc::new(PlainHttp)),
+            strategy: Cell::new(ConflictStrategy::default()),
+            progress: Observers::default(),
+            crypto: RefCell::new(None),
+            keys: RefCell::new(None),
+        }
+    }
+}
+
+impl fmt::Debug for SyncClient {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // never log the token
+        write!(f, "SyncClient({:?})", self.config.borrow().as_ref().map(|&(ref base_url, _)| base_url))
+    }
+}
+
+#[derive(Debug)]
+pub enum SyncError {
+    NotConfigured,
+    Io(io::Error),
+    /// The server answered with this status.
+    Http(u16),
+    Json(serde_json::Error),
+    Sqlite(rusqlite::Error),
+    /// Pulled items are sealed, and the sync passphrase is missing or cannot open them.
+    Sealed,
+    /// Pulled items are sealed with more salts than a key may be derived for in one pull.
+    TooManySalts(usize),
+}
+
+impl SyncError {
+    pub fn category(&self) -> ErrorCategory {
+        match *self {
+            SyncError::NotConfigured => ErrorCategory::Bug,
+            SyncError::Io(_) => ErrorCategory::Retryable,
+            SyncError::Http(401) |
+            SyncError::Http(403) => ErrorCategory::NeedsReauth,
+            SyncError::Http(408) |
+            SyncError::Http(409) |
+            SyncError::Http(429) => ErrorCategory::Retryable,
+            SyncError::Http(status) if status >= 500 => ErrorCategory::Retryable,
+            SyncError::Http(_) |
+            SyncError::Json(_) => ErrorCategory::Bug,
+            SyncError::Sqlite(ref e) => category_of(e),
+            SyncError::Sealed => ErrorCategory::NeedsUserInput,
+            SyncError::TooManySalts(_) => ErrorCategory::Bug,
+        }
+    }
+}
+
+impl fmt::Display for SyncError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SyncError::NotConfigured => write!(f, "sync has not been configured"),
+            SyncError::Io(ref e) => write!(f, "{}", e),
+            SyncError::Http(status) => write!(f, "the server answered {}", status),
+            SyncError::Json(ref e) => write!(f, "unexpected response: {}", e),
+            SyncError::Sqlite(ref e) => write!(f, "{}", e),
+            SyncError::Sealed => write!(f, "the sync passphrase is needed to open pulled items"),
+            SyncError::TooManySalts(count) => write!(f, "pulled items are sealed with {} unknown salts", count),
+        }
+    }
+}
+
+impl From<io::Error> for SyncError {
+    fn from(error: io::Error) -> SyncError {
+        SyncError::Io(error)
+    }
+}
+
+impl From<serde_json::Error> for SyncError {
+    fn from(error: serde_json::Error) -> SyncError {
+        SyncError::Json(error)
+    }
+}
+
+impl From<rusqlite::Error> for SyncError {
+    fn from(error: rusqlite::Error) -> SyncError {
+        SyncError::Sqlite(error)
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Changes {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<String>,
+    #[serde(default)]
+    pub items: Vec<Item>,
+    #[serde(default)]
+    pub deleted_items: Vec<String>,
+    #[serde(default)]
+    pub labels: Vec<Label>,
+    #[serde(default)]
+    pub deleted_labels: Vec<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub edited_at: BTreeMap<String, EditTimes>,
+    /// Items sent in place of `items` while a sync passphrase is set.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sealed_items: Vec<SealedItem>,
+    /// The account's salt when pulled, the one offered for it when pushed, as base64.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub salt: Option<String>,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct SyncSummary {
+    /// Items and labels sent to the server, including deletions.
+    pub pushed: i64,
+    /// Items and labels received from the server, including deletions.
+    pub pulled: i64,
+    /// Items changed on both sides. With `ConflictStrategy::Manual` they are waiting in
+    /// `conflicts_pending`.
+    pub conflicts: i64,
+}
+
+/// How often to pull again when others keep pushing between our pull and push.
+const MAX_ATTEMPTS: usize = 3;
+/// How many items and labels are sent per request, so large pushes report progress and a
+/// failure part way keeps what was sent.
+const PUSH_BATCH_SIZE: usize = 100;
+
+/// The stages of a sync, in order. Downloading and uploading may repeat when others push
+/// at the same time.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SyncProgress {
+    Started,
+    Downloading,
+    /// `sent` of `total` local changes have been pushed.
+    Uploading { sent: i64, total: i64 },
+    Finished(SyncSummary),
+    Failed(ErrorCategory),
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SyncStage {
+    Started = 0,
+    Downloading = 1,
+    Uploading = 2,
+    Finished = 3,
+    Failed = 4,
+}
+
+impl SyncProgress {
+    pub fn stage(&self) -> SyncStage {
+        match *self {
+            SyncProgress::Started => SyncStage::Started,
+            SyncProgress::Downloading => SyncStage::Downloading,
+            SyncProgress::Uploading { .. } => SyncStage::Uploading,
+            SyncProgress::Finished(_) => SyncStage::Finished,
+            SyncProgress::Failed(_) => SyncStage::Failed,
+        }
+    }
+}
+
+/// Called with the stage reached. While uploading, `done` of `total` changes have been sent;
+/// otherwise both are 0. `error` says what to do about a failed sync and is `None` for the
+/// other stages.
+pub type SyncProgressCallback = extern "C" fn(stage: SyncStage, done: i64, total: i64, error: ErrorCategory, user_data: *mut c_void);
+
+fn percent_encode(value: &str) -> String {
+    value.bytes().map(|b| match b {
+        b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+        _ => format!("%{:02X}", b),
+    }).collect()
+}
+
+impl ListManager {
+    /// Sets the server to sync with. Switching to another server starts over from its
+    /// beginning on the next sync.
+    pub fn set_sync_config(&self, base_url: &str, auth_token: &str) {
+        *self.sync_client.config.borrow_mut() = Some((base_url.trim_end_matches('/').to_string(), auth_token.to_string()));
+    }
+
+    pub fn is_sync_configured(&self) -> bool {
+        self.sync_client.config.borrow().is_some()
+    }
+
+    pub fn set_conflict_strategy(&self, strategy: ConflictStrategy) {
+        self.sync_client.strategy.set(strategy);
+    }
+
+    /// The strategy set, except that the `crdt_merge` flag turns manual resolution into a
+    /// field by field merge.
+    fn conflict_strategy(&self) -> ConflictStrategy {
+        match self.sync_client.strategy.get() {
+            ConflictStrategy::Manual if self.flag_enabled(CRDT_MERGE) => ConflictStrategy::FieldMerge,
+            strategy => strategy,
+        }
+    }
+
+    pub fn set_sync_transport(&self, transport: Box<dyn Transport>) {
+        *self.sync_client.transport.borrow_mut() = Rc::from(transport);
+    }
+
+    fn sync_request(&self, method: &'static str, path: &str, body: Option<String>) -> Result<String, SyncError> {
+        let (base_url, auth_token) = self.sync_client.config.borrow().clone().ok_or(SyncError::NotConfigured)?;
+        let request = HttpRequest {
+            method: method,
+            url: format!("{}{}", base_url, path),
+            auth_token: auth_token,
+            body: body,
+        };
+        // other threads may use the library while this one waits on the server
+        let transport = self.sync_client.transport.borrow().clone();
+        let response = lock::unlocked(|| transport.send(&request))?;
+        if response.status < 200 || response.status >= 300 {
+            return Err(SyncError::Http(response.status));
+        }
+        Ok(response.body)
+    }
+
+    fn sync_base_url(&self) -> Result<String, SyncError> {
+        self.sync_client.config.borrow().as_ref().map(|&(ref base_url, _)| base_url.clone()).ok_or(SyncError::NotConfigured)
+    }
+
+    /// `/changes`, asking for changes since the last pull from `base_url` if there was one.
+    fn changes_path(&self, base_url: &str) -> String {
+        let sql = r#"SELECT cursor FROM sync_state WHERE base_url=?"#;
+        let cursor: Option<String> = self.get_store().get_conn().query_row(sql, &[&base_url], |row| row.get(0)).ok().and_then(|cursor| cursor);
+        match cursor {
+            Some(cursor) => format!("/changes?since={}", percent_encode(&cursor)),
+            None => "/changes".to_string(),
+        }
+    }
+
+    fn set_sync_cursor(&self, base_url: &str, cursor: &Option<String>) -> rusqlite::Result<()> {
+        let sql = r#"INSERT OR REPLACE INTO sync_state (id, base_url, cursor) VALUES (0, ?, ?)"#;
+        self.get_store().get_conn().execute(sql, &[&base_url, cursor]).map(|_| ())
+    }
+
+    fn is_pending(&self, kind: &str, id: &str) -> bool {
+        let sql = r#"SELECT count(*) FROM sync_outbox WHERE kind=? AND id=?"#;
+        let count: i64 = self.get_store().get_conn().query_row(sql, &[&kind, &id], |row| row.get(0)).unwrap();
+        count > 0
+    }
+
+    /// How many items and labels changed locally are waiting to be pushed.
+    pub fn pending_change_count(&self) -> i64 {
+        let sql = r#"SELECT count(*) FROM sync_outbox"#;
+        self.get_store().get_conn().query_row(sql, &[], |row| row.get(0)).unwrap()
+    }
+
+    /// Forgets the local changes waiting to be pushed. They are not sent unless made again.
+    pub fn clear_pending_changes(&self) {
+        self.get_store().get_conn().execute_batch("DELETE FROM sync_outbox").unwrap();
+    }
+
+    fn has_pending_conflict(&self, uuid: &str) -> bool {
+        let sql = r#"SELECT count(*) FROM conflicts WHERE item_uuid=?"#;
+        let count: i64 = self.get_store().get_conn().query_row(sql, &[&uuid], |row| row.get(0)).unwrap();
+        count > 0
+    }
+
+    fn pull(&self, summary: &mut SyncSummary, report: &mut SyncReport) -> Result<(), SyncError> {
+        let base_url = self.sync_base_url()?;
+        let path = self.changes_path(&base_url);
+        let mut changes: Changes = serde_json::from_str(&self.sync_request("GET", &path, None)?)?;
+        if let (Some(keys), Some(salt)) = (self.sync_client.keys.borrow().as_ref(), changes.salt.as_ref()) {
+            keys.set_account_salt(salt);
+        }
+        if !changes.sealed_items.is_empty() {
+            let keys = self.sync_client.keys.borrow();
+            let keys = keys.as_ref().ok_or(SyncError::Sealed)?;
+            let unknown = keys.unknown_salts(&changes.sealed_items);
+            if unknown > MAX_UNKNOWN_SALTS {
+                return Err(SyncError::TooManySalts(unknown));
+            }
+            for sealed in changes.sealed_items.iter() {
+                changes.items.push(keys.open(sealed).ok_or(SyncError::Sealed)?);
+            }
+        }
+
+        let store = self.get_store();
+        let conn = store.get_conn();
+        let tx = store.begin_transaction()?;
+        for label in changes.labels.iter() {
+            if self.fetch_label(&label.name).as_ref() == Some(label) || !self.is_label_synced(&label.name) {
+                continue;
+            }
+            conn.execute("INSERT OR REPLACE INTO labels (name, color) VALUES (?, ?)", &[&label.name, &label.color])?;
+            self.unbury(&conn, KIND_LABEL, &label.name)?;
+            conn.execute("DELETE FROM sync_outbox WHERE kind=? AND id=?", &[&KIND_LABEL, &label.name])?;
+            summary.pulled += 1;
+            report.lists.insert(label.name.clone());
+        }
+        let no_edit_times = EditTimes::new();
+        for remote in changes.items.iter() {
+            let local = self.fetch_item(&remote.uuid);
+            if local.as_ref() == Some(remote) || self.is_local_only_item(&remote.uuid) {
+                continue;
+            }
+            let remote_edits = changes.edited_at.get(&remote.uuid).unwrap_or(&no_edit_times);
+            let mut conflict = false;
+            if self.is_pending(KIND_ITEM, &remote.uuid) {
+                match local {
+                    Some(ref local) => {
+                        conflict = true;
+                        summary.conflicts += 1;
+                        if !self.resolve_sync_conflict(self.conflict_strategy(), local, remote, remote_edits) {
+                            self.notify(ChangeEvent::SyncConflict(remote.uuid.clone()));
+                            continue;
+                        }
+                    },
+                    // deleted here and changed there: the deletion is pushed below
+                    None => continue,
+                }
+            }
+            if let Some(deleted_at) = self.deleted_at(KIND_ITEM, &remote.uuid) {
+                if deleted_at.sec >= remote_edits.values().cloned().max().unwrap_or(0) {
+                    // edited there before it was deleted here: tell the server again
+                    conn.execute("INSERT OR IGNORE INTO sync_outbox (kind, id) VALUES (?, ?)", &[&KIND_ITEM, &remote.uuid])?;
+                    continue;
+                }
+            }
+            report.item_pulled(local.as_ref(), remote);
+            self.try_save_item(remote)?;
+            self.set_edit_times(&remote.uuid, remote_edits);
+            conn.execute("DELETE FROM sync_outbox WHERE kind=? AND id=?", &[&KIND_ITEM, &remote.uuid])?;
+            summary.pulled += 1;
+            if conflict {
+                self.notify(ChangeEvent::SyncConflict(remote.uuid.clone()));
+            }
+        }
+        for uuid in changes.deleted_items.iter() {
+            if self.is_pending(KIND_ITEM, uuid) {
+                // changed here and deleted there: the change is pushed below and restores it
+                continue;
+            }
+            if self.is_local_only_item(uuid) {
+                continue;
+            }
+            let local = self.fetch_item(uuid);
+            if self.try_delete_item(uuid)? {
+                conn.execute("DELETE FROM sync_outbox WHERE kind=? AND id=?", &[&KIND_ITEM, uuid])?;
+                summary.pulled += 1;
+                if let Some(ref local) = local {
+                    report.item_deleted(local);
+                }
+            }
+        }
+        for name in changes.deleted_labels.iter() {
+            if self.is_pending(KIND_LABEL, name) || !self.is_label_synced(name) {
+                continue;
+            }
+            // removing the label from its items is part of the deletion, not a change to push
+            let unchanged: Vec<String> = {
+                let sql = r#"SELECT item_uuid FROM item_labels WHERE label_name=?
+                             AND item_uuid NOT IN (SELECT id FROM sync_outbox WHERE kind=?)"#;
+                let mut stmt = conn.prepare(sql)?;
+                let rows = stmt.query_map(&[name, &KIND_ITEM], |row| row.get(0))?;
+                rows.filter_map(|result| result.ok()).collect()
+            };
+            if self.try_delete_label(name)? {
+                conn.execute("DELETE FROM sync_outbox WHERE kind=? AND id=?", &[&KIND_LABEL, name])?;
+                for uuid in unchanged.iter() {
+                    conn.execute("DELETE FROM sync_outbox WHERE kind=? AND id=?", &[&KIND_ITEM, uuid])?;
+                }
+                summary.pulled += 1;
+                report.lists.insert(name.clone());
+            }
+        }
+        self.set_sync_cursor(&base_url, &changes.cursor)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Sends one `POST`, then forgets the pushed records and moves the cursor past them.
+    fn push_batch(&self, base_url: &str, batch: &[(i64, String, String)]) -> Result<(), SyncError> {
+        let mut changes = Changes::default();
+        for &(_, ref kind, ref id) in batch.iter() {
+            if kind == KIND_LABEL {
+                match self.fetch_label(id) {
+                    Some(label) => changes.labels.push(label),
+                    None if self.deleted_at(KIND_LABEL, id).is_some() => changes.deleted_labels.push(id.clone()),
+                    None => {},
+                }
+            } else {
+                match self.fetch_item(id) {
+                    Some(item) => {
+                        changes.edited_at.insert(id.clone(), self.edit_times(id));
+                        match *self.sync_client.keys.borrow() {
+                            Some(ref keys) => {
+                                changes.sealed_items.push(keys.seal(&item)?);
+                                changes.salt = Some(keys.salt());
+                            },
+                            None => changes.items.push(item),
+                        }
+                    },
+                    None if self.deleted_at(KIND_ITEM, id).is_some() => changes.deleted_items.push(id.clone()),
+                    // never saved, e.g. written by a transaction that was rolled back
+                    None => {},
+                }
+            }
+        }
+        let path = self.changes_path(base_url);
+        let response: Changes = serde_json::from_str(&self.sync_request("POST", &path, Some(serde_json::to_string(&changes)?))?)?;
+
+        let store = self.get_store();
+        let conn = store.get_conn();
+        let tx = store.begin_transaction()?;
+        // a record written again while the request was out was queued under a new rowid
+        for &(rowid, ref kind, ref id) in batch.iter() {
+            conn.execute("DELETE FROM sync_outbox WHERE rowid=? AND kind=? AND id=?", &[&rowid, kind, id])?;
+        }
+        if response.cursor.is_some() {
+            self.set_sync_cursor(base_url, &response.cursor)?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn push(&self, summary: &mut SyncSummary) -> Result<(), SyncError> {
+        self.drop_local_only_changes()?;
+        let pending: Vec<(i64, String, String)> = {
+            let conn = self.get_store().get_conn();
+            let mut stmt = conn.prepare("SELECT rowid, kind, id FROM sync_outbox ORDER BY kind DESC, rowid")?;
+            let rows = stmt.query_map(&[], |row| (row.get(0), row.get(1), row.get(2)))?;
+            rows.filter_map(|result| result.ok()).collect()
+        };
+        let pending: Vec<(i64, String, String)> = pending.into_iter()
+            .filter(|&(_, ref kind, ref id)| kind == KIND_LABEL || !self.has_pending_conflict(id))
+            .collect();
+        if pending.is_empty() {
+            return Ok(());
+        }
+        let base_url = self.sync_base_url()?;
+        let total = pending.len() as i64;
+        let mut sent = 0;
+        self.sync_client.progress.notify(&SyncProgress::Uploading { sent: sent, total: total });
+        for batch in pending.chunks(PUSH_BATCH_SIZE) {
+            self.push_batch(&base_url, batch)?;
+            sent += batch.len() as i64;
+            summary.pushed += batch.len() as i64;
+            self.sync_client.progress.notify(&SyncProgress::Uploading { sent: sent, total: total });
+        }
+        Ok(())
+    }
+
+    /// Pulls the server's changes, then pushes the local ones. Either half may fail on its
+    /// own; what was pulled stays applied and the rest is retried by the next sync. What
+    /// happened is kept for `last_sync_summary` either way.
+    pub fn sync_now(&self) -> Result<SyncSummary, SyncError> {
+        breadcrumbs::leave("list.sync");
+        let progress = &self.sync_client.progress;
+        progress.notify(&SyncProgress::Started);
+        let mut summary = SyncSummary::default();
+        let mut report = SyncReport::default();
+        let mut attempt = 1;
+        let result = loop {
+            progress.notify(&SyncProgress::Downloading);
+            if let Err(e) = self.pull(&mut summary, &mut report) {
+                break Err(e);
+            }
+            match self.push(&mut summary) {
+                Err(SyncError::Http(409)) if attempt < MAX_ATTEMPTS => attempt += 1,
+                result => break result,
+            }
+        };
+        report.finished_at = self.now().sec;
+        report.conflicts = summary.conflicts;
+        report.pushed = summary.pushed;
+        report.error = result.as_ref().err().map(|e| e.to_string());
+        self.save_sync_report(&report);
+        match result {
+            Ok(()) => {
+                progress.notify(&SyncProgress::Finished(summary.clone()));
+                Ok(summary)
+            },
+            Err(e) => {
+                progress.notify(&SyncProgress::Failed(e.category()));
+                Err(e)
+            },
+        }
+    }
+
+    /// Calls `observer` as `sync_now` progresses. Returns an id for `remove_sync_observer`.
+    pub fn add_sync_observer(&self, observer: Box<dyn Fn(&SyncProgress)>) -> u64 {
+        self.sync_client.progress.add(observer)
+    }
+
+    pub fn remove_sync_observer(&self, id: u64) -> bool {
+        self.sync_client.progress.remove(id)
+    }
+}
+
+/// `base_url` is everything before `/changes`, e.g. `https://example.com/api/v1`.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_set_sync_config(manager: *const Arc<ListManager>, base_url: *const c_char, auth_token: *const c_char) {
+    ffi_lock!();
+    ffi_guard!({
+        let manager = ffi_ref!("list_manager_set_sync_config", manager);
+        manager.set_sync_config(&c_char_to_string(base_url), &c_char_to_string(auth_token));
+    })
+}
+
+/// `strategy` is one of the `ConflictStrategy` values. Returns false and fills in `error` with
+/// `Bug` if it is not.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_set_conflict_strategy(manager: *const Arc<ListManager>, strategy: u32, error: *mut ExternError) -> bool {
+    ffi_lock!();
+    ffi_guard!(error, {
+        let manager = ffi_ref!("list_manager_set_conflict_strategy", manager, false, error);
+        match ConflictStrategy::from_u32(strategy) {
+            Some(strategy) => {
+                manager.set_conflict_strategy(strategy);
+                true
+            },
+            None => {
+                set_extern_error(error, ErrorCategory::Bug, format!("unknown conflict strategy {}", strategy));
+                false
+            },
+        }
+    })
+}
+
+/// Sends sync requests through `callback` instead of the built in plain HTTP client. It is
+/// called on the thread calling `list_manager_sync_now`, without the library lock, so other
+/// threads may call into the library while it waits on the network.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_set_sync_transport(manager: *const Arc<ListManager>, callback: SyncHttpCallback, user_data: *mut c_void) {
+    ffi_lock!();
+    ffi_guard!({
+        let manager = ffi_ref!("list_manager_set_sync_transport", manager);
+        manager.set_sync_transport(Box::new(HostTransport {
+            callback: callback,
+            user_data: user_data,
+        }));
+    })
+}
+
+/// Records the server's answer from inside a `SyncHttpCallback`. `body` may be null.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn sync_response_set(response: *mut HttpResponse, status: u16, body: *const c_char) {
+    ffi_guard!({
+        let response = ffi_mut!("sync_response_set", response);
+        response.status = status;
+        response.body = if body.is_null() { String::new() } else { c_char_to_string(body) };
+    })
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_pending_change_count(manager: *const Arc<ListManager>) -> i64 {
+    ffi_lock!();
+    ffi_guard!({
+        let manager = ffi_ref!("list_manager_pending_change_count", manager);
+        manager.pending_change_count()
+    })
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_clear_pending_changes(manager: *const Arc<ListManager>) {
+    ffi_lock!();
+    ffi_guard!({
+        let manager = ffi_ref!("list_manager_clear_pending_changes", manager);
+        manager.clear_pending_changes();
+    })
+}
+
+/// Calls `callback` on the thread calling `list_manager_sync_now` as the sync progresses.
+/// Returns an id for `list_manager_remove_sync_observer`.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_add_sync_observer(manager: *const Arc<ListManager>, callback: SyncProgressCallback, user_data: *mut c_void) -> u64 {
+    ffi_lock!();
+    ffi_guard!({
+        let manager = ffi_ref!("list_manager_add_sync_observer", manager);
+        let user_data = user_data as usize;
+        manager.add_sync_observer(Box::new(move |progress| {
+            let (done, total) = match *progress {
+                SyncProgress::Uploading { sent, total } => (sent, total),
+                _ => (0, 0),
+            };
+            let error = match *progress {
+                SyncProgress::Failed(category) => category,
+                _ => ErrorCategory::None,
+            };
+            callback(progress.stage(), done, total, error, user_data as *mut c_void);
+        }))
+    })
+}
+
+/// Returns false if there is no sync observer with that id.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_remove_sync_observer(manager: *const Arc<ListManager>, id: u64) -> bool {
+    ffi_lock!();
+    ffi_guard!({
+        let manager = ffi_ref!("list_manager_remove_sync_observer", manager);
+        manager.remove_sync_observer(id)
+    })
+}
+
+/// Returns null and fills in `error` if the sync failed.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_sync_now(manager: *const Arc<ListManager>, error: *mut ExternError) -> *mut SyncSummary {
+    ffi_lock!();
+    ffi_guard!(error, {
+        let manager = ffi_ref!("list_manager_sync_now", manager, ptr::null_mut(), error);
+        match manager.sync_now() {
+            Ok(summary) => Box::into_raw(Box::new(summary)),
+            Err(e) => {
+                log_error!("failed to sync {:?}", e);
+                set_extern_error(error, e.category(), e.to_string());
+                ptr::null_mut()
+            },
+        }
+    })
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn sync_summary_destroy(summary: *mut SyncSummary) {
+    ffi_guard!({
+        if summary.is_null() {
+            return;
+        }
+        let _ = Box::from_raw(summary);
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::{
+        Cell,
+        RefCell,
+    };
+    use std::io;
+    use std::io::{
+        Read,
+        Write,
+    };
+    use std::net::TcpListener;
+    use std::rc::Rc;
+    use std::sync::Arc;
+    use std::thread;
+
+    use serde_json;
+
+    use time::Timespec;
+
+    use super::{
+        is_loopback,
+        split_host,
+        Changes,
+        HttpRequest,
+        HttpResponse,
+        PlainHttp,
+        SyncError,
+        SyncProgress,
+        SyncSummary,
+        Transport,
+    };
+
+    use ffi_utils::errors::ErrorCategory;
+    use conflicts::ConflictStrategy;
+    use crypto::test::TestCrypto;
+    use events::ChangeEvent;
+    use flags::Flags;
+    use items::Item;
+    use store::Store;
+    use sync_encryption::SealedItem;
+    use ListManager;
+
+    /// Keeps every push, numbered; the cursor is the number of pushes seen.
+    #[derive(Default)]
+    struct FakeServer {
+        log: RefCell<Vec<Changes>>,
+        offline: Cell<bool>,
+        salt: RefCell<Option<String>>,
+    }
+
+    struct FakeTransport(Rc<FakeServer>);
+
+    impl Transport for FakeTransport {
+        fn send(&self, request: &HttpRequest) -> io::Result<HttpResponse> {
+            let server = &self.0;
+            if server.offline.get() {
+                return Err(io::Error::new(io::ErrorKind::NotConnected, "offline"));
+            }
+            assert_eq!(request.auth_token, "token");
+            assert!(request.url.starts_with("http://sync.test/v1/changes"));
+            let since: usize = request.url.split("since=").nth(1).map(|since| since.parse().unwrap()).unwrap_or(0);
+            if request.method == "POST" {
+                if since != server.log.borrow().len() {
+                    return Ok(HttpResponse { status: 409, body: "".to_string() });
+                }
+                let changes: Changes = serde_json::from_str(request.body.as_ref().unwrap()).unwrap();
+                if server.salt.borrow().is_none() {
+                    *server.salt.borrow_mut() = changes.salt.clone();
+                }
+                server.log.borrow_mut().push(changes);
+                let cursor = server.log.borrow().len();
+                return Ok(HttpResponse { status: 200, body: format!("{{\"cursor\": \"{}\"}}", cursor) });
+            }
+            let mut changes = Changes::default();
+            for logged in server.log.borrow().iter().skip(since) {
+                changes.labels.extend(logged.labels.iter().cloned());
+                changes.items.extend(logged.items.iter().cloned());
+                changes.sealed_items.extend(logged.sealed_items.iter().cloned());
+                changes.deleted_items.extend(logged.deleted_items.iter().cloned());
+                changes.deleted_labels.extend(logged.deleted_labels.iter().cloned());
+                changes.edited_at.extend(logged.edited_at.clone());
+            }
+            changes.cursor = Some(server.log.borrow().len().to_string());
+            changes.salt = server.salt.borrow().clone();
+            Ok(HttpResponse { status: 200, body: serde_json::to_string(&changes).unwrap() })
+        }
+    }
+
+    fn device(server: &Rc<FakeServer>) -> ListManager {
+        let manager = ListManager::new(Arc::new(Store::new(None)));
+        manager.set_sync_config("http://sync.test/v1/", "token");
+        manager.set_sync_transport(Box::new(FakeTransport(server.clone())));
+        manager.set_sync_crypto(Rc::new(TestCrypto::default()));
+        manager
+    }
+
+    fn names(manager: &ListManager) -> Vec<String> {
+        let mut names: Vec<String> = manager.fetch_items(&Default::default()).iter().map(|i| i.name.clone()).collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_sync_between_devices() {
+        let server = Rc::new(FakeServer::default());
+        let phone = device(&server);
+        let tablet = device(&server);
+
+        let label = phone.create_label("home".to_string(), "#ff0000".to_string()).unwrap();
+        let mut milk = Item::named("milk");
+        milk.labels = vec![Arc::new(label)];
+        let milk_uuid = phone.create_item(&milk);
+        phone.create_item(&Item::named("bread"));
+        assert_eq!(phone.sync_now().unwrap(), SyncSummary { pushed: 3, pulled: 0, conflicts: 0 });
+        // nothing left to push
+        assert_eq!(phone.sync_now().unwrap(), SyncSummary::default());
+
+        assert_eq!(tablet.sync_now().unwrap(), SyncSummary { pushed: 0, pulled: 3, conflicts: 0 });
+        assert_eq!(names(&tablet), vec!["bread", "milk"]);
+        assert_eq!(tablet.fetch_item(&milk_uuid).unwrap().labels[0].name, "home");
+
+        tablet.delete_item(&milk_uuid);
+        assert_eq!(tablet.sync_now().unwrap().pushed, 1);
+        phone.sync_now().unwrap();
+        assert_eq!(names(&phone), vec!["bread"]);
+
+        assert!(phone.delete_label(&"home".to_string()));
+        assert_eq!(phone.sync_now().unwrap().pushed, 1);
+        assert_eq!(tablet.sync_now().unwrap(), SyncSummary { pushed: 0, pulled: 1, conflicts: 0 });
+        assert_eq!(tablet.fetch_labels(), vec![]);
+    }
+
+    #[test]
+    fn test_offline_changes_replayed() {
+        let server = Rc::new(FakeServer::default());
+        let phone = device(&server);
+        server.offline.set(true);
+        let mut milk = Item::named("milk");
+        milk.uuid = phone.create_item(&milk);
+        phone.create_item(&Item::named("bread"));
+        milk.name = "oat milk".to_string();
+        phone.update_item(&milk, vec![]);
+        assert_eq!(phone.pending_change_count(), 2);
+        match phone.sync_now() {
+            Err(SyncError::Io(_)) => {},
+            other => panic!("expected sync to fail offline, got {:?}", other),
+        }
+        assert_eq!(phone.pending_change_count(), 2);
+
+        server.offline.set(false);
+        assert_eq!(phone.sync_now().unwrap().pushed, 2);
+        let pushed: Vec<String> = server.log.borrow()[0].items.iter().map(|item| item.name.clone()).collect();
+        assert_eq!(pushed, vec!["bread", "oat milk"]);
+        assert_eq!(phone.pending_change_count(), 0);
+
+        phone.create_item(&Item::named("eggs"));
+        phone.clear_pending_changes();
+        assert_eq!(phone.pending_change_count(), 0);
+        assert_eq!(phone.sync_now().unwrap().pushed, 0);
+    }
+
+    #[test]
+    fn test_sync_progress() {
+        let server = Rc::new(FakeServer::default());
+        let phone = device(&server);
+        for _ in 0..150 {
+            phone.create_item(&Item::named("milk"));
+        }
+        let stages = Rc::new(RefCell::new(vec![]));
+        let seen = stages.clone();
+        let id = phone.add_sync_observer(Box::new(move |progress| seen.borrow_mut().push(progress.clone())));
+        let summary = phone.sync_now().unwrap();
+        assert_eq!(*stages.borrow(), vec![
+            SyncProgress::Started,
+            SyncProgress::Downloading,
+            SyncProgress::Uploading { sent: 0, total: 150 },
+            SyncProgress::Uploading { sent: 100, total: 150 },
+            SyncProgress::Uploading { sent: 150, total: 150 },
+            SyncProgress::Finished(summary),
+        ]);
+        assert_eq!(server.log.borrow().len(), 2);
+
+        stages.borrow_mut().clear();
+        server.offline.set(true);
+        assert!(phone.sync_now().is_err());
+        assert_eq!(*stages.borrow(), vec![
+            SyncProgress::Started,
+            SyncProgress::Downloading,
+            SyncProgress::Failed(ErrorCategory::Retryable),
+        ]);
+
+        assert!(phone.remove_sync_observer(id));
+        assert!(!phone.remove_sync_observer(id));
+    }
+
+    #[test]
+    fn test_sealed_items() {
+        let server = Rc::new(FakeServer::default());
+        let phone = device(&server);
+        let tablet = device(&server);
+        phone.set_sync_passphrase(Some("correct horse")).unwrap();
+        let uuid = phone.create_item(&Item::named("renew passport"));
+        phone.sync_now().unwrap();
+        let pushed = serde_json::to_string(&server.log.borrow()[0]).unwrap();
+        assert!(pushed.contains(&uuid));
+        assert!(!pushed.contains("passport"));
+
+        match tablet.sync_now() {
+            Err(SyncError::Sealed) => {},
+            other => panic!("expected sealed items, got {:?}", other),
+        }
+        tablet.set_sync_passphrase(Some("correct horse")).unwrap();
+        assert_eq!(tablet.sync_now().unwrap().pulled, 1);
+        assert_eq!(names(&tablet), vec!["renew passport"]);
+
+        // rotating pushes every item again, sealed with the new passphrase
+        phone.rotate_sync_passphrase("battery staple").unwrap();
+        assert_eq!(phone.pending_change_count(), 1);
+        assert_eq!(phone.sync_now().unwrap().pushed, 1);
+        tablet.set_sync_passphrase(Some("battery staple")).unwrap();
+        tablet.sync_now().unwrap();
+        assert_eq!(SyncError::Sealed.category(), ErrorCategory::NeedsUserInput);
+    }
+
+    #[test]
+    fn test_sealed_items_share_the_account_salt() {
+        let server = Rc::new(FakeServer::default());
+        let phone = device(&server);
+        let tablet = device(&server);
+        phone.set_sync_passphrase(Some("correct horse")).unwrap();
+        tablet.set_sync_passphrase(Some("correct horse")).unwrap();
+        phone.create_item(&Item::named("renew passport"));
+        phone.sync_now().unwrap();
+        tablet.create_item(&Item::named("book flights"));
+        tablet.sync_now().unwrap();
+        let salt = server.salt.borrow().clone();
+        assert!(salt.is_some());
+        assert_eq!(server.log.borrow()[1].salt, salt);
+        assert_eq!(server.log.borrow()[1].sealed_items[0].salt, server.log.borrow()[0].sealed_items[0].salt);
+
+        // a server making up salts cannot have a pull derive a key for each
+        let forged = server.log.borrow()[0].sealed_items[0].clone();
+        server.log.borrow_mut().push(Changes {
+            sealed_items: ["AAAA", "AAAB", "AAAC"].iter().map(|salt| SealedItem { salt: salt.to_string(), ..forged.clone() }).collect(),
+            ..Changes::default()
+        });
+        let crypto = Rc::new(TestCrypto::default());
+        let laptop = device(&server);
+        laptop.set_sync_crypto(crypto.clone());
+        laptop.set_sync_passphrase(Some("correct horse")).unwrap();
+        match laptop.sync_now() {
+            Err(SyncError::TooManySalts(3)) => {},
+            other => panic!("expected too many salts, got {:?}", other),
+        }
+        assert_eq!(crypto.derived.get(), 0);
+    }
+
+    #[test]
+    fn test_last_sync_summary() {
+        let server = Rc::new(FakeServer::default());
+        let phone = device(&server);
+        let tablet = device(&server);
+        assert_eq!(tablet.last_sync_summary(), None);
+
+        let home = phone.create_label("home".to_string(), "#ff0000".to_string()).unwrap();
+        let mut milk = Item::named("milk");
+        milk.labels = vec![Arc::new(home)];
+        milk.uuid = phone.create_item(&milk);
+        let bread = phone.create_item(&Item::named("bread"));
+        phone.sync_now().unwrap();
+        tablet.sync_now().unwrap();
+
+        milk.completion_date = Some(Timespec::new(1510315200, 0));
+        let labels = milk.labels.clone();
+        phone.update_item(&milk, labels);
+        phone.delete_item(&bread);
+        phone.create_item(&Item::named("eggs"));
+        phone.freeze_clock(Some(Timespec::new(1510318800, 0)));
+        phone.sync_now().unwrap();
+        assert_eq!(phone.last_sync_summary().unwrap().pushed, 3);
+
+        tablet.freeze_clock(Some(Timespec::new(1510318800, 0)));
+        tablet.sync_now().unwrap();
+        let report = tablet.last_sync_summary().unwrap();
+        assert_eq!((report.added, report.updated, report.completed, report.deleted), (1, 1, 1, 1));
+        assert_eq!(report.lists.iter().cloned().collect::<Vec<String>>(), vec!["home"]);
+        assert_eq!(report.finished_at, 1510318800);
+        assert_eq!(report.error, None);
+
+        server.offline.set(true);
+        assert!(tablet.sync_now().is_err());
+        assert!(tablet.last_sync_summary().unwrap().error.is_some());
+    }
+
+    #[test]
+    fn test_local_only_labels() {
+        let server = Rc::new(FakeServer::default());
+        let phone = device(&server);
+        let tablet = device(&server);
+        let private = phone.create_label("private".to_string(), "#000000".to_string()).unwrap();
+        assert!(phone.is_label_synced("private"));
+        assert!(phone.set_label_synced("private", false));
+        assert!(!phone.set_label_synced("missing", false));
+        assert!(!phone.is_label_synced("private"));
+
+        let mut gift = Item::named("gift");
+        gift.labels = vec![Arc::new(private)];
+        gift.uuid = phone.create_item(&gift);
+        phone.create_item(&Item::named("milk"));
+        phone.sync_now().unwrap();
+        assert_eq!(phone.pending_change_count(), 0);
+        tablet.sync_now().unwrap();
+        assert_eq!(names(&tablet), vec!["milk"]);
+        assert_eq!(tablet.fetch_label(&"private".to_string()), None);
+
+        // a change to a private item stays on the phone
+        gift.name = "surprise gift".to_string();
+        let labels = gift.labels.clone();
+        phone.update_item(&gift, labels);
+        phone.sync_now().unwrap();
+        tablet.sync_now().unwrap();
+        assert_eq!(names(&tablet), vec!["milk"]);
+
+        assert!(phone.set_label_synced("private", true));
+        phone.sync_now().unwrap();
+        tablet.sync_now().unwrap();
+        assert_eq!(names(&tablet), vec!["milk", "surprise gift"]);
+        assert!(tablet.fetch_label(&"private".to_string()).is_some());
+
+        // once private again, the tablet's edits no longer reach the phone
+        phone.set_label_synced("private", false);
+        let mut copy = tablet.fetch_item(&gift.uuid).unwrap();
+        copy.name = "returned gift".to_string();
+        let labels = copy.labels.clone();
+        tablet.update_item(&copy, labels);
+        tablet.sync_now().unwrap();
+        phone.sync_now().unwrap();
+        assert_eq!(phone.fetch_item(&gift.uuid).unwrap().name, "surprise gift");
+    }
+
+    #[test]
+    fn test_deleted_item_stays_deleted() {
+        let server = Rc::new(FakeServer::default());
+        let phone = device(&server);
+        let tablet = device(&server);
+        phone.freeze_clock(Some(Timespec::new(10, 0)));
+        let uuid = phone.create_item(&Item::named("milk"));
+        phone.sync_now().unwrap();
+        tablet.sync_now().unwrap();
+
+        // the tablet renames it, then the phone deletes it, then the tablet syncs
+        tablet.freeze_clock(Some(Timespec::new(50, 0)));
+        let mut renamed = tablet.fetch_item(&uuid).unwrap();
+        renamed.name = "oat milk".to_string();
+        tablet.save_item(&renamed);
+        phone.freeze_clock(Some(Timespec::new(100, 0)));
+        phone.delete_item(&uuid);
+        phone.sync_now().unwrap();
+        assert_eq!(tablet.sync_now().unwrap().pushed, 1);
+
+        // the rename was made before the deletion, so the phone tells the server again
+        assert_eq!(phone.sync_now().unwrap(), SyncSummary { pushed: 1, pulled: 0, conflicts: 0 });
+        assert_eq!(phone.fetch_item(&uuid), None);
+        tablet.sync_now().unwrap();
+        assert_eq!(tablet.fetch_item(&uuid), None);
+    }
+
+    struct Conflicted {
+        server: Rc<FakeServer>,
+        tablet: ListManager,
+        uuid: String,
+        summary: SyncSummary,
+        events: Rc<RefCell<Vec<ChangeEvent>>>,
+    }
+
+    /// The phone renames an item at 100 while the tablet, offline, sets its due date at
+    /// `tablet_edited_at`. The tablet then syncs with `strategy`.
+    fn changed_on_both_devices(strategy: ConflictStrategy, tablet_edited_at: i64) -> Conflicted {
+        changed_on_both_devices_with_flags(strategy, tablet_edited_at, Flags::default())
+    }
+
+    fn changed_on_both_devices_with_flags(strategy: ConflictStrategy, tablet_edited_at: i64, flags: Flags) -> Conflicted {
+        let server = Rc::new(FakeServer::default());
+        let phone = device(&server);
+        let tablet = device(&server);
+        phone.freeze_clock(Some(Timespec::new(10, 0)));
+        let uuid = phone.create_item(&Item::named("milk"));
+        phone.sync_now().unwrap();
+        tablet.freeze_clock(Some(Timespec::new(20, 0)));
+        tablet.sync_now().unwrap();
+
+        phone.freeze_clock(Some(Timespec::new(100, 0)));
+        let mut on_phone = phone.fetch_item(&uuid).unwrap();
+        on_phone.name = "oat milk".to_string();
+        phone.save_item(&on_phone);
+        phone.sync_now().unwrap();
+
+        tablet.freeze_clock(Some(Timespec::new(tablet_edited_at, 0)));
+        let mut on_tablet = tablet.fetch_item(&uuid).unwrap();
+        on_tablet.due_date = Some(Timespec::new(1000, 0));
+        tablet.save_item(&on_tablet);
+        tablet.set_conflict_strategy(strategy);
+        tablet.set_flags(flags);
+        let events = Rc::new(RefCell::new(vec![]));
+        let seen = events.clone();
+        tablet.add_observer(Box::new(move |event| {
+            if let ChangeEvent::SyncConflict(_) = *event {
+                seen.borrow_mut().push(event.clone());
+            }
+        }));
+        let summary = tablet.sync_now().unwrap();
+        Conflicted {
+            server: server,
+            tablet: tablet,
+            uuid: uuid,
+            summary: summary,
+            events: events,
+        }
+    }
+
+    fn name_and_due_date(manager: &ListManager, uuid: &String) -> (String, Option<i64>) {
+        let item = manager.fetch_item(uuid).unwrap();
+        (item.name.clone(), item.due_date.map(|due| due.sec))
+    }
+
+    #[test]
+    fn test_manual_conflict() {
+        let c = changed_on_both_devices(ConflictStrategy::Manual, 200);
+        assert_eq!(c.summary, SyncSummary { pushed: 0, pulled: 0, conflicts: 1 });
+        assert_eq!(*c.events.borrow(), vec![ChangeEvent::SyncConflict(c.uuid.clone())]);
+        assert_eq!(name_and_due_date(&c.tablet, &c.uuid), ("milk".to_string(), Some(1000)));
+        let conflicts = c.tablet.conflicts_pending();
+        assert_eq!(conflicts[0].remote.name, "oat milk");
+    }
+
+    #[test]
+    fn test_last_writer_wins() {
+        let c = changed_on_both_devices(ConflictStrategy::LastWriterWins, 200);
+        assert_eq!(c.summary, SyncSummary { pushed: 1, pulled: 0, conflicts: 1 });
+        assert_eq!(*c.events.borrow(), vec![ChangeEvent::SyncConflict(c.uuid.clone())]);
+        assert_eq!(name_and_due_date(&c.tablet, &c.uuid), ("milk".to_string(), Some(1000)));
+        let phone = device(&c.server);
+        phone.sync_now().unwrap();
+        assert_eq!(name_and_due_date(&phone, &c.uuid), ("milk".to_string(), Some(1000)));
+
+        let c = changed_on_both_devices(ConflictStrategy::LastWriterWins, 50);
+        assert_eq!(c.summary, SyncSummary { pushed: 0, pulled: 1, conflicts: 1 });
+        assert_eq!(*c.events.borrow(), vec![ChangeEvent::SyncConflict(c.uuid.clone())]);
+        assert_eq!(name_and_due_date(&c.tablet, &c.uuid), ("oat milk".to_string(), None));
+        assert!(c.tablet.conflicts_pending().is_empty());
+    }
+
+    #[test]
+    fn test_field_merge() {
+        let c = changed_on_both_devices(ConflictStrategy::FieldMerge, 50);
+        assert_eq!(c.summary, SyncSummary { pushed: 1, pulled: 0, conflicts: 1 });
+        assert_eq!(name_and_due_date(&c.tablet, &c.uuid), ("oat milk".to_string(), Some(1000)));
+        let phone = device(&c.server);
+        phone.sync_now().unwrap();
+        assert_eq!(name_and_due_date(&phone, &c.uuid), ("oat milk".to_string(), Some(1000)));
+        // the merged item keeps the time of each edit it took
+        let times = c.tablet.edit_times(&c.uuid);
+        assert_eq!((times.get("name"), times.get("due_date")), (Some(&100), Some(&50)));
+    }
+
+    #[test]
+    fn test_crdt_merge_flag() {
+        let flags = Flags::parse(r#"{"crdt_merge": true}"#).unwrap();
+        let c = changed_on_both_devices_with_flags(ConflictStrategy::Manual, 50, flags.clone());
+        assert_eq!(c.summary, SyncSummary { pushed: 1, pulled: 0, conflicts: 1 });
+        assert_eq!(name_and_due_date(&c.tablet, &c.uuid), ("oat milk".to_string(), Some(1000)));
+        assert!(c.tablet.conflicts_pending().is_empty());
+
+        // other strategies are left alone
+        let c = changed_on_both_devices_with_flags(ConflictStrategy::DuplicateBoth, 200, flags);
+        assert_eq!(names(&c.tablet), vec!["milk", "oat milk"]);
+    }
+
+    #[test]
+    fn test_duplicate_both() {
+        let c = changed_on_both_devices(ConflictStrategy::DuplicateBoth, 200);
+        assert_eq!(c.summary, SyncSummary { pushed: 2, pulled: 0, conflicts: 1 });
+        assert_eq!(names(&c.tablet), vec!["milk", "oat milk"]);
+        assert_eq!(name_and_due_date(&c.tablet, &c.uuid), ("milk".to_string(), Some(1000)));
+    }
+
+    #[test]
+    fn test_failed_pull_is_retried() {
+        let server = Rc::new(FakeServer::default());
+        let phone = device(&server);
+        let tablet = device(&server);
+        phone.create_item(&Item::named("bread"));
+        phone.create_item(&Item::named("milk"));
+        phone.sync_now().unwrap();
+
+        tablet.get_store().get_conn().execute_batch(r#"
+            CREATE TEMP TRIGGER refuse_milk BEFORE INSERT ON items WHEN NEW.name = 'milk' BEGIN
+                SELECT RAISE(ABORT, 'refused');
+            END"#).unwrap();
+        match tablet.sync_now() {
+            Err(SyncError::Sqlite(_)) => {},
+            other => panic!("expected the pull to fail, got {:?}", other),
+        }
+        // nothing applied, and the cursor did not move past the failed item
+        assert!(names(&tablet).is_empty());
+        tablet.get_store().get_conn().execute_batch("DROP TRIGGER refuse_milk").unwrap();
+        assert_eq!(tablet.sync_now().unwrap().pulled, 2);
+        assert_eq!(names(&tablet), vec!["bread", "milk"]);
+    }
+
+    #[test]
+    fn test_sync_errors() {
+        let manager = ListManager::new(Arc::new(Store::new(None)));
+        match manager.sync_now() {
+            Err(SyncError::NotConfigured) => {},
+            other => panic!("expected sync to need configuring, got {:?}", other),
+        }
+        assert_eq!(SyncError::Http(401).category(), ErrorCategory::NeedsReauth);
+        assert_eq!(SyncError::Http(503).category(), ErrorCategory::Retryable);
+        assert_eq!(SyncError::Http(404).category(), ErrorCategory::Bug);
+    }
+
+    #[test]
+    fn test_unknown_conflict_strategy() {
+        use ffi_utils::errors::{
+            error_message_destroy,
+            ExternError,
+        };
+        use super::list_manager_set_conflict_strategy;
+
+        let manager = Arc::new(ListManager::new(Arc::new(Store::new(None))));
+        let mut error = ExternError::default();
+        assert!(unsafe { list_manager_set_conflict_strategy(&manager, ConflictStrategy::FieldMerge as u32, &mut error) });
+        assert_eq!(error.category, ErrorCategory::None);
+        assert_eq!(manager.sync_client.strategy.get(), ConflictStrategy::FieldMerge);
+
+        assert!(!unsafe { list_manager_set_conflict_strategy(&manager, 4, &mut error) });
+        assert_eq!(error.category, ErrorCategory::Bug);
+        unsafe { error_message_destroy(error.message) };
+        assert_eq!(manager.sync_client.strategy.get(), ConflictStrategy::FieldMerge);
+    }
+
+    #[test]
+    fn test_plain_http() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0; 1024];
+            let read = stream.read(&mut request).unwrap();
+            stream.write_all(b"HTTP/1.0 201 Created\r\nContent-Type: application/json\r\n\r\n{\"ok\":true}").unwrap();
+            String::from_utf8_lossy(&request[..read]).into_owned()
+        });
+        let response = PlainHttp.send(&HttpRequest {
+            method: "POST",
+            url: format!("http://{}/v1/changes", address),
+            auth_token: "token".to_string(),
+            body: Some("{}".to_string()),
+        }).unwrap();
+        assert_eq!(response, HttpResponse { status: 201, body: "{\"ok\":true}".to_string() });
+        let request = server.join().unwrap();
+        assert!(request.starts_with("POST /v1/changes HTTP/1.0\r\n"));
+        assert!(request.contains("Authorization: Bearer token\r\n"));
+        assert!(request.ends_with("\r\n\r\n{}"));
+
+        assert!(PlainHttp.send(&HttpRequest {
+            method: "GET",
+            url: "https://example.com/changes".to_string(),
+            auth_token: "token".to_string(),
+            body: None,
+        }).is_err());
+    }
+
+    #[test]
+    fn test_plain_http_stays_on_this_machine() {
+        let error = PlainHttp.send(&HttpRequest {
+            method: "GET",
+            url: "http://example.com:8080/changes".to_string(),
+            auth_token: "token".to_string(),
+            body: None,
+        }).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::PermissionDenied);
+
+        assert_eq!(split_host("localhost:8080").unwrap(), ("localhost", 8080));
+        assert_eq!(split_host("[::1]").unwrap(), ("::1", 80));
+        assert_eq!(split_host("[::1]:8080").unwrap(), ("::1", 8080));
+        assert!(is_loopback("127.0.0.1") && is_loopback("::1") && is_loopback("LOCALHOST"));
+        assert!(!is_loopback("10.0.0.1") && !is_loopback("sync.example.com"));
+    }
+}
@@ -0,0 +1,302 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! With a sync passphrase set, items are sealed before they are pushed, so the server keeps
+//! only their uuids, edit times and ciphertext. Devices seal with a key derived from the
+//! passphrase and the account's salt, so any device with the passphrase can open what the
+//! others pushed. Labels are not sealed.
+//!
+//! The first device to push sealed items offers a random salt, which the server keeps as
+//! the account's and returns with every pull; the others adopt it. Each sealed item still
+//! names its salt, so items pushed before a device learned the account's salt open too. As
+//! deriving a key is slow on purpose, only a few are kept, and a pull bringing more than a
+//! couple of salts without one fails instead of deriving them all.
+//!
+//! The passphrase is never stored; hosts keep it in the platform keychain and set it again
+//! at launch. The cryptography is the platform's too, see `crypto`.
+
+use std::cell::RefCell;
+use std::collections::{
+    BTreeMap,
+    BTreeSet,
+};
+use std::fmt;
+use std::io;
+use std::os::raw::c_char;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use serde_json;
+
+use ffi_utils::breadcrumbs;
+use ffi_utils::errors::{
+    set_extern_error,
+    ErrorCategory,
+    ExternError,
+};
+use ffi_utils::strings::c_char_to_string;
+use crypto;
+use crypto::{
+    HostCrypto,
+    Key,
+    SyncCrypto,
+    NONCE_LEN,
+};
+use items::Item;
+use ListManager;
+
+/// PBKDF2 rounds for deriving a key from the passphrase.
+pub const KDF_ROUNDS: u32 = 100_000;
+const SALT_LEN: usize = 16;
+/// The most keys kept derived at once.
+const MAX_KEYS: usize = 4;
+/// The most salts without a derived key that one pull may bring.
+pub const MAX_UNKNOWN_SALTS: usize = 2;
+
+/// An item as the server sees it with encryption on. All but the uuid are base64.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SealedItem {
+    pub uuid: String,
+    pub salt: String,
+    pub nonce: String,
+    pub ciphertext: String,
+}
+
+pub struct SyncKeys {
+    crypto: Rc<dyn SyncCrypto>,
+    passphrase: String,
+    /// The salt this device seals with: the account's once a pull has named it, until then
+    /// a random one offered as the account's.
+    salt: RefCell<Vec<u8>>,
+    /// Keys already derived, by salt. At most `MAX_KEYS`.
+    keys: RefCell<BTreeMap<Vec<u8>, Key>>,
+}
+
+impl fmt::Debug for SyncKeys {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // never log the passphrase
+        write!(f, "SyncKeys({})", self.salt())
+    }
+}
+
+impl SyncKeys {
+    fn new(crypto: Rc<dyn SyncCrypto>, passphrase: &str) -> io::Result<SyncKeys> {
+        Ok(SyncKeys {
+            crypto: crypto,
+            passphrase: passphrase.to_string(),
+            salt: RefCell::new(crypto::random_bytes(SALT_LEN)?),
+            keys: RefCell::new(BTreeMap::new()),
+        })
+    }
+
+    /// The salt items are sealed with, as base64.
+    pub fn salt(&self) -> String {
+        crypto::base64_encode(&self.salt.borrow())
+    }
+
+    /// Seals with the account's salt from now on. Ignored unless it is base64.
+    pub fn set_account_salt(&self, salt: &str) {
+        match crypto::base64_decode(salt) {
+            Some(salt) => *self.salt.borrow_mut() = salt,
+            None => log_warn!("ignoring a malformed account salt"),
+        }
+    }
+
+    /// How many salts other than the one this device seals with the items were sealed with
+    /// and no key has been derived for.
+    pub fn unknown_salts(&self, sealed: &[SealedItem]) -> usize {
+        let own = self.salt();
+        let keys = self.keys.borrow();
+        let unknown: BTreeSet<&str> = sealed.iter()
+            .map(|sealed| sealed.salt.as_str())
+            .filter(|&salt| salt != own && !crypto::base64_decode(salt).is_some_and(|salt| keys.contains_key(&salt)))
+            .collect();
+        unknown.len()
+    }
+
+    fn key(&self, salt: &[u8]) -> io::Result<Key> {
+        if let Some(key) = self.keys.borrow().get(salt) {
+            return Ok(*key);
+        }
+        let key = self.crypto.derive_key(self.passphrase.as_bytes(), salt, KDF_ROUNDS)?;
+        let mut keys = self.keys.borrow_mut();
+        if keys.len() >= MAX_KEYS {
+            let own = self.salt.borrow();
+            let evicted = keys.keys().find(|&salt| *salt != *own).cloned();
+            if let Some(evicted) = evicted {
+                keys.remove(&evicted);
+            }
+        }
+        keys.insert(salt.to_vec(), key);
+        Ok(key)
+    }
+
+    /// Seals the item with the uuid as associated data, so the ciphertext cannot be passed
+    /// off as another item's.
+    pub fn seal(&self, item: &Item) -> io::Result<SealedItem> {
+        let mut nonce = [0; NONCE_LEN];
+        nonce.copy_from_slice(&crypto::random_bytes(NONCE_LEN)?);
+        let plaintext = serde_json::to_vec(item)?;
+        let salt = self.salt.borrow().clone();
+        let ciphertext = self.crypto.seal(&self.key(&salt)?, &nonce, item.uuid.as_bytes(), &plaintext)?;
+        Ok(SealedItem {
+            uuid: item.uuid.clone(),
+            salt: crypto::base64_encode(&salt),
+            nonce: crypto::base64_encode(&nonce),
+            ciphertext: crypto::base64_encode(&ciphertext),
+        })
+    }
+
+    /// Returns `None` if the item was sealed with another passphrase or altered.
+    pub fn open(&self, sealed: &SealedItem) -> Option<Item> {
+        let salt = crypto::base64_decode(&sealed.salt)?;
+        let nonce_bytes = crypto::base64_decode(&sealed.nonce)?;
+        if nonce_bytes.len() != NONCE_LEN {
+            return None;
+        }
+        let mut nonce = [0; NONCE_LEN];
+        nonce.copy_from_slice(&nonce_bytes);
+        let ciphertext = crypto::base64_decode(&sealed.ciphertext)?;
+        let plaintext = self.crypto.open(&self.key(&salt).ok()?, &nonce, sealed.uuid.as_bytes(), &ciphertext)?;
+        serde_json::from_slice::<Item>(&plaintext).ok().filter(|item| item.uuid == sealed.uuid)
+    }
+}
+
+impl ListManager {
+    /// Sets the cryptography sealing items. Needed before a passphrase can be set.
+    pub fn set_sync_crypto(&self, crypto: Rc<dyn SyncCrypto>) {
+        if let Some(ref mut keys) = *self.sync_client.keys.borrow_mut() {
+            keys.crypto = crypto.clone();
+            keys.keys.borrow_mut().clear();
+        }
+        *self.sync_client.crypto.borrow_mut() = Some(crypto);
+    }
+
+    /// Seals items pushed from now on with a key derived from `passphrase`, and opens sealed
+    /// items pulled with it. `None` turns encryption off for pushes. Fails with
+    /// `InvalidInput` if no cryptography has been set.
+    pub fn set_sync_passphrase(&self, passphrase: Option<&str>) -> io::Result<()> {
+        breadcrumbs::leave("list.set_sync_passphrase");
+        let keys = match passphrase {
+            Some(passphrase) => {
+                let crypto = self.sync_client.crypto.borrow().clone()
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "set the sync crypto before a passphrase"))?;
+                Some(SyncKeys::new(crypto, passphrase)?)
+            },
+            None => None,
+        };
+        *self.sync_client.keys.borrow_mut() = keys;
+        Ok(())
+    }
+
+    /// Switches to a new passphrase and queues every item, so the next sync replaces the
+    /// server's copies with ones sealed with it. Other devices need the new passphrase to
+    /// open them.
+    pub fn rotate_sync_passphrase(&self, passphrase: &str) -> io::Result<()> {
+        self.set_sync_passphrase(Some(passphrase))?;
+        let sql = r#"INSERT OR IGNORE INTO sync_outbox (kind, id) SELECT 'item', uuid FROM items"#;
+        self.get_store().get_conn().execute(sql, &[]).map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        Ok(())
+    }
+}
+
+unsafe fn passphrase_set(result: io::Result<()>, error: *mut ExternError) -> bool {
+    match result {
+        Ok(()) => true,
+        Err(e) => {
+            log_error!("failed to set sync passphrase {:?}", e);
+            let category = if e.kind() == io::ErrorKind::InvalidInput { ErrorCategory::Bug } else { ErrorCategory::Retryable };
+            set_extern_error(error, category, e.to_string());
+            false
+        },
+    }
+}
+
+/// Copies `crypto`, whose callbacks are called on the thread syncing.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_set_sync_crypto(manager: *const Arc<ListManager>, crypto: *const HostCrypto) {
+    ffi_lock!();
+    ffi_guard!({
+        let manager = ffi_ref!("list_manager_set_sync_crypto", manager);
+        let crypto = ffi_ref!("list_manager_set_sync_crypto", crypto);
+        manager.set_sync_crypto(Rc::new(*crypto));
+    })
+}
+
+/// `passphrase` may be null to stop sealing pushed items. Returns false and fills in
+/// `error` if no sync crypto has been set or no random salt could be made.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_set_sync_passphrase(manager: *const Arc<ListManager>, passphrase: *const c_char, error: *mut ExternError) -> bool {
+    ffi_lock!();
+    ffi_guard!(error, {
+        let manager = ffi_ref!("list_manager_set_sync_passphrase", manager, false, error);
+        let passphrase = if passphrase.is_null() { None } else { Some(c_char_to_string(passphrase)) };
+        passphrase_set(manager.set_sync_passphrase(passphrase.as_deref()), error)
+    })
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_rotate_sync_passphrase(manager: *const Arc<ListManager>, passphrase: *const c_char, error: *mut ExternError) -> bool {
+    ffi_lock!();
+    ffi_guard!(error, {
+        let manager = ffi_ref!("list_manager_rotate_sync_passphrase", manager, false, error);
+        passphrase_set(manager.rotate_sync_passphrase(&c_char_to_string(passphrase)), error)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::rc::Rc;
+
+    use super::{
+        SyncKeys,
+        MAX_KEYS,
+    };
+
+    use crypto::test::TestCrypto;
+    use items::Item;
+
+    #[test]
+    fn test_seal_and_open() {
+        let item = Item {
+            uuid: "a".to_string(),
+            name: "renew passport".to_string(),
+            due_date: None,
+            completion_date: None,
+            labels: vec![],
+        };
+        let crypto = Rc::new(TestCrypto::default());
+        let keys = SyncKeys::new(crypto.clone(), "correct horse").unwrap();
+        let sealed = keys.seal(&item).unwrap();
+        assert!(!sealed.ciphertext.contains("passport"));
+        assert_eq!(keys.open(&sealed), Some(item.clone()));
+
+        // another device with the same passphrase
+        assert_eq!(SyncKeys::new(crypto.clone(), "correct horse").unwrap().open(&sealed), Some(item));
+        assert_eq!(SyncKeys::new(crypto.clone(), "battery staple").unwrap().open(&sealed), None);
+
+        let mut moved = sealed.clone();
+        moved.uuid = "b".to_string();
+        assert_eq!(keys.open(&moved), None);
+    }
+
+    #[test]
+    fn test_keys_are_bounded() {
+        let crypto = Rc::new(TestCrypto::default());
+        let keys = SyncKeys::new(crypto.clone(), "correct horse").unwrap();
+        for salt in 0..10 {
+            keys.key(&[salt]).unwrap();
+        }
+        assert_eq!(keys.keys.borrow().len(), MAX_KEYS);
+        assert_eq!(crypto.derived.get(), 10);
+        keys.key(&[9]).unwrap();
+        assert_eq!(crypto.derived.get(), 10);
+    }
+}
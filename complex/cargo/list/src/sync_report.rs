@@ -0,0 +1,107 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! What the last sync changed on this device, kept in the store for the app to show and for
+//! support to read when someone's tasks changed overnight.
+
+use std::collections::BTreeSet;
+use std::os::raw::c_char;
+use std::ptr;
+use std::sync::Arc;
+
+use serde_json;
+
+use ffi_utils::strings::string_to_c_char;
+use items::Item;
+use store::migrations::MigrationStep;
+use ListManager;
+
+pub const CREATE_SYNC_REPORT_TABLE: MigrationStep = MigrationStep {
+    name: "create_sync_report_table",
+    sql: r#"CREATE TABLE IF NOT EXISTS sync_report (
+            id INTEGER NOT NULL PRIMARY KEY CHECK (id = 0),
+            report TEXT NOT NULL
+        )"#,
+};
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SyncReport {
+    /// When the sync ended, in seconds since 1970.
+    pub finished_at: i64,
+    /// Items created elsewhere.
+    pub added: i64,
+    /// Items changed elsewhere, including those in `completed`.
+    pub updated: i64,
+    /// Items completed elsewhere.
+    pub completed: i64,
+    /// Items deleted elsewhere.
+    pub deleted: i64,
+    /// Items changed both here and elsewhere.
+    pub conflicts: i64,
+    /// Items and labels changed here and sent.
+    pub pushed: i64,
+    /// The labels changed elsewhere or holding items that were, by name.
+    pub lists: BTreeSet<String>,
+    /// Why the sync failed. What was pulled before the failure is still counted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl SyncReport {
+    /// Counts a pulled item, `local` being this device's version before it was replaced.
+    pub(crate) fn item_pulled(&mut self, local: Option<&Item>, remote: &Item) {
+        match local {
+            Some(local) => {
+                self.updated += 1;
+                if local.completion_date.is_none() && remote.completion_date.is_some() {
+                    self.completed += 1;
+                }
+                self.lists.extend(local.labels.iter().map(|label| label.name.clone()));
+            },
+            None => self.added += 1,
+        }
+        self.lists.extend(remote.labels.iter().map(|label| label.name.clone()));
+    }
+
+    pub(crate) fn item_deleted(&mut self, local: &Item) {
+        self.deleted += 1;
+        self.lists.extend(local.labels.iter().map(|label| label.name.clone()));
+    }
+}
+
+impl ListManager {
+    pub(crate) fn save_sync_report(&self, report: &SyncReport) {
+        let sql = r#"INSERT OR REPLACE INTO sync_report (id, report) VALUES (0, ?)"#;
+        let json = serde_json::to_string(report).unwrap();
+        if let Err(e) = self.get_store().get_conn().execute(sql, &[&json]) {
+            log_error!("failed to save sync report {:?}", e);
+        }
+    }
+
+    /// What the last sync changed here, or `None` if this device has not synced.
+    pub fn last_sync_summary(&self) -> Option<SyncReport> {
+        let sql = r#"SELECT report FROM sync_report WHERE id=0"#;
+        let json: String = self.get_store().get_conn().query_row(sql, &[], |row| row.get(0)).ok()?;
+        serde_json::from_str(&json).ok()
+    }
+}
+
+/// Returns null if this device has not synced.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_last_sync_summary(manager: *const Arc<ListManager>) -> *mut c_char {
+    ffi_lock!();
+    ffi_guard!({
+        let manager = ffi_ref!("list_manager_last_sync_summary", manager, ptr::null_mut());
+        match manager.last_sync_summary() {
+            Some(report) => string_to_c_char(serde_json::to_string(&report).unwrap()),
+            None => ptr::null_mut(),
+        }
+    })
+}
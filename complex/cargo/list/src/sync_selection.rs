@@ -0,0 +1,106 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! Lists kept off the server. A local-only label is never pushed, and neither is any item
+//! carrying it; the server's changes to them are not pulled either. What was pushed before
+//! the label was made local-only stays on the server.
+
+use std::os::raw::c_char;
+use std::sync::Arc;
+
+use rusqlite;
+
+use ffi_utils::breadcrumbs;
+use ffi_utils::strings::c_char_to_string;
+use store::migrations::MigrationStep;
+use tombstones::{
+    KIND_ITEM,
+    KIND_LABEL,
+};
+use ListManager;
+
+pub const CREATE_LOCAL_ONLY_LABELS_TABLE: MigrationStep = MigrationStep {
+    name: "create_local_only_labels_table",
+    sql: r#"CREATE TABLE IF NOT EXISTS local_only_labels (
+                name TEXT NOT NULL PRIMARY KEY
+            );
+            CREATE TRIGGER IF NOT EXISTS local_only_labels_deleted AFTER DELETE ON labels BEGIN
+                DELETE FROM local_only_labels WHERE name=OLD.name;
+            END"#,
+};
+
+impl ListManager {
+    /// Keeps the label and its items off the server, or syncs them again. Making a label
+    /// synced again queues it and its items for the next sync. Returns false if there is no
+    /// such label.
+    pub fn set_label_synced(&self, name: &str, synced: bool) -> bool {
+        breadcrumbs::leave("list.set_label_synced");
+        if self.fetch_label(&name.to_string()).is_none() {
+            return false;
+        }
+        let store = self.get_store();
+        let conn = store.get_conn();
+        let tx = store.begin_transaction().expect("expected a transaction");
+        if synced {
+            conn.execute("DELETE FROM local_only_labels WHERE name=?", &[&name]).unwrap();
+            conn.execute("INSERT OR REPLACE INTO sync_outbox (kind, id) VALUES (?, ?)", &[&KIND_LABEL, &name]).unwrap();
+            let sql = r#"INSERT OR REPLACE INTO sync_outbox (kind, id)
+                         SELECT ?, item_uuid FROM item_labels WHERE label_name=?"#;
+            conn.execute(sql, &[&KIND_ITEM, &name]).unwrap();
+        } else {
+            conn.execute("INSERT OR IGNORE INTO local_only_labels (name) VALUES (?)", &[&name]).unwrap();
+            self.drop_local_only_changes().unwrap();
+        }
+        let _ = tx.commit();
+        true
+    }
+
+    pub fn is_label_synced(&self, name: &str) -> bool {
+        let sql = r#"SELECT count(*) FROM local_only_labels WHERE name=?"#;
+        let count: i64 = self.get_store().get_conn().query_row(sql, &[&name], |row| row.get(0)).unwrap();
+        count == 0
+    }
+
+    /// Whether the item carries a local-only label.
+    pub(crate) fn is_local_only_item(&self, uuid: &str) -> bool {
+        let sql = r#"SELECT count(*) FROM item_labels WHERE item_uuid=?
+                     AND label_name IN (SELECT name FROM local_only_labels)"#;
+        let count: i64 = self.get_store().get_conn().query_row(sql, &[&uuid], |row| row.get(0)).unwrap();
+        count > 0
+    }
+
+    /// Removes local-only labels and their items from the outbox.
+    pub(crate) fn drop_local_only_changes(&self) -> rusqlite::Result<()> {
+        let sql = r#"DELETE FROM sync_outbox
+                     WHERE (kind=? AND id IN (SELECT name FROM local_only_labels))
+                     OR (kind=? AND id IN (SELECT item_uuid FROM item_labels
+                                           WHERE label_name IN (SELECT name FROM local_only_labels)))"#;
+        self.get_store().get_conn().execute(sql, &[&KIND_LABEL, &KIND_ITEM]).map(|_| ())
+    }
+}
+
+/// Returns false if there is no label called `name`.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_set_label_synced(manager: *const Arc<ListManager>, name: *const c_char, synced: bool) -> bool {
+    ffi_lock!();
+    ffi_guard!({
+        let manager = ffi_ref!("list_manager_set_label_synced", manager);
+        manager.set_label_synced(&c_char_to_string(name), synced)
+    })
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_is_label_synced(manager: *const Arc<ListManager>, name: *const c_char) -> bool {
+    ffi_lock!();
+    ffi_guard!({
+        let manager = ffi_ref!("list_manager_is_label_synced", manager);
+        manager.is_label_synced(&c_char_to_string(name))
+    })
+}
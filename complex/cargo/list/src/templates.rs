@@ -0,0 +1,231 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+use std::os::raw::c_char;
+use std::sync::Arc;
+
+use serde_json;
+
+use ffi_utils::strings::{
+    string_to_c_char,
+    c_char_to_string,
+};
+use items::Item;
+use ListManager;
+
+const DEFAULT_LANGUAGE: &'static str = "en";
+
+struct LocalizedTemplate {
+    language: &'static str,
+    name: &'static str,
+    label: &'static str,
+    items: &'static [&'static str],
+}
+
+struct TemplateData {
+    id: &'static str,
+    color: &'static str,
+    localized: &'static [LocalizedTemplate],
+}
+
+const TEMPLATES: &'static [TemplateData] = &[
+    TemplateData {
+        id: "grocery",
+        color: "#4caf50",
+        localized: &[
+            LocalizedTemplate {
+                language: "en",
+                name: "Grocery list",
+                label: "Groceries",
+                items: &["Milk", "Bread", "Eggs", "Apples", "Coffee"],
+            },
+            LocalizedTemplate {
+                language: "fr",
+                name: "Liste de courses",
+                label: "Courses",
+                items: &["Lait", "Pain", "Œufs", "Pommes", "Café"],
+            },
+        ],
+    },
+    TemplateData {
+        id: "travel_packing",
+        color: "#2196f3",
+        localized: &[
+            LocalizedTemplate {
+                language: "en",
+                name: "Travel packing",
+                label: "Packing",
+                items: &["Passport", "Phone charger", "Toothbrush", "Change of clothes", "Travel adapter"],
+            },
+            LocalizedTemplate {
+                language: "fr",
+                name: "Valise de voyage",
+                label: "Valise",
+                items: &["Passeport", "Chargeur de téléphone", "Brosse à dents", "Vêtements de rechange", "Adaptateur de voyage"],
+            },
+        ],
+    },
+    TemplateData {
+        id: "new_project",
+        color: "#ff9800",
+        localized: &[
+            LocalizedTemplate {
+                language: "en",
+                name: "New project",
+                label: "Project",
+                items: &["Write down the goal", "List the stakeholders", "Break the work into steps", "Schedule a kickoff"],
+            },
+            LocalizedTemplate {
+                language: "fr",
+                name: "Nouveau projet",
+                label: "Projet",
+                items: &["Définir l'objectif", "Lister les parties prenantes", "Découper le travail en étapes", "Planifier un lancement"],
+            },
+        ],
+    },
+];
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+/// A starter list, translated for one locale. Instantiating it creates `label` with
+/// `color` and one item per entry in `items`.
+pub struct Template {
+    pub id: String,
+    pub name: String,
+    pub label: String,
+    pub color: String,
+    pub items: Vec<String>,
+}
+
+impl TemplateData {
+    fn localize(&self, locale: &str) -> Template {
+        let language = language_of(locale);
+        let localized = self.localized.iter().find(|l| l.language == language)
+            .or_else(|| self.localized.iter().find(|l| l.language == DEFAULT_LANGUAGE))
+            .expect("every template has a default translation");
+        Template {
+            id: self.id.to_string(),
+            name: localized.name.to_string(),
+            label: localized.label.to_string(),
+            color: self.color.to_string(),
+            items: localized.items.iter().map(|item| item.to_string()).collect(),
+        }
+    }
+}
+
+/// The language part of a locale such as "fr-CA" or "fr_CA", lower cased.
+fn language_of(locale: &str) -> String {
+    locale.split(|c| c == '-' || c == '_').next().unwrap_or("").to_lowercase()
+}
+
+/// Every template, translated for `locale` where a translation exists and in English
+/// otherwise.
+pub fn templates_available(locale: &str) -> Vec<Template> {
+    TEMPLATES.iter().map(|template| template.localize(locale)).collect()
+}
+
+impl ListManager {
+    /// Creates the template's label, unless a label with that name already exists, and
+    /// its items. Returns the uuids of the new items, or `None` if there is no template
+    /// with that id.
+    pub fn template_instantiate(&self, id: &str, locale: &str) -> Option<Vec<String>> {
+        let template = match TEMPLATES.iter().find(|template| template.id == id) {
+            Some(template) => template.localize(locale),
+            None => return None,
+        };
+        let label = match self.fetch_label(&template.label) {
+            Some(label) => label,
+            None => self.create_label(template.label.clone(), template.color.clone())?,
+        };
+        let label = self.share_label(&label);
+        Some(template.items.iter().map(|name| {
+            self.create_item(&Item {
+                uuid: "".to_string(),
+                name: name.clone(),
+                due_date: None,
+                completion_date: None,
+                labels: vec![Arc::clone(&label)],
+            })
+        }).collect())
+    }
+}
+
+/// Returns the templates as a JSON array of objects with `id`, `name`, `label`, `color`
+/// and `items` keys.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn templates_available_json(locale: *const c_char) -> *mut c_char {
+    ffi_guard!({
+        let locale = c_char_to_string(locale);
+        string_to_c_char(serde_json::to_string(&templates_available(&locale)).unwrap())
+    })
+}
+
+/// Returns false if there is no template with that id.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_template_instantiate(manager: *const Arc<ListManager>, id: *const c_char, locale: *const c_char) -> bool {
+    ffi_lock!();
+    ffi_guard!({
+        let manager = ffi_ref!("list_manager_template_instantiate", manager);
+        let id = c_char_to_string(id);
+        let locale = c_char_to_string(locale);
+        manager.template_instantiate(&id, &locale).is_some()
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use super::{
+        templates_available,
+        TEMPLATES,
+    };
+
+    use filters::ItemFilter;
+    use store::Store;
+    use ListManager;
+
+    #[test]
+    fn test_templates_available() {
+        let english = templates_available("en-US");
+        assert_eq!(english.iter().map(|t| t.id.as_str()).collect::<Vec<&str>>(),
+                   vec!["grocery", "travel_packing", "new_project"]);
+        assert_eq!(english[0].name, "Grocery list");
+
+        assert_eq!(templates_available("fr_CA")[0].name, "Liste de courses");
+        // untranslated locales fall back to English
+        assert_eq!(templates_available("ja"), english);
+
+        // every translation has as many items as the English version
+        for template in TEMPLATES.iter() {
+            let count = template.localized[0].items.len();
+            assert!(template.localized.iter().all(|l| l.items.len() == count));
+        }
+    }
+
+    #[test]
+    fn test_template_instantiate() {
+        let manager = ListManager::new(Arc::new(Store::new(None)));
+        let uuids = manager.template_instantiate("grocery", "en").expect("expected items");
+        assert_eq!(uuids.len(), 5);
+        let filter = ItemFilter {
+            labels: vec!["Groceries".to_string()],
+            ..ItemFilter::default()
+        };
+        let names: Vec<String> = manager.fetch_items(&filter).iter().map(|i| i.name.clone()).collect();
+        assert_eq!(names, vec!["Milk", "Bread", "Eggs", "Apples", "Coffee"]);
+
+        // instantiating again reuses the label
+        manager.template_instantiate("grocery", "en").unwrap();
+        assert_eq!(manager.fetch_labels().len(), 1);
+        assert_eq!(manager.count_items(&filter), 10);
+
+        assert_eq!(manager.template_instantiate("missing", "en"), None);
+    }
+}
@@ -0,0 +1,313 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! Items in the todo.txt format (https://github.com/todotxt/todo.txt), one per line.
+//!
+//! Everything but the name becomes a label: `+project` the label `project`, `@context` the
+//! label `@context`, and priority `(A)` the label `priority:A`. Due dates are kept in the
+//! `due:` key. Dates are days in UTC.
+
+use std::os::raw::c_char;
+use std::sync::Arc;
+use std::ptr;
+
+use rusqlite;
+use serde_json;
+use time::{
+    at_utc,
+    strptime,
+    Timespec,
+};
+
+use ffi_utils::breadcrumbs;
+use ffi_utils::errors::ExternError;
+use ffi_utils::strings::{
+    string_to_c_char,
+    c_char_to_string,
+};
+use filters::ItemFilter;
+use ics::IMPORTED_LABEL_COLOR;
+use items::Item;
+use labels::Label;
+use store::errors::set_sqlite_error;
+use ListManager;
+
+/// Labels named this followed by a letter hold a todo.txt priority.
+pub const PRIORITY_LABEL_PREFIX: &'static str = "priority:";
+
+fn day(date: Timespec) -> String {
+    at_utc(date).strftime("%Y-%m-%d").unwrap().to_string()
+}
+
+fn parse_day(value: &str) -> Option<Timespec> {
+    if value.len() != 10 {
+        return None;
+    }
+    strptime(value, "%Y-%m-%d").ok().map(|tm| tm.to_timespec())
+}
+
+/// The letter of a `(A)` priority.
+fn parse_priority(token: &str) -> Option<char> {
+    let mut chars = token.chars();
+    match (chars.next(), chars.next(), chars.next(), chars.next()) {
+        (Some('('), Some(letter), Some(')'), None) if letter.is_ascii_uppercase() => Some(letter),
+        _ => None,
+    }
+}
+
+fn priority_of(label: &Label) -> Option<char> {
+    if !label.name.starts_with(PRIORITY_LABEL_PREFIX) {
+        return None;
+    }
+    let mut chars = label.name[PRIORITY_LABEL_PREFIX.len()..].chars();
+    match (chars.next(), chars.next()) {
+        (Some(letter), None) if letter.is_ascii_uppercase() => Some(letter),
+        _ => None,
+    }
+}
+
+/// A label as a `+project` or `@context` tag, which cannot hold spaces.
+fn tag(label: &Label) -> String {
+    let name = label.name.split_whitespace().collect::<Vec<&str>>().join("_");
+    if name.starts_with('@') { name } else { format!("+{}", name) }
+}
+
+/// One line for the item. Completed items keep their priority as `pri:A`, as the format
+/// asks.
+pub fn to_todo_txt_line(item: &Item) -> String {
+    let mut parts = vec![];
+    let priority = item.labels.iter().filter_map(|label| priority_of(label)).next();
+    match item.completion_date {
+        Some(completion_date) => {
+            parts.push("x".to_string());
+            parts.push(day(completion_date));
+        },
+        None => if let Some(priority) = priority {
+            parts.push(format!("({})", priority));
+        },
+    }
+    parts.extend(item.name.split_whitespace().map(|word| word.to_string()));
+    // projects before contexts
+    let mut tags: Vec<String> = item.labels.iter().filter(|label| priority_of(label).is_none()).map(|label| tag(label)).collect();
+    tags.sort_by_key(|tag| tag.starts_with('@'));
+    parts.extend(tags);
+    if let Some(due_date) = item.due_date {
+        parts.push(format!("due:{}", day(due_date)));
+    }
+    if let (Some(priority), Some(_)) = (priority, item.completion_date) {
+        parts.push(format!("pri:{}", priority));
+    }
+    parts.join(" ")
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct Task {
+    name: String,
+    completed: bool,
+    completion_date: Option<Timespec>,
+    due_date: Option<Timespec>,
+    labels: Vec<String>,
+}
+
+fn parse_line(line: &str) -> Option<Task> {
+    let mut task = Task::default();
+    let mut tokens = line.split_whitespace().peekable();
+    if tokens.peek() == Some(&"x") {
+        tokens.next();
+        task.completed = true;
+        task.completion_date = tokens.peek().and_then(|token| parse_day(token));
+        if task.completion_date.is_some() {
+            tokens.next();
+        }
+    } else if let Some(priority) = tokens.peek().and_then(|token| parse_priority(token)) {
+        tokens.next();
+        task.labels.push(format!("{}{}", PRIORITY_LABEL_PREFIX, priority));
+    }
+    // the creation date, which items do not keep
+    if tokens.peek().and_then(|token| parse_day(token)).is_some() {
+        tokens.next();
+    }
+    let mut words = vec![];
+    for token in tokens {
+        if token.len() > 1 && token.starts_with('+') {
+            task.labels.push(token[1..].to_string());
+        } else if token.len() > 1 && token.starts_with('@') {
+            task.labels.push(token.to_string());
+        } else if token.starts_with("due:") && parse_day(&token[4..]).is_some() {
+            task.due_date = parse_day(&token[4..]);
+        } else if token.starts_with("pri:") && parse_priority(&format!("({})", &token[4..])).is_some() {
+            task.labels.push(format!("{}{}", PRIORITY_LABEL_PREFIX, &token[4..]));
+        } else {
+            words.push(token);
+        }
+    }
+    task.name = words.join(" ");
+    if task.name.is_empty() { None } else { Some(task) }
+}
+
+impl ListManager {
+    /// Every item, one per line.
+    pub fn to_todo_txt(&self) -> String {
+        let mut text = String::new();
+        for item in self.fetch_items(&ItemFilter::default()).iter() {
+            text.push_str(&to_todo_txt_line(item));
+            text.push('\n');
+        }
+        text
+    }
+
+    /// Adds a new item for each task in `text`, creating labels not in the store. Blank
+    /// lines and lines holding nothing but tags are skipped. Returns the uuids of the new
+    /// items.
+    pub fn import_todo_txt(&self, text: &str) -> Vec<String> {
+        self.try_import_todo_txt(text).unwrap_or_else(|e| {
+            log_error!("failed to import todo.txt {:?}", e);
+            vec![]
+        })
+    }
+
+    /// Like `import_todo_txt`, but nothing is imported and the error is returned if any task
+    /// cannot be written.
+    pub fn try_import_todo_txt(&self, text: &str) -> rusqlite::Result<Vec<String>> {
+        breadcrumbs::leave("list.import_todo_txt");
+        let store = self.get_store();
+        let tx = store.begin_transaction()?;
+        let mut uuids = vec![];
+        for task in text.lines().filter_map(parse_line) {
+            let mut labels: Vec<Arc<Label>> = vec![];
+            for name in task.labels.iter() {
+                let label = match self.fetch_label(name) {
+                    Some(label) => label,
+                    None => self.try_create_label(name.clone(), IMPORTED_LABEL_COLOR.to_string())?,
+                };
+                let label = self.share_label(&label);
+                if !labels.iter().any(|existing| existing.name == label.name) {
+                    labels.push(label);
+                }
+            }
+            let completion_date = match task.completion_date {
+                Some(date) => Some(date),
+                None if task.completed => Some(self.now()),
+                None => None,
+            };
+            uuids.push(self.try_create_item(&Item {
+                uuid: "".to_string(),
+                name: task.name,
+                due_date: task.due_date,
+                completion_date: completion_date,
+                labels: labels,
+            })?);
+        }
+        tx.commit()?;
+        Ok(uuids)
+    }
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_export_todo_txt(manager: *const Arc<ListManager>) -> *mut c_char {
+    ffi_lock!();
+    ffi_guard!({
+        let manager = ffi_ref!("list_manager_export_todo_txt", manager, ptr::null_mut());
+        string_to_c_char(manager.to_todo_txt())
+    })
+}
+
+/// Returns the uuids of the new items as a JSON array, or null and fills in `error` if they
+/// could not be written, in which case none are.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_import_todo_txt(manager: *const Arc<ListManager>, text: *const c_char, error: *mut ExternError) -> *mut c_char {
+    ffi_lock!();
+    ffi_guard!(error, {
+        let manager = ffi_ref!("list_manager_import_todo_txt", manager, ptr::null_mut(), error);
+        match manager.try_import_todo_txt(&c_char_to_string(text)) {
+            Ok(uuids) => string_to_c_char(serde_json::to_string(&uuids).unwrap()),
+            Err(e) => {
+                log_error!("failed to import todo.txt {:?}", e);
+                set_sqlite_error(error, &e);
+                ptr::null_mut()
+            },
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use time::Timespec;
+
+    use super::{
+        parse_line,
+        to_todo_txt_line,
+        Task,
+    };
+
+    use filters::ItemFilter;
+    use items::Item;
+    use labels::Label;
+    use store::Store;
+    use ListManager;
+
+    const NOV_10: i64 = 1510272000;
+
+    fn label(name: &str) -> Label {
+        Label { name: name.to_string(), color: "#000000".to_string() }
+    }
+
+    #[test]
+    fn test_parse_line() {
+        assert_eq!(parse_line("(A) 2017-11-01 Call Mom +Family @phone due:2017-11-10"), Some(Task {
+            name: "Call Mom".to_string(),
+            completed: false,
+            completion_date: None,
+            due_date: Some(Timespec::new(NOV_10, 0)),
+            labels: vec!["priority:A".to_string(), "Family".to_string(), "@phone".to_string()],
+        }));
+        assert_eq!(parse_line("x 2017-11-10 2017-11-01 Pay rent pri:B"), Some(Task {
+            name: "Pay rent".to_string(),
+            completed: true,
+            completion_date: Some(Timespec::new(NOV_10, 0)),
+            due_date: None,
+            labels: vec!["priority:B".to_string()],
+        }));
+        // a priority anywhere else and unknown keys are part of the name
+        assert_eq!(parse_line("Email (A) team re: due:soon").unwrap().name, "Email (A) team re: due:soon");
+        assert_eq!(parse_line("x done").unwrap().completion_date, None);
+        assert_eq!(parse_line("   "), None);
+        assert_eq!(parse_line("+project @context"), None);
+    }
+
+    #[test]
+    fn test_to_todo_txt_line() {
+        let mut item = Item {
+            uuid: "a".to_string(),
+            name: "Plan\ntrip".to_string(),
+            due_date: Some(Timespec::new(NOV_10 + 3600, 0)),
+            completion_date: None,
+            labels: vec![Arc::new(label("priority:C")), Arc::new(label("summer holiday")), Arc::new(label("@laptop"))],
+        };
+        assert_eq!(to_todo_txt_line(&item), "(C) Plan trip +summer_holiday @laptop due:2017-11-10");
+        item.completion_date = Some(Timespec::new(NOV_10, 0));
+        assert_eq!(to_todo_txt_line(&item), "x 2017-11-10 Plan trip +summer_holiday @laptop due:2017-11-10 pri:C");
+    }
+
+    #[test]
+    fn test_todo_txt_round_trip() {
+        let text = "(A) Call Mom +Family @phone due:2017-11-10\n\
+                    \n\
+                    x 2017-11-10 Pay rent\n";
+        let manager = ListManager::new(Arc::new(Store::new(None)));
+        assert_eq!(manager.import_todo_txt(text).len(), 2);
+        let mut lines: Vec<String> = manager.fetch_items(&ItemFilter::default()).iter().map(to_todo_txt_line).collect();
+        lines.sort();
+        assert_eq!(lines, vec!["(A) Call Mom +Family @phone due:2017-11-10", "x 2017-11-10 Pay rent"]);
+        assert!(manager.fetch_label(&"@phone".to_string()).is_some());
+    }
+}
@@ -0,0 +1,183 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! A tombstone remembers that an item or label existed and when it was deleted, so that sync
+//! can tell a deletion from something it has never seen, and does not bring a deleted record
+//! back because of an edit made elsewhere before the deletion.
+
+use std::sync::Arc;
+
+use rusqlite;
+use rusqlite::Connection;
+use time::{
+    Duration,
+    Timespec,
+};
+
+use store::migrations::MigrationStep;
+use ListManager;
+
+pub const CREATE_TOMBSTONES_TABLE: MigrationStep = MigrationStep {
+    name: "create_tombstones_table",
+    sql: r#"CREATE TABLE IF NOT EXISTS tombstones (
+            kind TEXT NOT NULL,
+            id TEXT NOT NULL,
+            deleted_at DATETIME NOT NULL,
+            PRIMARY KEY(kind, id)
+        )"#,
+};
+
+/// How long tombstones are kept by default. Devices that have not synced for longer may
+/// bring deleted records back.
+pub const DEFAULT_HORIZON_DAYS: i64 = 90;
+
+pub const KIND_ITEM: &'static str = "item";
+pub const KIND_LABEL: &'static str = "label";
+
+impl ListManager {
+    /// Records the deletion. Expected to run inside the transaction deleting the record.
+    pub(crate) fn bury(&self, conn: &Connection, kind: &str, id: &str) -> rusqlite::Result<()> {
+        let sql = r#"INSERT OR REPLACE INTO tombstones (kind, id, deleted_at) VALUES (?, ?, ?)"#;
+        conn.execute(sql, &[&kind, &id, &self.now()]).map(|_| ())
+    }
+
+    /// Forgets the deletion of a record that has been created again.
+    pub(crate) fn unbury(&self, conn: &Connection, kind: &str, id: &str) -> rusqlite::Result<()> {
+        conn.execute("DELETE FROM tombstones WHERE kind=? AND id=?", &[&kind, &id]).map(|_| ())
+    }
+
+    /// When the record was deleted, or `None` if it was not, or so long ago that its
+    /// tombstone has been pruned.
+    pub fn deleted_at(&self, kind: &str, id: &str) -> Option<Timespec> {
+        let sql = r#"SELECT deleted_at FROM tombstones WHERE kind=? AND id=?"#;
+        self.get_store().get_conn().query_row(sql, &[&kind, &id], |row| row.get(0)).ok()
+    }
+
+    /// Removes tombstones older than `horizon`, except those for deletions not yet pushed by
+    /// sync. Returns how many were removed.
+    pub fn prune_tombstones(&self, horizon: Duration) -> i64 {
+        let sql = r#"DELETE FROM tombstones
+                     WHERE deleted_at < ?
+                     AND NOT EXISTS (SELECT 1 FROM sync_outbox WHERE sync_outbox.kind=tombstones.kind AND sync_outbox.id=tombstones.id)"#;
+        let cutoff = self.now() - horizon;
+        self.get_store().get_conn().execute(sql, &[&cutoff]).unwrap() as i64
+    }
+}
+
+/// Removes tombstones for deletions more than `horizon_days` ago that sync has pushed, or
+/// older than `DEFAULT_HORIZON_DAYS` if `horizon_days` is negative. Returns how many were
+/// removed.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_prune_tombstones(manager: *const Arc<ListManager>, horizon_days: i64) -> i64 {
+    ffi_lock!();
+    ffi_guard!({
+        let manager = ffi_ref!("list_manager_prune_tombstones", manager);
+        let days = if horizon_days < 0 { DEFAULT_HORIZON_DAYS } else { horizon_days };
+        manager.prune_tombstones(Duration::days(days))
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use time::{
+        Duration,
+        Timespec,
+    };
+
+    use super::{
+        KIND_ITEM,
+        KIND_LABEL,
+    };
+
+    use items::Item;
+    use store::Store;
+    use ListManager;
+
+    #[test]
+    fn test_tombstones() {
+        let manager = ListManager::new(Arc::new(Store::new(None)));
+        let now = Timespec::new(1510315200, 0);
+        manager.freeze_clock(Some(now));
+        let label = manager.create_label("home".to_string(), "#000000".to_string()).unwrap();
+        let mut item = Item {
+            uuid: "".to_string(),
+            name: "milk".to_string(),
+            due_date: None,
+            completion_date: None,
+            labels: vec![Arc::new(label)],
+        };
+        item.uuid = manager.create_item(&item);
+        assert_eq!(manager.deleted_at(KIND_ITEM, &item.uuid), None);
+
+        assert!(manager.delete_item(&item.uuid));
+        assert!(manager.delete_label(&"home".to_string()));
+        assert_eq!(manager.deleted_at(KIND_ITEM, &item.uuid), Some(now));
+        assert_eq!(manager.deleted_at(KIND_LABEL, "home"), Some(now));
+
+        // creating it again forgets the deletion
+        item.labels.clear();
+        manager.save_item(&item);
+        assert_eq!(manager.deleted_at(KIND_ITEM, &item.uuid), None);
+    }
+
+    #[test]
+    fn test_prune_tombstones() {
+        let manager = ListManager::new(Arc::new(Store::new(None)));
+        let now = Timespec::new(1510315200, 0);
+        let item = Item::named("milk");
+        let old = manager.create_item(&item);
+        let recent = manager.create_item(&item);
+        manager.freeze_clock(Some(now - Duration::days(100)));
+        manager.delete_item(&old);
+        manager.freeze_clock(Some(now));
+        manager.delete_item(&recent);
+
+        // neither deletion has been pushed
+        assert_eq!(manager.prune_tombstones(Duration::days(90)), 0);
+        manager.get_store().get_conn().execute_batch("DELETE FROM sync_outbox").unwrap();
+        assert_eq!(manager.prune_tombstones(Duration::days(90)), 1);
+        assert_eq!(manager.deleted_at(KIND_ITEM, &old), None);
+        assert_eq!(manager.deleted_at(KIND_ITEM, &recent), Some(now));
+    }
+
+    #[test]
+    fn test_store_caps() {
+        let manager = ListManager::new(Arc::new(Store::new(None)));
+        let store = manager.get_store();
+        let now = Timespec::new(1510315200, 0);
+        let uuids: Vec<String> = (0..3).map(|_| manager.create_item(&Item::named("milk"))).collect();
+        for (hours, uuid) in uuids.iter().enumerate() {
+            manager.freeze_clock(Some(now + Duration::hours(hours as i64)));
+            manager.delete_item(uuid);
+        }
+        let report = store.size_report().unwrap();
+        assert!(report.history > 0 && report.changelog > 0);
+
+        // the trash is pruned oldest first; no deletion has been pushed, so all are kept
+        store.set_history_cap(Some(1));
+        store.set_changelog_cap(Some(1));
+        assert_eq!(store.prune_to_caps().unwrap(), 2);
+        let trashed: Vec<String> = {
+            let conn = store.get_conn();
+            let mut stmt = conn.prepare("SELECT uuid FROM trashed_items").unwrap();
+            let rows = stmt.query_map(&[], |row| row.get(0)).unwrap();
+            rows.map(|row| row.unwrap()).collect()
+        };
+        assert_eq!(trashed, vec![uuids[2].clone()]);
+        assert!(uuids.iter().all(|uuid| manager.deleted_at(KIND_ITEM, uuid).is_some()));
+
+        store.get_conn().execute("DELETE FROM sync_outbox WHERE id<>?", &[&uuids[2]]).unwrap();
+        assert_eq!(store.prune_to_caps().unwrap(), 2);
+        assert_eq!(manager.deleted_at(KIND_ITEM, &uuids[1]), None);
+        assert_eq!(manager.deleted_at(KIND_ITEM, &uuids[2]), Some(now + Duration::hours(2)));
+    }
+}
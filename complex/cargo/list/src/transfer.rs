@@ -0,0 +1,265 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! Each profile, e.g. personal and work, is a store of its own. Items are copied or moved
+//! between them here, as new items in the destination.
+
+use std::os::raw::c_char;
+use std::sync::Arc;
+use std::ptr;
+
+use rusqlite;
+use serde_json;
+
+use ffi_utils::breadcrumbs;
+use ffi_utils::errors::{
+    set_extern_error,
+    ErrorCategory,
+    ExternError,
+};
+use ffi_utils::strings::{
+    string_to_c_char,
+    c_char_to_string,
+};
+use items::Item;
+use labels::Label;
+use store::errors::set_sqlite_error;
+use ListManager;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CopyOptions {
+    /// Delete the items from the source once copied.
+    pub move_items: bool,
+    /// Copy the items' links, contacts and locations too.
+    pub include_attachments: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CopiedItem {
+    /// The uuid in the source store.
+    pub from: String,
+    /// The uuid of the copy in the destination store.
+    pub to: String,
+}
+
+/// The destination's label of the same name, created with the source's color if missing.
+fn reconcile_label(dst: &ListManager, label: &Label) -> rusqlite::Result<Arc<Label>> {
+    let label = match dst.fetch_label(&label.name) {
+        Some(label) => label,
+        None => dst.try_create_label(label.name.clone(), label.color.clone())?,
+    };
+    Ok(dst.share_label(&label))
+}
+
+/// Copies the items with these uuids from `src` to `dst` under new uuids, with their labels
+/// and, if asked, attachments. Labels are matched by name; one the destination already has
+/// keeps its color. Returns the uuid of each copy, leaving out uuids not found in `src`.
+pub fn copy_items_between_stores(src: &ListManager, dst: &ListManager, uuids: &[String], options: CopyOptions) -> Vec<CopiedItem> {
+    try_copy_items_between_stores(src, dst, uuids, options).unwrap_or_else(|e| {
+        log_error!("failed to copy items between stores {:?}", e);
+        vec![]
+    })
+}
+
+/// Like `copy_items_between_stores`, but returns the error if the copies cannot be written,
+/// in which case none are, or if moved items cannot be deleted from `src`, in which case
+/// none are and the copies stay in `dst`.
+pub fn try_copy_items_between_stores(src: &ListManager, dst: &ListManager, uuids: &[String], options: CopyOptions) -> rusqlite::Result<Vec<CopiedItem>> {
+    breadcrumbs::leave("list.copy_items_between_stores");
+    if Arc::ptr_eq(&src.get_store(), &dst.get_store()) && options.move_items {
+        // moving within a store leaves everything where it is
+        return Ok(uuids.iter().filter(|uuid| src.fetch_item(uuid).is_some()).map(|uuid| CopiedItem {
+            from: uuid.clone(),
+            to: uuid.clone(),
+        }).collect());
+    }
+    let dst_store = dst.get_store();
+    let tx = dst_store.begin_transaction()?;
+    let mut copied = vec![];
+    for uuid in uuids.iter() {
+        let mut item = match src.fetch_item(uuid) {
+            Some(item) => item,
+            None => continue,
+        };
+        item.labels = item.labels.iter().map(|label| reconcile_label(dst, label)).collect::<rusqlite::Result<_>>()?;
+        let copy = dst.try_create_item(&item)?;
+        if options.include_attachments {
+            for attachment in src.fetch_attachments(uuid) {
+                dst.try_add_attachment(&copy, attachment.payload)?;
+            }
+        }
+        copied.push(CopiedItem {
+            from: uuid.clone(),
+            to: copy,
+        });
+    }
+    tx.commit()?;
+    if options.move_items {
+        let src_store = src.get_store();
+        let tx = src_store.begin_transaction()?;
+        for item in copied.iter() {
+            src.try_delete_item(&item.from)?;
+        }
+        tx.commit()?;
+    }
+    Ok(copied)
+}
+
+impl ListManager {
+    /// Writes a copy of `item`, as given, under a new uuid, with the attachments of the
+    /// stored item it was read from. Returns the copy's uuid.
+    pub fn duplicate_item(&self, item: &Item) -> rusqlite::Result<String> {
+        breadcrumbs::leave("list.duplicate_item");
+        let store = self.get_store();
+        let tx = store.begin_transaction()?;
+        let copy = self.try_create_item(item)?;
+        if !item.uuid.is_empty() {
+            for attachment in self.fetch_attachments(&item.uuid) {
+                self.try_add_attachment(&copy, attachment.payload)?;
+            }
+        }
+        tx.commit()?;
+        Ok(copy)
+    }
+}
+
+/// Returns the uuid of the copy, or null and fills in `error` if it could not be written.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn item_duplicate_in_store(manager: *const Arc<ListManager>, item: *const Item, error: *mut ExternError) -> *mut c_char {
+    ffi_lock!();
+    ffi_guard!(error, {
+        let manager = ffi_ref!("item_duplicate_in_store", manager, ptr::null_mut(), error);
+        let item = ffi_ref!("item_duplicate_in_store", item, ptr::null_mut(), error);
+        match manager.duplicate_item(item) {
+            Ok(uuid) => string_to_c_char(uuid),
+            Err(e) => {
+                log_error!("failed to duplicate item {:?}", e);
+                set_sqlite_error(error, &e);
+                ptr::null_mut()
+            },
+        }
+    })
+}
+
+/// `uuids` is a JSON array of item uuids in `src`. Returns a JSON array of `{"from", "to"}`
+/// objects giving the uuid of each copy in `dst`, or null and fills in `error` if `uuids` is
+/// not a JSON array of strings (`Bug`) or the items could not be copied or moved.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_copy_items_between_stores(src: *const Arc<ListManager>, dst: *const Arc<ListManager>, uuids: *const c_char, options: CopyOptions, error: *mut ExternError) -> *mut c_char {
+    ffi_lock!();
+    ffi_guard!(error, {
+        let src = ffi_ref!("list_manager_copy_items_between_stores", src, ptr::null_mut(), error);
+        let dst = ffi_ref!("list_manager_copy_items_between_stores", dst, ptr::null_mut(), error);
+        let uuids: Vec<String> = match serde_json::from_str(&c_char_to_string(uuids)) {
+            Ok(uuids) => uuids,
+            Err(e) => {
+                set_extern_error(error, ErrorCategory::Bug, e.to_string());
+                return ptr::null_mut();
+            },
+        };
+        match try_copy_items_between_stores(src, dst, &uuids, options) {
+            Ok(copied) => string_to_c_char(serde_json::to_string(&copied).unwrap()),
+            Err(e) => {
+                log_error!("failed to copy items between stores {:?}", e);
+                set_sqlite_error(error, &e);
+                ptr::null_mut()
+            },
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use super::{
+        copy_items_between_stores,
+        CopyOptions,
+    };
+
+    use attachments::Payload;
+    use items::Item;
+    use labels::Label;
+    use store::Store;
+    use ListManager;
+
+    fn project(manager: &ListManager) -> Vec<String> {
+        let release = manager.create_label("release".to_string(), "#00ff00".to_string()).unwrap();
+        let urgent = manager.create_label("urgent".to_string(), "#ff0000".to_string()).unwrap();
+        let uuid = manager.create_item(&Item {
+            uuid: "".to_string(),
+            name: "tag v2".to_string(),
+            due_date: None,
+            completion_date: None,
+            labels: vec![Arc::new(release), Arc::new(urgent)],
+        });
+        manager.add_attachment(&uuid, Payload::Link {
+            url: "https://example.com/v2".to_string(),
+            title: None,
+        }).unwrap();
+        vec![uuid]
+    }
+
+    #[test]
+    fn test_copy_between_stores() {
+        let personal = ListManager::new(Arc::new(Store::new(None)));
+        let work = ListManager::new(Arc::new(Store::new(None)));
+        work.create_label("release".to_string(), "#0000ff".to_string()).unwrap();
+        let uuids = project(&personal);
+
+        let copied = copy_items_between_stores(&personal, &work, &uuids, CopyOptions::default());
+        assert_eq!(copied.len(), 1);
+        assert_eq!(copied[0].from, uuids[0]);
+        assert!(copied[0].to != uuids[0]);
+        let copy = work.fetch_item(&copied[0].to).unwrap();
+        assert_eq!(copy.name, "tag v2");
+        let mut labels = copy.labels.clone();
+        labels.sort_by(|a, b| a.name.cmp(&b.name));
+        assert_eq!(labels, vec![
+            Arc::new(Label { name: "release".to_string(), color: "#0000ff".to_string() }),
+            Arc::new(Label { name: "urgent".to_string(), color: "#ff0000".to_string() }),
+        ]);
+        assert_eq!(work.fetch_attachments(&copied[0].to), vec![]);
+        assert!(personal.fetch_item(&uuids[0]).is_some());
+    }
+
+    #[test]
+    fn test_duplicate_item() {
+        let manager = ListManager::new(Arc::new(Store::new(None)));
+        let uuids = project(&manager);
+        let mut item = manager.fetch_item(&uuids[0]).unwrap();
+        item.name = "tag v3".to_string();
+
+        let copy = manager.duplicate_item(&item).unwrap();
+        assert!(copy != uuids[0]);
+        let duplicate = manager.fetch_item(&copy).unwrap();
+        assert_eq!(duplicate.name, "tag v3");
+        assert_eq!(duplicate.labels.len(), 2);
+        assert_eq!(manager.fetch_attachments(&copy).len(), 1);
+        assert_eq!(manager.fetch_item(&uuids[0]).unwrap().name, "tag v2");
+    }
+
+    #[test]
+    fn test_move_between_stores() {
+        let personal = ListManager::new(Arc::new(Store::new(None)));
+        let work = ListManager::new(Arc::new(Store::new(None)));
+        let mut uuids = project(&personal);
+        uuids.push("missing".to_string());
+
+        let options = CopyOptions { move_items: true, include_attachments: true };
+        let copied = copy_items_between_stores(&personal, &work, &uuids, options);
+        assert_eq!(copied.len(), 1);
+        assert_eq!(personal.fetch_item(&uuids[0]), None);
+        let attachments = work.fetch_attachments(&copied[0].to);
+        assert_eq!(attachments.len(), 1);
+        assert_eq!(attachments[0].payload, Payload::Link { url: "https://example.com/v2".to_string(), title: None });
+    }
+}
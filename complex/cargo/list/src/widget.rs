@@ -0,0 +1,171 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+//! What a home screen widget shows: the open items due today or earlier, soonest first.
+//! List managers keep a process wide snapshot of them up to date as items change, so an
+//! `AppWidgetProvider` can read it without a list manager, the FFI lock or a query.
+
+use std::os::raw::c_char;
+use std::ptr;
+use std::sync::Mutex;
+
+use serde_json;
+use time::{
+    Duration,
+    Timespec,
+};
+
+use ffi_utils::strings::string_to_c_char;
+use filters::start_of_local_day;
+use store::Store;
+use ListManager;
+
+/// The most items a snapshot keeps; `total` still counts the rest.
+pub const WIDGET_SNAPSHOT_CAPACITY: usize = 50;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WidgetItem {
+    pub uuid: String,
+    pub name: String,
+    /// Seconds since 1970.
+    pub due_date: i64,
+    /// Due before today rather than today.
+    pub overdue: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WidgetSnapshot {
+    pub generated_at: i64,
+    /// The end of the local day the snapshot was taken on, after which it is out of date
+    /// until the host calls `toodle_on_significant_time_change`.
+    pub expires_at: i64,
+    /// How many items are due, including those not in `items`.
+    pub total: i64,
+    pub items: Vec<WidgetItem>,
+}
+
+static SNAPSHOT: Mutex<Option<WidgetSnapshot>> = Mutex::new(None);
+
+impl ListManager {
+    /// The open items due before the end of the local day, soonest first, keeping at most
+    /// `capacity` of them. The day is that of the last `on_significant_time_change`, or the
+    /// UTC one if it was never called.
+    pub fn widget_snapshot(&self, capacity: usize) -> WidgetSnapshot {
+        let utc_offset = self.day_boundary.get().map_or(0, |(_, utc_offset)| utc_offset);
+        take_snapshot(&self.get_store(), self.now(), utc_offset, capacity)
+    }
+
+    /// Replaces the snapshot `widget_snapshot_json` returns with this list's.
+    pub(crate) fn refresh_widget_snapshot(&self) {
+        self.widget_refresh()();
+    }
+
+    /// Does what `refresh_widget_snapshot` does when called, with the clock and local day
+    /// as they are now, without needing the list manager any more.
+    pub(crate) fn widget_refresh(&self) -> Box<dyn FnOnce()> {
+        let store = self.get_store();
+        let now = self.now();
+        let utc_offset = self.day_boundary.get().map_or(0, |(_, utc_offset)| utc_offset);
+        Box::new(move || {
+            let snapshot = take_snapshot(&store, now, utc_offset, WIDGET_SNAPSHOT_CAPACITY);
+            *SNAPSHOT.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(snapshot);
+        })
+    }
+}
+
+fn take_snapshot(store: &Store, now: Timespec, utc_offset: i64, capacity: usize) -> WidgetSnapshot {
+    let today = start_of_local_day(now, utc_offset);
+    let tomorrow = today + Duration::days(1);
+    let sql = r#"SELECT uuid, name, due_date FROM items
+                 WHERE completion_date IS NULL AND due_date IS NOT NULL AND due_date < ?
+                 ORDER BY due_date, name"#;
+    let conn = store.get_conn();
+    let mut stmt = conn.prepare(sql).unwrap();
+    let mut total = 0;
+    let mut items = vec![];
+    let rows = stmt.query_map(&[&tomorrow], |row| -> (String, String, Timespec) { (row.get(0), row.get(1), row.get(2)) }).unwrap();
+    for (uuid, name, due_date) in rows.filter_map(|result| result.ok()) {
+        total += 1;
+        if items.len() < capacity {
+            items.push(WidgetItem {
+                uuid: uuid,
+                name: name,
+                due_date: due_date.sec,
+                overdue: due_date < today,
+            });
+        }
+    }
+    WidgetSnapshot {
+        generated_at: now.sec,
+        expires_at: tomorrow.sec,
+        total: total,
+        items: items,
+    }
+}
+
+/// The snapshot of the list that changed last, with at most `max_items` items, as a JSON
+/// object with `generated_at`, `expires_at`, `total` and `items` keys. Items have `uuid`,
+/// `name`, `due_date` and `overdue` keys. Returns null if no list has been opened in this
+/// process yet. Safe to call from any thread.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub extern "C" fn widget_snapshot_json(max_items: i64) -> *mut c_char {
+    ffi_guard!({
+        let mut snapshot = match *SNAPSHOT.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) {
+            Some(ref snapshot) => snapshot.clone(),
+            None => return ptr::null_mut(),
+        };
+        snapshot.items.truncate(max_items.max(0) as usize);
+        string_to_c_char(serde_json::to_string(&snapshot).unwrap())
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use time::{
+        Duration,
+        Timespec,
+    };
+
+    use items::Item;
+    use store::Store;
+    use ListManager;
+
+    fn item(name: &str, due_date: Option<Timespec>) -> Item {
+        let mut item = Item::named(name);
+        item.due_date = due_date;
+        item
+    }
+
+    #[test]
+    fn test_widget_snapshot() {
+        let manager = ListManager::new(Arc::new(Store::new(None)));
+        // 2017-11-10 12:00 UTC, 14:00 at UTC+2
+        let now = Timespec::new(1510315200, 0);
+        manager.freeze_clock(Some(now));
+        manager.on_significant_time_change(2 * 60 * 60);
+
+        manager.create_item(&item("tonight", Some(now + Duration::hours(9))));
+        manager.create_item(&item("this afternoon", Some(now + Duration::hours(2))));
+        manager.create_item(&item("yesterday", Some(now - Duration::days(1))));
+        manager.create_item(&item("tomorrow", Some(now + Duration::hours(11))));
+        manager.create_item(&item("someday", None));
+        let mut done = item("done", Some(now));
+        done.completion_date = Some(now);
+        manager.create_item(&done);
+
+        let snapshot = manager.widget_snapshot(2);
+        assert_eq!(snapshot.total, 3);
+        let names: Vec<&str> = snapshot.items.iter().map(|item| item.name.as_str()).collect();
+        assert_eq!(names, vec!["yesterday", "this afternoon"]);
+        assert!(snapshot.items[0].overdue && !snapshot.items[1].overdue);
+        assert_eq!(snapshot.expires_at, 1510351200);
+    }
+}
@@ -0,0 +1,378 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! Item lists as protocol buffers, so hosts fetch a list in one call and decode it with
+//! generated code instead of calling a getter per field. The schema is `items.proto`; keep
+//! the field numbers here in step with it.
+//!
+//! A single `Item` message is also how Android parcels an item, so the encoding lives in
+//! one place.
+
+use std::error::Error;
+use std::fmt;
+use std::ptr;
+use std::slice;
+use std::str;
+use std::sync::Arc;
+
+use time::Timespec;
+
+use ffi_utils::breadcrumbs;
+use ffi_utils::buffers::ByteBuffer;
+use ffi_utils::errors::{
+    set_extern_error,
+    ErrorCategory,
+    ExternError,
+};
+use filters::ItemFilter;
+use items::Item;
+use labels::Label;
+use ListManager;
+
+const VARINT: u64 = 0;
+const FIXED64: u64 = 1;
+const LENGTH_DELIMITED: u64 = 2;
+const FIXED32: u64 = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DecodeError {
+    /// The message ends in the middle of a field.
+    Truncated,
+    /// A string field is not UTF-8.
+    InvalidUtf8,
+    /// A field uses a wire type that no message in items.proto can produce.
+    UnsupportedWireType(u64),
+}
+
+impl DecodeError {
+    /// Bytes that did not come from `item_to_bytes` are a bug in the host.
+    pub fn category(&self) -> ErrorCategory {
+        ErrorCategory::Bug
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DecodeError::Truncated => write!(f, "the message is truncated"),
+            DecodeError::InvalidUtf8 => write!(f, "a string is not UTF-8"),
+            DecodeError::UnsupportedWireType(wire_type) => write!(f, "unsupported wire type {}", wire_type),
+        }
+    }
+}
+
+impl Error for DecodeError {
+    fn description(&self) -> &str {
+        "malformed message"
+    }
+}
+
+fn push_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn push_key(buf: &mut Vec<u8>, field: u64, wire_type: u64) {
+    push_varint(buf, field << 3 | wire_type);
+}
+
+fn push_bytes(buf: &mut Vec<u8>, field: u64, bytes: &[u8]) {
+    push_key(buf, field, LENGTH_DELIMITED);
+    push_varint(buf, bytes.len() as u64);
+    buf.extend_from_slice(bytes);
+}
+
+/// Empty strings are left out, as proto3 decodes a missing string as empty.
+fn push_string(buf: &mut Vec<u8>, field: u64, value: &str) {
+    if !value.is_empty() {
+        push_bytes(buf, field, value.as_bytes());
+    }
+}
+
+/// Negative values take ten bytes, as for any `int64`.
+fn push_int64(buf: &mut Vec<u8>, field: u64, value: i64) {
+    push_key(buf, field, VARINT);
+    push_varint(buf, value as u64);
+}
+
+fn encode_label(label: &Label) -> Vec<u8> {
+    let mut buf = vec![];
+    push_string(&mut buf, 1, &label.name);
+    push_string(&mut buf, 2, &label.color);
+    buf
+}
+
+/// The item as an `Item` message.
+pub fn encode_item(item: &Item) -> Vec<u8> {
+    let mut buf = vec![];
+    push_string(&mut buf, 1, &item.uuid);
+    push_string(&mut buf, 2, &item.name);
+    if let Some(due_date) = item.due_date {
+        push_int64(&mut buf, 3, due_date.sec);
+    }
+    if let Some(completion_date) = item.completion_date {
+        push_int64(&mut buf, 4, completion_date.sec);
+    }
+    for label in item.labels.iter() {
+        push_bytes(&mut buf, 5, &encode_label(label));
+    }
+    buf
+}
+
+/// Reads protocol buffer fields one at a time.
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(buf: &'a [u8]) -> Reader<'a> {
+        Reader { buf: buf, pos: 0 }
+    }
+
+    fn varint(&mut self) -> Result<u64, DecodeError> {
+        let mut value = 0;
+        for shift in 0..10 {
+            let byte = *self.buf.get(self.pos).ok_or(DecodeError::Truncated)?;
+            self.pos += 1;
+            value |= ((byte & 0x7f) as u64) << (shift * 7);
+            if byte < 0x80 {
+                return Ok(value);
+            }
+        }
+        Err(DecodeError::Truncated)
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        if self.buf.len() - self.pos < len {
+            return Err(DecodeError::Truncated);
+        }
+        let bytes = &self.buf[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn length_delimited(&mut self) -> Result<&'a [u8], DecodeError> {
+        let len = self.varint()?;
+        self.bytes(len as usize)
+    }
+
+    fn string(&mut self) -> Result<String, DecodeError> {
+        let bytes = self.length_delimited()?;
+        str::from_utf8(bytes).map(|s| s.to_string()).map_err(|_| DecodeError::InvalidUtf8)
+    }
+
+    /// The next field's number and wire type, or `None` at the end of the message.
+    fn key(&mut self) -> Result<Option<(u64, u64)>, DecodeError> {
+        if self.pos == self.buf.len() {
+            return Ok(None);
+        }
+        let key = self.varint()?;
+        Ok(Some((key >> 3, key & 7)))
+    }
+
+    /// Skips a field this version does not know, as protocol buffers require.
+    fn skip(&mut self, wire_type: u64) -> Result<(), DecodeError> {
+        match wire_type {
+            VARINT => self.varint().map(|_| ()),
+            FIXED64 => self.bytes(8).map(|_| ()),
+            LENGTH_DELIMITED => self.length_delimited().map(|_| ()),
+            FIXED32 => self.bytes(4).map(|_| ()),
+            wire_type => Err(DecodeError::UnsupportedWireType(wire_type)),
+        }
+    }
+}
+
+fn decode_label(buf: &[u8]) -> Result<Label, DecodeError> {
+    let mut label = Label { name: "".to_string(), color: "".to_string() };
+    let mut reader = Reader::new(buf);
+    while let Some((field, wire_type)) = reader.key()? {
+        match (field, wire_type) {
+            (1, LENGTH_DELIMITED) => label.name = reader.string()?,
+            (2, LENGTH_DELIMITED) => label.color = reader.string()?,
+            (_, wire_type) => reader.skip(wire_type)?,
+        }
+    }
+    Ok(label)
+}
+
+/// Reads an `Item` message, as `encode_item` writes it.
+pub fn decode_item(buf: &[u8]) -> Result<Item, DecodeError> {
+    let mut item = Item {
+        uuid: "".to_string(),
+        name: "".to_string(),
+        due_date: None,
+        completion_date: None,
+        labels: vec![],
+    };
+    let mut reader = Reader::new(buf);
+    while let Some((field, wire_type)) = reader.key()? {
+        match (field, wire_type) {
+            (1, LENGTH_DELIMITED) => item.uuid = reader.string()?,
+            (2, LENGTH_DELIMITED) => item.name = reader.string()?,
+            (3, VARINT) => item.due_date = Some(Timespec::new(reader.varint()? as i64, 0)),
+            (4, VARINT) => item.completion_date = Some(Timespec::new(reader.varint()? as i64, 0)),
+            (5, LENGTH_DELIMITED) => item.labels.push(Arc::new(decode_label(reader.length_delimited()?)?)),
+            (_, wire_type) => reader.skip(wire_type)?,
+        }
+    }
+    Ok(item)
+}
+
+/// The items as an `ItemList` message.
+pub fn encode_items(items: &[Item]) -> Vec<u8> {
+    let mut buf = vec![];
+    for item in items.iter() {
+        push_bytes(&mut buf, 1, &encode_item(item));
+    }
+    buf
+}
+
+impl ListManager {
+    /// Every item, encoded as an `ItemList`.
+    pub fn fetch_all_encoded(&self) -> Vec<u8> {
+        breadcrumbs::leave("list.fetch_all_encoded");
+        encode_items(&self.fetch_items(&ItemFilter::default()))
+    }
+}
+
+/// Every item as an `ItemList` protocol buffer, as described by items.proto.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_fetch_all(manager: *const Arc<ListManager>) -> ByteBuffer {
+    ffi_lock!();
+    ffi_guard!({
+        let manager = ffi_ref!("list_manager_fetch_all", manager);
+        ByteBuffer::from(manager.fetch_all_encoded())
+    })
+}
+
+/// Items matching `filter`, which may be null to fetch every item, as an `ItemList`.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn list_manager_fetch_items_encoded(manager: *const Arc<ListManager>, filter: *const ItemFilter) -> ByteBuffer {
+    ffi_lock!();
+    ffi_guard!({
+        let manager = ffi_ref!("list_manager_fetch_items_encoded", manager);
+        let items = match filter.as_ref() {
+            Some(filter) => manager.fetch_items(filter),
+            None => manager.fetch_items(&ItemFilter::default()),
+        };
+        ByteBuffer::from(encode_items(&items))
+    })
+}
+
+/// The item as an `Item` protocol buffer, e.g. to write to an Android `Parcel`.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn item_to_bytes(item: *const Item) -> ByteBuffer {
+    ffi_guard!({
+        let item = ffi_ref!("item_to_bytes", item);
+        ByteBuffer::from(encode_item(item))
+    })
+}
+
+/// Reads `len` bytes written by `item_to_bytes`. Returns null and fills in `error` if they
+/// are not an `Item`.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn item_from_bytes(data: *const u8, len: usize, error: *mut ExternError) -> *mut Item {
+    ffi_guard!(error, {
+        let bytes = if len == 0 { &[][..] } else { slice::from_raw_parts(ffi_ref!("item_from_bytes", data, ptr::null_mut(), error), len) };
+        match decode_item(bytes) {
+            Ok(item) => Box::into_raw(Box::new(item)),
+            Err(e) => {
+                log_error!("failed to decode item {:?}", e);
+                set_extern_error(error, e.category(), e.to_string());
+                ptr::null_mut()
+            },
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::slice;
+    use std::sync::Arc;
+
+    use time::Timespec;
+
+    use ffi_utils::buffers::{
+        byte_buffer_destroy,
+        ByteBuffer,
+    };
+    use items::Item;
+    use labels::Label;
+    use super::{
+        decode_item,
+        encode_item,
+        encode_items,
+        DecodeError,
+    };
+
+    #[test]
+    fn test_encode_items() {
+        let items = vec![
+            Item {
+                uuid: "a".to_string(),
+                name: "milk".to_string(),
+                due_date: Some(Timespec::new(1510315200, 0)),
+                completion_date: Some(Timespec::new(-1, 0)),
+                labels: vec![Arc::new(Label { name: "home".to_string(), color: "".to_string() })],
+            },
+            Item {
+                uuid: "b".to_string(),
+                name: "".to_string(),
+                due_date: None,
+                completion_date: Some(Timespec::new(0, 0)),
+                labels: vec![],
+            },
+        ];
+        // field keys are (number << 3 | wire type), dates varints, negative ones in ten bytes
+        let expected: Vec<u8> = vec![
+            0x0a, 0x22,
+                0x0a, 0x01, b'a',
+                0x12, 0x04, b'm', b'i', b'l', b'k',
+                0x18, 0xc0, 0xa9, 0x96, 0xd0, 0x05,
+                0x20, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01,
+                0x2a, 0x06, 0x0a, 0x04, b'h', b'o', b'm', b'e',
+            0x0a, 0x05,
+                0x0a, 0x01, b'b',
+                0x20, 0x00,
+        ];
+        assert_eq!(encode_items(&items), expected);
+
+        let buffer = ByteBuffer::from(encode_items(&items));
+        assert_eq!(unsafe { slice::from_raw_parts(buffer.data, buffer.len as usize) }, &expected[..]);
+        unsafe { byte_buffer_destroy(buffer) };
+        assert!(ByteBuffer::from(encode_items(&[])).data.is_null());
+    }
+
+    #[test]
+    fn test_item_round_trip() {
+        let item = Item {
+            uuid: "a".to_string(),
+            name: "pay rent".to_string(),
+            due_date: Some(Timespec::new(1510315200, 0)),
+            completion_date: None,
+            labels: vec![Arc::new(Label { name: "home".to_string(), color: "#00ff00".to_string() })],
+        };
+        let bytes = encode_item(&item);
+        assert_eq!(decode_item(&bytes), Ok(item.clone()));
+
+        // fields added in later versions are skipped
+        let mut newer = bytes.clone();
+        newer.extend_from_slice(&[0x30, 0x01, 0x3a, 0x01, b'x']);
+        assert_eq!(decode_item(&newer), Ok(item));
+
+        assert_eq!(decode_item(&bytes[..bytes.len() - 1]), Err(DecodeError::Truncated));
+        assert_eq!(decode_item(&[0x12, 0x01, 0xff]), Err(DecodeError::InvalidUtf8));
+        assert_eq!(decode_item(&[0x0b]), Err(DecodeError::UnsupportedWireType(3)));
+    }
+}
@@ -0,0 +1,13 @@
+#ifndef stats_h
+#define stats_h
+
+#include <stdint.h>
+
+struct list_manager;
+
+// Returns a JSON object with total, open, completed and overdue item counts, the completion
+// rate of items due in the last window_days days, the current completion streak in days
+// and per-label counts. utc_offset is the host's current offset from UTC in seconds.
+char* _Nonnull list_manager_stats_json(const struct list_manager* _Nonnull manager, int64_t window_days, int64_t utc_offset);
+
+#endif /* stats_h */
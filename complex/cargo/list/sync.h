@@ -0,0 +1,117 @@
+#ifndef sync_h
+#define sync_h
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include "conflicts.h"
+#include "errors.h"
+
+struct list_manager;
+struct sync_response;
+
+// Items and labels sent to and received from the server by one sync, including deletions.
+// `conflicts` counts items changed on both sides; with ConflictStrategyManual they wait in
+// list_manager_conflicts_pending and are not pushed until resolved.
+struct sync_summary {
+    int64_t pushed;
+    int64_t pulled;
+    int64_t conflicts;
+};
+
+// `base_url` is everything before `/changes`, e.g. "https://example.com/api/v1". Switching to
+// another server starts over from its beginning on the next sync.
+void list_manager_set_sync_config(const struct list_manager* _Nonnull manager, const char* _Nonnull base_url, const char* _Nonnull auth_token);
+// `strategy` is one of the enum conflict_strategy values. Returns false and fills in `error`
+// with ErrorCategoryBug if it is not.
+bool list_manager_set_conflict_strategy(const struct list_manager* _Nonnull manager, uint32_t strategy, struct extern_error* _Nullable error);
+
+// Performs one HTTP request with the platform's HTTP stack, sending `auth_token` as a bearer
+// token and `body`, if not NULL, as JSON. Call sync_response_set before returning; a response
+// left unset counts as a network failure. Called on the thread calling list_manager_sync_now,
+// without the library lock, so other threads may call into the library meanwhile.
+typedef void (*sync_http_callback)(const char* _Nonnull method, const char* _Nonnull url, const char* _Nonnull auth_token, const char* _Nullable body, struct sync_response* _Nonnull response, void* _Nullable user_data);
+// Sends requests through `callback` instead of the built in client, which only speaks plain
+// HTTP to this machine, for development. Needed for any other server.
+void list_manager_set_sync_transport(const struct list_manager* _Nonnull manager, sync_http_callback _Nonnull callback, void* _Nullable user_data);
+void sync_response_set(struct sync_response* _Nonnull response, uint16_t status, const char* _Nullable body);
+
+// Local changes to items and labels are kept until a sync pushes them, across restarts, and
+// pushed in the order they were made. Cleared changes are not pushed unless made again.
+int64_t list_manager_pending_change_count(const struct list_manager* _Nonnull manager);
+void list_manager_clear_pending_changes(const struct list_manager* _Nonnull manager);
+
+enum sync_stage {
+    SyncStageStarted = 0,
+    // Pulling the server's changes.
+    SyncStageDownloading = 1,
+    // Pushing local changes, in batches; `done` of `total` have been sent.
+    SyncStageUploading = 2,
+    SyncStageFinished = 3,
+    // `error` says what to do about it, as for list_manager_sync_now.
+    SyncStageFailed = 4,
+};
+
+// `done` and `total` are 0 except while uploading. `error` is ErrorCategoryNone except for
+// SyncStageFailed. Downloading and uploading may repeat when others push at the same time.
+typedef void (*sync_progress_callback)(enum sync_stage stage, int64_t done, int64_t total, enum error_category error, void* _Nullable user_data);
+
+// Calls `callback` on the thread calling list_manager_sync_now as the sync progresses.
+// Returns an id for list_manager_remove_sync_observer.
+uint64_t list_manager_add_sync_observer(const struct list_manager* _Nonnull manager, sync_progress_callback _Nonnull callback, void* _Nullable user_data);
+// Returns false if there is no sync observer with that id.
+bool list_manager_remove_sync_observer(const struct list_manager* _Nonnull manager, uint64_t id);
+
+// What the last sync changed on this device, as JSON with `finished_at` (seconds since 1970),
+// `added`, `updated`, `completed` and `deleted` (items changed elsewhere; `updated` includes
+// `completed`), `conflicts`, `pushed`, `lists` (names of the labels affected) and, if the
+// sync failed, `error`. Returns NULL if this device has not synced.
+char* _Nullable list_manager_last_sync_summary(const struct list_manager* _Nonnull manager);
+
+// PBKDF2-HMAC-SHA256 of the passphrase and salt; fills in the 32 bytes of `key`.
+typedef bool (*sync_derive_key_callback)(const uint8_t* _Nonnull passphrase, size_t passphrase_len, const uint8_t* _Nonnull salt, size_t salt_len, uint32_t rounds, uint8_t* _Nonnull key, void* _Nullable user_data);
+// ChaCha20-Poly1305 with a 32 byte key and 12 byte nonce; fills in `plaintext_len + 16` bytes
+// of `sealed`, the ciphertext followed by the tag.
+typedef bool (*sync_seal_callback)(const uint8_t* _Nonnull key, const uint8_t* _Nonnull nonce, const uint8_t* _Nonnull aad, size_t aad_len, const uint8_t* _Nonnull plaintext, size_t plaintext_len, uint8_t* _Nonnull sealed, void* _Nullable user_data);
+// The reverse of a seal; fills in `sealed_len - 16` bytes of `plaintext`. Returns false if the
+// tag does not match.
+typedef bool (*sync_open_callback)(const uint8_t* _Nonnull key, const uint8_t* _Nonnull nonce, const uint8_t* _Nonnull aad, size_t aad_len, const uint8_t* _Nonnull sealed, size_t sealed_len, uint8_t* _Nonnull plaintext, void* _Nullable user_data);
+
+// The platform's cryptography for sealing items, e.g. CryptoKit or javax.crypto. Each callback
+// returns false on failure, and is called on the thread syncing with the library lock held.
+struct sync_crypto {
+    sync_derive_key_callback _Nonnull derive_key;
+    sync_seal_callback _Nonnull seal;
+    sync_open_callback _Nonnull open;
+    void* _Nullable user_data;
+};
+
+// Copies `crypto`. Needed before a passphrase can be set.
+void list_manager_set_sync_crypto(const struct list_manager* _Nonnull manager, const struct sync_crypto* _Nonnull crypto);
+
+// Items pushed while a passphrase is set are sealed with ChaCha20-Poly1305 under a key derived
+// from it, so the server only keeps their uuids, edit times and ciphertext; labels are not
+// sealed. The passphrase is not stored: keep it in the keychain and set it at every launch.
+// NULL stops sealing. Syncs fail with ErrorCategoryNeedsUserInput while pulled items cannot
+// be opened with the passphrase set. Fails with ErrorCategoryBug if no sync crypto is set.
+// Items are sealed under the account's salt: the server keeps the first `salt` pushed and
+// returns it with every pull. A pull with items under more than two other salts fails with
+// ErrorCategoryBug.
+bool list_manager_set_sync_passphrase(const struct list_manager* _Nonnull manager, const char* _Nullable passphrase, struct extern_error* _Nullable error);
+// Sets a new passphrase and queues every item, so the next sync replaces the server's copies
+// with ones sealed with it. Other devices need the new passphrase from then on.
+bool list_manager_rotate_sync_passphrase(const struct list_manager* _Nonnull manager, const char* _Nonnull passphrase, struct extern_error* _Nullable error);
+
+// Keeps a list off the server when `synced` is false: the label and the items carrying it are
+// neither pushed nor updated from the server. Copies pushed earlier stay on the server.
+// Syncing it again queues the label and its items for the next sync. Returns false if there
+// is no such label.
+bool list_manager_set_label_synced(const struct list_manager* _Nonnull manager, const char* _Nonnull name, bool synced);
+bool list_manager_is_label_synced(const struct list_manager* _Nonnull manager, const char* _Nonnull name);
+
+// Pulls the server's changes, then pushes the local ones. Returns NULL and fills in `error`
+// if the sync failed; ErrorCategoryNeedsReauth means the token was refused.
+struct sync_summary* _Nullable list_manager_sync_now(const struct list_manager* _Nonnull manager, struct extern_error* _Nullable error);
+void sync_summary_destroy(struct sync_summary* _Nonnull summary);
+
+#endif /* sync_h */
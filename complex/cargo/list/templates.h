@@ -0,0 +1,16 @@
+#ifndef templates_h
+#define templates_h
+
+#include <stdbool.h>
+
+struct list_manager;
+
+// Returns the starter list templates as a JSON array of objects with `id`, `name`, `label`,
+// `color` and `items` keys, translated for `locale` (e.g. "fr-CA") where possible and in
+// English otherwise.
+char* _Nonnull templates_available_json(const char* _Nonnull locale);
+
+// Creates the template's label and items. Returns false if there is no template with that id.
+bool list_manager_template_instantiate(const struct list_manager* _Nonnull manager, const char* _Nonnull id, const char* _Nonnull locale);
+
+#endif /* templates_h */
@@ -0,0 +1,17 @@
+#ifndef todo_txt_h
+#define todo_txt_h
+
+#include "errors.h"
+
+struct list_manager;
+
+// Every item as a line of todo.txt. Labels become +project tags, labels starting with "@"
+// @context tags, and "priority:A" labels the item's (A) priority. Dates are days in UTC.
+char* _Nonnull list_manager_export_todo_txt(const struct list_manager* _Nonnull manager);
+// Adds a new item for each line of todo.txt in `text`, mapping tags and priorities to labels
+// as list_manager_export_todo_txt does and creating labels not in the store. Returns the
+// uuids of the new items as a JSON array, or NULL and fills in `error` if they could not be
+// written, in which case none are.
+char* _Nullable list_manager_import_todo_txt(const struct list_manager* _Nonnull manager, const char* _Nonnull text, struct extern_error* _Nullable error);
+
+#endif /* todo_txt_h */
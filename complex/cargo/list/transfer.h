@@ -0,0 +1,32 @@
+#ifndef transfer_h
+#define transfer_h
+
+#include <stdbool.h>
+#include "errors.h"
+
+struct list_manager;
+struct item;
+
+struct copy_options {
+    // Delete the items from the source once copied.
+    bool move_items;
+    // Copy the items' links, contacts and locations too.
+    bool include_attachments;
+};
+
+// Copies items from one profile's list manager to another's, e.g. a project from the
+// personal profile to the shared work profile. `uuids` is a JSON array of item uuids in
+// `src`. Copies get new uuids. Labels are matched by name: one `dst` already has keeps its
+// color, and missing ones are created with the color from `src`. Returns a JSON array of
+// {"from", "to"} objects giving the uuid of each copy, leaving out uuids not found in `src`.
+// Returns NULL and fills in `error` if `uuids` is not a JSON array of strings
+// (ErrorCategoryBug) or the copies could not be written, in which case none are. If moved
+// items could not be deleted from `src`, none are, and the copies stay in `dst`.
+char* _Nullable list_manager_copy_items_between_stores(const struct list_manager* _Nonnull src, const struct list_manager* _Nonnull dst, const char* _Nonnull uuids, struct copy_options options, struct extern_error* _Nullable error);
+
+// Duplicates a task: writes a copy of `item`, as it is now, under a new uuid, with the links,
+// contacts and locations attached to the stored item it was read from. Returns the copy's
+// uuid, or NULL and fills in `error` if it could not be written.
+char* _Nullable item_duplicate_in_store(const struct list_manager* _Nonnull manager, const struct item* _Nonnull item, struct extern_error* _Nullable error);
+
+#endif /* transfer_h */
@@ -0,0 +1,14 @@
+#ifndef widget_h
+#define widget_h
+
+#include <stdint.h>
+
+// The open items due today or earlier, soonest first, for a home screen widget: a JSON
+// object with generated_at, expires_at, total and items, at most max_items of them, each
+// with uuid, name, due_date and overdue. The snapshot is that of the list that changed
+// last and is kept up to date as items change, so this needs no list manager and never
+// waits on the store. It is out of date after expires_at, the end of the local day, until
+// toodle_on_significant_time_change is called. NULL if no list has been opened yet.
+char* _Nullable widget_snapshot_json(int64_t max_items);
+
+#endif /* widget_h */
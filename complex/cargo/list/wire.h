@@ -0,0 +1,25 @@
+#ifndef wire_h
+#define wire_h
+
+#include "buffers.h"
+#include "errors.h"
+#include "filters.h"
+
+struct list_manager;
+struct item;
+
+// Every item as an ItemList protocol buffer, as described by items.proto, to decode with
+// generated code in one go rather than calling a getter per field. Release the buffer with
+// byte_buffer_destroy.
+struct byte_buffer list_manager_fetch_all(const struct list_manager* _Nonnull manager);
+// The items matching `filter`, or every item if it is NULL, as an ItemList.
+struct byte_buffer list_manager_fetch_items_encoded(const struct list_manager* _Nonnull manager, const struct item_filter* _Nullable filter);
+
+// The item as an Item protocol buffer, e.g. for an Android Parcelable, released with
+// byte_buffer_destroy.
+struct byte_buffer item_to_bytes(const struct item* _Nonnull item);
+// Reads `len` bytes written by item_to_bytes. Fields it does not know are skipped. Returns
+// NULL and fills in `error` with ErrorCategoryBug if the bytes are not an Item.
+struct item* _Nullable item_from_bytes(const uint8_t* _Nullable data, size_t len, struct extern_error* _Nullable error);
+
+#endif /* wire_h */
@@ -0,0 +1,41 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+export interface Label {
+  name: string;
+  color: string;
+}
+
+export interface Item {
+  uuid: string;
+  name: string;
+  // Kept to the second.
+  dueDate: Date | null;
+  completionDate: Date | null;
+  labels: Label[];
+}
+
+// Calls throw an Error if the store fails, and a TypeError if an item cannot be read.
+export class ListManager {
+  // Opens the store at `path`, creating it if missing.
+  constructor(path: string);
+  fetchItems(): Item[];
+  fetchItem(uuid: string): Item | null;
+  // Returns the new item's uuid; the item's own uuid is ignored.
+  createItem(item: Item): string;
+  // Writes the item over the stored one with the same uuid.
+  saveItem(item: Item): void;
+  // Returns false if there is no item with that uuid.
+  deleteItem(uuid: string): boolean;
+  fetchLabels(): Label[];
+  createLabel(name: string, color: string): Label;
+  // Returns false if there is no label with that name.
+  deleteLabel(name: string): boolean;
+}
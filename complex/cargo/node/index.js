@@ -0,0 +1,85 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+'use strict';
+
+const native = require('./index.node');
+
+// Dates cross as ISO 8601 UTC to the second, which is all the library keeps.
+function fromDate(date) {
+  return date ? date.toISOString().replace(/\.\d{3}Z$/, 'Z') : null;
+}
+
+function toDate(date) {
+  return date ? new Date(date) : null;
+}
+
+function fromItem(item) {
+  return JSON.stringify({
+    uuid: item.uuid || '',
+    name: item.name,
+    due_date: fromDate(item.dueDate),
+    completion_date: fromDate(item.completionDate),
+    labels: item.labels || [],
+  });
+}
+
+function toItem(item) {
+  return {
+    uuid: item.uuid,
+    name: item.name,
+    dueDate: toDate(item.due_date),
+    completionDate: toDate(item.completion_date),
+    labels: item.labels,
+  };
+}
+
+class ListManager {
+  // Opens the store at `path`, creating it if missing.
+  constructor(path) {
+    this.manager = native.open(path);
+  }
+
+  fetchItems() {
+    return JSON.parse(native.fetchItems(this.manager)).map(toItem);
+  }
+
+  fetchItem(uuid) {
+    const json = native.fetchItem(this.manager, uuid);
+    return json === null ? null : toItem(JSON.parse(json));
+  }
+
+  // Returns the new item's uuid; the item's own uuid is ignored.
+  createItem(item) {
+    return native.createItem(this.manager, fromItem(item));
+  }
+
+  saveItem(item) {
+    native.saveItem(this.manager, fromItem(item));
+  }
+
+  deleteItem(uuid) {
+    return native.deleteItem(this.manager, uuid);
+  }
+
+  fetchLabels() {
+    return JSON.parse(native.fetchLabels(this.manager));
+  }
+
+  createLabel(name, color) {
+    return JSON.parse(native.createLabel(this.manager, name, color));
+  }
+
+  deleteLabel(name) {
+    return native.deleteLabel(this.manager, name);
+  }
+}
+
+module.exports = { ListManager };
@@ -0,0 +1,156 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! The list manager, items and labels for Node, through N-API, so that the Electron client
+//! shares the core with the phones. Items and labels cross as JSON, in the same shape as
+//! `item_to_json` writes them; `index.js` wraps these functions in a `ListManager` class.
+//!
+//! Everything runs on the JavaScript thread that loaded the module, so unlike the C API
+//! there is no lock to take.
+
+extern crate neon;
+extern crate serde_json;
+
+extern crate list;
+extern crate store;
+
+use std::sync::Arc;
+
+use neon::prelude::*;
+
+use list::ListManager;
+use list::filters::ItemFilter;
+use list::items::Item;
+use store::Store;
+use store::options::{
+    OpenOptions,
+    STORE_OPEN_CREATE_IF_MISSING,
+};
+
+struct Manager(Arc<ListManager>);
+
+impl Finalize for Manager {}
+
+fn manager(cx: &mut FunctionContext) -> NeonResult<Arc<ListManager>> {
+    Ok(cx.argument::<JsBox<Manager>>(0)?.0.clone())
+}
+
+fn string_argument(cx: &mut FunctionContext, index: i32) -> NeonResult<String> {
+    Ok(cx.argument::<JsString>(index)?.value(cx))
+}
+
+fn item_argument(cx: &mut FunctionContext, index: i32) -> NeonResult<Item> {
+    let json = string_argument(cx, index)?;
+    match serde_json::from_str(&json) {
+        Ok(item) => Ok(item),
+        Err(e) => cx.throw_type_error(format!("not an item: {}", e)),
+    }
+}
+
+/// `open(path)` opens the store at `path`, creating it if missing, and returns a handle for
+/// the other functions.
+fn open(mut cx: FunctionContext) -> JsResult<JsBox<Manager>> {
+    let path = string_argument(&mut cx, 0)?;
+    let opened = Store::open(&path, OpenOptions::from_bits(STORE_OPEN_CREATE_IF_MISSING))
+        .and_then(|store| ListManager::open(Arc::new(store)));
+    match opened {
+        Ok(manager) => Ok(cx.boxed(Manager(Arc::new(manager)))),
+        Err(e) => cx.throw_error(format!("failed to open {}: {}", path, e)),
+    }
+}
+
+/// `fetchItems(manager)` returns every item as a JSON array.
+fn fetch_items(mut cx: FunctionContext) -> JsResult<JsString> {
+    let manager = manager(&mut cx)?;
+    let items = manager.fetch_items(&ItemFilter::default());
+    Ok(cx.string(serde_json::to_string(&items).unwrap()))
+}
+
+/// `fetchItem(manager, uuid)` returns the item as JSON, or null if there is none.
+fn fetch_item(mut cx: FunctionContext) -> JsResult<JsValue> {
+    let manager = manager(&mut cx)?;
+    let uuid = string_argument(&mut cx, 1)?;
+    match manager.fetch_item(&uuid) {
+        Some(item) => Ok(cx.string(serde_json::to_string(&item).unwrap()).upcast()),
+        None => Ok(cx.null().upcast()),
+    }
+}
+
+/// `createItem(manager, json)` returns the new item's uuid.
+fn create_item(mut cx: FunctionContext) -> JsResult<JsString> {
+    let manager = manager(&mut cx)?;
+    let item = item_argument(&mut cx, 1)?;
+    match manager.try_create_item(&item) {
+        Ok(uuid) => Ok(cx.string(uuid)),
+        Err(e) => cx.throw_error(format!("failed to create item: {}", e)),
+    }
+}
+
+/// `saveItem(manager, json)` writes the item over the stored one with the same uuid.
+fn save_item(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let manager = manager(&mut cx)?;
+    let item = item_argument(&mut cx, 1)?;
+    match manager.try_save_item(&item) {
+        Ok(()) => Ok(cx.undefined()),
+        Err(e) => cx.throw_error(format!("failed to save item: {}", e)),
+    }
+}
+
+/// `deleteItem(manager, uuid)` returns false if there is no item with that uuid.
+fn delete_item(mut cx: FunctionContext) -> JsResult<JsBoolean> {
+    let manager = manager(&mut cx)?;
+    let uuid = string_argument(&mut cx, 1)?;
+    match manager.try_delete_item(&uuid) {
+        Ok(deleted) => Ok(cx.boolean(deleted)),
+        Err(e) => cx.throw_error(format!("failed to delete item: {}", e)),
+    }
+}
+
+/// `fetchLabels(manager)` returns every label as a JSON array.
+fn fetch_labels(mut cx: FunctionContext) -> JsResult<JsString> {
+    let manager = manager(&mut cx)?;
+    let labels = manager.fetch_labels();
+    Ok(cx.string(serde_json::to_string(&labels).unwrap()))
+}
+
+/// `createLabel(manager, name, color)` returns the new label as JSON.
+fn create_label(mut cx: FunctionContext) -> JsResult<JsString> {
+    let manager = manager(&mut cx)?;
+    let name = string_argument(&mut cx, 1)?;
+    let color = string_argument(&mut cx, 2)?;
+    match manager.try_create_label(name, color) {
+        Ok(label) => Ok(cx.string(serde_json::to_string(&label).unwrap())),
+        Err(e) => cx.throw_error(format!("failed to create label: {}", e)),
+    }
+}
+
+/// `deleteLabel(manager, name)` returns false if there is no label with that name.
+fn delete_label(mut cx: FunctionContext) -> JsResult<JsBoolean> {
+    let manager = manager(&mut cx)?;
+    let name = string_argument(&mut cx, 1)?;
+    match manager.try_delete_label(&name) {
+        Ok(deleted) => Ok(cx.boolean(deleted)),
+        Err(e) => cx.throw_error(format!("failed to delete label: {}", e)),
+    }
+}
+
+#[neon::main]
+fn main(mut cx: ModuleContext) -> NeonResult<()> {
+    cx.export_function("open", open)?;
+    cx.export_function("fetchItems", fetch_items)?;
+    cx.export_function("fetchItem", fetch_item)?;
+    cx.export_function("createItem", create_item)?;
+    cx.export_function("saveItem", save_item)?;
+    cx.export_function("deleteItem", delete_item)?;
+    cx.export_function("fetchLabels", fetch_labels)?;
+    cx.export_function("createLabel", create_label)?;
+    cx.export_function("deleteLabel", delete_label)?;
+    Ok(())
+}
@@ -0,0 +1,140 @@
+# Copyright 2016 Mozilla
+#
+# Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+# this file except in compliance with the License. You may obtain a copy of the
+# License at http://www.apache.org/licenses/LICENSE-2.0
+# Unless required by applicable law or agreed to in writing, software distributed
+# under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+# CONDITIONS OF ANY KIND, either express or implied. See the License for the
+# specific language governing permissions and limitations under the License.
+
+"""The To Do List library for scripts and data migrations.
+
+    manager = toodle.ListManager("todo.sqlite")
+    uuid = manager.create_item(toodle.Item(name="renew passport"))
+    overdue = manager.fetch_items(completed=False, due_before=datetime.now(timezone.utc))
+
+Dates are kept to the second, in UTC. Naive datetimes are taken to be local time.
+"""
+
+import json
+from dataclasses import dataclass, field
+from datetime import datetime, timezone
+from typing import List, Optional
+
+from . import _toodle
+
+__all__ = ["Item", "Label", "ListManager"]
+
+_ISO8601 = "%Y-%m-%dT%H:%M:%SZ"
+
+
+def _to_json_date(date):
+    return date.astimezone(timezone.utc).strftime(_ISO8601) if date else None
+
+
+def _from_json_date(date):
+    return datetime.strptime(date, _ISO8601).replace(tzinfo=timezone.utc) if date else None
+
+
+def _seconds(date):
+    return int(date.timestamp()) if date else None
+
+
+@dataclass
+class Label:
+    name: str
+    color: str
+
+
+@dataclass
+class Item:
+    name: str
+    uuid: str = ""
+    due_date: Optional[datetime] = None
+    completion_date: Optional[datetime] = None
+    labels: List[Label] = field(default_factory=list)
+
+    def _to_json(self):
+        return json.dumps({
+            "uuid": self.uuid,
+            "name": self.name,
+            "due_date": _to_json_date(self.due_date),
+            "completion_date": _to_json_date(self.completion_date),
+            "labels": [{"name": label.name, "color": label.color} for label in self.labels],
+        })
+
+    @staticmethod
+    def _from_dict(item):
+        return Item(
+            uuid=item["uuid"],
+            name=item["name"],
+            due_date=_from_json_date(item["due_date"]),
+            completion_date=_from_json_date(item["completion_date"]),
+            labels=[Label(**label) for label in item["labels"]],
+        )
+
+
+class ListManager:
+    """The items and labels in the store at `path`, which is created if missing.
+
+    Store failures raise RuntimeError and unreadable input raises ValueError. Use a
+    manager only from the thread that opened it.
+    """
+
+    def __init__(self, path):
+        self._manager = _toodle.ListManager(str(path))
+
+    def fetch_items(self, labels=None, completed=None, due_before=None, due_after=None, name_contains=None):
+        """The items matching every condition given: carrying all of `labels`, completed
+        or not, due before or after a datetime, or with `name_contains` in their name."""
+        items = self._manager.fetch_items(labels, completed, _seconds(due_before), _seconds(due_after), name_contains)
+        return [Item._from_dict(item) for item in json.loads(items)]
+
+    def fetch_item(self, uuid):
+        item = self._manager.fetch_item(uuid)
+        return Item._from_dict(json.loads(item)) if item is not None else None
+
+    def create_item(self, item):
+        """Returns the new item's uuid; the item's own uuid is ignored."""
+        return self._manager.create_item(item._to_json())
+
+    def save_item(self, item):
+        """Writes the item over the stored one with the same uuid."""
+        self._manager.save_item(item._to_json())
+
+    def delete_item(self, uuid):
+        """Returns False if there is no item with that uuid."""
+        return self._manager.delete_item(uuid)
+
+    def fetch_labels(self):
+        return [Label(**label) for label in json.loads(self._manager.fetch_labels())]
+
+    def create_label(self, name, color):
+        return Label(**json.loads(self._manager.create_label(name, color)))
+
+    def delete_label(self, name):
+        """Returns False if there is no label with that name."""
+        return self._manager.delete_label(name)
+
+    def export_csv(self):
+        return self._manager.export_csv()
+
+    def export_todo_txt(self):
+        return self._manager.export_todo_txt()
+
+    def export_ical(self):
+        return self._manager.export_ical()
+
+    def import_todo_txt(self, text):
+        """Returns the uuids of the new items."""
+        return self._manager.import_todo_txt(text)
+
+    def import_ical(self, data):
+        """`data` is the calendar as bytes. Returns the uuids of the new items."""
+        return self._manager.import_ical(data)
+
+    def import_dump(self, text, source):
+        """Imports a Todoist or Google Tasks export, `source` being "todoist" or
+        "google_tasks". Returns a dict with `created`, `skipped` and `errors`."""
+        return json.loads(self._manager.import_dump(text, source))
@@ -0,0 +1,151 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! The list manager for Python, for scripts and data migrations: items, labels, queries,
+//! and the import and export formats. Items and labels cross as JSON in the shape
+//! `item_to_json` writes; the `toodle` package wraps them in dataclasses.
+//!
+//! A `ListManager` may only be used from the thread that opened it.
+
+extern crate pyo3;
+extern crate serde_json;
+extern crate time;
+
+extern crate list;
+extern crate store;
+
+use std::sync::Arc;
+
+use pyo3::exceptions::{
+    PyIOError,
+    PyRuntimeError,
+    PyValueError,
+};
+use pyo3::prelude::*;
+use time::Timespec;
+
+use list::filters::{
+    DateBound,
+    ItemFilter,
+};
+use list::importers::ImportSource;
+use list::items::Item;
+use store::Store;
+use store::options::{
+    OpenOptions,
+    STORE_OPEN_CREATE_IF_MISSING,
+};
+
+fn item_from_json(json: &str) -> PyResult<Item> {
+    serde_json::from_str(json).map_err(|e| PyValueError::new_err(format!("not an item: {}", e)))
+}
+
+fn store_error<E: ToString>(what: &str, e: E) -> PyErr {
+    PyRuntimeError::new_err(format!("failed to {}: {}", what, e.to_string()))
+}
+
+#[pyclass(name = "ListManager", unsendable)]
+struct PyListManager {
+    manager: Arc<list::ListManager>,
+}
+
+#[pymethods]
+impl PyListManager {
+    /// Opens the store at `path`, creating it if missing.
+    #[new]
+    fn new(path: &str) -> PyResult<PyListManager> {
+        let store = Store::open(path, OpenOptions::from_bits(STORE_OPEN_CREATE_IF_MISSING)).map_err(|e| store_error("open store", e))?;
+        let manager = list::ListManager::open(Arc::new(store)).map_err(|e| store_error("open store", e))?;
+        Ok(PyListManager { manager: Arc::new(manager) })
+    }
+
+    /// The items matching every condition given, as a JSON array. `due_before` and
+    /// `due_after` are seconds since 1970.
+    #[pyo3(signature = (labels=None, completed=None, due_before=None, due_after=None, name_contains=None))]
+    fn fetch_items(&self, labels: Option<Vec<String>>, completed: Option<bool>, due_before: Option<i64>, due_after: Option<i64>, name_contains: Option<String>) -> String {
+        let filter = ItemFilter {
+            labels: labels.unwrap_or_default(),
+            completed: completed,
+            due_before: due_before.map(|sec| DateBound::Absolute(Timespec::new(sec, 0))),
+            due_after: due_after.map(|sec| DateBound::Absolute(Timespec::new(sec, 0))),
+            name_contains: name_contains,
+        };
+        serde_json::to_string(&self.manager.fetch_items(&filter)).unwrap()
+    }
+
+    fn fetch_item(&self, uuid: String) -> Option<String> {
+        self.manager.fetch_item(&uuid).map(|item| serde_json::to_string(&item).unwrap())
+    }
+
+    /// Returns the new item's uuid.
+    fn create_item(&self, json: &str) -> PyResult<String> {
+        self.manager.try_create_item(&item_from_json(json)?).map_err(|e| store_error("create item", e))
+    }
+
+    fn save_item(&self, json: &str) -> PyResult<()> {
+        self.manager.try_save_item(&item_from_json(json)?).map_err(|e| store_error("save item", e))
+    }
+
+    fn delete_item(&self, uuid: String) -> PyResult<bool> {
+        self.manager.try_delete_item(&uuid).map_err(|e| store_error("delete item", e))
+    }
+
+    fn fetch_labels(&self) -> String {
+        serde_json::to_string(&self.manager.fetch_labels()).unwrap()
+    }
+
+    fn create_label(&self, name: String, color: String) -> PyResult<String> {
+        let label = self.manager.try_create_label(name, color).map_err(|e| store_error("create label", e))?;
+        Ok(serde_json::to_string(&label).unwrap())
+    }
+
+    fn delete_label(&self, name: String) -> PyResult<bool> {
+        self.manager.try_delete_label(&name).map_err(|e| store_error("delete label", e))
+    }
+
+    fn export_csv(&self) -> String {
+        self.manager.export_csv()
+    }
+
+    fn export_todo_txt(&self) -> String {
+        self.manager.to_todo_txt()
+    }
+
+    fn export_ical(&self) -> String {
+        self.manager.to_ical()
+    }
+
+    /// Returns the uuids of the new items.
+    fn import_todo_txt(&self, text: &str) -> Vec<String> {
+        self.manager.import_todo_txt(text)
+    }
+
+    /// Returns the uuids of the new items.
+    fn import_ical(&self, data: &[u8]) -> PyResult<Vec<String>> {
+        self.manager.import_ical(data).map_err(|e| PyIOError::new_err(e.to_string()))
+    }
+
+    /// Imports a Todoist or Google Tasks export, `source` being `"todoist"` or
+    /// `"google_tasks"`. Returns the report as JSON.
+    fn import_dump(&self, text: &str, source: &str) -> PyResult<String> {
+        let source = match source {
+            "todoist" => ImportSource::Todoist,
+            "google_tasks" => ImportSource::GoogleTasks,
+            other => return Err(PyValueError::new_err(format!("unknown import source {}", other))),
+        };
+        Ok(serde_json::to_string(&self.manager.import_dump(text, source)).unwrap())
+    }
+}
+
+#[pymodule]
+fn _toodle(_py: Python, module: &PyModule) -> PyResult<()> {
+    module.add_class::<PyListManager>()?;
+    Ok(())
+}
@@ -0,0 +1,128 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! The C surface is `toodle.h` and the headers it includes. Functions declared there keep
+//! their signatures and meaning within an API version. Removing or changing one bumps
+//! `FFI_API_VERSION`; adding one does not. They are exported only with the `legacy-ffi`
+//! feature, which is on by default.
+
+/// Must match `TOODLE_FFI_API_VERSION` in `toodle.h`.
+pub const FFI_API_VERSION: u32 = 2;
+
+/// Hosts compare this with the `TOODLE_FFI_API_VERSION` they were compiled against at
+/// startup, and refuse to run on a mismatch rather than crash on a changed signature.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub extern "C" fn ffi_api_version() -> u32 {
+    ffi_guard!({
+        FFI_API_VERSION
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeSet;
+    use std::fs;
+    use std::path::{
+        Path,
+        PathBuf,
+    };
+
+    use super::FFI_API_VERSION;
+
+    const CRATES: [&'static str; 4] = [".", "ffi-utils", "list", "store"];
+    const EXPORTED: &'static str = "#[cfg_attr(feature = \"legacy-ffi\", no_mangle)]";
+
+    fn paths_with_extension(crate_dir: &Path, extension: &str) -> Vec<PathBuf> {
+        let mut paths = vec![];
+        for dir in [crate_dir.to_path_buf(), crate_dir.join("src")].iter() {
+            for entry in fs::read_dir(dir).unwrap() {
+                let path = entry.unwrap().path();
+                if path.extension().map_or(false, |e| e == extension) {
+                    paths.push(path);
+                }
+            }
+        }
+        paths
+    }
+
+    fn files_with_extension(crate_dir: &Path, extension: &str) -> Vec<String> {
+        paths_with_extension(crate_dir, extension).iter().map(|path| fs::read_to_string(path).unwrap()).collect()
+    }
+
+    /// The names of the functions the crates export.
+    fn exported() -> BTreeSet<String> {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let mut names = BTreeSet::new();
+        for name in CRATES.iter() {
+            for source in files_with_extension(&root.join(name), "rs") {
+                let mut lines = source.lines();
+                while let Some(line) = lines.next() {
+                    if line.trim() != EXPORTED {
+                        continue;
+                    }
+                    let signature = lines.next().unwrap();
+                    let after_fn = signature.split("fn ").nth(1).unwrap();
+                    names.insert(after_fn.split('(').next().unwrap().to_string());
+                }
+            }
+        }
+        names
+    }
+
+    /// The names of the functions the headers declare.
+    fn declared() -> BTreeSet<String> {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let mut names = BTreeSet::new();
+        for name in CRATES.iter() {
+            for header in files_with_extension(&root.join(name), "h") {
+                for line in header.lines() {
+                    let line = line.trim();
+                    if line.starts_with("//") || line.starts_with('#') || line.starts_with("typedef") || !line.ends_with(");") {
+                        continue;
+                    }
+                    let before_paren = line.split('(').next().unwrap();
+                    let name = before_paren.rsplit(|c: char| c == ' ' || c == '*').next().unwrap();
+                    names.insert(name.to_string());
+                }
+            }
+        }
+        names
+    }
+
+    #[test]
+    fn test_headers_match_exports() {
+        let exported = exported();
+        let declared = declared();
+        let undeclared: Vec<&String> = exported.difference(&declared).collect();
+        let missing: Vec<&String> = declared.difference(&exported).collect();
+        assert!(undeclared.is_empty(), "exported but not in a header: {:?}", undeclared);
+        assert!(missing.is_empty(), "declared but not exported: {:?}", missing);
+    }
+
+    #[test]
+    fn test_dart_header_includes_every_header() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let dart = include_str!("dart.h");
+        for name in CRATES.iter() {
+            for path in paths_with_extension(&root.join(name), "h") {
+                let file_name = path.file_name().unwrap().to_str().unwrap();
+                if file_name != "dart.h" {
+                    assert!(dart.contains(&format!("#include \"{}\"\n", file_name)), "dart.h does not include {}", file_name);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_header_version() {
+        let header = include_str!("toodle.h");
+        assert!(header.contains(&format!("#define TOODLE_FFI_API_VERSION {}\n", FFI_API_VERSION)));
+    }
+}
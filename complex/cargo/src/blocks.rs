@@ -0,0 +1,78 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+//! Observers given as Objective-C blocks, so Swift can pass a closure that captures state
+//! instead of a C function and a `user_data` pointer. Only on Apple platforms, which have
+//! the blocks runtime.
+
+use std::collections::BTreeMap;
+use std::os::raw::{
+    c_char,
+    c_int,
+    c_void,
+};
+use std::sync::Mutex;
+
+use list::dispatcher;
+use list::events::ChangeEventKind;
+
+/// The start of every block, as laid out by clang.
+#[repr(C)]
+struct ChangeBlock {
+    isa: *const c_void,
+    flags: c_int,
+    reserved: c_int,
+    invoke: unsafe extern "C" fn(block: *mut ChangeBlock, kind: ChangeEventKind, id: *const c_char),
+}
+
+extern "C" {
+    fn _Block_copy(block: *const c_void) -> *mut c_void;
+    fn _Block_release(block: *const c_void);
+}
+
+/// The copies of the registered blocks, by token, kept as integers so they may cross
+/// threads.
+static BLOCKS: Mutex<BTreeMap<u64, usize>> = Mutex::new(BTreeMap::new());
+
+extern "C" fn deliver(kind: ChangeEventKind, id: *const c_char, user_data: *mut c_void) {
+    let block = user_data as *mut ChangeBlock;
+    unsafe { ((*block).invoke)(block, kind, id) };
+}
+
+/// Like `toodle_register_observer`, calling `block` on the dispatcher thread. The block is
+/// copied, so a stack block may be passed.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn toodle_register_observer_block(block: *const c_void) -> u64 {
+    ffi_guard!({
+        if block.is_null() {
+            log_error!("toodle_register_observer_block: block is null");
+            return 0;
+        }
+        let copy = _Block_copy(block);
+        let mut blocks = BLOCKS.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let token = dispatcher::register(deliver, copy);
+        blocks.insert(token, copy as usize);
+        token
+    })
+}
+
+/// Like `toodle_unregister_observer`. Releases the copy of the block once it is no longer
+/// running.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn toodle_unregister_observer_block(token: u64) -> bool {
+    ffi_guard!({
+        let block = match BLOCKS.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).remove(&token) {
+            Some(block) => block as *const c_void,
+            None => return false,
+        };
+        dispatcher::unregister(token);
+        _Block_release(block);
+        true
+    })
+}
@@ -0,0 +1,61 @@
+#ifndef dart_h
+#define dart_h
+
+// The whole C surface in one header, for ffigen's entry point, plus teardown functions for
+// dart:ffi. The abi tests fail if a header is missing here.
+
+#include "buffers.h"
+#include "errors.h"
+#include "ffi_strings.h"
+#include "memory.h"
+#include "attachments.h"
+#include "attribution.h"
+#include "autosave.h"
+#include "batch.h"
+#include "conflicts.h"
+#include "cursors.h"
+#include "encryption.h"
+#include "events.h"
+#include "export.h"
+#include "filters.h"
+#include "ics.h"
+#include "import.h"
+#include "importers.h"
+#include "item_builder.h"
+#include "item_handles.h"
+#include "items.h"
+#include "jobs.h"
+#include "labels.h"
+#include "pinvoke.h"
+#include "prefs.h"
+#include "raw_query.h"
+#include "recent.h"
+#include "recurrence.h"
+#include "share.h"
+#include "smart_lists.h"
+#include "stats.h"
+#include "sync.h"
+#include "templates.h"
+#include "todo_txt.h"
+#include "transfer.h"
+#include "widget.h"
+#include "wire.h"
+#include "store.h"
+#include "jsi.h"
+#include "toodle.h"
+
+// Shaped as void (*)(void*), the type NativeFinalizer takes, so that ffigen's symbol
+// addresses for them can be passed to it as they are. Each calls the matching destroy
+// function, does nothing for NULL, and may run on any thread, also after the isolate that
+// made the object has shut down. toodle_free already has this shape.
+void toodle_finalize_toodle(void* _Nullable toodle);
+void toodle_finalize_store(void* _Nullable store);
+void toodle_finalize_list_manager(void* _Nullable manager);
+// Releases one ownership, like item_release.
+void toodle_finalize_item(void* _Nullable item);
+void toodle_finalize_label(void* _Nullable label);
+void toodle_finalize_item_filter(void* _Nullable filter);
+void toodle_finalize_cursor(void* _Nullable cursor);
+void toodle_finalize_string(void* _Nullable string);
+
+#endif /* dart_h */
@@ -0,0 +1,90 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! Teardown functions shaped as `void (*)(void*)`, the type `dart:ffi`'s `NativeFinalizer`
+//! takes, so that ffigen's symbol addresses for them can be handed to it directly. Each
+//! calls the usual destroy function, does nothing for null, and may run on any thread,
+//! including after the isolate that created the object has shut down.
+
+use std::os::raw::{
+    c_char,
+    c_void,
+};
+use std::sync::Arc;
+
+use ffi_utils::strings::string_destroy;
+use list::ListManager;
+use list::cursors::{
+    cursor_destroy,
+    Cursor,
+};
+use list::filters::{
+    item_filter_destroy,
+    ItemFilter,
+};
+use list::items::{
+    item_release,
+    Item,
+};
+use list::labels::{
+    label_destroy,
+    Label,
+};
+use list::list_manager_destroy;
+use store::{
+    store_destroy,
+    Store,
+};
+
+use {
+    toodle_destroy,
+    Toodle,
+};
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn toodle_finalize_toodle(toodle: *mut c_void) {
+    toodle_destroy(toodle as *mut Toodle)
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn toodle_finalize_store(store: *mut c_void) {
+    store_destroy(store as *mut Arc<Store>)
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn toodle_finalize_list_manager(manager: *mut c_void) {
+    list_manager_destroy(manager as *mut Arc<ListManager>)
+}
+
+/// Releases one ownership, like `item_release`, so an item retained elsewhere survives.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn toodle_finalize_item(item: *mut c_void) {
+    item_release(item as *mut Item)
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn toodle_finalize_label(label: *mut c_void) {
+    label_destroy(label as *mut Label)
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn toodle_finalize_item_filter(filter: *mut c_void) {
+    item_filter_destroy(filter as *mut ItemFilter)
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn toodle_finalize_cursor(cursor: *mut c_void) {
+    cursor_destroy(cursor as *mut Cursor)
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn toodle_finalize_string(string: *mut c_void) {
+    string_destroy(string as *mut c_char)
+}
@@ -0,0 +1,30 @@
+#ifndef jsi_h
+#define jsi_h
+
+#include <stdint.h>
+
+struct toodle;
+
+// A synchronous surface for React Native JSI host functions: a method name and JSON in,
+// JSON out. Returns {"result": ...}, or {"error": {"category": ..., "message": ...}} with an
+// ErrorCategory for an unknown method, bad arguments or a failed write; throw a JS Error for
+// those. `args` is a JSON object, or NULL for none. Items are as item_to_json writes them.
+//   fetchItems   {"labels"?: [...], "completed"?: bool, "nameContains"?: "..."} -> [item]
+//   fetchItem    {"uuid": "..."} -> item or null
+//   createItem   {"item": {...}} -> uuid
+//   saveItem     {"item": {...}} -> null
+//   deleteItem   {"uuid": "..."} -> bool
+//   fetchLabels  {} -> [label]
+//   createLabel  {"name": "...", "color": "..."} -> label
+//   deleteLabel  {"name": "..."} -> bool
+//   applyChanges {"changes": [...]} -> results, as list_manager_apply_changes
+char* _Nonnull toodle_jsi_call(const struct toodle* _Nonnull toodle, const char* _Nonnull method, const char* _Nullable args);
+
+// Called with each change as {"kind": "itemAdded", "id": "..."}, kinds being the
+// ChangeEventKind names in camelCase, on the dispatcher thread. The string is borrowed for
+// the call. Hop to the JS thread with the module's CallInvoker before touching the runtime.
+typedef void (*jsi_event_callback)(const char* _Nonnull event, void* _Nullable user_data);
+// Returns a token for toodle_unregister_change_observer.
+uint64_t toodle_jsi_subscribe(jsi_event_callback _Nonnull callback, void* _Nullable user_data);
+
+#endif /* jsi_h */
@@ -0,0 +1,252 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! A synchronous surface shaped for React Native JSI host functions: a method name and a
+//! JSON string in, a JSON string out, and events as JSON. A TurboModule forwards each call
+//! and parses the result, with no per-method C++ glue.
+
+use std::ffi::CString;
+use std::os::raw::{
+    c_char,
+    c_void,
+};
+use std::ptr;
+
+use rusqlite;
+use serde_json::{
+    self,
+    Value,
+};
+
+use ffi_utils::errors::ErrorCategory;
+use ffi_utils::strings::{
+    c_char_to_string,
+    string_to_c_char,
+};
+use list::ListManager;
+use list::batch::Change;
+use list::events::ChangeEventKind;
+use list::filters::ItemFilter;
+use list::items::Item;
+use store::errors::category_of;
+
+use observers::{
+    toodle_register_change_observer,
+    ChangeObserver,
+    ObserverQueue,
+};
+use Toodle;
+
+/// The methods and their arguments, which arrive as a JSON object.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "method", content = "args", rename_all = "camelCase")]
+enum Call {
+    #[serde(rename_all = "camelCase")]
+    FetchItems {
+        #[serde(default)]
+        labels: Vec<String>,
+        completed: Option<bool>,
+        name_contains: Option<String>,
+    },
+    FetchItem { uuid: String },
+    CreateItem { item: Item },
+    SaveItem { item: Item },
+    DeleteItem { uuid: String },
+    FetchLabels {},
+    CreateLabel { name: String, color: String },
+    DeleteLabel { name: String },
+    ApplyChanges { changes: Vec<Change> },
+}
+
+struct CallError {
+    category: ErrorCategory,
+    message: String,
+}
+
+impl From<rusqlite::Error> for CallError {
+    fn from(error: rusqlite::Error) -> CallError {
+        CallError {
+            category: category_of(&error),
+            message: error.to_string(),
+        }
+    }
+}
+
+fn run(manager: &ListManager, call: Call) -> Result<Value, CallError> {
+    Ok(match call {
+        Call::FetchItems { labels, completed, name_contains } => {
+            let filter = ItemFilter {
+                labels: labels,
+                completed: completed,
+                name_contains: name_contains,
+                ..ItemFilter::default()
+            };
+            json!(manager.fetch_items(&filter))
+        },
+        Call::FetchItem { uuid } => json!(manager.fetch_item(&uuid)),
+        Call::CreateItem { item } => json!(manager.try_create_item(&item)?),
+        Call::SaveItem { item } => {
+            manager.try_save_item(&item)?;
+            Value::Null
+        },
+        Call::DeleteItem { uuid } => json!(manager.try_delete_item(&uuid)?),
+        Call::FetchLabels {} => json!(manager.fetch_labels()),
+        Call::CreateLabel { name, color } => json!(manager.try_create_label(name, color)?),
+        Call::DeleteLabel { name } => json!(manager.try_delete_label(&name)?),
+        Call::ApplyChanges { changes } => json!(manager.apply_changes(&changes)?),
+    })
+}
+
+fn call(manager: &ListManager, method: String, args: String) -> String {
+    let args: Value = if args.trim().is_empty() {
+        json!({})
+    } else {
+        match serde_json::from_str(&args) {
+            Ok(Value::Null) => json!({}),
+            Ok(args) => args,
+            Err(e) => return error_json(ErrorCategory::Bug, format!("arguments are not JSON: {}", e)),
+        }
+    };
+    let result = serde_json::from_value(json!({ "method": method, "args": args }))
+        .map_err(|e| CallError { category: ErrorCategory::Bug, message: format!("bad call to {}: {}", method, e) })
+        .and_then(|call| run(manager, call));
+    match result {
+        Ok(result) => json!({ "result": result }).to_string(),
+        Err(e) => {
+            log_error!("{} failed {}", method, e.message);
+            error_json(e.category, e.message)
+        },
+    }
+}
+
+fn error_json(category: ErrorCategory, message: String) -> String {
+    json!({ "error": { "category": category as i32, "message": message } }).to_string()
+}
+
+/// Calls `method` with `args`, a JSON object or null, on the toodle's current list manager.
+/// Returns `{"result": ...}`, or `{"error": {"category": ..., "message": ...}}` for an
+/// unknown method, bad arguments or a failed write. Methods, with items as `item_to_json`
+/// writes them: `fetchItems` with optional `labels`, `completed` and `nameContains`,
+/// `fetchItem` and `deleteItem` with `uuid`, `createItem` and `saveItem` with `item`,
+/// `fetchLabels`, `createLabel` with `name` and `color`, `deleteLabel` with `name`, and
+/// `applyChanges` with `changes` as for `list_manager_apply_changes`.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn toodle_jsi_call(toodle: *const Toodle, method: *const c_char, args: *const c_char) -> *mut c_char {
+    ffi_lock!();
+    ffi_guard!({
+        let toodle = ffi_ref!("toodle_jsi_call", toodle, ptr::null_mut());
+        let args = if args.is_null() { "".to_string() } else { c_char_to_string(args) };
+        string_to_c_char(call(&toodle.list, c_char_to_string(method), args))
+    })
+}
+
+pub type JsiEventCallback = extern "C" fn(event: *const c_char, user_data: *mut c_void);
+
+struct Subscriber {
+    callback: JsiEventCallback,
+    user_data: *mut c_void,
+}
+
+fn kind_name(kind: ChangeEventKind) -> &'static str {
+    match kind {
+        ChangeEventKind::Invalidated => "invalidated",
+        ChangeEventKind::ItemAdded => "itemAdded",
+        ChangeEventKind::ItemUpdated => "itemUpdated",
+        ChangeEventKind::ItemDeleted => "itemDeleted",
+        ChangeEventKind::LabelChanged => "labelChanged",
+        ChangeEventKind::DueBucketChanged => "dueBucketChanged",
+        ChangeEventKind::SyncConflict => "syncConflict",
+        ChangeEventKind::LabelDeleted => "labelDeleted",
+    }
+}
+
+fn event_json(kind: ChangeEventKind, id: Option<String>) -> String {
+    json!({ "kind": kind_name(kind), "id": id }).to_string()
+}
+
+extern "C" fn deliver(kind: ChangeEventKind, id: *const c_char, context: *mut c_void) {
+    let subscriber = unsafe { &*(context as *const Subscriber) };
+    let id = if id.is_null() { None } else { Some(c_char_to_string(id)) };
+    let event = CString::new(event_json(kind, id)).unwrap();
+    (subscriber.callback)(event.as_ptr(), subscriber.user_data);
+}
+
+extern "C" fn release(context: *mut c_void) {
+    let _ = unsafe { Box::from_raw(context as *mut Subscriber) };
+}
+
+/// Calls `callback` with each change as JSON, `{"kind": "itemAdded", "id": "..."}`, on the
+/// dispatcher thread; hop to the JS thread with the module's `CallInvoker`. Returns a token
+/// for `toodle_unregister_change_observer`.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub extern "C" fn toodle_jsi_subscribe(callback: JsiEventCallback, user_data: *mut c_void) -> u64 {
+    ffi_guard!({
+        let subscriber = Box::new(Subscriber {
+            callback: callback,
+            user_data: user_data,
+        });
+        toodle_register_change_observer(ChangeObserver {
+            callback: deliver,
+            context: Box::into_raw(subscriber) as *mut c_void,
+            release: Some(release),
+            queue: ObserverQueue::Dispatcher,
+        })
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use serde_json::{
+        self,
+        Value,
+    };
+
+    use list::ListManager;
+    use list::events::ChangeEventKind;
+    use store::Store;
+
+    use super::{
+        call,
+        event_json,
+    };
+
+    fn call_json(manager: &ListManager, method: &str, args: &str) -> Value {
+        serde_json::from_str(&call(manager, method.to_string(), args.to_string())).unwrap()
+    }
+
+    #[test]
+    fn test_jsi_call() {
+        let manager = ListManager::new(Arc::new(Store::new(None)));
+        let created = call_json(&manager, "createItem", r#"{"item": {"name": "renew passport", "due_date": "2017-11-10T12:00:00Z"}}"#);
+        let uuid = created["result"].as_str().unwrap().to_string();
+
+        let fetched = call_json(&manager, "fetchItems", r#"{"nameContains": "passport", "completed": false}"#);
+        assert_eq!(fetched["result"][0]["uuid"], uuid.as_str());
+        assert_eq!(fetched["result"][0]["due_date"], "2017-11-10T12:00:00Z");
+        assert_eq!(call_json(&manager, "fetchLabels", "")["result"], json!([]));
+        assert_eq!(call_json(&manager, "deleteItem", &format!(r#"{{"uuid": "{}"}}"#, uuid))["result"], true);
+        assert_eq!(call_json(&manager, "fetchItem", &format!(r#"{{"uuid": "{}"}}"#, uuid))["result"], Value::Null);
+
+        assert_eq!(call_json(&manager, "createLabel", r##"{"name": "home", "color": "#00ff00"}"##)["result"]["name"], "home");
+        let duplicate = call_json(&manager, "createLabel", r##"{"name": "home", "color": "#ff0000"}"##);
+        assert!(duplicate["error"]["message"].is_string());
+        assert_eq!(call_json(&manager, "launchRockets", "{}")["error"]["category"], 5);
+        assert_eq!(call_json(&manager, "deleteItem", "{}")["error"]["category"], 5);
+        assert_eq!(call_json(&manager, "fetchItems", "not json")["error"]["category"], 5);
+    }
+
+    #[test]
+    fn test_event_json() {
+        assert_eq!(event_json(ChangeEventKind::ItemAdded, Some("a".to_string())), r#"{"id":"a","kind":"itemAdded"}"#);
+        assert_eq!(event_json(ChangeEventKind::Invalidated, None), r#"{"id":null,"kind":"invalidated"}"#);
+    }
+}
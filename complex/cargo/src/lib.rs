@@ -8,49 +8,424 @@
 // CONDITIONS OF ANY KIND, either express or implied. See the License for the
 // specific language governing permissions and limitations under the License.
 
+#[macro_use]
 extern crate ffi_utils;
+extern crate rusqlite;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+#[macro_use]
+extern crate serde_json;
 extern crate store;
 extern crate list;
 
+use std::mem;
+use std::path::PathBuf;
+use std::ptr;
+use std::slice;
 use std::os::raw::{
-    c_char
+    c_char,
+    c_void,
 };
 use std::sync::{
     Arc,
 };
+use std::time::Duration;
 
-use ffi_utils::strings::c_char_to_string;
+pub mod abi;
+#[cfg(any(target_os="ios", target_os="macos"))]
+mod blocks;
+pub mod dart;
+pub mod jsi;
+pub mod observers;
+mod self_test;
+
+use ffi_utils::breadcrumbs;
+use ffi_utils::diagnostics;
+use ffi_utils::errors::ExternError;
+use ffi_utils::logging::{
+    self,
+    LogCallback,
+    LogLevel,
+};
+use ffi_utils::nulls::set_assert_on_null;
+use ffi_utils::strings::{
+    c_char_to_string,
+    string_to_c_char,
+    wide_to_string,
+};
 use list::ListManager;
+use list::dates::set_strict_dates;
+use list::demo::demo_list_manager;
+use list::dispatcher;
+use list::events::ChangeCallback;
+use list::fixtures::fixture_list_manager;
+use list::flags::Flags;
+use list::ids::set_deterministic_ids;
 use store::Store;
+use store::errors::set_sqlite_error;
+use store::options::OpenOptions;
+use store::migrations::{
+    MigrationProgress,
+    MigrationProgressCallback,
+};
+
+const DEFAULT_FILENAME: &'static str = "toodle.db";
+
+/// Flags for `toodle_init`.
+/// Number new item ids 1, 2, 3... in each store instead of picking them at random.
+pub const TOODLE_INIT_DETERMINISTIC_IDS: u32 = 1;
+/// Reject dates after the year 3000 instead of reading them as milliseconds.
+pub const TOODLE_INIT_STRICT_DATES: u32 = 2;
+/// Abort on null arguments in debug builds instead of returning a default.
+pub const TOODLE_INIT_ASSERT_ON_NULL: u32 = 4;
 
 pub struct Toodle {
     store: Arc<Store>,
-    list: Arc<ListManager>
+    list: Arc<ListManager>,
+    /// The user's store and list, set aside while in demo mode.
+    real: Option<(Arc<Store>, Arc<ListManager>)>,
 }
 
 impl Toodle {
-    fn new(uri: String) -> Toodle {
-        let store = Arc::new(Store::new(uri));
-        Toodle {
+    fn new(uri: String, migration_progress: Option<MigrationProgress>) -> Toodle {
+        Toodle::with_store(Store::new(uri), migration_progress).expect("expected the store to migrate")
+    }
+
+    fn with_store(store: Store, migration_progress: Option<MigrationProgress>) -> rusqlite::Result<Toodle> {
+        breadcrumbs::install_panic_hook();
+        breadcrumbs::leave("toodle.open");
+        store.set_migration_progress(migration_progress);
+        let store = Arc::new(store);
+        Ok(Toodle {
             store: store.clone(),
-            list: Arc::new(ListManager::new(store.clone()))
+            list: Arc::new(ListManager::open(store.clone())?),
+            real: None,
+        })
+    }
+
+    /// Sets the user's store and list aside in favour of `list`. Returns false if already
+    /// in demo mode.
+    fn enter_demo_mode(&mut self, list: ListManager) -> bool {
+        if self.real.is_some() {
+            return false;
+        }
+        breadcrumbs::leave("toodle.enter_demo_mode");
+        list.set_flags(self.list.flags());
+        let list = Arc::new(list);
+        let real_store = mem::replace(&mut self.store, list.get_store());
+        let real_list = mem::replace(&mut self.list, list);
+        self.real = Some((real_store, real_list));
+        true
+    }
+
+    /// Discards the demo store and restores the user's. Returns false if not in demo mode.
+    fn exit_demo_mode(&mut self) -> bool {
+        match self.real.take() {
+            Some((store, list)) => {
+                breadcrumbs::leave("toodle.exit_demo_mode");
+                self.store = store;
+                self.list = list;
+                true
+            },
+            None => false,
         }
     }
 }
 
-#[no_mangle]
+/// Sets process wide options before any toodle is created. `flags` combines the
+/// `TOODLE_INIT_` constants; pass 0 in the shipping app. Calling it again replaces the
+/// options for toodles created afterwards.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub extern "C" fn toodle_init(flags: u32) {
+    ffi_guard!({
+        set_deterministic_ids(flags & TOODLE_INIT_DETERMINISTIC_IDS != 0);
+        set_strict_dates(flags & TOODLE_INIT_STRICT_DATES != 0);
+        set_assert_on_null(flags & TOODLE_INIT_ASSERT_ON_NULL != 0);
+    })
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
 pub extern "C" fn new_toodle(uri: *const c_char) -> *mut Toodle {
-    let uri = c_char_to_string(uri);
-    Box::into_raw(Box::new(Toodle::new(uri)))
+    ffi_guard!({
+        let uri = c_char_to_string(uri);
+        Box::into_raw(Box::new(Toodle::new(uri, None)))
+    })
+}
+
+/// Opens the store like `new_toodle`, reporting the progress of any schema upgrade to
+/// `callback` on the calling thread before returning.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub extern "C" fn new_toodle_with_migration_progress(uri: *const c_char, callback: MigrationProgressCallback, user_data: *mut c_void) -> *mut Toodle {
+    ffi_guard!({
+        let uri = c_char_to_string(uri);
+        let progress = MigrationProgress {
+            callback: callback,
+            user_data: user_data,
+        };
+        Box::into_raw(Box::new(Toodle::new(uri, Some(progress))))
+    })
+}
+
+/// Opens `filename` inside `directory`, which the host picks (e.g. the Android files
+/// directory or an iOS app group container). `filename` may be null for the default.
+/// `flags` combines `STORE_OPEN_READ_ONLY` and `STORE_OPEN_CREATE_IF_MISSING`. Returns null
+/// and fills in `error` if the store could not be opened or upgraded.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn new_toodle_with_options(directory: *const c_char, filename: *const c_char, flags: u32, error: *mut ExternError) -> *mut Toodle {
+    ffi_guard!(error, {
+        let mut path = PathBuf::from(c_char_to_string(directory));
+        path.push(if filename.is_null() { DEFAULT_FILENAME.to_string() } else { c_char_to_string(filename) });
+        open_toodle(path, flags, error)
+    })
+}
+
+/// `new_toodle_with_options` for Windows hosts, with `directory` and `filename` as NUL
+/// terminated UTF-16, so that paths outside the ANSI code page open.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn new_toodle_with_options_wide(directory: *const u16, filename: *const u16, flags: u32, error: *mut ExternError) -> *mut Toodle {
+    ffi_guard!(error, {
+        let mut path = PathBuf::from(wide_to_string(directory));
+        path.push(if filename.is_null() { DEFAULT_FILENAME.to_string() } else { wide_to_string(filename) });
+        open_toodle(path, flags, error)
+    })
+}
+
+unsafe fn open_toodle(path: PathBuf, flags: u32, error: *mut ExternError) -> *mut Toodle {
+    match Store::open(&path, OpenOptions::from_bits(flags)).and_then(|store| Toodle::with_store(store, None)) {
+        Ok(toodle) => Box::into_raw(Box::new(toodle)),
+        Err(e) => {
+            log_error!("failed to open store at {:?} {:?}", path, e);
+            set_sqlite_error(error, &e);
+            ptr::null_mut()
+        },
+    }
 }
 
-#[no_mangle]
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
 pub unsafe extern "C" fn toodle_destroy(toodle: *mut Toodle) {
-    let _ = Box::from_raw(toodle);
+    ffi_lock!();
+    ffi_guard!({
+        if toodle.is_null() {
+            return;
+        }
+        let _ = Box::from_raw(toodle);
+    })
 }
 
-#[no_mangle]
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
 pub unsafe extern "C" fn toodle_list(toodle: *mut Toodle) -> *mut Arc<ListManager> {
-    let toodle = &*toodle;
-    Box::into_raw(Box::new(toodle.list.clone()))
+    ffi_lock!();
+    ffi_guard!({
+        let toodle = ffi_ref!("toodle_list", toodle, ptr::null_mut());
+        Box::into_raw(Box::new(toodle.list.clone()))
+    })
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn toodle_store(toodle: *mut Toodle) -> *mut Arc<Store> {
+    ffi_lock!();
+    ffi_guard!({
+        let toodle = ffi_ref!("toodle_store", toodle, ptr::null_mut());
+        Box::into_raw(Box::new(toodle.store.clone()))
+    })
+}
+
+/// Call after the database file was replaced underneath the running process, e.g. by a
+/// backup restore. Open cursors become stale and list observers are told to refetch.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn toodle_reload_store(toodle: *mut Toodle, error: *mut ExternError) -> bool {
+    ffi_lock!();
+    ffi_guard!(error, {
+        let toodle = ffi_ref!("toodle_reload_store", toodle, false, error);
+        match toodle.list.reload_store() {
+            Ok(()) => true,
+            Err(e) => {
+                log_error!("failed to reload store {:?}", e);
+                set_sqlite_error(error, &e);
+                false
+            }
+        }
+    })
+}
+
+/// Replaces the store with a throwaway in memory one holding sample content in `locale`,
+/// for app tours and screenshots. List managers and stores obtained before the switch keep
+/// pointing at the user's data, so fetch them again with `toodle_list` and `toodle_store`.
+/// Returns false if already in demo mode.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn toodle_enter_demo_mode(toodle: *mut Toodle, locale: *const c_char) -> bool {
+    ffi_lock!();
+    ffi_guard!({
+        let toodle = ffi_mut!("toodle_enter_demo_mode", toodle);
+        toodle.enter_demo_mode(demo_list_manager(&c_char_to_string(locale)))
+    })
+}
+
+/// Enters demo mode with the named dataset from `list/tests/fixtures` and the clock frozen
+/// at the fixture's time, so that screenshot and UI tests render identical content on every
+/// run. Leave with `toodle_exit_demo_mode`. Returns false if there is no such fixture or
+/// already in demo mode.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn toodle_load_fixture(toodle: *mut Toodle, name: *const c_char) -> bool {
+    ffi_lock!();
+    ffi_guard!({
+        let toodle = ffi_mut!("toodle_load_fixture", toodle);
+        match fixture_list_manager(&c_char_to_string(name)) {
+            Some(list) => toodle.enter_demo_mode(list),
+            None => false,
+        }
+    })
+}
+
+/// Discards the demo store and everything in it, and switches back to the user's store.
+/// Returns false if not in demo mode.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn toodle_exit_demo_mode(toodle: *mut Toodle) -> bool {
+    ffi_lock!();
+    ffi_guard!({
+        let toodle = ffi_mut!("toodle_exit_demo_mode", toodle);
+        toodle.exit_demo_mode()
+    })
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn toodle_is_demo_mode(toodle: *const Toodle) -> bool {
+    ffi_lock!();
+    ffi_guard!({
+        let toodle = ffi_ref!("toodle_is_demo_mode", toodle);
+        toodle.real.is_some()
+    })
+}
+
+/// Copies the last few operations the library performed, oldest first, one per line, into
+/// `buffer` as a NUL terminated string of at most `len` bytes, and returns its length. Hand
+/// this to the crash reporter: it neither allocates nor locks, so it is safe to call from a
+/// signal handler.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn toodle_crash_context(buffer: *mut c_char, len: usize) -> usize {
+    ffi_guard!({
+        if buffer.is_null() || len == 0 {
+            return 0;
+        }
+        let buffer = slice::from_raw_parts_mut(buffer as *mut u8, len);
+        let copied = breadcrumbs::crash_context(&mut buffer[..len - 1]);
+        buffer[copied] = 0;
+        copied
+    })
+}
+
+/// Sends the library's log messages at `min_level` and above to `callback`, e.g. to os_log
+/// or logcat. A null callback sends them back to stdout, where they go until this is called.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub extern "C" fn toodle_set_logger(callback: Option<LogCallback>, min_level: LogLevel) {
+    ffi_guard!({
+        logging::set_logger(callback, min_level);
+    })
+}
+
+/// Logs drops of items, labels and stores, every `sample_every`th of each type, and counts
+/// them for `toodle_drop_counts`. Off unless turned on; for debug builds and leak hunts.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub extern "C" fn toodle_set_drop_diagnostics(enabled: bool, sample_every: u32) {
+    ffi_guard!({
+        diagnostics::set_drop_diagnostics(enabled, sample_every as usize);
+    })
+}
+
+/// A JSON object of how many values of each type were dropped, e.g. `{"Item": 120}`.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub extern "C" fn toodle_drop_counts() -> *mut c_char {
+    ffi_guard!({
+        string_to_c_char(serde_json::to_string(&diagnostics::drop_counts()).unwrap())
+    })
+}
+
+/// Checks that the store, string conversion, threads and callbacks work on this device.
+/// Returns a JSON object with `passed`, `sqlite_version`, `schema_version` and `checks`, an
+/// array of objects with `name`, `passed` and `detail` keys. Touches none of the user's data.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn toodle_self_test(toodle: *const Toodle) -> *mut c_char {
+    ffi_lock!();
+    ffi_guard!({
+        let toodle = ffi_ref!("toodle_self_test", toodle, ptr::null_mut());
+        string_to_c_char(serde_json::to_string(&self_test::self_test(&toodle.store)).unwrap())
+    })
+}
+
+/// Sets the flags that stage risky changes, from a JSON object mapping flag names to
+/// booleans, e.g. `{"crdt_merge": true}`. Replaces every flag set before, so flags left out
+/// turn off. Returns false, leaving the flags unchanged, if the JSON is not understood.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn toodle_set_flags(toodle: *mut Toodle, json: *const c_char) -> bool {
+    ffi_lock!();
+    ffi_guard!({
+        let toodle = ffi_ref!("toodle_set_flags", toodle);
+        match Flags::parse(&c_char_to_string(json)) {
+            Some(flags) => {
+                if let Some((_, ref list)) = toodle.real {
+                    list.set_flags(flags.clone());
+                }
+                toodle.list.set_flags(flags);
+                true
+            },
+            None => false,
+        }
+    })
+}
+
+/// Returns the flags currently set as a JSON object.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn toodle_flags_json(toodle: *const Toodle) -> *mut c_char {
+    ffi_lock!();
+    ffi_guard!({
+        let toodle = ffi_ref!("toodle_flags_json", toodle, ptr::null_mut());
+        string_to_c_char(serde_json::to_string(&toodle.list.flags()).unwrap())
+    })
+}
+
+/// Call at local midnight, on timezone changes and when daylight saving starts or ends, with
+/// the new offset from UTC in seconds, and once at launch. Returns a JSON array of objects
+/// with `uuid`, `from` and `to` keys for the open items that moved between the `overdue`,
+/// `today` and `later` views; list observers get a `DueBucketChanged` event for each.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn toodle_on_significant_time_change(toodle: *const Toodle, utc_offset: i64) -> *mut c_char {
+    ffi_lock!();
+    ffi_guard!({
+        let toodle = ffi_ref!("toodle_on_significant_time_change", toodle, ptr::null_mut());
+        string_to_c_char(serde_json::to_string(&toodle.list.on_significant_time_change(utc_offset)).unwrap())
+    })
+}
+
+/// Call from a background task the OS granted, e.g. an iOS `BGAppRefreshTask`, with the time
+/// it allows in seconds. Runs the pending saves, sync and pruning, most important first, until
+/// the budget is spent. Returns a JSON object with `completed`, `elapsed_ms` and `steps`, an
+/// array of objects with `name`, `outcome` and `detail` keys.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn toodle_background_refresh(toodle: *const Toodle, budget_seconds: f64) -> *mut c_char {
+    ffi_lock!();
+    ffi_guard!({
+        let toodle = ffi_ref!("toodle_background_refresh", toodle, ptr::null_mut());
+        let budget = Duration::from_millis((budget_seconds.max(0.0) * 1000.0) as u64);
+        string_to_c_char(serde_json::to_string(&toodle.list.background_refresh(budget)).unwrap())
+    })
+}
+
+/// Calls `callback` after every change made through any list manager, always on the same
+/// thread, which the library starts and owns, in the order the changes were made. Returns a
+/// token for `toodle_unregister_observer`.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub extern "C" fn toodle_register_observer(callback: ChangeCallback, user_data: *mut c_void) -> u64 {
+    ffi_guard!({
+        dispatcher::register(callback, user_data)
+    })
+}
+
+/// Returns false if there is no observer with that token. Waits for a call to the callback
+/// in progress on the dispatcher thread to return, so `user_data` may be freed afterwards.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub extern "C" fn toodle_unregister_observer(token: u64) -> bool {
+    ffi_guard!({
+        dispatcher::unregister(token)
+    })
 }
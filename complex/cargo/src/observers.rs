@@ -0,0 +1,241 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+//! Change observers shaped for closures: the host passes a function, a context pointer and
+//! a function releasing the context, so Swift can hand over a retained box holding a
+//! closure and have the library release it exactly once, after its last call.
+
+use std::collections::BTreeMap;
+use std::os::raw::{
+    c_char,
+    c_void,
+};
+use std::sync::Mutex;
+
+use list::dispatcher;
+use list::events::{
+    ChangeCallback,
+    ChangeEventKind,
+};
+
+/// Where an observer is called.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ObserverQueue {
+    /// The dispatcher thread, one at a time, in the order the changes were made.
+    Dispatcher = 0,
+    /// The main dispatch queue, in the same order, so SwiftUI state can be set directly.
+    /// Only on Apple platforms.
+    Main = 1,
+}
+
+/// `struct change_observer` in toodle.h.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct ChangeObserver {
+    pub callback: ChangeCallback,
+    pub context: *mut c_void,
+    /// Called once with `context` after the last call to `callback`, on the same queue.
+    pub release: Option<extern "C" fn(context: *mut c_void)>,
+    pub queue: ObserverQueue,
+}
+
+/// The registered observers, boxed, by token, kept as integers so they may cross threads.
+static OBSERVERS: Mutex<BTreeMap<u64, usize>> = Mutex::new(BTreeMap::new());
+
+extern "C" fn deliver(kind: ChangeEventKind, id: *const c_char, user_data: *mut c_void) {
+    let observer = unsafe { &*(user_data as *const ChangeObserver) };
+    match observer.queue {
+        ObserverQueue::Dispatcher => (observer.callback)(kind, id, observer.context),
+        ObserverQueue::Main => main_queue::deliver(observer, kind, id),
+    }
+}
+
+#[cfg(any(target_os="ios", target_os="macos"))]
+mod main_queue {
+    use std::ffi::{
+        CStr,
+        CString,
+    };
+    use std::os::raw::{
+        c_char,
+        c_void,
+    };
+    use std::ptr;
+
+    use list::events::ChangeEventKind;
+
+    use super::ChangeObserver;
+
+    pub const AVAILABLE: bool = true;
+
+    extern "C" {
+        /// What `dispatch_get_main_queue()` returns.
+        static _dispatch_main_q: c_void;
+        fn dispatch_async_f(queue: *const c_void, context: *mut c_void, work: extern "C" fn(*mut c_void));
+    }
+
+    /// Copied because `id` only lives for the dispatcher's call.
+    struct Delivery {
+        observer: ChangeObserver,
+        kind: ChangeEventKind,
+        id: Option<CString>,
+    }
+
+    extern "C" fn run_delivery(context: *mut c_void) {
+        let delivery = unsafe { Box::from_raw(context as *mut Delivery) };
+        let id = delivery.id.as_ref().map_or(ptr::null(), |id| id.as_ptr());
+        (delivery.observer.callback)(delivery.kind, id, delivery.observer.context);
+    }
+
+    pub fn deliver(observer: &ChangeObserver, kind: ChangeEventKind, id: *const c_char) {
+        let id = if id.is_null() { None } else { Some(unsafe { CStr::from_ptr(id) }.to_owned()) };
+        let delivery = Box::new(Delivery { observer: *observer, kind: kind, id: id });
+        unsafe { dispatch_async_f(&_dispatch_main_q, Box::into_raw(delivery) as *mut c_void, run_delivery) };
+    }
+
+    extern "C" fn run_release(context: *mut c_void) {
+        let observer = unsafe { Box::from_raw(context as *mut ChangeObserver) };
+        if let Some(release) = observer.release {
+            release(observer.context);
+        }
+    }
+
+    /// Releases the observer after the deliveries already queued on the main queue.
+    pub fn release(observer: Box<ChangeObserver>) {
+        unsafe { dispatch_async_f(&_dispatch_main_q, Box::into_raw(observer) as *mut c_void, run_release) };
+    }
+}
+
+#[cfg(not(any(target_os="ios", target_os="macos")))]
+mod main_queue {
+    use std::os::raw::c_char;
+
+    use list::events::ChangeEventKind;
+
+    use super::ChangeObserver;
+
+    pub const AVAILABLE: bool = false;
+
+    pub fn deliver(_: &ChangeObserver, _: ChangeEventKind, _: *const c_char) {
+        unreachable!("main queue observers are refused on this platform");
+    }
+
+    pub fn release(_: Box<ChangeObserver>) {
+        unreachable!("main queue observers are refused on this platform");
+    }
+}
+
+/// Calls `observer.callback` with `observer.context` after every change made through any
+/// list manager, on `observer.queue`. Returns a token for
+/// `toodle_unregister_change_observer`, or 0 if the queue is not available here.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub extern "C" fn toodle_register_change_observer(observer: ChangeObserver) -> u64 {
+    ffi_guard!({
+        if observer.queue == ObserverQueue::Main && !main_queue::AVAILABLE {
+            log_error!("toodle_register_change_observer: there is no main queue on this platform");
+            return 0;
+        }
+        let observer = Box::into_raw(Box::new(observer));
+        let mut observers = OBSERVERS.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let token = dispatcher::register(deliver, observer as *mut c_void);
+        observers.insert(token, observer as usize);
+        token
+    })
+}
+
+/// Returns false if there is no observer with that token. The observer is not called for
+/// changes made afterwards, and its `release` is called once calls already under way or
+/// queued have finished. May be called from the observer itself.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub extern "C" fn toodle_unregister_change_observer(token: u64) -> bool {
+    ffi_guard!({
+        let observer = match OBSERVERS.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).remove(&token) {
+            Some(observer) => observer,
+            None => return false,
+        };
+        dispatcher::unregister(token);
+        dispatcher::defer(Box::new(move || {
+            let observer = unsafe { Box::from_raw(observer as *mut ChangeObserver) };
+            match observer.queue {
+                ObserverQueue::Dispatcher => if let Some(release) = observer.release {
+                    release(observer.context);
+                },
+                ObserverQueue::Main => main_queue::release(observer),
+            }
+        }));
+        true
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::os::raw::{
+        c_char,
+        c_void,
+    };
+    use std::sync::Arc;
+    use std::sync::atomic::{
+        AtomicUsize,
+        Ordering,
+    };
+
+    use list::dispatcher;
+    use list::events::ChangeEventKind;
+    use list::ListManager;
+    use store::Store;
+
+    use super::{
+        main_queue,
+        toodle_register_change_observer,
+        toodle_unregister_change_observer,
+        ChangeObserver,
+        ObserverQueue,
+    };
+
+    struct Counts {
+        calls: AtomicUsize,
+        releases: AtomicUsize,
+    }
+
+    extern "C" fn count_call(_: ChangeEventKind, _: *const c_char, context: *mut c_void) {
+        unsafe { &*(context as *const Counts) }.calls.fetch_add(1, Ordering::SeqCst);
+    }
+
+    extern "C" fn count_release(context: *mut c_void) {
+        unsafe { &*(context as *const Counts) }.releases.fetch_add(1, Ordering::SeqCst);
+    }
+
+    #[test]
+    fn test_change_observer_is_released_once() {
+        let counts = Box::new(Counts { calls: AtomicUsize::new(0), releases: AtomicUsize::new(0) });
+        let observer = ChangeObserver {
+            callback: count_call,
+            context: &*counts as *const Counts as *mut c_void,
+            release: Some(count_release),
+            queue: ObserverQueue::Dispatcher,
+        };
+        let token = toodle_register_change_observer(observer);
+        assert!(token != 0);
+
+        let manager = ListManager::new(Arc::new(Store::new(None)));
+        manager.create_label("observed".to_string(), "#000000".to_string()).unwrap();
+        dispatcher::flush();
+        assert!(counts.calls.load(Ordering::SeqCst) >= 1);
+
+        assert!(toodle_unregister_change_observer(token));
+        assert!(!toodle_unregister_change_observer(token));
+        dispatcher::flush();
+        assert_eq!(counts.releases.load(Ordering::SeqCst), 1);
+
+        if !main_queue::AVAILABLE {
+            assert_eq!(toodle_register_change_observer(ChangeObserver { queue: ObserverQueue::Main, ..observer }), 0);
+        }
+    }
+}
@@ -0,0 +1,161 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! Checks that the library works on this device, for support requests where the app only
+//! says "database error".
+
+use std::ffi::CString;
+use std::os::raw::{
+    c_char,
+    c_int,
+    c_void,
+};
+use std::panic;
+use std::thread;
+
+use rusqlite;
+use rusqlite::Connection;
+
+use ffi_utils::strings::{
+    c_char_to_string,
+    string_to_c_char,
+};
+use store::Store;
+use store::migrations::{
+    MigrationProgress,
+    MigrationStep,
+};
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Check {
+    pub name: &'static str,
+    pub passed: bool,
+    /// Why the check failed.
+    pub detail: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SelfTestReport {
+    pub passed: bool,
+    pub sqlite_version: &'static str,
+    pub schema_version: Option<i64>,
+    pub checks: Vec<Check>,
+}
+
+/// Runs `test`, turning an error or a panic into a failed check.
+fn check<F>(name: &'static str, test: F) -> Check
+where F: FnOnce() -> Result<(), String> + panic::UnwindSafe {
+    let detail = match panic::catch_unwind(test) {
+        Ok(Ok(())) => None,
+        Ok(Err(detail)) => Some(detail),
+        Err(_) => Some("panicked".to_string()),
+    };
+    Check {
+        name: name,
+        passed: detail.is_none(),
+        detail: detail,
+    }
+}
+
+/// Writes, reads back and deletes a row in a temporary table on the store's own
+/// connection. Temporary tables are private to the connection and never reach the file,
+/// so this works on read only stores and leaves the user's data alone.
+fn store_roundtrip(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch("CREATE TEMP TABLE IF NOT EXISTS toodle_self_test (value TEXT)")?;
+    let result = (|| {
+        conn.execute("INSERT INTO toodle_self_test (value) VALUES (?)", &[&"roundtrip"])?;
+        let value: String = conn.query_row("SELECT value FROM toodle_self_test", &[], |row| row.get(0))?;
+        if value != "roundtrip" {
+            return Err(rusqlite::Error::QueryReturnedNoRows);
+        }
+        conn.execute("DELETE FROM toodle_self_test", &[])?;
+        let remaining: i64 = conn.query_row("SELECT count(*) FROM toodle_self_test", &[], |row| row.get(0))?;
+        if remaining != 0 {
+            return Err(rusqlite::Error::QueryReturnedNoRows);
+        }
+        Ok(())
+    })();
+    conn.execute_batch("DROP TABLE temp.toodle_self_test")?;
+    result
+}
+
+fn strings_roundtrip() -> Result<(), String> {
+    let text = "Grüße, 世界 ✓".to_string();
+    let raw = string_to_c_char(text.clone());
+    let back = c_char_to_string(raw);
+    unsafe { let _ = CString::from_raw(raw); }
+    if back == text { Ok(()) } else { Err(format!("got {:?}", back)) }
+}
+
+fn threads() -> Result<(), String> {
+    let worker = thread::spawn(|| {
+        let conn = Connection::open_in_memory().map_err(|e| e.to_string())?;
+        conn.query_row("SELECT 6 * 7", &[], |row| row.get::<i32, i64>(0)).map_err(|e| e.to_string())
+    });
+    match worker.join() {
+        Ok(Ok(42)) => Ok(()),
+        Ok(Ok(other)) => Err(format!("got {}", other)),
+        Ok(Err(e)) => Err(e),
+        Err(_) => Err("the thread panicked".to_string()),
+    }
+}
+
+extern "C" fn count_progress(_step: *const c_char, _percent: c_int, user_data: *mut c_void) {
+    let calls = unsafe { &mut *(user_data as *mut i32) };
+    *calls += 1;
+}
+
+/// Calls back through a C function pointer the way migration progress reaches the host.
+fn callbacks() -> Result<(), String> {
+    let mut calls: i32 = 0;
+    let store = Store::new(None);
+    store.set_migration_progress(Some(MigrationProgress {
+        callback: count_progress,
+        user_data: &mut calls as *mut i32 as *mut c_void,
+    }));
+    let steps = [MigrationStep::new("self_test", "CREATE TABLE self_test (x INTEGER)")];
+    store.migrate(&steps).map_err(|e| e.to_string())?;
+    // once for the step and once when done
+    if calls == 2 { Ok(()) } else { Err(format!("called back {} times", calls)) }
+}
+
+pub fn self_test(store: &Store) -> SelfTestReport {
+    let conn = store.get_conn();
+    let checks = vec![
+        check("store", panic::AssertUnwindSafe(|| store_roundtrip(&conn).map_err(|e| e.to_string()))),
+        check("strings", strings_roundtrip),
+        check("threads", threads),
+        check("callbacks", callbacks),
+    ];
+    SelfTestReport {
+        passed: checks.iter().all(|c| c.passed),
+        sqlite_version: rusqlite::version(),
+        schema_version: store.schema_version().ok(),
+        checks: checks,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::self_test;
+
+    use store::Store;
+
+    #[test]
+    fn test_self_test() {
+        let store = Store::new(None);
+        let report = self_test(&store);
+        assert!(report.passed, "{:?}", report);
+        assert_eq!(report.checks.len(), 4);
+        // nothing was left behind
+        let tables: i64 = store.get_conn().query_row("SELECT count(*) FROM sqlite_temp_master", &[], |row| row.get(0)).unwrap();
+        assert_eq!(tables, 0);
+    }
+}
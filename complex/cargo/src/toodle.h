@@ -1,9 +1,180 @@
+#ifndef toodle_h
+#define toodle_h
+
+#include <stdbool.h>
+#include <stddef.h>
 #include <stdint.h>
 #include "labels.h"
+#include "store.h"
 
 struct toodle;
 
+// Ownership: a char* the library returns is the caller's, released with string_destroy. A
+// returned struct pointer or list is the caller's, released with the matching _destroy
+// function. Declarations say so where a result is borrowed instead, e.g. label_count_get_label,
+// or released some other way, e.g. store_commit_transaction. Arguments are only borrowed.
+
+// The version of the functions declared in this header and those it includes. It changes
+// when one is removed or its signature or meaning changes, not when one is added. At
+// startup, check that ffi_api_version() returns this, and refuse to run otherwise: the
+// library the app was linked against does not match the headers it was compiled with.
+#define TOODLE_FFI_API_VERSION 2
+uint32_t ffi_api_version(void);
+
+// Flags for toodle_init.
+// Number new item ids 1, 2, 3... in each store instead of picking them at random, so that
+// integration tests and golden files are identical on every run. Never in the shipping app.
+#define TOODLE_INIT_DETERMINISTIC_IDS 1
+// Reject dates after the year 3000 passed to item_try_set_due_date and the like, instead of
+// reading them as milliseconds, so that hosts passing the wrong unit find out in debug builds.
+#define TOODLE_INIT_STRICT_DATES 2
+// Crash on a NULL passed where an object is required, in debug builds of the library, so the
+// mistake is caught where it is made. Otherwise, and always in release builds, the function
+// logs it, fills in its extern_error with ErrorCategoryBug if it takes one, and returns
+// false, NULL, 0 or an empty list without doing anything.
+#define TOODLE_INIT_ASSERT_ON_NULL 4
+// Sets process wide options for toodles created afterwards. Pass 0 in the shipping app.
+void toodle_init(uint32_t flags);
+
 struct toodle* new_toodle(const char* uri);
+// Called before each pending migration step with the percentage of steps already applied,
+// and once more with "done" and 100 when the store is up to date.
+typedef void (*migration_progress_callback)(const char* _Nonnull step, int percent, void* _Nullable user_data);
+struct toodle* new_toodle_with_migration_progress(const char* uri, migration_progress_callback callback, void* _Nullable user_data);
+// Opens `filename` (NULL for the default) inside `directory`, e.g. the Android files
+// directory or an iOS app group container. `flags` combines STORE_OPEN_READ_ONLY and
+// STORE_OPEN_CREATE_IF_MISSING. Returns NULL and fills in `error` if the store could not be
+// opened or upgraded.
+struct toodle* _Nullable new_toodle_with_options(const char* _Nonnull directory, const char* _Nullable filename, uint32_t flags, struct extern_error* _Nullable error);
+// new_toodle_with_options for Windows, with the paths as NUL terminated UTF-16 (wchar_t*,
+// or UnmanagedType.LPWStr from .NET).
+struct toodle* _Nullable new_toodle_with_options_wide(const uint16_t* _Nonnull directory, const uint16_t* _Nullable filename, uint32_t flags, struct extern_error* _Nullable error);
 void toodle_destroy(struct toodle* toodle);
 
+// Release with list_manager_destroy and store_destroy respectively.
 struct list_manager* toodle_list(struct toodle* toodle);
+struct store* toodle_store(struct toodle* toodle);
+// Call after the database file was replaced underneath the running process, e.g. by a
+// backup restore. Open cursors become stale and list observers are told to refetch.
+bool toodle_reload_store(struct toodle* toodle, struct extern_error* _Nullable error);
+
+// Replaces the store with a throwaway in memory one holding sample content in `locale`, for
+// app tours and screenshots. List managers and stores obtained before the switch keep
+// pointing at the user's data, so fetch them again. Returns false if already in demo mode.
+bool toodle_enter_demo_mode(struct toodle* toodle, const char* _Nonnull locale);
+// Enters demo mode with the named dataset ("basic", "empty") and the clock frozen at the
+// fixture's time, so screenshot and UI tests render identical content on every run. Returns
+// false if there is no such fixture or already in demo mode.
+bool toodle_load_fixture(struct toodle* toodle, const char* _Nonnull name);
+// Discards the demo store and switches back to the user's. Returns false if not in demo mode.
+bool toodle_exit_demo_mode(struct toodle* toodle);
+bool toodle_is_demo_mode(const struct toodle* toodle);
+
+// Copies the last few operations the library performed, oldest first, one per line as
+// "<unix seconds> <operation>", into `buffer` as a NUL terminated string of at most `len`
+// bytes, keeping the most recent if they do not all fit. Returns its length. Attach it to
+// crash reports: it neither allocates nor locks, so it is safe to call from a signal handler.
+size_t toodle_crash_context(char* _Nonnull buffer, size_t len);
+
+enum log_level {
+    LogLevelDebug = 0,
+    LogLevelInfo = 1,
+    LogLevelWarn = 2,
+    LogLevelError = 3,
+};
+
+// Called with each log message at or above the logger's level. `tag` names the part of the
+// library that logged it, e.g. "list::sync". Neither string may be kept after the call.
+typedef void (*log_callback)(enum log_level level, const char* _Nonnull tag, const char* _Nonnull message);
+// Sends the library's log messages at `min_level` and above to `callback`, e.g. os_log on iOS
+// or __android_log_write on Android. Until it is called, and after it is called with NULL,
+// messages are written to logcat on Android and printed to stdout elsewhere. The callback
+// may be called from any thread.
+void toodle_set_logger(log_callback _Nullable callback, enum log_level min_level);
+
+// Memory diagnostics, off by default. While on, drops of items, labels and stores are counted
+// by type and every `sample_every`th drop of each type is logged, starting with the first; 0
+// logs none. Counts are kept when turned off.
+void toodle_set_drop_diagnostics(bool enabled, uint32_t sample_every);
+// How many values of each type were dropped while diagnostics were on, as a JSON object,
+// e.g. {"Item": 120, "Label": 4}.
+char* _Nonnull toodle_drop_counts(void);
+
+// Checks that the store, string conversion, threads and callbacks work on this device, for
+// support requests. Returns a JSON object with `passed`, `sqlite_version`, `schema_version`
+// and `checks`, an array of objects with `name`, `passed` and `detail` keys. The user's data
+// is not touched.
+char* _Nonnull toodle_self_test(const struct toodle* _Nonnull toodle);
+
+// Sets the flags that stage risky changes, e.g. {"crdt_merge": true}, which settles items
+// changed on both sides of a sync field by field where the manual conflict strategy would
+// record a conflict. Every flag is off unless set, and each call replaces all flags set
+// before. Returns false, leaving the flags unchanged, if the JSON is not understood.
+bool toodle_set_flags(struct toodle* _Nonnull toodle, const char* _Nonnull json);
+// Returns the flags currently set as a JSON object.
+char* _Nonnull toodle_flags_json(const struct toodle* _Nonnull toodle);
+
+// Call at local midnight, when the timezone changes and when daylight saving starts or ends,
+// with the new offset from UTC in seconds, and once at launch to record the current day.
+// Returns a JSON array of {"uuid", "from", "to"} objects, where from and to are "overdue",
+// "today" or "later", for the open items that moved between views. List observers also get
+// a ChangeEventKindDueBucketChanged event for each; reschedule their reminders.
+char* _Nonnull toodle_on_significant_time_change(const struct toodle* _Nonnull toodle, int64_t utc_offset);
+
+// Call from a BGAppRefreshTask with the time it may run, in seconds, leaving some slack: a
+// step already started is not interrupted. Saves queued edits, syncs if configured and prunes
+// old history, most important first, until the budget is spent. Returns a JSON object with
+// `completed`, which is what to pass to setTaskCompleted(success:), `elapsed_ms` and `steps`,
+// an array of {"name", "outcome", "detail"} objects where outcome is "done", "not_needed",
+// "failed" or "skipped". The detail key is left out when there is nothing to say.
+char* _Nonnull toodle_background_refresh(const struct toodle* _Nonnull toodle, double budget_seconds);
+
+// Push notifications of changes made through any list manager. Unlike
+// list_manager_add_observer, which calls back on the thread that made the change, `callback`
+// always runs on one thread the library starts for it, in the order the changes were made,
+// so post to the UI queue from there. Returns a token for toodle_unregister_observer.
+uint64_t toodle_register_observer(change_callback _Nonnull callback, void* _Nullable user_data);
+// Returns false if there is no observer with that token. Once it returns, the callback is
+// neither running nor called again, so `user_data` may be freed; called from the callback
+// itself, it cannot wait for that call to finish.
+bool toodle_unregister_observer(uint64_t token);
+
+// For wrapping an observer in a closure, e.g. in Swift: pass a retained box holding the
+// closure as `context` and a `release` that releases it.
+enum observer_queue {
+    // The dispatcher thread, one call at a time, in the order the changes were made.
+    ObserverQueueDispatcher = 0,
+    // The main dispatch queue, in the same order, so SwiftUI state can be set directly from
+    // the callback. Only on Apple platforms.
+    ObserverQueueMain = 1,
+};
+
+// Called once with the observer's context, on its queue, after the last call to its
+// callback.
+typedef void (*observer_release)(void* _Nullable context);
+
+struct change_observer {
+    change_callback _Nonnull callback;
+    void* _Nullable context;
+    observer_release _Nullable release;
+    enum observer_queue queue;
+};
+
+// Calls observer.callback with observer.context as user_data after every change made
+// through any list manager, on observer.queue. Returns a token for
+// toodle_unregister_change_observer, or 0 if the queue is not available on this platform.
+uint64_t toodle_register_change_observer(struct change_observer observer);
+// Returns false if there is no observer with that token. The observer is not called for
+// changes made afterwards; calls already under way or queued still happen, and then
+// `release` is called. Safe to call from the callback itself, e.g. from a SwiftUI view's
+// onDisappear on the main queue.
+bool toodle_unregister_change_observer(uint64_t token);
+#if __BLOCKS__
+// The same with a block, e.g. a Swift closure, instead of a function and user_data. Only on
+// Apple platforms. The block is copied, and released by toodle_unregister_observer_block.
+typedef void (^change_block)(enum change_event_kind kind, const char* _Nullable id);
+uint64_t toodle_register_observer_block(change_block _Nonnull block);
+bool toodle_unregister_observer_block(uint64_t token);
+#endif
+
+#endif /* toodle_h */
@@ -0,0 +1,220 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::os::raw::c_char;
+use std::path::{
+    Path,
+    PathBuf,
+};
+use std::sync::Arc;
+
+use rusqlite;
+use rusqlite::{
+    Connection,
+    DatabaseName,
+};
+
+use ffi_utils::breadcrumbs;
+use ffi_utils::errors::{
+    set_extern_error,
+    ErrorCategory,
+    ExternError,
+};
+use ffi_utils::strings::c_char_to_string;
+use errors::category_of;
+use {
+    encryption,
+    locking,
+    tracing,
+    Store,
+};
+
+#[derive(Debug)]
+pub enum BackupError {
+    Io(io::Error),
+    Sqlite(rusqlite::Error),
+}
+
+impl BackupError {
+    pub fn category(&self) -> ErrorCategory {
+        match *self {
+            // a missing file, a full disk or a directory the app cannot write to
+            BackupError::Io(_) => ErrorCategory::NeedsUserInput,
+            BackupError::Sqlite(ref e) => category_of(e),
+        }
+    }
+}
+
+impl fmt::Display for BackupError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BackupError::Io(ref e) => write!(f, "{}", e),
+            BackupError::Sqlite(ref e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<io::Error> for BackupError {
+    fn from(error: io::Error) -> BackupError {
+        BackupError::Io(error)
+    }
+}
+
+impl From<rusqlite::Error> for BackupError {
+    fn from(error: rusqlite::Error) -> BackupError {
+        BackupError::Sqlite(error)
+    }
+}
+
+impl Store {
+    /// Writes a consistent snapshot of the whole database to `path`. The snapshot is written
+    /// next to `path` and moved into place once complete, so `path` never holds a partial copy.
+    pub fn backup<P: AsRef<Path>>(&self, path: P) -> Result<(), BackupError> {
+        breadcrumbs::leave("store.backup");
+        let path = path.as_ref();
+        let mut partial = PathBuf::from(path);
+        partial.set_extension("partial");
+        let _ = fs::remove_file(&partial);
+        if let Err(e) = self.get_conn().backup(DatabaseName::Main, &partial, None) {
+            let _ = fs::remove_file(&partial);
+            return Err(e.into());
+        }
+        fs::rename(&partial, path)?;
+        Ok(())
+    }
+
+    /// Replaces the contents of the database with the snapshot at `path`, in a single
+    /// transaction. The store is left untouched if the snapshot cannot be read. Like
+    /// `reopen`, this starts a new generation; the restored data may need migrating.
+    pub fn restore<P: AsRef<Path>>(&self, path: P) -> Result<(), BackupError> {
+        breadcrumbs::leave("store.restore");
+        let path = path.as_ref();
+        // opening a missing snapshot would create an empty one and restore that
+        fs::metadata(path)?;
+        let mut c = match self.uri {
+            Some(ref uri) => Connection::open_with_flags(uri.clone(), self.open_flags.get())?,
+            None => Connection::open_in_memory()?,
+        };
+        locking::apply_busy_timeout(&c, self.busy_timeout.get())?;
+        if let Some(ref key) = *self.key.borrow() {
+            encryption::apply_key(&c, key)?;
+        }
+        c.restore(DatabaseName::Main, path, None)?;
+        tracing::apply_sql_tracing(&mut c, self.sql_tracing.get());
+        *self.conn.borrow_mut() = Arc::new(c);
+        self.generation.set(self.generation.get() + 1);
+        Ok(())
+    }
+}
+
+/// Returns false and fills in `error` if the backup could not be written.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn store_backup(store: *const Arc<Store>, path: *const c_char, error: *mut ExternError) -> bool {
+    ffi_lock!();
+    ffi_guard!(error, {
+        let store = ffi_ref!("store_backup", store, false, error);
+        match store.backup(c_char_to_string(path)) {
+            Ok(()) => true,
+            Err(e) => {
+                log_error!("failed to back up store {:?}", e);
+                set_extern_error(error, e.category(), e.to_string());
+                false
+            },
+        }
+    })
+}
+
+/// Returns false and fills in `error` if the backup could not be restored, in which case
+/// the store is unchanged.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn store_restore(store: *const Arc<Store>, path: *const c_char, error: *mut ExternError) -> bool {
+    ffi_lock!();
+    ffi_guard!(error, {
+        let store = ffi_ref!("store_restore", store, false, error);
+        match store.restore(c_char_to_string(path)) {
+            Ok(()) => true,
+            Err(e) => {
+                log_error!("failed to restore store {:?}", e);
+                set_extern_error(error, e.category(), e.to_string());
+                false
+            },
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::fs;
+
+    use super::BackupError;
+
+    use Store;
+
+    fn names(store: &Store) -> Vec<String> {
+        let conn = store.get_conn();
+        let mut stmt = conn.prepare("SELECT name FROM t ORDER BY name").unwrap();
+        let names = stmt.query_map(&[], |row| row.get(0)).unwrap().map(|r| r.unwrap()).collect();
+        names
+    }
+
+    #[test]
+    fn test_backup_and_restore() {
+        let dir = env::temp_dir();
+        let db = dir.join("toodle_backup_test.db");
+        let backup = dir.join("toodle_backup_test.backup");
+        let _ = fs::remove_file(&db);
+        let _ = fs::remove_file(&backup);
+
+        let store = Store::new(db.to_string_lossy().into_owned());
+        store.get_conn().execute_batch("CREATE TABLE t (name TEXT); INSERT INTO t VALUES ('kept')").unwrap();
+        store.backup(&backup).expect("expected a backup");
+        assert!(backup.exists());
+        assert!(!backup.with_extension("partial").exists());
+
+        store.get_conn().execute_batch("DELETE FROM t; INSERT INTO t VALUES ('lost')").unwrap();
+        let generation = store.generation();
+        store.restore(&backup).expect("expected the backup to be restored");
+        assert_eq!(names(&store), vec!["kept"]);
+        assert_eq!(store.generation(), generation + 1);
+
+        // the restore reached the file, not just the connection
+        assert_eq!(names(&Store::new(db.to_string_lossy().into_owned())), vec!["kept"]);
+    }
+
+    #[test]
+    fn test_restore_in_memory_store() {
+        let backup = env::temp_dir().join("toodle_backup_memory_test.backup");
+        let _ = fs::remove_file(&backup);
+        let store = Store::new(None);
+        store.get_conn().execute_batch("CREATE TABLE t (name TEXT); INSERT INTO t VALUES ('a')").unwrap();
+        store.backup(&backup).unwrap();
+        store.get_conn().execute_batch("INSERT INTO t VALUES ('b')").unwrap();
+        store.restore(&backup).unwrap();
+        assert_eq!(names(&store), vec!["a"]);
+    }
+
+    #[test]
+    fn test_restore_missing_backup() {
+        let missing = env::temp_dir().join("toodle_backup_missing_test.backup");
+        let _ = fs::remove_file(&missing);
+        let store = Store::new(None);
+        store.get_conn().execute_batch("CREATE TABLE t (name TEXT); INSERT INTO t VALUES ('a')").unwrap();
+        match store.restore(&missing) {
+            Err(BackupError::Io(_)) => {},
+            other => panic!("expected an io error, got {:?}", other),
+        }
+        assert!(!missing.exists());
+        assert_eq!(names(&store), vec!["a"]);
+    }
+}
@@ -0,0 +1,165 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+use std::fmt;
+
+use rusqlite;
+use rusqlite::{
+    Connection,
+    ErrorCode,
+};
+
+use ffi_utils::errors::ErrorCategory;
+use errors::category_of;
+use Store;
+
+/// A database key. Never printed, so that logging a `Store` does not leak it.
+pub struct Key(String);
+
+impl fmt::Debug for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Key(<redacted>)")
+    }
+}
+
+#[derive(Debug)]
+pub enum EncryptionError {
+    /// The SQLite library linked in cannot encrypt databases. The store is not opened
+    /// rather than silently writing plaintext.
+    Unsupported,
+    /// The store was not opened with a key, so there is nothing to change.
+    NotEncrypted,
+    Sqlite(rusqlite::Error),
+}
+
+impl EncryptionError {
+    pub fn category(&self) -> ErrorCategory {
+        match *self {
+            EncryptionError::Unsupported | EncryptionError::NotEncrypted => ErrorCategory::Bug,
+            // SQLCipher cannot tell a wrong key from a file that is not a database.
+            EncryptionError::Sqlite(rusqlite::Error::SqliteFailure(ref e, _)) if e.code == ErrorCode::NotADatabase => ErrorCategory::NeedsReauth,
+            EncryptionError::Sqlite(ref e) => category_of(e),
+        }
+    }
+}
+
+impl fmt::Display for EncryptionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            EncryptionError::Unsupported => write!(f, "SQLite was built without encryption support"),
+            EncryptionError::NotEncrypted => write!(f, "the store is not encrypted"),
+            EncryptionError::Sqlite(ref e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for EncryptionError {
+    fn from(error: rusqlite::Error) -> EncryptionError {
+        EncryptionError::Sqlite(error)
+    }
+}
+
+fn quote(key: &str) -> String {
+    format!("'{}'", key.replace("'", "''"))
+}
+
+/// Whether SQLite was built with SQLCipher. Plain SQLite ignores the key pragmas.
+pub fn cipher_supported() -> bool {
+    match Connection::open_in_memory() {
+        Ok(conn) => conn.query_row("PRAGMA cipher_version", &[], |row| row.get::<i32, String>(0)).is_ok(),
+        Err(_) => false,
+    }
+}
+
+/// Keys a freshly opened connection. Fails if the key does not decrypt the database.
+pub fn apply_key(conn: &Connection, key: &Key) -> rusqlite::Result<()> {
+    conn.execute_batch(&format!("PRAGMA key = {}", quote(&key.0)))?;
+    // The key is only checked once the database is read.
+    conn.query_row("SELECT count(*) FROM sqlite_master", &[], |row| row.get::<i32, i64>(0)).map(|_| ())
+}
+
+impl Store {
+    /// Opens the database at `uri` encrypted with `key`, creating it if needed.
+    pub fn open_encrypted(uri: String, key: &str) -> Result<Store, EncryptionError> {
+        if !cipher_supported() {
+            return Err(EncryptionError::Unsupported);
+        }
+        let key = Key(key.to_string());
+        let conn = Connection::open(uri.clone())?;
+        apply_key(&conn, &key)?;
+        let store = Store::with_connection(conn, Some(uri));
+        *store.key.borrow_mut() = Some(key);
+        Ok(store)
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.key.borrow().is_some()
+    }
+
+    /// Re-encrypts the database with `key`.
+    pub fn rekey(&self, key: &str) -> Result<(), EncryptionError> {
+        if !self.is_encrypted() {
+            return Err(EncryptionError::NotEncrypted);
+        }
+        self.get_conn().execute_batch(&format!("PRAGMA rekey = {}", quote(key)))?;
+        *self.key.borrow_mut() = Some(Key(key.to_string()));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::fs;
+
+    use super::{
+        cipher_supported,
+        quote,
+        EncryptionError,
+        Key,
+    };
+
+    use Store;
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(quote("it's"), "'it''s'");
+    }
+
+    #[test]
+    fn test_key_is_not_printed() {
+        assert_eq!(format!("{:?}", Key("hunter2".to_string())), "Key(<redacted>)");
+    }
+
+    #[test]
+    fn test_open_encrypted_without_cipher_support() {
+        if cipher_supported() {
+            return;
+        }
+        let path = env::temp_dir().join("toodle_encrypted_test.db");
+        let _ = fs::remove_file(&path);
+        match Store::open_encrypted(path.to_string_lossy().into_owned(), "secret") {
+            Err(EncryptionError::Unsupported) => {},
+            other => panic!("expected the store to refuse to open, got {:?}", other),
+        }
+        // nothing was written in the clear
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_rekey_requires_encrypted_store() {
+        let store = Store::new(None);
+        assert!(!store.is_encrypted());
+        match store.rekey("secret") {
+            Err(EncryptionError::NotEncrypted) => {},
+            other => panic!("expected NotEncrypted, got {:?}", other),
+        }
+    }
+}
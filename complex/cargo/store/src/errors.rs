@@ -0,0 +1,73 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+use rusqlite;
+use rusqlite::ErrorCode;
+
+use ffi_utils::errors::{
+    set_extern_error,
+    ErrorCategory,
+    ExternError,
+};
+
+/// The category of a SQLite error, for the host to act on.
+pub fn category_of(error: &rusqlite::Error) -> ErrorCategory {
+    match *error {
+        rusqlite::Error::SqliteFailure(ref e, _) => match e.code {
+            ErrorCode::DatabaseBusy |
+            ErrorCode::DatabaseLocked => ErrorCategory::Busy,
+            ErrorCode::OperationInterrupted |
+            ErrorCode::SystemIOFailure |
+            ErrorCode::OutOfMemory |
+            ErrorCode::FileLockingProtocolFailed |
+            ErrorCode::SchemaChanged => ErrorCategory::Retryable,
+            ErrorCode::DiskFull |
+            ErrorCode::PermissionDenied |
+            ErrorCode::CannotOpen |
+            ErrorCode::ReadOnly |
+            ErrorCode::TooBig |
+            ErrorCode::ConstraintViolation => ErrorCategory::NeedsUserInput,
+            ErrorCode::DatabaseCorrupt |
+            ErrorCode::NotADatabase => ErrorCategory::DataCorruption,
+            _ => ErrorCategory::Bug,
+        },
+        _ => ErrorCategory::Bug,
+    }
+}
+
+/// Records a SQLite error in `out`, which may be null.
+pub unsafe fn set_sqlite_error(out: *mut ExternError, error: &rusqlite::Error) {
+    set_extern_error(out, category_of(error), error.to_string());
+}
+
+#[cfg(test)]
+mod test {
+    use rusqlite;
+
+    use ffi_utils::errors::ErrorCategory;
+
+    use super::category_of;
+
+    use Store;
+
+    fn error_for(sql: &str) -> rusqlite::Error {
+        let store = Store::new(None);
+        store.get_conn().execute_batch("CREATE TABLE t (x INTEGER PRIMARY KEY)").unwrap();
+        store.get_conn().execute_batch(sql).unwrap_err()
+    }
+
+    #[test]
+    fn test_category_of() {
+        assert_eq!(category_of(&error_for("INSERT INTO t VALUES (1); INSERT INTO t VALUES (1)")), ErrorCategory::NeedsUserInput);
+        assert_eq!(category_of(&error_for("SELEKT 1")), ErrorCategory::Bug);
+        assert_eq!(category_of(&error_for("PRAGMA query_only = 1; INSERT INTO t VALUES (1)")), ErrorCategory::NeedsUserInput);
+        assert_eq!(category_of(&rusqlite::Error::QueryReturnedNoRows), ErrorCategory::Bug);
+    }
+}
@@ -8,33 +8,67 @@
 // CONDITIONS OF ANY KIND, either express or implied. See the License for the
 // specific language governing permissions and limitations under the License.
 
+extern crate libc;
 extern crate rusqlite;
+#[macro_use]
 extern crate ffi_utils;
 
+use std::cell::{
+    Cell,
+    RefCell,
+};
 use std::os::raw::{
     c_char
 };
+use std::rc::Rc;
 use std::sync::{
     Arc,
 };
 
 use rusqlite::{
-    Connection
+    Connection,
+    OpenFlags,
 };
 
+use ffi_utils::breadcrumbs;
+use ffi_utils::diagnostics;
 use ffi_utils::strings::c_char_to_string;
 
+pub mod backup;
+pub mod encryption;
+pub mod errors;
+pub mod locking;
+pub mod maintenance;
+pub mod migrations;
+pub mod options;
+pub mod tracing;
+pub mod transactions;
+pub mod usage;
+
+use migrations::MigrationProgress;
+
 #[derive(Debug)]
 #[repr(C)]
 /// Store containing a SQLite connection
 pub struct Store {
-    conn: Arc<Connection>,
+    conn: RefCell<Arc<Connection>>,
     uri: Option<String>,
+    generation: Cell<u64>,
+    sql_tracing: Cell<bool>,
+    history_cap: Cell<Option<i64>>,
+    changelog_cap: Cell<Option<i64>>,
+    migration_progress: Cell<Option<MigrationProgress>>,
+    key: RefCell<Option<encryption::Key>>,
+    savepoints: Cell<u64>,
+    open_transactions: Rc<Cell<u64>>,
+    deferred: Rc<transactions::Deferred>,
+    open_flags: Cell<OpenFlags>,
+    busy_timeout: Cell<i64>,
 }
 
 impl Drop for Store {
     fn drop(&mut self) {
-        println!("{:?} is being deallocated", self);
+        diagnostics::record_drop("Store", || format!("{:?}", self));
     }
 }
 
@@ -46,29 +80,80 @@ impl Store {
             &Some(ref u) => Connection::open(u.clone()).expect("Expected a connection for URI"),
             &None => Connection::open_in_memory().expect("Expected an in memory connection"),
         };
+        Store::with_connection(c, uri_string)
+    }
+
+    fn with_connection(c: Connection, uri_string: Option<String>) -> Self {
+        if let Err(e) = locking::apply_busy_timeout(&c, locking::DEFAULT_BUSY_TIMEOUT_MS) {
+            log_error!("failed to set busy timeout {:?}", e);
+        }
         Store {
-            conn: Arc::new(c),
+            conn: RefCell::new(Arc::new(c)),
             uri: uri_string,
+            generation: Cell::new(0),
+            sql_tracing: Cell::new(false),
+            history_cap: Cell::new(None),
+            changelog_cap: Cell::new(None),
+            migration_progress: Cell::new(None),
+            key: RefCell::new(None),
+            savepoints: Cell::new(0),
+            open_transactions: Rc::new(Cell::new(0)),
+            deferred: Rc::new(transactions::Deferred::default()),
+            open_flags: Cell::new(OpenFlags::default()),
+            busy_timeout: Cell::new(locking::DEFAULT_BUSY_TIMEOUT_MS),
         }
     }
 
     pub fn get_conn_mut(&mut self) -> &mut Connection {
-        Arc::get_mut(&mut self.conn).unwrap()
+        Arc::get_mut(self.conn.get_mut()).unwrap()
     }
 
     pub fn get_conn(&self) -> Arc<Connection> {
-        Arc::clone(&self.conn)
+        Arc::clone(&self.conn.borrow())
+    }
+
+    /// Replaces the connection with a fresh one to the same file, for when the database has
+    /// been swapped out underneath the process (e.g. restored from a backup). The previous
+    /// connection closes once the last statement using it is done. In memory stores keep
+    /// their connection, as reopening would lose their contents.
+    pub fn reopen(&self) -> rusqlite::Result<()> {
+        breadcrumbs::leave("store.reopen");
+        if let Some(ref uri) = self.uri {
+            let mut c = Connection::open_with_flags(uri.clone(), self.open_flags.get())?;
+            locking::apply_busy_timeout(&c, self.busy_timeout.get())?;
+            if let Some(ref key) = *self.key.borrow() {
+                encryption::apply_key(&c, key)?;
+            }
+            tracing::apply_sql_tracing(&mut c, self.sql_tracing.get());
+            *self.conn.borrow_mut() = Arc::new(c);
+        }
+        self.generation.set(self.generation.get() + 1);
+        Ok(())
+    }
+
+    /// Incremented every time the store is reopened. Anything holding on to results read
+    /// from an earlier generation should consider them stale.
+    pub fn generation(&self) -> u64 {
+        self.generation.get()
     }
 }
 
-#[no_mangle]
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
 pub extern "C" fn new_store(uri: *const c_char) -> *mut Arc<Store> {
-    let uri = c_char_to_string(uri);
-    let store = Arc::new(Store::new(Some(uri)));
-    Box::into_raw(Box::new(store))
+    ffi_guard!({
+        let uri = c_char_to_string(uri);
+        let store = Arc::new(Store::new(Some(uri)));
+        Box::into_raw(Box::new(store))
+    })
 }
 
-#[no_mangle]
-pub unsafe extern "C" fn store_destroy(data: *mut Store) {
-    let _ = Box::from_raw(data);
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn store_destroy(data: *mut Arc<Store>) {
+    ffi_lock!();
+    ffi_guard!({
+        if data.is_null() {
+            return;
+        }
+        let _ = Box::from_raw(data);
+    })
 }
@@ -0,0 +1,98 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! Sharing a database file with other processes, such as an iOS share extension and the
+//! app it belongs to. SQLite's file locks keep the data consistent; a process finding the
+//! file locked waits up to the busy timeout for the other to finish, then fails with
+//! `ErrorCategory::Busy` so the host can retry later.
+
+use std::sync::Arc;
+
+use rusqlite;
+use rusqlite::Connection;
+
+use ffi_utils::errors::ExternError;
+use errors::set_sqlite_error;
+use Store;
+
+/// Long enough to outlast any single write by another process.
+pub const DEFAULT_BUSY_TIMEOUT_MS: i64 = 5000;
+
+pub fn apply_busy_timeout(conn: &Connection, milliseconds: i64) -> rusqlite::Result<()> {
+    conn.query_row(&format!("PRAGMA busy_timeout = {}", milliseconds), &[], |row| row.get::<i32, i64>(0)).map(|_| ())
+}
+
+impl Store {
+    /// How long to wait for another process to release the database before failing.
+    pub fn set_busy_timeout(&self, milliseconds: i64) -> rusqlite::Result<()> {
+        apply_busy_timeout(&self.get_conn(), milliseconds)?;
+        self.busy_timeout.set(milliseconds);
+        Ok(())
+    }
+
+    pub fn busy_timeout(&self) -> i64 {
+        self.busy_timeout.get()
+    }
+}
+
+/// 0 fails straight away if another process holds the database.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn store_set_busy_timeout(store: *const Arc<Store>, milliseconds: i64, error: *mut ExternError) -> bool {
+    ffi_lock!();
+    ffi_guard!(error, {
+        let store = ffi_ref!("store_set_busy_timeout", store, false, error);
+        match store.set_busy_timeout(milliseconds) {
+            Ok(()) => true,
+            Err(e) => {
+                log_error!("failed to set busy timeout {:?}", e);
+                set_sqlite_error(error, &e);
+                false
+            },
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::fs;
+
+    use ffi_utils::errors::ErrorCategory;
+
+    use errors::category_of;
+    use Store;
+
+    #[test]
+    fn test_other_process_holding_the_database() {
+        let path = env::temp_dir().join("toodle_busy_test.db");
+        let _ = fs::remove_file(&path);
+        let uri = path.to_string_lossy().into_owned();
+        // two connections to one file behave like two processes
+        let app = Store::new(uri.clone());
+        let extension = Store::new(uri);
+        app.get_conn().execute_batch("CREATE TABLE t (x INTEGER)").unwrap();
+        extension.set_busy_timeout(50).unwrap();
+        assert_eq!(extension.busy_timeout(), 50);
+
+        let tx = app.begin_transaction().unwrap();
+        app.get_conn().execute_batch("INSERT INTO t VALUES (1)").unwrap();
+        match extension.begin_transaction() {
+            Err(e) => assert_eq!(category_of(&e), ErrorCategory::Busy),
+            Ok(_) => panic!("expected the database to be busy"),
+        }
+        tx.commit().unwrap();
+
+        let tx = extension.begin_transaction().unwrap();
+        extension.get_conn().execute_batch("INSERT INTO t VALUES (2)").unwrap();
+        tx.commit().unwrap();
+        let count: i64 = app.get_conn().query_row("SELECT count(*) FROM t", &[], |row| row.get(0)).unwrap();
+        assert_eq!(count, 2);
+    }
+}
@@ -0,0 +1,173 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+use libc::size_t;
+use std::os::raw::c_char;
+use std::ptr;
+use std::sync::Arc;
+
+use rusqlite;
+
+use ffi_utils::breadcrumbs;
+use ffi_utils::errors::ExternError;
+use ffi_utils::strings::string_to_c_char;
+use errors::set_sqlite_error;
+use Store;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+#[repr(C)]
+/// The size of the database file before and after it was compacted.
+pub struct StoreVacuumReport {
+    pub bytes_before: i64,
+    pub bytes_after: i64,
+}
+
+impl Store {
+    fn size_in_bytes(&self) -> rusqlite::Result<i64> {
+        let conn = self.get_conn();
+        let page_count: i64 = conn.query_row("PRAGMA page_count", &[], |row| row.get(0))?;
+        let page_size: i64 = conn.query_row("PRAGMA page_size", &[], |row| row.get(0))?;
+        Ok(page_count * page_size)
+    }
+
+    /// Rebuilds the database file without its free pages. Fails inside a transaction.
+    pub fn vacuum(&self) -> rusqlite::Result<StoreVacuumReport> {
+        breadcrumbs::leave("store.vacuum");
+        let bytes_before = self.size_in_bytes()?;
+        self.get_conn().execute_batch("VACUUM")?;
+        Ok(StoreVacuumReport {
+            bytes_before: bytes_before,
+            bytes_after: self.size_in_bytes()?,
+        })
+    }
+
+    /// Every problem SQLite finds with the database's structure, or none if it is intact.
+    pub fn check_integrity(&self) -> rusqlite::Result<Vec<String>> {
+        breadcrumbs::leave("store.check_integrity");
+        let conn = self.get_conn();
+        let mut stmt = conn.prepare("PRAGMA integrity_check")?;
+        let mut problems = Vec::new();
+        for result in stmt.query_map(&[], |row| row.get::<i32, String>(0))? {
+            let problem = result?;
+            if problem != "ok" {
+                problems.push(problem);
+            }
+        }
+        Ok(problems)
+    }
+}
+
+/// Returns null and fills in `error` if the store could not be compacted, e.g. because a
+/// transaction is open.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn store_vacuum(store: *const Arc<Store>, error: *mut ExternError) -> *mut StoreVacuumReport {
+    ffi_lock!();
+    ffi_guard!(error, {
+        let store = ffi_ref!("store_vacuum", store, ptr::null_mut(), error);
+        match store.vacuum() {
+            Ok(report) => Box::into_raw(Box::new(report)),
+            Err(e) => {
+                log_error!("failed to vacuum store {:?}", e);
+                set_sqlite_error(error, &e);
+                ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn store_vacuum_report_destroy(report: *mut StoreVacuumReport) {
+    ffi_guard!({
+        if report.is_null() {
+            return;
+        }
+        let _ = Box::from_raw(report);
+    })
+}
+
+/// Returns the problems found, an empty list if the store is intact. Returns null and fills
+/// in `error` if the check could not run, which for a badly damaged file is itself a sign
+/// of corruption and is reported as such.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn store_check_integrity(store: *const Arc<Store>, error: *mut ExternError) -> *mut Vec<String> {
+    ffi_lock!();
+    ffi_guard!(error, {
+        let store = ffi_ref!("store_check_integrity", store, ptr::null_mut(), error);
+        match store.check_integrity() {
+            Ok(problems) => Box::into_raw(Box::new(problems)),
+            Err(e) => {
+                log_error!("failed to check store integrity {:?}", e);
+                set_sqlite_error(error, &e);
+                ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn integrity_problems_destroy(problems: *mut Vec<String>) {
+    ffi_guard!({
+        if problems.is_null() {
+            return;
+        }
+        let _ = Box::from_raw(problems);
+    })
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn integrity_problems_count(problems: *const Vec<String>) -> size_t {
+    ffi_guard!({
+        let problems = ffi_ref!("integrity_problems_count", problems);
+        problems.len()
+    })
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn integrity_problem_at(problems: *const Vec<String>, index: size_t) -> *mut c_char {
+    ffi_guard!({
+        let problems = ffi_ref!("integrity_problem_at", problems, ptr::null_mut());
+        string_to_c_char(problems[index].clone())
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::fs;
+
+    use Store;
+
+    #[test]
+    fn test_vacuum() {
+        let path = env::temp_dir().join("toodle_vacuum_test.db");
+        let _ = fs::remove_file(&path);
+        let store = Store::new(path.to_string_lossy().into_owned());
+        {
+            let conn = store.get_conn();
+            conn.execute_batch("CREATE TABLE t (payload TEXT)").unwrap();
+            for _ in 0..200 {
+                conn.execute("INSERT INTO t (payload) VALUES (?)", &[&"x".repeat(1000)]).unwrap();
+            }
+            conn.execute_batch("DELETE FROM t").unwrap();
+        }
+        let report = store.vacuum().expect("expected a report");
+        assert!(report.bytes_after < report.bytes_before);
+
+        let _tx = store.begin_transaction().unwrap();
+        assert!(store.vacuum().is_err());
+    }
+
+    #[test]
+    fn test_check_integrity() {
+        let store = Store::new(None);
+        store.get_conn().execute_batch("CREATE TABLE t (x INTEGER); CREATE INDEX t_x ON t (x);").unwrap();
+        assert_eq!(store.check_integrity().unwrap(), Vec::<String>::new());
+    }
+}
@@ -0,0 +1,201 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+use std::ffi::CString;
+use std::os::raw::{
+    c_char,
+    c_int,
+    c_void,
+};
+use std::sync::Arc;
+
+use rusqlite;
+
+use ffi_utils::breadcrumbs;
+use Store;
+
+/// Called before each pending migration step with the step's name and the percentage of
+/// steps already applied, and once more with `"done"` and 100 when the store is up to date.
+pub type MigrationProgressCallback = extern "C" fn(step: *const c_char, percent: c_int, user_data: *mut c_void);
+
+#[derive(Debug, Clone, Copy)]
+pub struct MigrationProgress {
+    pub callback: MigrationProgressCallback,
+    pub user_data: *mut c_void,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// A named batch of SQL. Each step is applied in its own transaction and recorded in the
+/// migration journal, so an upgrade interrupted by the process being killed resumes at the
+/// first step that did not commit. Steps are only ever appended, never edited or reordered,
+/// and the schema version is the number of steps applied.
+pub struct MigrationStep {
+    pub name: &'static str,
+    pub sql: &'static str,
+}
+
+impl MigrationStep {
+    pub fn new(name: &'static str, sql: &'static str) -> MigrationStep {
+        MigrationStep {
+            name: name,
+            sql: sql,
+        }
+    }
+}
+
+impl Store {
+    pub fn set_migration_progress(&self, progress: Option<MigrationProgress>) {
+        self.migration_progress.set(progress);
+    }
+
+    /// Applies every step that has not been applied yet, in order, reporting to the
+    /// registered migration progress callback.
+    pub fn migrate(&self, steps: &[MigrationStep]) -> rusqlite::Result<()> {
+        breadcrumbs::leave("store.migrate");
+        match self.migration_progress.get() {
+            Some(progress) => {
+                self.migrate_with_progress(steps, &mut |step, percent| {
+                    let step = CString::new(step).unwrap_or_default();
+                    (progress.callback)(step.as_ptr(), percent, progress.user_data);
+                })
+            },
+            None => self.migrate_with_progress(steps, &mut |_, _| {}),
+        }
+    }
+
+    pub fn migrate_with_progress(&self, steps: &[MigrationStep], progress: &mut dyn FnMut(&str, c_int)) -> rusqlite::Result<()> {
+        let conn = self.get_conn();
+        conn.execute_batch(r#"CREATE TABLE IF NOT EXISTS migration_journal (
+                name TEXT NOT NULL PRIMARY KEY,
+                applied_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+            )"#)?;
+
+        let mut pending = Vec::new();
+        for step in steps.iter() {
+            if !self.migration_applied(step.name)? {
+                pending.push(step);
+            }
+        }
+
+        for (index, step) in pending.iter().enumerate() {
+            progress(step.name, (index * 100 / pending.len()) as c_int);
+            let tx = self.begin_transaction()?;
+            conn.execute_batch(step.sql)
+                .and_then(|_| conn.execute("INSERT INTO migration_journal (name) VALUES (?)", &[&step.name]))
+                .and_then(|_| self.sync_schema_version())?;
+            tx.commit()?;
+        }
+        // Stores migrated before versioning was introduced have a journal but no version.
+        self.sync_schema_version()?;
+        progress("done", 100);
+        Ok(())
+    }
+
+    fn sync_schema_version(&self) -> rusqlite::Result<()> {
+        let conn = self.get_conn();
+        let version: i64 = conn.query_row("SELECT count(*) FROM migration_journal", &[], |row| row.get(0))?;
+        // Read only stores that are up to date must not be written to.
+        if version != self.schema_version()? {
+            conn.execute_batch(&format!("PRAGMA user_version = {}", version))?;
+        }
+        Ok(())
+    }
+
+    /// The number of migration steps applied to the store, 0 for a new store.
+    pub fn schema_version(&self) -> rusqlite::Result<i64> {
+        self.get_conn().query_row("PRAGMA user_version", &[], |row| row.get(0))
+    }
+
+    pub fn migration_applied(&self, name: &str) -> rusqlite::Result<bool> {
+        let sql = r#"SELECT count(*) FROM migration_journal WHERE name=?"#;
+        let count: i64 = self.get_conn().query_row(sql, &[&name], |row| row.get(0))?;
+        Ok(count > 0)
+    }
+}
+
+/// Returns -1 if the version could not be read.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn store_schema_version(store: *const Arc<Store>) -> i64 {
+    ffi_lock!();
+    ffi_guard!({
+        let store = ffi_ref!("store_schema_version", store, -1);
+        store.schema_version().unwrap_or(-1)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::MigrationStep;
+
+    use Store;
+
+    fn steps() -> Vec<MigrationStep> {
+        vec![
+            MigrationStep::new("create_a", "CREATE TABLE a (x INTEGER)"),
+            MigrationStep::new("create_b", "CREATE TABLE b (x INTEGER)"),
+            MigrationStep::new("fill_b", "INSERT INTO b (x) VALUES (1); INSERT INTO b (x) VALUES (2);"),
+        ]
+    }
+
+    #[test]
+    fn test_migrate_reports_progress() {
+        let store = Store::new(None);
+        let mut reported = Vec::new();
+        store.migrate_with_progress(&steps(), &mut |step, percent| reported.push((step.to_string(), percent))).unwrap();
+        assert_eq!(reported, vec![("create_a".to_string(), 0), ("create_b".to_string(), 33),
+                                  ("fill_b".to_string(), 66), ("done".to_string(), 100)]);
+
+        let mut reported = Vec::new();
+        store.migrate_with_progress(&steps(), &mut |step, percent| reported.push((step.to_string(), percent))).unwrap();
+        assert_eq!(reported, vec![("done".to_string(), 100)]);
+    }
+
+    #[test]
+    fn test_migrate_resumes_after_failure() {
+        let store = Store::new(None);
+        let mut broken = steps();
+        broken[2] = MigrationStep::new("fill_b", "INSERT INTO b (x) VALUES (1); INSERT INTO missing (x) VALUES (2);");
+        assert!(store.migrate(&broken).is_err());
+        assert!(store.migration_applied("create_b").unwrap());
+        assert!(!store.migration_applied("fill_b").unwrap());
+
+        // the failed step was rolled back as a whole
+        let count: i64 = store.get_conn().query_row("SELECT count(*) FROM b", &[], |row| row.get(0)).unwrap();
+        assert_eq!(count, 0);
+
+        let mut reported = Vec::new();
+        store.migrate_with_progress(&steps(), &mut |step, _| reported.push(step.to_string())).unwrap();
+        assert_eq!(reported, vec!["fill_b", "done"]);
+        let count: i64 = store.get_conn().query_row("SELECT count(*) FROM b", &[], |row| row.get(0)).unwrap();
+        assert_eq!(count, 2);
+    }
+
+    #[test]
+    fn test_schema_version() {
+        let store = Store::new(None);
+        assert_eq!(store.schema_version().unwrap(), 0);
+
+        let mut broken = steps();
+        broken[2] = MigrationStep::new("fill_b", "INSERT INTO missing (x) VALUES (1)");
+        assert!(store.migrate(&broken).is_err());
+        assert_eq!(store.schema_version().unwrap(), 2);
+
+        store.migrate(&steps()).unwrap();
+        assert_eq!(store.schema_version().unwrap(), 3);
+
+        // a later release appends a step
+        let mut upgraded = steps();
+        upgraded.push(MigrationStep::new("add_b_note", "ALTER TABLE b ADD COLUMN note TEXT"));
+        store.migrate(&upgraded).unwrap();
+        assert_eq!(store.schema_version().unwrap(), 4);
+        let count: i64 = store.get_conn().query_row("SELECT count(*) FROM b", &[], |row| row.get(0)).unwrap();
+        assert_eq!(count, 2);
+    }
+}
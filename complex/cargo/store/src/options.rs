@@ -0,0 +1,116 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+use std::fs;
+use std::path::Path;
+
+use rusqlite;
+use rusqlite::{
+    Connection,
+    OpenFlags,
+    SQLITE_OPEN_CREATE,
+    SQLITE_OPEN_NO_MUTEX,
+    SQLITE_OPEN_READ_ONLY,
+    SQLITE_OPEN_READ_WRITE,
+};
+
+use Store;
+
+pub const STORE_OPEN_READ_ONLY: u32 = 1;
+pub const STORE_OPEN_CREATE_IF_MISSING: u32 = 1 << 1;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct OpenOptions {
+    /// Never write to the database. Opening fails if the schema is out of date.
+    pub read_only: bool,
+    /// Create the database, and the directory holding it, if they do not exist. Ignored
+    /// for read only stores.
+    pub create_if_missing: bool,
+}
+
+impl OpenOptions {
+    pub fn from_bits(flags: u32) -> OpenOptions {
+        OpenOptions {
+            read_only: flags & STORE_OPEN_READ_ONLY != 0,
+            create_if_missing: flags & STORE_OPEN_CREATE_IF_MISSING != 0,
+        }
+    }
+
+    fn sqlite_flags(&self) -> OpenFlags {
+        if self.read_only {
+            SQLITE_OPEN_READ_ONLY | SQLITE_OPEN_NO_MUTEX
+        } else if self.create_if_missing {
+            SQLITE_OPEN_READ_WRITE | SQLITE_OPEN_CREATE | SQLITE_OPEN_NO_MUTEX
+        } else {
+            SQLITE_OPEN_READ_WRITE | SQLITE_OPEN_NO_MUTEX
+        }
+    }
+}
+
+impl Store {
+    /// Opens the database at `path`, which the host chooses, e.g. inside the Android files
+    /// directory or an iOS app group container.
+    pub fn open<P: AsRef<Path>>(path: P, options: OpenOptions) -> rusqlite::Result<Store> {
+        let path = path.as_ref();
+        if options.create_if_missing && !options.read_only {
+            if let Some(directory) = path.parent() {
+                fs::create_dir_all(directory).map_err(|_| rusqlite::Error::InvalidPath(directory.to_path_buf()))?;
+            }
+        }
+        let flags = options.sqlite_flags();
+        let conn = Connection::open_with_flags(path, flags)?;
+        let store = Store::with_connection(conn, Some(path.to_string_lossy().into_owned()));
+        store.open_flags.set(flags);
+        Ok(store)
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.open_flags.get().contains(SQLITE_OPEN_READ_ONLY)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::fs;
+
+    use super::{
+        OpenOptions,
+        STORE_OPEN_CREATE_IF_MISSING,
+        STORE_OPEN_READ_ONLY,
+    };
+
+    use Store;
+
+    #[test]
+    fn test_open_options() {
+        let directory = env::temp_dir().join("toodle_open_options_test");
+        let _ = fs::remove_dir_all(&directory);
+        let path = directory.join("nested").join("toodle.db");
+
+        assert!(Store::open(&path, OpenOptions::from_bits(0)).is_err());
+        assert!(!path.exists());
+
+        let store = Store::open(&path, OpenOptions::from_bits(STORE_OPEN_CREATE_IF_MISSING)).unwrap();
+        assert!(!store.is_read_only());
+        store.get_conn().execute_batch("CREATE TABLE t (x INTEGER); INSERT INTO t (x) VALUES (1);").unwrap();
+        drop(store);
+
+        let store = Store::open(&path, OpenOptions::from_bits(STORE_OPEN_READ_ONLY | STORE_OPEN_CREATE_IF_MISSING)).unwrap();
+        assert!(store.is_read_only());
+        let count: i64 = store.get_conn().query_row("SELECT count(*) FROM t", &[], |row| row.get(0)).unwrap();
+        assert_eq!(count, 1);
+        assert!(store.get_conn().execute_batch("INSERT INTO t (x) VALUES (2)").is_err());
+        store.reopen().unwrap();
+        assert!(store.get_conn().execute_batch("INSERT INTO t (x) VALUES (2)").is_err());
+
+        let _ = fs::remove_dir_all(&directory);
+    }
+}
@@ -0,0 +1,159 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{
+    Hash,
+    Hasher,
+};
+use std::sync::Arc;
+
+use rusqlite::Connection;
+
+use Store;
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '$'
+}
+
+fn hash_text(text: &str) -> String {
+    let mut hasher = DefaultHasher::new();
+    text.hash(&mut hasher);
+    format!("{:08x}", hasher.finish() as u32)
+}
+
+/// Replaces every literal in `sql` with a typed placeholder so that statements can be
+/// logged without exposing user data. Text literals become `<text:HASH>`, where equal
+/// strings share a hash, numbers become `<int>` or `<real>` and blobs `<blob:LENGTH>`.
+/// Quoted identifiers are kept.
+pub fn redact(sql: &str) -> String {
+    let chars: Vec<char> = sql.chars().collect();
+    let mut redacted = String::with_capacity(sql.len());
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let follows_identifier = i > 0 && is_identifier_char(chars[i - 1]);
+        let is_blob = (c == 'x' || c == 'X') && !follows_identifier && chars.get(i + 1) == Some(&'\'');
+        if c == '\'' || is_blob {
+            if is_blob {
+                i += 1;
+            }
+            let mut text = String::new();
+            i += 1;
+            while i < chars.len() {
+                if chars[i] == '\'' {
+                    if chars.get(i + 1) == Some(&'\'') {
+                        text.push('\'');
+                        i += 2;
+                        continue;
+                    }
+                    break;
+                }
+                text.push(chars[i]);
+                i += 1;
+            }
+            i += 1;
+            if is_blob {
+                redacted.push_str(&format!("<blob:{}>", text.len() / 2));
+            } else {
+                redacted.push_str(&format!("<text:{}>", hash_text(&text)));
+            }
+        } else if c.is_ascii_digit() && !follows_identifier {
+            let mut is_real = false;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '.') {
+                is_real = is_real || chars[i] == '.' || chars[i] == 'e' || chars[i] == 'E';
+                i += 1;
+            }
+            redacted.push_str(if is_real { "<real>" } else { "<int>" });
+        } else if c == '"' || c == '`' || c == '[' {
+            let close = if c == '[' { ']' } else { c };
+            redacted.push(c);
+            i += 1;
+            while i < chars.len() && chars[i] != close {
+                redacted.push(chars[i]);
+                i += 1;
+            }
+            if i < chars.len() {
+                redacted.push(close);
+                i += 1;
+            }
+        } else {
+            redacted.push(c);
+            i += 1;
+        }
+    }
+    redacted
+}
+
+fn trace_redacted(sql: &str) {
+    log_debug!("SQL: {}", redact(sql));
+}
+
+pub fn apply_sql_tracing(conn: &mut Connection, enabled: bool) {
+    conn.trace(if enabled { Some(trace_redacted) } else { None });
+}
+
+impl Store {
+    /// Logs every statement run against the store, with all literals and bound parameters
+    /// redacted. Returns false if the connection is in use and tracing could not be changed.
+    pub fn set_sql_tracing(&self, enabled: bool) -> bool {
+        let mut conn = self.conn.borrow_mut();
+        match Arc::get_mut(&mut conn) {
+            Some(conn) => {
+                apply_sql_tracing(conn, enabled);
+                self.sql_tracing.set(enabled);
+                true
+            },
+            None => false,
+        }
+    }
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn store_set_sql_tracing(store: *const Arc<Store>, enabled: bool) -> bool {
+    ffi_lock!();
+    ffi_guard!({
+        let store = ffi_ref!("store_set_sql_tracing", store);
+        store.set_sql_tracing(enabled)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        hash_text,
+        redact,
+    };
+
+    use Store;
+
+    #[test]
+    fn test_redact() {
+        let sql = "INSERT INTO items (uuid, name, due_date) VALUES ('abc1', 'Buy milk', '2017-11-10 12:00:00')";
+        assert_eq!(redact(sql), format!("INSERT INTO items (uuid, name, due_date) VALUES (<text:{}>, <text:{}>, <text:{}>)",
+                                        hash_text("abc1"), hash_text("Buy milk"), hash_text("2017-11-10 12:00:00")));
+
+        let sql = r#"SELECT "col1", t1.x FROM t1 WHERE a=12 AND b=-3.5e2 AND c=x'00ff' AND d='it''s' AND e IS NULL"#;
+        assert_eq!(redact(sql), format!(r#"SELECT "col1", t1.x FROM t1 WHERE a=<int> AND b=-<real> AND c=<blob:2> AND d=<text:{}> AND e IS NULL"#,
+                                        hash_text("it's")));
+    }
+
+    #[test]
+    fn test_set_sql_tracing() {
+        let store = Store::new(None);
+        assert!(store.set_sql_tracing(true));
+        store.get_conn().execute("CREATE TABLE t (x TEXT)", &[]).unwrap();
+        let conn = store.get_conn();
+        // the connection is shared, so tracing cannot be toggled until it is released
+        assert!(!store.set_sql_tracing(false));
+        drop(conn);
+        assert!(store.set_sql_tracing(false));
+    }
+}
@@ -0,0 +1,378 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+use std::cell::{
+    Cell,
+    RefCell,
+};
+use std::fmt;
+use std::mem;
+use std::ptr;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use rusqlite;
+use rusqlite::Connection;
+
+use ffi_utils::errors::{
+    set_extern_error,
+    ErrorCategory,
+    ExternError,
+};
+use ffi_utils::lock;
+use errors::set_sqlite_error;
+use Store;
+
+type Work = Box<dyn FnOnce()>;
+
+/// Work waiting for the outermost transaction to commit, each with the depth of the
+/// transaction that was innermost when it was queued.
+#[derive(Default)]
+pub(crate) struct Deferred(RefCell<Vec<(u64, Work)>>);
+
+impl fmt::Debug for Deferred {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Deferred({})", self.0.borrow().len())
+    }
+}
+
+/// A transaction backed by a SQLite savepoint, so that transactions nest: the writes
+/// grouped by one only become durable once every enclosing transaction has committed.
+/// Dropping a transaction without committing it rolls it back.
+///
+/// The outermost transaction takes the write lock as it begins. Another process holding the
+/// database then makes `begin_transaction` wait for the busy timeout, rather than failing
+/// partway through the transaction's writes with no way to retry.
+#[derive(Debug)]
+pub struct StoreTransaction {
+    conn: Arc<Connection>,
+    name: String,
+    outermost: bool,
+    depth: u64,
+    open: Rc<Cell<u64>>,
+    deferred: Rc<Deferred>,
+    finished: bool,
+}
+
+impl StoreTransaction {
+    /// A transaction that fails to commit, e.g. on a full disk, is rolled back.
+    pub fn commit(mut self) -> rusqlite::Result<()> {
+        if self.outermost {
+            self.conn.execute_batch(&format!("RELEASE {}; COMMIT", self.name))?;
+        } else {
+            self.conn.execute_batch(&format!("RELEASE {}", self.name))?;
+        }
+        self.finish();
+        if self.outermost {
+            // taken out first, as the work may begin transactions of its own
+            let ready = mem::take(&mut *self.deferred.0.borrow_mut());
+            for (_, work) in ready {
+                work();
+            }
+        } else {
+            for entry in self.deferred.0.borrow_mut().iter_mut().filter(|entry| entry.0 >= self.depth) {
+                entry.0 = self.depth - 1;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn rollback(mut self) -> rusqlite::Result<()> {
+        self.finish();
+        self.discard_deferred();
+        self.rollback_savepoint()
+    }
+
+    fn discard_deferred(&self) {
+        let discarded: Vec<_> = {
+            let mut deferred = self.deferred.0.borrow_mut();
+            let (discarded, kept) = mem::take(&mut *deferred).into_iter().partition(|entry| entry.0 >= self.depth);
+            *deferred = kept;
+            discarded
+        };
+        // dropped once the borrow is released, in case dropping the work touches the store
+        drop(discarded);
+    }
+
+    fn finish(&mut self) {
+        self.finished = true;
+        self.open.set(self.open.get() - 1);
+    }
+
+    fn rollback_savepoint(&self) -> rusqlite::Result<()> {
+        if self.outermost {
+            self.conn.execute_batch("ROLLBACK")
+        } else {
+            self.conn.execute_batch(&format!("ROLLBACK TO {0}; RELEASE {0}", self.name))
+        }
+    }
+}
+
+impl Drop for StoreTransaction {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.open.set(self.open.get() - 1);
+            self.discard_deferred();
+            if let Err(e) = self.rollback_savepoint() {
+                log_warn!("failed to roll back {} {:?}", self.name, e);
+            }
+        }
+    }
+}
+
+impl Store {
+    /// Runs `work` once the writes made so far are durable: now if no transaction is open,
+    /// otherwise when the outermost one commits. It is dropped without running if the
+    /// transaction that is innermost now, or any enclosing it, rolls back.
+    pub fn after_commit(&self, work: Box<dyn FnOnce()>) {
+        match self.open_transactions.get() {
+            0 => work(),
+            depth => self.deferred.0.borrow_mut().push((depth, work)),
+        }
+    }
+
+    pub fn begin_transaction(&self) -> rusqlite::Result<StoreTransaction> {
+        let id = self.savepoints.get() + 1;
+        self.savepoints.set(id);
+        let name = format!("store_tx_{}", id);
+        let conn = self.get_conn();
+        let outermost = self.open_transactions.get() == 0;
+        if outermost {
+            conn.execute_batch(&format!("BEGIN IMMEDIATE; SAVEPOINT {}", name))?;
+        } else {
+            conn.execute_batch(&format!("SAVEPOINT {}", name))?;
+        }
+        self.open_transactions.set(self.open_transactions.get() + 1);
+        Ok(StoreTransaction {
+            conn: conn,
+            name: name,
+            outermost: outermost,
+            depth: self.open_transactions.get(),
+            open: self.open_transactions.clone(),
+            deferred: self.deferred.clone(),
+            finished: false,
+        })
+    }
+}
+
+/// Groups every write made through the store until the transaction is committed or rolled
+/// back. Returns null and fills in `error` if the transaction could not be started.
+///
+/// Until then the calling thread keeps the library lock, so calls from other threads wait
+/// rather than have their writes join the transaction. It must end on the same thread.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn store_begin_transaction(store: *const Arc<Store>, error: *mut ExternError) -> *mut StoreTransaction {
+    ffi_lock!();
+    ffi_guard!(error, {
+        let store = ffi_ref!("store_begin_transaction", store, ptr::null_mut(), error);
+        match store.begin_transaction() {
+            Ok(tx) => {
+                lock::hold();
+                Box::into_raw(Box::new(tx))
+            },
+            Err(e) => {
+                log_error!("failed to begin transaction {:?}", e);
+                set_sqlite_error(error, &e);
+                ptr::null_mut()
+            },
+        }
+    })
+}
+
+/// Checked before taking the library lock, which the thread that began the transaction
+/// holds until it ends: any other thread would wait for it forever.
+unsafe fn on_transaction_thread(name: &str, error: *mut ExternError) -> bool {
+    if lock::is_held() {
+        return true;
+    }
+    let message = format!("{} called on a thread other than the one that began the transaction", name);
+    log_error!("{}", message);
+    set_extern_error(error, ErrorCategory::Bug, message);
+    false
+}
+
+/// Commits and releases the transaction.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn store_commit_transaction(tx: *mut StoreTransaction, error: *mut ExternError) -> bool {
+    if !on_transaction_thread("store_commit_transaction", error) {
+        return false;
+    }
+    ffi_lock!();
+    ffi_guard!(error, {
+        ffi_ref!("store_commit_transaction", tx, false, error);
+        lock::release();
+        let tx = Box::from_raw(tx);
+        tx.commit().map_err(|e| set_sqlite_error(error, &e)).is_ok()
+    })
+}
+
+/// Discards every write made since the transaction began, and releases it.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn store_rollback_transaction(tx: *mut StoreTransaction, error: *mut ExternError) -> bool {
+    if !on_transaction_thread("store_rollback_transaction", error) {
+        return false;
+    }
+    ffi_lock!();
+    ffi_guard!(error, {
+        ffi_ref!("store_rollback_transaction", tx, false, error);
+        lock::release();
+        let tx = Box::from_raw(tx);
+        tx.rollback().map_err(|e| set_sqlite_error(error, &e)).is_ok()
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::RefCell;
+    use std::ptr;
+    use std::rc::Rc;
+    use std::sync::mpsc;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    use ffi_utils::errors::{
+        error_message_destroy,
+        ErrorCategory,
+        ExternError,
+    };
+    use ffi_utils::lock;
+
+    use super::{
+        store_begin_transaction,
+        store_commit_transaction,
+        store_rollback_transaction,
+        StoreTransaction,
+    };
+    use Store;
+
+    fn count(store: &Store) -> i64 {
+        store.get_conn().query_row("SELECT count(*) FROM t", &[], |row| row.get(0)).unwrap()
+    }
+
+    fn store() -> Store {
+        let store = Store::new(None);
+        store.get_conn().execute_batch("CREATE TABLE t (x INTEGER)").unwrap();
+        store
+    }
+
+    #[test]
+    fn test_commit_and_rollback() {
+        let store = store();
+        let tx = store.begin_transaction().unwrap();
+        store.get_conn().execute_batch("INSERT INTO t (x) VALUES (1)").unwrap();
+        tx.commit().unwrap();
+        assert_eq!(count(&store), 1);
+
+        let tx = store.begin_transaction().unwrap();
+        store.get_conn().execute_batch("INSERT INTO t (x) VALUES (2)").unwrap();
+        tx.rollback().unwrap();
+        assert_eq!(count(&store), 1);
+
+        {
+            let _tx = store.begin_transaction().unwrap();
+            store.get_conn().execute_batch("INSERT INTO t (x) VALUES (3)").unwrap();
+        }
+        assert_eq!(count(&store), 1);
+    }
+
+    #[test]
+    fn test_nested_transactions() {
+        let store = store();
+        let outer = store.begin_transaction().unwrap();
+        let inner = store.begin_transaction().unwrap();
+        store.get_conn().execute_batch("INSERT INTO t (x) VALUES (1)").unwrap();
+        inner.commit().unwrap();
+        assert_eq!(count(&store), 1);
+        outer.rollback().unwrap();
+        assert_eq!(count(&store), 0);
+    }
+
+    #[test]
+    fn test_failed_commit_rolls_back() {
+        let store = store();
+        store.get_conn().execute_batch(r#"
+            PRAGMA foreign_keys = ON;
+            CREATE TABLE parent (id INTEGER PRIMARY KEY);
+            CREATE TABLE child (parent INTEGER REFERENCES parent(id) DEFERRABLE INITIALLY DEFERRED);
+        "#).unwrap();
+        let tx = store.begin_transaction().unwrap();
+        // only checked on COMMIT
+        store.get_conn().execute_batch("INSERT INTO t (x) VALUES (1); INSERT INTO child (parent) VALUES (1)").unwrap();
+        assert!(tx.commit().is_err());
+        assert_eq!(count(&store), 0);
+
+        // the connection is not left inside the failed transaction
+        let tx = store.begin_transaction().unwrap();
+        store.get_conn().execute_batch("INSERT INTO t (x) VALUES (2)").unwrap();
+        tx.commit().unwrap();
+        assert_eq!(count(&store), 1);
+    }
+
+    #[test]
+    fn test_after_commit() {
+        let store = store();
+        let ran = Rc::new(RefCell::new(vec![]));
+        let run = |name: &'static str| -> Box<dyn FnOnce()> {
+            let ran = ran.clone();
+            Box::new(move || ran.borrow_mut().push(name))
+        };
+        store.after_commit(run("at once"));
+        assert_eq!(*ran.borrow(), vec!["at once"]);
+
+        let outer = store.begin_transaction().unwrap();
+        store.after_commit(run("outer"));
+        let inner = store.begin_transaction().unwrap();
+        store.after_commit(run("committed inner"));
+        inner.commit().unwrap();
+        let inner = store.begin_transaction().unwrap();
+        store.after_commit(run("rolled back inner"));
+        inner.rollback().unwrap();
+        assert_eq!(ran.borrow().len(), 1);
+        outer.commit().unwrap();
+        assert_eq!(*ran.borrow(), vec!["at once", "outer", "committed inner"]);
+
+        let outer = store.begin_transaction().unwrap();
+        let inner = store.begin_transaction().unwrap();
+        store.after_commit(run("rolled back outer"));
+        inner.commit().unwrap();
+        drop(outer);
+        store.begin_transaction().unwrap().commit().unwrap();
+        assert_eq!(ran.borrow().len(), 3);
+    }
+
+    #[test]
+    fn test_host_transaction_keeps_other_threads_out() {
+        let store = Arc::new(store());
+        let tx = unsafe { store_begin_transaction(&store, ptr::null_mut()) };
+        assert!(!tx.is_null());
+        // neither is Send; the library lock is what keeps the threads apart
+        let (shared, tx_address) = (&*store as *const Store as usize, tx as usize);
+        let (wrote, written) = mpsc::channel();
+        let other = thread::spawn(move || {
+            let mut error = ExternError::default();
+            assert!(!unsafe { store_commit_transaction(tx_address as *mut StoreTransaction, &mut error) });
+            assert_eq!(error.category, ErrorCategory::Bug);
+            unsafe { error_message_destroy(error.message) };
+
+            let _library_lock = lock::enter();
+            let store = unsafe { &*(shared as *const Store) };
+            store.get_conn().execute_batch("INSERT INTO t (x) VALUES (2)").unwrap();
+            wrote.send(()).unwrap();
+        });
+        store.get_conn().execute_batch("INSERT INTO t (x) VALUES (1)").unwrap();
+        assert!(written.recv_timeout(Duration::from_millis(50)).is_err());
+        assert!(unsafe { store_rollback_transaction(tx, ptr::null_mut()) });
+        written.recv().unwrap();
+        other.join().unwrap();
+        // only the other thread's write, made once the transaction had ended
+        assert_eq!(count(&store), 1);
+    }
+}
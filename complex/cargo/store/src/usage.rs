@@ -0,0 +1,221 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+use std::sync::Arc;
+use std::ptr;
+
+use rusqlite;
+
+use ffi_utils::errors::ExternError;
+use errors::set_sqlite_error;
+use Store;
+
+/// A table whose rows are pruned oldest first once there are more than its cap.
+struct CappedTable {
+    name: &'static str,
+    /// The column rows are pruned in ascending order of.
+    age: &'static str,
+    /// Rows that are kept whatever the cap, or `None`.
+    keep: Option<&'static str>,
+}
+
+/// The history is the trash: items deleted locally, kept so they can be restored.
+pub const HISTORY_TABLE: &'static str = "trashed_items";
+/// The changelog is the record of deletions kept for sync. Those not pushed yet are kept
+/// whatever the cap, or other devices would never hear of them.
+pub const CHANGELOG_TABLE: &'static str = "tombstones";
+
+const HISTORY: CappedTable = CappedTable {
+    name: HISTORY_TABLE,
+    age: "trashed_at",
+    keep: None,
+};
+
+const CHANGELOG: CappedTable = CappedTable {
+    name: CHANGELOG_TABLE,
+    age: "deleted_at",
+    keep: Some("EXISTS (SELECT 1 FROM sync_outbox WHERE sync_outbox.kind=tombstones.kind AND sync_outbox.id=tombstones.id)"),
+};
+
+#[derive(Debug, Clone, Default, PartialEq)]
+#[repr(C)]
+/// Bytes used on disk, broken down by the subsystem that owns each table.
+/// Indexes are counted against the table they belong to.
+pub struct StoreSizeReport {
+    pub items: i64,
+    pub labels: i64,
+    pub history: i64,
+    pub fts: i64,
+    pub attachments: i64,
+    pub changelog: i64,
+    pub other: i64,
+    pub total: i64,
+}
+
+impl StoreSizeReport {
+    fn add(&mut self, table: &str, bytes: i64) {
+        match table {
+            "items" | "item_labels" => self.items += bytes,
+            "labels" => self.labels += bytes,
+            HISTORY_TABLE => self.history += bytes,
+            "item_search" => self.fts += bytes,
+            t if t.starts_with("attachment") => self.attachments += bytes,
+            CHANGELOG_TABLE | "sync_outbox" => self.changelog += bytes,
+            _ => self.other += bytes,
+        }
+        self.total += bytes;
+    }
+}
+
+impl Store {
+    pub fn size_report(&self) -> rusqlite::Result<StoreSizeReport> {
+        let sql = r#"SELECT COALESCE(m.tbl_name, s.name), SUM(s.pgsize)
+                     FROM dbstat s LEFT JOIN sqlite_master m ON m.name=s.name
+                     GROUP BY 1"#;
+        let conn = self.get_conn();
+        let mut stmt = conn.prepare(sql)?;
+        let mut rows = stmt.query(&[])?;
+        let mut report = StoreSizeReport::default();
+        while let Some(row) = rows.next() {
+            let row = row?;
+            let table: String = row.get(0);
+            let bytes: i64 = row.get(1);
+            report.add(&table, bytes);
+        }
+        Ok(report)
+    }
+
+    pub fn set_history_cap(&self, cap: Option<i64>) {
+        self.history_cap.set(cap);
+    }
+
+    pub fn set_changelog_cap(&self, cap: Option<i64>) {
+        self.changelog_cap.set(cap);
+    }
+
+    /// Deletes the oldest rows from the history and changelog tables until each
+    /// is within its cap, or only rows that must be kept are left. Returns the number
+    /// of rows removed.
+    pub fn prune_to_caps(&self) -> rusqlite::Result<i64> {
+        let mut pruned = 0;
+        if let Some(cap) = self.history_cap.get() {
+            pruned += self.prune_table(&HISTORY, cap)?;
+        }
+        if let Some(cap) = self.changelog_cap.get() {
+            pruned += self.prune_table(&CHANGELOG, cap)?;
+        }
+        Ok(pruned)
+    }
+
+    pub fn table_exists(&self, table: &str) -> rusqlite::Result<bool> {
+        let sql = r#"SELECT count(*) FROM sqlite_master WHERE type='table' AND name=?"#;
+        let count: i64 = self.get_conn().query_row(sql, &[&table], |row| row.get(0))?;
+        Ok(count > 0)
+    }
+
+    fn prune_table(&self, table: &CappedTable, cap: i64) -> rusqlite::Result<i64> {
+        // stores without a list manager's tables have nothing to prune
+        if !self.table_exists(table.name)? {
+            return Ok(0);
+        }
+        let sql = format!(r#"DELETE FROM {0} WHERE rowid IN (
+                                SELECT rowid FROM {0} WHERE NOT ({2}) ORDER BY {1}, rowid
+                                LIMIT MAX((SELECT count(*) FROM {0}) - ?, 0))"#,
+                          table.name, table.age, table.keep.unwrap_or("0"));
+        let removed = self.get_conn().execute(&sql, &[&cap])?;
+        Ok(removed as i64)
+    }
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn store_size_report(store: *const Arc<Store>, error: *mut ExternError) -> *mut StoreSizeReport {
+    ffi_lock!();
+    ffi_guard!(error, {
+        let store = ffi_ref!("store_size_report", store, ptr::null_mut(), error);
+        match store.size_report() {
+            Ok(report) => Box::into_raw(Box::new(report)),
+            Err(e) => {
+                log_error!("failed to compute store size report {:?}", e);
+                set_sqlite_error(error, &e);
+                ::std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn store_size_report_destroy(report: *mut StoreSizeReport) {
+    ffi_guard!({
+        if report.is_null() {
+            return;
+        }
+        let _ = Box::from_raw(report);
+    })
+}
+
+/// A negative cap removes the limit.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn store_set_history_cap(store: *const Arc<Store>, cap: i64) {
+    ffi_lock!();
+    ffi_guard!({
+        let store = ffi_ref!("store_set_history_cap", store);
+        store.set_history_cap(if cap < 0 { None } else { Some(cap) });
+    })
+}
+
+/// A negative cap removes the limit.
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn store_set_changelog_cap(store: *const Arc<Store>, cap: i64) {
+    ffi_lock!();
+    ffi_guard!({
+        let store = ffi_ref!("store_set_changelog_cap", store);
+        store.set_changelog_cap(if cap < 0 { None } else { Some(cap) });
+    })
+}
+
+#[cfg_attr(feature = "legacy-ffi", no_mangle)]
+pub unsafe extern "C" fn store_prune_to_caps(store: *const Arc<Store>, error: *mut ExternError) -> i64 {
+    ffi_lock!();
+    ffi_guard!(error, {
+        let store = ffi_ref!("store_prune_to_caps", store, 0, error);
+        store.prune_to_caps().unwrap_or_else(|e| {
+            log_error!("failed to prune store {:?}", e);
+            set_sqlite_error(error, &e);
+            0
+        })
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::Store;
+
+    fn populated_store() -> Store {
+        let store = Store::new(None);
+        {
+            let conn = store.get_conn();
+            conn.execute_batch(r#"CREATE TABLE items (uuid TEXT NOT NULL PRIMARY KEY, name TEXT NOT NULL);"#).unwrap();
+            for i in 0..10 {
+                conn.execute("INSERT INTO items (uuid, name) VALUES (?, ?)", &[&format!("uuid{}", i), &"an item"]).unwrap();
+            }
+        }
+        store
+    }
+
+    #[test]
+    fn test_size_report() {
+        let store = populated_store();
+        let report = store.size_report().expect("expected a report");
+        assert!(report.items > 0);
+        assert_eq!(report.history, 0);
+        assert_eq!(report.total, report.items + report.labels + report.history + report.fts +
+                                 report.attachments + report.changelog + report.other);
+    }
+}
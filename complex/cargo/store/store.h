@@ -1,8 +1,90 @@
+#ifndef store_h
+#define store_h
+
+#include <stdbool.h>
 #include <stdint.h>
+#include "errors.h"
+#include "ffi_strings.h"
 
 struct store;
 
 struct store* new_store(const char* uri);
-void store_destroy(struct store* store);
 
+// Flags for opening a store at a host chosen location.
+// Never write to the database. Opening fails if the schema is out of date.
+#define STORE_OPEN_READ_ONLY 1
+// Create the database, and the directory holding it, if they do not exist.
+#define STORE_OPEN_CREATE_IF_MISSING 2
+// Releases a store returned by new_store or toodle_store.
+void store_destroy(struct store* _Nonnull store);
+
+// The number of schema migration steps applied to the store, or -1 if it could not be read.
+// Stores are upgraded when they are opened.
+int64_t store_schema_version(const struct store* _Nonnull store);
+
+struct store_size_report {
+    int64_t items;
+    int64_t labels;
+    int64_t history;
+    int64_t fts;
+    int64_t attachments;
+    int64_t changelog;
+    int64_t other;
+    int64_t total;
+};
+
+struct store_size_report* _Nullable store_size_report(const struct store* _Nonnull store, struct extern_error* _Nullable error);
+void store_size_report_destroy(struct store_size_report* _Nonnull report);
+// A negative cap removes the limit. The history is the trash of deleted items; the
+// changelog is the record of deletions kept for sync, of which those not synced yet are
+// kept whatever the cap. store_prune_to_caps removes the oldest rows over each cap.
+void store_set_history_cap(const struct store* _Nonnull store, int64_t cap);
+void store_set_changelog_cap(const struct store* _Nonnull store, int64_t cap);
+int64_t store_prune_to_caps(const struct store* _Nonnull store, struct extern_error* _Nullable error);
+
+// Logs every statement run against the store with literals and bound parameters replaced
+// by typed placeholders. Returns false if the connection is busy and tracing was not changed.
+bool store_set_sql_tracing(const struct store* _Nonnull store, bool enabled);
+
+// Groups every write made through the store, including list manager mutations, until the
+// transaction is committed or rolled back. Transactions nest. Returns NULL if the
+// transaction could not be started. Until it ends, the calling thread keeps the library
+// lock: calls from other threads wait for it, so their writes do not join the transaction.
+struct store_transaction;
+struct store_transaction* _Nullable store_begin_transaction(const struct store* _Nonnull store, struct extern_error* _Nullable error);
+// Both release the transaction, which must not be used afterwards. They must be called on
+// the thread that began it, and fail with ErrorCategoryBug elsewhere.
+bool store_commit_transaction(struct store_transaction* _Nonnull tx, struct extern_error* _Nullable error);
+bool store_rollback_transaction(struct store_transaction* _Nonnull tx, struct extern_error* _Nullable error);
+
+struct store_vacuum_report {
+    int64_t bytes_before;
+    int64_t bytes_after;
+};
+
+// Compacts the database file. Returns NULL and fills in `error` if that failed, e.g. because
+// a transaction is open.
+struct store_vacuum_report* _Nullable store_vacuum(const struct store* _Nonnull store, struct extern_error* _Nullable error);
+void store_vacuum_report_destroy(struct store_vacuum_report* _Nonnull report);
+
+// Returns the problems found with the database's structure, an empty list if it is intact.
+// Returns NULL and fills in `error` if the check could not run; a category of
+// ErrorCategoryDataCorruption means the file is too damaged to check.
+struct integrity_problems;
+struct integrity_problems* _Nullable store_check_integrity(const struct store* _Nonnull store, struct extern_error* _Nullable error);
+void integrity_problems_destroy(struct integrity_problems* _Nonnull problems);
+size_t integrity_problems_count(const struct integrity_problems* _Nonnull problems);
+char* _Nonnull integrity_problem_at(const struct integrity_problems* _Nonnull problems, size_t index);
+
+// How long to wait for another process, e.g. a share extension, to release the database
+// before failing with ErrorCategoryBusy. Defaults to 5000; 0 fails straight away.
+bool store_set_busy_timeout(const struct store* _Nonnull store, int64_t milliseconds, struct extern_error* _Nullable error);
+
+// Writes a snapshot of every list, item and label to `path`, replacing any file there only
+// once the snapshot is complete. Returns false and fills in `error` on failure.
+bool store_backup(const struct store* _Nonnull store, const char* _Nonnull path, struct extern_error* _Nullable error);
+// Replaces the store's contents with the snapshot at `path`. The store is unchanged if that
+// fails. Call toodle_reload_store afterwards to upgrade the restored data and notify observers.
+bool store_restore(const struct store* _Nonnull store, const char* _Nonnull path, struct extern_error* _Nullable error);
 
+#endif /* store_h */
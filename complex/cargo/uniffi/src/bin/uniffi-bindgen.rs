@@ -0,0 +1,19 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! Generates the Kotlin and Swift wrappers from the built library:
+//!   cargo run --bin uniffi-bindgen -- generate --library target/debug/libtoodle_uniffi.so \
+//!       --language kotlin --language swift --out-dir target/bindings
+
+extern crate uniffi;
+
+fn main() {
+    uniffi::uniffi_bindgen_main()
+}
@@ -0,0 +1,212 @@
+// Copyright 2016 Mozilla
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use
+// this file except in compliance with the License. You may obtain a copy of the
+// License at http://www.apache.org/licenses/LICENSE-2.0
+// Unless required by applicable law or agreed to in writing, software distributed
+// under the License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR
+// CONDITIONS OF ANY KIND, either express or implied. See the License for the
+// specific language governing permissions and limitations under the License.
+
+//! The list manager, items and labels as described by `toodle.udl`, from which
+//! `uniffi-bindgen` generates the Kotlin and Swift wrappers. This replaces writing each
+//! `extern "C"` function by hand along with its JNA and Swift mirror; the hand-written
+//! functions are only exported when the `legacy-ffi` feature is on.
+//!
+//! The generated code may call in from any thread, so every method takes the library
+//! lock, as the C functions do.
+
+#[macro_use]
+extern crate ffi_utils;
+extern crate list;
+extern crate rusqlite;
+extern crate store;
+extern crate time;
+extern crate uniffi;
+
+use std::error;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{
+    Duration,
+    SystemTime,
+    UNIX_EPOCH,
+};
+
+use time::Timespec;
+
+use ffi_utils::errors::ErrorCategory;
+use list::filters::ItemFilter;
+use store::Store;
+use store::errors::category_of;
+use store::options::{
+    OpenOptions,
+    STORE_OPEN_CREATE_IF_MISSING,
+};
+
+uniffi::include_scaffolding!("toodle");
+
+#[derive(Debug)]
+pub enum ToodleError {
+    Retryable { message: String },
+    NeedsUserInput { message: String },
+    NeedsReauth { message: String },
+    DataCorruption { message: String },
+    Bug { message: String },
+    Busy { message: String },
+}
+
+impl fmt::Display for ToodleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ToodleError::Retryable { ref message } |
+            ToodleError::NeedsUserInput { ref message } |
+            ToodleError::NeedsReauth { ref message } |
+            ToodleError::DataCorruption { ref message } |
+            ToodleError::Bug { ref message } |
+            ToodleError::Busy { ref message } => write!(f, "{}", message),
+        }
+    }
+}
+
+impl error::Error for ToodleError {}
+
+impl From<rusqlite::Error> for ToodleError {
+    fn from(error: rusqlite::Error) -> ToodleError {
+        let message = error.to_string();
+        match category_of(&error) {
+            ErrorCategory::Retryable => ToodleError::Retryable { message },
+            ErrorCategory::NeedsUserInput => ToodleError::NeedsUserInput { message },
+            ErrorCategory::NeedsReauth => ToodleError::NeedsReauth { message },
+            ErrorCategory::DataCorruption => ToodleError::DataCorruption { message },
+            ErrorCategory::Busy => ToodleError::Busy { message },
+            ErrorCategory::Bug | ErrorCategory::None => ToodleError::Bug { message },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Label {
+    pub name: String,
+    pub color: String,
+}
+
+impl<'a> From<&'a list::labels::Label> for Label {
+    fn from(label: &'a list::labels::Label) -> Label {
+        Label { name: label.name.clone(), color: label.color.clone() }
+    }
+}
+
+impl From<Label> for list::labels::Label {
+    fn from(label: Label) -> list::labels::Label {
+        list::labels::Label { name: label.name, color: label.color }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Item {
+    pub uuid: String,
+    pub name: String,
+    pub due_date: Option<SystemTime>,
+    pub completion_date: Option<SystemTime>,
+    pub labels: Vec<Label>,
+}
+
+fn system_time(date: Timespec) -> SystemTime {
+    if date.sec >= 0 {
+        UNIX_EPOCH + Duration::from_secs(date.sec as u64)
+    } else {
+        UNIX_EPOCH - Duration::from_secs(date.sec.unsigned_abs())
+    }
+}
+
+fn timespec(time: SystemTime) -> Timespec {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(after) => Timespec::new(after.as_secs() as i64, 0),
+        Err(before) => Timespec::new(-(before.duration().as_secs() as i64), 0),
+    }
+}
+
+impl From<list::items::Item> for Item {
+    fn from(item: list::items::Item) -> Item {
+        Item {
+            uuid: item.uuid.clone(),
+            name: item.name.clone(),
+            due_date: item.due_date.map(system_time),
+            completion_date: item.completion_date.map(system_time),
+            labels: item.labels.iter().map(|label| Label::from(&**label)).collect(),
+        }
+    }
+}
+
+impl<'a> From<&'a Item> for list::items::Item {
+    fn from(item: &'a Item) -> list::items::Item {
+        list::items::Item {
+            uuid: item.uuid.clone(),
+            name: item.name.clone(),
+            due_date: item.due_date.map(timespec),
+            completion_date: item.completion_date.map(timespec),
+            labels: item.labels.iter().cloned().map(list::labels::Label::from).map(Arc::new).collect(),
+        }
+    }
+}
+
+pub struct ListManager {
+    manager: list::ListManager,
+}
+
+// `list::ListManager` holds `Rc`s and a `RefCell`, so it isn't `Send`. Every method below
+// takes the library lock before touching it, which serializes access from the threads the
+// generated code calls in on, the same guarantee `ffi_lock!` gives the C API.
+unsafe impl Send for ListManager {}
+unsafe impl Sync for ListManager {}
+
+impl ListManager {
+    pub fn new(path: String) -> Result<ListManager, ToodleError> {
+        ffi_lock!();
+        let store = Store::open(&path, OpenOptions::from_bits(STORE_OPEN_CREATE_IF_MISSING))?;
+        let manager = list::ListManager::open(Arc::new(store))?;
+        Ok(ListManager { manager: manager })
+    }
+
+    pub fn fetch_items(&self) -> Vec<Item> {
+        ffi_lock!();
+        self.manager.fetch_items(&ItemFilter::default()).into_iter().map(Item::from).collect()
+    }
+
+    pub fn fetch_item(&self, uuid: String) -> Option<Item> {
+        ffi_lock!();
+        self.manager.fetch_item(&uuid).map(Item::from)
+    }
+
+    pub fn create_item(&self, item: Item) -> Result<String, ToodleError> {
+        ffi_lock!();
+        Ok(self.manager.try_create_item(&list::items::Item::from(&item))?)
+    }
+
+    pub fn save_item(&self, item: Item) -> Result<(), ToodleError> {
+        ffi_lock!();
+        let existing_labels = self.manager.fetch_labels_for_item(&item.uuid);
+        Ok(self.manager.try_update_item(&list::items::Item::from(&item), existing_labels)?)
+    }
+
+    pub fn delete_item(&self, uuid: String) -> Result<bool, ToodleError> {
+        ffi_lock!();
+        Ok(self.manager.try_delete_item(&uuid)?)
+    }
+
+    pub fn fetch_labels(&self) -> Vec<Label> {
+        ffi_lock!();
+        self.manager.fetch_labels().iter().map(Label::from).collect()
+    }
+
+    pub fn create_label(&self, name: String, color: String) -> Result<Label, ToodleError> {
+        ffi_lock!();
+        Ok(Label::from(&self.manager.try_create_label(name, color)?))
+    }
+
+    pub fn delete_label(&self, name: String) -> Result<bool, ToodleError> {
+        ffi_lock!();
+        Ok(self.manager.try_delete_label(&name)?)
+    }
+}